@@ -2,6 +2,13 @@ use anyhow::*;
 use fs_extra::copy_items;
 use fs_extra::dir::CopyOptions;
 use std::env;
+use std::fmt::Write;
+use std::path::Path;
+
+// shared with the crate, which reads resources.txt the same way
+#[allow(dead_code)]
+#[path = "src/state/world/manifest.rs"]
+mod manifest;
 
 fn main() -> Result<()> {
     // This tells Cargo to rerun this script if something in /res/ changes.
@@ -11,9 +18,38 @@ fn main() -> Result<()> {
     let out_dir = env::var("OUT_DIR")?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    let paths_to_copy = vec!["res/"];
+    copy_items(&paths_to_copy, &out_dir, &copy_options)?;
+
+    if env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_some() {
+        write_embedded_assets(Path::new(&out_dir))?;
+    }
+
+    Ok(())
+}
+
+/// Write the table of files the `embed-assets` feature includes: resources.txt and everything it loads
+fn write_embedded_assets(out_dir: &Path) -> Result<()> {
+    let res = Path::new(env!("CARGO_MANIFEST_DIR")).join("res");
+    let listing = std::fs::read_to_string(res.join("resources.txt"))?;
+    let mut files = vec![std::path::PathBuf::from("resources.txt")];
+    for line in listing.lines().filter(|line| !line.trim().is_empty()) {
+        files.extend(manifest::model_files(line, |file| std::fs::read_to_string(res.join(file)).ok())?);
+    }
 
+    let mut table = String::from("&[\n");
+    for file in files {
+        let path = res.join(&file);
+        if !path.is_file() {
+            println!("cargo:warning=resources.txt loads {}, which isn't there to embed", file.display());
+            continue;
+        }
+        // the crate looks files up with / between folders on every platform
+        let name = file.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        writeln!(table, "    ({:?}, include_bytes!({:?})),", name, path)?;
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    table.push(']');
+    std::fs::write(out_dir.join("embedded_assets.rs"), table)?;
     Ok(())
-}
\ No newline at end of file
+}
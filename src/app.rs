@@ -0,0 +1,282 @@
+//! The engine as something a host program drives one frame at a time
+use std::{path::Path, time::Duration};
+
+use winit::{
+    event::*,
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
+};
+
+use image::RgbaImage;
+
+use crate::{config::AppConfig, state::{camera::{Projection, Ray}, screenshot::parse_poses, world::{diagnostics::ValidationReport, environment::Environment, ik::IkChain, instance::EntityId, stats::WorldStats, stream::ImageStream, ModelHandle}, Click, State}};
+
+/// Everything needed to run the playground in a window the host owns
+///
+/// The host keeps its own event loop and timing: forward events to `handle_event`, then call
+/// `update` and `render` whenever it wants a new frame.
+pub struct App<'a> {
+    state: State<'a>,
+    /// set when the window was closed or escape was pressed
+    exit_requested: bool,
+}
+
+impl<'a> App<'a> {
+    /// Set up rendering into a window
+    pub async fn new(window: &'a Window, config: AppConfig) -> App<'a> {
+        Self {
+            state: State::new(window, config).await,
+            exit_requested: false,
+        }
+    }
+
+    /// the window being drawn into
+    pub fn window(&self) -> &Window {
+        self.state.window()
+    }
+
+    /// whether stencil effects can be used, see `RenderSettings::depth_format`
+    pub fn has_stencil(&self) -> bool {
+        self.state.has_stencil()
+    }
+
+    /// Counts of models, instances and triangles drawn last frame, for the host's own dashboards
+    pub fn world_stats(&self) -> WorldStats {
+        self.state.world_stats()
+    }
+
+    /// Problems found in the loaded models, like missing textures or degenerate triangles
+    pub fn validate_scene(&self) -> ValidationReport {
+        self.state.validate_scene()
+    }
+
+    /// Open a scene or model file in the res folder, the same as picking it from the recent files
+    ///
+    /// The file loads in the background and opens on a later frame, a file that can't be opened is logged.
+    pub fn open_file(&mut self, file_name: &str) {
+        self.state.open_file(file_name)
+    }
+
+    /// Change the field of view in degrees, easing into it over some seconds
+    pub fn set_field_of_view(&mut self, fovy: f32, seconds: f32) {
+        self.state.set_field_of_view(fovy, seconds);
+    }
+
+    /// Switch between perspective and an orthographic view some units tall, like the projection key does,
+    /// heights are clamped to between 0.01 and 1000 units
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.state.set_projection(projection);
+    }
+
+    /// Change the near and far clipping planes, fails unless 0 < near < far
+    pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) -> anyhow::Result<()> {
+        self.state.set_clip_planes(znear, zfar)
+    }
+
+    /// Play `res/cutscenes/<name>.txt`, it starts once it's read the first time and stays loaded for the next
+    pub fn play_cutscene(&mut self, name: &str) {
+        self.state.play_cutscene(name);
+    }
+
+    /// Stop the cutscene playing, named or the one T plays
+    pub fn stop_cutscene(&mut self) {
+        self.state.stop_cutscene();
+    }
+
+    /// Bend three instances of a model every frame so the last reaches a target, like a leg reaching for the ground
+    ///
+    /// The chain bends what is drawn after the timeline and behaviors have moved its instances.
+    /// Returns the chain's number for `set_ik_target` and the `ik` console command, None if the model or an instance doesn't exist.
+    pub fn add_ik_chain(&mut self, model: ModelHandle, chain: IkChain) -> Option<usize> {
+        self.state.add_ik_chain(model, chain)
+    }
+
+    /// Move where an IK chain reaches for, returns false if there's no chain with that number
+    pub fn set_ik_target(&mut self, chain: usize, target: cgmath::Point3<f32>) -> bool {
+        self.state.set_ik_target(chain, target)
+    }
+
+    /// Start or stop recording the window like F9 does, fails if the window can't be read back
+    pub fn set_recording(&mut self, is_recording: bool) -> anyhow::Result<()> {
+        self.state.set_recording(is_recording)
+    }
+
+    /// whether the window is being recorded
+    pub fn is_recording(&self) -> bool {
+        self.state.is_recording()
+    }
+
+    /// Where browsers can watch the window, None unless `AppConfig::remote` has an address that could be listened on
+    pub fn remote_address(&self) -> Option<std::net::SocketAddr> {
+        self.state.remote_address()
+    }
+
+    /// Change the air the world sits in, thick air shows shafts of sunlight
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.state.set_environment(environment);
+    }
+
+    /// Render every camera bookmark to numbered pictures, see `ScreenshotSettings`
+    ///
+    /// Args:
+    ///     poses_file: a file of poses to render instead, one `ex ey ez tx ty tz` per line
+    ///
+    /// Returns how many pictures were rendered
+    pub fn render_gallery(&mut self, poses_file: Option<&Path>) -> anyhow::Result<usize> {
+        let poses = match poses_file {
+            Some(path) => parse_poses(&std::fs::read_to_string(path)?)?,
+            None => self.state.bookmarks().to_vec(),
+        };
+        self.state.render_gallery(&poses)
+    }
+
+    /// Every model loaded into the world, in load order
+    pub fn model_handles(&self) -> Vec<ModelHandle> {
+        self.state.model_handles()
+    }
+
+    /// Render a square preview of a model for asset browsers
+    ///
+    /// The model's bounding box is framed from a 3/4 view with plain lighting and a transparent background.
+    ///
+    /// Args:
+    ///     size: width and height of the picture in pixels
+    pub fn render_thumbnail(&mut self, model: ModelHandle, size: u32) -> anyhow::Result<RgbaImage> {
+        self.state.render_thumbnail(model, size)
+    }
+
+    /// Left clicks on the world since the last call, oldest first, with the model and instance each was on
+    ///
+    /// Clicks on the debug panel and its windows don't count. Only the last 64 are kept between calls.
+    pub fn take_clicks(&mut self) -> Vec<Click> {
+        self.state.take_clicks()
+    }
+
+    /// The ray from the camera through a point of the window, the same one clicks pick with
+    ///
+    /// Args:
+    ///     cursor_px: physical pixels from the window's top left corner, multiply logical positions by the scale factor first
+    pub fn screen_to_ray(&self, cursor_px: (f32, f32)) -> Ray {
+        self.state.screen_to_ray(cursor_px)
+    }
+
+    /// Where a point of the world is drawn, in physical pixels from the window's top left corner, for
+    /// putting labels over things. None when the point is behind the camera.
+    pub fn world_to_screen(&self, point: cgmath::Point3<f32>) -> Option<(f32, f32)> {
+        self.state.world_to_screen(point)
+    }
+
+    /// Where to push RGBA frames for the materials with `map_stream <name>` in their .mtl, from any thread
+    ///
+    /// None if no loaded material streams under that name. Frames have to be the size in the material's
+    /// `video_size`, 640 by 360 without one.
+    pub fn image_stream(&self, name: &str) -> Option<ImageStream> {
+        self.state.image_stream(name)
+    }
+
+    /// The cubes currently picked, in the order they were picked
+    pub fn selection(&self) -> &[EntityId] {
+        self.state.selection()
+    }
+
+    /// Pick cubes as if they had been clicked, ids that aren't cubes of the grid are ignored
+    pub fn set_selection(&mut self, ids: impl IntoIterator<Item = EntityId>) {
+        self.state.set_selection(ids);
+    }
+
+    /// stop after this frame, saving the user's settings first
+    fn request_exit(&mut self) {
+        if !self.exit_requested {
+            self.state.save_settings();
+        }
+        self.exit_requested = true;
+    }
+
+    /// whether the user asked to quit, the host should stop calling the app after this
+    pub fn exit_requested(&self) -> bool {
+        self.exit_requested
+    }
+
+    /// Handle an event from the host's event loop
+    ///
+    /// Redraw requests are left to the host, which decides when to call `update` and `render`
+    pub fn handle_event<T>(&mut self, event: &Event<T>) {
+        match event {
+            // Handle mouse movement separate from window events
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                self.state.process_mouse_movement(delta.0, delta.1);
+            },
+
+            // Handle events in the window
+            Event::WindowEvent {
+                event,
+                window_id,
+            // Make sure the event is in the window and check if the event should be handled by the state instead
+            } if *window_id == self.state.window().id() && !self.state.input(event) => {
+                match event {
+                    // If window close requested, or key pressed then close window
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key: PhysicalKey::Code(KeyCode::Escape),
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    } => self.request_exit(),
+
+                    // If someone tries to resize the window, allow it
+                    WindowEvent::Resized(physical_size) => {
+                        self.state.resize(*physical_size);
+                    },
+
+                    WindowEvent::MouseWheel {
+                        delta,
+                        ..
+                    } => {
+                        self.state.process_mouse_wheel(delta);
+                    },
+
+                    // Catch all
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Advance the world by one frame
+    ///
+    /// Args:
+    ///     dt: time since the last frame, hosts stepping by hand can pass a fixed step
+    pub fn update(&mut self, dt: Duration) {
+        self.state.update(dt);
+    }
+
+    /// Draw the current frame to the window
+    pub fn render(&mut self) {
+        match self.state.render() {
+            Ok(_) => {}
+            // Reconfigure the surface if it's lost or outdated
+            Err(
+                wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated,
+            ) => self.state.resize(self.state.size),
+            // The system is out of memory, we should probably quit
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                log::error!("OutOfMemory");
+                self.request_exit();
+            }
+
+            // This happens when the a frame takes too long to present, quality drops if it keeps happening
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("Surface timeout");
+                self.state.surface_timed_out();
+            }
+        }
+    }
+}
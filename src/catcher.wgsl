@@ -0,0 +1,83 @@
+// Shadow catcher shader, a see-through ground plane that only shows the sun's shadows
+
+// structure to represent the camera, see shader.wgsl
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    near_fade: f32,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// a point light, see light.rs
+struct Light {
+    position: vec4<f32>,
+    color: vec4<f32>,
+};
+
+// every light in the world, see shader.wgsl
+struct LightUniform {
+    lights: array<Light, 4>,
+    count: u32,
+    ambient: f32,
+    light_counts: u32,
+    sun_view_proj: mat4x4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> light_data: LightUniform;
+@group(1) @binding(1)
+var t_shadow: texture_depth_2d;
+@group(1) @binding(2)
+var s_shadow: sampler_comparison;
+
+// where the plane lies, see studio.rs
+struct CatcherUniform {
+    // xyz is the middle of the plane, w the radius
+    center: vec4<f32>,
+    // two directions along the ground
+    right: vec4<f32>,
+    forward: vec4<f32>,
+    // x is how dark a full shadow is drawn
+    params: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> catcher: CatcherUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // -1 to 1 across the plane
+    @location(0) local: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+}
+
+// a square made of two triangles from the vertex index alone
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let local = vec2<f32>(f32(index % 2u) * 2.0 - 1.0, f32(index / 2u) * 2.0 - 1.0);
+    let world_position = catcher.center.xyz + (local.x * catcher.right.xyz + local.y * catcher.forward.xyz) * catcher.center.w;
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.local = local;
+    out.world_position = world_position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // no shadow map was drawn, so there is nothing to catch
+    if light_data.sun_direction.w < 0.5 {
+        return vec4<f32>(0.0);
+    }
+    let light_clip = light_data.sun_view_proj * vec4<f32>(in.world_position, 1.0);
+    let light_ndc = light_clip.xyz / light_clip.w;
+    let uv = vec2<f32>(light_ndc.x * 0.5 + 0.5, light_ndc.y * -0.5 + 0.5);
+    let lit = textureSampleCompareLevel(t_shadow, s_shadow, uv, light_ndc.z);
+    let outside = any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || light_ndc.z > 1.0;
+    let shadow = 1.0 - select(lit, 1.0, outside);
+
+    // fade out towards the edge so the plane itself never shows
+    let edge = 1.0 - smoothstep(0.6, 1.0, length(in.local));
+    return vec4<f32>(0.0, 0.0, 0.0, shadow * edge * catcher.params.x);
+}
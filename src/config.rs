@@ -0,0 +1,58 @@
+//! Options an application picks before the window opens
+use crate::state::{accessibility::AccessibilitySettings, locale::FALLBACK_LOCALE, recorder::RecorderSettings, remote::RemoteSettings, render_settings::RenderSettings, screenshot::ScreenshotSettings, up_axis::UpAxis, world::{environment::Environment, resources::ResourceContext, selection::SnapSettings}, turntable::TurntableSettings, tween::AnimationSettings};
+
+/// Everything that can be configured when starting the program
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    /// graphics options
+    pub render: RenderSettings,
+    /// keyboard rates and hold/toggle behavior
+    pub accessibility: AccessibilitySettings,
+    /// language for on screen text, the name of a file in `res/locale`
+    pub locale: String,
+    /// which way is up in the world, .obj files are turned to match
+    pub up_axis: UpAxis,
+    /// grid, rotation and scale steps edits snap to while Alt is held
+    pub snap: SnapSettings,
+    /// how the camera circles the scene in presentation mode
+    pub turntable: TurntableSettings,
+    /// size and folder of the pictures F12 renders from the camera bookmarks
+    pub screenshot: ScreenshotSettings,
+    /// folder and format of the takes F9 records
+    pub recorder: RecorderSettings,
+    /// address and quality the window is served to browsers with, nothing is served by default. Remote
+    /// input is unauthenticated: anyone who can reach the address can watch and steer the camera
+    pub remote: RemoteSettings,
+    /// seconds transitions like zooming and flying to the help menu take
+    pub animation: AnimationSettings,
+    /// the air the world starts in
+    pub environment: Environment,
+    /// load models from resources.txt again when their files change, on in debug builds
+    pub hot_reload: bool,
+    /// folder the models, scene and other files are loaded from and saved to
+    pub resources: ResourceContext,
+    /// load the user's graphics options, moved keys and window placement from their config folder
+    /// over these settings, and save them there when they change
+    pub user_settings: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            render: RenderSettings::default(),
+            accessibility: AccessibilitySettings::default(),
+            locale: FALLBACK_LOCALE.to_string(),
+            up_axis: UpAxis::default(),
+            snap: SnapSettings::default(),
+            turntable: TurntableSettings::default(),
+            screenshot: ScreenshotSettings::default(),
+            recorder: RecorderSettings::default(),
+            remote: RemoteSettings::default(),
+            animation: AnimationSettings::default(),
+            environment: Environment::default(),
+            hot_reload: cfg!(debug_assertions),
+            resources: ResourceContext::default(),
+            user_settings: true,
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Writing down what was going on when the program panics, and giving the mouse back
+//!
+//! `install` sets a panic hook that shows the cursor again, so a crash doesn't leave it hidden,
+//! and writes a report with the panic, what the state last published with `update` and the
+//! latest log lines to the user's data folder. Panics on the task threads are caught and turned
+//! into errors by `tasks`, so they aren't reported here.
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use winit::window::{CursorGrabMode, Window};
+
+use crate::state::{render_settings::RenderSettings, screenshot::CameraPose};
+
+/// Log lines kept for the report, the oldest drop off
+pub const LOG_LINES: usize = 200;
+
+/// What the report says about the running app, kept up to date by the state
+#[derive(Clone, Debug, Default)]
+pub struct CrashContext {
+    /// name, backend and driver of the GPU
+    pub adapter: Option<String>,
+    pub render: Option<RenderSettings>,
+    /// scene file in the resource folder being edited
+    pub scene_file: Option<String>,
+    pub camera: Option<CameraPose>,
+}
+
+static CONTEXT: Mutex<Option<CrashContext>> = Mutex::new(None);
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+/// window to give the cursor back to
+static WINDOW: Mutex<Option<Arc<Window>>> = Mutex::new(None);
+
+/// Change what the report would say
+pub fn update(change: impl FnOnce(&mut CrashContext)) {
+    change(CONTEXT.lock().unwrap().get_or_insert_with(CrashContext::default));
+}
+
+/// Passes records on to env_logger and keeps the recent ones for the report
+struct RecordingLogger {
+    inner: env_logger::Logger,
+}
+
+impl log::Log for RecordingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        // info and up is kept even when RUST_LOG doesn't print it
+        if record.level() <= log::Level::Info {
+            if let Ok(mut log) = LOG.lock() {
+                if log.len() == LOG_LINES {
+                    log.pop_front();
+                }
+                log.push_back(format!("[{} {}] {}", record.level(), record.target(), record.args()));
+            }
+        }
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Start logging the way `env_logger::init` does, keeping recent lines for the report
+pub fn init_logging() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let level = inner.filter().max(log::LevelFilter::Info);
+    if log::set_boxed_logger(Box::new(RecordingLogger { inner })).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Set the panic hook, the default hook still prints the panic first
+///
+/// Args:
+///     window: the window whose cursor is shown again, None if there isn't one
+pub fn install(window: Option<Arc<Window>>) {
+    *WINDOW.lock().unwrap() = window;
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let is_task = std::thread::current().name().is_some_and(|name| name.starts_with(crate::tasks::THREAD_PREFIX));
+        if is_task {
+            return;
+        }
+
+        // a lock held by the code that panicked is skipped rather than waited on
+        if let Ok(window) = WINDOW.try_lock() {
+            if let Some(window) = window.as_ref() {
+                let _ = window.set_cursor_grab(CursorGrabMode::None);
+                window.set_cursor_visible(true);
+            }
+        }
+        let context = CONTEXT.try_lock().ok().and_then(|context| context.clone()).unwrap_or_default();
+        let log: Vec<String> = LOG.try_lock().map(|log| log.iter().cloned().collect()).unwrap_or_default();
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        let report = report(&info.to_string(), &backtrace.to_string(), &context, &log);
+        match write_report(&report) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Couldn't write the crash report: {}", e),
+        }
+    }));
+}
+
+/// The text of a report
+fn report(panic: &str, backtrace: &str, context: &CrashContext, log: &[String]) -> String {
+    let unknown = || "unknown".to_string();
+    let mut text = String::new();
+    let _ = writeln!(text, "rust3d {} crashed", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(text, "{}\n", panic);
+    let _ = writeln!(text, "adapter: {}", context.adapter.clone().unwrap_or_else(unknown));
+    let _ = writeln!(text, "scene: {}", context.scene_file.clone().unwrap_or_else(unknown));
+    let camera = context.camera.map(|pose| {
+        let (eye, target) = (pose.eye, pose.target);
+        format!("eye {:?} target {:?}", [eye.x, eye.y, eye.z], [target.x, target.y, target.z])
+    });
+    let _ = writeln!(text, "camera: {}", camera.unwrap_or_else(unknown));
+    let render = context.render.as_ref().map(|render| format!("{:#?}", render));
+    let _ = writeln!(text, "render settings: {}\n", render.unwrap_or_else(unknown));
+    let _ = writeln!(text, "last {} log lines:", log.len());
+    for line in log {
+        let _ = writeln!(text, "{}", line);
+    }
+    let _ = writeln!(text, "\nbacktrace:\n{}", backtrace);
+    text
+}
+
+/// Write a report to a new file in the data folder, or the temp folder without one
+fn write_report(report: &str) -> std::io::Result<PathBuf> {
+    let folder = directories::ProjectDirs::from("", "", "rust3d")
+        .map_or_else(std::env::temp_dir, |dirs| dirs.data_local_dir().to_path_buf())
+        .join("crashes");
+    std::fs::create_dir_all(&folder)?;
+    let seconds = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let path = folder.join(format!("crash-{}-{}.txt", seconds, std::process::id()));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let context = CrashContext {
+            adapter: Some("Test GPU (Vulkan)".to_string()),
+            scene_file: Some("scene.txt".to_string()),
+            camera: Some(CameraPose { eye: (0.0, 1.0, 2.0).into(), target: (0.0, 0.0, 0.0).into() }),
+            ..Default::default()
+        };
+        let text = report("panicked at src/state.rs:1:1:\nboom", "0: main", &context, &["[INFO rust3d] loaded".to_string()]);
+        assert!(text.contains("boom"));
+        assert!(text.contains("adapter: Test GPU (Vulkan)"));
+        assert!(text.contains("camera: eye [0.0, 1.0, 2.0] target [0.0, 0.0, 0.0]"));
+        assert!(text.contains("render settings: unknown"));
+        assert!(text.contains("last 1 log lines:\n[INFO rust3d] loaded"));
+    }
+}
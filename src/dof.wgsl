@@ -0,0 +1,82 @@
+// Depth of field shader, blurs the resolved scene by how far each pixel is from the focus distance
+//
+// Each pixel's circle of confusion, how wide a blur it gets, comes from its distance to the camera.
+// Out of focus pixels gather the scene from a disc that size, and only from neighbours whose own
+// blur reaches back to them, so sharp things in front don't smear over the blurred background.
+
+struct DofParams {
+    // units from the camera that are sharp
+    focus_distance: f32,
+    // how quickly the blur grows away from the focus distance
+    aperture: f32,
+    znear: f32,
+    zfar: f32,
+    // the widest blur in pixels
+    max_radius: f32,
+    // 1 when depth runs evenly from near to far, as an orthographic projection leaves it
+    orthographic: f32,
+    _padding0: f32,
+    _padding1: f32,
+};
+@group(0) @binding(0)
+var<uniform> params: DofParams;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+// dof.rs swaps in the multisampled type when MSAA is on
+@group(0) @binding(2)
+var t_depth: texture_depth_2d;
+
+const SAMPLES: u32 = 32u;
+// turning by this much each sample spreads them evenly over the disc
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+// one triangle covering the whole frame
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// distance along the view of what a pixel of the scene shows, the depth buffer may be smaller than the scene
+fn view_distance(pixel: vec2<i32>) -> f32 {
+    let scene_size = vec2<f32>(textureDimensions(t_scene));
+    let depth_size = vec2<i32>(textureDimensions(t_depth));
+    let depth_pixel = min(vec2<i32>((vec2<f32>(pixel) + 0.5) / scene_size * vec2<f32>(depth_size)), depth_size - 1);
+    let depth = textureLoad(t_depth, depth_pixel, 0);
+    let n = params.znear;
+    let f = params.zfar;
+    if params.orthographic > 0.5 {
+        return n + depth * (f - n);
+    }
+    return 2.0 * f * n / ((f + n) - (depth * 2.0 - 1.0) * (f - n));
+}
+
+// radius in pixels of the blur at a distance
+fn circle_of_confusion(distance: f32) -> f32 {
+    let blur = params.aperture * abs(distance - params.focus_distance) / max(distance, 1e-4);
+    return min(blur, 1.0) * params.max_radius;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let size = vec2<i32>(textureDimensions(t_scene));
+    let center = textureLoad(t_scene, pixel, 0).rgb;
+    let radius = circle_of_confusion(view_distance(pixel));
+    if radius < 0.5 {
+        return vec4<f32>(center, 1.0);
+    }
+
+    var sum = center;
+    var weight = 1.0;
+    for (var i = 0u; i < SAMPLES; i = i + 1u) {
+        let distance = sqrt((f32(i) + 0.5) / f32(SAMPLES)) * radius;
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let neighbour = clamp(pixel + vec2<i32>(round(vec2<f32>(cos(angle), sin(angle)) * distance)), vec2<i32>(0), size - 1);
+        // a neighbour only spreads this far if its own blur is at least as wide
+        let reach = clamp(circle_of_confusion(view_distance(neighbour)) - distance + 1.0, 0.0, 1.0);
+        sum += textureLoad(t_scene, neighbour, 0).rgb * reach;
+        weight += reach;
+    }
+    return vec4<f32>(sum / weight, 1.0);
+}
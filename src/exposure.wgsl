@@ -0,0 +1,95 @@
+// Auto-exposure compute shader, a histogram of how bright the HDR frame is and an exposure that adapts to it
+//
+// build_histogram counts the frame's pixels into bins of log2 luminance, then adapt averages
+// the bins, eases the average towards it over time like an eye adjusting, and clears the bins
+// for the next frame. The tone mapping pass reads the exposure back out of `state`.
+
+// how the histogram is laid out and how fast the eye adapts, see exposure.rs
+struct ExposureParams {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // seconds since the last frame
+    dt: f32,
+    adaptation_speed: f32,
+    width: u32,
+    height: u32,
+    min_exposure: f32,
+    max_exposure: f32,
+};
+@group(0) @binding(0)
+var<uniform> params: ExposureParams;
+@group(0) @binding(1)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+
+// the adapted luminance and the exposure that brings it to middle grey
+struct ExposureState {
+    average_luminance: f32,
+    exposure: f32,
+};
+@group(0) @binding(3)
+var<storage, read_write> state: ExposureState;
+
+// the brightness an average frame is exposed to
+const MIDDLE_GREY: f32 = 0.18;
+
+// bin 0 holds black pixels, which say nothing about how bright the scene is
+fn bin_of(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if (luminance < 1e-5) {
+        return 0u;
+    }
+    let t = clamp((log2(luminance) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(t * 254.0 + 1.0);
+}
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+
+// one thread per pixel, counted into the workgroup's bins first so the global ones are touched once per group
+@compute @workgroup_size(16, 16)
+fn build_histogram(@builtin(global_invocation_id) id: vec3<u32>, @builtin(local_invocation_index) index: u32) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+    if (id.x < params.width && id.y < params.height) {
+        let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&local_bins[bin_of(color)], 1u);
+    }
+    workgroupBarrier();
+    atomicAdd(&histogram[index], atomicLoad(&local_bins[index]));
+}
+
+var<workgroup> weighted: array<f32, 256>;
+
+// one workgroup, one thread per bin
+@compute @workgroup_size(256)
+fn adapt(@builtin(local_invocation_index) index: u32) {
+    let count = atomicExchange(&histogram[index], 0u);
+    weighted[index] = f32(count) * f32(index);
+    workgroupBarrier();
+
+    // add the bins up in halves
+    for (var stride = 128u; stride > 0u; stride = stride >> 1u) {
+        if (index < stride) {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (index == 0u) {
+        // thread 0 holds the count of the black bin
+        let lit = f32(params.width * params.height) - f32(count);
+        if (lit < 1.0) {
+            return;
+        }
+        let average_bin = weighted[0] / lit - 1.0;
+        let target_luminance = exp2(average_bin / 254.0 * params.log_luminance_range + params.min_log_luminance);
+
+        // the first frame starts adapted, after that the average eases towards the frame's
+        let previous = state.average_luminance;
+        let blend = 1.0 - exp(-params.dt * params.adaptation_speed);
+        let average = select(mix(previous, target_luminance, blend), target_luminance, previous <= 0.0);
+        state.average_luminance = average;
+        state.exposure = clamp(MIDDLE_GREY / average, params.min_exposure, params.max_exposure);
+    }
+}
@@ -0,0 +1,79 @@
+// FXAA shader, smooths jagged edges in the finished frame by blurring along them
+//
+// A cheaper stand-in for MSAA that works on any frame after tone mapping. Pixels with little contrast
+// around them are left alone, on an edge the direction it runs is estimated from the corners' luma and
+// the frame is sampled a few times along it. Based on Timothy Lottes' FXAA, the small PC version.
+
+@group(0) @binding(0)
+var t_frame: texture_2d<f32>;
+@group(0) @binding(1)
+var s_frame: sampler;
+
+// edges with less contrast than this, relative to their brightest pixel, aren't smoothed
+const EDGE_THRESHOLD: f32 = 0.125;
+// and neither are ones darker than this, where noise would be smoothed as well
+const EDGE_THRESHOLD_MIN: f32 = 0.0312;
+// how far along an edge samples reach, in pixels
+const SPAN_MAX: f32 = 8.0;
+// keep the direction from blowing up on faint edges
+const REDUCE_MUL: f32 = 0.125;
+const REDUCE_MIN: f32 = 0.0078125;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle covering the whole viewport
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn sample_at(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(t_frame, s_frame, uv, 0.0).rgb;
+}
+
+// how bright a color looks, the frame is read in linear light so it is brought back closer to how it is seen
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_frame));
+    let center = textureSampleLevel(t_frame, s_frame, in.uv, 0.0);
+    let luma_m = luma(center.rgb);
+    let luma_nw = luma(sample_at(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_at(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_at(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_at(in.uv + vec2<f32>(1.0, 1.0) * texel));
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+    if luma_max - luma_min < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD) {
+        return center;
+    }
+
+    // the edge runs across the steepest change in luma
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    // two samples close along the edge, and two more further out if they don't cross another edge
+    let near = 0.5 * (sample_at(in.uv + direction * (1.0 / 3.0 - 0.5)) + sample_at(in.uv + direction * (2.0 / 3.0 - 0.5)));
+    let far = near * 0.5 + 0.25 * (sample_at(in.uv - direction * 0.5) + sample_at(in.uv + direction * 0.5));
+    let luma_far = luma(far);
+    if luma_far < luma_min || luma_far > luma_max {
+        return vec4<f32>(near, center.a);
+    }
+    return vec4<f32>(far, center.a);
+}
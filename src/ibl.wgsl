@@ -0,0 +1,142 @@
+// Image based lighting filters, draw one face of a cube map level from an equirectangular HDR image
+//
+// fs_specular blurs the image the way a surface of some roughness reflects it, at roughness 0 it is just
+// the image turned into a cube. fs_irradiance adds up the light arriving at a surface facing each way.
+// Both read blurrier mip levels of the image where samples are spread out, so a few hundred are enough.
+
+// one face of one level, see `FilterUniform` in ibl.rs
+struct FilterUniform {
+    // cube face drawn, in +x, -x, +y, -y, +z, -z order
+    face: u32,
+    // how rough a surface the reflection is for, 0 is a mirror
+    roughness: f32,
+    // width of the image's first mip level
+    source_width: f32,
+    // width of the face drawn
+    target_size: f32,
+};
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> params: FilterUniform;
+
+const PI: f32 = 3.14159265;
+
+// samples each texel of a specular level averages
+const SPECULAR_SAMPLES: u32 = 256u;
+
+// angle between the directions the irradiance adds up
+const IRRADIANCE_STEP: f32 = 0.05;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle covering the whole face
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// direction a texel of a cube face points in, the same as `skybox::face_direction`
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x * 2.0 - 1.0;
+    let v = uv.y * 2.0 - 1.0;
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -v, -u)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -v, u)); }
+        case 2u: { return normalize(vec3<f32>(u, 1.0, v)); }
+        case 3u: { return normalize(vec3<f32>(u, -1.0, -v)); }
+        case 4u: { return normalize(vec3<f32>(u, -v, 1.0)); }
+        default: { return normalize(vec3<f32>(-u, -v, -1.0)); }
+    }
+}
+
+// light arriving from a direction, the image wraps around the horizon with +y at the top
+fn radiance(direction: vec3<f32>, level: f32) -> vec3<f32> {
+    let uv = vec2<f32>(atan2(direction.z, direction.x) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+    return textureSampleLevel(t_source, s_source, uv, level).rgb;
+}
+
+// two directions at right angles to a normal and each other
+fn tangent_frame(n: vec3<f32>) -> mat3x3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    return mat3x3<f32>(tangent, cross(n, tangent), n);
+}
+
+// evenly spread points in the unit square
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// a microfacet normal picked with the GGX distribution, so most samples land where the reflection is
+fn sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return normalize(tangent_frame(n) * vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta));
+}
+
+// GGX/Trowbridge-Reitz, the same as in pbr.wgsl
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+@fragment
+fn fs_specular(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = face_direction(params.face, in.uv);
+    // a face covers a quarter of the image's width
+    let texel_level = max(log2(params.source_width / (4.0 * params.target_size)), 0.0);
+    if params.roughness <= 0.0 {
+        return vec4<f32>(radiance(n, texel_level), 1.0);
+    }
+
+    // the reflection is looked at straight on, so the view direction is the normal
+    let texel_angle = 8.0 * PI / (params.source_width * params.source_width);
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SPECULAR_SAMPLES; i += 1u) {
+        let h = sample_ggx(hammersley(i, SPECULAR_SAMPLES), n, params.roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // read a level whose texels are about as big as the patch this sample stands for
+            let pdf = distribution_ggx(max(dot(n, h), 0.0), params.roughness) / 4.0 + 0.0001;
+            let sample_angle = 1.0 / (f32(SPECULAR_SAMPLES) * pdf);
+            let level = max(0.5 * log2(sample_angle / texel_angle) + 1.0, texel_level);
+            color += radiance(l, level) * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
+
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = tangent_frame(face_direction(params.face, in.uv));
+    // the steps are far apart, read a level blurred about as much
+    let level = max(log2(params.source_width * IRRADIANCE_STEP / (2.0 * PI)), 0.0);
+    var sum = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += IRRADIANCE_STEP) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += IRRADIANCE_STEP) {
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            // light from the side lands at a glancing angle, and there is less sky near the top to sample
+            sum += radiance(frame * local, level) * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    return vec4<f32>(PI * sum / count, 1.0);
+}
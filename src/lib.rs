@@ -1,104 +1,96 @@
 /// Define available library functions and setup our window
+mod app;
+mod config;
+mod crash;
 mod state;
+mod tasks;
+mod time;
+
+pub use app::App;
+pub use config::AppConfig;
+pub use image::RgbaImage;
+pub use state::{
+    accessibility::{AccessibilitySettings, SwitchMode},
+    camera::{Projection, Ray},
+    dof::DofSettings,
+    exposure::AutoExposureSettings,
+    gi::GiSettings,
+    recorder::RecorderSettings,
+    remote::RemoteSettings,
+    render_settings::{RenderOverrides, RenderSettings, RenderView},
+    screenshot::{CameraPose, ScreenshotSettings},
+    tonemap::{ToneMapOperator, ToneMapSettings},
+    turntable::TurntableSettings,
+    tween::AnimationSettings,
+    up_axis::UpAxis,
+    world::{
+        diagnostics::{Issue, Problem, Severity, ValidationReport},
+        environment::{Environment, Fog, FogMode},
+        ik::{solve_two_bone, IkChain, TwoBoneSolution},
+        importer::{register_importer, AssetImporter, ImportedMaterial, ImportedModel},
+        instance::EntityId,
+        model::{ModelVertex, Submesh},
+        resources::{MeshData, ResourceContext, ARCHIVE_VAR, RESOURCE_DIR_VAR},
+        selection::SnapSettings,
+        stl::StlImporter,
+        stats::{ModelStats, WorldStats},
+        stream::ImageStream,
+        ModelHandle,
+        PickHit,
+    },
+    Click,
+};
+
+use std::sync::Arc;
 
 use winit::{
     event::*,
     event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
     window::WindowBuilder,
 };
 
 pub async fn run() {
+    run_with_config(AppConfig::default()).await
+}
+
+/// Open the window and run it with a custom configuration
+pub async fn run_with_config(config: AppConfig) {
     // Window setup...
 
-    env_logger::init();
+    crash::init_logging();
 
     // establish the event loop
     let event_loop = EventLoop::new().unwrap();
 
     // create the window
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let window = Arc::new(WindowBuilder::new().build(&event_loop).unwrap());
+    // a panic from here on writes a crash report and shows the cursor again
+    crash::install(Some(window.clone()));
+
+    // set up the app drawing into the window
+    let mut app = App::new(&window, config).await;
+    let mut clock = time::FrameClock::new();
 
-    // set up the state of the window
-    let mut state = state::State::new(&window).await;
-    
     // here we set what the event loop actually does
     let _ = event_loop.run(move |event, control_flow| {
         match event {
-            // Handle mouse movement separate from window events
-            Event::DeviceEvent {
-                event: DeviceEvent::MouseMotion { delta },
-                ..
-            } => {
-                state.process_mouse_movement(delta.0, delta.1);
-            },
-
-            // Handle events in the window
+            // Event to redraw the screen
             Event::WindowEvent {
-                ref event,
+                event: WindowEvent::RedrawRequested,
                 window_id,
-            // Make sure the event is in the window and check if the event should be handled by the state instead
-            } if window_id == state.window().id() => if !state.input(event) {
-                match event {
-                    // If window close requested, or key pressed then close window
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        event:
-                            KeyEvent {
-                                state: ElementState::Pressed,
-                                physical_key: PhysicalKey::Code(KeyCode::Escape),
-                                repeat: false,
-                                ..
-                            },
-                        ..
-                    } => control_flow.exit(),
+            } if window_id == app.window().id() => {
+                // This tells winit that we want another frame after this one
+                app.window().request_redraw();
 
-                    // If someone tries to resize the window, allow it
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    },
-
-                    WindowEvent::MouseWheel { 
-                        delta,
-                        ..
-                    } => {
-                        state.process_mouse_wheel(delta);
-                    },
-                    
-                    // Event to redraw the screen
-                    WindowEvent::RedrawRequested => {
-                        // This tells winit that we want another frame after this one
-                        state.window().request_redraw();
-
-                        // if !surface_configured {
-                        //     return;
-                        // }
-                        
-                        // update and render the screen
-                        state.update();
-                        match state.render() {
-                            Ok(_) => {}
-                            // Reconfigure the surface if it's lost or outdated
-                            Err(
-                                wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated,
-                            ) => state.resize(state.size),
-                            // The system is out of memory, we should probably quit
-                            Err(wgpu::SurfaceError::OutOfMemory) => {
-                                log::error!("OutOfMemory");
-                                control_flow.exit();
-                            }
-
-                            // This happens when the a frame takes too long to present
-                            Err(wgpu::SurfaceError::Timeout) => {
-                                log::warn!("Surface timeout")
-                            }
-                        }
-                    }
-                    // Catch all
-                    _ => {}
-                }
+                // update and render the screen
+                app.update(clock.tick());
+                app.render();
             }
-            _ => {}
+            _ => app.handle_event(&event),
+        }
+
+        if app.exit_requested() {
+            control_flow.exit();
         }
     });
-}
\ No newline at end of file
+}
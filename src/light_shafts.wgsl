@@ -0,0 +1,106 @@
+// Light shaft shader, the sun's light scattered by the air towards the camera
+//
+// fs_march walks each view ray at half resolution up to the scene's depth, adding up the light
+// the air scatters at every step the shadow map says the sun reaches. fs_composite stretches
+// that back over the scene and adds it on top.
+
+// see light_shafts.rs
+struct ShaftParams {
+    // clip space back to world space, for finding where each ray stops
+    inv_view_proj: mat4x4<f32>,
+    // world space to shadow map clip space
+    sun_view_proj: mat4x4<f32>,
+    // w is how far rays march at most
+    camera_position: vec4<f32>,
+    // direction the sun shines in, w is the density
+    sun_direction: vec4<f32>,
+    // color times intensity, w is how much light scatters onward
+    sun_color: vec4<f32>,
+    // xy the scene's size, zw the half size the rays are marched at
+    size: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> params: ShaftParams;
+
+// march only, light_shafts.rs swaps in the multisampled type when MSAA is on
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var t_shadow: texture_depth_2d;
+@group(0) @binding(3)
+var s_shadow: sampler_comparison;
+
+// composite only
+@group(0) @binding(4)
+var t_shafts: texture_2d<f32>;
+@group(0) @binding(5)
+var s_shafts: sampler;
+
+// steps along each ray, the dither hides the bands between them
+const STEPS: u32 = 32u;
+const PI: f32 = 3.14159265;
+
+// one triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Henyey-Greenstein, how much light turning by an angle with this cosine scatters
+fn phase(cos_theta: f32, g: f32) -> f32 {
+    let g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5));
+}
+
+// 1 where the sun reaches a point, past the edges of the map counts as lit
+fn sun_visibility(world_position: vec3<f32>) -> f32 {
+    let clip = params.sun_view_proj * vec4<f32>(world_position, 1.0);
+    let uv = clip.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || clip.z > 1.0) {
+        return 1.0;
+    }
+    return textureSampleCompareLevel(t_shadow, s_shadow, uv, clip.z);
+}
+
+// a different offset for neighbouring pixels, from Jimenez's interleaved gradient noise
+fn dither(position: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(position, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_march(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = position.xy / params.size.zw;
+    let pixel = min(vec2<i32>(uv * params.size.xy), vec2<i32>(params.size.xy) - 1);
+    let depth = textureLoad(t_depth, pixel, 0);
+
+    // where the ray hits the scene, or the far plane where nothing was drawn
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let hit = params.inv_view_proj * ndc;
+    let to_hit = hit.xyz / hit.w - params.camera_position.xyz;
+    let length_to_hit = length(to_hit);
+    let direction = to_hit / max(length_to_hit, 1e-5);
+    let distance = min(length_to_hit, params.camera_position.w);
+
+    let density = params.sun_direction.w;
+    let step_length = distance / f32(STEPS);
+    let offset = dither(position.xy);
+    var transmittance = 1.0;
+    var scattered = 0.0;
+    for (var i = 0u; i < STEPS; i = i + 1u) {
+        let point = params.camera_position.xyz + direction * (f32(i) + offset) * step_length;
+        scattered += sun_visibility(point) * density * step_length * transmittance;
+        transmittance *= exp(-density * step_length);
+    }
+
+    // the light turns from the sun's direction back along the ray to the camera
+    let cos_theta = dot(params.sun_direction.xyz, -direction);
+    let light = params.sun_color.rgb * phase(cos_theta, params.sun_color.w) * scattered;
+    return vec4<f32>(light, 1.0);
+}
+
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = position.xy / params.size.xy;
+    return vec4<f32>(textureSampleLevel(t_shafts, s_shafts, uv, 0.0).rgb, 0.0);
+}
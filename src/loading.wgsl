@@ -0,0 +1,35 @@
+// Loading screen shader, a progress bar in the middle of the window while the models load
+
+// see loading.rs
+struct LoadingParams {
+    // the window's size in pixels
+    size: vec2<f32>,
+    // how much has loaded, 0 to 1
+    fraction: f32,
+    _padding: f32,
+};
+@group(0) @binding(0)
+var<uniform> params: LoadingParams;
+
+const BACKGROUND: vec3<f32> = vec3<f32>(0.02, 0.02, 0.03);
+const TRACK: vec3<f32> = vec3<f32>(0.08, 0.08, 0.1);
+const FILL: vec3<f32> = vec3<f32>(0.35, 0.55, 0.9);
+
+// one triangle covering the whole window
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // a bar 40% of the window wide and 8 pixels tall, filled from the left
+    let half_size = vec2<f32>(params.size.x * 0.2, 4.0);
+    let from_center = position.xy - params.size * 0.5;
+    if (any(abs(from_center) > half_size)) {
+        return vec4<f32>(BACKGROUND, 1.0);
+    }
+    let along = (from_center.x + half_size.x) / (half_size.x * 2.0);
+    return vec4<f32>(select(TRACK, FILL, along <= params.fraction), 1.0);
+}
@@ -1,7 +1,24 @@
 /// Main entry point for the function
-use rust3d::run;
+use rust3d::{run_with_config, AppConfig};
 
 /// main function to start program
+///
+/// `--res <folder>` loads resources from a folder instead of the one `ResourceContext::from_env` finds,
+/// `--pak <file>` loads them from an archive before the folder, and `--serve <address>` shows the window
+/// to browsers at that address, a port on its own only to this machine
 fn main() {
-    pollster::block_on(run());
+    let mut config = AppConfig::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--res", Some(folder)) => config.resources.root = folder.into(),
+            ("--pak", Some(file)) => config.resources.archive = Some(file.into()),
+            ("--serve", Some(address)) => config.remote.address = Some(address),
+            _ => {
+                eprintln!("usage: rust3d [--res <folder>] [--pak <file>] [--serve <address>]");
+                std::process::exit(2);
+            }
+        }
+    }
+    pollster::block_on(run_with_config(config));
 }
@@ -0,0 +1,130 @@
+// Physically based fragment shader, appended to shader.wgsl and using its inputs and lights
+
+// metallic, roughness and ambient occlusion, each read from the red channel
+@group(0) @binding(5)
+var t_metallic: texture_2d<f32>;
+@group(0) @binding(6)
+var t_roughness: texture_2d<f32>;
+@group(0) @binding(7)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(8)
+var s_pbr: sampler;
+
+const PI: f32 = 3.14159265;
+
+// how a surface looks to the lighting, see `brdf`
+struct PbrSurface {
+    albedo: vec3<f32>,
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    // reflectance looking straight at the surface
+    f0: vec3<f32>,
+};
+
+// GGX/Trowbridge-Reitz, how many microfacets face halfway between the light and the eye
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+// Schlick-GGX, how much of the surface shadows itself at one angle
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Schlick's approximation of how much light is reflected rather than let into the surface
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Cook-Torrance, the light leaving towards the eye for light arriving from one direction
+fn brdf(surface: PbrSurface, light_dir: vec3<f32>, radiance: vec3<f32>) -> vec3<f32> {
+    let n = surface.normal;
+    let half_dir = normalize(light_dir + surface.view_dir);
+    let n_dot_l = max(dot(n, light_dir), 0.0);
+    let n_dot_v = max(dot(n, surface.view_dir), 0.0);
+
+    let d = distribution_ggx(max(dot(n, half_dir), 0.0), surface.roughness);
+    let g = geometry_schlick_ggx(n_dot_v, surface.roughness) * geometry_schlick_ggx(n_dot_l, surface.roughness);
+    let f = fresnel_schlick(max(dot(half_dir, surface.view_dir), 0.0), surface.f0);
+    let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+
+    // whatever isn't reflected is scattered, except by metals which absorb it
+    let diffuse = (1.0 - f) * (1.0 - surface.metallic) * surface.albedo / PI;
+    return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// the environment map reflected in a surface, rougher surfaces reflect a blurrier level of it
+fn environment_specular(surface: PbrSurface) -> vec3<f32> {
+    let n_dot_v = max(dot(surface.normal, surface.view_dir), 0.0);
+    let reflected = reflect(-surface.view_dir, surface.normal);
+    let level = surface.roughness * light_data.environment.y;
+    let radiance = textureSampleLevel(t_specular, s_environment, to_environment(reflected), level).rgb * light_data.environment.x;
+
+    // Karis' fit of the split sum lookup table, how much of the reflection the fresnel and roughness let through
+    let r = surface.roughness * vec4<f32>(-1.0, -0.0275, -0.572, 0.022) + vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let scale_bias = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return radiance * (surface.f0 * scale_bias.x + scale_bias.y);
+}
+
+// every light in the world on one surface, the lights are scaled so a white surface
+// facing one comes out about as bright as with `shade`
+fn shade_pbr(surface: PbrSurface, position: vec3<f32>, occlusion: f32) -> vec3<f32> {
+    var result = ambient_light(position, surface.normal) * tweak_ambient_boost() * surface.albedo * occlusion;
+    if light_data.environment.x > 0.0 {
+        // metals reflect all the light they get instead of scattering it
+        result = result * (1.0 - surface.metallic) + environment_specular(surface) * occlusion;
+    }
+
+    let sun_dir = -normalize(light_data.sun_direction.xyz);
+    let sun = light_data.sun_color.rgb * tweak_sun_tint() * PI * sun_visibility(position, surface.normal);
+    result += brdf(surface, sun_dir, sun);
+
+    for (var i = 0u; i < light_data.count; i += 1u) {
+        let light = light_data.lights[i];
+        let to_light = light.position.xyz - position;
+        let distance = length(to_light);
+        let falloff = clamp(1.0 - pow(distance / light.position.w, 2.0), 0.0, 1.0);
+        result += brdf(surface, to_light / max(distance, 0.0001), light.color.rgb * PI * falloff * falloff);
+    }
+    return result;
+}
+
+@fragment
+fn fs_pbr(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    discard_hidden(in);
+    let color = base_color(in);
+    if light_data.light_counts != 0u {
+        return light_count_color(in.world_position);
+    }
+    let normal = surface_normal(in, front_facing);
+    let metallic = material.metallic * textureSample(t_metallic, s_pbr, in.tex_coords).r;
+    // perfectly smooth surfaces turn lights into invisible points, keep a little roughness
+    let roughness = clamp(material.roughness * textureSample(t_roughness, s_pbr, in.tex_coords).r, 0.04, 1.0);
+    let occlusion = textureSample(t_occlusion, s_pbr, in.tex_coords).r;
+
+    // the insides of closed models are only seen through the cut, fill them so the cut looks solid
+    if !front_facing && camera.section_cap_color.w > 0.5 {
+        return vec4<f32>(camera.section_cap_color.rgb, 1.0);
+    }
+    // meshes without normals can't be lit, show them as they are like `shade` does
+    if length(normal) < 0.0001 {
+        return vec4<f32>(apply_fog(color.rgb, in.world_position), color.a);
+    }
+
+    var surface: PbrSurface;
+    surface.albedo = color.rgb;
+    surface.normal = normalize(normal);
+    surface.view_dir = to_camera(in.world_position);
+    surface.metallic = metallic;
+    surface.roughness = roughness;
+    surface.f0 = mix(vec3<f32>(0.04), color.rgb, metallic);
+    return vec4<f32>(apply_fog(shade_pbr(surface, in.world_position, occlusion), in.world_position), color.a);
+}
@@ -3,6 +3,15 @@
 // structure to represent the camera
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    // geometry closer than this fades out, 0 turns fading off
+    near_fade: f32,
+    // everything past w along the normal in xyz is cut away
+    section_plane: vec4<f32>,
+    // back faces seen through the cut get this color if w is 1
+    section_cap_color: vec4<f32>,
+    // the way the camera looks, w is 1 when the projection is orthographic and every view ray runs along it
+    view_forward: vec4<f32>,
 };
 @group(1) @binding(0) // 1.
 var<uniform> camera: CameraUniform;
@@ -11,11 +20,21 @@ var<uniform> camera: CameraUniform;
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec3<f32>,
+    @location(3) normal: vec3<f32>,
+    // w flips the bitangent, all zero when the mesh has no uvs
+    @location(4) tangent: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) @interpolate(flat) fade: f32,
+    @location(3) color: vec3<f32>,
+    @location(4) world_normal: vec3<f32>,
+    @location(5) world_tangent: vec3<f32>,
+    @location(6) world_bitangent: vec3<f32>,
 }
 
 // structure for instances to translate them
@@ -24,6 +43,8 @@ struct InstanceInput {
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    // share of the dither pattern drawn, negative draws the other part so two detail levels can cross-fade
+    @location(9) fade: f32,
 };
 
 @vertex
@@ -40,9 +61,16 @@ fn vs_main(
 
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.fade = instance.fade;
+    out.color = model.color;
+    out.world_normal = (model_matrix * vec4<f32>(model.normal, 0.0)).xyz;
+    out.world_tangent = (model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz;
+    out.world_bitangent = cross(out.world_normal, out.world_tangent) * model.tangent.w;
     
     // project the vertex onto the camera
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
@@ -54,7 +82,303 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+// per material settings
+struct MaterialUniform {
+    // pixels with less alpha than this are cut out, 0 keeps everything
+    alpha_cutoff: f32,
+    // multiplied with the metallic and roughness maps, only read by pbr.wgsl
+    metallic: f32,
+    roughness: f32,
+};
+@group(0) @binding(2)
+var<uniform> material: MaterialUniform;
+// tangent space normals, flat for materials without a normal map
+@group(0) @binding(3)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(4)
+var s_normal: sampler;
+
+// bend the surface normal by the normal map, meshes without tangents keep theirs
+fn map_normal(normal: vec3<f32>, tangent: vec3<f32>, bitangent: vec3<f32>, tex_coords: vec2<f32>) -> vec3<f32> {
+    let sampled = textureSample(t_normal, s_normal, tex_coords).xyz * 2.0 - 1.0;
+    if length(tangent) < 0.0001 || length(normal) < 0.0001 {
+        return normal;
+    }
+    let tbn = mat3x3<f32>(normalize(tangent), normalize(bitangent), normalize(normal));
+    return tbn * sampled;
+}
+
+// a point light, see light.rs
+struct Light {
+    // xyz is the position, w the range
+    position: vec4<f32>,
+    // color times intensity
+    color: vec4<f32>,
+};
+
+// every light in the world
+struct LightUniform {
+    lights: array<Light, 4>,
+    count: u32,
+    ambient: f32,
+    // 1 colors surfaces by how many lights reach them instead of shading them
+    light_counts: u32,
+    // world space to shadow map clip space
+    sun_view_proj: mat4x4<f32>,
+    // direction the sun shines in, w is 1 if the shadow map was drawn
+    sun_direction: vec4<f32>,
+    // color times intensity
+    sun_color: vec4<f32>,
+    // x scales the environment map's light, 0 without one, y is its last specular level, z is 1 in a Z-up world
+    environment: vec4<f32>,
+    // color far away things fade into, w is the exponential fog's density
+    fog_color: vec4<f32>,
+    // x is where fog starts and y where linear fog ends, z is 0 without fog, 1 for linear and 2 for exponential
+    fog_range: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> light_data: LightUniform;
+// depth of everything as the sun sees it, drawn by shadow.wgsl
+@group(2) @binding(1)
+var t_shadow: texture_depth_2d;
+@group(2) @binding(2)
+var s_shadow: sampler_comparison;
+
+// light probes replacing the flat ambient light, see gi.rs
+struct ProbeGrid {
+    // xyz the first probe's position, w is 1 when the probes are used
+    origin: vec4<f32>,
+    // xyz the distance between neighbouring probes
+    spacing: vec4<f32>,
+    // probes along each axis
+    counts: vec4<u32>,
+    // six colors per probe, the light arriving from +x, -x, +y, -y, +z and -z
+    probes: array<vec4<f32>>,
+};
+@group(2) @binding(4)
+var<storage, read> probe_grid: ProbeGrid;
+
+// light from the surroundings filtered out of an HDR image, see ibl.rs
+@group(2) @binding(5)
+var t_irradiance: texture_cube<f32>;
+// blurrier at each level, for rougher surfaces
+@group(2) @binding(6)
+var t_specular: texture_cube<f32>;
+@group(2) @binding(7)
+var s_environment: sampler;
+
+// numbers for tuning while the program runs, see tweaks.rs, each is read with tweak_<name>()
+// tweak float ambient_boost 1.0 0.0 4.0
+// tweak color sun_tint 1.0 1.0 1.0
+// tweak float specular_strength 0.25 0.0 2.0
+// tweak float shininess 32.0 1.0 256.0
+struct TweakUniform {
+    values: array<vec4<f32>, 16>,
+};
+@group(2) @binding(3)
+var<uniform> tweaks: TweakUniform;
+
+// how much of the sun reaches a point, 0 in full shadow and 1 in full light
+fn sun_visibility(position: vec3<f32>, n: vec3<f32>) -> f32 {
+    if light_data.sun_direction.w < 0.5 {
+        return 1.0;
+    }
+    // nudge the point off the surface so it doesn't shadow itself
+    let offset_position = position + n * 0.02;
+    let light_clip = light_data.sun_view_proj * vec4<f32>(offset_position, 1.0);
+    let light_ndc = light_clip.xyz / light_clip.w;
+    // texture coordinates have y pointing down
+    let uv = vec2<f32>(light_ndc.x * 0.5 + 0.5, light_ndc.y * -0.5 + 0.5);
+    let lit = textureSampleCompareLevel(t_shadow, s_shadow, uv, light_ndc.z);
+    // outside the map there is nothing to cast a shadow
+    let outside = any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || light_ndc.z > 1.0;
+    return select(lit, 1.0, outside);
+}
+
+// the light a probe's ambient cube gives a surface facing along a normal
+fn probe_irradiance(probe: u32, n: vec3<f32>) -> vec3<f32> {
+    let base = probe * 6u;
+    let x = select(probe_grid.probes[base + 1u], probe_grid.probes[base], n.x > 0.0).rgb;
+    let y = select(probe_grid.probes[base + 3u], probe_grid.probes[base + 2u], n.y > 0.0).rgb;
+    let z = select(probe_grid.probes[base + 5u], probe_grid.probes[base + 4u], n.z > 0.0).rgb;
+    let n2 = n * n;
+    return n2.x * x + n2.y * y + n2.z * z;
+}
+
+// a direction in the world turned the way the environment map is, which is Y-up
+fn to_environment(direction: vec3<f32>) -> vec3<f32> {
+    return select(direction, vec3<f32>(direction.x, direction.z, -direction.y), light_data.environment.z > 0.5);
+}
+
+// light that doesn't come straight from a light, blended from the eight probes around the point when
+// they are on, otherwise from the environment map or flat if there isn't one
+fn ambient_light(position: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    if probe_grid.origin.w < 0.5 {
+        if light_data.environment.x > 0.0 {
+            return textureSampleLevel(t_irradiance, s_environment, to_environment(n), 0.0).rgb * light_data.environment.x;
+        }
+        return vec3<f32>(light_data.ambient);
+    }
+    // past the edges of the grid the closest probes are used
+    let counts = vec3<i32>(probe_grid.counts.xyz);
+    let cell = clamp((position - probe_grid.origin.xyz) / probe_grid.spacing.xyz, vec3<f32>(0.0), vec3<f32>(counts - 1));
+    let base = min(vec3<i32>(floor(cell)), counts - 2);
+    let t = cell - vec3<f32>(base);
+    var result = vec3<f32>(0.0);
+    for (var corner = 0u; corner < 8u; corner += 1u) {
+        let offset = vec3<u32>(corner & 1u, (corner >> 1u) & 1u, (corner >> 2u) & 1u);
+        let weights = select(1.0 - t, t, offset == vec3<u32>(1u));
+        let probe = base + vec3<i32>(offset);
+        let index = probe.x + (probe.y + probe.z * counts.y) * counts.x;
+        result += weights.x * weights.y * weights.z * probe_irradiance(u32(index), n);
+    }
+    return result;
+}
+
+// direction from a point back to the camera, the same everywhere for an orthographic camera
+fn to_camera(position: vec3<f32>) -> vec3<f32> {
+    if camera.view_forward.w > 0.5 {
+        return -camera.view_forward.xyz;
+    }
+    return normalize(camera.view_position.xyz - position);
+}
+
+// how much light reaches a point, ambient plus every point light
+fn shade(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    // meshes without normals can't be lit, show them as they are
+    if length(normal) < 0.0001 {
+        return vec3<f32>(1.0);
+    }
+    let n = normalize(normal);
+    let view_dir = to_camera(position);
+
+    var result = ambient_light(position, n) * tweak_ambient_boost();
+
+    // the sun only lights the sides facing it, and only where nothing is in the way
+    let sun_dir = -normalize(light_data.sun_direction.xyz);
+    let sun_diffuse = max(dot(n, sun_dir), 0.0);
+    result += light_data.sun_color.rgb * tweak_sun_tint() * sun_diffuse * sun_visibility(position, n);
+
+    for (var i = 0u; i < light_data.count; i += 1u) {
+        let light = light_data.lights[i];
+        let to_light = light.position.xyz - position;
+        let distance = length(to_light);
+        let light_dir = to_light / max(distance, 0.0001);
+
+        // fade smoothly to nothing at the light's range
+        let falloff = clamp(1.0 - pow(distance / light.position.w, 2.0), 0.0, 1.0);
+        let diffuse = max(dot(n, light_dir), 0.0);
+        // a small blinn-phong highlight, only on the lit side
+        let half_dir = normalize(light_dir + view_dir);
+        let specular = pow(max(dot(n, half_dir), 0.0), tweak_shininess()) * tweak_specular_strength() * select(0.0, 1.0, diffuse > 0.0);
+
+        result += light.color.rgb * (diffuse + specular) * falloff * falloff;
+    }
+    return result;
+}
+
+// blue where no light reaches, through green and yellow to red where every light does
+fn light_count_color(position: vec3<f32>) -> vec4<f32> {
+    var reached = 0u;
+    for (var i = 0u; i < light_data.count; i += 1u) {
+        let light = light_data.lights[i];
+        reached += select(0u, 1u, distance(light.position.xyz, position) < light.position.w);
+    }
+    let t = f32(reached) / 4.0;
+    let heat = clamp(vec3<f32>(2.0 * t - 0.5, 2.0 - abs(4.0 * t - 2.0), 1.0 - 2.0 * t), vec3<f32>(0.0), vec3<f32>(1.0));
+    return vec4<f32>(heat, 1.0);
+}
+
+// 4x4 ordered dither threshold for a pixel, lets us fade things out without blending
+fn dither_threshold(pixel: vec2<f32>) -> f32 {
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    let index = u32(pixel.x) % 4u + (u32(pixel.y) % 4u) * 4u;
+    return (bayer[index] + 0.5) / 16.0;
+}
+
+// drop the pixels that shouldn't be drawn, shared by every fragment shader
+fn discard_hidden(in: VertexOutput) {
+    // cut away the side of the section plane its normal points to
+    if dot(in.world_position, camera.section_plane.xyz) > camera.section_plane.w {
+        discard;
+    }
+
+    // drop more and more pixels as geometry gets close to the camera so it doesn't clip through the near plane
+    let distance = length(in.world_position - camera.view_position.xyz);
+    if camera.near_fade > 0.0 && distance / camera.near_fade < dither_threshold(in.clip_position.xy) {
+        discard;
+    }
+
+    // instances switching detail level split the pixels between both levels
+    let threshold = dither_threshold(in.clip_position.xy);
+    if (in.fade >= 0.0 && threshold >= in.fade) || (in.fade < 0.0 && threshold < -in.fade) {
+        discard;
+    }
+}
+
+// a lit color faded into the fog by how far the point is from the camera
+fn apply_fog(color: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    let mode = light_data.fog_range.z;
+    if mode < 0.5 {
+        return color;
+    }
+    let past_start = max(length(world_position - camera.view_position.xyz) - light_data.fog_range.x, 0.0);
+    var amount: f32;
+    if mode < 1.5 {
+        amount = past_start / max(light_data.fog_range.y - light_data.fog_range.x, 1e-4);
+    } else {
+        amount = 1.0 - exp(-light_data.fog_color.w * past_start);
+    }
+    return mix(color, light_data.fog_color.rgb, clamp(amount, 0.0, 1.0));
+}
+
+// the texture tinted by the vertex colors, dropping the pixel if the material cuts it out
+fn base_color(in: VertexOutput) -> vec4<f32> {
+    // set the color based of the texture coordinates, tinted by the vertex colors
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords) * vec4<f32>(in.color, 1.0);
+
+    // cutout materials drop see through pixels instead of blending them
+    if color.a < material.alpha_cutoff {
+        discard;
+    }
+    return color;
+}
+
+// the normal facing the camera, bent by the normal map
+fn surface_normal(in: VertexOutput, front_facing: bool) -> vec3<f32> {
+    // double sided materials show their back faces, which should face the camera too
+    let normal = map_normal(in.world_normal, in.world_tangent, in.world_bitangent, in.tex_coords);
+    return select(-normal, normal, front_facing);
+}
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords); // set the color based of the texture coordinates
-}
\ No newline at end of file
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    discard_hidden(in);
+    let normal = surface_normal(in, front_facing);
+    let color = base_color(in);
+    if light_data.light_counts != 0u {
+        return light_count_color(in.world_position);
+    }
+
+    // the insides of closed models are only seen through the cut, fill them so the cut looks solid
+    if !front_facing && camera.section_cap_color.w > 0.5 {
+        return vec4<f32>(camera.section_cap_color.rgb, 1.0);
+    }
+    return vec4<f32>(apply_fog(color.rgb * shade(in.world_position, normal), in.world_position), color.a);
+}
+
+// how dark a full shadow is on shadow catcher materials
+const SHADOW_CATCHER_DARKNESS: f32 = 0.6;
+
+// shadow catchers can't be seen themselves, the sun's shadows on them darken whatever is behind
+@fragment
+fn fs_shadow_catcher(in: VertexOutput) -> @location(0) vec4<f32> {
+    discard_hidden(in);
+    let shadow = 1.0 - sun_visibility(in.world_position, in.world_normal);
+    return vec4<f32>(vec3<f32>(1.0 - shadow * SHADOW_CATCHER_DARKNESS), 1.0);
+}
@@ -0,0 +1,46 @@
+// Shadow map pass, draws the depth of everything as the sun sees it
+
+// a point light, see light.rs
+struct Light {
+    position: vec4<f32>,
+    color: vec4<f32>,
+};
+
+// same layout as shader.wgsl, only the sun's matrix is used here
+struct LightUniform {
+    lights: array<Light, 4>,
+    count: u32,
+    ambient: f32,
+    sun_view_proj: mat4x4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> light_data: LightUniform;
+
+// Structure for vertex, only the position matters
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+}
+
+// structure for instances to translate them
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput
+) -> @builtin(position) vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return light_data.sun_view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+}
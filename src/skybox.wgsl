@@ -0,0 +1,40 @@
+// Skybox shader, fills everything the world didn't draw over with a cube map
+
+// where the camera looks, already turned so the sky's +y is up
+struct SkyUniform {
+    // direction through the center of the screen
+    forward: vec4<f32>,
+    // added per unit of screen x, includes the field of view and aspect ratio
+    right: vec4<f32>,
+    // added per unit of screen y, includes the field of view
+    up: vec4<f32>,
+    // x is the mip level to sample, higher levels are blurrier
+    blur: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> sky: SkyUniform;
+@group(0) @binding(1)
+var t_sky: texture_cube<f32>;
+@group(0) @binding(2)
+var s_sky: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) screen: vec2<f32>,
+}
+
+// one triangle covering the screen, at the far plane so anything drawn stays in front
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let screen = vec2<f32>(f32(index / 2u) * 4.0 - 1.0, f32(index % 2u) * 4.0 - 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(screen, 1.0, 1.0);
+    out.screen = screen;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = sky.forward.xyz + in.screen.x * sky.right.xyz + in.screen.y * sky.up.xyz;
+    return textureSampleLevel(t_sky, s_sky, direction, sky.blur.x);
+}
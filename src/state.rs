@@ -1,46 +1,273 @@
-/// File to represent the overall state of the current window
+//! File to represent the overall state of the current window
 
-mod camera;
+pub mod accessibility;
+mod asset_browser;
+mod axis_gizmo;
+mod bind_groups;
+pub mod camera;
 mod camera_controller;
-mod world;
+mod debug_ui;
+pub mod dof;
+mod easing;
+pub mod exposure;
+mod console;
+mod curve_editor;
+mod data_binding;
+mod frame_stats;
+mod fxaa;
+pub mod gi;
+mod hot_reload;
+mod ibl;
+mod inspector;
+mod key_bindings;
+pub mod light;
+mod light_shafts;
+mod lines;
+mod loading;
+mod recent;
+mod render_graph;
+pub mod world;
+pub mod locale;
 mod mouse_grabber;
+mod mqtt;
+mod palette;
+mod quantize;
+mod readback;
+pub mod recorder;
+pub mod remote;
+pub mod screenshot;
+mod section;
+mod settings;
+mod studio;
+mod submit;
+pub mod render_settings;
+mod thumbnail;
+mod timeline;
+pub mod tonemap;
+pub mod tween;
+mod tweaks;
+mod upscale;
+mod watchdog;
+pub mod turntable;
+pub mod up_axis;
+#[cfg(feature = "debug-validation")]
+mod validation;
 
-use std::rc::Rc;
+use std::{collections::VecDeque, sync::Arc};
 
+use cgmath::InnerSpace;
+
+use asset_browser::{AssetBrowser, SpawnRequest, THUMBNAIL_SIZE};
+use bind_groups::BindGroupCache;
+use debug_ui::{DebugUi, DebugValues};
+use exposure::AutoExposure;
+use light_shafts::LightShafts;
+use render_graph::{RenderGraph, TextureDesc, TexturePool};
+use frame_stats::FrameStats;
+use gi::ProbeGrid;
+use hot_reload::HotReload;
+use lines::{LineList, LineRenderer};
+use loading::{LoadingScreen, ModelLoader, ModelTask, LOADING_FRAME};
+use key_bindings::{KeyBindings, KEY_BINDINGS_FILE};
+use locale::Strings;
 use mouse_grabber::{MouseGrabber};
+use palette::{PalettePreset, Theme};
+use recent::{RecentFiles, RecentKind, RECENT_FILE};
+use data_binding::{BindingConfig, DataBindings, BINDINGS_FILE};
+use recorder::Recorder;
+use remote::{RemoteInput, RemoteViewer};
+use screenshot::{CameraPose, Gallery, OffscreenTarget, ScreenshotSettings, BOOKMARKS_FILE};
+use section::SectionPlane;
+use settings::{SettingsSaver, UserSettings, WindowPlacement};
+use studio::{Studio, STUDIO_SKY_BLUR};
+use timeline::{CutscenePlayer, Timeline, CUTSCENE_FILE};
+use turntable::Turntable;
+use tonemap::{HdrFrame, ToneMapper, HDR_FORMAT};
+use tweaks::Tweaks;
+use upscale::{DynamicResolution, Upscaler};
+use watchdog::{Fallback, GpuWatchdog};
 use wgpu::util::DeviceExt;
-use winit::{event::{MouseScrollDelta, WindowEvent}, window::Window};
+use winit::{event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent}, window::Window};
+
+use crate::config::AppConfig;
+use render_settings::{RenderSettings, RenderView, ViewSettings};
+use world::{behavior::BehaviorContext, diagnostics::ValidationReport, environment::Environment, instance::{Instance, InstanceRaw}, model::{self, DrawModel, MaterialPipelines, PipelineVariant, Vertex, ViewContext}, skybox::Skybox, stats::WorldStats, texture, DrawWorld, ModelHandle, PickHit, World};
+
+/// Clicks kept for the host to take, older ones are dropped so hosts that never take them don't grow the list
+const CLICK_HISTORY: usize = 64;
+
+/// A left click that reached the world, and what it was on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Click {
+    /// where in the window it was, in pixels from the top left, the center while the mouse is locked
+    pub position: (f32, f32),
+    /// None if it was on nothing but sky
+    pub hit: Option<PickHit>,
+}
 
-use world::{instance::InstanceRaw, model::{self, Vertex}, texture, DrawWorld, World};
+/// A file `State::open_file` is loading on the task threads
+enum Opening {
+    Scene(crate::tasks::Task<anyhow::Result<String>>),
+    Model(ModelTask),
+}
 
 /// structure to store the sate of the window/frame
 pub struct State<'a> {
     pub size: winit::dpi::PhysicalSize<u32>,
     surface: wgpu::Surface<'a>,
-    device: Rc<wgpu::Device>,
-    queue: wgpu::Queue,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
     config: wgpu::SurfaceConfiguration,
+    /// graphics options the program was started with
+    render_settings: RenderSettings,
     /// describe how we render things
-    render_pipeline: wgpu::RenderPipeline,
+    pipelines: MaterialPipelines,
     camera: camera::Camera,
     camera_uniform: camera::CameraUniform,
     camera_buffer: wgpu::Buffer,
     pub camera_controller: camera_controller::CameraController,
     camera_bind_group: wgpu::BindGroup,
+    light_uniform: light::LightUniform,
+    light_buffer: wgpu::Buffer,
+    /// numbers the shaders declared for tuning, changed from the debug panel and its console
+    tweaks: Tweaks,
+    tweak_buffer: wgpu::Buffer,
+    /// color surfaces by how many lights reach them and outline each light's range, to see where lights pile up
+    show_light_counts: bool,
+    /// outline every instance's bounding box, the box picking tests against
+    show_bounds: bool,
+    /// the world's axes in the window's corner, turning with the camera
+    axis_gizmo: axis_gizmo::AxisGizmo,
+    show_axis_gizmo: bool,
+    /// light probes the shaders use instead of flat ambient light while global illumination is on
+    probe_grid: ProbeGrid,
+    environment_map: ibl::EnvironmentMap,
+    /// watches the files of the models from resources.txt, None when hot reload is off
+    hot_reload: Option<HotReload>,
+    /// models from resources.txt loading again after their files changed, with their index and line
+    reloading: Vec<(usize, String, ModelTask)>,
+    /// what is saved for the user, brought up to date before saving
+    user_settings: UserSettings,
+    /// None when the app was configured not to keep the user's settings
+    settings_saver: Option<SettingsSaver>,
+    /// where the cursor was last seen in the window
+    cursor_position: (f32, f32),
+    /// the part of the window the scene is drawn in, all of it unless the aspect ratio is fixed
+    viewport: camera::Viewport,
+    /// clicks since the host last took them, the last `CLICK_HISTORY` of them
+    clicks: VecDeque<Click>,
+    light_bind_group: wgpu::BindGroup,
+    /// depth of the world seen from the sun, read back by the main pass
+    shadow_map: texture::Texture,
+    /// draws the world's depth into the shadow map, None when shadows are off
+    shadow_pipeline: Option<wgpu::RenderPipeline>,
+    shadow_bind_group: wgpu::BindGroup,
     depth_texture: texture::Texture,
+    /// the depth alone, for passes reading it after the main pass
+    depth_sample_view: wgpu::TextureView,
+    /// groups binding resources that are made again now and then, like the depth buffer
+    bind_groups: BindGroupCache,
+    /// multisampled color the main pass draws into before it is resolved to the surface, None without MSAA
+    msaa_target: Option<wgpu::TextureView>,
+    /// how much smaller than the window the scene is drawn when frames run slow
+    dynamic_resolution: DynamicResolution,
+    /// turns quality down when frames hang
+    watchdog: GpuWatchdog,
+    /// stretches the smaller frame back over the window
+    upscaler: Upscaler,
+    /// the sun's light through thick air, drawn when the world's environment has any
+    light_shafts: LightShafts,
+    /// exposes the HDR frame into the window
+    tone_mapper: ToneMapper,
+    /// blurs the scene by its depth when `render_settings.depth_of_field` is on
+    depth_of_field: dof::DepthOfField,
+    /// smooths edges in the tone mapped frame when `render_settings.fxaa` is on
+    fxaa: fxaa::Fxaa,
+    /// textures the render graph made for the passes after the scene, kept for the next frame
+    graph_textures: TexturePool,
+    /// what the scene is drawn into before tone mapping
+    hdr_frame: HdrFrame,
+    /// lines drawn over the world by tools
+    lines: LineRenderer,
+    /// drawn behind the world
+    skybox: Skybox,
+    studio: Studio,
+    /// egui panel for changing settings without keys
+    debug_ui: DebugUi,
+    /// models in the res folder that can be placed from the debug UI
+    asset_browser: AssetBrowser,
+    /// the asset browser's model loading on the task threads, with its index in the list
+    asset_loading: Option<(usize, ModelTask)>,
+    /// files `open_file` is loading on the task threads, with their names
+    opening: Vec<(String, Opening)>,
+    /// scenes and models opened lately, reopened from the F7 window or the console
+    recent: RecentFiles,
+    /// layout of the material bind groups, for models loaded after startup
+    texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// recent frame times for the stats overlay
+    frame_stats: FrameStats,
     world: World,
     mouse_grabber: MouseGrabber,
+    cutscene: CutscenePlayer,
+    turntable: Turntable,
+    /// camera bookmarks and where pictures of them go
+    gallery: Gallery,
+    screenshot_settings: ScreenshotSettings,
+    /// F9 records the window to pictures or a video
+    recorder: Recorder,
+    /// serves the window to browsers, if the config gave it an address
+    remote: RemoteViewer,
+    /// values from MQTT topics moving cubes, see `res/bindings.txt`
+    data_bindings: DataBindings,
+    theme: Theme,
+    /// plane cutting the world open
+    section: SectionPlane,
+    /// translated on screen text
+    strings: Strings,
+    /// title to go back to when no caption is shown
+    window_title: String,
+    #[cfg(feature = "debug-validation")]
+    validation_errors: std::sync::Arc<std::sync::Mutex<validation::ErrorCapture>>,
     // The window must be declared after the surface so
     // it gets dropped after it as the surface contains
     // unsafe references to the window's resources.
     window: &'a Window,
 }
 
+// models are loaded and the world is moved between threads, so nothing in them may be tied to one
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<State<'static>>();
+    assert_send::<World>();
+};
+
 impl<'a> State<'a> {
     // Creating some of the wgpu types requires async code
 
     /// create a new state object for a window
-    pub async fn new(window: &'a Window) -> State<'a> {
+    pub async fn new(window: &'a Window, mut app_config: AppConfig) -> State<'a> {
+        // everything below loads from the configured folder
+        app_config.resources.install();
+        log::info!("Loading resources from {}", app_config.resources.root.display());
+
+        // the user's own graphics options, keys and window placement go over the configured ones
+        let defaults = UserSettings::new(app_config.render.clone(), app_config.accessibility.mouse_sensitivity);
+        let (user_settings, settings_saver) = match settings::settings_path().filter(|_| app_config.user_settings) {
+            Some(path) => {
+                let (user_settings, saver) = SettingsSaver::load(path, defaults);
+                (user_settings, Some(saver))
+            }
+            None => (defaults, None),
+        };
+        app_config.render = user_settings.render.clone();
+        app_config.accessibility.mouse_sensitivity = user_settings.mouse_sensitivity;
+        if let Some(placement) = user_settings.window {
+            let _ = window.request_inner_size(placement.size);
+            if let Some(position) = placement.position {
+                window.set_outer_position(position);
+            }
+        }
+
         // set the size
         let size = window.inner_size();
 
@@ -48,6 +275,9 @@ impl<'a> State<'a> {
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::PRIMARY,
+            // validation is normally only on in debug builds
+            #[cfg(feature = "debug-validation")]
+            flags: wgpu::InstanceFlags::VALIDATION | wgpu::InstanceFlags::DEBUG,
             ..Default::default()
         });
         
@@ -62,11 +292,37 @@ impl<'a> State<'a> {
                 force_fallback_adapter: false,
             },
         ).await.unwrap();
+        let info = adapter.get_info();
+        crate::crash::update(|context| {
+            context.adapter = Some(format!("{} ({:?}, {} {})", info.name, info.backend, info.driver, info.driver_info));
+        });
+
+        // pick the depth buffer format now, some need a device feature turned on
+        let depth_format = app_config.render.choose_depth_format(|format| {
+            adapter.features().contains(format.required_features())
+                && adapter.get_texture_format_features(format).allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        });
+
+        // returns what the surface can do/our available operations with the present GPU
+        let surface_caps = surface.get_capabilities(&adapter);
+
+        // configure our surface to be an sRGB surface texture, or a float one for HDR
+        let surface_format = app_config.render.choose_surface_format(&surface_caps.formats);
+
+        // pick the MSAA sample count, every format can do 4x but other counts need an adapter feature.
+        // The scene is multisampled in HDR before it is tone mapped into the surface, so that's the format to check
+        let adapter_specific = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        let msaa_samples = app_config.render.choose_msaa_samples(|samples| {
+            (samples == 4 || adapter.features().contains(adapter_specific))
+                && [HDR_FORMAT, depth_format].iter().all(|format| adapter.get_texture_format_features(*format).flags.sample_count_supported(samples))
+        });
+        let msaa_features = if msaa_samples == 1 || msaa_samples == 4 { wgpu::Features::empty() } else { adapter_specific };
 
         // Set up our interface with our GPU to interact with it
         let (device_obj, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
+                // compressed textures load whenever the adapter can sample their format
+                required_features: depth_format.required_features() | msaa_features | (adapter.features() & texture::COMPRESSION_FEATURES),
                 required_limits: wgpu::Limits::default(),
                 label: None,
                 memory_hints: Default::default(),
@@ -75,27 +331,24 @@ impl<'a> State<'a> {
         ).await.unwrap();
 
         // put device onto the heap so we can share ownership
-        let device = Rc::new(device_obj);
+        let device = Arc::new(device_obj);
+        let queue = Arc::new(queue);
 
-        // returns what the surface can do/our available operations with the present GPU
-        let surface_caps = surface.get_capabilities(&adapter);
-
-        // configure our surface to be an sRGB surface texture
-        let surface_format = surface_caps.formats.iter()
-            .find(|f| f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
+        // catch validation errors before anything else is created
+        #[cfg(feature = "debug-validation")]
+        let validation_errors = validation::install(&device);
 
         // Configure our surface size and refresh rate
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // frames are copied out of the window while recording, where the platform allows it
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC),
             format: surface_format,
             width: size.width,
             height: size.height,
             present_mode: surface_caps.present_modes[0],
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
-            desired_maximum_frame_latency: 2,
+            desired_maximum_frame_latency: model::FRAMES_IN_FLIGHT as u32,
         };
         
         // Textures:
@@ -121,24 +374,83 @@ impl<'a> State<'a> {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        // material settings like the alpha cutoff
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // the normal map and its sampler
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    // metallic, roughness and ambient occlusion maps for the pbr pipeline, with one sampler
+                    pbr_map_entry(5),
+                    pbr_map_entry(6),
+                    pbr_map_entry(7),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
                 ],
                 label: Some("texture_bind_group_layout"),
             });
+        let texture_bind_group_layout = Arc::new(texture_bind_group_layout);
+
+        // load the models on the task threads, showing how far along they are until they're all in
+        let mut loader = ModelLoader::start(&device, &queue, &texture_bind_group_layout, app_config.up_axis).await.expect("couldn't read resources.txt");
+        let loading_screen = LoadingScreen::new(&device, config.format);
+        let is_shown = size.width > 0 && size.height > 0;
+        if is_shown {
+            surface.configure(&device, &config);
+        }
+        while !loader.poll().expect("couldn't load a model from resources.txt") {
+            if is_shown {
+                if let Err(e) = loading_screen.draw(&surface, &device, &queue, size, loader.fraction()) {
+                    log::warn!("Couldn't draw the loading screen: {}", e);
+                }
+            }
+            std::thread::sleep(LOADING_FRAME);
+        }
         
+        // the scene keeps its aspect ratio if it was given one, drawn in the middle of the window
+        let viewport = camera::Viewport::letterboxed(config.width, config.height, app_config.render.fixed_aspect);
+
         // setting up the camera
         // Here is the user friendly info
         let camera = camera::Camera {
             // position the camera 1 unit up and 2 units back
-            // +z is out of the screen
-            eye: (0.0, 1.0, 2.0).into(),
+            // +z is out of the screen when y is up
+            eye: app_config.up_axis.point_from_y_up((0.0, 1.0, 2.0).into()),
             // have it look at the origin
             target: (0.0, 0.0, 0.0).into(),
             // which way is "up"
-            up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
+            up: app_config.up_axis.up(),
+            aspect: viewport.width as f32 / viewport.height as f32,
             fovy: 45.0,
             znear: 0.1,
             zfar: 100.0,
+            projection: camera::Projection::Perspective,
         };
 
         // This stores the main camera matrix
@@ -155,14 +467,27 @@ impl<'a> State<'a> {
         );
 
         // set up a controller to control the camera
-        let camera_controller = camera_controller::CameraController::new(0.05);
+        let accessibility = app_config.accessibility;
+        // the keys the camera and the world listen to, the defaults if there's no bindings file or it's broken
+        let bindings = match world::resources::load_string(&KEY_BINDINGS_FILE).await.and_then(|text| KeyBindings::parse(&text)) {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                log::warn!("Couldn't load key bindings, using the defaults: {}", e);
+                KeyBindings::default()
+            }
+        };
+        let bindings = bindings.with_overrides(&user_settings.bindings).unwrap_or_else(|e| {
+            log::warn!("Couldn't move the keys from the settings: {}", e);
+            bindings
+        });
+        let camera_controller = camera_controller::CameraController::new(3.0, accessibility, bindings.clone(), app_config.up_axis, app_config.animation);
 
         // set up the camera bind group memory layout
         let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -186,13 +511,217 @@ impl<'a> State<'a> {
             label: Some("camera_bind_group"),
         });
 
+        // the lights are filled in from the world once it's loaded
+        let light_uniform = light::LightUniform::new(&[], light::DEFAULT_AMBIENT, None, false);
+        let light_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Light Buffer"),
+                contents: bytemuck::cast_slice(&[light_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        // a 1x1 map stands in when shadows are off so the bind group stays the same
+        let shadow_map = texture::Texture::create_shadow_map(&device, app_config.render.shadow_map_size, "shadow_map");
+
+        // tweaks the shaders declare, read through functions added to the shader below
+        let shader_source = concat!(include_str!("shader.wgsl"), "\n", include_str!("pbr.wgsl"));
+        let tweaks = Tweaks::new(tweaks::parse_tweaks(shader_source).expect("shader.wgsl declares a tweak it can't have"));
+        let tweak_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Tweak Buffer"),
+                contents: bytemuck::cast_slice(&[tweaks.to_uniform()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        // probes for global illumination, only traced while it is switched on
+        let probe_grid = ProbeGrid::new(&device, app_config.render.global_illumination);
+
+        // ambient light and reflections from the surroundings, filtered from an HDR image
+        let environment_map = ibl::EnvironmentMap::load(&device, &queue).await;
+
+        // the lights, the shadow map, the tweaks, the probes and the environment are read by the fragment shader
+        let light_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("light_bind_group_layout"),
+        });
+
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &light_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: tweak_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: probe_grid.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&environment_map.irradiance),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&environment_map.specular),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::Sampler(&environment_map.sampler),
+                },
+            ],
+            label: Some("light_bind_group"),
+        });
+
+        // the shadow pass only needs the sun's matrix, which lives in the light buffer
+        let shadow_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("shadow_bind_group_layout"),
+        });
+
+        let shadow_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &shadow_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("shadow_bind_group"),
+        });
+
+        let shadow_pipeline = (app_config.render.shadow_map_size > 0).then(|| {
+            let shadow_shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+            let shadow_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Pipeline Layout"),
+                bind_group_layouts: &[&shadow_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            create_shadow_pipeline(&device, &shadow_pipeline_layout, &shadow_shader)
+        });
+
 
         // create our depth texture
-        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+        let depth_texture = texture::Texture::create_depth_texture(&device, &scene_config(&config, &viewport), depth_format, msaa_samples, "depth_texture");
+        let depth_sample_view = texture::depth_only_view(&depth_texture.texture);
+        let bind_groups = BindGroupCache::new(device.clone());
+        // the scene is drawn in HDR and tone mapped into the window, everything drawing it draws in that format
+        let auto_exposure = app_config.render.auto_exposure.enabled.then(|| AutoExposure::new(&device, app_config.render.auto_exposure));
+        let tone_map_output = app_config.render.tone_map_output(config.format);
+        let tone_mapper = ToneMapper::new(&device, config.format, auto_exposure, app_config.render.tone_mapping, tone_map_output);
+        let hdr_frame = tone_mapper.frame(&device, viewport.width, viewport.height);
+        let fxaa = fxaa::Fxaa::new(&device, config.format);
+        let scene_format = HDR_FORMAT;
+        let msaa_target = create_msaa_target(&device, &scene_config(&config, &viewport), scene_format, msaa_samples);
+        let dynamic_resolution = DynamicResolution::new(app_config.render.min_render_scale, app_config.render.target_frame_ms);
+        let upscaler = Upscaler::new(&device, scene_format, app_config.render.upscale_sharpness);
+        let light_shafts = LightShafts::new(&device, scene_format, msaa_samples);
+        let depth_of_field = dof::DepthOfField::new(&device, msaa_samples);
     
         // creating the shaders
-        // We are going to use the functions from the shader.wgsl for our shaders
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+        // We are going to use the functions from the shader.wgsl for our shaders,
+        // pbr.wgsl adds a second fragment shader on top of them
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader.wgsl + pbr.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", shader_source, tweaks::tweak_functions(tweaks.tweaks())).into()),
+        });
 
         // setup the layout for the render pipeline
         let render_pipeline_layout =
@@ -201,65 +730,89 @@ impl<'a> State<'a> {
                 bind_group_layouts: &[  // this is where we register our bind layouts
                     &texture_bind_group_layout,
                     &camera_bind_group_layout,
+                    &light_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState { // Specify that we use the vertex function from shader.wgsl
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    model::ModelVertex::desc(),
-                    InstanceRaw::desc(),
-                ],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState { // Specify that we use the fragment vertex function from shader.wgsl
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState { // setup a color output for the surface
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState { // handle depth and when things are behind each other
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less, // draw front to back
-                stencil: wgpu::StencilState::default(), 
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1, // only 1 sample because multisampling is a bit complex
-                mask: !0, // use all the samples
-                alpha_to_coverage_enabled: false, // we won't do aliasing either
-            },
-            multiview: None, // we also wont be using array textures
-            cache: None, // we dont need caching either
+        // materials pick a pipeline by culling and depth bias, so make one for every combination
+        let pipelines = MaterialPipelines::new(|variant| {
+            create_render_pipeline(&device, &render_pipeline_layout, &shader, scene_format, depth_format, msaa_samples, variant)
         });
 
+        let lines = LineRenderer::new(&device, &camera_bind_group_layout, scene_format, Some(depth_format), msaa_samples);
+        let axis_gizmo = axis_gizmo::AxisGizmo::new(&device, &camera_bind_group_layout, config.format);
+
         // establish the world with all its models and instances
-        let world = World::new(&device, &queue, &texture_bind_group_layout).await;
+        let hot_reload = app_config.hot_reload.then(|| HotReload::new(&loader.lines));
+        let mut world = World::new(loader.finish(), accessibility, bindings, app_config.up_axis, app_config.snap).await;
+        world.animation = app_config.animation;
+        world.environment = app_config.environment;
+        crate::crash::update(|context| context.scene_file = Some(world.scene_file().to_string()));
+
+        // fill the background with the sky instead of a flat color
+        let skybox = Skybox::load(&device, &queue, &environment_map, scene_format, depth_format, msaa_samples, app_config.up_axis).await;
+        let debug_ui = DebugUi::new(&device, config.format, window.scale_factor() as f32);
+        let studio = Studio::new(&device, &camera_bind_group_layout, &light_bind_group_layout, scene_format, depth_format, msaa_samples, app_config.up_axis);
 
         // setup something to keep our mouse centered
         let mouse_grabber = MouseGrabber { mouse_locked: false };
+
+        // load the cutscene that plays when T is pressed, an empty one if it's missing or broken
+        let timeline = match world::resources::load_string(&CUTSCENE_FILE).await.and_then(|text| Timeline::parse(&text)) {
+            Ok(timeline) => timeline,
+            Err(e) => {
+                log::warn!("Couldn't load cutscene: {}", e);
+                Timeline::default()
+            }
+        };
+        let cutscene = CutscenePlayer::new(timeline);
+
+        // load the camera bookmarks F12 takes pictures of, none if the file isn't there yet
+        let bookmarks = match world::resources::load_string(&BOOKMARKS_FILE).await.and_then(|text| screenshot::parse_poses(&text)) {
+            Ok(bookmarks) => bookmarks,
+            Err(e) => {
+                log::info!("No camera bookmarks loaded: {}", e);
+                Vec::new()
+            }
+        };
+
+        // live data driving the cubes, there are no bindings unless the file is there
+        let data_bindings = match world::resources::load_string(&BINDINGS_FILE).await {
+            Ok(text) => match BindingConfig::parse(&text) {
+                Ok(config) => DataBindings::new(config),
+                Err(e) => {
+                    log::warn!("Couldn't load data bindings: {}", e);
+                    DataBindings::default()
+                }
+            },
+            Err(_) => DataBindings::default(),
+        };
+
+        // frames are copied out of the window to be sent, so it has to allow that
+        let remote = if app_config.remote.address.is_some() && !config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            log::warn!("Can't serve the window, it can't be copied from on this platform");
+            RemoteViewer::default()
+        } else {
+            RemoteViewer::new(&app_config.remote).unwrap_or_else(|e| {
+                log::warn!("Couldn't serve the window: {}", e);
+                RemoteViewer::default()
+            })
+        };
+
+        // load the files opened last time, none on the first run
+        let recent = match world::resources::load_string(&RECENT_FILE).await {
+            Ok(text) => RecentFiles::parse(&text),
+            Err(e) => {
+                log::info!("No recent files loaded: {}", e);
+                RecentFiles::default()
+            }
+        };
+
+        // load the on screen text in the configured language
+        let strings = Strings::load(&app_config.locale).await;
+        let window_title = strings.get("window.title").to_string();
+        window.set_title(&window_title);
         
 
         Self {
@@ -268,22 +821,159 @@ impl<'a> State<'a> {
             device,
             queue,
             config,
+            render_settings: app_config.render,
             size,
-            render_pipeline,
+            pipelines,
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
+            light_uniform,
+            light_buffer,
+            tweaks,
+            tweak_buffer,
+            show_light_counts: false,
+            show_bounds: false,
+            axis_gizmo,
+            show_axis_gizmo: true,
+            probe_grid,
+            environment_map,
+            hot_reload,
+            reloading: Vec::new(),
+            user_settings,
+            settings_saver,
+            cursor_position: (0.0, 0.0),
+            viewport,
+            clicks: VecDeque::new(),
+            light_bind_group,
+            shadow_map,
+            shadow_pipeline,
+            shadow_bind_group,
             camera_controller,
             depth_texture,
+            depth_sample_view,
+            bind_groups,
+            msaa_target,
+            dynamic_resolution,
+            watchdog: GpuWatchdog::default(),
+            upscaler,
+            light_shafts,
+            tone_mapper,
+            depth_of_field,
+            fxaa,
+            graph_textures: TexturePool::default(),
+            hdr_frame,
+            lines,
+            skybox,
+            studio,
+            debug_ui,
+            asset_browser: AssetBrowser::default(),
+            asset_loading: None,
+            opening: Vec::new(),
+            recent,
+            texture_bind_group_layout,
+            frame_stats: FrameStats::default(),
             world,
             mouse_grabber,
+            cutscene,
+            turntable: Turntable::new(app_config.turntable, app_config.up_axis),
+            gallery: Gallery::new(bookmarks),
+            screenshot_settings: app_config.screenshot,
+            recorder: Recorder::new(app_config.recorder),
+            remote,
+            data_bindings,
+            theme: Theme { preset: PalettePreset::Default },
+            section: SectionPlane::default(),
+            strings,
+            window_title,
+            #[cfg(feature = "debug-validation")]
+            validation_errors,
         }
     }
     
     /// get the current window
     pub fn window(&self) -> &Window {
-        &self.window
+        self.window
+    }
+
+    /// counts of what the world holds and drew last frame
+    pub fn world_stats(&self) -> WorldStats {
+        self.world.stats()
+    }
+
+    /// problems with the loaded models, textures are checked against the limit every supported device has
+    pub fn validate_scene(&self) -> ValidationReport {
+        let max_texture_size = wgpu::Limits::downlevel_defaults().max_texture_dimension_2d.min(self.device.limits().max_texture_dimension_2d);
+        self.world.validate(max_texture_size)
+    }
+
+    /// Smoothly change the normal field of view, 0 seconds changes it right away
+    pub fn set_field_of_view(&mut self, fovy: f32, seconds: f32) {
+        let fovy = fovy.clamp(camera::MIN_FOVY, camera::MAX_FOVY);
+        self.camera_controller.base_fovy = fovy;
+        self.camera_controller.transition_fov(&self.camera, fovy, seconds);
+    }
+
+    /// Switch the camera to a projection, see `camera::Projection`
+    pub fn set_projection(&mut self, projection: camera::Projection) {
+        self.camera.set_projection(projection);
+    }
+
+    /// Play a cutscene from `res/cutscenes`, read the first time it's played, see `CutscenePlayer::play`
+    pub fn play_cutscene(&mut self, name: &str) {
+        self.cutscene.play(name);
+    }
+
+    /// Stop the cutscene playing
+    pub fn stop_cutscene(&mut self) {
+        self.cutscene.stop();
+    }
+
+    /// Bend three instances of a model every frame so the last reaches a target, see `World::add_ik_chain`
+    pub fn add_ik_chain(&mut self, model: ModelHandle, chain: world::ik::IkChain) -> Option<usize> {
+        self.world.add_ik_chain(model, chain)
+    }
+
+    /// Move where an IK chain reaches for
+    pub fn set_ik_target(&mut self, chain: usize, target: cgmath::Point3<f32>) -> bool {
+        self.world.set_ik_target(chain, target)
+    }
+
+    /// Change the near and far clipping planes
+    pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) -> anyhow::Result<()> {
+        self.camera.set_clip_planes(znear, zfar)
+    }
+
+    /// Start or stop recording the window, see `Recorder`
+    pub fn set_recording(&mut self, is_recording: bool) -> anyhow::Result<()> {
+        if !is_recording {
+            self.recorder.stop(&self.device);
+            return Ok(());
+        }
+        if !self.config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            anyhow::bail!("the window can't be copied from on this platform");
+        }
+        self.recorder.start(&self.device, self.config.width, self.config.height, self.config.format)
+    }
+
+    /// whether the window is being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_recording()
+    }
+
+    /// where the window is served to browsers, see `RemoteViewer`
+    pub fn remote_address(&self) -> Option<std::net::SocketAddr> {
+        self.remote.address()
+    }
+
+    /// Change the air the world sits in
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.world.environment = environment;
+    }
+
+    /// whether the depth buffer has a stencil aspect to draw with
+    pub fn has_stencil(&self) -> bool {
+        self.depth_texture.texture.format().has_stencil_aspect()
     }
 
     /// resize the window
@@ -292,22 +982,129 @@ impl<'a> State<'a> {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
+            self.viewport = camera::Viewport::letterboxed(new_size.width, new_size.height, self.render_settings.fixed_aspect);
+            self.camera.set_aspect(self.viewport.width, self.viewport.height);
             self.surface.configure(&self.device, &self.config);
         }
 
-        self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+        let samples = self.depth_texture.texture.sample_count();
+        let scene_config = scene_config(&self.config, &self.viewport);
+        self.depth_texture = texture::Texture::create_depth_texture(&self.device, &scene_config, self.depth_texture.texture.format(), samples, "depth_texture");
+        self.depth_sample_view = texture::depth_only_view(&self.depth_texture.texture);
+        self.msaa_target = create_msaa_target(&self.device, &scene_config, self.scene_format(), samples);
+        self.hdr_frame = self.tone_mapper.frame(&self.device, self.viewport.width, self.viewport.height);
+    }
+
+    /// the format the scene is drawn in before it is tone mapped into the window
+    fn scene_format(&self) -> wgpu::TextureFormat {
+        HDR_FORMAT
     }
 
     /// Handle user input
     /// 
     /// Returns true if it successfully handled the user input
     pub fn input(&mut self, event: &WindowEvent) -> bool {
+        // the panel goes first so clicks and typing on it don't reach the world
+        let was_open = self.debug_ui.is_open();
+        if self.debug_ui.process_events(event, !self.mouse_grabber.mouse_locked) {
+            if self.debug_ui.is_open() && !was_open {
+                self.mouse_grabber.release(self.window);
+            }
+            return true;
+        }
+        self.record_click(event);
         let mut result = self.mouse_grabber.process_events(event, self.window);
         result = self.camera_controller.process_events(event) || result;
         result = self.world.process_events(event) || result;
-        return result;
+        result = self.cutscene.process_events(event) || result;
+        result = self.turntable.process_events(event) || result;
+        result = self.theme.process_events(event) || result;
+        result = self.section.process_events(event) || result;
+        result = self.studio.process_events(event) || result;
+        result = self.gallery.process_events(event) || result;
+        result = self.recorder.process_events(event) || result;
+        result
+    }
+
+
+    /// follow the cursor and remember what left clicks were on, before the mouse grabber centers it
+    fn record_click(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => self.cursor_position = (position.x as f32, position.y as f32),
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } => {
+                let position = if self.mouse_grabber.mouse_locked {
+                    self.viewport.center()
+                } else {
+                    self.cursor_position
+                };
+                self.click_at(position);
+            }
+            _ => {}
+        }
     }
 
+    /// remember a left click at a point of the window and what it was on
+    fn click_at(&mut self, position: (f32, f32)) {
+        // clicks on the bars around a letterboxed scene hit nothing
+        let hit = self.viewport.local_point(position.0, position.1)
+            .and_then(|_| self.world.pick(&self.camera.screen_to_ray(position, &self.viewport)));
+        if self.clicks.len() == CLICK_HISTORY {
+            self.clicks.pop_front();
+        }
+        self.clicks.push_back(Click { position, hit });
+    }
+
+    /// act on what remote viewers did as if it was done in the window
+    ///
+    /// Returns where the last click was, picking goes through it this frame.
+    fn apply_remote_inputs(&mut self) -> Option<(f32, f32)> {
+        let mut click = None;
+        for input in self.remote.take_inputs() {
+            match input {
+                RemoteInput::Key { code, is_pressed } => {
+                    self.camera_controller.process_key(code, is_pressed);
+                }
+                RemoteInput::Look { dx, dy } => self.camera_controller.process_mouse(dx, dy),
+                RemoteInput::Scroll(lines) => self.camera_controller.process_mouse_wheel(lines, &mut self.camera),
+                RemoteInput::Click { x, y } => {
+                    self.click_at((x, y));
+                    self.world.press_select();
+                    click = Some((x, y));
+                }
+            }
+        }
+        click
+    }
+
+    /// left clicks on the world since the last call, oldest first, at most the last `CLICK_HISTORY`
+    pub fn take_clicks(&mut self) -> Vec<Click> {
+        self.clicks.drain(..).collect()
+    }
+
+    /// the ray through a point of the window, see `Camera::screen_to_ray`
+    pub fn screen_to_ray(&self, cursor_px: (f32, f32)) -> camera::Ray {
+        self.camera.screen_to_ray(cursor_px, &self.viewport)
+    }
+
+    /// where a point of the world is drawn in the window, see `Camera::world_to_screen`
+    pub fn world_to_screen(&self, point: cgmath::Point3<f32>) -> Option<(f32, f32)> {
+        self.camera.world_to_screen(point, &self.viewport)
+    }
+
+    /// where to push frames for a `map_stream` texture
+    pub fn image_stream(&self, name: &str) -> Option<world::stream::ImageStream> {
+        self.world.image_stream(name)
+    }
+
+    /// the cubes currently picked
+    pub fn selection(&self) -> &[world::instance::EntityId] {
+        self.world.selection()
+    }
+
+    /// pick cubes as if they had been clicked, replacing the selection
+    pub fn set_selection(&mut self, ids: impl IntoIterator<Item = world::instance::EntityId>) {
+        self.world.set_selection(ids);
+    }
 
     /// Handle mouse movement event
     pub fn process_mouse_movement(&mut self, delta_x: f64, delta_y: f64) {
@@ -322,19 +1119,529 @@ impl<'a> State<'a> {
         if self.mouse_grabber.mouse_locked {
             match delta {
                 MouseScrollDelta::LineDelta(_, scroll) => self.camera_controller.process_mouse_wheel(*scroll, &mut self.camera),
-                MouseScrollDelta::PixelDelta(physical_position) => (),
+                MouseScrollDelta::PixelDelta(_) => (),
             };
     
         }
     }
 
     /// update various objects in the program
-    pub fn update(&mut self) {
-        self.world.update_world();
-        self.world.go_to_help();
-        self.camera_controller.update_camera(&mut self.camera);
+    ///
+    /// Args:
+    ///     dt: time since the last update
+    pub fn update(&mut self, dt: std::time::Duration) {
+        if self.watchdog.frame(dt.as_secs_f32()) {
+            self.lower_quality();
+        }
+        self.update_debug_ui(dt);
+        self.reload_changed_models(dt.as_secs_f32());
+        self.finish_opening();
+        self.save_changed_settings(dt.as_secs_f32());
+        self.dynamic_resolution.update(dt.as_secs_f32());
+        self.tone_mapper.update(dt.as_secs_f32());
+
+        // the cursor is locked to the center of the screen, so the view direction is what the user points at,
+        // unless a remote viewer clicked somewhere else
+        let view_direction = (self.camera.target - self.camera.eye).normalize();
+        let pointer = match self.apply_remote_inputs() {
+            Some(position) => self.camera.screen_to_ray(position, &self.viewport),
+            None => camera::Ray { origin: self.camera.eye, direction: view_direction },
+        };
+        self.world.handle_picking(pointer.origin, pointer.direction, &self.queue);
+        self.world.edit_selection(&self.queue);
+        self.world.update_world(dt.as_secs_f32());
+        self.world.go_to_help(dt.as_secs_f32());
+        self.camera_controller.update_camera(&mut self.camera, dt.as_secs_f32());
+        self.camera_controller.update_fov(&mut self.camera, dt.as_secs_f32());
+        // depth of field focuses on the picked cubes, measured along the view like the depth buffer
+        if self.render_settings.depth_of_field.enabled && !self.world.selection().is_empty() {
+            let forward = (self.camera.target - self.camera.eye).normalize();
+            self.camera_controller.pull_focus((self.world.focus_point() - self.camera.eye).dot(forward));
+        }
+        self.camera_controller.update_focus(dt.as_secs_f32());
+        crate::crash::update(|context| {
+            context.camera = Some(CameraPose { eye: self.camera.eye, target: self.camera.target });
+            context.render = Some(self.render_settings.clone());
+        });
+
+        // keep the eye out of the cubes so they don't get cut open by the near plane
+        if let Some(radius) = self.camera_controller.collision_radius {
+            let eye = self.world.push_out(self.camera.eye, radius);
+            self.camera.target += eye - self.camera.eye;
+            self.camera.eye = eye;
+        }
+
+        // presentation mode circles the selection, or everything when nothing is selected
+        if self.turntable.take_toggle() {
+            self.turntable.toggle(self.camera.eye, self.camera.target, self.world.focus_point());
+        }
+        if let Some((eye, target)) = self.turntable.advance(dt.as_secs_f32()) {
+            self.camera.eye = eye;
+            self.camera.target = target;
+        }
+
+        // a playing cutscene takes over the camera
+        self.cutscene.advance(dt.as_secs_f32());
+        let frame = self.cutscene.current_frame().unwrap_or_default();
+        if let Some((eye, target)) = frame.camera {
+            self.camera.eye = eye;
+            self.camera.target = target;
+        }
+        self.world.apply_timeline(&frame, &self.queue);
+        self.data_bindings.update();
+        for (property, value) in self.data_bindings.values() {
+            self.world.apply_binding(property, value, &self.queue);
+        }
+        if self.gallery.take_bookmark() {
+            self.gallery.bookmark(CameraPose { eye: self.camera.eye, target: self.camera.target });
+        }
+
+        // now that the camera and instances are settled, work out what gets drawn and in which order
+        self.prepare_models(self.viewport.height, dt.as_secs_f32());
+        let mut title = frame.text.as_deref().map_or(self.window_title.as_str(), |text| self.strings.get(text)).to_string();
+
+        // show the measure points and lines, with the numbers in the title, presentations and studio shots keep the view clean
+        let mut lines = LineList::default();
+        let is_clean = self.turntable.is_presenting() || self.studio.enabled;
+        if let Some(measurement) = self.world.measurement().filter(|_| !is_clean) {
+            let color = self.theme.palette().highlight;
+            for point in &measurement.points {
+                lines.cross(*point, 0.1, color);
+            }
+            for (start, end) in measurement.segments() {
+                lines.line(start, end, color);
+            }
+            title = format!("{} | {}", title, self.strings.get("measure.mode"));
+            if let Some(distance) = measurement.distance() {
+                title = format!("{} | {}: {:.3}", title, self.strings.get("measure.distance"), distance);
+            }
+            if let Some(angle) = measurement.angle() {
+                title = format!("{} | {}: {:.1}°", title, self.strings.get("measure.angle"), angle);
+            }
+        }
+        if self.show_light_counts {
+            // lights past the ones the shader reads are outlined too, they light nothing
+            let palette = self.theme.palette();
+            for (index, light) in self.world.lights.iter().enumerate() {
+                let color = if index < light::MAX_LIGHTS { palette.good } else { palette.bad };
+                lines.cross(light.position, 0.1, color);
+                lines.sphere(light.position, light.range, color);
+            }
+        }
+        if self.show_bounds {
+            // the picked cubes stand out from the rest
+            let palette = self.theme.palette();
+            let selected = self.world.selection();
+            for model in self.world.models.iter().filter(|model| model.visible) {
+                for (id, instance) in model.placed_instances() {
+                    let color = if selected.contains(&id) { palette.highlight } else { palette.good };
+                    lines.cuboid(&instance.corners(&model.bounding_box), color);
+                }
+            }
+        }
+        self.lines.set_lines(&self.device, &self.queue, &lines);
+        self.axis_gizmo.update(&self.device, &self.queue, &self.camera);
+        if self.recorder.take_toggle() {
+            let is_recording = !self.recorder.is_recording();
+            if let Err(e) = self.set_recording(is_recording) {
+                log::warn!("Couldn't start recording: {}", e);
+            }
+        }
+        if self.recorder.is_recording() {
+            title = format!("{} | {}", title, self.strings.get("recorder.recording"));
+        }
+        #[cfg(feature = "debug-validation")]
+        if let Some(summary) = self.validation_errors.lock().unwrap().summary() {
+            title = format!("{} | {}", title, summary);
+        }
+        if self.window.title() != title {
+            self.window.set_title(&title);
+        }
+        let is_capped = self.section.enabled && self.section.caps;
+        // back faces are the caps, so they can't be culled while capping
+        self.pipelines.force_double_sided = is_capped;
+        self.skybox.blur = if self.studio.enabled { STUDIO_SKY_BLUR } else { 0.0 };
+        self.upload_camera();
+        if self.studio.enabled {
+            let (floor, radius) = self.world.ground_under(self.world.focus_point());
+            self.studio.update(&self.queue, floor, radius);
+        }
+
+        // the world can move its lights around, so send them every frame
+        let has_shadows = self.shadow_pipeline.is_some();
+        self.light_uniform = light::LightUniform::new(&self.world.lights, self.world.ambient, Some(&self.world.sun), has_shadows)
+            .with_light_counts(self.show_light_counts)
+            .with_environment(if self.environment_map.is_loaded { self.world.environment.sky_light } else { 0.0 }, self.world.up_axis())
+            .with_fog(&self.world.environment.fog);
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+        self.probe_grid.update(&self.queue, &self.world);
+        if let Some(uniform) = self.tweaks.take_uniform() {
+            self.queue.write_buffer(&self.tweak_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        }
+    }
+
+    /// load a model on the task threads, see `loading::spawn_model`
+    fn spawn_model_load(&self, line: &str) -> ModelTask {
+        loading::spawn_model(line, &self.device, &self.queue, &self.texture_bind_group_layout, self.world.up_axis())
+    }
+
+    /// load models from resources.txt again when their files changed, a model that fails to load keeps the old version
+    ///
+    /// They load on the task threads and are swapped in on the first frame after they're done.
+    fn reload_changed_models(&mut self, dt: f32) {
+        let Some(hot_reload) = &mut self.hot_reload else {
+            return;
+        };
+        let changed = hot_reload.changed(dt);
+        if !changed.is_empty() {
+            // a changed texture has to be read again rather than shared
+            world::resources::forget_textures();
+        }
+        for (index, line) in changed {
+            // a model changed again before it finished loading only needs its newest version
+            self.reloading.retain(|(loading, _, _)| *loading != index);
+            let task = self.spawn_model_load(&line);
+            self.reloading.push((index, line, task));
+        }
+
+        let mut still_loading = Vec::new();
+        for (index, line, mut task) in std::mem::take(&mut self.reloading) {
+            let Some(model) = task.try_take() else {
+                still_loading.push((index, line, task));
+                continue;
+            };
+            match (model.and_then(|model| model), self.world.models.get_mut(index)) {
+                (Ok(model), Some(old)) => {
+                    old.replace_assets(model);
+                    log::info!("Reloaded {}", line);
+                }
+                (Err(e), _) => log::warn!("Couldn't reload {}: {}", line, e),
+                (_, None) => {}
+            }
+        }
+        self.reloading = still_loading;
+    }
+
+    /// apply the next fallback that changes anything, after frames kept hanging
+    ///
+    /// The settings the user picked are left as they were, so the next run tries full quality again.
+    fn lower_quality(&mut self) {
+        while let Some(fallback) = self.watchdog.next_fallback() {
+            let is_lowered = match fallback {
+                Fallback::Shadows => self.shadow_pipeline.take().is_some(),
+                Fallback::GlobalIllumination => std::mem::replace(&mut self.probe_grid.enabled, false),
+                Fallback::Resolution => self.dynamic_resolution.cap(watchdog::FALLBACK_SCALE),
+            };
+            if is_lowered {
+                log::warn!("Frames keep hanging, lowering quality: {:?}", fallback);
+                self.watchdog.applied(fallback);
+                return;
+            }
+        }
+    }
+
+    /// note that the surface timed out this frame, for the watchdog
+    pub fn surface_timed_out(&mut self) {
+        self.watchdog.timed_out();
+    }
+
+    /// bring the user's settings up to date with the window and what was changed while running
+    fn update_user_settings(&mut self) {
+        self.user_settings.render = self.render_settings.clone();
+        self.user_settings.mouse_sensitivity = self.camera_controller.accessibility.mouse_sensitivity;
+        // a minimized window has no size worth coming back to
+        let size = self.window.inner_size();
+        if size.width > 0 && size.height > 0 {
+            self.user_settings.window = Some(WindowPlacement { size, position: self.window.outer_position().ok() });
+        }
+    }
+
+    /// write the user's settings in the background if they changed, looked at every `settings::SAVE_CHECK_SECONDS`
+    fn save_changed_settings(&mut self, dt: f32) {
+        if !self.settings_saver.as_mut().is_some_and(|saver| saver.is_due(dt)) {
+            return;
+        }
+        self.update_user_settings();
+        if let Some(saver) = &mut self.settings_saver {
+            saver.save(&self.user_settings);
+        }
+    }
+
+    /// write the user's settings before the program closes, if they changed
+    pub fn save_settings(&mut self) {
+        self.update_user_settings();
+        if let Some(saver) = &mut self.settings_saver {
+            saver.save_now(&self.user_settings);
+        }
+    }
+
+    /// load the asset browser's models one at a time on the task threads, taking each one's thumbnail once it's in
+    fn load_next_asset(&mut self) {
+        self.asset_browser.scan(&world::resources::res_dir());
+        let Some((index, task)) = &mut self.asset_loading else {
+            if let Some((index, file_name)) = self.asset_browser.next_to_load() {
+                self.asset_loading = Some((index, self.spawn_model_load(file_name)));
+            }
+            return;
+        };
+        // the list fills in as models come in, the next starts on the frame after
+        let index = *index;
+        let Some(model) = task.try_take() else {
+            return;
+        };
+        self.asset_loading = None;
+        let result = model.and_then(|model| model).and_then(|model| {
+            let handle = self.world.add_model(model);
+            Ok((handle, self.render_thumbnail(handle, THUMBNAIL_SIZE)?))
+        });
+        self.asset_browser.loaded(index, result);
+    }
+
+    /// place a model picked in the asset browser where it was dropped, or where the camera looks
+    fn spawn_asset(&mut self, request: SpawnRequest) {
+        let direction = match request.at {
+            Some(at) => {
+                let pixels = at * self.debug_ui.pixels_per_point();
+                self.camera.screen_to_ray((pixels.x, pixels.y), &self.viewport).direction
+            }
+            None => (self.camera.target - self.camera.eye).normalize(),
+        };
+        let point = self.world.drop_point(self.camera.eye, direction);
+        self.world.spawn_model(request.model, point);
+        if let Some(file_name) = self.asset_browser.file_name(request.model) {
+            self.recent.push(file_name);
+            self.recent.save();
+        }
+    }
+
+    /// start opening a scene or model file in `res`, it goes to the top of the recent files once it's open
+    ///
+    /// The file loads on the task threads and is opened on the first frame after, see `finish_opening`. A
+    /// scene replaces the one being edited, a model is placed where the camera looks then. Models the
+    /// asset browser already loaded are placed right away.
+    pub fn open_file(&mut self, file_name: &str) {
+        let opening = match RecentKind::of(file_name) {
+            RecentKind::Scene => {
+                let name = file_name.to_string();
+                Opening::Scene(crate::tasks::spawn(async move { world::resources::load_string(&name).await }))
+            }
+            RecentKind::Model => match self.asset_browser.model(file_name) {
+                Some(model) => {
+                    self.place_opened_model(model);
+                    self.recent.push(file_name);
+                    self.recent.save();
+                    return;
+                }
+                None => Opening::Model(self.spawn_model_load(file_name)),
+            },
+        };
+        self.opening.push((file_name.to_string(), opening));
+    }
+
+    /// open the files `open_file` started loading that are in, failures are logged and printed on the console
+    fn finish_opening(&mut self) {
+        for (file_name, mut opening) in std::mem::take(&mut self.opening) {
+            let result = match &mut opening {
+                Opening::Scene(task) => task.try_take().map(|text| -> anyhow::Result<()> {
+                    self.world.open_scene(&file_name, &text.and_then(|text| text)?)?;
+                    crate::crash::update(|context| context.scene_file = Some(file_name.clone()));
+                    Ok(())
+                }),
+                Opening::Model(task) => task.try_take().map(|model| -> anyhow::Result<()> {
+                    let model = self.world.add_model(model.and_then(|model| model)?);
+                    self.place_opened_model(model);
+                    Ok(())
+                }),
+            };
+            match result {
+                None => self.opening.push((file_name, opening)),
+                Some(Ok(())) => {
+                    self.recent.push(&file_name);
+                    self.recent.save();
+                }
+                Some(Err(e)) => {
+                    log::warn!("Couldn't open {}: {}", file_name, e);
+                    self.debug_ui.print(&format!("Couldn't open {}: {}", file_name, e));
+                }
+            }
+        }
+    }
+
+    /// put a model that was opened where the camera looks
+    fn place_opened_model(&mut self, model: ModelHandle) {
+        let direction = (self.camera.target - self.camera.eye).normalize();
+        let point = self.world.drop_point(self.camera.eye, direction);
+        self.world.spawn_model(model, point);
+    }
+
+    /// run a line typed in the console, returns what it printed
+    fn run_command(&mut self, command: &str) -> String {
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("validate"), None) => self.validate_scene().to_string(),
+            (Some("recent"), None) => self.recent.listing(),
+            (Some("fov"), None) => format!("fov = {}", self.camera_controller.base_fovy),
+            (Some("fov"), Some(degrees)) => match (degrees.parse::<f32>(), words.next().map_or(Ok(0.0), str::parse::<f32>)) {
+                (Ok(fovy), Ok(seconds)) => {
+                    self.set_field_of_view(fovy, seconds.max(0.0));
+                    format!("fov = {}", self.camera_controller.base_fovy)
+                }
+                _ => "fov <degrees> [seconds] eases the field of view to an angle".to_string(),
+            },
+            (Some("clip"), None) => format!("clip = {} {}", self.camera.znear, self.camera.zfar),
+            (Some("clip"), Some(near)) => match (near.parse::<f32>(), words.next().map(str::parse::<f32>)) {
+                (Ok(znear), Some(Ok(zfar))) => match self.set_clip_planes(znear, zfar) {
+                    Ok(()) => format!("clip = {} {}", znear, zfar),
+                    Err(e) => e.to_string(),
+                },
+                _ => "clip <near> <far> moves the clipping planes".to_string(),
+            },
+            (Some("cutscene"), _) => {
+                let arguments = command.split_whitespace().skip(1).collect::<Vec<_>>();
+                self.cutscene.run_command(&arguments).unwrap_or_else(|e| e.to_string())
+            }
+            (Some("ik"), _) => {
+                let arguments = command.split_whitespace().skip(1).collect::<Vec<_>>();
+                match world::ik::parse_command(&arguments) {
+                    Ok(None) => self.world.ik_listing(),
+                    Ok(Some((chain, target))) if self.world.set_ik_target(chain, target) => format!("IK chain {} reaches for {} {} {}", chain, target.x, target.y, target.z),
+                    Ok(Some((chain, _))) => format!("No IK chain {}", chain),
+                    Err(e) => e.to_string(),
+                }
+            }
+            (Some("open"), Some(name)) => {
+                // a number picks from the recent files, anything else is a path in res
+                let file_name = self.recent.find(name).unwrap_or(name).to_string();
+                self.open_file(&file_name);
+                format!("Opening {}", file_name)
+            }
+            _ => self.tweaks.run_command(command).unwrap_or_else(|e| e.to_string()),
+        }
+    }
+
+    /// pick the instances and detail levels each model draws from the current camera
+    ///
+    /// Args:
+    ///     height: height in pixels of the picture being drawn
+    ///     dt: seconds the behaviors move on, 0 to draw the same moment again
+    fn prepare_models(&mut self, height: u32, dt: f32) {
+        let view = ViewContext {
+            camera_eye: self.camera.eye,
+            pixels_per_unit: self.camera.pixels_per_unit(height),
+            is_orthographic: matches!(self.camera.projection, camera::Projection::Orthographic { .. }),
+            render: &self.render_settings,
+        };
+        self.world.prepare_models(&BehaviorContext { camera_eye: self.camera.eye, up: self.camera.up, dt }, &view, &self.queue);
+    }
+
+    /// send the camera to the GPU, along with the sky that is drawn from it
+    fn upload_camera(&mut self) {
         self.camera_uniform.update_view_proj(&self.camera);
+        self.camera_uniform.near_fade = self.camera_controller.near_fade_distance;
+        self.camera_uniform.section_plane = self.section.to_raw();
+        let is_capped = self.section.enabled && self.section.caps;
+        let cap = self.theme.palette().highlight;
+        self.camera_uniform.section_cap_color = [cap[0], cap[1], cap[2], if is_capped { 1.0 } else { 0.0 }];
         self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        self.skybox.update(&self.queue, &self.camera);
+    }
+
+    /// run the debug panel and apply whatever was changed on it
+    fn update_debug_ui(&mut self, dt: std::time::Duration) {
+        let mut values = DebugValues {
+            eye: self.camera.eye,
+            target: self.camera.target,
+            fovy: self.camera_controller.base_fovy,
+            znear: self.camera.znear,
+            zfar: self.camera.zfar,
+            grid_size: self.world.grid_size(),
+            crowd_size: self.world.crowd_size(),
+            studio: self.studio.enabled,
+            section: self.section.enabled,
+            section_caps: self.section.caps,
+            sort_front_to_back: self.render_settings.sort_front_to_back,
+            tone_mapping: self.render_settings.tone_mapping,
+            fxaa: self.render_settings.fxaa,
+            depth_of_field: self.render_settings.depth_of_field,
+            focus_distance: self.camera_controller.focus_distance(),
+            global_illumination: self.probe_grid.enabled,
+            light_counts: self.show_light_counts,
+            bounds: self.show_bounds,
+            axis_gizmo: self.show_axis_gizmo,
+            inspection: self.debug_ui.show_inspector.then(|| self.world.inspect()).flatten(),
+            tweaks: self.tweaks.tweaks().to_vec(),
+            command: None,
+            curves: self.cutscene.timeline.tracks().into_iter().map(|track| (track, self.cutscene.timeline.curve(track))).collect(),
+            render_scale: self.dynamic_resolution.scale(),
+            recent: self.recent.entries.clone(),
+            open_recent: None,
+            notice: self.watchdog.notice().map(|fallback| self.strings.get(fallback.message_key()).to_string()),
+            #[cfg(feature = "debug-validation")]
+            gpu_errors: self.validation_errors.lock().unwrap().errors.iter().map(|error| (error.message.clone(), error.count)).collect(),
+            #[cfg(not(feature = "debug-validation"))]
+            gpu_errors: Vec::new(),
+        };
+        let curves = values.curves.clone();
+        let inspected = values.inspection.as_ref().map(|inspection| inspection.properties.clone());
+        self.frame_stats.push(dt.as_secs_f32());
+        self.frame_stats.submissions = submit::take_stats();
+        self.frame_stats.bind_groups = self.bind_groups.end_frame();
+        self.frame_stats.animation = self.cutscene.stats();
+        if self.debug_ui.show_assets {
+            self.load_next_asset();
+        }
+        let request = self.debug_ui.run(&mut values, &self.frame_stats, &mut self.asset_browser, &self.strings, self.size, dt.as_secs_f32());
+        if let Some(request) = request {
+            self.spawn_asset(request);
+        }
+
+        // moving the eye takes the target along so the view direction stays the same
+        self.camera.target += values.eye - self.camera.eye;
+        self.camera.eye = values.eye;
+        if values.fovy != self.camera_controller.base_fovy {
+            self.set_field_of_view(values.fovy, 0.0);
+        }
+        if (values.znear, values.zfar) != (self.camera.znear, self.camera.zfar) {
+            if let Err(e) = self.set_clip_planes(values.znear, values.zfar) {
+                log::warn!("{}", e);
+            }
+        }
+        self.world.set_grid_size(values.grid_size);
+        self.world.set_crowd_size(values.crowd_size);
+        self.studio.enabled = values.studio;
+        self.section.enabled = values.section;
+        self.section.caps = values.section_caps;
+        self.render_settings.sort_front_to_back = values.sort_front_to_back;
+        self.render_settings.fxaa = values.fxaa;
+        self.render_settings.depth_of_field = values.depth_of_field;
+        if values.focus_distance != self.camera_controller.focus_distance() {
+            self.camera_controller.set_focus_distance(values.focus_distance);
+        }
+        if values.tone_mapping != self.render_settings.tone_mapping {
+            self.render_settings.tone_mapping = values.tone_mapping;
+            self.tone_mapper.set_settings(&self.queue, values.tone_mapping);
+        }
+        self.probe_grid.enabled = values.global_illumination;
+        self.show_light_counts = values.light_counts;
+        self.show_bounds = values.bounds;
+        self.show_axis_gizmo = values.axis_gizmo;
+        self.tweaks.set_values(&values.tweaks);
+        if values.curves != curves {
+            for (track, curve) in values.curves {
+                self.cutscene.timeline.set_curve(track, curve);
+            }
+            self.cutscene.timeline.save();
+        }
+        if let Some(command) = values.command {
+            let printed = self.run_command(&command);
+            self.debug_ui.print(&printed);
+        }
+        if let Some(file_name) = values.open_recent {
+            self.open_file(&file_name);
+        }
+        if let Some(inspection) = values.inspection.filter(|inspection| inspected.as_ref() != Some(&inspection.properties)) {
+            self.world.edit_properties(inspection.id, inspection.properties, inspection.is_continuing, &self.queue);
+        }
     }
 
     /// render objects to the screen
@@ -350,6 +1657,153 @@ impl<'a> State<'a> {
             label: Some("Render Encoder"),
         });
     
+        // frames running slow draw the scene smaller, then stretch and sharpen it over the window
+        let scene_size = if self.dynamic_resolution.is_active() {
+            self.dynamic_resolution.scaled_size(self.viewport.width, self.viewport.height)
+        } else {
+            (self.viewport.width, self.viewport.height)
+        };
+        if self.has_light_shafts() {
+            self.light_shafts.prepare(&self.device, &self.queue, &self.camera, &self.world.sun, &self.world.environment, scene_size);
+        }
+
+        // the scene goes into an HDR frame of its own, tone mapped into the window after
+        let scene_view = &self.hdr_frame.view;
+        let window_view = self.render_settings.for_view(RenderView::Window);
+        let scene_depth = if self.dynamic_resolution.is_active() {
+            let depth = &self.depth_texture.texture;
+            self.upscaler.prepare(&self.device, &self.queue, scene_size, self.scene_format(), depth.format(), depth.sample_count());
+            if let Some(target) = self.upscaler.target() {
+                self.encode_scene(&mut encoder, target.msaa_view.as_ref().unwrap_or(&target.color_view), target.msaa_view.as_ref().map(|_| &target.color_view), &target.depth_view, window_view);
+                self.draw_light_shafts(&mut encoder, &target.depth_sample_view, &target.color_view);
+            }
+            self.upscaler.draw(&mut encoder, scene_view);
+            self.upscaler.target().map_or(&self.depth_sample_view, |target| &target.depth_sample_view)
+        } else {
+            self.upscaler.release();
+            // with MSAA the samples are drawn off screen and averaged into the view from earlier
+            self.encode_scene(&mut encoder, self.msaa_target.as_ref().unwrap_or(scene_view), self.msaa_target.as_ref().map(|_| scene_view), &self.depth_texture.view, window_view);
+            self.draw_light_shafts(&mut encoder, &self.depth_sample_view, scene_view);
+            &self.depth_sample_view
+        };
+
+        // the passes after the scene go through a render graph, which orders them and makes the textures between them
+        let dof = self.render_settings.depth_of_field;
+        if dof.enabled {
+            self.depth_of_field.prepare(&self.device, &self.tone_mapper, (self.viewport.width, self.viewport.height));
+            self.depth_of_field.focus(&self.queue, &self.camera, self.camera_controller.focus_distance(), dof.aperture);
+        } else {
+            self.depth_of_field.release();
+        }
+        let viewport = self.viewport;
+        let window_format = self.config.format;
+        let mut graph = RenderGraph::new();
+        let scene = graph.import("scene", scene_view);
+        let window = graph.import("window", &view);
+
+        // out of focus parts are blurred into a frame of their own, which is tone mapped in place of the scene
+        let (mut tone_map_input, mut scene_frame) = (scene, &self.hdr_frame);
+        if let Some(blurred_frame) = self.depth_of_field.frame() {
+            let blurred = graph.import("blurred", &blurred_frame.view);
+            let (depth_of_field, bind_groups) = (&self.depth_of_field, &self.bind_groups);
+            graph.add_pass("depth_of_field", &[scene], &[blurred], move |encoder, textures| {
+                depth_of_field.draw(bind_groups, encoder, textures.view(scene), scene_depth);
+            });
+            (tone_map_input, scene_frame) = (blurred, blurred_frame);
+        }
+
+        // with FXAA the frame is tone mapped into a texture of its own and smoothed on the way into the window,
+        // whichever pass draws into the window also clears the bars around a letterboxed scene
+        let (tone_mapper, queue) = (&mut self.tone_mapper, &self.queue);
+        if self.render_settings.fxaa {
+            let tone_mapped = graph.create("tone_mapped", TextureDesc { width: viewport.width, height: viewport.height, format: window_format });
+            graph.add_pass("tone_map", &[tone_map_input], &[tone_mapped], move |encoder, textures| {
+                let full = camera::Viewport { x: 0, y: 0, width: viewport.width, height: viewport.height };
+                tone_mapper.resolve(queue, encoder, scene_frame, textures.view(tone_mapped), full);
+            });
+            let (fxaa, bind_groups) = (&self.fxaa, &self.bind_groups);
+            graph.add_pass("fxaa", &[tone_mapped], &[window], move |encoder, textures| {
+                fxaa.draw(bind_groups, encoder, textures.view(tone_mapped), textures.view(window), viewport);
+            });
+        } else {
+            graph.add_pass("tone_map", &[tone_map_input], &[window], move |encoder, textures| {
+                tone_mapper.resolve(queue, encoder, scene_frame, textures.view(window), viewport);
+            });
+        }
+        if let Err(e) = graph.execute(&self.device, &mut self.graph_textures, &mut encoder) {
+            log::warn!("Couldn't draw the frame's post processing: {}", e);
+        }
+
+        // recordings leave the panels out
+        self.recorder.capture(&self.device, &mut encoder, &output.texture);
+        self.remote.capture(&self.device, &mut encoder, &output.texture, self.config.format);
+
+        // the gizmo goes over the scene like the panel does, presentations and studio shots keep the view clean
+        if self.show_axis_gizmo && !self.turntable.is_presenting() && !self.studio.enabled {
+            self.axis_gizmo.draw(&mut encoder, &view, self.viewport);
+        }
+
+        // the panel and frame stats go on top in a pass of their own, after any multisampling was resolved
+        self.debug_ui.render(&self.device, &self.queue, &mut encoder, &view, self.size);
+
+        // submit will accept anything that implements IntoIter
+        submit::submit(&self.device, &self.queue, std::iter::once(encoder.finish()));
+        self.recorder.after_submit(&self.device);
+        self.remote.after_submit(&self.device);
+        output.present();
+
+        if self.gallery.take_render() {
+            let poses = self.gallery.bookmarks.clone();
+            match self.render_gallery(&poses) {
+                Ok(count) => log::info!("Rendered {} bookmarks to {}", count, self.screenshot_settings.directory.display()),
+                Err(e) => log::warn!("Couldn't render the gallery: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// whether the sun shines through the air this frame, it needs the shadow map to know where
+    fn has_light_shafts(&self) -> bool {
+        self.shadow_pipeline.is_some() && self.world.environment.has_light_shafts() && self.world.sun.intensity > 0.0
+    }
+
+    /// record the light shafts added over a resolved scene, after `encode_scene` drew it and its shadow map
+    fn draw_light_shafts(&self, encoder: &mut wgpu::CommandEncoder, depth_view: &wgpu::TextureView, output: &wgpu::TextureView) {
+        if self.has_light_shafts() {
+            self.light_shafts.draw(&self.bind_groups, encoder, depth_view, (&self.shadow_map.view, &self.shadow_map.sampler), output);
+        }
+    }
+
+    /// record the shadow pass and the main pass, drawing the world into a color target
+    ///
+    /// Args:
+    ///     color_view: what the main pass draws into, multisampled when MSAA is on
+    ///     resolve_target: the view the samples are averaged into, None without MSAA
+    ///     depth_view: depth buffer with the same sample count as the color
+    ///     view: what the view drawn overrides, the light buffer has to agree on whether there are shadows
+    fn encode_scene(&self, encoder: &mut wgpu::CommandEncoder, color_view: &wgpu::TextureView, resolve_target: Option<&wgpu::TextureView>, depth_view: &wgpu::TextureView, view: ViewSettings) {
+        // draw the world as the sun sees it first, the main pass reads it back for shadows
+        if let Some(shadow_pipeline) = self.shadow_pipeline.as_ref().filter(|_| view.shadows) {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.shadow_map.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            shadow_pass.set_pipeline(shadow_pipeline);
+            shadow_pass.set_bind_group(0, &self.shadow_bind_group, &[]);
+            shadow_pass.draw_world_depth(&self.world);
+        }
+
         // put this in a borrow block since render pass will borrow the encoder
         // When this section is done rust will know to release the mutable borrow
         // allowing us to perform encoder.finish()
@@ -358,20 +1812,159 @@ impl<'a> State<'a> {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view, // render to the view from earlier
-                    resolve_target: None,
+                    view: color_view,
+                    resolve_target,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color { // clear the screen to a color
-                            r: 0.5,
-                            g: 0.1,
-                            b: 0.5,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.theme.palette().clear_color()), // clear the screen to a color
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment { // make sure pixels are drawn back to front
-                    view: &self.depth_texture.view,
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    // keep the stencil around for effects that mark pixels, if the format has one
+                    stencil_ops: self.has_stencil().then_some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            // Here we are drawing all the instances, each material sets the pipeline it needs
+            // in the future we could optimize this to only draw the instances on screen
+            render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+            render_pass.draw_world(&self.world, &self.pipelines, &self.camera_bind_group);
+            // after the world, so the sky is only drawn where nothing else was
+            self.skybox.draw(&mut render_pass);
+            // the sky was drawn with its own layout, so bind the lights again for the catchers
+            render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+            render_pass.draw_world_shadow_catchers(&self.world, &self.pipelines, &self.camera_bind_group);
+            // the floor's shadows blend over the sky
+            self.studio.draw(&mut render_pass, &self.camera_bind_group, &self.light_bind_group);
+            self.lines.draw(&mut render_pass, &self.camera_bind_group);
+        }
+    }
+
+    /// Render the world from each pose off screen and write the pictures as numbered PNGs
+    ///
+    /// The pictures are the size in `ScreenshotSettings` whatever the window's size is, and the camera is
+    /// put back where it was afterwards. `RenderSettings::gallery_overrides` can leave out shadows or post processing.
+    /// Returns how many pictures were rendered, they are written in the background.
+    pub fn render_gallery(&mut self, poses: &[CameraPose]) -> anyhow::Result<usize> {
+        let settings = self.screenshot_settings.clone();
+        let target = OffscreenTarget::new(
+            &self.device,
+            settings.width,
+            settings.height,
+            self.config.format,
+            self.scene_format(),
+            self.depth_texture.texture.format(),
+            self.depth_texture.texture.sample_count(),
+        )?;
+        std::fs::create_dir_all(&settings.directory)?;
+
+        let view = self.render_settings.for_view(RenderView::Gallery);
+        if !view.shadows {
+            self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform.without_shadows()]));
+        }
+        let home = self.camera.clone();
+        self.camera.set_aspect(settings.width, settings.height);
+        let mut result = Ok(());
+        for (index, pose) in poses.iter().enumerate() {
+            self.camera.eye = pose.eye;
+            self.camera.target = pose.target;
+            self.upload_camera();
+            self.prepare_models(settings.height, 0.0);
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Gallery Encoder"),
+            });
+            let (color_view, resolve_target) = target.scene_views();
+            self.encode_scene(&mut encoder, color_view, resolve_target, &target.depth_view, view);
+            // with post processing, pictures are exposed like the window is at the moment
+            self.tone_map_offscreen(&mut encoder, &target, view.post_processing);
+            target.copy_out(&mut encoder);
+            submit::submit(&self.device, &self.queue, std::iter::once(encoder.finish()));
+
+            let picture = match target.read(&self.device) {
+                Ok(picture) => picture,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            let path = settings.directory.join(screenshot::shot_file_name(index));
+            crate::tasks::spawn_blocking(move || {
+                if let Err(e) = picture.save(&path) {
+                    log::warn!("Couldn't save {}: {}", path.display(), e);
+                }
+            });
+        }
+
+        // put the window's view and lights back for the next frame
+        self.camera = home;
+        self.upload_camera();
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+        self.prepare_models(self.viewport.height, 0.0);
+        result.map(|_| poses.len())
+    }
+
+    /// Render one model on its own in a square picture, framed from the front right and above
+    ///
+    /// The model is drawn once at the origin with plain lighting over a transparent background, whatever
+    /// the rest of the world looks like, and without post processing unless `RenderSettings::thumbnail_overrides` turns it on.
+    ///
+    /// Args:
+    ///     size: width and height of the picture in pixels
+    pub fn render_thumbnail(&mut self, handle: ModelHandle, size: u32) -> anyhow::Result<image::RgbaImage> {
+        let model = self.world.model(handle).ok_or_else(|| anyhow::anyhow!("no model for {:?}", handle))?;
+        let target = OffscreenTarget::new(
+            &self.device,
+            size,
+            size,
+            self.config.format,
+            self.scene_format(),
+            self.depth_texture.texture.format(),
+            self.depth_texture.texture.sample_count(),
+        )?;
+
+        let up_axis = self.world.up_axis();
+        let camera = thumbnail::thumbnail_camera(&model.bounding_box, up_axis);
+        let mut camera_uniform = camera::CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[thumbnail::thumbnail_lights(up_axis)]));
+
+        let instance = Instance { position: cgmath::Vector3::new(0.0, 0.0, 0.0), rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0), scale: 1.0 };
+        let instance_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Thumbnail Instance Buffer"),
+            contents: bytemuck::cast_slice(&[instance.to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Thumbnail Encoder"),
+        });
+        {
+            let (view, resolve_target) = target.scene_views();
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Thumbnail Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        // no sky, so tools can put the picture over anything
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &target.depth_view,
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Clear(1.0),
                         store: wgpu::StoreOp::Store,
@@ -381,21 +1974,197 @@ impl<'a> State<'a> {
                 occlusion_query_set: None,
                 timestamp_writes: None,
             });
+            render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            for mesh in &model.meshes {
+                render_pass.draw_mesh(mesh, &model.materials, &self.pipelines, &self.camera_bind_group);
+            }
+        }
+        // the thumbnail's own lights never cast shadows, so only post processing can be overridden
+        let view = self.render_settings.for_view(RenderView::Thumbnail);
+        self.tone_map_offscreen(&mut encoder, &target, view.post_processing);
+        target.copy_out(&mut encoder);
+        submit::submit(&self.device, &self.queue, std::iter::once(encoder.finish()));
+        let picture = target.read(&self.device);
 
-            // Use our pipeline we defined
-            render_pass.set_pipeline(&self.render_pipeline);
+        // put the window's camera and lights back for the next frame
+        self.upload_camera();
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+        picture
+    }
 
-            // Here we are drawing all the instances
-            // in the future we could optimize this to only draw the instances on screen
-            render_pass.draw_world(&self.world, &self.camera_bind_group);
- 
+    /// bring an off screen picture drawn in HDR into its saved format, does nothing when it was drawn in that
+    ///
+    /// Args:
+    ///     is_post_processed: expose and tone map it like the window, otherwise keep the colors it was drawn with
+    fn tone_map_offscreen(&self, encoder: &mut wgpu::CommandEncoder, target: &OffscreenTarget, is_post_processed: bool) {
+        if let Some(hdr_view) = &target.hdr_view {
+            let bind_group = self.tone_mapper.bind_cached(&self.bind_groups, hdr_view, is_post_processed);
+            self.tone_mapper.draw(encoder, &bind_group, &target.color_view, None);
         }
+    }
 
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+    /// handles of the loaded models, for `render_thumbnail`
+    pub fn model_handles(&self) -> Vec<ModelHandle> {
+        self.world.model_handles()
+    }
 
+    /// the camera bookmarks F6 saved
+    pub fn bookmarks(&self) -> &[CameraPose] {
+        &self.gallery.bookmarks
+    }
+}
 
-        Ok(())
+/// create the pipeline models are drawn with
+///
+/// Args:
+///     sample_count: samples per pixel of the main pass
+///     variant: the culling and depth bias the materials drawn with it need
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+    variant: PipelineVariant,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState { // Specify that we use the vertex function from shader.wgsl
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[
+                model::ModelVertex::desc(),
+                InstanceRaw::desc(),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState { // Specify that we use the fragment vertex function from shader.wgsl
+            module: shader,
+            entry_point: variant.fragment_entry_point(),
+            targets: &[Some(wgpu::ColorTargetState { // setup a color output for the surface
+                format,
+                blend: Some(variant.blend()),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: variant.cull_mode(),
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState { // handle depth and when things are behind each other
+            format: depth_format,
+            depth_write_enabled: variant.writes_depth(),
+            depth_compare: wgpu::CompareFunction::Less, // draw front to back
+            stencil: wgpu::StencilState::default(), 
+            bias: variant.depth_bias.state(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count, // more than 1 sample turns on MSAA, see RenderSettings::msaa_samples
+            mask: !0, // use all the samples
+            alpha_to_coverage_enabled: false, // we won't do aliasing either
+        },
+        multiview: None, // we also wont be using array textures
+        cache: None, // we dont need caching either
+    })
+}
+
+/// the multisampled color texture the main pass draws into, None when not multisampling
+fn create_msaa_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, format: wgpu::TextureFormat, sample_count: u32) -> Option<wgpu::TextureView> {
+    if sample_count == 1 {
+        return None;
+    }
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("msaa_target"),
+        size: wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+/// a texture binding for one of the pbr maps
+fn pbr_map_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
     }
-}
\ No newline at end of file
+}
+
+/// the window's configuration at the size the scene is drawn at, for textures the scene is drawn into
+fn scene_config(config: &wgpu::SurfaceConfiguration, viewport: &camera::Viewport) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration { width: viewport.width, height: viewport.height, ..config.clone() }
+}
+
+/// create the pipeline that draws the world's depth into the shadow map
+///
+/// There is no fragment stage, only depth is written. Both faces are drawn so open meshes
+/// still cast shadows, and a slope scaled bias keeps surfaces from shadowing themselves.
+fn create_shadow_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shadow Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[
+                model::ModelVertex::desc(),
+                InstanceRaw::desc(),
+            ],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::SHADOW_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,80 @@
+//! Options that make the controls usable with only a keyboard or without holding keys down
+
+/// How a key switches something on and off
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SwitchMode {
+    /// on only while the key is held
+    Hold,
+    /// each press flips it on or off
+    Toggle,
+}
+
+impl SwitchMode {
+    /// Update a switch from a key press or release
+    ///
+    /// Args:
+    ///     active: whether the switch is on
+    ///     is_pressed: whether the key went down (true) or up (false)
+    pub fn switch(self, active: &mut bool, is_pressed: bool) {
+        match self {
+            SwitchMode::Hold => *active = is_pressed,
+            SwitchMode::Toggle => {
+                if is_pressed {
+                    *active = !*active;
+                }
+            }
+        }
+    }
+}
+
+/// Accessibility options for the input handling
+#[derive(Copy, Clone, Debug)]
+pub struct AccessibilitySettings {
+    /// degrees the arrow keys turn the camera every second
+    pub look_rate: f32,
+    /// degrees the camera turns for every pixel the mouse moves
+    pub mouse_sensitivity: f32,
+    /// mouse wheel lines the zoom keys (page up/down) scroll every second
+    pub zoom_rate: f32,
+    /// whether the movement keys need holding or stay on until pressed again
+    pub movement: SwitchMode,
+    /// whether spin, pulse and help need holding or are toggled
+    pub modal_controls: SwitchMode,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        Self {
+            look_rate: 30.0,
+            mouse_sensitivity: 0.1,
+            zoom_rate: 3.0,
+            movement: SwitchMode::Hold,
+            modal_controls: SwitchMode::Toggle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hold() {
+        let mut active = false;
+        SwitchMode::Hold.switch(&mut active, true);
+        assert!(active);
+        SwitchMode::Hold.switch(&mut active, false);
+        assert!(!active);
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut active = false;
+        SwitchMode::Toggle.switch(&mut active, true);
+        SwitchMode::Toggle.switch(&mut active, false);
+        assert!(active);
+        SwitchMode::Toggle.switch(&mut active, true);
+        SwitchMode::Toggle.switch(&mut active, false);
+        assert!(!active);
+    }
+}
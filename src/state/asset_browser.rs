@@ -0,0 +1,197 @@
+//! A panel listing the models in the res folder, click or drag one out to place it in the world
+use std::path::Path;
+
+use super::{locale::Strings, world::{importer, ModelHandle}};
+
+/// Width and height of the pictures in the list, in pixels
+pub const THUMBNAIL_SIZE: u32 = 96;
+
+/// Every model file under a folder, .obj or a format an importer reads, as paths relative to it with / between folders, sorted by name
+pub fn scan_models(root: &Path) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut folders = vec![root.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let Ok(entries) = std::fs::read_dir(&folder) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                folders.push(path);
+            } else if path.to_str().is_some_and(importer::is_model_file) {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    let parts = relative.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>();
+                    found.push(parts.join("/"));
+                }
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Where a model was loaded to, once it has been
+#[derive(Debug)]
+enum AssetState {
+    NotLoaded,
+    /// loaded with a picture waiting to be handed to egui
+    Loaded(ModelHandle, Option<image::RgbaImage>),
+    Failed,
+}
+
+/// One model file in the list
+struct Asset {
+    file_name: String,
+    state: AssetState,
+    thumbnail: Option<egui::TextureHandle>,
+}
+
+/// A model the user asked to put in the world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnRequest {
+    pub model: ModelHandle,
+    /// where the model was dropped in window points, None to place it where the camera looks
+    pub at: Option<egui::Pos2>,
+}
+
+/// The list of models in the res folder
+///
+/// Models are loaded one at a time on the task threads while the list is open, so opening it doesn't
+/// freeze the window, and their thumbnails show up as they come in.
+#[derive(Default)]
+pub struct AssetBrowser {
+    assets: Vec<Asset>,
+    is_scanned: bool,
+}
+
+impl AssetBrowser {
+    /// Look for models the first time the list is shown
+    pub fn scan(&mut self, root: &Path) {
+        if std::mem::replace(&mut self.is_scanned, true) {
+            return;
+        }
+        self.assets = scan_models(root).into_iter()
+            .map(|file_name| Asset { file_name, state: AssetState::NotLoaded, thumbnail: None })
+            .collect();
+    }
+
+    /// The next model waiting to be loaded, with its index for `loaded`
+    pub fn next_to_load(&self) -> Option<(usize, &str)> {
+        self.assets.iter().enumerate()
+            .find(|(_, asset)| matches!(asset.state, AssetState::NotLoaded))
+            .map(|(index, asset)| (index, asset.file_name.as_str()))
+    }
+
+    /// Record how loading a model went, with its thumbnail if it worked
+    pub fn loaded(&mut self, index: usize, result: anyhow::Result<(ModelHandle, image::RgbaImage)>) {
+        let Some(asset) = self.assets.get_mut(index) else {
+            return;
+        };
+        asset.state = match result {
+            Ok((model, thumbnail)) => AssetState::Loaded(model, Some(thumbnail)),
+            Err(e) => {
+                log::warn!("Couldn't load {} for the asset browser: {}", asset.file_name, e);
+                AssetState::Failed
+            }
+        };
+    }
+
+    /// The model loaded from a file, if the list loaded it
+    pub fn model(&self, file_name: &str) -> Option<ModelHandle> {
+        self.assets.iter().find(|asset| asset.file_name == file_name).and_then(|asset| match asset.state {
+            AssetState::Loaded(model, _) => Some(model),
+            _ => None,
+        })
+    }
+
+    /// The file a model the list loaded came from
+    pub fn file_name(&self, model: ModelHandle) -> Option<&str> {
+        self.assets.iter()
+            .find(|asset| matches!(asset.state, AssetState::Loaded(loaded, _) if loaded == model))
+            .map(|asset| asset.file_name.as_str())
+    }
+
+    /// Lay out the list, returns the model to place if one was clicked or dragged into the world
+    pub fn show(&mut self, context: &egui::Context, strings: &Strings) -> Option<SpawnRequest> {
+        // hand new thumbnails to egui, it keeps them until the handle is dropped
+        for asset in &mut self.assets {
+            if let AssetState::Loaded(_, picture) = &mut asset.state {
+                if let Some(picture) = picture.take() {
+                    let size = [picture.width() as usize, picture.height() as usize];
+                    let image = egui::ColorImage::from_rgba_unmultiplied(size, picture.as_raw());
+                    asset.thumbnail = Some(context.load_texture(&asset.file_name, image, egui::TextureOptions::LINEAR));
+                }
+            }
+        }
+
+        let mut request = None;
+        egui::Window::new(strings.get("assets.title")).default_pos((8.0, 300.0)).show(context, |ui| {
+            if self.assets.is_empty() {
+                ui.label(strings.get("assets.empty"));
+            }
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                for asset in &self.assets {
+                    ui.horizontal(|ui| {
+                        let size = egui::vec2(THUMBNAIL_SIZE as f32, THUMBNAIL_SIZE as f32) / 2.0;
+                        let response = match (&asset.state, &asset.thumbnail) {
+                            (AssetState::Loaded(..), Some(thumbnail)) => ui.add(egui::Image::new((thumbnail.id(), size)).sense(egui::Sense::click_and_drag())),
+                            (AssetState::Failed, _) => ui.add_sized(size, egui::Label::new("✖")),
+                            _ => ui.add_sized(size, egui::Spinner::new()),
+                        };
+                        ui.label(&asset.file_name);
+
+                        let AssetState::Loaded(model, _) = asset.state else {
+                            return;
+                        };
+                        if response.clicked() {
+                            request = Some(SpawnRequest { model, at: None });
+                        } else if response.drag_stopped() {
+                            // dropped back on a panel is a cancelled drag
+                            let pointer = ui.ctx().pointer_interact_pos();
+                            if let Some(at) = pointer.filter(|at| ui.ctx().layer_id_at(*at).is_none()) {
+                                request = Some(SpawnRequest { model, at: Some(at) });
+                            }
+                        }
+                        if response.dragged() {
+                            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+                        }
+                    });
+                }
+            });
+        });
+        request
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_models() {
+        let root = std::env::temp_dir().join(format!("rust3d_scan_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("props/chairs")).unwrap();
+        for file in ["b.obj", "a.OBJ", "notes.txt", "props/chairs/chair.obj", "props/table.mtl", "props/bracket.stl"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let found = scan_models(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(found, vec!["a.OBJ", "b.obj", "props/bracket.stl", "props/chairs/chair.obj"]);
+        assert!(scan_models(&root).is_empty());
+    }
+
+    #[test]
+    fn test_load_order() {
+        let assets = ["a.obj", "b.obj"].into_iter()
+            .map(|file_name| Asset { file_name: file_name.to_string(), state: AssetState::NotLoaded, thumbnail: None })
+            .collect();
+        let mut browser = AssetBrowser { assets, is_scanned: true };
+        assert_eq!(browser.next_to_load(), Some((0, "a.obj")));
+        browser.loaded(0, Err(anyhow::anyhow!("broken")));
+        assert_eq!(browser.next_to_load(), Some((1, "b.obj")));
+        browser.loaded(1, Ok((ModelHandle(0), image::RgbaImage::new(1, 1))));
+        assert_eq!(browser.next_to_load(), None);
+        assert_eq!(browser.model("b.obj"), Some(ModelHandle(0)));
+        assert_eq!(browser.model("a.obj"), None);
+        assert_eq!(browser.file_name(ModelHandle(0)), Some("b.obj"));
+    }
+}
@@ -0,0 +1,181 @@
+//! The X, Y and Z axes drawn small in a corner of the window, turning with the camera to show which way it faces
+use cgmath::InnerSpace;
+use wgpu::util::DeviceExt;
+
+use super::{
+    camera::{self, Camera, CameraUniform, Viewport},
+    lines::{LineList, LineRenderer},
+    palette::Color,
+};
+
+/// Colors of the X, Y and Z axes, the usual red, green and blue
+const AXIS_COLORS: [Color; 3] = [[0.9, 0.2, 0.2], [0.2, 0.8, 0.2], [0.25, 0.45, 1.0]];
+
+/// Share of the viewport's shorter side the gizmo's square takes
+const SIZE_SHARE: f32 = 0.15;
+
+/// Smallest and largest the square gets in pixels, whatever the viewport's size
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 160;
+
+/// Pixels between the square and the viewport's edges
+const MARGIN: u32 = 8;
+
+/// How much of an axis is drawn past the middle on the negative side, and how bright
+const NEGATIVE_LENGTH: f32 = 0.4;
+const NEGATIVE_BRIGHTNESS: f32 = 0.35;
+
+/// Axes of the world seen from the direction the camera looks in, without its position or perspective
+pub struct AxisGizmo {
+    lines: LineRenderer,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+}
+
+impl AxisGizmo {
+    /// Make the gizmo's lines and the camera they are seen through
+    ///
+    /// Args:
+    ///     camera_bind_group_layout: layout of the main camera's bind group, the gizmo's camera is laid out the same
+    ///     format: format of the window the gizmo is drawn over
+    pub fn new(device: &wgpu::Device, camera_bind_group_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat) -> Self {
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Axis Gizmo Camera Buffer"),
+            contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("axis_gizmo_camera_bind_group"),
+        });
+        Self {
+            lines: LineRenderer::new(device, camera_bind_group_layout, format, None, 1),
+            camera_buffer,
+            camera_bind_group,
+        }
+    }
+
+    /// Turn the axes to match the camera
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera) {
+        let forward = (camera.target - camera.eye).normalize();
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[CameraUniform::with_view_proj(view_projection(forward, camera.up))]));
+        self.lines.set_lines(device, queue, &axis_lines(forward));
+    }
+
+    /// Record a pass drawing the gizmo over the bottom left corner of the viewport
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, viewport: Viewport) {
+        let corner = corner_viewport(viewport);
+        if corner.width == 0 {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Axis Gizmo Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_viewport(corner.x as f32, corner.y as f32, corner.width as f32, corner.height as f32, 0.0, 1.0);
+        self.lines.draw(&mut render_pass, &self.camera_bind_group);
+    }
+}
+
+/// Where the gizmo's square goes in the bottom left of a viewport, zero sized when it doesn't fit
+fn corner_viewport(viewport: Viewport) -> Viewport {
+    let shorter = viewport.width.min(viewport.height);
+    let size = ((shorter as f32 * SIZE_SHARE) as u32).clamp(MIN_SIZE, MAX_SIZE);
+    if size + 2 * MARGIN > shorter {
+        return Viewport { x: viewport.x, y: viewport.y, width: 0, height: 0 };
+    }
+    Viewport {
+        x: viewport.x + MARGIN,
+        y: viewport.y + viewport.height - MARGIN - size,
+        width: size,
+        height: size,
+    }
+}
+
+/// Look at the origin from the camera's direction with a square orthographic projection that fits the axes
+fn view_projection(forward: cgmath::Vector3<f32>, up: cgmath::Vector3<f32>) -> cgmath::Matrix4<f32> {
+    // looking straight along up has no sideways direction, any other axis does
+    let up = if forward.cross(up).magnitude2() < 1e-6 { forward.cross(cgmath::Vector3::unit_x()).cross(forward) } else { up };
+    let view = cgmath::Matrix4::look_to_rh(cgmath::Point3::new(0.0, 0.0, 0.0) - forward * 2.0, forward, up);
+    // a little past 1 so the tips aren't cut off
+    let proj = cgmath::ortho(-1.15, 1.15, -1.15, 1.15, 0.5, 3.5);
+    camera::OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+/// The three axes with a dim stub on their negative sides, farthest from the camera first
+///
+/// Nothing is depth tested, so the axes pointing at the camera are drawn last to cross over the others.
+fn axis_lines(forward: cgmath::Vector3<f32>) -> LineList {
+    let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+    let axes = [cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y(), cgmath::Vector3::unit_z()];
+    let mut ends = axes
+        .into_iter()
+        .zip(AXIS_COLORS)
+        .flat_map(|(axis, color)| [(axis, color), (-axis * NEGATIVE_LENGTH, color.map(|channel| channel * NEGATIVE_BRIGHTNESS))])
+        .collect::<Vec<_>>();
+    // the further along the view direction, the further away
+    ends.sort_by(|a, b| b.0.dot(forward).total_cmp(&a.0.dot(forward)));
+    let mut lines = LineList::default();
+    for (end, color) in ends {
+        lines.line(origin, origin + end, color);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corner_viewport() {
+        let viewport = Viewport { x: 0, y: 60, width: 1280, height: 600 };
+        assert_eq!(corner_viewport(viewport), Viewport { x: 8, y: 60 + 600 - 8 - 90, width: 90, height: 90 });
+        // big windows stop growing it, small ones stop shrinking it, tiny ones leave it out
+        assert_eq!(corner_viewport(Viewport { x: 0, y: 0, width: 4000, height: 3000 }).width, MAX_SIZE);
+        assert_eq!(corner_viewport(Viewport { x: 0, y: 0, width: 200, height: 100 }).width, MIN_SIZE);
+        assert_eq!(corner_viewport(Viewport { x: 0, y: 0, width: 70, height: 70 }).width, 0);
+    }
+
+    #[test]
+    fn test_view_projection() {
+        use cgmath::Transform;
+
+        // looking down -Z with Y up, X points right, Y up and every axis end is within the clip volume
+        let matrix = view_projection(-cgmath::Vector3::unit_z(), cgmath::Vector3::unit_y());
+        let x = matrix.transform_point((1.0, 0.0, 0.0).into());
+        let y = matrix.transform_point((0.0, 1.0, 0.0).into());
+        assert!(x.x > 0.8 && x.y.abs() < 1e-5);
+        assert!(y.y > 0.8 && y.x.abs() < 1e-5);
+        for end in [(0.0, 0.0, 1.0), (0.0, 0.0, -1.0)] {
+            let z = matrix.transform_point(end.into());
+            assert!((0.0..=1.0).contains(&z.z));
+        }
+        // straight down the up axis still makes a usable matrix
+        let down = view_projection(-cgmath::Vector3::unit_y(), cgmath::Vector3::unit_y());
+        assert!(down.transform_point((1.0, 0.0, 0.0).into()).x.is_finite());
+    }
+
+    #[test]
+    fn test_axis_lines() {
+        // looking down -Z, +Z points at the camera so it goes last, -Z's stub first
+        let lines = axis_lines(-cgmath::Vector3::unit_z());
+        assert_eq!(lines.vertices.len(), 12);
+        assert_eq!(lines.vertices[1].position, [0.0, 0.0, -NEGATIVE_LENGTH]);
+        assert_eq!(lines.vertices[11].position, [0.0, 0.0, 1.0]);
+        assert_eq!(lines.vertices[11].color, AXIS_COLORS[2]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Reusing bind groups made for the same layout and resources instead of making them again every frame
+//!
+//! Passes that bind resources owned by something else, like the light shafts reading the depth
+//! buffer and shadow map, ask `BindGroupCache::get` for their group each time they draw. A group
+//! not asked for in `KEEP_FRAMES` frames is dropped, so one holding a texture that was replaced
+//! doesn't keep it alive for long.
+use std::{collections::HashMap, hash::Hash, sync::{Arc, Mutex}};
+
+/// Frames a group is kept without being used
+pub const KEEP_FRAMES: u64 = 3;
+
+/// How the cache did since the stats were last taken
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BindGroupStats {
+    /// groups found in the cache
+    pub hits: u32,
+    /// groups that had to be made
+    pub created: u32,
+    /// groups in the cache now
+    pub cached: usize,
+}
+
+/// Values kept by key while they are used, see `BindGroupCache`
+#[derive(Debug)]
+struct FrameCache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    frame: u64,
+    stats: BindGroupStats,
+}
+
+impl<K: Eq + Hash, V: Clone> FrameCache<K, V> {
+    fn new() -> Self {
+        Self { entries: HashMap::new(), frame: 0, stats: BindGroupStats::default() }
+    }
+
+    /// The value for a key, made with `make` if it isn't cached
+    fn get(&mut self, key: K, make: impl FnOnce() -> V) -> V {
+        let frame = self.frame;
+        if let Some((value, last_used)) = self.entries.get_mut(&key) {
+            *last_used = frame;
+            self.stats.hits += 1;
+            return value.clone();
+        }
+        let value = make();
+        self.entries.insert(key, (value.clone(), frame));
+        self.stats.created += 1;
+        value
+    }
+
+    /// Drop values unused for `KEEP_FRAMES`, start the next frame and hand back the stats
+    fn end_frame(&mut self) -> BindGroupStats {
+        let frame = self.frame;
+        self.entries.retain(|_, (_, last_used)| frame - *last_used < KEEP_FRAMES);
+        self.frame += 1;
+        let stats = BindGroupStats { cached: self.entries.len(), ..self.stats };
+        self.stats = BindGroupStats::default();
+        stats
+    }
+}
+
+/// A buffer with the offset and size of the part bound
+type BufferKey = (wgpu::Id<wgpu::Buffer>, u64, Option<u64>);
+
+/// A bound resource by the ids of what it binds
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ResourceKey {
+    Buffer(BufferKey),
+    Buffers(Vec<BufferKey>),
+    Sampler(wgpu::Id<wgpu::Sampler>),
+    Samplers(Vec<wgpu::Id<wgpu::Sampler>>),
+    TextureView(wgpu::Id<wgpu::TextureView>),
+    TextureViews(Vec<wgpu::Id<wgpu::TextureView>>),
+}
+
+impl ResourceKey {
+    /// The key of a resource, None for kinds the cache doesn't know
+    fn of(resource: &wgpu::BindingResource) -> Option<Self> {
+        let buffer = |binding: &wgpu::BufferBinding| (binding.buffer.global_id(), binding.offset, binding.size.map(u64::from));
+        Some(match resource {
+            wgpu::BindingResource::Buffer(binding) => Self::Buffer(buffer(binding)),
+            wgpu::BindingResource::BufferArray(bindings) => Self::Buffers(bindings.iter().map(buffer).collect()),
+            wgpu::BindingResource::Sampler(sampler) => Self::Sampler(sampler.global_id()),
+            wgpu::BindingResource::SamplerArray(samplers) => Self::Samplers(samplers.iter().map(|sampler| sampler.global_id()).collect()),
+            wgpu::BindingResource::TextureView(view) => Self::TextureView(view.global_id()),
+            wgpu::BindingResource::TextureViewArray(views) => Self::TextureViews(views.iter().map(|view| view.global_id()).collect()),
+            _ => return None,
+        })
+    }
+}
+
+/// A layout and what is bound at each binding
+type BindGroupKey = (wgpu::Id<wgpu::BindGroupLayout>, Vec<(u32, ResourceKey)>);
+
+/// Bind groups made on a device, by their layout and resources
+pub struct BindGroupCache {
+    device: Arc<wgpu::Device>,
+    /// groups are asked for while drawing, which only borrows the state
+    groups: Mutex<FrameCache<BindGroupKey, Arc<wgpu::BindGroup>>>,
+}
+
+impl BindGroupCache {
+    pub fn new(device: Arc<wgpu::Device>) -> Self {
+        Self { device, groups: Mutex::new(FrameCache::new()) }
+    }
+
+    /// The group for a descriptor, made the first time its layout and resources are seen
+    ///
+    /// The label is only used when the group is made.
+    pub fn get(&self, descriptor: &wgpu::BindGroupDescriptor) -> Arc<wgpu::BindGroup> {
+        let entries: Option<Vec<_>> = descriptor.entries.iter().map(|entry| Some((entry.binding, ResourceKey::of(&entry.resource)?))).collect();
+        let make = || Arc::new(self.device.create_bind_group(descriptor));
+        match entries {
+            Some(entries) => self.groups.lock().unwrap().get((descriptor.layout.global_id(), entries), make),
+            None => make(),
+        }
+    }
+
+    /// Drop groups that weren't used lately, call once a frame, returns the stats since the last call
+    pub fn end_frame(&self) -> BindGroupStats {
+        self.groups.lock().unwrap().end_frame()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse_and_drop() {
+        let mut cache = FrameCache::new();
+        let mut made = 0;
+        for _ in 0..3 {
+            cache.get("shafts", || { made += 1; made });
+        }
+        assert_eq!(made, 1);
+        cache.get("offscreen", || 10);
+        assert_eq!(cache.end_frame(), BindGroupStats { hits: 2, created: 2, cached: 2 });
+
+        // the shafts keep drawing, the offscreen group is dropped once it goes unused for long enough
+        for _ in 1..KEEP_FRAMES {
+            cache.get("shafts", || unreachable!());
+            assert_eq!(cache.end_frame().cached, 2);
+        }
+        cache.get("shafts", || unreachable!());
+        assert_eq!(cache.end_frame(), BindGroupStats { hits: 1, created: 0, cached: 1 });
+    }
+}
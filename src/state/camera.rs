@@ -1,6 +1,8 @@
-/// Represent the camera in the screen.
+//! Represent the camera in the screen.
+use cgmath::InnerSpace;
 
 /// Represents the camera in easier user friendly format
+#[derive(Clone, Debug)]
 pub struct Camera {
     /// Location of camera
     pub eye: cgmath::Point3<f32>,
@@ -16,15 +18,96 @@ pub struct Camera {
     pub znear: f32,
     /// distance to the far clipping plane
     pub zfar: f32,
+    /// perspective, or orthographic for lining things up without distance shrinking them
+    pub projection: Projection,
 }
 
-// translation matrix that translates from openGL space to wGPU space
+/// How the camera flattens the world onto the screen
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Projection {
+    /// things shrink with distance, over the camera's field of view
+    #[default]
+    Perspective,
+    /// things keep their size however far away they are, like CAD tools show parts
+    Orthographic {
+        /// units of the world the view is tall
+        height: f32,
+    },
+}
+
+/// A half line starting at a point, for picking what is under the cursor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: cgmath::Point3<f32>,
+    /// normalized
+    pub direction: cgmath::Vector3<f32>,
+}
+
+/// The part of the window the scene is drawn in, in physical pixels from the top left corner
+///
+/// It is the whole window unless the scene keeps a fixed aspect ratio, then it is the largest
+/// rectangle of that shape centered in the window with bars filling the rest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    /// The viewport for a window
+    ///
+    /// Args:
+    ///     aspect: width over height the scene is kept at, None fills the window
+    pub fn letterboxed(window_width: u32, window_height: u32, aspect: Option<f32>) -> Self {
+        let (window_width, window_height) = (window_width.max(1), window_height.max(1));
+        let full = Self { x: 0, y: 0, width: window_width, height: window_height };
+        let Some(aspect) = aspect.filter(|aspect| aspect.is_finite() && *aspect > 0.0) else {
+            return full;
+        };
+        let window_aspect = window_width as f32 / window_height as f32;
+        if window_aspect > aspect {
+            // wider than the scene, bars down the sides
+            let width = ((window_height as f32 * aspect).round() as u32).clamp(1, window_width);
+            Self { x: (window_width - width) / 2, width, ..full }
+        } else {
+            // taller than the scene, bars across the top and bottom
+            let height = ((window_width as f32 / aspect).round() as u32).clamp(1, window_height);
+            Self { y: (window_height - height) / 2, height, ..full }
+        }
+    }
+
+    /// A point of the window as a point of the viewport, None when it is on the bars
+    pub fn local_point(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let (x, y) = (x - self.x as f32, y - self.y as f32);
+        (x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32).then_some((x, y))
+    }
+
+    /// The middle of the viewport in window pixels
+    pub fn center(&self) -> (f32, f32) {
+        (self.x as f32 + self.width as f32 / 2.0, self.y as f32 + self.height as f32 / 2.0)
+    }
+}
+
+/// Narrowest field of view the setters allow, in degrees
+pub const MIN_FOVY: f32 = 1.0;
+/// Widest field of view the setters allow, in degrees
+pub const MAX_FOVY: f32 = 170.0;
+
+/// Smallest and largest the orthographic view gets, in units of the world it is tall
+pub const MIN_ORTHO_HEIGHT: f32 = 0.01;
+pub const MAX_ORTHO_HEIGHT: f32 = 1000.0;
+
+/// Moves depth from OpenGL's -1..1 to wgpu's 0..1 and leaves x, y and w alone
+///
+/// cgmath takes the numbers a column at a time, so the 0.5 adding half of w to z is in the last column.
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
 impl Camera {
@@ -33,9 +116,123 @@ impl Camera {
         // matrix to represent the location of the camera
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
         // matrix to represent the depth/perspective of the camera
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        let proj = match self.projection {
+            Projection::Perspective => cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar),
+            Projection::Orthographic { height } => {
+                let (half_width, half_height) = (height * self.aspect / 2.0, height / 2.0);
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, self.znear, self.zfar)
+            }
+        };
+
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    /// Change the field of view, clamped to a usable range
+    pub fn set_fovy(&mut self, fovy: f32) {
+        self.fovy = fovy.clamp(MIN_FOVY, MAX_FOVY);
+    }
 
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+    /// Change the near and far clipping planes
+    ///
+    /// The near plane has to be in front of the camera and closer than the far plane
+    pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) -> anyhow::Result<()> {
+        if znear <= 0.0 || zfar <= znear {
+            anyhow::bail!("clip planes need 0 < near < far, got near {} and far {}", znear, zfar);
+        }
+        self.znear = znear;
+        self.zfar = zfar;
+        Ok(())
+    }
+
+    /// Use a projection, an orthographic height is clamped to a usable range
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = match projection {
+            Projection::Orthographic { height } => Projection::Orthographic { height: height.clamp(MIN_ORTHO_HEIGHT, MAX_ORTHO_HEIGHT) },
+            Projection::Perspective => Projection::Perspective,
+        };
+    }
+
+    /// Switch between perspective and orthographic
+    ///
+    /// The orthographic view is made as tall as the perspective one is at a distance from the eye,
+    /// so what is there keeps its size on screen.
+    pub fn toggle_projection(&mut self, distance: f32) {
+        self.projection = match self.projection {
+            Projection::Perspective => Projection::Orthographic {
+                height: (2.0 * distance * (self.fovy.to_radians() / 2.0).tan()).clamp(MIN_ORTHO_HEIGHT, MAX_ORTHO_HEIGHT),
+            },
+            Projection::Orthographic { .. } => Projection::Perspective,
+        };
+    }
+
+    /// Make the orthographic view taller or shorter by a factor, perspective views are left alone
+    pub fn scale_ortho_height(&mut self, factor: f32) {
+        if let Projection::Orthographic { height } = &mut self.projection {
+            *height = (*height * factor).clamp(MIN_ORTHO_HEIGHT, MAX_ORTHO_HEIGHT);
+        }
+    }
+
+    /// Match the aspect ratio to the window size
+    pub fn set_aspect(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    /// How many pixels tall something one unit tall is when it's one unit in front of the camera
+    ///
+    /// Divide by the distance to get the size of things further away, except with an orthographic
+    /// projection where this is the size at every distance.
+    pub fn pixels_per_unit(&self, screen_height: u32) -> f32 {
+        match self.projection {
+            Projection::Perspective => screen_height as f32 / (2.0 * (self.fovy.to_radians() / 2.0).tan()),
+            Projection::Orthographic { height } => screen_height as f32 / height,
+        }
+    }
+
+    /// The ray through a point of the window, from the near plane away from the camera
+    ///
+    /// The point is taken back through the inverse of the matrix the shaders draw with, so the ray goes
+    /// through exactly what is drawn under it. Positions aren't rounded to whole pixels, a cursor between
+    /// two pixels gets a ray between them.
+    ///
+    /// Args:
+    ///     cursor_px: the point in physical pixels from the window's top left corner, like winit's cursor
+    ///         position, logical positions like egui's have to be multiplied by the scale factor first
+    ///     viewport: the part of the window the camera draws into, points outside it get rays outside the view
+    pub fn screen_to_ray(&self, cursor_px: (f32, f32), viewport: &Viewport) -> Ray {
+        use cgmath::{InnerSpace, SquareMatrix};
+
+        let forward = (self.target - self.eye).normalize();
+        let Some(inverse) = self.build_view_projection_matrix().invert() else {
+            return Ray { origin: self.eye, direction: forward };
+        };
+        let ndc_x = (cursor_px.0 - viewport.x as f32) / viewport.width.max(1) as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (cursor_px.1 - viewport.y as f32) / viewport.height.max(1) as f32 * 2.0;
+        // wgpu's depth runs from 0 at the near plane to 1 at the far plane
+        let unproject = |depth: f32| {
+            let point = inverse * cgmath::Vector4::new(ndc_x, ndc_y, depth, 1.0);
+            cgmath::Point3::from_homogeneous(point)
+        };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        Ray { origin: near, direction: (far - near).normalize() }
+    }
+
+    /// Where a point of the world is drawn in the window, the inverse of `screen_to_ray`
+    ///
+    /// Returns physical pixels from the window's top left corner, which may be outside the viewport when
+    /// the point is off to the side, or None when the point is behind the camera.
+    pub fn world_to_screen(&self, point: cgmath::Point3<f32>, viewport: &Viewport) -> Option<(f32, f32)> {
+        // w is the distance in front of the camera for a perspective projection but always 1 for an orthographic one
+        if (point - self.eye).dot(self.target - self.eye) <= 0.0 {
+            return None;
+        }
+        let clip = self.build_view_projection_matrix() * point.to_homogeneous();
+        let (ndc_x, ndc_y) = (clip.x / clip.w, clip.y / clip.w);
+        Some((
+            viewport.x as f32 + (ndc_x + 1.0) / 2.0 * viewport.width as f32,
+            viewport.y as f32 + (1.0 - ndc_y) / 2.0 * viewport.height as f32,
+        ))
     }
 }
 
@@ -48,6 +245,18 @@ pub struct CameraUniform {
     // We can't use cgmath with bytemuck directly, so we'll have
     // to convert the Matrix4 into a 4x4 f32 array
     view_proj: [[f32; 4]; 4],
+    /// position of the camera, w is unused
+    view_position: [f32; 4],
+    /// geometry closer than this fades out, 0 turns fading off
+    pub near_fade: f32,
+    // uniforms are padded out to 16 bytes
+    _padding: [f32; 3],
+    /// everything on the side the normal in xyz points to, past w along it, is cut away, all zero cuts nothing
+    pub section_plane: [f32; 4],
+    /// color the insides of cut models are filled with, w is 1 if they are filled
+    pub section_cap_color: [f32; 4],
+    /// direction the camera looks in, w is 1 for an orthographic projection where every view ray is parallel to it
+    view_forward: [f32; 4],
 }
 
 impl CameraUniform {
@@ -56,12 +265,27 @@ impl CameraUniform {
         use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.0; 4],
+            near_fade: 0.0,
+            _padding: [0.0; 3],
+            section_plane: [0.0; 4],
+            section_cap_color: [0.0; 4],
+            view_forward: [0.0, 0.0, -1.0, 0.0],
         }
     }
 
+    /// A uniform seeing through a matrix of its own rather than a camera's
+    pub fn with_view_proj(view_proj: cgmath::Matrix4<f32>) -> Self {
+        Self { view_proj: view_proj.into(), ..Self::new() }
+    }
+
     /// Update the camera matrix based off the camera values
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix().into();
+        self.view_position = camera.eye.to_homogeneous().into();
+        let forward = (camera.target - camera.eye).normalize();
+        let is_orthographic = matches!(camera.projection, Projection::Orthographic { .. });
+        self.view_forward = forward.extend(if is_orthographic { 1.0 } else { 0.0 }).into();
     }
 }
 
@@ -79,15 +303,67 @@ mod tests {
             fovy: 90.0,
             znear: 1.0,
             zfar: 100.0,
+            projection: camera::Projection::Perspective,
         };
 
         let mut camera_uniform = camera::CameraUniform::new();
 
         camera_uniform.update_view_proj(&fake_camera);
         assert_eq!(fake_camera.build_view_projection_matrix(), camera_uniform.view_proj.into());
+        assert_eq!(camera_uniform.view_position, [0.0, 1.0, 2.0, 1.0]);
     }
 
     #[test]
+    fn test_screen_to_ray() {
+        use cgmath::InnerSpace;
+
+        let camera = camera::Camera {
+            eye: (0.0, 0.0, 0.0).into(),
+            target: (0.0, 0.0, -1.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 2.0,
+            fovy: 90.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: camera::Projection::Perspective,
+        };
+        // a letterboxed viewport, so the offset has to be taken off too
+        let viewport = camera::Viewport { x: 50, y: 20, width: 200, height: 100 };
+        // the middle of the viewport is straight ahead, starting on the near plane
+        let middle = camera.screen_to_ray((150.0, 70.0), &viewport);
+        assert!((middle.origin - cgmath::Point3::new(0.0, 0.0, -0.1)).magnitude() < 1e-5, "{:?}", middle);
+        assert!((middle.direction - cgmath::Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+        // the top right corner is 45 degrees up and, with twice the width, twice as far right
+        let corner = camera.screen_to_ray((250.0, 20.0), &viewport);
+        assert!((corner.direction - cgmath::Vector3::new(2.0, 1.0, -1.0).normalize()).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn test_world_to_screen() {
+        let camera = camera::Camera {
+            eye: (1.0, 2.0, 3.0).into(),
+            target: (0.0, 0.5, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 1.5,
+            fovy: 60.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: camera::Projection::Perspective,
+        };
+        let viewport = camera::Viewport { x: 10, y: 0, width: 300, height: 200 };
+        assert_eq!(camera.world_to_screen(camera.target, &viewport), Some((160.0, 100.0)));
+        assert_eq!(camera.world_to_screen((2.0, 3.5, 6.0).into(), &viewport), None);
+
+        // a point between pixels comes back where it was, through its ray and out again
+        for cursor in [(10.25, 0.5), (123.4, 56.7), (309.0, 199.9)] {
+            let ray = camera.screen_to_ray(cursor, &viewport);
+            let (x, y) = camera.world_to_screen(ray.origin + ray.direction * 5.0, &viewport).unwrap();
+            assert!((x - cursor.0).abs() < 1e-2 && (y - cursor.1).abs() < 1e-2, "{:?} came back as {:?}", cursor, (x, y));
+        }
+    }
+
+    #[test]
+    #[allow(clippy::excessive_precision)]
     fn test_build_view_projection_matrix() {
         let fake_camera = camera::Camera {
             eye: (0.0, 1.0, 2.0).into(),
@@ -97,18 +373,123 @@ mod tests {
             fovy: 90.0,
             znear: 1.0,
             zfar: 100.0,
+            projection: camera::Projection::Perspective,
         };
 
         let return_value = fake_camera.build_view_projection_matrix();
         let expected=[
             [1.0,0.00000000,0.00000000, 0.00000000],
-            [0.00000000, 0.894427180, -0.228124112, -0.675337732],
-            [0.00000000, -0.447213590, -0.456248224, -1.35067546],
-            [0.00000000, 0.00000000, 0.130519629, 2.36658764]
+            [0.00000000, 0.894427180, -0.451730940, -0.447213590],
+            [0.00000000, -0.447213590, -0.903461900, -0.894427180],
+            [0.00000000, 0.00000000, 1.24855360, 2.23606800]
         ];
 
         assert_eq!(return_value, expected.into());
 
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_setters() {
+        let mut fake_camera = camera::Camera {
+            eye: (0.0, 1.0, 2.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 1.0,
+            fovy: 90.0,
+            znear: 1.0,
+            zfar: 100.0,
+            projection: camera::Projection::Perspective,
+        };
+
+        fake_camera.set_fovy(500.0);
+        assert_eq!(fake_camera.fovy, camera::MAX_FOVY);
+
+        assert!(fake_camera.set_clip_planes(10.0, 5.0).is_err());
+        assert!(fake_camera.set_clip_planes(0.0, 5.0).is_err());
+        fake_camera.set_clip_planes(0.5, 50.0).unwrap();
+        assert_eq!((fake_camera.znear, fake_camera.zfar), (0.5, 50.0));
+
+        fake_camera.set_aspect(1600, 800);
+        assert_eq!(fake_camera.aspect, 2.0);
+    }
+
+    #[test]
+    fn test_pixels_per_unit() {
+        let fake_camera = camera::Camera {
+            eye: (0.0, 1.0, 2.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 1.0,
+            fovy: 90.0,
+            znear: 1.0,
+            zfar: 100.0,
+            projection: camera::Projection::Perspective,
+        };
+
+        // with a 90 degree view the screen is two units tall one unit away
+        assert!((fake_camera.pixels_per_unit(600) - 300.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_orthographic() {
+        use cgmath::{InnerSpace, Transform};
+
+        let mut camera = camera::Camera {
+            eye: (0.0, 0.0, 0.0).into(),
+            target: (0.0, 0.0, -1.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 2.0,
+            fovy: 90.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: camera::Projection::Perspective,
+        };
+        // 3 units away a 90 degree view is 6 units tall, which the orthographic one keeps
+        camera.toggle_projection(3.0);
+        assert_eq!(camera.projection, camera::Projection::Orthographic { height: 6.0 });
+        assert!((camera.pixels_per_unit(600) - 100.0).abs() < 0.001);
+
+        // the top of the view stays at the top however far away it is
+        let matrix = camera.build_view_projection_matrix();
+        for depth in [1.0, 50.0] {
+            let point = matrix.transform_point((6.0, 3.0, -depth).into());
+            assert!((point.x - 1.0).abs() < 1e-4 && (point.y - 1.0).abs() < 1e-4);
+        }
+
+        // every ray runs along the view, starting where the cursor is
+        let viewport = camera::Viewport { x: 0, y: 0, width: 200, height: 100 };
+        let ray = camera.screen_to_ray((200.0, 0.0), &viewport);
+        assert!((ray.direction - cgmath::Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+        assert!((ray.origin - cgmath::Point3::new(6.0, 3.0, -0.1)).magnitude() < 1e-3);
+        assert_eq!(camera.world_to_screen((6.0, 3.0, -20.0).into(), &viewport), Some((200.0, 0.0)));
+        assert_eq!(camera.world_to_screen((0.0, 0.0, 1.0).into(), &viewport), None);
+
+        camera.scale_ortho_height(0.5);
+        assert_eq!(camera.projection, camera::Projection::Orthographic { height: 3.0 });
+        camera.toggle_projection(3.0);
+        assert_eq!(camera.projection, camera::Projection::Perspective);
+
+        // heights from the host are kept usable too
+        camera.set_projection(camera::Projection::Orthographic { height: 0.0 });
+        assert_eq!(camera.projection, camera::Projection::Orthographic { height: camera::MIN_ORTHO_HEIGHT });
+        camera.set_projection(camera::Projection::Orthographic { height: -5.0 });
+        assert!(camera.build_view_projection_matrix().x.x.is_finite());
+    }
+
+    #[test]
+    fn test_letterboxed() {
+        use camera::Viewport;
+
+        let full = Viewport { x: 0, y: 0, width: 1000, height: 500 };
+        assert_eq!(Viewport::letterboxed(1000, 500, None), full);
+        assert_eq!(Viewport::letterboxed(1000, 500, Some(2.0)), full);
+        // a wide window gets bars on the sides, a tall one on the top and bottom
+        assert_eq!(Viewport::letterboxed(1000, 500, Some(1.0)), Viewport { x: 250, y: 0, width: 500, height: 500 });
+        assert_eq!(Viewport::letterboxed(1000, 1000, Some(16.0 / 9.0)), Viewport { x: 0, y: 218, width: 1000, height: 563 });
+
+        let viewport = Viewport::letterboxed(1000, 500, Some(1.0));
+        assert_eq!(viewport.local_point(300.0, 100.0), Some((50.0, 100.0)));
+        assert_eq!(viewport.local_point(100.0, 100.0), None);
+        assert_eq!(viewport.center(), (500.0, 250.0));
+    }
+}
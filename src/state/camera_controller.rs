@@ -1,13 +1,23 @@
 /// Define the controls for the camera and handle user input.
-use super::camera::Camera;
+use super::{accessibility::AccessibilitySettings, camera::{Camera, Projection}, key_bindings::{Action, KeyBindings}, tween::{AnimationSettings, Tween}, up_axis::UpAxis};
 
 use winit::{
     event::*,
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
 };
+
+/// How far one line of the mouse wheel moves the camera
+const WHEEL_STEP: f32 = 2.5;
+
+/// How much one line of the mouse wheel shrinks the orthographic view
+const ORTHO_WHEEL_FACTOR: f32 = 0.8;
+
+/// Share of the focus distance it has to change by before focus is pulled again
+const FOCUS_TOLERANCE: f32 = 0.05;
+
 pub struct CameraController {
+    /// units the movement keys move the camera every second
     speed: f32,
-    sensitivity: f32,
     // Movement controls
     is_forward_pressed: bool,
     is_backward_pressed: bool,
@@ -20,24 +30,57 @@ pub struct CameraController {
     is_looking_right: bool,
     is_looking_up: bool,
     is_looking_down: bool,
+    // Zoom controls (page up/down)
+    is_zoom_in_pressed: bool,
+    is_zoom_out_pressed: bool,
+    // Lens zoom (Z), narrows the field of view
+    is_lens_zoomed: bool,
+    is_lens_zoom_changed: bool,
+    /// the projection key was pressed since the last update
+    is_projection_toggled: bool,
+    fov_transition: Option<Tween<f32>>,
     // Camera help controls
-    is_h_pressed: bool,
     is_being_helped: bool,
-    is_h_just_pressed: bool,
+    /// whether the camera is at the help cube or on its way there, as opposed to with the user
+    is_at_help: bool,
+    /// the camera flying to the help cube or back, eye and target
+    help_flight: Option<Tween<(cgmath::Point3<f32>, cgmath::Point3<f32>)>>,
     // Saved camera fields
     eyecpy: cgmath::Point3<f32>,
     targetcpy: cgmath::Point3<f32>,
     // Camera rotation state
     yaw: f32,   // Left/right rotation
     pitch: f32, // Up/down rotation
+    // Near plane safety
+    /// radius of the sphere kept out of geometry around the eye, None lets the camera fly through things
+    pub collision_radius: Option<f32>,
+    /// geometry closer than this fades out, 0 turns fading off
+    pub near_fade_distance: f32,
+    /// keyboard rates and hold/toggle behavior
+    pub accessibility: AccessibilitySettings,
+    /// which key does what
+    bindings: KeyBindings,
+    /// normal field of view in degrees
+    pub base_fovy: f32,
+    /// field of view while Z zooms the lens in
+    pub zoom_fovy: f32,
+    /// seconds zooming and flying to the help cube take
+    pub animation: AnimationSettings,
+    /// units from the eye that depth of field keeps sharp
+    focus_distance: f32,
+    /// focus moving to a new distance
+    focus_pull: Option<Tween<f32>>,
+    /// which way is up, yaw turns around it
+    up_axis: UpAxis,
+    /// held modifier keys, keys pressed with ctrl are editing shortcuts rather than camera controls
+    modifiers: ModifiersState,
 }
 
 impl CameraController {
     /// Create new camera controller
-    pub fn new(speed: f32) -> Self {
+    pub fn new(speed: f32, accessibility: AccessibilitySettings, bindings: KeyBindings, up_axis: UpAxis, animation: AnimationSettings) -> Self {
         Self {
             speed,
-            sensitivity: 0.1,
             is_forward_pressed: false,
             is_backward_pressed: false,
             is_left_pressed: false,
@@ -48,13 +91,30 @@ impl CameraController {
             is_looking_right: false,
             is_looking_up: false,
             is_looking_down: false,
-            is_h_pressed: false,
+            is_zoom_in_pressed: false,
+            is_zoom_out_pressed: false,
+            is_lens_zoomed: false,
+            is_lens_zoom_changed: false,
+            is_projection_toggled: false,
+            fov_transition: None,
             is_being_helped: true,
-            is_h_just_pressed: false,
+            is_at_help: true,
+            help_flight: None,
             yaw: -90.0,   // Start looking along -Z
             pitch: 0.0,
-            eyecpy: (0.0, 1.0, 2.0).into(),
+            eyecpy: up_axis.point_from_y_up((0.0, 1.0, 2.0).into()),
             targetcpy: (0.0, 0.0, 0.0).into(),
+            collision_radius: Some(0.2),
+            near_fade_distance: 0.5,
+            accessibility,
+            bindings,
+            base_fovy: 45.0,
+            zoom_fovy: 15.0,
+            animation,
+            focus_distance: 5.0,
+            focus_pull: None,
+            up_axis,
+            modifiers: ModifiersState::empty(),
         }
     }
 
@@ -69,66 +129,94 @@ impl CameraController {
                     ..
                 },
                 ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    // WASD controls
-                    KeyCode::KeyW => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyA => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyS => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyD => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    // Up/Down controls
-                    KeyCode::Space => {
-                        self.is_up_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::ShiftLeft => {
-                        self.is_down_pressed = is_pressed;
-                        true
-                    }
-                    // Arrow key controls
-                    KeyCode::ArrowLeft => {
-                        self.is_looking_left = is_pressed;
-                        true
-                    }
-                    KeyCode::ArrowRight => {
-                        self.is_looking_right = is_pressed;
-                        true
-                    }
-                    KeyCode::ArrowUp => {
-                        self.is_looking_up = is_pressed;
-                        true
-                    }
-                    KeyCode::ArrowDown => {
-                        self.is_looking_down = is_pressed;
-                        true
-                    }
-                    // help menu toggle
-                    KeyCode::KeyH => {
-                        self.is_h_pressed = is_pressed;
-                        if is_pressed && !self.is_being_helped {
-                            self.is_being_helped = true;
-                        }
-                        else if is_pressed && self.is_being_helped {
-                            self.is_h_just_pressed = true;
-                            self.is_being_helped = false;
-                        }
-                        true
-                    }
-                    _ => false,
+            } if !(*state == ElementState::Pressed && self.modifiers.control_key()) => {
+                self.process_key(*keycode, *state == ElementState::Pressed)
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Move or look while a bound key is held, for key presses that don't come from the window
+    ///
+    /// Returns true if the key is bound to a camera action.
+    pub fn process_key(&mut self, keycode: KeyCode, is_pressed: bool) -> bool {
+        // movement keys can be made sticky
+        let movement = self.accessibility.movement;
+        match self.bindings.action(keycode) {
+            // WASD controls
+            Some(Action::MoveForward) => {
+                movement.switch(&mut self.is_forward_pressed, is_pressed);
+                true
+            }
+            Some(Action::MoveLeft) => {
+                movement.switch(&mut self.is_left_pressed, is_pressed);
+                true
+            }
+            Some(Action::MoveBackward) => {
+                movement.switch(&mut self.is_backward_pressed, is_pressed);
+                true
+            }
+            Some(Action::MoveRight) => {
+                movement.switch(&mut self.is_right_pressed, is_pressed);
+                true
+            }
+            // Up/Down controls
+            Some(Action::MoveUp) => {
+                movement.switch(&mut self.is_up_pressed, is_pressed);
+                true
+            }
+            Some(Action::MoveDown) => {
+                movement.switch(&mut self.is_down_pressed, is_pressed);
+                true
+            }
+            // Zoom controls, the same as the mouse wheel
+            Some(Action::ZoomIn) => {
+                self.is_zoom_in_pressed = is_pressed;
+                true
+            }
+            Some(Action::ZoomOut) => {
+                self.is_zoom_out_pressed = is_pressed;
+                true
+            }
+            // Lens zoom
+            Some(Action::LensZoom) => {
+                let was_zoomed = self.is_lens_zoomed;
+                self.accessibility.modal_controls.switch(&mut self.is_lens_zoomed, is_pressed);
+                if was_zoomed != self.is_lens_zoomed {
+                    self.fov_transition = None;
+                    self.is_lens_zoom_changed = true;
                 }
+                true
+            }
+            Some(Action::ToggleProjection) => {
+                self.is_projection_toggled |= is_pressed;
+                true
+            }
+            // Arrow key controls
+            Some(Action::LookLeft) => {
+                self.is_looking_left = is_pressed;
+                true
+            }
+            Some(Action::LookRight) => {
+                self.is_looking_right = is_pressed;
+                true
+            }
+            Some(Action::LookUp) => {
+                self.is_looking_up = is_pressed;
+                true
+            }
+            Some(Action::LookDown) => {
+                self.is_looking_down = is_pressed;
+                true
+            }
+            // help menu toggle
+            Some(Action::Help) => {
+                self.accessibility.modal_controls.switch(&mut self.is_being_helped, is_pressed);
+                true
             }
             _ => false,
         }
@@ -139,8 +227,8 @@ impl CameraController {
         // if not in the help menu
         if !self.is_being_helped {
             // Always process mouse movement
-            self.yaw += dx as f32 * self.sensitivity;
-            self.pitch -= dy as f32 * self.sensitivity;
+            self.yaw += dx as f32 * self.accessibility.mouse_sensitivity;
+            self.pitch -= dy as f32 * self.accessibility.mouse_sensitivity;
 
             // Allow full 360-degree horizontal rotation
             if self.yaw > 360.0 {
@@ -164,24 +252,47 @@ impl CameraController {
                 self.pitch.to_radians(),
             );
 
-            let front = cgmath::Vector3::new(
+            // worked out with y up, then turned to the world's up axis
+            let front = self.up_axis.from_y_up(cgmath::Vector3::new(
                 yaw_rad.cos() * pitch_rad.cos(),
                 pitch_rad.sin(),
                 yaw_rad.sin() * pitch_rad.cos(),
-            ).normalize();
+            )).normalize();
 
-            camera.eye += scroll * front * self.speed * 50.0;
+            // moving closer doesn't make anything bigger without perspective, so the view shrinks instead
+            if matches!(camera.projection, Projection::Orthographic { .. }) {
+                camera.scale_ortho_height(ORTHO_WHEEL_FACTOR.powf(scroll));
+            } else {
+                camera.eye += scroll * front * WHEEL_STEP;
+            }
         }
     }
 
     /// Update the camera based of what is pressed
-    pub fn update_camera(&mut self, camera: &mut Camera) {
+    ///
+    /// Args:
+    ///     dt: seconds since the last update, held keys move and turn the camera by their rate times this
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
         use cgmath::InnerSpace;
-            self.go_to_help(camera);
+        self.go_to_help(camera, dt);
+
+        // the orthographic view starts out showing the focus at the size it had
+        if std::mem::take(&mut self.is_projection_toggled) {
+            camera.toggle_projection(self.focus_distance);
+        }
+
+        // the flight to and from the help menu has the camera until it lands
+        if !self.is_being_helped && self.help_flight.is_none() {
+            // Handle zooming from the keyboard
+            if self.is_zoom_in_pressed {
+                self.process_mouse_wheel(self.accessibility.zoom_rate * dt, camera);
+            }
+            if self.is_zoom_out_pressed {
+                self.process_mouse_wheel(-self.accessibility.zoom_rate * dt, camera);
+            }
 
-        if !self.is_being_helped {
             // Handle rotation from arrow keys
-            let rotation_speed = 0.5;
+            let rotation_speed = self.accessibility.look_rate * dt;
             if self.is_looking_left {
                 self.yaw -= rotation_speed;
             }
@@ -204,35 +315,37 @@ impl CameraController {
                 self.pitch.to_radians(),
             );
             
-            let front = cgmath::Vector3::new(
+            // worked out with y up, then turned to the world's up axis
+            let front = self.up_axis.from_y_up(cgmath::Vector3::new(
                 yaw_rad.cos() * pitch_rad.cos(),
                 pitch_rad.sin(),
                 yaw_rad.sin() * pitch_rad.cos(),
-            ).normalize();
+            )).normalize();
 
             // Calculate right vector
             let right = front.cross(camera.up).normalize();
 
             // Update movement based on where we're looking
+            let distance = self.speed * dt;
             if self.is_forward_pressed {
-                camera.eye += front * self.speed;
+                camera.eye += front * distance;
             }
             if self.is_backward_pressed {
-                camera.eye -= front * self.speed;
+                camera.eye -= front * distance;
             }
             if self.is_right_pressed {
-                camera.eye += right * self.speed;
+                camera.eye += right * distance;
             }
             if self.is_left_pressed {
-                camera.eye -= right * self.speed;
+                camera.eye -= right * distance;
             }
             
             // Handle up/down movement
             if self.is_up_pressed {
-                camera.eye.y += self.speed;
+                camera.eye += camera.up * distance;
             }
             if self.is_down_pressed {
-                camera.eye.y -= self.speed;
+                camera.eye -= camera.up * distance;
             }
         
             // Update where we're looking
@@ -241,22 +354,85 @@ impl CameraController {
         }
     }
 
-    // sets the camera's position and direction to face the help menu
-    pub fn go_to_help(&mut self, camera: &mut Camera) {
-        // set camera direction to help cube location if not already in menu
-        if self.is_being_helped {
-            camera.eye = (0.0, 0.0, 2.0).into();
-            camera.target = (0.0, 0.0, 0.0).into();
+    /// Start smoothly changing the camera's field of view
+    pub fn transition_fov(&mut self, camera: &Camera, fovy: f32, seconds: f32) {
+        self.fov_transition = Some(Tween::new(camera.fovy, fovy, seconds));
+    }
+
+    /// Move any field of view change forward
+    ///
+    /// Args:
+    ///     dt: seconds since the last update
+    pub fn update_fov(&mut self, camera: &mut Camera, dt: f32) {
+        if self.is_lens_zoom_changed {
+            let fovy = if self.is_lens_zoomed { self.zoom_fovy } else { self.base_fovy };
+            self.transition_fov(camera, fovy, self.animation.zoom_seconds);
+            self.is_lens_zoom_changed = false;
         }
-        // set camera direction to previous state if already in menu
-        if !self.is_being_helped {
-            if self.is_h_just_pressed {
-                camera.eye = self.eyecpy;
-                camera.target = self.targetcpy;
-                self.is_h_just_pressed = false;
+
+        if let Some(transition) = &mut self.fov_transition {
+            camera.set_fovy(transition.advance(dt));
+            if transition.is_finished() {
+                self.fov_transition = None;
+            }
+        }
+    }
+
+    /// Units from the eye that depth of field keeps sharp
+    pub fn focus_distance(&self) -> f32 {
+        self.focus_distance
+    }
+
+    /// Focus at a distance straight away
+    pub fn set_focus_distance(&mut self, distance: f32) {
+        self.focus_distance = distance;
+        self.focus_pull = None;
+    }
+
+    /// Start smoothly moving the focus to a distance, unless it is already there or on its way
+    pub fn pull_focus(&mut self, distance: f32) {
+        let heading_to = self.focus_pull.as_ref().map_or(self.focus_distance, |pull| pull.to());
+        if (heading_to - distance).abs() > heading_to.abs() * FOCUS_TOLERANCE {
+            self.focus_pull = Some(Tween::new(self.focus_distance, distance, self.animation.focus_seconds));
+        }
+    }
+
+    /// Move any focus pull forward
+    ///
+    /// Args:
+    ///     dt: seconds since the last update
+    pub fn update_focus(&mut self, dt: f32) {
+        if let Some(pull) = &mut self.focus_pull {
+            self.focus_distance = pull.advance(dt);
+            if pull.is_finished() {
+                self.focus_pull = None;
+            }
+        }
+    }
+
+    /// Fly the camera to face the help menu when help is turned on, and back to where it was when turned off
+    ///
+    /// Args:
+    ///     dt: seconds the flight moves on
+    fn go_to_help(&mut self, camera: &mut Camera, dt: f32) {
+        let help_pose = (self.up_axis.point_from_y_up((0.0, 0.0, 2.0).into()), (0.0, 0.0, 0.0).into());
+        if self.is_being_helped != self.is_at_help {
+            self.is_at_help = self.is_being_helped;
+            let to = if self.is_at_help { help_pose } else { (self.eyecpy, self.targetcpy) };
+            self.help_flight = Some(Tween::new((camera.eye, camera.target), to, self.animation.help_flight_seconds));
+        }
+
+        if let Some(flight) = &mut self.help_flight {
+            (camera.eye, camera.target) = flight.advance(dt);
+            if flight.is_finished() {
+                self.help_flight = None;
             }
-            self.eyecpy = camera.eye.clone();
-            self.targetcpy = camera.target.clone();
+        } else if self.is_at_help {
+            (camera.eye, camera.target) = help_pose;
+        } else {
+            // remember where the user was for coming back from the help menu
+            self.eyecpy = camera.eye;
+            self.targetcpy = camera.target;
         }
     }
 }
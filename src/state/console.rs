@@ -0,0 +1,61 @@
+//! A command line at the bottom of the debug panel, with the last few lines it printed above it
+use std::collections::VecDeque;
+
+/// Lines of output kept, older ones scroll away
+const CONSOLE_HISTORY: usize = 50;
+
+/// What was typed and printed so far
+#[derive(Debug, Default)]
+pub struct Console {
+    lines: VecDeque<String>,
+    input: String,
+}
+
+impl Console {
+    /// Add text to the output, one line per line of the text
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.lines.len() == CONSOLE_HISTORY {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line.to_string());
+        }
+    }
+
+    /// Lay out the output and the command line, returns a command once enter is pressed on it
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        egui::ScrollArea::vertical().max_height(120.0).stick_to_bottom(true).show(ui, |ui| {
+            for line in &self.lines {
+                ui.monospace(line);
+            }
+        });
+        let response = ui.add(egui::TextEdit::singleline(&mut self.input).font(egui::TextStyle::Monospace).hint_text("> "));
+        if !response.lost_focus() || !ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+            return None;
+        }
+        // stay on the line for the next command
+        response.request_focus();
+        let command = std::mem::take(&mut self.input);
+        if command.trim().is_empty() {
+            return None;
+        }
+        self.print(&format!("> {}", command));
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print_scrolls() {
+        let mut console = Console::default();
+        console.print("a\nb");
+        for i in 0..CONSOLE_HISTORY {
+            console.print(&i.to_string());
+        }
+        assert_eq!(console.lines.len(), CONSOLE_HISTORY);
+        assert_eq!(console.lines.front().map(String::as_str), Some("0"));
+    }
+}
@@ -0,0 +1,105 @@
+//! A small window for shaping the easing curve of each cutscene track by dragging its two handles
+use super::{easing::EasingCurve, locale::Strings, timeline::Track};
+
+/// Width and height of the curve's square in points
+const CANVAS_SIZE: f32 = 160.0;
+
+/// Lowest and highest values the square shows, room for curves that pull back or overshoot
+const Y_MIN: f32 = -0.5;
+const Y_MAX: f32 = 1.5;
+
+/// Straight pieces the curve is drawn with
+const CURVE_SEGMENTS: usize = 48;
+
+/// Radius of the handles in points
+const HANDLE_RADIUS: f32 = 5.0;
+
+/// The curve editor, edits are made to the curves in place and written back by the caller
+#[derive(Debug, Default)]
+pub struct CurveEditor {
+    /// the track being edited
+    selected: usize,
+}
+
+impl CurveEditor {
+    /// Lay out the window for a timeline's tracks and their curves
+    pub fn show(&mut self, context: &egui::Context, curves: &mut [(Track, EasingCurve)], strings: &Strings) {
+        egui::Window::new(strings.get("curves.title")).default_pos((300.0, 8.0)).show(context, |ui| {
+            if curves.is_empty() {
+                ui.label(strings.get("curves.empty"));
+                return;
+            }
+            self.selected = self.selected.min(curves.len() - 1);
+            egui::ComboBox::from_id_salt("curve_track").selected_text(track_name(curves[self.selected].0, strings)).show_ui(ui, |ui| {
+                for (index, (track, _)) in curves.iter().enumerate() {
+                    ui.selectable_value(&mut self.selected, index, track_name(*track, strings));
+                }
+            });
+
+            let curve = &mut curves[self.selected].1;
+            let (mut p1, mut p2) = (curve.p1(), curve.p2());
+
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(CANVAS_SIZE, CANVAS_SIZE), egui::Sense::hover());
+            let to_screen = |(x, y): (f32, f32)| egui::pos2(rect.left() + x * rect.width(), rect.bottom() - (y - Y_MIN) / (Y_MAX - Y_MIN) * rect.height());
+            for (index, handle) in [&mut p1, &mut p2].into_iter().enumerate() {
+                let response = ui.interact(egui::Rect::from_center_size(to_screen(*handle), egui::Vec2::splat(HANDLE_RADIUS * 3.0)), ui.id().with(("curve_handle", index)), egui::Sense::drag());
+                let delta = response.drag_delta();
+                handle.0 += delta.x / rect.width();
+                handle.1 -= delta.y / rect.height() * (Y_MAX - Y_MIN);
+            }
+
+            ui.horizontal(|ui| {
+                for value in [&mut p1.0, &mut p1.1, &mut p2.0, &mut p2.1] {
+                    ui.add(egui::DragValue::new(value).speed(0.01).max_decimals(2));
+                }
+            });
+            let edited = EasingCurve::new(p1, p2);
+            *curve = if ui.button(strings.get("curves.linear")).clicked() { EasingCurve::LINEAR } else { edited };
+
+            // the unit square the curve runs across, its handles, and the curve itself
+            let painter = ui.painter_at(rect);
+            let stroke = ui.visuals().widgets.noninteractive.fg_stroke;
+            painter.rect_stroke(egui::Rect::from_two_pos(to_screen((0.0, 0.0)), to_screen((1.0, 1.0))), 0.0, stroke);
+            let handle_color = ui.visuals().selection.bg_fill;
+            for (end, handle) in [((0.0, 0.0), curve.p1()), ((1.0, 1.0), curve.p2())] {
+                painter.line_segment([to_screen(end), to_screen(handle)], egui::Stroke::new(1.0, handle_color));
+                painter.circle_filled(to_screen(handle), HANDLE_RADIUS, handle_color);
+            }
+            let points = (0..=CURVE_SEGMENTS).map(|i| {
+                let x = i as f32 / CURVE_SEGMENTS as f32;
+                to_screen((x, curve.evaluate(x)))
+            }).collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(2.0, ui.visuals().strong_text_color())));
+        });
+    }
+}
+
+/// What a track is called in the list
+fn track_name(track: Track, strings: &Strings) -> String {
+    match track {
+        Track::Camera => strings.get("curves.camera").to_string(),
+        Track::Entity { model, id } => format!("{} {} #{}", strings.get("curves.entity"), model, id.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::world::instance::EntityId;
+
+    #[test]
+    fn test_showing_changes_nothing() {
+        let context = egui::Context::default();
+        let mut editor = CurveEditor { selected: 5 };
+        let mut curves = vec![
+            (Track::Camera, EasingCurve::LINEAR),
+            (Track::Entity { model: 0, id: EntityId(3) }, EasingCurve::new((0.42, 0.0), (0.58, 1.0))),
+        ];
+        let before = curves.clone();
+        let output = context.run(egui::RawInput::default(), |context| editor.show(context, &mut curves, &Strings::default()));
+        assert_eq!(curves, before);
+        // a track that went away isn't picked anymore
+        assert_eq!(editor.selected, 1);
+        assert!(!output.shapes.is_empty());
+    }
+}
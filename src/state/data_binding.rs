@@ -0,0 +1,228 @@
+//! Live data driving the world, to use it as a 3D dashboard of sensor readings
+//!
+//! `res/bindings.txt` names an MQTT broker and maps the numbers published to its topics onto cubes:
+//!
+//! ```text
+//! broker localhost:1883
+//! # <topic> [json field] -> <property> <targets> [scale [offset]]
+//! sensors/room1/temperature -> height 0 12 0.1
+//! sensors/room2 humidity -> scale 0 13 0.01 0.5
+//! sensors/+/alarm -> material 0
+//! factory/machines running -> count
+//! ```
+//!
+//! A value is multiplied by the scale and the offset is added before it is used. `height` lifts an instance
+//! of a model along the up axis, `scale` sizes it, `material` picks a model's material by number and
+//! `count` sets how many cubes each side of the grid has. Payloads are a plain number, `true` or `false`,
+//! or a JSON object holding the named field. The newest value of each binding is applied every frame,
+//! so it holds when the grid is rebuilt.
+use std::sync::mpsc;
+
+use super::{mqtt::{self, Message}, world::instance::EntityId};
+
+/// Where the bindings are loaded from, relative to the resource folder
+pub const BINDINGS_FILE: &str = "bindings.txt";
+
+/// What a value drives
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundProperty {
+    /// how far along the up axis an instance sits
+    Height { model: usize, id: EntityId },
+    /// an instance's size
+    Scale { model: usize, id: EntityId },
+    /// which of a model's materials it is drawn with, rounded to a whole number
+    Material { model: usize },
+    /// cubes along each side of the grid, rounded to a whole number
+    Count,
+}
+
+/// One topic driving one property
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    /// subscription filter, `+` and `#` match like MQTT's
+    pub topic: String,
+    /// field of a JSON payload the number is in, the whole payload is the number without one
+    pub field: Option<String>,
+    pub property: BoundProperty,
+    pub scale: f32,
+    pub offset: f32,
+}
+
+/// The broker and the bindings from `bindings.txt`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BindingConfig {
+    /// address of the MQTT broker, nothing is subscribed to without one
+    pub broker: Option<String>,
+    pub bindings: Vec<Binding>,
+}
+
+impl BindingConfig {
+    /// Read a bindings file, see the module's docs for the format
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut config = BindingConfig::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at_line = |e: anyhow::Error| anyhow::anyhow!("line {}: {}", line_number + 1, e);
+            match line.strip_prefix("broker ") {
+                Some(address) => config.broker = Some(address.trim().to_string()),
+                None => config.bindings.push(Self::parse_binding(line).map_err(at_line)?),
+            }
+        }
+        Ok(config)
+    }
+
+    fn parse_binding(line: &str) -> anyhow::Result<Binding> {
+        let (source, target) = line.split_once("->").ok_or_else(|| anyhow::anyhow!("missing -> between the topic and what it drives"))?;
+        let mut source = source.split_whitespace();
+        let topic = source.next().ok_or_else(|| anyhow::anyhow!("missing topic"))?.to_string();
+        let field = source.next().map(str::to_string);
+        if source.next().is_some() {
+            anyhow::bail!("expected a topic and at most one field before ->");
+        }
+
+        let mut words = target.split_whitespace();
+        let kind = words.next().unwrap_or_default();
+        let numbers = words.map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()?;
+        let (property, rest) = match (kind, &numbers[..]) {
+            ("height", [model, id, rest @ ..]) => (BoundProperty::Height { model: *model as usize, id: EntityId(*id as u32) }, rest),
+            ("scale", [model, id, rest @ ..]) => (BoundProperty::Scale { model: *model as usize, id: EntityId(*id as u32) }, rest),
+            ("height" | "scale", _) => anyhow::bail!("{} needs a model and an id", kind),
+            ("material", [model, rest @ ..]) => (BoundProperty::Material { model: *model as usize }, rest),
+            ("material", _) => anyhow::bail!("material needs a model"),
+            ("count", rest) => (BoundProperty::Count, rest),
+            _ => anyhow::bail!("unknown property {}", kind),
+        };
+        let (scale, offset) = match rest {
+            [] => (1.0, 0.0),
+            [scale] => (*scale, 0.0),
+            [scale, offset] => (*scale, *offset),
+            _ => anyhow::bail!("expected at most a scale and an offset after the targets"),
+        };
+        Ok(Binding { topic, field, property, scale, offset })
+    }
+}
+
+/// Bindings and the newest value each got
+#[derive(Default)]
+pub struct DataBindings {
+    bindings: Vec<Binding>,
+    /// scaled values by binding, None until the first message
+    values: Vec<Option<f32>>,
+    messages: Option<mpsc::Receiver<Message>>,
+}
+
+impl DataBindings {
+    /// Subscribe to the topics of every binding, when there is a broker
+    pub fn new(config: BindingConfig) -> Self {
+        let mut topics = config.bindings.iter().map(|binding| binding.topic.clone()).collect::<Vec<_>>();
+        topics.sort();
+        topics.dedup();
+        let messages = match config.broker {
+            Some(broker) if !topics.is_empty() => Some(mqtt::subscribe(broker, format!("rust3d-{}", std::process::id()), topics)),
+            _ => None,
+        };
+        Self { values: vec![None; config.bindings.len()], bindings: config.bindings, messages }
+    }
+
+    /// Take in the messages that arrived since the last call
+    pub fn update(&mut self) {
+        let messages = self.messages.as_ref().map(|messages| messages.try_iter().collect::<Vec<_>>()).unwrap_or_default();
+        for message in messages {
+            self.receive(&message);
+        }
+    }
+
+    /// Store the value a message carries for every binding of its topic
+    fn receive(&mut self, message: &Message) {
+        for (binding, value) in self.bindings.iter().zip(&mut self.values) {
+            if !mqtt::topic_matches(&binding.topic, &message.topic) {
+                continue;
+            }
+            match read_value(&message.payload, binding.field.as_deref()) {
+                Some(number) => *value = Some(number * binding.scale + binding.offset),
+                None => log::debug!("No number for {} in {:?}", binding.topic, String::from_utf8_lossy(&message.payload)),
+            }
+        }
+    }
+
+    /// What every binding that got a value drives and the value, in the order of the file
+    pub fn values(&self) -> impl Iterator<Item = (BoundProperty, f32)> + '_ {
+        self.bindings.iter().zip(&self.values).filter_map(|(binding, value)| value.map(|value| (binding.property, value)))
+    }
+}
+
+/// The number in a payload, the whole of it or the value of a field of a JSON object
+///
+/// The field is found by scanning for its key rather than parsing the JSON, which is enough for the
+/// flat objects sensors send.
+fn read_value(payload: &[u8], field: Option<&str>) -> Option<f32> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+    let text = match field {
+        Some(field) => {
+            let key = format!("\"{}\"", field);
+            let after_key = &text[text.find(&key)? + key.len()..];
+            after_key.trim_start().strip_prefix(':')?.trim_start()
+        }
+        None => text,
+    };
+    let end = text.find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c))).unwrap_or(text.len());
+    match &text[..end] {
+        "" if text.starts_with("true") => Some(1.0),
+        "" if text.starts_with("false") => Some(0.0),
+        number => number.parse::<f32>().ok().filter(|value| value.is_finite()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "broker localhost:1883\n# comment\nsensors/t -> height 0 12 0.1\nsensors/h humidity -> scale 0 13 0.01 0.5\nalarm -> material 1\nmachines -> count";
+        let config = BindingConfig::parse(text).unwrap();
+        assert_eq!(config.broker.as_deref(), Some("localhost:1883"));
+        assert_eq!(config.bindings[0], Binding {
+            topic: "sensors/t".to_string(),
+            field: None,
+            property: BoundProperty::Height { model: 0, id: EntityId(12) },
+            scale: 0.1,
+            offset: 0.0,
+        });
+        assert_eq!(config.bindings[1].field.as_deref(), Some("humidity"));
+        assert_eq!((config.bindings[1].scale, config.bindings[1].offset), (0.01, 0.5));
+        assert_eq!(config.bindings[2].property, BoundProperty::Material { model: 1 });
+        assert_eq!(config.bindings[3].property, BoundProperty::Count);
+
+        assert!(BindingConfig::parse("sensors/t height 0 12").is_err());
+        assert!(BindingConfig::parse("sensors/t -> height 0").is_err());
+        assert!(BindingConfig::parse("sensors/t -> glow").is_err());
+        assert!(BindingConfig::parse("sensors/t -> count 1 2 3").is_err());
+    }
+
+    #[test]
+    fn test_read_value() {
+        assert_eq!(read_value(b" 21.5\n", None), Some(21.5));
+        assert_eq!(read_value(b"{\"temp\": -3e1, \"humidity\":40}", Some("humidity")), Some(40.0));
+        assert_eq!(read_value(b"{\"temp\": -3e1}", Some("temp")), Some(-30.0));
+        assert_eq!(read_value(b"{\"on\": true}", Some("on")), Some(1.0));
+        assert_eq!(read_value(b"{\"temp\": \"warm\"}", Some("temp")), None);
+        assert_eq!(read_value(b"{\"temp\": 1}", Some("humidity")), None);
+        assert_eq!(read_value(b"hot", None), None);
+    }
+
+    #[test]
+    fn test_receive() {
+        let config = BindingConfig::parse("sensors/+ -> height 0 1 2 1\nsensors/b -> count").unwrap();
+        let mut bindings = DataBindings::new(config);
+        assert_eq!(bindings.values().count(), 0);
+        bindings.receive(&Message { topic: "sensors/a".to_string(), payload: b"3".to_vec() });
+        assert_eq!(bindings.values().collect::<Vec<_>>(), vec![(BoundProperty::Height { model: 0, id: EntityId(1) }, 7.0)]);
+        // a topic both match drives both
+        bindings.receive(&Message { topic: "sensors/b".to_string(), payload: b"4".to_vec() });
+        assert_eq!(bindings.values().map(|(_, value)| value).collect::<Vec<_>>(), vec![9.0, 4.0]);
+    }
+}
@@ -0,0 +1,621 @@
+//! An egui panel drawn over the world for reading and changing settings while the program runs
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use super::{asset_browser::{AssetBrowser, SpawnRequest}, camera::{MAX_FOVY, MIN_FOVY}, console::Console, curve_editor::CurveEditor, dof::DofSettings, easing::EasingCurve, frame_stats::FrameStats, inspector::Inspector, locale::Strings, recent, timeline::Track, tonemap::{ToneMapOperator, ToneMapSettings}, tweaks::{Tweak, TweakKind}, world::{crowd::MAX_AGENTS, Inspection}};
+
+/// Largest grid the panel's slider goes up to, rows and columns
+pub const MAX_GRID_SIZE: u32 = 50;
+
+/// Frames shown as bars in the stats overlay, newest on the right
+const GRAPH_FRAMES: usize = 120;
+
+/// Frame time in milliseconds that fills a bar of the graph, slower frames are cut off
+const GRAPH_MAX_MS: f32 = 50.0;
+
+/// Most the exposure slider goes up to, it is a multiplier so 1 leaves the scene as it is
+const MAX_EXPOSURE: f32 = 8.0;
+
+/// Furthest the depth of field slider focuses
+const MAX_FOCUS_DISTANCE: f32 = 100.0;
+
+/// Widest aperture on the depth of field slider
+const MAX_APERTURE: f32 = 4.0;
+
+/// Ranges of the near and far clipping plane sliders
+const NEAR_RANGE: std::ops::RangeInclusive<f32> = 0.001..=10.0;
+const FAR_RANGE: std::ops::RangeInclusive<f32> = 1.0..=10000.0;
+
+/// Frame time of 60 fps, marked across the graph
+const GRAPH_TARGET_MS: f32 = 1000.0 / 60.0;
+
+/// What the panel shows and edits, copied out of the state before the panel runs and written back after
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugValues {
+    pub eye: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+    /// field of view in degrees when not zoomed
+    pub fovy: f32,
+    /// distances to the near and far clipping planes
+    pub znear: f32,
+    pub zfar: f32,
+    /// cubes along each side of the grid
+    pub grid_size: u32,
+    /// agents walking between the cubes
+    pub crowd_size: u32,
+    pub studio: bool,
+    pub section: bool,
+    pub section_caps: bool,
+    pub sort_front_to_back: bool,
+    pub tone_mapping: ToneMapSettings,
+    /// smooth edges after tone mapping
+    pub fxaa: bool,
+    pub depth_of_field: DofSettings,
+    /// units from the eye kept sharp, moved by picking when depth of field is on
+    pub focus_distance: f32,
+    /// light probes instead of flat ambient light
+    pub global_illumination: bool,
+    /// surfaces colored by how many lights reach them, with each light's range outlined
+    pub light_counts: bool,
+    /// every instance's bounding box outlined
+    pub bounds: bool,
+    /// the world's axes drawn in the window's corner
+    pub axis_gizmo: bool,
+    /// the picked cube, if a single one is picked
+    pub inspection: Option<Inspection>,
+    /// values shaders declared for tuning
+    pub tweaks: Vec<Tweak>,
+    /// a line entered in the console, for the caller to run
+    pub command: Option<String>,
+    /// every track of the cutscene with its easing curve
+    pub curves: Vec<(Track, EasingCurve)>,
+    /// share of the window's resolution the scene is drawn at, shown on the stats overlay
+    pub render_scale: f32,
+    /// files opened lately, newest first
+    pub recent: Vec<String>,
+    /// a recent file picked to open again, for the caller to open
+    pub open_recent: Option<String>,
+    /// a message shown at the top of the window whatever is open, like quality being lowered
+    pub notice: Option<String>,
+    /// each unique wgpu error and how often it happened, only captured with the debug-validation feature
+    pub gpu_errors: Vec<(String, usize)>,
+}
+
+/// Debug panel, shown and hidden with the key left of 1, the asset browser, shown with F2, the
+/// frame stats overlay, shown with F3, the inspector, shown with F4, the cutscene's curve
+/// editor, shown with F5, and the recent files, shown with F7
+///
+/// The panels only get the mouse while the cursor is free. Opening one frees it, and clicking
+/// anywhere off the panels locks it again for looking around. The overlay never takes input.
+pub struct DebugUi {
+    pub visible: bool,
+    pub show_assets: bool,
+    pub show_stats: bool,
+    pub show_inspector: bool,
+    pub show_curves: bool,
+    pub show_recent: bool,
+    inspector: Inspector,
+    curve_editor: CurveEditor,
+    console: Console,
+    context: egui::Context,
+    renderer: egui_wgpu::Renderer,
+    /// window pixels per egui point
+    pixels_per_point: f32,
+    /// input since the last frame, in egui's form
+    events: Vec<egui::Event>,
+    pointer: egui::Pos2,
+    modifiers: egui::Modifiers,
+    /// shapes and texture changes from the last run, waiting to be drawn
+    output: Option<(Vec<egui::ClippedPrimitive>, egui::TexturesDelta)>,
+}
+
+impl DebugUi {
+    /// Set up the panel
+    ///
+    /// Args:
+    ///     format: format of the surface the panel is drawn onto
+    ///     pixels_per_point: the window's scale factor
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, pixels_per_point: f32) -> Self {
+        Self {
+            visible: false,
+            show_assets: false,
+            show_stats: false,
+            show_inspector: false,
+            show_curves: false,
+            show_recent: false,
+            inspector: Inspector::default(),
+            curve_editor: CurveEditor::default(),
+            console: Console::default(),
+            context: egui::Context::default(),
+            // drawn in its own pass straight onto the surface, so no depth or multisampling
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1, false),
+            pixels_per_point,
+            events: Vec::new(),
+            pointer: egui::Pos2::ZERO,
+            modifiers: egui::Modifiers::default(),
+            output: None,
+        }
+    }
+
+    /// Call this for window events before anything else sees them
+    ///
+    /// Args:
+    ///     is_cursor_free: the cursor isn't locked for looking around, so it can point at the panel
+    ///
+    /// Returns true if the panel used the event and nothing else should get it
+    pub fn process_events(&mut self, event: &WindowEvent, is_cursor_free: bool) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::Backquote),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.visible = !self.visible;
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F2),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.show_assets = !self.show_assets;
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F3),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.show_stats = !self.show_stats;
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F4),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.show_inspector = !self.show_inspector;
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F5),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.show_curves = !self.show_curves;
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F7),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.show_recent = !self.show_recent;
+                return true;
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.pixels_per_point = *scale_factor as f32;
+                return false;
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                let state = modifiers.state();
+                self.modifiers = egui::Modifiers {
+                    alt: state.alt_key(),
+                    ctrl: state.control_key(),
+                    shift: state.shift_key(),
+                    mac_cmd: false,
+                    command: state.control_key(),
+                };
+                return false;
+            }
+            _ => (),
+        }
+        if !self.is_open() {
+            return false;
+        }
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } if is_cursor_free => {
+                self.pointer = egui::pos2(position.x as f32, position.y as f32) / self.pixels_per_point;
+                self.events.push(egui::Event::PointerMoved(self.pointer));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } if is_cursor_free => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    _ => return false,
+                };
+                self.events.push(egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+                self.context.is_pointer_over_area()
+            }
+            WindowEvent::MouseWheel { delta, .. } if is_cursor_free => {
+                let (unit, delta) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (egui::MouseWheelUnit::Line, egui::vec2(*x, *y)),
+                    MouseScrollDelta::PixelDelta(delta) => (egui::MouseWheelUnit::Point, egui::vec2(delta.x as f32, delta.y as f32) / self.pixels_per_point),
+                };
+                self.events.push(egui::Event::MouseWheel { unit, delta, modifiers: self.modifiers });
+                self.context.is_pointer_over_area()
+            }
+            WindowEvent::KeyboardInput { event, .. } if self.context.wants_keyboard_input() => {
+                let pressed = event.state == ElementState::Pressed;
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    if let Some(key) = egui_key(code) {
+                        self.events.push(egui::Event::Key { key, physical_key: Some(key), pressed, repeat: event.repeat, modifiers: self.modifiers });
+                    }
+                }
+                if let Some(text) = event.text.as_ref().filter(|text| pressed && text.chars().all(|c| !c.is_control())) {
+                    self.events.push(egui::Event::Text(text.to_string()));
+                }
+                // typing into the panel shouldn't also fly the camera around
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a panel that takes the mouse is showing
+    pub fn is_open(&self) -> bool {
+        self.visible || self.show_assets || self.show_inspector || self.show_curves || self.show_recent
+    }
+
+    /// Add what a console command printed to the console
+    pub fn print(&mut self, text: &str) {
+        self.console.print(text);
+    }
+
+    /// Window pixels per egui point, for turning positions from the panels into window positions
+    pub fn pixels_per_point(&self) -> f32 {
+        self.pixels_per_point
+    }
+
+    /// Lay out the panels and the overlay, editing the values in place
+    ///
+    /// Args:
+    ///     stats: recent frame times for the overlay
+    ///     assets: the models the asset browser lists
+    ///     size: size of the window in pixels
+    ///     dt: seconds since the last frame
+    ///
+    /// Returns the model to place if one was picked in the asset browser
+    pub fn run(&mut self, values: &mut DebugValues, stats: &FrameStats, assets: &mut AssetBrowser, strings: &Strings, size: PhysicalSize<u32>, dt: f32) -> Option<SpawnRequest> {
+        // input only goes to the panels, the overlay alone doesn't need it
+        if !self.is_open() {
+            self.events.clear();
+        }
+        if !self.is_open() && !self.show_stats && values.notice.is_none() {
+            return None;
+        }
+
+        let mut input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(size.width as f32, size.height as f32) / self.pixels_per_point)),
+            events: std::mem::take(&mut self.events),
+            modifiers: self.modifiers,
+            predicted_dt: dt,
+            ..Default::default()
+        };
+        input.viewports.entry(input.viewport_id).or_default().native_pixels_per_point = Some(self.pixels_per_point);
+
+        let (visible, show_assets, show_stats, show_inspector, show_curves, show_recent) = (self.visible, self.show_assets, self.show_stats, self.show_inspector, self.show_curves, self.show_recent);
+        let (inspector, console, curve_editor) = (&mut self.inspector, &mut self.console, &mut self.curve_editor);
+        let mut request = None;
+        let output = self.context.run(input, |context| {
+            if let Some(notice) = &values.notice {
+                notice_overlay(context, notice);
+            }
+            if show_stats {
+                stats_overlay(context, stats, values.render_scale, strings);
+            }
+            if visible {
+                panel(context, values, console, stats, strings);
+            }
+            if show_assets {
+                request = assets.show(context, strings);
+            }
+            if show_inspector {
+                inspector.show(context, &mut values.inspection, strings);
+            }
+            if show_curves {
+                curve_editor.show(context, &mut values.curves, strings);
+            }
+            if show_recent {
+                values.open_recent = recent::show(context, &values.recent, strings);
+            }
+        });
+        let primitives = self.context.tessellate(output.shapes, output.pixels_per_point);
+        self.output = Some((primitives, output.textures_delta));
+        request
+    }
+
+    /// Draw the panel laid out by the last `run` in a pass of its own over the finished frame
+    ///
+    /// Args:
+    ///     view: the surface texture, after the world was resolved to it
+    ///     size: size of the window in pixels
+    pub fn render(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, size: PhysicalSize<u32>) {
+        let Some((primitives, textures)) = self.output.take() else {
+            return;
+        };
+        let screen = egui_wgpu::ScreenDescriptor { size_in_pixels: [size.width, size.height], pixels_per_point: self.pixels_per_point };
+
+        for (id, delta) in &textures.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        // the renderer only hands back command buffers for paint callbacks, which the panel doesn't use
+        self.renderer.update_buffers(device, queue, encoder, &primitives, &screen);
+
+        {
+            let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Debug UI Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // keep the world that is already there
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            self.renderer.render(&mut render_pass.forget_lifetime(), &primitives, &screen);
+        }
+
+        for id in &textures.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+/// The panel's contents
+fn panel(context: &egui::Context, values: &mut DebugValues, console: &mut Console, stats: &FrameStats, strings: &Strings) {
+    egui::Window::new(strings.get("debug.title")).default_pos((8.0, 8.0)).show(context, |ui| {
+        ui.label(format!("{:.0} {}", stats.average_fps(), strings.get("debug.fps")));
+
+        ui.label(strings.get("debug.eye"));
+        ui.horizontal(|ui| {
+            for axis in [&mut values.eye.x, &mut values.eye.y, &mut values.eye.z] {
+                ui.add(egui::DragValue::new(axis).speed(0.1).max_decimals(2));
+            }
+        });
+        ui.label(format!("{}: {:.2} {:.2} {:.2}", strings.get("debug.target"), values.target.x, values.target.y, values.target.z));
+        ui.add(egui::Slider::new(&mut values.fovy, MIN_FOVY..=MAX_FOVY).suffix("°").text(strings.get("debug.fov")));
+        ui.add(egui::Slider::new(&mut values.znear, NEAR_RANGE).logarithmic(true).text(strings.get("debug.near")));
+        ui.add(egui::Slider::new(&mut values.zfar, FAR_RANGE).logarithmic(true).text(strings.get("debug.far")));
+        ui.separator();
+
+        ui.add(egui::Slider::new(&mut values.grid_size, 0..=MAX_GRID_SIZE).text(strings.get("debug.grid")));
+        ui.add(egui::Slider::new(&mut values.crowd_size, 0..=MAX_AGENTS).logarithmic(true).text(strings.get("debug.crowd")));
+        ui.checkbox(&mut values.studio, strings.get("debug.studio"));
+        ui.checkbox(&mut values.section, strings.get("debug.section"));
+        ui.add_enabled(values.section, egui::Checkbox::new(&mut values.section_caps, strings.get("debug.caps")));
+        ui.checkbox(&mut values.sort_front_to_back, strings.get("debug.sort"));
+        egui::ComboBox::from_label(strings.get("debug.tone_map"))
+            .selected_text(values.tone_mapping.operator.name())
+            .show_ui(ui, |ui| {
+                for operator in ToneMapOperator::ALL {
+                    ui.selectable_value(&mut values.tone_mapping.operator, operator, operator.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut values.tone_mapping.exposure, 0.0..=MAX_EXPOSURE).logarithmic(true).text(strings.get("debug.exposure")));
+        ui.checkbox(&mut values.fxaa, strings.get("debug.fxaa"));
+        ui.checkbox(&mut values.depth_of_field.enabled, strings.get("debug.dof"));
+        ui.add_enabled(values.depth_of_field.enabled, egui::Slider::new(&mut values.focus_distance, 0.1..=MAX_FOCUS_DISTANCE).logarithmic(true).text(strings.get("debug.focus")));
+        ui.add_enabled(values.depth_of_field.enabled, egui::Slider::new(&mut values.depth_of_field.aperture, 0.0..=MAX_APERTURE).text(strings.get("debug.aperture")));
+        ui.checkbox(&mut values.global_illumination, strings.get("debug.gi"));
+        ui.checkbox(&mut values.light_counts, strings.get("debug.light_counts"));
+        ui.checkbox(&mut values.bounds, strings.get("debug.bounds"));
+        ui.checkbox(&mut values.axis_gizmo, strings.get("debug.axis_gizmo"));
+
+        if !values.gpu_errors.is_empty() {
+            let total: usize = values.gpu_errors.iter().map(|(_, count)| count).sum();
+            egui::CollapsingHeader::new(format!("{} ({})", strings.get("debug.gpu_errors"), total)).default_open(true).show(ui, |ui| {
+                egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    for (message, count) in &values.gpu_errors {
+                        ui.horizontal_top(|ui| {
+                            ui.strong(format!("{}×", count));
+                            ui.label(message.trim());
+                        });
+                    }
+                });
+            });
+        }
+
+        egui::CollapsingHeader::new(strings.get("debug.tweaks")).show(ui, |ui| {
+            for tweak in &mut values.tweaks {
+                match tweak.kind {
+                    TweakKind::Float { min, max } => ui.add(egui::Slider::new(&mut tweak.value[0], min..=max).text(&tweak.name)),
+                    TweakKind::Color => ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut tweak.value);
+                        ui.label(&tweak.name);
+                    }).response,
+                };
+            }
+        });
+        ui.separator();
+        values.command = console.show(ui);
+    });
+}
+
+/// A message across the top of the window, like quality being lowered
+fn notice_overlay(context: &egui::Context, notice: &str) {
+    egui::Area::new(egui::Id::new("notice"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 8.0))
+        .interactable(false)
+        .show(context, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(notice);
+            });
+        });
+}
+
+/// Frame rate numbers and a bar for each recent frame in the top right corner
+fn stats_overlay(context: &egui::Context, stats: &FrameStats, render_scale: f32, strings: &Strings) {
+    egui::Area::new(egui::Id::new("frame_stats"))
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+        .interactable(false)
+        .show(context, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("{:.0} {}  {:.2} {}", stats.average_fps(), strings.get("debug.fps"), stats.average_ms(), strings.get("stats.ms")));
+                ui.label(format!("{}: {:.0} {}", strings.get("stats.low"), stats.one_percent_low_fps(), strings.get("debug.fps")));
+                ui.label(format!("{}: {:.2} {}", strings.get("stats.worst"), stats.worst_ms(), strings.get("stats.ms")));
+                if render_scale < 1.0 {
+                    ui.label(format!("{}: {:.0}%", strings.get("stats.render_scale"), render_scale * 100.0));
+                }
+                let submissions = stats.submissions;
+                ui.label(format!("{}: {} ({} {})", strings.get("stats.submissions"), submissions.submissions, submissions.command_buffers, strings.get("stats.command_buffers")));
+                let bind_groups = stats.bind_groups;
+                ui.label(format!("{}: {} {}, {} {}, {} {}", strings.get("stats.bind_groups"), bind_groups.cached, strings.get("stats.cached"), bind_groups.hits, strings.get("stats.reused"), bind_groups.created, strings.get("stats.created")));
+                let animation = stats.animation;
+                ui.label(format!("{}: {} {}, {} {}, {:.1} KB", strings.get("stats.animation"), animation.keys, strings.get("stats.keys"), animation.clips, strings.get("stats.clips"), animation.bytes as f32 / 1024.0));
+
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(GRAPH_FRAMES as f32 * 2.0, 50.0), egui::Sense::hover());
+                let painter = ui.painter_at(rect);
+                let height_of = |ms: f32| (ms / GRAPH_MAX_MS).min(1.0) * rect.height();
+                let times = stats.times();
+                let shown = times.len().min(GRAPH_FRAMES);
+                let bar_width = rect.width() / GRAPH_FRAMES as f32;
+                for (i, dt) in times.skip(stats.times().len().saturating_sub(shown)).enumerate() {
+                    let ms = dt * 1000.0;
+                    let x = rect.right() - (shown - i) as f32 * bar_width;
+                    let color = if ms > GRAPH_TARGET_MS * 2.0 {
+                        egui::Color32::RED
+                    } else if ms > GRAPH_TARGET_MS {
+                        egui::Color32::YELLOW
+                    } else {
+                        egui::Color32::GREEN
+                    };
+                    let bar = egui::Rect::from_min_max(egui::pos2(x, rect.bottom() - height_of(ms)), egui::pos2(x + bar_width, rect.bottom()));
+                    painter.rect_filled(bar, 0.0, color);
+                }
+                let target_y = rect.bottom() - height_of(GRAPH_TARGET_MS);
+                painter.hline(rect.x_range(), target_y, egui::Stroke::new(1.0, egui::Color32::WHITE));
+            });
+        });
+}
+
+/// The egui key for the keys text fields use, None for the rest
+fn egui_key(code: KeyCode) -> Option<egui::Key> {
+    Some(match code {
+        KeyCode::Backspace => egui::Key::Backspace,
+        KeyCode::Delete => egui::Key::Delete,
+        KeyCode::Enter | KeyCode::NumpadEnter => egui::Key::Enter,
+        KeyCode::Tab => egui::Key::Tab,
+        KeyCode::Escape => egui::Key::Escape,
+        KeyCode::Home => egui::Key::Home,
+        KeyCode::End => egui::Key::End,
+        KeyCode::ArrowLeft => egui::Key::ArrowLeft,
+        KeyCode::ArrowRight => egui::Key::ArrowRight,
+        KeyCode::ArrowUp => egui::Key::ArrowUp,
+        KeyCode::ArrowDown => egui::Key::ArrowDown,
+        KeyCode::KeyA => egui::Key::A,
+        KeyCode::KeyC => egui::Key::C,
+        KeyCode::KeyV => egui::Key::V,
+        KeyCode::KeyX => egui::Key::X,
+        KeyCode::KeyZ => egui::Key::Z,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_egui_key() {
+        assert_eq!(egui_key(KeyCode::Backspace), Some(egui::Key::Backspace));
+        assert_eq!(egui_key(KeyCode::NumpadEnter), Some(egui::Key::Enter));
+        assert_eq!(egui_key(KeyCode::KeyQ), None);
+    }
+
+    #[test]
+    fn test_panel_keeps_values() {
+        // laying the panel out without any input leaves everything as it was
+        let context = egui::Context::default();
+        let strings = Strings::default();
+        let mut values = DebugValues {
+            eye: (1.0, 2.0, 3.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            grid_size: 5,
+            crowd_size: 0,
+            studio: false,
+            section: true,
+            section_caps: true,
+            sort_front_to_back: true,
+            tone_mapping: ToneMapSettings::default(),
+            fxaa: true,
+            depth_of_field: DofSettings::default(),
+            focus_distance: 5.0,
+            global_illumination: false,
+            light_counts: false,
+            bounds: false,
+            axis_gizmo: true,
+            inspection: None,
+            tweaks: crate::state::tweaks::parse_tweaks("// tweak float a 0.5 0 1\n// tweak color b 1 0 0").unwrap(),
+            command: None,
+            curves: Vec::new(),
+            render_scale: 1.0,
+            recent: Vec::new(),
+            open_recent: None,
+            notice: None,
+            gpu_errors: vec![("Validation Error\n\nCaused by: bad binding".to_string(), 3), ("other".to_string(), 1)],
+        };
+        let before = values.clone();
+        let mut console = Console::default();
+        let _ = context.run(egui::RawInput::default(), |context| panel(context, &mut values, &mut console, &FrameStats::default(), &strings));
+        assert_eq!(values, before);
+    }
+
+    #[test]
+    fn test_stats_overlay_draws_bars() {
+        let context = egui::Context::default();
+        let mut stats = FrameStats::default();
+        for _ in 0..GRAPH_FRAMES * 2 {
+            stats.push(0.01);
+        }
+        let output = context.run(egui::RawInput::default(), |context| stats_overlay(context, &stats, 0.75, &Strings::default()));
+        assert!(!output.shapes.is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+//! Depth of field, blurring what is nearer or further than the distance the camera is focused at
+//!
+//! See `dof.wgsl`. The resolved scene and its depth are gathered into an HDR frame of its own, which
+//! is tone mapped in place of the scene. Where the camera focuses is kept by the camera controller.
+use wgpu::util::DeviceExt;
+
+use super::{bind_groups::BindGroupCache, camera::{Camera, Projection}, tonemap::{HdrFrame, ToneMapper}};
+
+/// The widest blur in pixels for a frame 1080 pixels tall, scaled with the frame's height
+const MAX_BLUR_PIXELS: f32 = 12.0;
+
+/// How the camera's lens blurs what is out of focus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DofSettings {
+    pub enabled: bool,
+    /// how quickly things blur away from the focus distance, 1 blurs the far distance the most
+    pub aperture: f32,
+}
+
+impl Default for DofSettings {
+    fn default() -> Self {
+        Self { enabled: false, aperture: 1.0 }
+    }
+}
+
+/// What the shader needs, laid out like `DofParams` in the shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct DofParams {
+    focus_distance: f32,
+    aperture: f32,
+    znear: f32,
+    zfar: f32,
+    max_radius: f32,
+    /// 1 when the depth buffer runs evenly from near to far, as an orthographic projection leaves it
+    orthographic: f32,
+    _padding: [f32; 2],
+}
+
+impl DofParams {
+    fn new(camera: &Camera, focus_distance: f32, aperture: f32, height: u32) -> Self {
+        Self {
+            focus_distance: focus_distance.max(camera.znear),
+            aperture: aperture.max(0.0),
+            znear: camera.znear,
+            zfar: camera.zfar,
+            max_radius: MAX_BLUR_PIXELS * height as f32 / 1080.0,
+            orthographic: if matches!(camera.projection, Projection::Orthographic { .. }) { 1.0 } else { 0.0 },
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// The frame the blurred scene goes into, made again when the scene's size changes
+struct DofTarget {
+    width: u32,
+    height: u32,
+    frame: HdrFrame,
+}
+
+/// The pass blurring the scene by its depth
+pub struct DepthOfField {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    target: Option<DofTarget>,
+}
+
+impl DepthOfField {
+    /// Set up the pass
+    ///
+    /// Args:
+    ///     depth_sample_count: samples per pixel of the scene's depth buffer
+    pub fn new(device: &wgpu::Device, depth_sample_count: u32) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("DOF Params Buffer"),
+            contents: bytemuck::cast_slice(&[<DofParams as bytemuck::Zeroable>::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: depth_sample_count > 1,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("dof_bind_group_layout"),
+        });
+
+        // with MSAA the depth buffer has samples, the first one is enough to tell how far a pixel is
+        let mut source = include_str!("../dof.wgsl").to_string();
+        if depth_sample_count > 1 {
+            source = source.replace("var t_depth: texture_depth_2d;", "var t_depth: texture_depth_multisampled_2d;");
+        }
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("dof.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("DOF Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("DOF Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: super::tonemap::HDR_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self { pipeline, bind_group_layout, params_buffer, target: None }
+    }
+
+    /// Make the frame to blur a scene of some size into, again only when the size changed
+    ///
+    /// Args:
+    ///     tone_mapper: makes the frame, so it can be tone mapped like the scene's
+    pub fn prepare(&mut self, device: &wgpu::Device, tone_mapper: &ToneMapper, (width, height): (u32, u32)) {
+        if self.target.as_ref().is_some_and(|target| target.width == width && target.height == height) {
+            return;
+        }
+        self.target = Some(DofTarget { width, height, frame: tone_mapper.frame(device, width, height) });
+    }
+
+    /// Focus the lens for this frame, after `prepare`
+    ///
+    /// Args:
+    ///     focus_distance: units from the camera that stay sharp
+    ///     aperture: how quickly things blur away from the focus distance
+    pub fn focus(&self, queue: &wgpu::Queue, camera: &Camera, focus_distance: f32, aperture: f32) {
+        if let Some(target) = &self.target {
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[DofParams::new(camera, focus_distance, aperture, target.height)]));
+        }
+    }
+
+    /// The blurred scene made by the last `draw`, None while depth of field is off
+    pub fn frame(&self) -> Option<&HdrFrame> {
+        self.target.as_ref().map(|target| &target.frame)
+    }
+
+    /// Let go of the frame while depth of field is off
+    pub fn release(&mut self) {
+        self.target = None;
+    }
+
+    /// Record blurring the scene into the frame, after `prepare` and `focus`
+    ///
+    /// Args:
+    ///     bind_groups: where the group binding the scene and its depth is kept between frames
+    ///     scene_view: the resolved scene, the size `prepare` was given
+    ///     depth_view: the depth alone of the scene's depth buffer, it may be smaller than the scene
+    pub fn draw(&self, bind_groups: &BindGroupCache, encoder: &mut wgpu::CommandEncoder, scene_view: &wgpu::TextureView, depth_view: &wgpu::TextureView) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        let bind_group = bind_groups.get(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(scene_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+            ],
+            label: Some("dof_bind_group"),
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("DOF Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.frame.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, bind_group.as_ref(), &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dof_params() {
+        let camera = Camera {
+            eye: (0.0, 0.0, 5.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 16.0 / 9.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+        // the same blur covers the same share of the frame at any height
+        let params = DofParams::new(&camera, 5.0, 2.0, 540);
+        assert_eq!(params.max_radius, MAX_BLUR_PIXELS / 2.0);
+        assert_eq!((params.focus_distance, params.aperture, params.znear, params.zfar), (5.0, 2.0, 0.1, 100.0));
+        // focus can't be in front of the near plane, and a negative aperture is a pinhole
+        let params = DofParams::new(&camera, 0.0, -1.0, 1080);
+        assert_eq!((params.focus_distance, params.aperture), (0.1, 0.0));
+        // four floats, then the radius padded out to 16 bytes like the shader's struct
+        assert_eq!(std::mem::size_of::<DofParams>(), 32);
+    }
+}
@@ -0,0 +1,97 @@
+//! Cubic Bézier easing curves, the same shape as CSS `cubic-bezier()`
+use std::fmt;
+
+/// Steps of bisection when finding where on the curve a time falls, plenty for f32
+const SOLVE_STEPS: usize = 24;
+
+/// A curve from (0, 0) to (1, 1) shaped by two handles, x is the time between two keys and y how far along the move is
+///
+/// The handles' x stays between 0 and 1 so every time has exactly one value, y can leave that
+/// range to overshoot or pull back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EasingCurve {
+    p1: (f32, f32),
+    p2: (f32, f32),
+}
+
+impl EasingCurve {
+    /// Moves at the same speed the whole way
+    pub const LINEAR: EasingCurve = EasingCurve { p1: (0.0, 0.0), p2: (1.0, 1.0) };
+
+    /// Starts and stops gently, so a move doesn't begin or end with a jolt
+    pub const EASE_IN_OUT: EasingCurve = EasingCurve { p1: (0.42, 0.0), p2: (0.58, 1.0) };
+
+    /// A curve with its handles at two points, their x is clamped between 0 and 1
+    pub const fn new(p1: (f32, f32), p2: (f32, f32)) -> Self {
+        Self { p1: (p1.0.clamp(0.0, 1.0), p1.1), p2: (p2.0.clamp(0.0, 1.0), p2.1) }
+    }
+
+    /// The handle leaving (0, 0)
+    pub fn p1(&self) -> (f32, f32) {
+        self.p1
+    }
+
+    /// The handle arriving at (1, 1)
+    pub fn p2(&self) -> (f32, f32) {
+        self.p2
+    }
+
+    /// How far along the move is at a share of the time between two keys, clamped between 0 and 1
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        if *self == Self::LINEAR {
+            return x;
+        }
+        // x only grows along the curve, so halving the range always closes in on the one point with this x
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..SOLVE_STEPS {
+            let middle = (low + high) / 2.0;
+            if bezier(middle, self.p1.0, self.p2.0) < x {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        bezier((low + high) / 2.0, self.p1.1, self.p2.1)
+    }
+}
+
+/// One coordinate of the curve at parameter t, with the end points fixed at 0 and 1
+fn bezier(t: f32, p1: f32, p2: f32) -> f32 {
+    let u = 1.0 - t;
+    3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+}
+
+/// `x1 y1 x2 y2`, the form timelines store curves in
+impl fmt::Display for EasingCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.p1.0, self.p1.1, self.p2.0, self.p2.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        for x in [0.0, 0.25, 0.5, 1.0] {
+            assert_eq!(EasingCurve::LINEAR.evaluate(x), x);
+        }
+        // handles on the diagonal are linear too, however the curve is parameterized
+        let diagonal = EasingCurve::new((0.3, 0.3), (0.6, 0.6));
+        assert!((diagonal.evaluate(0.4) - 0.4).abs() < 1e-4);
+
+        // CSS ease-in-out starts slow, is halfway at half time and ends at 1
+        let ease_in_out = EasingCurve::EASE_IN_OUT;
+        assert!(ease_in_out.evaluate(0.1) < 0.05);
+        assert!((ease_in_out.evaluate(0.5) - 0.5).abs() < 1e-4);
+        assert_eq!(ease_in_out.evaluate(1.0), 1.0);
+        assert_eq!(ease_in_out.evaluate(2.0), 1.0);
+
+        // handles above 1 overshoot
+        let overshoot = EasingCurve::new((0.3, 1.6), (0.7, 1.4));
+        assert!(overshoot.evaluate(0.7) > 1.0);
+        assert_eq!(EasingCurve::new((-1.0, 0.0), (3.0, 1.0)), EasingCurve::LINEAR);
+    }
+}
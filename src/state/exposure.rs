@@ -0,0 +1,259 @@
+//! Automatic exposure, a compute pass measuring how bright the HDR frame is and an exposure that follows it over time
+//!
+//! See `exposure.wgsl`. The exposure stays on the GPU in a storage buffer that the tone mapping pass reads.
+use wgpu::util::DeviceExt;
+
+/// Darkest and brightest log2 luminance the histogram tells apart, darker or brighter pixels go in the end bins
+const MIN_LOG_LUMINANCE: f32 = -10.0;
+const MAX_LOG_LUMINANCE: f32 = 4.0;
+
+/// Bins in the histogram, the first one counts black pixels
+const HISTOGRAM_BINS: u64 = 256;
+
+/// Pixels along each side of a histogram workgroup
+const WORKGROUP_SIZE: u32 = 16;
+
+/// How the exposure follows the scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoExposureSettings {
+    /// measure the HDR frame and expose it for its brightness, off keeps the exposure the settings give
+    pub enabled: bool,
+    /// how quickly the exposure catches up with a change in brightness, higher is quicker
+    pub adaptation_speed: f32,
+    /// least and most the scene is brightened by, so a black or blinding frame doesn't blow up the exposure
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            adaptation_speed: 1.5,
+            min_exposure: 0.25,
+            max_exposure: 8.0,
+        }
+    }
+}
+
+/// What the compute passes need to know this frame
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureParams {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    dt: f32,
+    adaptation_speed: f32,
+    width: u32,
+    height: u32,
+    min_exposure: f32,
+    max_exposure: f32,
+}
+
+impl ExposureParams {
+    fn new(settings: &AutoExposureSettings, width: u32, height: u32, dt: f32) -> Self {
+        Self {
+            min_log_luminance: MIN_LOG_LUMINANCE,
+            log_luminance_range: MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE,
+            dt,
+            adaptation_speed: settings.adaptation_speed.max(0.0),
+            width,
+            height,
+            min_exposure: settings.min_exposure,
+            max_exposure: settings.max_exposure.max(settings.min_exposure),
+        }
+    }
+}
+
+/// The adapted luminance and exposure as they sit in the state buffer
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ExposureState {
+    average_luminance: f32,
+    exposure: f32,
+}
+
+impl ExposureState {
+    /// Draws the frame as it is, for pictures that shouldn't be adapted like thumbnails
+    pub const UNIT: ExposureState = ExposureState { average_luminance: 0.0, exposure: 1.0 };
+}
+
+/// The histogram and adaptation passes
+pub struct AutoExposure {
+    settings: AutoExposureSettings,
+    histogram_pipeline: wgpu::ComputePipeline,
+    adapt_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    histogram_buffer: wgpu::Buffer,
+    /// the adapted exposure, read by the tone mapping pass
+    state_buffer: wgpu::Buffer,
+    /// seconds since the exposure last adapted
+    pending_dt: f32,
+}
+
+impl AutoExposure {
+    pub fn new(device: &wgpu::Device, settings: AutoExposureSettings) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Exposure Params Buffer"),
+            contents: bytemuck::cast_slice(&[ExposureParams::new(&settings, 1, 1, 0.0)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Exposure Histogram Buffer"),
+            size: HISTOGRAM_BINS * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // an average of 0 tells the first frame to start adapted instead of easing in from black
+        let state_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Exposure State Buffer"),
+            contents: bytemuck::cast_slice(&[ExposureState::UNIT]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                storage(2),
+                storage(3),
+            ],
+            label: Some("exposure_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../exposure.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Exposure Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Self {
+            settings,
+            histogram_pipeline: pipeline("Exposure Histogram Pipeline", "build_histogram"),
+            adapt_pipeline: pipeline("Exposure Adapt Pipeline", "adapt"),
+            bind_group_layout,
+            params_buffer,
+            histogram_buffer,
+            state_buffer,
+            pending_dt: 0.0,
+        }
+    }
+
+    /// The buffer holding the adapted exposure, for the tone mapping pass
+    pub fn state_buffer(&self) -> &wgpu::Buffer {
+        &self.state_buffer
+    }
+
+    /// Bind an HDR frame for measuring, made again whenever the frame's texture is
+    pub fn bind(&self, device: &wgpu::Device, hdr_view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.state_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("exposure_bind_group"),
+        })
+    }
+
+    /// Let time pass, the exposure adapts by all of it the next time it is measured
+    ///
+    /// Args:
+    ///     dt: seconds since the last update
+    pub fn update(&mut self, dt: f32) {
+        self.pending_dt += dt;
+    }
+
+    /// Record the passes measuring a frame and adapting the exposure to it
+    ///
+    /// Args:
+    ///     bind_group: from `bind`, for the frame that was just drawn
+    ///     width, height: size of that frame in pixels
+    pub fn measure(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, bind_group: &wgpu::BindGroup, width: u32, height: u32) {
+        let params = ExposureParams::new(&self.settings, width, height, std::mem::take(&mut self.pending_dt));
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Exposure Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_pipeline(&self.histogram_pipeline);
+        pass.dispatch_workgroups(width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1);
+        pass.set_pipeline(&self.adapt_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_match_shader() {
+        let settings = AutoExposureSettings { max_exposure: 0.1, adaptation_speed: -1.0, ..Default::default() };
+        let params = ExposureParams::new(&settings, 640, 480, 0.016);
+        // limits the wrong way round collapse to the minimum, and the eye never adapts backwards
+        assert_eq!(params.max_exposure, settings.min_exposure);
+        assert_eq!(params.adaptation_speed, 0.0);
+        assert_eq!(params.log_luminance_range, 14.0);
+
+        // the uniform and the bins are the size the shader declares
+        let shader = include_str!("../exposure.wgsl");
+        assert_eq!(std::mem::size_of::<ExposureParams>(), 8 * 4);
+        assert!(shader.contains(&format!("array<atomic<u32>, {}>", HISTOGRAM_BINS)));
+        assert!(shader.contains(&format!("@workgroup_size({}, {})", WORKGROUP_SIZE, WORKGROUP_SIZE)));
+    }
+}
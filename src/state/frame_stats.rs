@@ -0,0 +1,102 @@
+//! Rolling frame time statistics for the on screen overlay
+use std::collections::VecDeque;
+
+use super::{bind_groups::BindGroupStats, submit::SubmitStats, timeline::AnimationStats};
+
+/// Frames kept for the averages, about 5 seconds at 120 fps
+const FRAME_HISTORY: usize = 600;
+
+/// The last few hundred frame times
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    /// seconds each frame took, oldest first
+    times: VecDeque<f32>,
+    /// what went to the GPU queue since the frame before
+    pub submissions: SubmitStats,
+    /// how the bind group cache did since the frame before
+    pub bind_groups: BindGroupStats,
+    /// memory held by the cutscene's keys
+    pub animation: AnimationStats,
+}
+
+impl FrameStats {
+    /// Add the time the last frame took, dropping the oldest once the history is full
+    pub fn push(&mut self, dt: f32) {
+        if self.times.len() == FRAME_HISTORY {
+            self.times.pop_front();
+        }
+        self.times.push_back(dt);
+    }
+
+    /// Frame times in seconds, oldest first
+    pub fn times(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        self.times.iter().copied()
+    }
+
+    /// Average milliseconds per frame, 0 before any frames
+    pub fn average_ms(&self) -> f32 {
+        if self.times.is_empty() {
+            return 0.0;
+        }
+        self.times.iter().sum::<f32>() / self.times.len() as f32 * 1000.0
+    }
+
+    /// Average frames per second
+    pub fn average_fps(&self) -> f32 {
+        fps(self.average_ms())
+    }
+
+    /// Frames per second over the slowest 1% of frames, how bad the stutters are
+    pub fn one_percent_low_fps(&self) -> f32 {
+        let mut sorted = self.times.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| b.total_cmp(a));
+        let slowest = &sorted[..sorted.len().div_ceil(100)];
+        if slowest.is_empty() {
+            return 0.0;
+        }
+        fps(slowest.iter().sum::<f32>() / slowest.len() as f32 * 1000.0)
+    }
+
+    /// The slowest frame in the history in milliseconds
+    pub fn worst_ms(&self) -> f32 {
+        self.times.iter().copied().fold(0.0, f32::max) * 1000.0
+    }
+}
+
+/// Frames per second for a frame time in milliseconds, 0 for no time at all
+fn fps(ms: f32) -> f32 {
+    if ms > 0.0 { 1000.0 / ms } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_averages_and_lows() {
+        let mut stats = FrameStats::default();
+        assert_eq!(stats.average_fps(), 0.0);
+        assert_eq!(stats.one_percent_low_fps(), 0.0);
+
+        // 198 smooth frames and 2 stutters, the 1% low is the two stutters
+        for _ in 0..198 {
+            stats.push(0.01);
+        }
+        stats.push(0.05);
+        stats.push(0.1);
+        assert!((stats.one_percent_low_fps() - 1.0 / 0.075).abs() < 1e-3);
+        assert!((stats.worst_ms() - 100.0).abs() < 1e-3);
+        assert!((stats.average_ms() - (1.98 + 0.15) / 200.0 * 1000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_history_rolls() {
+        let mut stats = FrameStats::default();
+        stats.push(1.0);
+        for _ in 0..FRAME_HISTORY {
+            stats.push(0.02);
+        }
+        assert_eq!(stats.times().len(), FRAME_HISTORY);
+        assert!((stats.average_fps() - 50.0).abs() < 1e-2);
+    }
+}
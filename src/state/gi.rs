@@ -0,0 +1,276 @@
+//! Experimental global illumination, a coarse grid of light probes that replaces the flat ambient light
+//!
+//! Each probe traces rays into the world on the CPU and keeps the light coming back from each side as an
+//! ambient cube, six colors for +x, -x, +y, -y, +z and -z. A few probes are traced every frame in turn, so
+//! the grid follows moving cubes and lights a little behind them. The shaders blend the eight probes
+//! around a point instead of using the world's flat ambient.
+use cgmath::{ElementWise, InnerSpace};
+use wgpu::util::DeviceExt;
+
+use super::{camera::Ray, light::{DirectionalLight, Light, MAX_LIGHTS}, world::World};
+
+/// The six directions of an ambient cube, in the order the shader reads them
+const CUBE_AXES: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+/// How far off a surface rays towards the sun start, so they don't hit the surface they start on
+const SURFACE_OFFSET: f32 = 0.01;
+
+/// How the probe grid is laid out and how much of it is traced every frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GiSettings {
+    /// use the probes instead of the flat ambient light, can be switched from the debug panel to compare
+    pub enabled: bool,
+    /// probes along each axis, spread over the area the sun's shadows cover, at least 2 each
+    pub probe_counts: [u32; 3],
+    /// rays each probe traces when it is updated
+    pub rays_per_probe: u32,
+    /// probes traced every frame, the rest keep what they last saw
+    pub probes_per_frame: u32,
+    /// share of the light arriving at a surface it bounces back, the same for every surface
+    pub bounce_albedo: f32,
+}
+
+impl Default for GiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            probe_counts: [6, 6, 6],
+            rays_per_probe: 24,
+            probes_per_frame: 8,
+            bounce_albedo: 0.6,
+        }
+    }
+}
+
+/// Where the grid is and whether it is used, laid out like the start of `ProbeGrid` in the shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProbeGridHeader {
+    /// xyz is the first probe's position, w is 1 when the probes are used
+    origin: [f32; 4],
+    /// xyz is the distance between neighbouring probes, w is unused
+    spacing: [f32; 4],
+    /// probes along each axis, w is unused
+    counts: [u32; 4],
+}
+
+/// The light the shaders use for what isn't lit directly
+pub struct ProbeGrid {
+    pub enabled: bool,
+    settings: GiSettings,
+    /// evenly spread directions the probes trace along
+    directions: Vec<cgmath::Vector3<f32>>,
+    /// light arriving from each side of every probe, rgb and an unused w
+    probes: Vec<[[f32; 4]; 6]>,
+    /// the probe traced next
+    next: usize,
+    buffer: wgpu::Buffer,
+}
+
+impl ProbeGrid {
+    pub fn new(device: &wgpu::Device, settings: GiSettings) -> Self {
+        let settings = GiSettings { probe_counts: settings.probe_counts.map(|count| count.max(2)), ..settings };
+        let [x, y, z] = settings.probe_counts;
+        let probes = vec![[[0.0; 4]; 6]; (x * y * z) as usize];
+        let header = ProbeGridHeader { origin: [0.0; 4], spacing: [1.0, 1.0, 1.0, 0.0], counts: [x, y, z, 0] };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Probe Grid Buffer"),
+            contents: &[bytemuck::bytes_of(&header), bytemuck::cast_slice(&probes)].concat(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            enabled: settings.enabled,
+            settings,
+            directions: sphere_directions(settings.rays_per_probe.max(1)),
+            probes,
+            next: 0,
+            buffer,
+        }
+    }
+
+    /// The storage buffer the shaders read the grid from
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Trace the next few probes through the world and send the grid to the shaders
+    ///
+    /// The grid covers the sun's shadow area, so it moves along when that does.
+    pub fn update(&mut self, queue: &wgpu::Queue, world: &World) {
+        let (origin, spacing) = self.placement(&world.sun);
+        let header = ProbeGridHeader {
+            origin: [origin.x, origin.y, origin.z, if self.enabled { 1.0 } else { 0.0 }],
+            spacing: [spacing.x, spacing.y, spacing.z, 0.0],
+            counts: [self.settings.probe_counts[0], self.settings.probe_counts[1], self.settings.probe_counts[2], 0],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&header));
+        if !self.enabled {
+            return;
+        }
+
+        let lighting = ProbeLighting {
+            lights: &world.lights[..world.lights.len().min(MAX_LIGHTS)],
+            ambient: world.ambient,
+            sun: &world.sun,
+            albedo: self.settings.bounce_albedo,
+        };
+        let hit = |ray: &Ray| world.pick(ray).map(|hit| hit.distance);
+        self.trace_next(origin, spacing, &lighting, hit);
+        let header_size = std::mem::size_of::<ProbeGridHeader>() as wgpu::BufferAddress;
+        queue.write_buffer(&self.buffer, header_size, bytemuck::cast_slice(&self.probes));
+    }
+
+    /// Where the first probe goes and how far apart they are, spread over the sun's shadow area
+    fn placement(&self, sun: &DirectionalLight) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        let radius = sun.shadow_radius.max(f32::EPSILON);
+        let [x, y, z] = self.settings.probe_counts.map(|count| 2.0 * radius / (count - 1) as f32);
+        (sun.shadow_center - cgmath::Vector3::new(radius, radius, radius), cgmath::Vector3::new(x, y, z))
+    }
+
+    /// Trace this frame's share of probes, starting where the last frame stopped
+    ///
+    /// Args:
+    ///     hit: how far along a ray the closest surface is, None if the ray hits nothing
+    fn trace_next(&mut self, origin: cgmath::Point3<f32>, spacing: cgmath::Vector3<f32>, lighting: &ProbeLighting, hit: impl Fn(&Ray) -> Option<f32>) {
+        let [x, y, _] = self.settings.probe_counts;
+        for _ in 0..self.settings.probes_per_frame.min(self.probes.len() as u32) {
+            let index = self.next as u32;
+            let cell = cgmath::Vector3::new((index % x) as f32, (index / x % y) as f32, (index / (x * y)) as f32);
+            let position = origin + cell.mul_element_wise(spacing);
+            self.probes[self.next] = trace_probe(position, &self.directions, lighting, &hit);
+            self.next = (self.next + 1) % self.probes.len();
+        }
+    }
+}
+
+/// The lights a probe's rays pick up where they hit something
+struct ProbeLighting<'a> {
+    lights: &'a [Light],
+    ambient: f32,
+    sun: &'a DirectionalLight,
+    albedo: f32,
+}
+
+impl ProbeLighting<'_> {
+    /// Light bounced off a surface towards a probe
+    ///
+    /// The surface is taken to face the probe, there is no normal to go on from a bounding box.
+    fn bounced(&self, point: cgmath::Point3<f32>, facing: cgmath::Vector3<f32>, hit: &impl Fn(&Ray) -> Option<f32>) -> cgmath::Vector3<f32> {
+        let mut light = cgmath::Vector3::new(self.ambient, self.ambient, self.ambient);
+
+        let to_sun = -self.sun.direction.normalize();
+        let sun_facing = facing.dot(to_sun);
+        if sun_facing > 0.0 && self.sun.intensity > 0.0 {
+            let shadow_ray = Ray { origin: point + facing * SURFACE_OFFSET, direction: to_sun };
+            if hit(&shadow_ray).is_none() {
+                light += cgmath::Vector3::from(self.sun.color) * self.sun.intensity * sun_facing;
+            }
+        }
+        // the same falloff as the shader, unshadowed
+        for point_light in self.lights {
+            let to_light = point_light.position - point;
+            let distance = to_light.magnitude();
+            let falloff = (1.0 - (distance / point_light.range).powi(2)).clamp(0.0, 1.0);
+            let diffuse = facing.dot(to_light / distance.max(f32::EPSILON)).max(0.0);
+            light += cgmath::Vector3::from(point_light.color) * point_light.intensity * diffuse * falloff * falloff;
+        }
+        light * self.albedo
+    }
+}
+
+/// The ambient cube a probe at a point sees
+///
+/// Rays that escape see the flat ambient light like the sky gives it. A probe stuck inside something
+/// sees nothing useful, and rays that start inside a surface are left out, falling back to flat ambient.
+fn trace_probe(position: cgmath::Point3<f32>, directions: &[cgmath::Vector3<f32>], lighting: &ProbeLighting, hit: &impl Fn(&Ray) -> Option<f32>) -> [[f32; 4]; 6] {
+    let mut sums = [cgmath::Vector3::new(0.0, 0.0, 0.0); 6];
+    let mut weights = [0.0; 6];
+    for &direction in directions {
+        let radiance = match hit(&Ray { origin: position, direction }) {
+            Some(distance) if distance <= f32::EPSILON => continue,
+            Some(distance) => lighting.bounced(position + direction * distance, -direction, hit),
+            None => cgmath::Vector3::new(lighting.ambient, lighting.ambient, lighting.ambient),
+        };
+        for (axis, (sum, weight)) in CUBE_AXES.iter().zip(sums.iter_mut().zip(&mut weights)) {
+            let cosine = direction.dot((*axis).into()).max(0.0);
+            *sum += radiance * cosine;
+            *weight += cosine;
+        }
+    }
+
+    let ambient = [lighting.ambient, lighting.ambient, lighting.ambient, 0.0];
+    let mut cube = [ambient; 6];
+    for (side, (sum, weight)) in cube.iter_mut().zip(sums.iter().zip(weights)) {
+        if weight > 0.0 {
+            let light = sum / weight;
+            *side = [light.x, light.y, light.z, 0.0];
+        }
+    }
+    cube
+}
+
+/// Directions spread evenly over the sphere along a spiral
+fn sphere_directions(count: u32) -> Vec<cgmath::Vector3<f32>> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+    (0..count)
+        .map(|i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let radius = (1.0 - y * y).sqrt();
+            let angle = golden_angle * i as f32;
+            cgmath::Vector3::new(angle.cos() * radius, y, angle.sin() * radius)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lighting(sun: &DirectionalLight) -> ProbeLighting<'_> {
+        ProbeLighting { lights: &[], ambient: 0.2, sun, albedo: 0.5 }
+    }
+
+    #[test]
+    fn test_open_probe_sees_flat_ambient() {
+        let sun = DirectionalLight::new(cgmath::Vector3::new(0.0, -1.0, 0.0), 10.0);
+        let cube = trace_probe((0.0, 0.0, 0.0).into(), &sphere_directions(64), &lighting(&sun), &|_: &Ray| None);
+        assert!(cube.iter().flat_map(|side| &side[..3]).all(|light| (light - 0.2).abs() < 1e-5));
+
+        // a probe inside something keeps the flat ambient too
+        let cube = trace_probe((0.0, 0.0, 0.0).into(), &sphere_directions(64), &lighting(&sun), &|_: &Ray| Some(0.0));
+        assert!(cube.iter().all(|side| *side == [0.2, 0.2, 0.2, 0.0]));
+    }
+
+    #[test]
+    fn test_sunlit_floor_bounces_up() {
+        // a floor below the probe, lit by the sun straight above
+        let sun = DirectionalLight::new(cgmath::Vector3::new(0.0, -1.0, 0.0), 10.0);
+        let floor = |ray: &Ray| (ray.direction.y < -f32::EPSILON).then(|| (ray.origin.y + 1.0) / -ray.direction.y).filter(|distance| *distance > 0.0);
+        let cube = trace_probe((0.0, 0.0, 0.0).into(), &sphere_directions(256), &lighting(&sun), &floor);
+
+        // the floor faces the probe, so light coming from below is lit floor and light from above is sky
+        let below = cube[3];
+        let above = cube[2];
+        assert!((above[0] - 0.2).abs() < 1e-5);
+        assert!(below[0] > above[0]);
+        // bounced sun and ambient, times the albedo, never more than looking straight down at it
+        assert!(below[0] <= (0.2 + sun.intensity) * 0.5 + 1e-4);
+    }
+
+    #[test]
+    fn test_sphere_directions() {
+        let directions = sphere_directions(100);
+        assert!(directions.iter().all(|direction| (direction.magnitude() - 1.0).abs() < 1e-4));
+        // spread evenly, so they about cancel out
+        let sum: cgmath::Vector3<f32> = directions.iter().sum();
+        assert!(sum.magnitude() < 1.0);
+    }
+}
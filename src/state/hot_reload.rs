@@ -0,0 +1,103 @@
+//! Notice when the files behind the models in `resources.txt` change, so they can be loaded again
+//!
+//! Files are checked by their modification time every `CHECK_SECONDS`, which needs no help from
+//! the platform and costs a handful of metadata reads.
+use std::{path::{Path, PathBuf}, time::SystemTime};
+
+use super::world::resources;
+
+/// Seconds between looking at the files
+pub const CHECK_SECONDS: f32 = 0.5;
+
+/// One line of `resources.txt` and the files it loads from
+struct WatchedModel {
+    line: String,
+    /// each file and when it was last changed, None while it can't be read
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl WatchedModel {
+    fn new(line: String) -> Self {
+        let files = resources::model_files(&line)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|file| {
+                let modified = modified(&file);
+                (file, modified)
+            })
+            .collect();
+        Self { line, files }
+    }
+
+    /// Whether any file changed, appeared or went away since the last look
+    fn is_changed(&mut self) -> bool {
+        let mut is_changed = false;
+        for (file, seen) in &mut self.files {
+            let modified = modified(file);
+            is_changed |= modified != *seen;
+            *seen = modified;
+        }
+        is_changed
+    }
+}
+
+/// When a file in the resource folder was last changed
+fn modified(file: &Path) -> Option<SystemTime> {
+    std::fs::metadata(resources::res_dir().join(file)).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Watches the files of every model loaded from `resources.txt`
+pub struct HotReload {
+    /// in the order of `resources.txt`, the same order as the world's first models
+    models: Vec<WatchedModel>,
+    since_check: f32,
+}
+
+impl HotReload {
+    /// Start watching the files of each line of `resources.txt`
+    pub fn new(lines: &[String]) -> Self {
+        Self { models: lines.iter().cloned().map(WatchedModel::new).collect(), since_check: 0.0 }
+    }
+
+    /// Models with files that changed, as their index and line in `resources.txt`
+    ///
+    /// Args:
+    ///     dt: seconds since the last call, files are only looked at every `CHECK_SECONDS`
+    pub fn changed(&mut self, dt: f32) -> Vec<(usize, String)> {
+        self.since_check += dt;
+        if self.since_check < CHECK_SECONDS {
+            return Vec::new();
+        }
+        self.since_check = 0.0;
+
+        let mut changed = Vec::new();
+        for (index, model) in self.models.iter_mut().enumerate() {
+            if model.is_changed() {
+                // the .mtl may now name other textures
+                *model = WatchedModel::new(model.line.clone());
+                changed.push((index, model.line.clone()));
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed() {
+        let folder = resources::res_dir().join("test_files");
+        let file = folder.join(format!("hot_reload_{}.stl", std::process::id()));
+        std::fs::write(&file, "solid").unwrap();
+        let line = format!("test_files/{}", file.file_name().unwrap().to_str().unwrap());
+        let mut hot_reload = HotReload::new(std::slice::from_ref(&line));
+
+        std::fs::remove_file(&file).unwrap();
+        // nothing is looked at before CHECK_SECONDS pass
+        assert!(hot_reload.changed(CHECK_SECONDS / 2.0).is_empty());
+        assert_eq!(hot_reload.changed(CHECK_SECONDS / 2.0), vec![(0, line)]);
+        assert!(hot_reload.changed(CHECK_SECONDS).is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+//! Image based lighting, ambient light and reflections from an HDR picture of the surroundings
+//!
+//! `res/skybox/environment.hdr`, an equirectangular image, is filtered on the GPU into two cube maps
+//! when the program starts. The specular cube holds the image itself in its first mip level and
+//! reflections off rougher and rougher surfaces in the levels below, the irradiance cube the light
+//! arriving at a surface facing each way. The material shaders read them in place of the flat ambient
+//! light, and the sky draws the specular cube. Without the file both are black and left unused.
+use image::Rgba32FImage;
+use wgpu::util::DeviceExt;
+
+use super::{submit, world::{resources::load_binary, skybox::SKYBOX_DIR, texture}};
+
+/// File in the sky folder the environment is loaded from
+pub const HDR_FILE: &str = "environment.hdr";
+
+/// Size of the faces of the specular cube's first level
+pub const SPECULAR_SIZE: u32 = 256;
+
+/// Mip levels of the specular cube, from a mirror at the top to fully rough at the bottom
+pub const SPECULAR_LEVELS: u32 = 6;
+
+/// Size of the faces of the irradiance cube, it changes slowly so it can be small
+pub const IRRADIANCE_SIZE: u32 = 32;
+
+/// Format of both cubes, bright parts of the sky go well past 1
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Largest value a half float holds
+const HALF_MAX: f32 = 65504.0;
+
+/// One face of one level to draw, laid out like `FilterUniform` in ibl.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilterUniform {
+    face: u32,
+    roughness: f32,
+    source_width: f32,
+    target_size: f32,
+}
+
+/// The cubes the material shaders light surfaces with
+pub struct EnvironmentMap {
+    /// light arriving at a surface facing each way
+    pub irradiance: wgpu::TextureView,
+    /// the surroundings, each of the `SPECULAR_LEVELS` mip levels for a rougher surface
+    pub specular: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    /// whether an image was loaded, both cubes are black otherwise
+    pub is_loaded: bool,
+}
+
+impl EnvironmentMap {
+    /// Load and filter the environment image, leaving the cubes black if there isn't one
+    pub async fn load(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let image = load_binary(&format!("{}/{}", SKYBOX_DIR, HDR_FILE)).await
+            .and_then(|bytes| Ok(image::load_from_memory(&bytes)?.to_rgba32f()));
+        match image {
+            Ok(image) => Self::from_equirect(device, queue, &image),
+            Err(e) => {
+                log::info!("No environment map loaded, using flat ambient light: {}", e);
+                Self::dark(device)
+            }
+        }
+    }
+
+    /// Filter an equirectangular image into the cubes
+    pub fn from_equirect(device: &wgpu::Device, queue: &wgpu::Queue, image: &Rgba32FImage) -> Self {
+        let max_width = device.limits().max_texture_dimension_2d;
+        let image = if image.width() > max_width {
+            image::imageops::resize(image, max_width, max_width / 2, image::imageops::FilterType::Triangle)
+        } else {
+            image.clone()
+        };
+        let (width, height) = image.dimensions();
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+
+        // samples far apart read blurrier levels, so small bright spots like the sun aren't missed
+        let source = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Environment Image"),
+            size,
+            mip_level_count: texture::mip_level_count(width, height),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let halves = image.as_raw().iter().map(|value| to_half(*value)).collect::<Vec<_>>();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &source,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(&halves),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(8 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        texture::generate_mipmaps(device, &source);
+
+        let specular = create_cube(device, "Environment Specular", SPECULAR_SIZE, SPECULAR_LEVELS);
+        let irradiance = create_cube(device, "Environment Irradiance", IRRADIANCE_SIZE, 1);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../ibl.wgsl"));
+        let specular_pipeline = create_filter_pipeline(device, &shader, "fs_specular");
+        let irradiance_pipeline = create_filter_pipeline(device, &shader, "fs_irradiance");
+        // the image wraps around the horizon
+        let source_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+        let filter = Filter { device, source: &source_view, sampler: &source_sampler, source_width: width as f32 };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Environment Encoder") });
+        for level in 0..SPECULAR_LEVELS {
+            let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+            for face in 0..6 {
+                filter.draw(&mut encoder, &specular_pipeline, &specular, level, face, roughness);
+            }
+        }
+        for face in 0..6 {
+            filter.draw(&mut encoder, &irradiance_pipeline, &irradiance, 0, face, 1.0);
+        }
+        // the image's mip levels are deferred, so they go out ahead of this
+        submit::submit(device, queue, [encoder.finish()]);
+
+        Self {
+            irradiance: cube_view(&irradiance),
+            specular: cube_view(&specular),
+            sampler: create_sampler(device),
+            is_loaded: true,
+        }
+    }
+
+    /// Black cubes for when there is no image, the shaders don't read them
+    fn dark(device: &wgpu::Device) -> Self {
+        Self {
+            irradiance: cube_view(&create_cube(device, "Environment Irradiance", 1, 1)),
+            specular: cube_view(&create_cube(device, "Environment Specular", 1, 1)),
+            sampler: create_sampler(device),
+            is_loaded: false,
+        }
+    }
+}
+
+/// What every filter draw reads
+struct Filter<'a> {
+    device: &'a wgpu::Device,
+    source: &'a wgpu::TextureView,
+    sampler: &'a wgpu::Sampler,
+    source_width: f32,
+}
+
+impl Filter<'_> {
+    /// Draw one face of one level of a cube
+    fn draw(&self, encoder: &mut wgpu::CommandEncoder, pipeline: &wgpu::RenderPipeline, target: &wgpu::Texture, level: u32, face: u32, roughness: f32) {
+        let uniform = FilterUniform { face, roughness, source_width: self.source_width, target_size: (target.width() >> level).max(1) as f32 };
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Filter Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(self.source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Environment Filter Bind Group"),
+        });
+        let view = target.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            base_array_layer: face,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Environment Filter Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// A pipeline drawing one of the filters in ibl.wgsl, the layout comes from the shader
+fn create_filter_pipeline(device: &wgpu::Device, shader: &wgpu::ShaderModule, entry_point: &str) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Environment Filter Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format: FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// A cube texture the filters draw into, black until they do
+fn create_cube(device: &wgpu::Device, label: &str, size: u32, mip_level_count: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+/// Trilinear, so rough reflections blend between the specular levels
+fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// The bits of the half float closest to a value, too bright values become the brightest one
+fn to_half(value: f32) -> u16 {
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    let value = value.abs();
+    if value.is_nan() {
+        return 0x7e00;
+    }
+    // values too small for a normal half are dropped, nobody sees them
+    if value < 6.103_515_6e-5 {
+        return sign;
+    }
+    let bits = value.min(HALF_MAX).to_bits();
+    let exponent = (bits >> 23) + 15 - 127;
+    let mantissa = bits & 0x7f_ffff;
+    // rounding up can carry into the exponent, which is still the right number
+    let half = (exponent << 10 | mantissa >> 13) + (mantissa >> 12 & 1);
+    sign | half as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_half() {
+        assert_eq!(to_half(0.0), 0);
+        assert_eq!(to_half(1.0), 0x3c00);
+        assert_eq!(to_half(-2.0), 0xc000);
+        assert_eq!(to_half(0.5), 0x3800);
+        // the sun is far brighter than a half float goes
+        assert_eq!(to_half(1.0e6), 0x7bff);
+        assert_eq!(to_half(1.0e-8), 0);
+        // rounds to the closest half, 1 + 1/1024 is the next one after 1
+        assert_eq!(to_half(1.0 + 0.6 / 1024.0), 0x3c01);
+        assert_eq!(to_half(1.0 + 0.4 / 1024.0), 0x3c00);
+    }
+
+    #[test]
+    fn test_uniform_matches_shader() {
+        let shader = include_str!("../ibl.wgsl");
+        assert_eq!(std::mem::size_of::<FilterUniform>(), 4 * 4);
+        assert!(shader.contains("struct FilterUniform"));
+    }
+}
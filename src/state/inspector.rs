@@ -0,0 +1,163 @@
+//! A window for reading and typing in the picked cube's transform, material, visibility and tags
+use cgmath::Deg;
+
+use super::{locale::Strings, world::{instance::EntityId, Inspection}};
+
+/// Smallest scale the inspector lets a cube shrink to
+const MIN_SCALE: f32 = 0.01;
+
+/// Largest scale the inspector lets a cube grow to
+const MAX_SCALE: f32 = 100.0;
+
+/// The inspector window, edits are made to the inspection in place and written back by the caller
+#[derive(Default)]
+pub struct Inspector {
+    /// cube changed by a drag or typing that is still going on
+    editing: Option<EntityId>,
+    /// tags as typed, so a space can be typed before the next tag
+    tags_text: String,
+}
+
+impl Inspector {
+    /// Lay out the window for the picked cube, or a hint to pick one if there isn't a single one
+    pub fn show(&mut self, context: &egui::Context, inspection: &mut Option<Inspection>, strings: &Strings) {
+        egui::Window::new(strings.get("inspector.title")).default_pos((8.0, 560.0)).show(context, |ui| {
+            let Some(inspection) = inspection else {
+                self.editing = None;
+                ui.label(strings.get("inspector.none"));
+                return;
+            };
+            let properties = &mut inspection.properties;
+
+            // undo or another cube can change the tags under the field
+            if self.tags_text.split_whitespace().ne(properties.tags.iter().map(String::as_str)) {
+                self.tags_text = properties.tags.join(" ");
+            }
+
+            let mut responses = Vec::new();
+            egui::Grid::new("inspector").num_columns(2).show(ui, |ui| {
+                ui.label(strings.get("inspector.position"));
+                ui.horizontal(|ui| {
+                    let position = &mut properties.instance.position;
+                    for value in [&mut position.x, &mut position.y, &mut position.z] {
+                        responses.push(ui.add(egui::DragValue::new(value).speed(0.05).max_decimals(3)));
+                    }
+                });
+                ui.end_row();
+
+                // turned back into a quaternion only when changed, so just looking doesn't nudge it
+                ui.label(strings.get("inspector.rotation"));
+                let euler = cgmath::Euler::from(properties.instance.rotation);
+                let mut degrees = [Deg::from(euler.x).0, Deg::from(euler.y).0, Deg::from(euler.z).0];
+                ui.horizontal(|ui| {
+                    let turned = degrees.iter_mut()
+                        .map(|value| ui.add(egui::DragValue::new(value).speed(1.0).max_decimals(1).suffix("°")))
+                        .collect::<Vec<_>>();
+                    if turned.iter().any(egui::Response::changed) {
+                        properties.instance.rotation = cgmath::Euler::new(Deg(degrees[0]), Deg(degrees[1]), Deg(degrees[2])).into();
+                    }
+                    responses.extend(turned);
+                });
+                ui.end_row();
+
+                ui.label(strings.get("inspector.scale"));
+                responses.push(ui.add(egui::DragValue::new(&mut properties.instance.scale).speed(0.01).range(MIN_SCALE..=MAX_SCALE)));
+                ui.end_row();
+
+                ui.label(strings.get("inspector.material"));
+                let selected = inspection.materials.get(properties.material).map_or("", String::as_str);
+                let combo = egui::ComboBox::from_id_salt("inspector_material").selected_text(selected).show_ui(ui, |ui| {
+                    inspection.materials.iter().enumerate()
+                        .map(|(index, name)| ui.selectable_value(&mut properties.material, index, name))
+                        .reduce(|a, b| a | b)
+                });
+                responses.extend(combo.inner.flatten());
+                ui.end_row();
+
+                ui.label(strings.get("inspector.visible"));
+                let mut visible = !properties.hidden;
+                responses.push(ui.checkbox(&mut visible, ""));
+                properties.hidden = !visible;
+                ui.end_row();
+
+                if let Some(enabled) = &mut properties.group_enabled {
+                    ui.label(strings.get("inspector.group_enabled"));
+                    responses.push(ui.checkbox(enabled, ""));
+                    ui.end_row();
+                }
+
+                ui.label(strings.get("inspector.tags"));
+                let tags = ui.text_edit_singleline(&mut self.tags_text);
+                if tags.changed() {
+                    properties.tags = self.tags_text.split_whitespace().map(str::to_string).collect();
+                }
+                responses.push(tags);
+                ui.end_row();
+
+                ui.label(strings.get("inspector.behaviors"));
+                if inspection.behaviors.is_empty() {
+                    ui.label(strings.get("inspector.no_behaviors"));
+                } else {
+                    ui.label(inspection.behaviors.join(", "));
+                }
+                ui.end_row();
+            });
+            if let Some(size) = inspection.group_size {
+                ui.label(format!("{} {}", strings.get("inspector.group"), size));
+            }
+
+            let id = inspection.id;
+            let is_changed = responses.iter().any(egui::Response::changed);
+            let is_active = responses.iter().any(|response| response.dragged() || response.has_focus());
+            inspection.is_continuing = is_changed && self.editing == Some(id);
+            self.editing = (is_active && (is_changed || self.editing == Some(id))).then_some(id);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Rotation3;
+
+    use super::*;
+    use crate::state::world::{instance::Instance, scene::EntityProperties};
+
+    fn inspection() -> Inspection {
+        Inspection {
+            id: EntityId(3),
+            properties: EntityProperties {
+                instance: Instance {
+                    position: (1.0, 2.0, 3.0).into(),
+                    rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_y(), Deg(30.0)),
+                    scale: 0.5,
+                },
+                material: 1,
+                hidden: true,
+                tags: vec!["red".to_string(), "door".to_string()],
+                group_enabled: Some(false),
+            },
+            materials: vec!["stone".to_string(), "wood".to_string()],
+            behaviors: vec!["rotate"],
+            group_size: Some(2),
+            is_continuing: false,
+        }
+    }
+
+    #[test]
+    fn test_showing_changes_nothing() {
+        // laying the window out without any input leaves the cube as it was, rotation included
+        let context = egui::Context::default();
+        let mut inspector = Inspector::default();
+        let before = inspection();
+        let mut inspected = Some(before.clone());
+        for _ in 0..2 {
+            let _ = context.run(egui::RawInput::default(), |context| inspector.show(context, &mut inspected, &Strings::default()));
+        }
+        assert_eq!(inspected, Some(before));
+        assert_eq!(inspector.tags_text, "red door");
+
+        let mut nothing = None;
+        let _ = context.run(egui::RawInput::default(), |context| inspector.show(context, &mut nothing, &Strings::default()));
+        assert_eq!(inspector.editing, None);
+    }
+}
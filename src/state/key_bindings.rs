@@ -0,0 +1,197 @@
+//! Which key does what, read from `res/key_bindings.toml` so controls can be changed without recompiling
+use std::collections::HashMap;
+
+use winit::keyboard::KeyCode;
+
+/// Bindings file in the res folder, missing actions keep their default keys
+pub const KEY_BINDINGS_FILE: &str = "key_bindings.toml";
+
+/// Something a key can be bound to
+///
+/// Editing shortcuts held with Ctrl and the numpad selection nudges aren't rebindable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ZoomIn,
+    ZoomOut,
+    LensZoom,
+    /// switch the camera between perspective and orthographic
+    ToggleProjection,
+    LookLeft,
+    LookRight,
+    LookUp,
+    LookDown,
+    /// show the help cube, the camera and the world both follow it
+    Help,
+    GrowGrid,
+    ShrinkGrid,
+    Spin,
+    ChangeColor,
+    Resize,
+    Delete,
+    AttachBehavior,
+    Measure,
+    Group,
+}
+
+impl Action {
+    /// Every action with its name in the bindings file and its default key
+    pub const ALL: [(Action, &'static str, KeyCode); 24] = [
+        (Action::MoveForward, "move_forward", KeyCode::KeyW),
+        (Action::MoveBackward, "move_backward", KeyCode::KeyS),
+        (Action::MoveLeft, "move_left", KeyCode::KeyA),
+        (Action::MoveRight, "move_right", KeyCode::KeyD),
+        (Action::MoveUp, "move_up", KeyCode::Space),
+        (Action::MoveDown, "move_down", KeyCode::ShiftLeft),
+        (Action::ZoomIn, "zoom_in", KeyCode::PageUp),
+        (Action::ZoomOut, "zoom_out", KeyCode::PageDown),
+        (Action::LensZoom, "lens_zoom", KeyCode::KeyZ),
+        (Action::ToggleProjection, "toggle_projection", KeyCode::Numpad5),
+        (Action::LookLeft, "look_left", KeyCode::ArrowLeft),
+        (Action::LookRight, "look_right", KeyCode::ArrowRight),
+        (Action::LookUp, "look_up", KeyCode::ArrowUp),
+        (Action::LookDown, "look_down", KeyCode::ArrowDown),
+        (Action::Help, "help", KeyCode::KeyH),
+        (Action::GrowGrid, "grow_grid", KeyCode::KeyJ),
+        (Action::ShrinkGrid, "shrink_grid", KeyCode::KeyK),
+        (Action::Spin, "spin", KeyCode::Digit1),
+        (Action::ChangeColor, "change_color", KeyCode::Digit2),
+        (Action::Resize, "resize", KeyCode::Digit3),
+        (Action::Delete, "delete", KeyCode::Delete),
+        (Action::AttachBehavior, "attach_behavior", KeyCode::KeyB),
+        (Action::Measure, "measure", KeyCode::KeyM),
+        (Action::Group, "group", KeyCode::KeyG),
+    ];
+
+    /// The action a name in the bindings file stands for
+    pub fn parse(name: &str) -> Option<Action> {
+        Self::ALL.iter().find(|(_, n, _)| *n == name).map(|(action, _, _)| *action)
+    }
+
+    /// The name used in the bindings file
+    pub fn name(self) -> &'static str {
+        Self::ALL.iter().find(|(action, _, _)| *action == self).map_or("", |(_, name, _)| name)
+    }
+}
+
+/// Keys that can be bound, matched by the name winit gives them, like `KeyW` or `ArrowLeft`
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowLeft, KeyCode::ArrowRight, KeyCode::ArrowUp, KeyCode::ArrowDown,
+    KeyCode::Space, KeyCode::Tab, KeyCode::Enter, KeyCode::Backspace, KeyCode::Delete, KeyCode::Insert,
+    KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Backslash,
+    KeyCode::Semicolon, KeyCode::Quote, KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Backquote,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::NumpadAdd, KeyCode::NumpadSubtract, KeyCode::NumpadMultiply, KeyCode::NumpadDivide, KeyCode::NumpadEnter,
+];
+
+/// The key with a name, `KeyW`, `Digit1`, `ArrowLeft` and so on
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS.iter().copied().find(|code| format!("{:?}", code) == name)
+}
+
+/// The keys every action is bound to
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyBindings {
+    actions: HashMap<KeyCode, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self { actions: Action::ALL.iter().map(|(action, _, key)| (*key, *action)).collect() }
+    }
+}
+
+impl KeyBindings {
+    /// Parse bindings, one `action = "Key"` per line on top of the defaults
+    ///
+    /// Blank lines and lines starting with # are skipped, the quotes are optional. Two actions on
+    /// one key is an error, so moving an action onto a key that has one means moving that one too.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut keys = Action::ALL.iter().map(|(action, _, key)| (*action, *key)).collect::<HashMap<_, _>>();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, key) = line.split_once('=').ok_or_else(|| anyhow::anyhow!("line {}: expected action = \"Key\"", line_number + 1))?;
+            let action = Action::parse(name.trim()).ok_or_else(|| anyhow::anyhow!("line {}: unknown action {}", line_number + 1, name.trim()))?;
+            let key_name = key.trim().trim_matches('"');
+            let key = parse_key(key_name).ok_or_else(|| anyhow::anyhow!("line {}: unknown key {}", line_number + 1, key_name))?;
+            keys.insert(action, key);
+        }
+        Self::from_keys(keys)
+    }
+
+    /// The bindings with some actions moved to other keys, the same checks as `parse` apply
+    pub fn with_overrides(&self, overrides: &[(Action, KeyCode)]) -> anyhow::Result<Self> {
+        let mut keys = self.actions.iter().map(|(key, action)| (*action, *key)).collect::<HashMap<_, _>>();
+        keys.extend(overrides.iter().copied());
+        Self::from_keys(keys)
+    }
+
+    /// Bindings from the key of every action, failing if two share a key
+    fn from_keys(keys: HashMap<Action, KeyCode>) -> anyhow::Result<Self> {
+        let mut actions = HashMap::new();
+        for (action, _, _) in Action::ALL {
+            let key = keys[&action];
+            if let Some(other) = actions.insert(key, action) {
+                anyhow::bail!("{:?} is bound to both {} and {}", key, other.name(), action.name());
+            }
+        }
+        Ok(Self { actions })
+    }
+
+    /// The action a key is bound to
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.actions.get(&key).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides_defaults() {
+        let bindings = KeyBindings::parse("# arrows to move\nmove_forward = \"ArrowUp\"\nlook_up = KeyI\n\n").unwrap();
+        assert_eq!(bindings.action(KeyCode::ArrowUp), Some(Action::MoveForward));
+        assert_eq!(bindings.action(KeyCode::KeyI), Some(Action::LookUp));
+        assert_eq!(bindings.action(KeyCode::KeyW), None);
+        assert_eq!(bindings.action(KeyCode::KeyJ), Some(Action::GrowGrid));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(KeyBindings::parse("fly = KeyF").unwrap_err().to_string().contains("unknown action"));
+        assert!(KeyBindings::parse("\nspin = Hyper").unwrap_err().to_string().starts_with("line 2"));
+        assert!(KeyBindings::parse("spin").is_err());
+        // J already grows the grid
+        assert!(KeyBindings::parse("spin = KeyJ").unwrap_err().to_string().contains("grow_grid"));
+        assert!(KeyBindings::parse("spin = KeyJ\ngrow_grid = Digit1").is_ok());
+    }
+
+    #[test]
+    fn test_default_file_matches_defaults() {
+        let text = tokio_test::block_on(super::super::world::resources::load_string(&KEY_BINDINGS_FILE)).unwrap();
+        assert_eq!(KeyBindings::parse(&text).unwrap(), KeyBindings::default());
+        for (_, name, _) in Action::ALL {
+            assert!(text.contains(name), "{} is missing from the file", name);
+        }
+    }
+}
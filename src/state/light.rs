@@ -0,0 +1,265 @@
+//! Point lights and the sun that shade the models
+use super::{camera::OPENGL_TO_WGPU_MATRIX, up_axis::UpAxis, world::environment::{Fog, FogMode}};
+
+/// How many lights the shader reads, extra lights are ignored
+pub const MAX_LIGHTS: usize = 4;
+
+/// Light that isn't coming from any light, so the sides facing away aren't pitch black
+pub const DEFAULT_AMBIENT: f32 = 0.2;
+
+/// A light shining in every direction from a point
+#[derive(Clone, Debug, PartialEq)]
+pub struct Light {
+    pub position: cgmath::Point3<f32>,
+    /// linear rgb color
+    pub color: [f32; 3],
+    /// brightness multiplier, 1 is full color right next to the light
+    pub intensity: f32,
+    /// distance at which the light has faded out completely
+    pub range: f32,
+}
+
+impl Light {
+    /// A white light
+    pub fn new(position: cgmath::Point3<f32>, range: f32) -> Self {
+        Self { position, color: [1.0, 1.0, 1.0], intensity: 1.0, range }
+    }
+
+    fn to_raw(&self) -> LightRaw {
+        LightRaw {
+            position: [self.position.x, self.position.y, self.position.z, self.range],
+            color: [self.color[0] * self.intensity, self.color[1] * self.intensity, self.color[2] * self.intensity, 0.0],
+        }
+    }
+}
+
+/// Light from far away shining in one direction, like the sun, the only light that casts shadows
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectionalLight {
+    /// direction the light travels in
+    pub direction: cgmath::Vector3<f32>,
+    /// linear rgb color
+    pub color: [f32; 3],
+    /// brightness multiplier
+    pub intensity: f32,
+    /// center of the area that gets shadows
+    pub shadow_center: cgmath::Point3<f32>,
+    /// distance from the center that still gets shadows
+    pub shadow_radius: f32,
+}
+
+impl DirectionalLight {
+    /// A white light shining down the direction, casting shadows around the origin
+    pub fn new(direction: cgmath::Vector3<f32>, shadow_radius: f32) -> Self {
+        Self {
+            direction,
+            color: [1.0, 1.0, 1.0],
+            intensity: 0.6,
+            shadow_center: (0.0, 0.0, 0.0).into(),
+            shadow_radius,
+        }
+    }
+
+    /// Matrix from world space to the shadow map's clip space
+    ///
+    /// Looks along the light at the shadow area with an orthographic box just big enough to hold it
+    pub fn view_projection(&self) -> cgmath::Matrix4<f32> {
+        use cgmath::InnerSpace;
+
+        let direction = self.direction.normalize();
+        let radius = self.shadow_radius.max(f32::EPSILON);
+        // any up works as long as it isn't the direction itself
+        let up = if direction.y.abs() > 0.99 { cgmath::Vector3::unit_z() } else { cgmath::Vector3::unit_y() };
+        let eye = self.shadow_center - direction * radius * 2.0;
+        let view = cgmath::Matrix4::look_at_rh(eye, self.shadow_center, up);
+        let proj = cgmath::ortho(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+}
+
+/// One light the way the shader reads it
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    /// xyz is the position, w the range
+    position: [f32; 4],
+    /// rgb is the color times the intensity, w is unused
+    color: [f32; 4],
+}
+
+// We need this for Rust to store our data correctly for the shaders
+#[repr(C)]
+// This is so we can store this in a buffer
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+/// Every light in the world, sent to the shader in one buffer
+pub struct LightUniform {
+    lights: [LightRaw; MAX_LIGHTS],
+    /// how many of the lights are used
+    count: u32,
+    /// brightness of the light everything gets
+    ambient: f32,
+    /// 1 to color surfaces by how many lights reach them instead of shading them
+    light_counts: u32,
+    // uniforms are padded out to 16 bytes
+    _padding: f32,
+    /// world space to shadow map clip space
+    sun_view_proj: [[f32; 4]; 4],
+    /// direction the sun shines in, w is 1 if shadows are on
+    sun_direction: [f32; 4],
+    /// color times intensity, w is unused
+    sun_color: [f32; 4],
+    /// x scales the light from the environment map, 0 when there isn't one, y is the last specular
+    /// level and z is 1 if the world is Z-up, the environment always is Y-up
+    environment: [f32; 4],
+    /// color things fade into far away, w is the exponential fog's density
+    fog_color: [f32; 4],
+    /// where the fog starts and where linear fog ends, z is 0 without fog, 1 for linear and 2 for exponential
+    fog_range: [f32; 4],
+}
+
+impl LightUniform {
+    /// Pack the first `MAX_LIGHTS` lights and the sun for the shader
+    ///
+    /// Args:
+    ///     lights: point lights
+    ///     ambient: brightness everything gets
+    ///     sun: light casting shadows, None leaves it dark
+    ///     shadows: whether the shadow map was drawn this frame
+    pub fn new(lights: &[Light], ambient: f32, sun: Option<&DirectionalLight>, shadows: bool) -> Self {
+        use cgmath::{InnerSpace, SquareMatrix};
+
+        let mut raw = [LightRaw::default(); MAX_LIGHTS];
+        for (raw, light) in raw.iter_mut().zip(lights) {
+            *raw = light.to_raw();
+        }
+        let (sun_view_proj, sun_direction, sun_color) = match sun {
+            Some(sun) => {
+                let direction = sun.direction.normalize();
+                (
+                    sun.view_projection().into(),
+                    [direction.x, direction.y, direction.z, if shadows { 1.0 } else { 0.0 }],
+                    [sun.color[0] * sun.intensity, sun.color[1] * sun.intensity, sun.color[2] * sun.intensity, 0.0],
+                )
+            }
+            None => (cgmath::Matrix4::identity().into(), [0.0, -1.0, 0.0, 0.0], [0.0; 4]),
+        };
+        Self {
+            lights: raw,
+            count: lights.len().min(MAX_LIGHTS) as u32,
+            ambient,
+            light_counts: 0,
+            _padding: 0.0,
+            sun_view_proj,
+            sun_direction,
+            sun_color,
+            environment: [0.0; 4],
+            fog_color: [0.0; 4],
+            fog_range: [0.0; 4],
+        }
+    }
+
+    /// Light surfaces with an environment map, see `ibl`
+    ///
+    /// Args:
+    ///     intensity: multiplier for its light, 0 goes back to the flat ambient light
+    ///     up_axis: which way is up in the world
+    pub fn with_environment(self, intensity: f32, up_axis: UpAxis) -> Self {
+        let last_level = (super::ibl::SPECULAR_LEVELS - 1) as f32;
+        Self { environment: [intensity.max(0.0), last_level, (up_axis == UpAxis::Z) as u32 as f32, 0.0], ..self }
+    }
+
+    /// Fade far away surfaces into fog
+    pub fn with_fog(self, fog: &Fog) -> Self {
+        let [r, g, b] = fog.color;
+        let mode = match fog.mode {
+            FogMode::Off => 0.0,
+            FogMode::Linear => 1.0,
+            FogMode::Exponential => 2.0,
+        };
+        Self { fog_color: [r, g, b, fog.density.max(0.0)], fog_range: [fog.start, fog.end.max(fog.start), mode, 0.0], ..self }
+    }
+
+    /// The same lights with the sun's shadows left out, for views drawn without a shadow map
+    pub fn without_shadows(self) -> Self {
+        let [x, y, z, _] = self.sun_direction;
+        Self { sun_direction: [x, y, z, 0.0], ..self }
+    }
+
+    /// Color surfaces by how many lights reach them, to see where lights pile up
+    pub fn with_light_counts(self, shown: bool) -> Self {
+        Self { light_counts: shown as u32, ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_uniform() {
+        let mut light = Light::new((1.0, 2.0, 3.0).into(), 10.0);
+        light.color = [1.0, 0.5, 0.0];
+        light.intensity = 2.0;
+        let uniform = LightUniform::new(&vec![light; MAX_LIGHTS + 2], DEFAULT_AMBIENT, None, false);
+
+        assert_eq!(uniform.count, MAX_LIGHTS as u32);
+        assert_eq!(uniform.lights[0].position, [1.0, 2.0, 3.0, 10.0]);
+        assert_eq!(uniform.lights[0].color, [2.0, 1.0, 0.0, 0.0]);
+        // the shader expects the struct laid out in 16 byte rows
+        assert_eq!(std::mem::size_of::<LightUniform>() % 16, 0);
+    }
+
+    #[test]
+    fn test_unused_lights_are_zero() {
+        let uniform = LightUniform::new(&[Light::new((0.0, 0.0, 0.0).into(), 5.0)], 0.0, None, false);
+        assert_eq!(uniform.count, 1);
+        assert_eq!(uniform.lights[1], LightRaw::default());
+        assert_eq!(uniform.sun_color, [0.0; 4]);
+        // without an environment map the flat ambient light is used
+        assert_eq!(uniform.environment[0], 0.0);
+        let uniform = uniform.with_environment(2.0, UpAxis::Z);
+        assert_eq!(uniform.environment[0], 2.0);
+        assert_eq!(uniform.environment[2], 1.0);
+    }
+
+    #[test]
+    fn test_fog() {
+        let uniform = LightUniform::new(&[], 0.0, None, false);
+        assert_eq!(uniform.with_fog(&Fog::default()).fog_range[2], 0.0);
+
+        // linear fog can't end before it starts, and negative density is clear air
+        let fog = Fog { mode: FogMode::Linear, color: [1.0, 0.5, 0.25], density: -1.0, start: 10.0, end: 5.0 };
+        let uniform = uniform.with_fog(&fog);
+        assert_eq!(uniform.fog_color, [1.0, 0.5, 0.25, 0.0]);
+        assert_eq!(uniform.fog_range, [10.0, 10.0, 1.0, 0.0]);
+        let uniform = uniform.with_fog(&Fog { mode: FogMode::Exponential, ..fog });
+        assert_eq!(uniform.fog_range[2], 2.0);
+    }
+
+    #[test]
+    fn test_sun_view_projection() {
+        use cgmath::{Transform, InnerSpace};
+
+        let sun = DirectionalLight::new(cgmath::Vector3::new(-1.0, -2.0, -1.0), 10.0);
+        let matrix = sun.view_projection();
+
+        // the shadow center lands in the middle of the map, halfway into the depth range
+        let center = matrix.transform_point(sun.shadow_center);
+        assert!(center.x.abs() < 1e-5 && center.y.abs() < 1e-5);
+        assert!((center.z - 0.5).abs() < 1e-5);
+
+        // points along the light are only further away, points across it move over the map
+        let further = matrix.transform_point(sun.shadow_center + sun.direction.normalize() * 5.0);
+        assert!(further.x.abs() < 1e-4 && further.y.abs() < 1e-4 && further.z > center.z);
+
+        // the edge of the radius is the edge of the map
+        let across = sun.direction.normalize().cross(cgmath::Vector3::unit_y()).normalize();
+        let edge = matrix.transform_point(sun.shadow_center + across * 10.0);
+        assert!(((edge.x * edge.x + edge.y * edge.y).sqrt() - 1.0).abs() < 1e-4);
+
+        let uniform = LightUniform::new(&[], 0.0, Some(&sun), true);
+        assert_eq!(uniform.sun_direction[3], 1.0);
+        assert!((uniform.sun_color[0] - sun.intensity).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,341 @@
+//! Volumetric light shafts, the sun's light showing through thick air where the shadow map says it reaches
+//!
+//! See `light_shafts.wgsl`. Rays are marched at half the scene's size, then added over the resolved
+//! scene before it is upscaled or tone mapped.
+use wgpu::util::DeviceExt;
+
+use super::{bind_groups::BindGroupCache, camera::Camera, light::DirectionalLight, world::environment::Environment};
+
+/// What both passes need to know, laid out like `ShaftParams` in the shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShaftParams {
+    inv_view_proj: [[f32; 4]; 4],
+    sun_view_proj: [[f32; 4]; 4],
+    /// w is how far rays march at most
+    camera_position: [f32; 4],
+    /// w is the density
+    sun_direction: [f32; 4],
+    /// w is how much light scatters onward
+    sun_color: [f32; 4],
+    /// the scene's size, then the half size the rays are marched at
+    size: [f32; 4],
+}
+
+impl ShaftParams {
+    fn new(camera: &Camera, sun: &DirectionalLight, environment: &Environment, (width, height): (u32, u32)) -> Self {
+        use cgmath::{InnerSpace, SquareMatrix};
+
+        let (march_width, march_height) = march_size(width, height);
+        let inv_view_proj = camera.build_view_projection_matrix().invert().unwrap_or_else(cgmath::Matrix4::identity);
+        let direction = sun.direction.normalize();
+        Self {
+            inv_view_proj: inv_view_proj.into(),
+            sun_view_proj: sun.view_projection().into(),
+            // past the shadow area every step is lit, so the shafts would only wash out
+            camera_position: [camera.eye.x, camera.eye.y, camera.eye.z, sun.shadow_radius * 2.0],
+            sun_direction: [direction.x, direction.y, direction.z, environment.light_shaft_density.max(0.0)],
+            // the phase function blows up at exactly -1 or 1
+            sun_color: [
+                sun.color[0] * sun.intensity,
+                sun.color[1] * sun.intensity,
+                sun.color[2] * sun.intensity,
+                environment.light_shaft_scattering.clamp(-0.95, 0.95),
+            ],
+            size: [width as f32, height as f32, march_width as f32, march_height as f32],
+        }
+    }
+}
+
+/// Half a size, rounded up so odd sizes keep their last pixel
+fn march_size(width: u32, height: u32) -> (u32, u32) {
+    (width.div_ceil(2).max(1), height.div_ceil(2).max(1))
+}
+
+/// The half size texture the rays are marched into, made again when the scene's size changes
+struct MarchTarget {
+    width: u32,
+    height: u32,
+    view: wgpu::TextureView,
+    composite_group: wgpu::BindGroup,
+}
+
+/// The march and composite passes
+pub struct LightShafts {
+    march_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    march_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    target: Option<MarchTarget>,
+}
+
+impl LightShafts {
+    /// Set up the passes
+    ///
+    /// Args:
+    ///     scene_format: format the scene is drawn in, the shafts are added to that
+    ///     depth_sample_count: samples per pixel of the scene's depth buffer
+    pub fn new(device: &wgpu::Device, scene_format: wgpu::TextureFormat, depth_sample_count: u32) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Shaft Params Buffer"),
+            contents: bytemuck::cast_slice(&[<ShaftParams as bytemuck::Zeroable>::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let params_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let depth_entry = |binding, multisampled| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Depth,
+            },
+            count: None,
+        };
+        let march_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                params_entry,
+                depth_entry(1, depth_sample_count > 1),
+                depth_entry(2, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("light_shaft_march_bind_group_layout"),
+        });
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                params_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("light_shaft_composite_bind_group_layout"),
+        });
+
+        // with MSAA the depth buffer has samples, the first one is enough for where the ray stops
+        let mut source = include_str!("../light_shafts.wgsl").to_string();
+        if depth_sample_count > 1 {
+            source = source.replace("var t_depth: texture_depth_2d;", "var t_depth: texture_depth_multisampled_2d;");
+        }
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("light_shafts.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = |label, layout: &wgpu::BindGroupLayout, entry_point, target| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(target)],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let march_pipeline = pipeline("Light Shaft March Pipeline", &march_layout, "fs_march", wgpu::ColorTargetState {
+            format: super::tonemap::HDR_FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        });
+        // the shafts add light to the scene and leave its alpha alone
+        let composite_pipeline = pipeline("Light Shaft Composite Pipeline", &composite_layout, "fs_composite", wgpu::ColorTargetState {
+            format: scene_format,
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            }),
+            write_mask: wgpu::ColorWrites::COLOR,
+        });
+
+        Self { march_pipeline, composite_pipeline, march_layout, composite_layout, params_buffer, sampler, target: None }
+    }
+
+    /// Get ready to draw over a scene of some size, seen from the camera
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera, sun: &DirectionalLight, environment: &Environment, size: (u32, u32)) {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[ShaftParams::new(camera, sun, environment, size)]));
+
+        let (width, height) = march_size(size.0, size.1);
+        if self.target.as_ref().is_some_and(|target| target.width == width && target.height == height) {
+            return;
+        }
+        // HDR even when the scene isn't, the shafts are faint and banding shows in 8 bits
+        let view = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("light_shafts"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: super::tonemap::HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }).create_view(&wgpu::TextureViewDescriptor::default());
+        let composite_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.composite_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("light_shaft_composite_bind_group"),
+        });
+        self.target = Some(MarchTarget { width, height, view, composite_group });
+    }
+
+    /// Record marching the rays and adding them over the scene, after `prepare`
+    ///
+    /// Args:
+    ///     bind_groups: where the group binding the depth and shadow map is kept between frames
+    ///     depth_view: the depth alone of the scene's depth buffer, the size `prepare` was given
+    ///     shadow_map: the sun's shadow map and its comparison sampler, drawn this frame
+    ///     output: the resolved scene the shafts are added to
+    pub fn draw(&self, bind_groups: &BindGroupCache, encoder: &mut wgpu::CommandEncoder, depth_view: &wgpu::TextureView, shadow_map: (&wgpu::TextureView, &wgpu::Sampler), output: &wgpu::TextureView) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        let march_group = bind_groups.get(&wgpu::BindGroupDescriptor {
+            layout: &self.march_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(shadow_map.0),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(shadow_map.1),
+                },
+            ],
+            label: Some("light_shaft_march_bind_group"),
+        });
+
+        let pass = |encoder: &mut wgpu::CommandEncoder, label, view, load, pipeline, bind_group| {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        };
+        pass(encoder, "Light Shaft March Pass", &target.view, wgpu::LoadOp::Clear(wgpu::Color::BLACK), &self.march_pipeline, march_group.as_ref());
+        pass(encoder, "Light Shaft Composite Pass", output, wgpu::LoadOp::Load, &self.composite_pipeline, &target.composite_group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shaft_params() {
+        use cgmath::{InnerSpace, Transform};
+
+        let camera = Camera {
+            eye: (0.0, 2.0, 6.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 16.0 / 9.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: crate::state::camera::Projection::Perspective,
+        };
+        let sun = DirectionalLight::new(cgmath::Vector3::new(0.0, -2.0, 0.0), 10.0);
+        let environment = Environment { light_shaft_density: -1.0, light_shaft_scattering: 1.0, ..Default::default() };
+        let params = ShaftParams::new(&camera, &sun, &environment, (1281, 720));
+
+        // rays march at half size, keeping the odd pixel
+        assert_eq!(params.size, [1281.0, 720.0, 641.0, 360.0]);
+        // negative density is clear air, and the phase function never gets a pole
+        assert_eq!(params.sun_direction, [0.0, -1.0, 0.0, 0.0]);
+        assert_eq!(params.sun_color[3], 0.95);
+
+        // unprojecting the camera's target lands back on it
+        let inverse = cgmath::Matrix4::from(params.inv_view_proj);
+        let target = inverse.transform_point(camera.build_view_projection_matrix().transform_point(camera.target));
+        assert!((target - camera.target).magnitude() < 1e-3);
+
+        // the uniform is laid out in 16 byte rows like the shader's
+        assert_eq!(std::mem::size_of::<ShaftParams>() % 16, 0);
+    }
+}
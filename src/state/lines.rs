@@ -0,0 +1,218 @@
+//! Colored lines drawn on top of the world, for tools like the measure tool
+use wgpu::util::DeviceExt;
+
+use super::{palette::Color, world::model::Vertex};
+
+/// One end of a line
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: Color,
+}
+
+impl Vertex for LineVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0, // position field
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1, // color field
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+            ],
+        }
+    }
+}
+
+/// Lines to draw this frame, each pair of vertices is one line
+#[derive(Clone, Debug, Default)]
+pub struct LineList {
+    pub vertices: Vec<LineVertex>,
+}
+
+impl LineList {
+    pub fn line(&mut self, start: cgmath::Point3<f32>, end: cgmath::Point3<f32>, color: Color) {
+        self.vertices.push(LineVertex { position: start.into(), color });
+        self.vertices.push(LineVertex { position: end.into(), color });
+    }
+
+    /// A small three axis cross marking a point
+    pub fn cross(&mut self, center: cgmath::Point3<f32>, size: f32, color: Color) {
+        for axis in [cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y(), cgmath::Vector3::unit_z()] {
+            self.line(center - axis * size, center + axis * size, color);
+        }
+    }
+
+    /// The twelve edges of a box, corners ordered like `Instance::corners`
+    pub fn cuboid(&mut self, corners: &[cgmath::Point3<f32>; 8], color: Color) {
+        for corner in 0..8 {
+            // every edge joins two corners differing in one bit, drawn once from the smaller one
+            for bit in [1, 2, 4] {
+                if corner & bit == 0 {
+                    self.line(corners[corner], corners[corner | bit], color);
+                }
+            }
+        }
+    }
+
+    /// Three circles around a point, one in each axis plane, outlining a sphere
+    pub fn sphere(&mut self, center: cgmath::Point3<f32>, radius: f32, color: Color) {
+        const SEGMENTS: usize = 32;
+        let axes = [cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y(), cgmath::Vector3::unit_z()];
+        for plane in 0..3 {
+            let (u, v) = (axes[plane], axes[(plane + 1) % 3]);
+            let point = |segment: usize| {
+                let angle = std::f32::consts::TAU * segment as f32 / SEGMENTS as f32;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for segment in 0..SEGMENTS {
+                self.line(point(segment), point(segment + 1), color);
+            }
+        }
+    }
+}
+
+/// Draws line lists with the camera, always on top so tools stay visible through the models
+pub struct LineRenderer {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    /// how many vertices the buffer has room for
+    capacity: usize,
+    /// how many vertices were written last
+    count: u32,
+}
+
+impl LineRenderer {
+    /// Make the line pipeline
+    ///
+    /// Args:
+    ///     camera_bind_group_layout: layout of the camera the lines are seen through
+    ///     format: format of the surface the lines are drawn to
+    ///     depth_format: format of the depth buffer in the pass, the lines ignore it, None for passes without one
+    ///     sample_count: samples per pixel of the pass
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../lines.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Line Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            // lines share the main pass, so they need its depth format even though they never test against it
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer: create_line_buffer(device, 0),
+            capacity: 0,
+            count: 0,
+        }
+    }
+
+    /// Replace the lines drawn, growing the buffer if they don't fit
+    pub fn set_lines(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lines: &LineList) {
+        if lines.vertices.len() > self.capacity {
+            self.capacity = lines.vertices.len().next_power_of_two();
+            self.vertex_buffer = create_line_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&lines.vertices));
+        self.count = lines.vertices.len() as u32;
+    }
+
+    /// Draw the lines, the pass must have been started with the depth format given to `new`
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        if self.count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.count, 0..1);
+    }
+}
+
+// an empty vertex buffer with room for some line vertices
+fn create_line_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Line Buffer"),
+        contents: bytemuck::cast_slice(&vec![LineVertex { position: [0.0; 3], color: [0.0; 3] }; capacity.max(1)]),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cross() {
+        let mut lines = LineList::default();
+        lines.cross((1.0, 2.0, 3.0).into(), 0.5, [1.0, 0.0, 0.0]);
+        assert_eq!(lines.vertices.len(), 6);
+        assert_eq!(lines.vertices[0].position, [0.5, 2.0, 3.0]);
+        assert_eq!(lines.vertices[5].position, [1.0, 2.0, 3.5]);
+    }
+
+    #[test]
+    fn test_sphere() {
+        use cgmath::MetricSpace;
+
+        let mut lines = LineList::default();
+        lines.sphere((1.0, 2.0, 3.0).into(), 2.0, [1.0, 0.0, 0.0]);
+        // closed circles, every point on the sphere
+        assert_eq!(lines.vertices.len(), 3 * 32 * 2);
+        let (first, last) = (cgmath::Point3::from(lines.vertices[0].position), cgmath::Point3::from(lines.vertices[63].position));
+        assert!(first.distance(last) < 1e-4);
+        assert!(lines.vertices.iter().all(|vertex| (cgmath::Point3::from(vertex.position).distance((1.0, 2.0, 3.0).into()) - 2.0).abs() < 1e-4));
+    }
+}
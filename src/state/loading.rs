@@ -0,0 +1,229 @@
+//! Loading the models in `resources.txt` on the task threads, with a progress bar drawn until they're in
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::tasks::{self, Task};
+
+use super::{up_axis::UpAxis, world::{model::Model, resources}};
+
+/// How long to wait between frames of the loading screen
+pub const LOADING_FRAME: std::time::Duration = std::time::Duration::from_millis(16);
+
+/// A model loading on the task threads
+pub type ModelTask = Task<anyhow::Result<Model>>;
+
+/// Load one model on the task threads, see `resources::load_model_entry` for what a line can hold
+pub fn spawn_model(
+    line: &str,
+    device: &Arc<wgpu::Device>,
+    queue: &Arc<wgpu::Queue>,
+    layout: &Arc<wgpu::BindGroupLayout>,
+    up_axis: UpAxis,
+) -> ModelTask {
+    let (line, device, queue, layout) = (line.to_string(), device.clone(), queue.clone(), layout.clone());
+    tasks::spawn(async move { resources::load_model_entry(&line, device, &queue, &layout, up_axis).await })
+}
+
+/// The models listed in `resources.txt` while they load, kept in the file's order
+pub struct ModelLoader {
+    /// the lines of `resources.txt`, one for each model
+    pub lines: Vec<String>,
+    tasks: Vec<Option<Task<anyhow::Result<Model>>>>,
+    models: Vec<Option<Model>>,
+}
+
+impl ModelLoader {
+    /// Read `resources.txt` and start loading every model in it
+    ///
+    /// Args:
+    ///     layout: the bind group layout materials are made with
+    ///     up_axis: which way is up in the world
+    pub async fn start(
+        device: &Arc<wgpu::Device>,
+        queue: &Arc<wgpu::Queue>,
+        layout: &Arc<wgpu::BindGroupLayout>,
+        up_axis: UpAxis,
+    ) -> anyhow::Result<Self> {
+        let lines = resources::load_string(&"resources.txt")
+            .await?
+            .split('\n')
+            .map(|line| line.trim_end().to_string())
+            .collect::<Vec<_>>();
+        let tasks = lines
+            .iter()
+            .map(|line| {
+                let (line, device, queue, layout) = (line.clone(), device.clone(), queue.clone(), layout.clone());
+                Some(tasks::spawn(async move { resources::load_model_entry(&line, device, &queue, &layout, up_axis).await }))
+            })
+            .collect::<Vec<_>>();
+        let models = tasks.iter().map(|_| None).collect();
+        Ok(Self { lines, tasks, models })
+    }
+
+    /// Take the models that finished loading, returns true once every one is in
+    pub fn poll(&mut self) -> anyhow::Result<bool> {
+        for (task, model) in self.tasks.iter_mut().zip(&mut self.models) {
+            if let Some(result) = task.as_mut().and_then(Task::try_take) {
+                *task = None;
+                *model = Some(result??);
+            }
+        }
+        Ok(self.models.iter().all(Option::is_some))
+    }
+
+    /// How much has loaded, from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        loaded_fraction(self.models.iter().filter(|model| model.is_some()).count(), self.models.len())
+    }
+
+    /// The loaded models in the order `resources.txt` lists them, once `poll` returned true
+    pub fn finish(self) -> Vec<Model> {
+        self.models.into_iter().flatten().collect()
+    }
+}
+
+/// Share of the models loaded, nothing to load counts as done
+fn loaded_fraction(loaded: usize, total: usize) -> f32 {
+    if total == 0 { 1.0 } else { loaded as f32 / total as f32 }
+}
+
+/// Keeps the layout of `LoadingParams` in loading.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LoadingParams {
+    size: [f32; 2],
+    fraction: f32,
+    _padding: f32,
+}
+
+/// Draws a progress bar straight into the window
+pub struct LoadingScreen {
+    pipeline: wgpu::RenderPipeline,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl LoadingScreen {
+    /// Set up the pass
+    ///
+    /// Args:
+    ///     format: format of the window
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Loading Params Buffer"),
+            contents: bytemuck::cast_slice(&[LoadingParams { size: [1.0, 1.0], fraction: 0.0, _padding: 0.0 }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("loading_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+            label: Some("loading_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../loading.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Loading Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Loading Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self { pipeline, params_buffer, bind_group }
+    }
+
+    /// Draw and show one frame of the bar, the surface must already be configured at this size
+    ///
+    /// Args:
+    ///     fraction: how much has loaded, from 0 to 1
+    pub fn draw(
+        &self,
+        surface: &wgpu::Surface,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: winit::dpi::PhysicalSize<u32>,
+        fraction: f32,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let output = surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let params = LoadingParams { size: [size.width as f32, size.height as f32], fraction, _padding: 0.0 };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Loading Encoder") });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Loading Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        super::submit::submit(device, queue, std::iter::once(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loaded_fraction() {
+        assert_eq!(loaded_fraction(0, 4), 0.0);
+        assert_eq!(loaded_fraction(3, 4), 0.75);
+        assert_eq!(loaded_fraction(0, 0), 1.0);
+    }
+}
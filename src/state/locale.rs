@@ -0,0 +1,87 @@
+//! Translated on screen text loaded from `res/locale/<language>.txt`
+use std::collections::HashMap;
+
+use super::world::resources::load_string;
+
+/// Language used when a string is missing from the chosen one
+pub const FALLBACK_LOCALE: &str = "en";
+
+/// Text shown to the user, looked up by key
+///
+/// Locale files have one `key = text` per line, blank lines and lines starting with `#` are skipped.
+#[derive(Default)]
+pub struct Strings {
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Strings {
+    /// Load a language, falling back to English for anything it doesn't have
+    pub async fn load(locale: &str) -> Self {
+        let fallback = Self::load_table(FALLBACK_LOCALE).await.unwrap_or_else(|e| {
+            log::warn!("Couldn't load the {} strings: {}", FALLBACK_LOCALE, e);
+            HashMap::new()
+        });
+        let strings = if locale == FALLBACK_LOCALE {
+            HashMap::new()
+        } else {
+            Self::load_table(locale).await.unwrap_or_else(|e| {
+                log::warn!("Couldn't load the {} strings, using {}: {}", locale, FALLBACK_LOCALE, e);
+                HashMap::new()
+            })
+        };
+        Self { strings, fallback }
+    }
+
+    async fn load_table(locale: &str) -> anyhow::Result<HashMap<String, String>> {
+        let text = load_string(&format!("locale/{}.txt", locale)).await?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Read the `key = text` lines of a locale file
+    pub fn parse(text: &str) -> HashMap<String, String> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect()
+    }
+
+    /// The text for a key, or the key itself if no language has it
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map_or(key, String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let table = Strings::parse("# comment\n\nwindow.title = 3D Playground\nbroken line\n");
+        assert_eq!(table.len(), 1);
+        assert_eq!(table["window.title"], "3D Playground");
+    }
+
+    #[test]
+    fn test_get_falls_back() {
+        let strings = Strings {
+            strings: Strings::parse("greeting = Hola"),
+            fallback: Strings::parse("greeting = Hello\nfarewell = Goodbye"),
+        };
+        assert_eq!(strings.get("greeting"), "Hola");
+        assert_eq!(strings.get("farewell"), "Goodbye");
+        assert_eq!(strings.get("Plain caption"), "Plain caption");
+    }
+
+    #[test]
+    fn test_missing_locale_uses_english() {
+        let strings = tokio_test::block_on(Strings::load("does-not-exist"));
+        assert_eq!(strings.get("window.title"), "Rust 3D Playground");
+    }
+}
@@ -14,6 +14,14 @@ impl MouseGrabber {
         }
     }
 
+    /// Let go of the mouse so it can be used on screen
+    pub fn release(&mut self, window: &Window) {
+        if self.mouse_locked {
+            self.mouse_locked = false;
+            window.set_cursor_visible(true);
+        }
+    }
+
     /// Call this for button presses so we can lock and unlock the mouse
     pub fn process_events(&mut self, event: &WindowEvent, window: &Window) -> bool {
         match event {
@@ -0,0 +1,280 @@
+//! Just enough of MQTT 3.1.1 to subscribe to topics on a broker and hear what is published to them
+//!
+//! Everything is at most once (QoS 0) and without a login, which is what sensor dashboards on a local
+//! network usually run with. The connection lives on a thread of its own, pings the broker to keep
+//! alive, and connects again a few seconds after it drops.
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// Seconds the broker waits without hearing from us before dropping the connection
+const KEEP_ALIVE_SECONDS: u16 = 60;
+
+/// Wait before connecting again after the connection failed or dropped
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Largest packet accepted, bigger ones are taken as a broken connection
+const MAX_PACKET_BYTES: usize = 1 << 20;
+
+/// A message published to one of the topics
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Connect to a broker and subscribe to topics on a thread of its own
+///
+/// Messages arrive on the returned channel. The thread ends once the receiver is dropped and the next
+/// message comes in.
+pub fn subscribe(address: String, client_id: String, topics: Vec<String>) -> mpsc::Receiver<Message> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        match run(&address, &client_id, &topics, &sender) {
+            Ok(()) => return,
+            Err(e) => log::warn!("MQTT connection to {} failed: {}", address, e),
+        }
+        std::thread::sleep(RETRY_DELAY);
+    });
+    receiver
+}
+
+/// Stay connected until the connection fails, Ok once nobody listens for messages anymore
+fn run(address: &str, client_id: &str, topics: &[String], sender: &mpsc::Sender<Message>) -> anyhow::Result<()> {
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(&connect_packet(client_id, KEEP_ALIVE_SECONDS))?;
+    match read_packet(&mut stream)? {
+        (0x20, body) if body.len() == 2 && body[1] == 0 => {}
+        (0x20, body) => anyhow::bail!("broker refused the connection with code {:?}", body.get(1)),
+        (kind, _) => anyhow::bail!("expected a CONNACK, got packet type {}", kind >> 4),
+    }
+    stream.write_all(&subscribe_packet(1, topics))?;
+    log::info!("Subscribed to {} topics on {}", topics.len(), address);
+
+    // reads time out in time to ping before the broker gives up on us
+    let ping_every = Duration::from_secs(KEEP_ALIVE_SECONDS as u64 / 2);
+    stream.set_read_timeout(Some(ping_every))?;
+    let mut last_sent = Instant::now();
+    loop {
+        if last_sent.elapsed() >= ping_every {
+            stream.write_all(&[0xC0, 0x00])?;
+            last_sent = Instant::now();
+        }
+        let Some((kind, body)) = read_next_packet(&mut stream)? else {
+            continue;
+        };
+        if kind >> 4 == 3 {
+            let message = parse_publish(kind, &body)?;
+            if sender.send(message).is_err() {
+                return Ok(());
+            }
+        }
+        // SUBACK and PINGRESP need nothing done
+    }
+}
+
+/// Read the next packet, None if the read timed out before one started
+///
+/// A timeout once a packet has started leaves the rest of it unread, so that is an error and the connection
+/// is made again rather than reading on from the middle of the packet.
+fn read_next_packet(stream: &mut impl Read) -> anyhow::Result<Option<(u8, Vec<u8>)>> {
+    let mut kind = [0];
+    match stream.read_exact(&mut kind) {
+        Ok(()) => Ok(Some((kind[0], read_packet_body(stream)?))),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read one packet, its first byte and what follows the length
+fn read_packet(stream: &mut impl Read) -> anyhow::Result<(u8, Vec<u8>)> {
+    let mut kind = [0];
+    stream.read_exact(&mut kind)?;
+    Ok((kind[0], read_packet_body(stream)?))
+}
+
+/// Read the length of a packet whose first byte was read and the body after it
+fn read_packet_body(stream: &mut impl Read) -> anyhow::Result<Vec<u8>> {
+    // the length takes 7 bits from each byte for as long as the top bit is set
+    let mut length = 0;
+    for shift in (0..4).map(|index| index * 7) {
+        let mut byte = [0];
+        stream.read_exact(&mut byte)?;
+        length |= ((byte[0] & 0x7F) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            if length > MAX_PACKET_BYTES {
+                anyhow::bail!("packet of {} bytes is too big", length);
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body)?;
+            return Ok(body);
+        }
+    }
+    anyhow::bail!("packet length runs past four bytes")
+}
+
+/// A packet with its first byte and length in front of the body
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        packet.push(if length > 0 { byte | 0x80 } else { byte });
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// A string as MQTT sends them, its length in two bytes then UTF-8
+fn push_string(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+/// Ask for a clean session without a login
+fn connect_packet(client_id: &str, keep_alive: u16) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_string(&mut body, "MQTT");
+    // protocol level 4 is 3.1.1, flag 2 asks for a clean session
+    body.extend_from_slice(&[4, 0x02]);
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+    push_string(&mut body, client_id);
+    packet(0x10, &body)
+}
+
+/// Subscribe to every topic at most once
+fn subscribe_packet(packet_id: u16, topics: &[String]) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    for topic in topics {
+        push_string(&mut body, topic);
+        body.push(0);
+    }
+    packet(0x82, &body)
+}
+
+/// The topic and payload of a PUBLISH
+fn parse_publish(kind: u8, body: &[u8]) -> anyhow::Result<Message> {
+    let length = match body {
+        [high, low, ..] => u16::from_be_bytes([*high, *low]) as usize,
+        _ => anyhow::bail!("PUBLISH too short"),
+    };
+    let topic = body.get(2..2 + length).ok_or_else(|| anyhow::anyhow!("PUBLISH topic runs past the packet"))?;
+    // messages sent at least once carry a packet id after the topic
+    let payload_start = 2 + length + if (kind >> 1) & 0x03 > 0 { 2 } else { 0 };
+    let payload = body.get(payload_start..).ok_or_else(|| anyhow::anyhow!("PUBLISH payload runs past the packet"))?;
+    Ok(Message { topic: String::from_utf8(topic.to_vec())?, payload: payload.to_vec() })
+}
+
+/// Whether a topic is one a subscription filter asks for, with `+` for one level and `#` for the rest
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(topic_level)) if level == topic_level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        assert_eq!(packet(0xC0, &[]), vec![0xC0, 0x00]);
+        // 200 bytes need a second length byte
+        let long = packet(0x30, &[0; 200]);
+        assert_eq!(&long[..3], &[0x30, 0xC8, 0x01]);
+        assert_eq!(read_packet(&mut long.as_slice()).unwrap(), (0x30, vec![0; 200]));
+
+        let connect = connect_packet("viewer", 60);
+        assert_eq!(&connect[..2], &[0x10, 18]);
+        assert_eq!(&connect[2..8], b"\x00\x04MQTT");
+        assert_eq!(&connect[8..12], &[4, 2, 0, 60]);
+
+        let subscribe = subscribe_packet(1, &["a/b".to_string()]);
+        assert_eq!(subscribe, vec![0x82, 8, 0, 1, 0, 3, b'a', b'/', b'b', 0]);
+    }
+
+    /// Hands out its bytes, then times out like a socket with a read timeout
+    struct Stalling<'a>(&'a [u8]);
+
+    impl Read for Stalling<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            let count = buf.len().min(self.0.len());
+            buf[..count].copy_from_slice(&self.0[..count]);
+            self.0 = &self.0[count..];
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn test_read_timeouts() {
+        // nothing started yet is only the connection being quiet
+        assert_eq!(read_next_packet(&mut Stalling(&[])).unwrap(), None);
+        assert_eq!(read_next_packet(&mut Stalling(&[0xD0, 0x00])).unwrap(), Some((0xD0, Vec::new())));
+        // stopping after the first byte or partway through the body can't be read on from
+        assert!(read_next_packet(&mut Stalling(&[0x30])).is_err());
+        assert!(read_next_packet(&mut Stalling(&[0x30, 0x05, 0x00])).is_err());
+    }
+
+    #[test]
+    fn test_parse_publish() {
+        let mut body = Vec::new();
+        push_string(&mut body, "sensors/temp");
+        body.extend_from_slice(b"21.5");
+        assert_eq!(parse_publish(0x30, &body).unwrap(), Message { topic: "sensors/temp".to_string(), payload: b"21.5".to_vec() });
+        // at least once, with a packet id before the payload
+        let mut body = Vec::new();
+        push_string(&mut body, "t");
+        body.extend_from_slice(&[0, 7, b'1']);
+        assert_eq!(parse_publish(0x32, &body).unwrap().payload, b"1".to_vec());
+        assert!(parse_publish(0x30, &[0, 9, b't']).is_err());
+    }
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("sensors/temp", "sensors/temp"));
+        assert!(topic_matches("sensors/+/temp", "sensors/room1/temp"));
+        assert!(topic_matches("sensors/#", "sensors/room1/temp"));
+        assert!(!topic_matches("sensors/+", "sensors/room1/temp"));
+        assert!(!topic_matches("sensors/temp", "sensors"));
+    }
+
+    #[test]
+    fn test_subscribe() {
+        // a broker that accepts, takes the subscription and publishes one message
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(read_packet(&mut stream).unwrap().0, 0x10);
+            stream.write_all(&[0x20, 2, 0, 0]).unwrap();
+            let (kind, body) = read_packet(&mut stream).unwrap();
+            assert_eq!((kind, &body[2..]), (0x82, &b"\x00\x06room/t\x00"[..]));
+            let mut publish = Vec::new();
+            push_string(&mut publish, "room/t");
+            publish.extend_from_slice(b"3");
+            stream.write_all(&packet(0x30, &publish)).unwrap();
+            // hold the connection open until the client is done
+            let _ = read_packet(&mut stream);
+        });
+        let messages = subscribe(address, "test".to_string(), vec!["room/t".to_string()]);
+        let message = messages.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(message, Message { topic: "room/t".to_string(), payload: b"3".to_vec() });
+    }
+}
@@ -0,0 +1,158 @@
+//! Colors used by debug drawing, highlights and on screen UI, with colorblind friendly presets
+use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+/// An RGB color with components from 0 to 1
+pub type Color = [f32; 3];
+
+/// The colors for every role in one theme
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Palette {
+    /// color the screen is cleared to
+    pub background: Color,
+    /// selected or hovered things
+    pub highlight: Color,
+    /// something is fine/enabled
+    pub good: Color,
+    /// something is wrong/disabled
+    pub bad: Color,
+    /// colors for the x, y and z axes
+    pub axes: [Color; 3],
+}
+
+impl Palette {
+    /// Background as a clear color for a render pass
+    pub fn clear_color(&self) -> wgpu::Color {
+        wgpu::Color {
+            r: self.background[0] as f64,
+            g: self.background[1] as f64,
+            b: self.background[2] as f64,
+            a: 1.0,
+        }
+    }
+}
+
+/// Built in palettes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PalettePreset {
+    Default,
+    /// no reliance on red vs green
+    Deuteranopia,
+    /// no reliance on red vs green, and no dark reds
+    Protanopia,
+    /// no reliance on blue vs green or yellow vs violet
+    Tritanopia,
+}
+
+// convert 0-255 colors so the presets can be written like the color charts they come from
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
+}
+
+const BACKGROUND: Color = [0.5, 0.1, 0.5];
+
+impl PalettePreset {
+    /// The preset after this one, wrapping around
+    pub fn next(self) -> Self {
+        match self {
+            PalettePreset::Default => PalettePreset::Deuteranopia,
+            PalettePreset::Deuteranopia => PalettePreset::Protanopia,
+            PalettePreset::Protanopia => PalettePreset::Tritanopia,
+            PalettePreset::Tritanopia => PalettePreset::Default,
+        }
+    }
+
+    /// The colors for this preset
+    ///
+    /// The colorblind presets use the Okabe-Ito colors, which stay distinct for the matching deficiency
+    pub fn palette(self) -> Palette {
+        match self {
+            PalettePreset::Default => Palette {
+                background: BACKGROUND,
+                highlight: rgb(255, 255, 0),
+                good: rgb(0, 200, 0),
+                bad: rgb(220, 0, 0),
+                axes: [rgb(220, 0, 0), rgb(0, 200, 0), rgb(0, 80, 255)],
+            },
+            PalettePreset::Deuteranopia => Palette {
+                background: BACKGROUND,
+                highlight: rgb(240, 228, 66),
+                good: rgb(0, 114, 178),
+                bad: rgb(230, 159, 0),
+                axes: [rgb(230, 159, 0), rgb(86, 180, 233), rgb(0, 114, 178)],
+            },
+            PalettePreset::Protanopia => Palette {
+                background: BACKGROUND,
+                highlight: rgb(240, 228, 66),
+                good: rgb(86, 180, 233),
+                bad: rgb(230, 159, 0),
+                axes: [rgb(230, 159, 0), rgb(240, 228, 66), rgb(0, 114, 178)],
+            },
+            PalettePreset::Tritanopia => Palette {
+                background: BACKGROUND,
+                highlight: rgb(255, 255, 255),
+                good: rgb(0, 158, 115),
+                bad: rgb(213, 94, 0),
+                axes: [rgb(213, 94, 0), rgb(0, 158, 115), rgb(204, 121, 167)],
+            },
+        }
+    }
+}
+
+/// Keeps track of the palette in use and switches it when P is pressed
+pub struct Theme {
+    pub preset: PalettePreset,
+}
+
+impl Theme {
+    /// The colors currently in use
+    pub fn palette(&self) -> Palette {
+        self.preset.palette()
+    }
+
+    /// Call this for key presses so the palette can be cycled
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyP),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.preset = self.preset.next();
+                log::info!("Using the {:?} palette", self.preset);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_cycles_through_every_preset() {
+        let mut preset = PalettePreset::Default;
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            seen.push(preset);
+            preset = preset.next();
+        }
+        assert_eq!(preset, PalettePreset::Default);
+        assert_eq!(seen, vec![PalettePreset::Default, PalettePreset::Deuteranopia, PalettePreset::Protanopia, PalettePreset::Tritanopia]);
+    }
+
+    #[test]
+    fn test_colorblind_presets_avoid_red_green() {
+        let default = PalettePreset::Default.palette();
+        for preset in [PalettePreset::Deuteranopia, PalettePreset::Protanopia, PalettePreset::Tritanopia] {
+            let palette = preset.palette();
+            assert_ne!(palette.good, default.good);
+            assert_ne!(palette.bad, default.bad);
+        }
+    }
+}
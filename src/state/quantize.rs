@@ -0,0 +1,178 @@
+//! Keyframes packed into 16 bit integers, what timelines keep their animated tracks in
+//!
+//! Every component of a track, and its times, is stored as a step between the smallest and largest
+//! value it takes, so a key costs 2 bytes per number instead of 4 and is never off by more than half a step.
+use super::easing::EasingCurve;
+
+/// Steps between the smallest and largest value of a component
+const STEPS: f32 = u16::MAX as f32;
+
+/// A key's time and numbers before they are packed
+pub type UnpackedKey = (f32, Vec<f32>);
+
+/// Values of one component, packed as steps from `min` to `max`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Range {
+    min: f32,
+    max: f32,
+}
+
+impl Range {
+    fn of(values: impl Iterator<Item = f32>) -> Self {
+        let (min, max) = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| (min.min(value), max.max(value)));
+        if min > max { Self { min: 0.0, max: 0.0 } } else { Self { min, max } }
+    }
+
+    fn pack(self, value: f32) -> u16 {
+        if self.max <= self.min {
+            return 0;
+        }
+        ((value - self.min) / (self.max - self.min) * STEPS).round().clamp(0.0, STEPS) as u16
+    }
+
+    /// The ends come back exactly, so packing what was unpacked gives the same steps
+    fn unpack(self, packed: u16) -> f32 {
+        let amount = packed as f32 / STEPS;
+        self.min * (1.0 - amount) + self.max * amount
+    }
+
+    /// Furthest an unpacked value can be from the one packed
+    #[cfg(test)]
+    fn max_error(self) -> f32 {
+        (self.max - self.min) / STEPS * 0.5
+    }
+}
+
+/// The keys of one track, each a time and the same number of values
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuantizedTrack {
+    /// numbers in each key
+    width: usize,
+    time_range: Range,
+    /// one range for each of the `width` numbers
+    ranges: Vec<Range>,
+    /// in order, no earlier than the key before
+    times: Vec<u16>,
+    /// `width` numbers for each key
+    values: Vec<u16>,
+}
+
+impl QuantizedTrack {
+    /// Pack keys given in time order
+    ///
+    /// Args:
+    ///     width: numbers in each key, every key must have this many
+    pub fn new(width: usize, keys: &[UnpackedKey]) -> Self {
+        debug_assert!(keys.iter().all(|(_, values)| values.len() == width));
+        let time_range = Range::of(keys.iter().map(|(time, _)| *time));
+        let ranges = (0..width).map(|component| Range::of(keys.iter().map(|(_, values)| values[component]))).collect::<Vec<_>>();
+        let mut values = Vec::with_capacity(keys.len() * width);
+        for (_, key) in keys {
+            values.extend(key.iter().zip(&ranges).map(|(value, range)| range.pack(*value)));
+        }
+        Self {
+            width,
+            time_range,
+            times: keys.iter().map(|(time, _)| time_range.pack(*time)).collect(),
+            values,
+            ranges,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Time of a key in seconds
+    pub fn time(&self, index: usize) -> f32 {
+        self.time_range.unpack(self.times[index])
+    }
+
+    /// The numbers of a key
+    pub fn values(&self, index: usize) -> Vec<f32> {
+        self.values[index * self.width..(index + 1) * self.width]
+            .iter()
+            .zip(&self.ranges)
+            .map(|(packed, range)| range.unpack(*packed))
+            .collect()
+    }
+
+    /// Time of the last key
+    pub fn duration(&self) -> f32 {
+        self.time_range.max
+    }
+
+    /// The numbers at a time, moving from one key to the next along a curve, None before the first key
+    pub fn sample(&self, time: f32, curve: EasingCurve) -> Option<Vec<f32>> {
+        let after = self.times.partition_point(|packed| self.time_range.unpack(*packed) <= time);
+        let before = after.checked_sub(1)?;
+        let values = self.values(before);
+        if after == self.len() {
+            return Some(values);
+        }
+        let (t0, t1) = (self.time(before), self.time(after));
+        if t1 <= t0 {
+            return Some(self.values(after));
+        }
+        let amount = curve.evaluate((time - t0) / (t1 - t0));
+        Some(values.iter().zip(self.values(after)).map(|(from, to)| from + (to - from) * amount).collect())
+    }
+
+    /// Furthest a key's time and any of its numbers can be from the ones it was packed from
+    #[cfg(test)]
+    pub fn max_error(&self) -> (f32, f32) {
+        (self.time_range.max_error(), self.ranges.iter().map(|range| range.max_error()).fold(0.0, f32::max))
+    }
+
+    /// Bytes held by the packed keys
+    pub fn bytes(&self) -> usize {
+        self.ranges.capacity() * std::mem::size_of::<Range>() + (self.times.capacity() + self.values.capacity()) * std::mem::size_of::<u16>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_error() {
+        let keys = (0..500)
+            .map(|i| {
+                let time = i as f32 * 0.137;
+                (time, vec![(time * 1.3).sin() * 40.0, time * time - 7.0, 3.5])
+            })
+            .collect::<Vec<_>>();
+        let track = QuantizedTrack::new(3, &keys);
+        let (time_error, value_error) = track.max_error();
+        assert!(time_error > 0.0 && value_error > 0.0);
+        for (index, (time, values)) in keys.iter().enumerate() {
+            assert!((track.time(index) - time).abs() <= time_error * 1.01);
+            for (unpacked, value) in track.values(index).iter().zip(values) {
+                assert!((unpacked - value).abs() <= value_error * 1.01, "{} vs {}", unpacked, value);
+            }
+        }
+        // the ends and components that never change are exact
+        assert_eq!(track.time(0), 0.0);
+        assert_eq!(track.duration(), keys[499].0);
+        assert_eq!(track.values(42)[2], 3.5);
+        // two bytes a number
+        assert!(track.bytes() < keys.len() * 4 * 2 + 64);
+    }
+
+    #[test]
+    fn test_packing_is_stable() {
+        let keys = vec![(0.0, vec![0.1]), (0.7, vec![-3.3]), (2.9, vec![12.6])];
+        let track = QuantizedTrack::new(1, &keys);
+        let unpacked = (0..track.len()).map(|index| (track.time(index), track.values(index))).collect::<Vec<_>>();
+        assert_eq!(QuantizedTrack::new(1, &unpacked), track);
+    }
+
+    #[test]
+    fn test_sample() {
+        let track = QuantizedTrack::new(1, &[(1.0, vec![0.0]), (3.0, vec![4.0])]);
+        assert_eq!(track.sample(0.5, EasingCurve::LINEAR), None);
+        assert_eq!(track.sample(1.0, EasingCurve::LINEAR), Some(vec![0.0]));
+        assert!((track.sample(2.0, EasingCurve::LINEAR).unwrap()[0] - 2.0).abs() < 1e-3);
+        assert_eq!(track.sample(9.0, EasingCurve::LINEAR), Some(vec![4.0]));
+    }
+}
@@ -0,0 +1,165 @@
+//! Copying drawn frames back from the GPU without waiting on it, for recording and the remote viewer
+//!
+//! Every frame is copied into one of a few buffers and mapped once the copy is submitted. Frames that
+//! finished mapping are handed back in the order they were drawn. When every buffer is still busy the
+//! frame is dropped rather than stalling the render loop.
+use std::{collections::VecDeque, sync::mpsc};
+
+use super::screenshot::{bgra_to_rgba, is_bgra, is_savable, padded_row_bytes, unpad_rows};
+
+/// Frames copied out at the same time, more rides out slow frames at the cost of memory
+const READBACK_SLOTS: usize = 3;
+
+/// Where a readback buffer is in taking a frame from the GPU
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SlotState {
+    Free,
+    /// a frame was copied in, it gets mapped once the copy is submitted
+    Copied,
+    Mapping,
+    /// ready to read, unless the mapping failed
+    Mapped { is_ok: bool },
+}
+
+/// A slot whose mapping finished and how it went
+type MapResult = (usize, Result<(), wgpu::BufferAsyncError>);
+
+/// One buffer a frame is copied into
+struct Slot {
+    buffer: wgpu::Buffer,
+    state: SlotState,
+}
+
+/// Frames of one size on their way from the GPU
+pub struct FrameReadback {
+    width: u32,
+    height: u32,
+    is_bgra: bool,
+    slots: Vec<Slot>,
+    /// slots whose mapping finished, sent from the map callbacks
+    mapped: (mpsc::Sender<MapResult>, mpsc::Receiver<MapResult>),
+    /// slots in the order their frames were copied, so frames come back in order
+    in_flight: VecDeque<usize>,
+    dropped: usize,
+}
+
+impl FrameReadback {
+    /// Buffers for frames of a size
+    ///
+    /// Args:
+    ///     format: the frame's format, it has to be 8 bit color and copyable
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, label: &str) -> anyhow::Result<Self> {
+        if !is_savable(format) {
+            anyhow::bail!("can't read back a {:?} frame", format);
+        }
+        let slots = (0..READBACK_SLOTS)
+            .map(|_| Slot {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size: (padded_row_bytes(width) * height) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: SlotState::Free,
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            is_bgra: is_bgra(format),
+            slots,
+            mapped: mpsc::channel(),
+            in_flight: VecDeque::new(),
+            dropped: 0,
+        })
+    }
+
+    /// Width and height of the frames
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Frames that were skipped because every buffer was busy, or that couldn't be read
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Record copying a frame into a free buffer, call this after the frame was drawn
+    ///
+    /// The frame has to be the readback's size.
+    pub fn capture(&mut self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::Texture) {
+        let Some(index) = self.slots.iter().position(|slot| slot.state == SlotState::Free) else {
+            self.dropped += 1;
+            return;
+        };
+        encoder.copy_texture_to_buffer(
+            frame.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.slots[index].buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes(self.width)),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+        );
+        self.slots[index].state = SlotState::Copied;
+        self.in_flight.push_back(index);
+    }
+
+    /// Start mapping the frames copied this frame, call this after submitting
+    ///
+    /// Returns the RGBA pixels of the frames that are ready, oldest first.
+    pub fn after_submit(&mut self, device: &wgpu::Device) -> Vec<Vec<u8>> {
+        for (index, slot) in self.slots.iter_mut().enumerate().filter(|(_, slot)| slot.state == SlotState::Copied) {
+            slot.state = SlotState::Mapping;
+            let sender = self.mapped.0.clone();
+            slot.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send((index, result));
+            });
+        }
+        device.poll(wgpu::Maintain::Poll);
+        self.collect()
+    }
+
+    /// Wait for every frame still on the GPU and return them, oldest first
+    pub fn finish(&mut self, device: &wgpu::Device) -> Vec<Vec<u8>> {
+        if self.in_flight.is_empty() {
+            return Vec::new();
+        }
+        device.poll(wgpu::Maintain::Wait);
+        self.collect()
+    }
+
+    /// Read the frames whose mapping finished, oldest first
+    fn collect(&mut self) -> Vec<Vec<u8>> {
+        for (index, result) in self.mapped.1.try_iter() {
+            if let Err(e) = &result {
+                log::warn!("Couldn't read a frame back: {}", e);
+            }
+            self.slots[index].state = SlotState::Mapped { is_ok: result.is_ok() };
+        }
+        // a later frame can finish first, it waits for the ones before it
+        let mut frames = Vec::new();
+        while let Some(&index) = self.in_flight.front() {
+            let slot = &mut self.slots[index];
+            let SlotState::Mapped { is_ok } = slot.state else {
+                break;
+            };
+            self.in_flight.pop_front();
+            slot.state = SlotState::Free;
+            if !is_ok {
+                self.dropped += 1;
+                continue;
+            }
+            let mut pixels = unpad_rows(&slot.buffer.slice(..).get_mapped_range(), self.width, self.height);
+            slot.buffer.unmap();
+            if self.is_bgra {
+                bgra_to_rgba(&mut pixels);
+            }
+            frames.push(pixels);
+        }
+        frames
+    }
+}
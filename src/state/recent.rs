@@ -0,0 +1,135 @@
+//! Scenes and models opened lately, kept in `recent.txt` and listed in a window shown with F7
+use super::{locale::Strings, world::{importer, resources::save_string}};
+
+/// Name of the file in `res` the list is kept in
+pub const RECENT_FILE: &str = "recent.txt";
+
+/// Most entries the list keeps, the oldest drop off the end
+pub const MAX_RECENT: usize = 10;
+
+/// What opening an entry does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecentKind {
+    /// a scene file, which replaces the scene being edited
+    Scene,
+    /// a model file, which places another instance of the model
+    Model,
+}
+
+impl RecentKind {
+    /// Model files are the ones an importer or the .obj loader reads, anything else is a scene
+    pub fn of(file_name: &str) -> Self {
+        if importer::is_model_file(file_name) { Self::Model } else { Self::Scene }
+    }
+}
+
+/// Files in `res` opened lately, newest first
+///
+/// Stored as text with one path relative to `res` per line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecentFiles {
+    pub entries: Vec<String>,
+}
+
+impl RecentFiles {
+    /// Read the list from its text form, blank lines are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut recent = Self::default();
+        // pushed oldest first so duplicates and the limit work the same as when opening
+        for line in text.lines().rev().map(str::trim).filter(|line| !line.is_empty()) {
+            recent.push(line);
+        }
+        recent
+    }
+
+    /// The list in the form `parse` reads
+    pub fn to_text(&self) -> String {
+        self.entries.iter().map(|entry| format!("{}\n", entry)).collect()
+    }
+
+    /// Put a file at the top of the list, moving it up if it was already there
+    pub fn push(&mut self, file_name: &str) {
+        self.entries.retain(|entry| entry != file_name);
+        self.entries.insert(0, file_name.to_string());
+        self.entries.truncate(MAX_RECENT);
+    }
+
+    /// The entry a console argument names, either its number counting from 1 or its file name
+    pub fn find(&self, name: &str) -> Option<&str> {
+        match name.parse::<usize>() {
+            Ok(number) => self.entries.get(number.checked_sub(1)?),
+            Err(_) => self.entries.iter().find(|entry| *entry == name),
+        }
+        .map(String::as_str)
+    }
+
+    /// The numbered list the `recent` console command prints
+    pub fn listing(&self) -> String {
+        self.entries.iter().enumerate().map(|(index, entry)| format!("{}. {}", index + 1, entry)).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Write the list to `recent.txt` in the background
+    pub fn save(&self) {
+        let task = save_string(&RECENT_FILE, self.to_text());
+        crate::tasks::spawn(async move {
+            if let Ok(Err(e)) = task.await {
+                log::warn!("Couldn't save the recent files: {}", e);
+            }
+        });
+    }
+}
+
+/// Lay out the window listing recent files, returns the one clicked to open it again
+pub fn show(context: &egui::Context, entries: &[String], strings: &Strings) -> Option<String> {
+    let mut opened = None;
+    egui::Window::new(strings.get("recent.title")).default_pos((240.0, 8.0)).show(context, |ui| {
+        if entries.is_empty() {
+            ui.label(strings.get("recent.empty"));
+        }
+        for entry in entries {
+            let kind = match RecentKind::of(entry) {
+                RecentKind::Scene => strings.get("recent.scene"),
+                RecentKind::Model => strings.get("recent.model"),
+            };
+            ui.horizontal(|ui| {
+                ui.label(kind);
+                if ui.button(entry).clicked() {
+                    opened = Some(entry.clone());
+                }
+            });
+        }
+    });
+    opened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let mut recent = RecentFiles::parse("scene.txt\n\ncube/cube.obj\n");
+        assert_eq!(recent.entries, vec!["scene.txt", "cube/cube.obj"]);
+        recent.push("cube/cube.obj");
+        assert_eq!(recent.entries, vec!["cube/cube.obj", "scene.txt"]);
+        assert_eq!(RecentFiles::parse(&recent.to_text()), recent);
+
+        for number in 0..MAX_RECENT {
+            recent.push(&format!("scene_{}.txt", number));
+        }
+        assert_eq!(recent.entries.len(), MAX_RECENT);
+        assert_eq!(recent.entries[0], format!("scene_{}.txt", MAX_RECENT - 1));
+    }
+
+    #[test]
+    fn test_find() {
+        let recent = RecentFiles::parse("other.txt\nparts/bracket.stl");
+        assert_eq!(recent.find("2"), Some("parts/bracket.stl"));
+        assert_eq!(recent.find("other.txt"), Some("other.txt"));
+        assert_eq!(recent.find("0"), None);
+        assert_eq!(recent.find("3"), None);
+        assert_eq!(RecentKind::of("parts/bracket.stl"), RecentKind::Model);
+        assert_eq!(RecentKind::of("other.txt"), RecentKind::Scene);
+        assert_eq!(recent.listing(), "1. other.txt\n2. parts/bracket.stl");
+    }
+}
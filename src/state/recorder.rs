@@ -0,0 +1,239 @@
+//! Recording the window to numbered pictures or a video, F9 starts and stops a take
+//!
+//! Frames of a take are read back without waiting on the GPU, see `readback`, and handed to a background
+//! thread, which saves them as PNGs or pipes them into ffmpeg.
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc,
+};
+
+use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+use super::readback::FrameReadback;
+
+/// How takes are written
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecorderSettings {
+    /// folder the takes go in, made if it's missing
+    pub directory: PathBuf,
+    /// pipe frames into ffmpeg for an .mp4 instead of saving numbered PNGs, ffmpeg has to be on the path
+    pub ffmpeg: bool,
+    /// frames per second the video plays back at, frames are recorded as fast as they are drawn
+    pub frame_rate: u32,
+}
+
+impl Default for RecorderSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("recordings"),
+            ffmpeg: false,
+            frame_rate: 60,
+        }
+    }
+}
+
+/// Name of the nth frame of a take, counting from 1 so they sort in order
+fn frame_file_name(index: usize) -> String {
+    format!("frame_{:05}.png", index + 1)
+}
+
+/// The first take name not used in a folder yet, `take_001` and up
+fn next_take(directory: &Path, extension: &str) -> PathBuf {
+    (1..)
+        .map(|number| directory.join(format!("take_{:03}{}", number, extension)))
+        .find(|path| !path.exists())
+        .expect("ran out of take numbers")
+}
+
+/// Where finished frames go, on a thread of its own so saving never holds up a frame
+///
+/// Dropping it lets the thread finish the frames it was sent and end.
+struct Writer {
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl Writer {
+    /// Save every frame sent as a numbered PNG in a folder
+    fn pictures(folder: PathBuf, width: u32, height: u32) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&folder)?;
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        std::thread::spawn(move || {
+            for (index, pixels) in receiver.into_iter().enumerate() {
+                let path = folder.join(frame_file_name(index));
+                let saved = image::RgbaImage::from_raw(width, height, pixels)
+                    .ok_or_else(|| anyhow::anyhow!("frame came back the wrong size"))
+                    .and_then(|picture| Ok(picture.save(&path)?));
+                if let Err(e) = saved {
+                    log::warn!("Couldn't save {}: {}", path.display(), e);
+                }
+            }
+        });
+        Ok(Self { sender })
+    }
+
+    /// Pipe every frame sent into an ffmpeg encoding a video
+    fn video(path: PathBuf, width: u32, height: u32, frame_rate: u32) -> anyhow::Result<Self> {
+        if let Some(folder) = path.parent() {
+            std::fs::create_dir_all(folder)?;
+        }
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{}x{}", width, height), "-r", &frame_rate.max(1).to_string(), "-i", "-"])
+            // most players only take 4:2:0, which needs an even size
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("couldn't start ffmpeg: {}", e))?;
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("ffmpeg has no input"))?;
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        std::thread::spawn(move || {
+            for pixels in receiver {
+                if let Err(e) = stdin.write_all(&pixels) {
+                    log::warn!("ffmpeg stopped taking frames: {}", e);
+                    break;
+                }
+            }
+            // closing the input tells ffmpeg the video is done
+            drop(stdin);
+            match child.wait() {
+                Ok(status) if !status.success() => log::warn!("ffmpeg failed writing {}: {}", path.display(), status),
+                Err(e) => log::warn!("ffmpeg failed writing {}: {}", path.display(), e),
+                Ok(_) => {}
+            }
+        });
+        Ok(Self { sender })
+    }
+}
+
+/// A take being recorded
+struct Take {
+    readback: FrameReadback,
+    recorded: usize,
+    writer: Writer,
+    /// where the take is written, for the log
+    destination: PathBuf,
+}
+
+/// F9 records the window until it is pressed again
+#[derive(Default)]
+pub struct Recorder {
+    settings: RecorderSettings,
+    is_toggle_pressed: bool,
+    take: Option<Take>,
+}
+
+impl Recorder {
+    pub fn new(settings: RecorderSettings) -> Self {
+        Self { settings, ..Default::default() }
+    }
+
+    /// Call this for key presses
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F9),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.is_toggle_pressed = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// True once after F9 was pressed, the caller then starts or stops a take
+    pub fn take_toggle(&mut self) -> bool {
+        std::mem::take(&mut self.is_toggle_pressed)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.take.is_some()
+    }
+
+    /// Start a take of frames of one size
+    ///
+    /// Args:
+    ///     format: the window's format, it has to be 8 bit color and copyable
+    pub fn start(&mut self, device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat) -> anyhow::Result<()> {
+        if self.take.is_some() {
+            anyhow::bail!("already recording");
+        }
+        let readback = FrameReadback::new(device, width, height, format, "recorder_readback")?;
+        let (destination, writer) = if self.settings.ffmpeg {
+            let path = next_take(&self.settings.directory, ".mp4");
+            (path.clone(), Writer::video(path, width, height, self.settings.frame_rate)?)
+        } else {
+            let folder = next_take(&self.settings.directory, "");
+            (folder.clone(), Writer::pictures(folder, width, height)?)
+        };
+        log::info!("Recording to {}", destination.display());
+        self.take = Some(Take { readback, recorded: 0, writer, destination });
+        Ok(())
+    }
+
+    /// Finish the take, waiting for the frames still on the GPU
+    pub fn stop(&mut self, device: &wgpu::Device) {
+        if let Some(mut take) = self.take.take() {
+            let frames = take.readback.finish(device);
+            take.write(frames);
+            log::info!("Recorded {} frames to {}, {} dropped", take.recorded, take.destination.display(), take.readback.dropped());
+        }
+    }
+
+    /// Record copying the frame into a free buffer, call this after the frame was drawn
+    ///
+    /// A frame of another size than the take's ends the take, resizing starts a new one.
+    pub fn capture(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::Texture) {
+        let Some(take) = &mut self.take else {
+            return;
+        };
+        if (frame.width(), frame.height()) != take.readback.size() {
+            log::warn!("The window changed size, stopping the recording");
+            self.stop(device);
+            return;
+        }
+        take.readback.capture(encoder, frame);
+    }
+
+    /// Start mapping the frame copied this frame and write out the ones that are ready, call this after submitting
+    pub fn after_submit(&mut self, device: &wgpu::Device) {
+        if let Some(take) = &mut self.take {
+            let frames = take.readback.after_submit(device);
+            take.write(frames);
+        }
+    }
+}
+
+impl Take {
+    /// Hand frames that came back to the writer, oldest first
+    fn write(&mut self, frames: Vec<Vec<u8>>) {
+        for pixels in frames {
+            if self.writer.sender.send(pixels).is_ok() {
+                self.recorded += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_names() {
+        assert_eq!(frame_file_name(0), "frame_00001.png");
+        let directory = std::env::temp_dir().join(format!("rust3d_takes_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("take_001")).unwrap();
+        assert_eq!(next_take(&directory, ""), directory.join("take_002"));
+        assert_eq!(next_take(&directory, ".mp4"), directory.join("take_001.mp4"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
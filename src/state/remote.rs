@@ -0,0 +1,436 @@
+//! Serving the window over HTTP, to watch and steer the world from a browser on another machine
+//!
+//! With `RemoteSettings::address` set a small server listens there: `/` is a page showing the picture,
+//! `/stream` sends the frames as a motion JPEG and `/input` takes the keys, drags, clicks and scrolls the
+//! page sends back. Frames are read back like a recording, see `readback`, and only while someone is
+//! watching. They are encoded on a thread of its own, frames coming faster than that are skipped.
+//!
+//! Nobody has to log in: anyone who can reach the address can watch and steer the camera, so it
+//! listens on this machine only unless an address for other machines is given.
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+use winit::keyboard::KeyCode;
+
+use super::{key_bindings::parse_key, readback::FrameReadback};
+
+/// The page viewers open, the picture with scripts sending input back
+const PAGE: &str = include_str!("../remote.html");
+
+/// Separates the pictures of the motion JPEG
+const BOUNDARY: &str = "rust3d_frame";
+
+/// Largest input request read, the page sends a few lines at a time
+const MAX_INPUT_BYTES: usize = 64 * 1024;
+
+/// How long a connection may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where a port given on its own listens, only this machine can connect
+const DEFAULT_HOST: &str = "127.0.0.1";
+
+/// Where and how the window is served
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteSettings {
+    /// where to listen, a port like `8080` listens on this machine only, `0.0.0.0:8080` for other machines
+    /// too, None serves nothing
+    pub address: Option<String>,
+    /// JPEG quality from 1 to 100, lower sends smaller frames
+    pub quality: u8,
+    /// connections served at once, more are turned away until one closes
+    pub max_clients: usize,
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        Self { address: None, quality: 80, max_clients: 8 }
+    }
+}
+
+/// The address to listen on, a port on its own listens on `DEFAULT_HOST`
+fn bind_address(address: &str) -> String {
+    match address.parse::<u16>() {
+        Ok(port) => format!("{}:{}", DEFAULT_HOST, port),
+        Err(_) => address.to_string(),
+    }
+}
+
+/// Something done on a viewer's page
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RemoteInput {
+    Key { code: KeyCode, is_pressed: bool },
+    /// the mouse was dragged by some pixels, turns the camera like the locked mouse does
+    Look { dx: f64, dy: f64 },
+    /// a click at a pixel of the frame
+    Click { x: f32, y: f32 },
+    /// lines of the mouse wheel, positive away from the user
+    Scroll(f32),
+}
+
+impl RemoteInput {
+    /// Read a line the page sends: `key KeyW down`, `look 3 -2`, `click 120 80` or `scroll 1`
+    pub fn parse(line: &str) -> Option<Self> {
+        let number = |text: &str| text.parse::<f64>().ok().filter(|value| value.is_finite());
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["key", code, state] => {
+                let is_pressed = match *state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return None,
+                };
+                Some(RemoteInput::Key { code: parse_key(code)?, is_pressed })
+            }
+            ["look", dx, dy] => Some(RemoteInput::Look { dx: number(dx)?, dy: number(dy)? }),
+            ["click", x, y] => Some(RemoteInput::Click { x: number(x)? as f32, y: number(y)? as f32 }),
+            ["scroll", lines] => Some(RemoteInput::Scroll(number(lines)? as f32)),
+            _ => None,
+        }
+    }
+}
+
+/// What the server's threads share
+#[derive(Default)]
+struct Shared {
+    /// number of the newest frame and the frame as a JPEG, 0 before the first
+    frame: Mutex<(u64, Arc<Vec<u8>>)>,
+    new_frame: Condvar,
+    /// pages watching the stream, frames are only read back while there are some
+    viewers: AtomicUsize,
+    /// connections being served, watching or not
+    clients: AtomicUsize,
+}
+
+/// A server that is running
+struct Server {
+    address: SocketAddr,
+    shared: Arc<Shared>,
+    /// frames for the encoder, one waits at most
+    frames: mpsc::SyncSender<(u32, u32, Vec<u8>)>,
+    inputs: mpsc::Receiver<RemoteInput>,
+}
+
+/// Serves the window to browsers, does nothing without an address
+#[derive(Default)]
+pub struct RemoteViewer {
+    server: Option<Server>,
+    readback: Option<FrameReadback>,
+}
+
+impl RemoteViewer {
+    /// Start listening on the settings' address
+    pub fn new(settings: &RemoteSettings) -> anyhow::Result<Self> {
+        let Some(address) = &settings.address else {
+            return Ok(Self::default());
+        };
+        let listener = TcpListener::bind(bind_address(address))?;
+        let address = listener.local_addr()?;
+        if !address.ip().is_loopback() {
+            log::warn!("Serving the window to other machines on {}, anyone who can reach it can watch and steer the camera without logging in", address);
+        }
+        let shared = Arc::new(Shared::default());
+        let (input_sender, inputs) = mpsc::channel();
+        let (frames, to_encode) = mpsc::sync_channel(1);
+
+        let encoded = shared.clone();
+        let quality = settings.quality.clamp(1, 100);
+        std::thread::spawn(move || {
+            for (width, height, pixels) in to_encode {
+                match encode_jpeg(width, height, pixels, quality) {
+                    Ok(jpeg) => {
+                        let mut frame = encoded.frame.lock().unwrap();
+                        *frame = (frame.0 + 1, Arc::new(jpeg));
+                        encoded.new_frame.notify_all();
+                    }
+                    Err(e) => log::warn!("Couldn't encode a frame for the remote viewer: {}", e),
+                }
+            }
+        });
+
+        let connections = shared.clone();
+        let max_clients = settings.max_clients;
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("Couldn't accept a remote viewer: {}", e);
+                        continue;
+                    }
+                };
+                // counted here rather than on the connection's thread so a burst of them can't all get in
+                if connections.clients.fetch_add(1, Ordering::Relaxed) >= max_clients {
+                    connections.clients.fetch_sub(1, Ordering::Relaxed);
+                    log::debug!("Turned away a remote viewer, {} are connected", max_clients);
+                    let _ = stream.set_write_timeout(Some(REQUEST_TIMEOUT));
+                    let _ = respond(&mut stream, "503 Service Unavailable", "text/plain", b"too many viewers");
+                    continue;
+                }
+                let (shared, inputs) = (connections.clone(), input_sender.clone());
+                std::thread::spawn(move || {
+                    let _client = Counted(&shared.clients);
+                    if let Err(e) = serve(stream, &shared, &inputs) {
+                        log::debug!("Remote viewer connection ended: {}", e);
+                    }
+                });
+            }
+        });
+        log::info!("Serving the window on http://{}", address);
+        Ok(Self { server: Some(Server { address, shared, frames, inputs }), readback: None })
+    }
+
+    /// Where the server listens, with the port picked if the settings asked for port 0
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.server.as_ref().map(|server| server.address)
+    }
+
+    /// Record copying the frame to send it, call this after the frame was drawn
+    ///
+    /// Args:
+    ///     format: the frame's format, it has to be 8 bit color and copyable
+    pub fn capture(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::Texture, format: wgpu::TextureFormat) {
+        let Some(server) = &self.server else {
+            return;
+        };
+        if server.shared.viewers.load(Ordering::Relaxed) == 0 {
+            self.readback = None;
+            return;
+        }
+        // the buffers are made again when the window changes size
+        let size = (frame.width(), frame.height());
+        if self.readback.as_ref().map(FrameReadback::size) != Some(size) {
+            match FrameReadback::new(device, size.0, size.1, format, "remote_readback") {
+                Ok(readback) => self.readback = Some(readback),
+                Err(e) => {
+                    log::warn!("Stopped serving the window: {}", e);
+                    *self = Self::default();
+                    return;
+                }
+            }
+        }
+        if let Some(readback) = &mut self.readback {
+            readback.capture(encoder, frame);
+        }
+    }
+
+    /// Send on the newest frame that came back, call this after submitting
+    pub fn after_submit(&mut self, device: &wgpu::Device) {
+        let (Some(server), Some(readback)) = (&self.server, &mut self.readback) else {
+            return;
+        };
+        let (width, height) = readback.size();
+        if let Some(pixels) = readback.after_submit(device).pop() {
+            // skipped while the encoder is still busy with the one before
+            let _ = server.frames.try_send((width, height, pixels));
+        }
+    }
+
+    /// What viewers did since the last call, oldest first
+    pub fn take_inputs(&self) -> Vec<RemoteInput> {
+        self.server.as_ref().map(|server| server.inputs.try_iter().collect()).unwrap_or_default()
+    }
+}
+
+/// Keeps a connection counted until it ends, however it ends
+struct Counted<'a>(&'a AtomicUsize);
+
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Answer one connection
+fn serve(stream: TcpStream, shared: &Shared, inputs: &mpsc::Sender<RemoteInput>) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut content_length = 0;
+    let (mut origin, mut host) = (None, None);
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim(), value.trim().to_string());
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse()?;
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value);
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value);
+            }
+        }
+    }
+
+    let mut stream = stream;
+    match parse_request(&request) {
+        Some(("GET", "/")) => respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE.as_bytes()),
+        Some(("GET", "/stream")) => stream_frames(stream, shared),
+        Some(("POST", "/input")) => {
+            // browsers send any page's posts here, so only the page served here may steer
+            if !same_origin(origin.as_deref(), host.as_deref()) {
+                return respond(&mut stream, "403 Forbidden", "text/plain", b"input is only taken from the viewer page");
+            }
+            if content_length > MAX_INPUT_BYTES {
+                return respond(&mut stream, "413 Payload Too Large", "text/plain", b"");
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            for line in String::from_utf8_lossy(&body).lines() {
+                match RemoteInput::parse(line) {
+                    Some(input) => {
+                        let _ = inputs.send(input);
+                    }
+                    None => log::debug!("Unknown remote input {:?}", line),
+                }
+            }
+            respond(&mut stream, "204 No Content", "text/plain", b"")
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+    }
+}
+
+/// Whether a request came from the page served at the host it was sent to
+///
+/// Browsers add the page's origin to every post, so a post without one didn't come from the viewer page either.
+fn same_origin(origin: Option<&str>, host: Option<&str>) -> bool {
+    match (origin, host) {
+        (Some(origin), Some(host)) => origin.strip_prefix("http://") == Some(host),
+        _ => false,
+    }
+}
+
+/// Method and path of a request line, without the query browsers add to skip their cache
+fn parse_request(line: &str) -> Option<(&str, &str)> {
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    Some((method, target.split('?').next().unwrap_or(target)))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> anyhow::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+/// Send every new frame until the viewer goes away
+fn stream_frames(mut stream: TcpStream, shared: &Shared) -> anyhow::Result<()> {
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n", BOUNDARY)?;
+    shared.viewers.fetch_add(1, Ordering::Relaxed);
+    let _watching = Counted(&shared.viewers);
+    let mut shown = 0;
+    loop {
+        let jpeg = {
+            let mut frame = shared.frame.lock().unwrap();
+            while frame.0 == shown {
+                frame = shared.new_frame.wait(frame).unwrap();
+            }
+            shown = frame.0;
+            frame.1.clone()
+        };
+        write!(stream, "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", BOUNDARY, jpeg.len())?;
+        stream.write_all(&jpeg)?;
+        stream.write_all(b"\r\n")?;
+    }
+}
+
+/// Compress RGBA pixels, JPEG has no alpha so it is left out
+fn encode_jpeg(width: u32, height: u32, pixels: Vec<u8>, quality: u8) -> anyhow::Result<Vec<u8>> {
+    let picture = image::RgbaImage::from_raw(width, height, pixels).ok_or_else(|| anyhow::anyhow!("frame came back the wrong size"))?;
+    let rgb = image::DynamicImage::ImageRgba8(picture).into_rgb8();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality).encode(&rgb, width, height, image::ColorType::Rgb8)?;
+    Ok(jpeg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(RemoteInput::parse("key KeyW down"), Some(RemoteInput::Key { code: KeyCode::KeyW, is_pressed: true }));
+        assert_eq!(RemoteInput::parse("key ArrowLeft up"), Some(RemoteInput::Key { code: KeyCode::ArrowLeft, is_pressed: false }));
+        assert_eq!(RemoteInput::parse("look 3 -2"), Some(RemoteInput::Look { dx: 3.0, dy: -2.0 }));
+        assert_eq!(RemoteInput::parse("click 120.5 80"), Some(RemoteInput::Click { x: 120.5, y: 80.0 }));
+        assert_eq!(RemoteInput::parse("scroll -1"), Some(RemoteInput::Scroll(-1.0)));
+        assert_eq!(RemoteInput::parse("key KeyW sideways"), None);
+        assert_eq!(RemoteInput::parse("look NaN 1"), None);
+        assert_eq!(RemoteInput::parse("jump"), None);
+        assert_eq!(parse_request("GET /stream?t=12 HTTP/1.1\r\n"), Some(("GET", "/stream")));
+        assert_eq!(parse_request("\r\n"), None);
+    }
+
+    #[test]
+    fn test_serve() {
+        let viewer = RemoteViewer::new(&RemoteSettings { address: Some("127.0.0.1:0".to_string()), ..Default::default() }).unwrap();
+        let address = viewer.address().unwrap();
+        let request = |text: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(text.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let page = request("GET / HTTP/1.1\r\n\r\n");
+        assert!(page.starts_with("HTTP/1.1 200 OK") && page.contains("/stream"));
+        assert!(request("GET /missing HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+
+        // input lines arrive before the answer, lines that mean nothing are skipped
+        let body = "key KeyW down\njump\nscroll 1";
+        let post = |origin: &str| request(&format!("POST /input HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\n\r\n{}", address, origin, body.len(), body));
+        assert!(post(&format!("Origin: http://{}\r\n", address)).starts_with("HTTP/1.1 204"));
+        assert_eq!(viewer.take_inputs(), vec![RemoteInput::Key { code: KeyCode::KeyW, is_pressed: true }, RemoteInput::Scroll(1.0)]);
+
+        // other pages and posts without an origin can't steer
+        assert!(post("Origin: http://example.com\r\n").starts_with("HTTP/1.1 403"));
+        assert!(post("").starts_with("HTTP/1.1 403"));
+        assert_eq!(viewer.take_inputs(), vec![]);
+    }
+
+    #[test]
+    fn test_same_origin() {
+        assert!(same_origin(Some("http://localhost:8080"), Some("localhost:8080")));
+        assert!(!same_origin(Some("http://evil.example"), Some("localhost:8080")));
+        assert!(!same_origin(Some("https://localhost:8080"), Some("localhost:8080")));
+        assert!(!same_origin(None, Some("localhost:8080")));
+        assert!(!same_origin(Some("http://localhost:8080"), None));
+    }
+
+    #[test]
+    fn test_max_clients() {
+        let viewer = RemoteViewer::new(&RemoteSettings { address: Some("0".to_string()), max_clients: 1, ..Default::default() }).unwrap();
+        let address = viewer.address().unwrap();
+        // a port on its own only listens on this machine
+        assert!(address.ip().is_loopback());
+
+        // one connection that hasn't sent its request yet takes the only place
+        let _waiting = TcpStream::connect(address).unwrap();
+        // turned away before its request is read
+        let mut response = String::new();
+        TcpStream::connect(address).unwrap().read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"));
+
+        assert_eq!(bind_address("8080"), "127.0.0.1:8080");
+        assert_eq!(bind_address("0.0.0.0:8080"), "0.0.0.0:8080");
+    }
+
+    #[test]
+    fn test_encode_jpeg() {
+        let jpeg = encode_jpeg(4, 2, vec![200; 4 * 2 * 4], 80).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (4, 2));
+        assert!(encode_jpeg(4, 2, vec![0; 3], 80).is_err());
+    }
+}
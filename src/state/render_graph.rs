@@ -0,0 +1,295 @@
+//! A small render graph, passes declare the textures they read and write and the graph runs them in order
+//!
+//! Textures are either imported, made by someone else like the window's frame, or created by the graph
+//! for the passes in between. Created textures come out of a `TexturePool` kept between frames, and two
+//! that are never needed at the same time share one. Passes nothing reads from are left out, unless
+//! they write an imported texture.
+use std::collections::BTreeSet;
+
+/// What a texture created by the graph looks like, textures with the same description can be shared
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+}
+
+/// A texture in the graph, handed out by `import` and `create`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot(usize);
+
+enum SlotSource<'a> {
+    Imported(&'a wgpu::TextureView),
+    Created(TextureDesc),
+}
+
+type RecordPass<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &GraphTextures) + 'a>;
+
+struct PassNode<'a> {
+    name: &'static str,
+    reads: Vec<Slot>,
+    writes: Vec<Slot>,
+    record: RecordPass<'a>,
+}
+
+/// The order passes run in and which pool texture each created slot gets
+#[derive(Debug, PartialEq)]
+struct Schedule {
+    /// passes by the order they were added, the culled ones left out
+    order: Vec<usize>,
+    /// the pool texture of each created slot, None for imported ones and ones no pass that runs touches
+    slot_textures: Vec<Option<usize>>,
+    /// what each pool texture has to look like
+    textures: Vec<TextureDesc>,
+}
+
+/// Passes for one frame and the textures they pass between them
+pub struct RenderGraph<'a> {
+    slots: Vec<(&'static str, SlotSource<'a>)>,
+    passes: Vec<PassNode<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self { slots: Vec::new(), passes: Vec::new() }
+    }
+
+    /// Bring in a texture made outside the graph, passes writing it always run
+    pub fn import(&mut self, name: &'static str, view: &'a wgpu::TextureView) -> Slot {
+        self.slots.push((name, SlotSource::Imported(view)));
+        Slot(self.slots.len() - 1)
+    }
+
+    /// Ask for a texture only passes in the graph use
+    pub fn create(&mut self, name: &'static str, desc: TextureDesc) -> Slot {
+        self.slots.push((name, SlotSource::Created(desc)));
+        Slot(self.slots.len() - 1)
+    }
+
+    /// Add a pass, it runs after every pass writing what it reads
+    ///
+    /// Passes writing the same texture run in the order they were added, so a pass can draw over
+    /// another's output by reading and writing it.
+    ///
+    /// Args:
+    ///     record: records the pass, the views of its textures are looked up in what it is given
+    pub fn add_pass(&mut self, name: &'static str, reads: &[Slot], writes: &[Slot], record: impl FnOnce(&mut wgpu::CommandEncoder, &GraphTextures) + 'a) {
+        self.passes.push(PassNode { name, reads: reads.to_vec(), writes: writes.to_vec(), record: Box::new(record) });
+    }
+
+    /// Record every pass that is needed, in order, with created textures from a pool
+    pub fn execute(self, device: &wgpu::Device, pool: &mut TexturePool, encoder: &mut wgpu::CommandEncoder) -> anyhow::Result<()> {
+        let slots = self.slots.iter().map(|(_, source)| match source {
+            SlotSource::Imported(_) => None,
+            SlotSource::Created(desc) => Some(*desc),
+        }).collect::<Vec<_>>();
+        let passes = self.passes.iter().map(|pass| PassIo { name: pass.name, reads: &pass.reads, writes: &pass.writes }).collect::<Vec<_>>();
+        let schedule = schedule(&slots, &passes)?;
+        pool.acquire(device, &schedule.textures);
+
+        let views = self.slots.iter().zip(&schedule.slot_textures).map(|((_, source), texture)| match (source, texture) {
+            (SlotSource::Imported(view), _) => Some(*view),
+            (SlotSource::Created(_), Some(texture)) => Some(&pool.textures[*texture].view),
+            (SlotSource::Created(_), None) => None,
+        }).collect();
+        let textures = GraphTextures { views, names: self.slots.iter().map(|(name, _)| *name).collect() };
+
+        let mut passes = self.passes.into_iter().map(Some).collect::<Vec<_>>();
+        for index in schedule.order {
+            if let Some(pass) = passes[index].take() {
+                (pass.record)(encoder, &textures);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What scheduling needs to know about a pass
+struct PassIo<'p> {
+    name: &'static str,
+    reads: &'p [Slot],
+    writes: &'p [Slot],
+}
+
+/// Work out which passes run in what order, and which created slots can share a texture
+///
+/// Args:
+///     slots: the description of each created slot, None for imported ones
+fn schedule(slots: &[Option<TextureDesc>], passes: &[PassIo]) -> anyhow::Result<Schedule> {
+    let writers = |slot: Slot| passes.iter().enumerate().filter(move |(_, pass)| pass.writes.contains(&slot)).map(|(index, _)| index);
+
+    // passes writing imported textures are the graph's output, the rest run if a pass that runs reads them
+    let mut is_live = passes.iter().map(|pass| pass.writes.iter().any(|slot| slots[slot.0].is_none())).collect::<Vec<_>>();
+    let mut is_changed = true;
+    while is_changed {
+        is_changed = false;
+        for (index, pass) in passes.iter().enumerate() {
+            if !is_live[index] {
+                continue;
+            }
+            for slot in pass.reads {
+                for writer in writers(*slot).filter(|writer| *writer != index) {
+                    is_changed |= !is_live[writer];
+                    is_live[writer] = true;
+                }
+            }
+        }
+    }
+
+    // a pass waits for the writers of what it reads, and for passes added before it writing the same texture
+    let depends_on = |index: usize| {
+        let pass = &passes[index];
+        let mut before = BTreeSet::new();
+        for slot in pass.reads {
+            before.extend(writers(*slot).filter(|writer| *writer != index));
+        }
+        for slot in pass.writes {
+            before.extend(writers(*slot).filter(|writer| *writer < index));
+        }
+        before.retain(|writer| is_live[*writer]);
+        before
+    };
+    let mut waiting = (0..passes.len()).filter(|index| is_live[*index]).map(|index| (index, depends_on(index))).collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(waiting.len());
+    while !waiting.is_empty() {
+        // the earliest added pass that is ready, so independent passes keep the order they were added in
+        let Some(ready) = waiting.iter().position(|(_, before)| before.iter().all(|index| order.contains(index))) else {
+            let stuck = waiting.iter().map(|(index, _)| passes[*index].name).collect::<Vec<_>>();
+            anyhow::bail!("render passes wait on each other: {}", stuck.join(", "));
+        };
+        order.push(waiting.remove(ready).0);
+    }
+
+    // created slots live from the first pass touching them to the last, then their texture is free again
+    let lifetimes = slots.iter().enumerate().map(|(slot, desc)| {
+        let touching = order.iter().enumerate()
+            .filter(|(_, index)| passes[**index].reads.contains(&Slot(slot)) || passes[**index].writes.contains(&Slot(slot)))
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+        desc.zip(touching.first()).map(|(desc, first)| (desc, *first, touching[touching.len() - 1]))
+    }).collect::<Vec<_>>();
+
+    let mut slot_textures = vec![None; slots.len()];
+    let mut textures: Vec<TextureDesc> = Vec::new();
+    for position in 0..order.len() {
+        for slot in 0..slots.len() {
+            let Some((desc, first, _)) = lifetimes[slot] else {
+                continue;
+            };
+            if first != position {
+                continue;
+            }
+            let is_free = |texture: usize| lifetimes.iter().zip(&slot_textures).all(|(lifetime, assigned)| {
+                *assigned != Some(texture) || lifetime.is_some_and(|(_, _, last)| last < position)
+            });
+            let shared = (0..textures.len()).find(|texture| textures[*texture] == desc && is_free(*texture));
+            slot_textures[slot] = Some(shared.unwrap_or_else(|| {
+                textures.push(desc);
+                textures.len() - 1
+            }));
+        }
+    }
+    Ok(Schedule { order, slot_textures, textures })
+}
+
+/// The views of a graph's textures, for passes to look theirs up in
+pub struct GraphTextures<'g> {
+    views: Vec<Option<&'g wgpu::TextureView>>,
+    names: Vec<&'static str>,
+}
+
+impl GraphTextures<'_> {
+    /// The view of a texture, panics for a created texture the pass didn't declare
+    pub fn view(&self, slot: Slot) -> &wgpu::TextureView {
+        self.views[slot.0].unwrap_or_else(|| panic!("render graph texture {} isn't used by any pass that runs", self.names[slot.0]))
+    }
+}
+
+struct PooledTexture {
+    desc: TextureDesc,
+    view: wgpu::TextureView,
+}
+
+/// Textures created by render graphs, kept from one frame to the next
+#[derive(Default)]
+pub struct TexturePool {
+    textures: Vec<PooledTexture>,
+}
+
+impl TexturePool {
+    /// Have exactly these textures in this order, reusing the ones from last time and dropping the rest
+    fn acquire(&mut self, device: &wgpu::Device, descs: &[TextureDesc]) {
+        let mut old = std::mem::take(&mut self.textures);
+        for desc in descs {
+            let texture = match old.iter().position(|texture| texture.desc == *desc) {
+                Some(index) => old.swap_remove(index),
+                None => PooledTexture {
+                    desc: *desc,
+                    view: device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("render_graph_texture"),
+                        size: wgpu::Extent3d { width: desc.width.max(1), height: desc.height.max(1), depth_or_array_layers: 1 },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: desc.format,
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                        view_formats: &[],
+                    }).create_view(&wgpu::TextureViewDescriptor::default()),
+                },
+            };
+            self.textures.push(texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HDR: TextureDesc = TextureDesc { width: 64, height: 32, format: wgpu::TextureFormat::Rgba16Float };
+    const LDR: TextureDesc = TextureDesc { width: 64, height: 32, format: wgpu::TextureFormat::Rgba8UnormSrgb };
+    const WINDOW: Slot = Slot(0);
+    const SCENE: Slot = Slot(1);
+    const BLURRED: Slot = Slot(2);
+    const SHARPENED: Slot = Slot(3);
+    const TONE_MAPPED: Slot = Slot(4);
+
+    fn pass<'p>(name: &'static str, reads: &'p [Slot], writes: &'p [Slot]) -> PassIo<'p> {
+        PassIo { name, reads, writes }
+    }
+
+    #[test]
+    fn test_schedule_order() {
+        let slots = [None, Some(HDR), Some(HDR), Some(HDR)];
+        // added out of order, passes writing the same texture keep theirs and the unread pass is left out
+        let passes = [
+            pass("tone_map", &[BLURRED], &[WINDOW]),
+            pass("blur", &[SCENE], &[BLURRED]),
+            pass("scene", &[], &[SCENE]),
+            pass("shafts", &[SCENE], &[SCENE]),
+            pass("unused", &[SCENE], &[SHARPENED]),
+        ];
+        let planned = schedule(&slots, &passes).unwrap();
+        assert_eq!(planned.order, [2, 3, 1, 0]);
+        assert_eq!(planned.slot_textures[3], None);
+
+        let passes = [pass("a", &[BLURRED], &[SCENE, WINDOW]), pass("b", &[SCENE], &[BLURRED])];
+        assert!(schedule(&slots, &passes).is_err());
+    }
+
+    #[test]
+    fn test_schedule_shares_textures() {
+        let slots = [None, Some(HDR), Some(HDR), Some(HDR), Some(LDR)];
+        let passes = [
+            pass("scene", &[], &[SCENE]),
+            pass("blur", &[SCENE], &[BLURRED]),
+            pass("sharpen", &[BLURRED], &[SHARPENED]),
+            pass("tone_map", &[SHARPENED], &[TONE_MAPPED]),
+            pass("fxaa", &[TONE_MAPPED], &[WINDOW]),
+        ];
+        let planned = schedule(&slots, &passes).unwrap();
+        // the scene is done with once blurred, so the sharpened frame goes into its texture
+        assert_eq!(planned.slot_textures, [None, Some(0), Some(1), Some(0), Some(2)]);
+        assert_eq!(planned.textures, [HDR, HDR, LDR]);
+    }
+}
@@ -0,0 +1,265 @@
+//! Options that control how the window is rendered
+use super::{dof::DofSettings, exposure::AutoExposureSettings, gi::GiSettings, tonemap::{ToneMapOutput, ToneMapSettings, PAPER_WHITE_NITS}};
+
+/// Depth format used when the chosen one can't be
+pub const DEFAULT_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Sample counts multisample anti-aliasing can use, 1 is off
+pub const MSAA_SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+/// The views the world is drawn for, the window's uses the settings as they are and the others can override them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderView {
+    Window,
+    /// pictures from camera bookmarks, see `State::render_gallery`
+    Gallery,
+    /// pictures of single models, lit by lights of their own so shadows never change anything
+    Thumbnail,
+}
+
+/// Settings a view draws with instead of the window's, None keeps the window's
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderOverrides {
+    /// the sun's shadows, only when the window has a shadow map to draw them with
+    pub shadows: Option<bool>,
+    /// auto-exposure and the tone mapping curve and exposure, off brings the HDR frame in as it was drawn
+    pub post_processing: Option<bool>,
+}
+
+/// What a view is drawn with once its overrides are applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ViewSettings {
+    pub shadows: bool,
+    pub post_processing: bool,
+}
+
+/// Graphics options chosen before the window is created
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// render to a 16 bit float (scRGB) surface when the platform supports it, for HDR monitors
+    pub hdr_output: bool,
+    /// width over height the scene is always drawn at, with black bars filling the rest of the window, None fills the window
+    pub fixed_aspect: Option<f32>,
+    /// instances smaller than this many pixels across aren't drawn at all, 0 draws everything
+    pub min_instance_pixels: f32,
+    /// instances fade out over this many pixels above `min_instance_pixels` instead of popping
+    pub instance_fade_pixels: f32,
+    /// draw opaque instances roughly closest first so hidden pixels are skipped early
+    ///
+    /// Instances are reordered when one moves into another 2 unit distance bucket, which uploads all of them again.
+    pub sort_front_to_back: bool,
+    /// format of the depth buffer, one with stencil (like Depth24PlusStencil8) is needed for stencil effects
+    pub depth_format: wgpu::TextureFormat,
+    /// width and height of the sun's shadow map, 0 turns shadows off
+    pub shadow_map_size: u32,
+    /// samples per pixel for multisample anti-aliasing, one of `MSAA_SAMPLE_COUNTS`, 1 turns it off
+    pub msaa_samples: u32,
+    /// smooth edges in the finished frame with FXAA, cheaper than MSAA but softer
+    pub fxaa: bool,
+    /// lowest share of the window's resolution the scene drops to when frames run slow, 1 always draws at full resolution
+    pub min_render_scale: f32,
+    /// milliseconds a frame should take, dynamic resolution lowers the scale when frames take longer
+    pub target_frame_ms: f32,
+    /// how much frames drawn at a lower resolution are sharpened when upscaled, from 0 to 1
+    pub upscale_sharpness: f32,
+    /// blur what the camera isn't focused on, the focus follows the picked cubes
+    pub depth_of_field: DofSettings,
+    /// the curve and exposure bringing the HDR scene into the window
+    pub tone_mapping: ToneMapSettings,
+    /// expose the scene for how bright it is, on top of the exposure in `tone_mapping`
+    pub auto_exposure: AutoExposureSettings,
+    /// light probes bouncing light around in place of flat ambient light, experimental
+    pub global_illumination: GiSettings,
+    /// how gallery pictures differ from the window
+    pub gallery_overrides: RenderOverrides,
+    /// how thumbnails differ from the window, by default they are drawn plain so they look the same in any world
+    pub thumbnail_overrides: RenderOverrides,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            hdr_output: false,
+            fixed_aspect: None,
+            min_instance_pixels: 2.0,
+            instance_fade_pixels: 4.0,
+            sort_front_to_back: false,
+            depth_format: DEFAULT_DEPTH_FORMAT,
+            shadow_map_size: 2048,
+            msaa_samples: 1,
+            fxaa: false,
+            min_render_scale: 1.0,
+            target_frame_ms: 1000.0 / 60.0,
+            upscale_sharpness: 0.5,
+            depth_of_field: DofSettings::default(),
+            tone_mapping: ToneMapSettings::default(),
+            auto_exposure: AutoExposureSettings::default(),
+            global_illumination: GiSettings::default(),
+            gallery_overrides: RenderOverrides::default(),
+            thumbnail_overrides: RenderOverrides { shadows: Some(false), post_processing: Some(false) },
+        }
+    }
+}
+
+impl RenderSettings {
+    /// The settings a view is drawn with, its overrides on top of the window's
+    pub fn for_view(&self, view: RenderView) -> ViewSettings {
+        let overrides = match view {
+            RenderView::Window => RenderOverrides::default(),
+            RenderView::Gallery => self.gallery_overrides,
+            RenderView::Thumbnail => self.thumbnail_overrides,
+        };
+        let has_shadows = self.shadow_map_size > 0;
+        ViewSettings {
+            shadows: has_shadows && overrides.shadows.unwrap_or(true),
+            post_processing: overrides.post_processing.unwrap_or(true),
+        }
+    }
+
+    /// Check the depth format can be rendered to, falling back to Depth32Float if not
+    ///
+    /// Args:
+    ///     supported: whether the adapter can use a format as a depth attachment
+    pub fn choose_depth_format(&self, supported: impl Fn(wgpu::TextureFormat) -> bool) -> wgpu::TextureFormat {
+        if !self.depth_format.is_depth_stencil_format() {
+            log::warn!("{:?} isn't a depth format, using {:?}", self.depth_format, DEFAULT_DEPTH_FORMAT);
+            return DEFAULT_DEPTH_FORMAT;
+        }
+        if !supported(self.depth_format) {
+            log::warn!("{:?} isn't supported by the adapter, using {:?}", self.depth_format, DEFAULT_DEPTH_FORMAT);
+            return DEFAULT_DEPTH_FORMAT;
+        }
+        self.depth_format
+    }
+
+    /// Check the MSAA sample count can be used, falling back to the next lower one that can
+    ///
+    /// Args:
+    ///     supported: whether the adapter can render with a sample count
+    pub fn choose_msaa_samples(&self, supported: impl Fn(u32) -> bool) -> u32 {
+        if !MSAA_SAMPLE_COUNTS.contains(&self.msaa_samples) {
+            log::warn!("{} isn't an MSAA sample count, expected one of {:?}", self.msaa_samples, MSAA_SAMPLE_COUNTS);
+        }
+        let samples = MSAA_SAMPLE_COUNTS.into_iter()
+            .rev()
+            .find(|samples| *samples == 1 || (*samples <= self.msaa_samples && supported(*samples)))
+            .unwrap_or(1);
+        if samples != self.msaa_samples && MSAA_SAMPLE_COUNTS.contains(&self.msaa_samples) {
+            log::warn!("{}x MSAA isn't supported by the adapter, using {}x", self.msaa_samples, samples);
+        }
+        samples
+    }
+
+    /// How much of an instance is drawn given how many pixels across it is on screen
+    ///
+    /// Returns None when it is too small to be drawn at all, otherwise the fraction to draw from 0 to 1
+    pub fn screen_size_fade(&self, pixels: f32) -> Option<f32> {
+        if pixels < self.min_instance_pixels {
+            return None;
+        }
+        if self.instance_fade_pixels <= 0.0 {
+            return Some(1.0);
+        }
+        Some(((pixels - self.min_instance_pixels) / self.instance_fade_pixels).min(1.0))
+    }
+
+    /// Pick the surface format to render to out of the ones the surface supports
+    ///
+    /// HDR output uses a linear Rgba16Float surface where values above 1.0 are brighter than SDR white,
+    /// otherwise we use an sRGB surface so the GPU handles the gamma for us.
+    pub fn choose_surface_format(&self, available: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+        if self.hdr_output {
+            if available.contains(&wgpu::TextureFormat::Rgba16Float) {
+                return wgpu::TextureFormat::Rgba16Float;
+            }
+            log::warn!("HDR output requested but the surface doesn't support Rgba16Float, using SDR");
+        }
+
+        available.iter()
+            .find(|f| f.is_srgb())
+            .copied()
+            .unwrap_or(available[0])
+    }
+
+    /// What the tone mapper writes for the surface `choose_surface_format` picked
+    ///
+    /// Only an HDR surface keeps light brighter than white, when it wasn't available the frame is tone mapped as SDR.
+    pub fn tone_map_output(&self, surface_format: wgpu::TextureFormat) -> ToneMapOutput {
+        if self.hdr_output && surface_format == wgpu::TextureFormat::Rgba16Float {
+            ToneMapOutput::ScRgb { paper_white_nits: PAPER_WHITE_NITS }
+        } else {
+            ToneMapOutput::Sdr
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_surface_format() {
+        let sdr_only = [wgpu::TextureFormat::Bgra8Unorm, wgpu::TextureFormat::Bgra8UnormSrgb];
+        let with_hdr = [wgpu::TextureFormat::Bgra8UnormSrgb, wgpu::TextureFormat::Rgba16Float];
+
+        let sdr = RenderSettings::default();
+        let hdr = RenderSettings { hdr_output: true, ..Default::default() };
+
+        assert_eq!(sdr.choose_surface_format(&with_hdr), wgpu::TextureFormat::Bgra8UnormSrgb);
+        assert_eq!(hdr.choose_surface_format(&with_hdr), wgpu::TextureFormat::Rgba16Float);
+        // falls back to sRGB when HDR isn't available
+        assert_eq!(hdr.choose_surface_format(&sdr_only), wgpu::TextureFormat::Bgra8UnormSrgb);
+
+        assert_eq!(sdr.tone_map_output(wgpu::TextureFormat::Rgba16Float), ToneMapOutput::Sdr);
+        assert_eq!(hdr.tone_map_output(wgpu::TextureFormat::Bgra8UnormSrgb), ToneMapOutput::Sdr);
+        assert!(matches!(hdr.tone_map_output(wgpu::TextureFormat::Rgba16Float), ToneMapOutput::ScRgb { .. }));
+    }
+
+    #[test]
+    fn test_choose_depth_format() {
+        let stencil = RenderSettings { depth_format: wgpu::TextureFormat::Depth24PlusStencil8, ..Default::default() };
+        assert_eq!(stencil.choose_depth_format(|_| true), wgpu::TextureFormat::Depth24PlusStencil8);
+        assert_eq!(stencil.choose_depth_format(|_| false), DEFAULT_DEPTH_FORMAT);
+
+        // color formats can't be used for depth
+        let color = RenderSettings { depth_format: wgpu::TextureFormat::Rgba8Unorm, ..Default::default() };
+        assert_eq!(color.choose_depth_format(|_| true), DEFAULT_DEPTH_FORMAT);
+    }
+
+    #[test]
+    fn test_choose_msaa_samples() {
+        let msaa = RenderSettings { msaa_samples: 8, ..Default::default() };
+        assert_eq!(msaa.choose_msaa_samples(|_| true), 8);
+        assert_eq!(msaa.choose_msaa_samples(|samples| samples <= 4), 4);
+        assert_eq!(msaa.choose_msaa_samples(|_| false), 1);
+
+        // counts in between round down to one the GPU knows
+        let odd = RenderSettings { msaa_samples: 6, ..Default::default() };
+        assert_eq!(odd.choose_msaa_samples(|_| true), 4);
+        assert_eq!(RenderSettings::default().choose_msaa_samples(|_| true), 1);
+    }
+
+    #[test]
+    fn test_for_view() {
+        let settings = RenderSettings { gallery_overrides: RenderOverrides { shadows: Some(false), ..Default::default() }, ..Default::default() };
+        assert_eq!(settings.for_view(RenderView::Window), ViewSettings { shadows: true, post_processing: true });
+        assert_eq!(settings.for_view(RenderView::Gallery), ViewSettings { shadows: false, post_processing: true });
+        assert_eq!(settings.for_view(RenderView::Thumbnail), ViewSettings { shadows: false, post_processing: false });
+
+        // a view can't turn on shadows the window has no map for
+        let no_shadows = RenderSettings { shadow_map_size: 0, gallery_overrides: RenderOverrides { shadows: Some(true), ..Default::default() }, ..Default::default() };
+        assert!(!no_shadows.for_view(RenderView::Gallery).shadows);
+    }
+
+    #[test]
+    fn test_screen_size_fade() {
+        let settings = RenderSettings { min_instance_pixels: 2.0, instance_fade_pixels: 4.0, ..Default::default() };
+        assert_eq!(settings.screen_size_fade(1.0), None);
+        assert_eq!(settings.screen_size_fade(4.0), Some(0.5));
+        assert_eq!(settings.screen_size_fade(100.0), Some(1.0));
+
+        // turning the threshold off draws everything
+        let everything = RenderSettings { min_instance_pixels: 0.0, instance_fade_pixels: 0.0, ..Default::default() };
+        assert_eq!(everything.screen_size_fade(0.0), Some(1.0));
+    }
+}
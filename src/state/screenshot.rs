@@ -0,0 +1,307 @@
+//! Camera bookmarks and rendering them off screen into numbered pictures for preview galleries
+use std::path::PathBuf;
+
+use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+/// Bookmarked camera poses, one per line, in the res folder
+pub const BOOKMARKS_FILE: &str = "bookmarks.txt";
+
+/// Row alignment copies out of textures need, see `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`
+const ROW_ALIGNMENT: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+/// How gallery pictures are taken
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenshotSettings {
+    /// size of every picture in pixels, independent of the window
+    pub width: u32,
+    pub height: u32,
+    /// folder the numbered pictures are written to, made if it's missing
+    pub directory: PathBuf,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            width: 1024,
+            height: 1024,
+            directory: PathBuf::from("gallery"),
+        }
+    }
+}
+
+/// Where the camera is and what it looks at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub eye: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+}
+
+/// Parse poses from their text form, `ex ey ez tx ty tz` on each line
+///
+/// Blank lines and lines starting with # are skipped.
+pub fn parse_poses(text: &str) -> anyhow::Result<Vec<CameraPose>> {
+    let mut poses = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let numbers = line.split_whitespace().map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("line {}: {}", line_number + 1, e))?;
+        match numbers[..] {
+            [ex, ey, ez, tx, ty, tz] => poses.push(CameraPose { eye: (ex, ey, ez).into(), target: (tx, ty, tz).into() }),
+            _ => anyhow::bail!("line {}: a pose needs 6 numbers", line_number + 1),
+        }
+    }
+    Ok(poses)
+}
+
+/// The text form of poses, read back by `parse_poses`
+pub fn poses_to_text(poses: &[CameraPose]) -> String {
+    poses.iter()
+        .map(|pose| format!("{} {} {} {} {} {}\n", pose.eye.x, pose.eye.y, pose.eye.z, pose.target.x, pose.target.y, pose.target.z))
+        .collect()
+}
+
+/// Name of the nth picture in a gallery, counting from 1 so they sort in order
+pub fn shot_file_name(index: usize) -> String {
+    format!("shot_{:04}.png", index + 1)
+}
+
+/// Camera bookmarks, F6 adds the current view and F12 renders them all to pictures
+#[derive(Default)]
+pub struct Gallery {
+    pub bookmarks: Vec<CameraPose>,
+    is_bookmark_pressed: bool,
+    is_render_pressed: bool,
+}
+
+impl Gallery {
+    pub fn new(bookmarks: Vec<CameraPose>) -> Self {
+        Self { bookmarks, ..Default::default() }
+    }
+
+    /// Call this for key presses
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(code @ (KeyCode::F6 | KeyCode::F12)),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                if *code == KeyCode::F6 {
+                    self.is_bookmark_pressed = true;
+                } else {
+                    self.is_render_pressed = true;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// True once after F6 was pressed, the caller then adds the camera with `bookmark`
+    pub fn take_bookmark(&mut self) -> bool {
+        std::mem::take(&mut self.is_bookmark_pressed)
+    }
+
+    /// True once after F12 was pressed, the caller then renders every bookmark
+    pub fn take_render(&mut self) -> bool {
+        std::mem::take(&mut self.is_render_pressed)
+    }
+
+    /// Add a pose and write every bookmark to `bookmarks.txt` in the background
+    pub fn bookmark(&mut self, pose: CameraPose) {
+        self.bookmarks.push(pose);
+        let task = super::world::resources::save_string(&BOOKMARKS_FILE, poses_to_text(&self.bookmarks));
+        crate::tasks::spawn(async move {
+            if let Ok(Err(e)) = task.await {
+                log::warn!("Couldn't save the bookmarks: {}", e);
+            }
+        });
+    }
+}
+
+/// Textures a gallery picture is drawn into, the same formats and sample count as the window's
+pub struct OffscreenTarget {
+    pub width: u32,
+    pub height: u32,
+    format: wgpu::TextureFormat,
+    /// the resolved picture, copied out to `readback`
+    color: wgpu::Texture,
+    pub color_view: wgpu::TextureView,
+    /// the scene in HDR before it is tone mapped into `color`, None when the scene is drawn in the window's format
+    pub hdr_view: Option<wgpu::TextureView>,
+    /// multisampled color resolved into `hdr_view` or `color`, None without MSAA
+    msaa_view: Option<wgpu::TextureView>,
+    pub depth_view: wgpu::TextureView,
+    readback: wgpu::Buffer,
+}
+
+impl OffscreenTarget {
+    /// Make the textures for pictures of one size
+    ///
+    /// Args:
+    ///     format: the window's format, the picture is saved from that
+    ///     scene_format: the format the pipelines draw in, the window's or HDR
+    ///     depth_format: format of the window's depth buffer
+    ///     sample_count: samples per pixel of the main pass
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, scene_format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32) -> anyhow::Result<Self> {
+        if width == 0 || height == 0 {
+            anyhow::bail!("pictures need a size, got {}x{}", width, height);
+        }
+        if !is_savable(format) {
+            anyhow::bail!("can't save pictures from a {:?} window", format);
+        }
+
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = |label, format, sample_count, usage| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        let view = |texture: &wgpu::Texture| texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let color = texture("gallery_color", format, 1, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC);
+        let hdr_view = (scene_format != format).then(|| view(&texture("gallery_hdr", scene_format, 1, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)));
+        let msaa_view = (sample_count > 1).then(|| view(&texture("gallery_msaa", scene_format, sample_count, wgpu::TextureUsages::RENDER_ATTACHMENT)));
+        let depth_view = view(&texture("gallery_depth", depth_format, sample_count, wgpu::TextureUsages::RENDER_ATTACHMENT));
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gallery Readback Buffer"),
+            size: (padded_row_bytes(width) * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self { width, height, format, color_view: view(&color), color, hdr_view, msaa_view, depth_view, readback })
+    }
+
+    /// The view the scene is drawn into and the one it is resolved into, None without MSAA
+    pub fn scene_views(&self) -> (&wgpu::TextureView, Option<&wgpu::TextureView>) {
+        let resolved = self.hdr_view.as_ref().unwrap_or(&self.color_view);
+        match &self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(resolved)),
+            None => (resolved, None),
+        }
+    }
+
+    /// Copy the finished picture into the readback buffer, call this after the scene was drawn
+    pub fn copy_out(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            self.color.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes(self.width)),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+        );
+    }
+
+    /// Wait for the copy to finish and read the picture back as RGBA
+    pub fn read(&self, device: &wgpu::Device) -> anyhow::Result<image::RgbaImage> {
+        let slice = self.readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let mut pixels = unpad_rows(&slice.get_mapped_range(), self.width, self.height);
+        self.readback.unmap();
+        if is_bgra(self.format) {
+            bgra_to_rgba(&mut pixels);
+        }
+        image::RgbaImage::from_raw(self.width, self.height, pixels).ok_or_else(|| anyhow::anyhow!("picture came back the wrong size"))
+    }
+}
+
+/// Whether pictures in a format can be read back and saved, only 8 bit color is
+pub fn is_savable(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Rgba8UnormSrgb | wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb | wgpu::TextureFormat::Bgra8Unorm)
+}
+
+/// Whether a savable format has its red and blue swapped
+pub fn is_bgra(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Bgra8UnormSrgb | wgpu::TextureFormat::Bgra8Unorm)
+}
+
+/// Bytes in one row of a picture once padded to the copy alignment
+pub fn padded_row_bytes(width: u32) -> u32 {
+    (width * 4).div_ceil(ROW_ALIGNMENT) * ROW_ALIGNMENT
+}
+
+/// Drop the padding at the end of every row
+pub fn unpad_rows(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row = (width * 4) as usize;
+    data.chunks(padded_row_bytes(width) as usize)
+        .take(height as usize)
+        .flat_map(|padded| &padded[..row])
+        .copied()
+        .collect()
+}
+
+/// Swap the red and blue channels of every pixel in place
+pub fn bgra_to_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_poses() {
+        let poses = parse_poses("# front\n0 1 2 0 0 0\n\n  -1.5 2 3 1 1 1  \n").unwrap();
+        assert_eq!(poses, vec![
+            CameraPose { eye: (0.0, 1.0, 2.0).into(), target: (0.0, 0.0, 0.0).into() },
+            CameraPose { eye: (-1.5, 2.0, 3.0).into(), target: (1.0, 1.0, 1.0).into() },
+        ]);
+        assert_eq!(parse_poses(&poses_to_text(&poses)).unwrap(), poses);
+
+        let error = parse_poses("0 1 2 0 0 0\n1 2 3\n").unwrap_err();
+        assert!(error.to_string().starts_with("line 2"));
+        assert!(parse_poses("0 1 2 0 0 x").is_err());
+    }
+
+    #[test]
+    fn test_shot_file_names_sort() {
+        assert_eq!(shot_file_name(0), "shot_0001.png");
+        let names = (0..12).map(shot_file_name).collect::<Vec<_>>();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+
+    #[test]
+    fn test_unpad_rows() {
+        // 3 pixels is 12 bytes, padded out to a full aligned row
+        let padded = padded_row_bytes(3) as usize;
+        assert_eq!(padded, ROW_ALIGNMENT as usize);
+        let mut data = vec![0; padded * 2];
+        data[..12].fill(1);
+        data[padded..padded + 12].fill(2);
+        let pixels = unpad_rows(&data, 3, 2);
+        assert_eq!(pixels.len(), 24);
+        assert!(pixels[..12].iter().all(|&b| b == 1) && pixels[12..].iter().all(|&b| b == 2));
+
+        let mut pixel = [1, 2, 3, 4];
+        bgra_to_rgba(&mut pixel);
+        assert_eq!(pixel, [3, 2, 1, 4]);
+    }
+}
@@ -0,0 +1,149 @@
+//! A plane that cuts away everything on one side of it, to look inside models
+use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, ModifiersState, PhysicalKey}};
+
+/// How far the plane moves per key press
+pub const SECTION_STEP: f32 = 0.25;
+
+/// Directions the plane can face, everything on the side it faces is cut away
+const SECTION_NORMALS: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, -1.0],
+];
+
+/// Controls for the section plane
+///
+/// X turns it on and off, Shift+X turns it to face the next axis, `[` and `]` slide it along
+/// its normal and `\` switches capping, which fills the cut open insides with a flat color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SectionPlane {
+    pub enabled: bool,
+    /// index into the directions the plane can face
+    normal_index: usize,
+    /// distance of the plane from the origin along its normal
+    pub offset: f32,
+    /// fill the inside of cut models so the cut reads as solid
+    pub caps: bool,
+    modifiers: ModifiersState,
+}
+
+impl Default for SectionPlane {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            normal_index: 0,
+            offset: 0.0,
+            caps: true,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+}
+
+impl SectionPlane {
+    /// Direction the cut away side lies in
+    pub fn normal(&self) -> cgmath::Vector3<f32> {
+        SECTION_NORMALS[self.normal_index].into()
+    }
+
+    /// The plane as the shader reads it, xyz is the normal and w the offset, all zero cuts nothing
+    pub fn to_raw(&self) -> [f32; 4] {
+        if !self.enabled {
+            return [0.0; 4];
+        }
+        let normal = self.normal();
+        [normal.x, normal.y, normal.z, self.offset]
+    }
+
+    /// Whether a point is cut away
+    #[allow(unused)]
+    pub fn is_clipped(&self, point: cgmath::Point3<f32>) -> bool {
+        use cgmath::{EuclideanSpace, InnerSpace};
+
+        self.enabled && point.to_vec().dot(self.normal()) > self.offset
+    }
+
+    /// Face the next direction, starting back at the origin
+    fn next_normal(&mut self) {
+        self.normal_index = (self.normal_index + 1) % SECTION_NORMALS.len();
+        self.offset = 0.0;
+    }
+
+    /// Call this for key presses so the plane can be moved
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(keycode),
+                    repeat,
+                    ..
+                },
+                ..
+            } => match keycode {
+                KeyCode::KeyX if !repeat => {
+                    if self.modifiers.shift_key() {
+                        self.next_normal();
+                        self.enabled = true;
+                    } else {
+                        self.enabled = !self.enabled;
+                    }
+                    true
+                }
+                KeyCode::Backslash if !repeat => {
+                    self.caps = !self.caps;
+                    true
+                }
+                // holding the key keeps sliding the plane
+                KeyCode::BracketLeft if self.enabled => {
+                    self.offset -= SECTION_STEP;
+                    true
+                }
+                KeyCode::BracketRight if self.enabled => {
+                    self.offset += SECTION_STEP;
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clip_side() {
+        let mut plane = SectionPlane::default();
+        assert!(!plane.is_clipped((5.0, 0.0, 0.0).into()));
+        assert_eq!(plane.to_raw(), [0.0; 4]);
+
+        plane.enabled = true;
+        plane.offset = 1.0;
+        assert!(plane.is_clipped((1.5, 0.0, 0.0).into()));
+        assert!(!plane.is_clipped((0.5, 9.0, 0.0).into()));
+        assert_eq!(plane.to_raw(), [1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_next_normal_cycles_both_sides() {
+        let mut plane = SectionPlane { offset: 2.0, ..SectionPlane::default() };
+        for _ in 0..3 {
+            plane.next_normal();
+        }
+        assert_eq!(plane.normal(), cgmath::Vector3::new(-1.0, 0.0, 0.0));
+        assert_eq!(plane.offset, 0.0);
+        for _ in 0..3 {
+            plane.next_normal();
+        }
+        assert_eq!(plane.normal(), cgmath::Vector3::unit_x());
+    }
+}
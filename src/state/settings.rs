@@ -0,0 +1,316 @@
+//! Graphics options, controls and where the window was, kept between runs in the user's config folder
+//!
+//! The file is only written when something in it changed, checked every `SAVE_CHECK_SECONDS` and
+//! once more when the program closes.
+use std::path::{Path, PathBuf};
+
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, keyboard::KeyCode};
+
+use super::{key_bindings::{self, Action}, render_settings::RenderSettings, tonemap::ToneMapOperator};
+
+/// Name of the settings file in the config folder
+pub const SETTINGS_FILE: &str = "settings.toml";
+
+/// Seconds between looking for changed settings
+pub const SAVE_CHECK_SECONDS: f32 = 1.0;
+
+/// The settings file in the platform's config folder, like `~/.config/rust3d` on Linux, None if there is no home folder
+pub fn settings_path() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "rust3d").map(|dirs| dirs.config_dir().join(SETTINGS_FILE))
+}
+
+/// Size and place of the window on the desktop, in physical pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowPlacement {
+    pub size: PhysicalSize<u32>,
+    /// top left corner of the window's frame, None where windows can't be placed, like Wayland
+    pub position: Option<PhysicalPosition<i32>>,
+}
+
+/// What is saved for the user
+///
+/// Stored as `section.name = value` lines. Only the render options that are plain values are kept,
+/// and only the key bindings the user moved, on top of `res/key_bindings.toml`.
+#[derive(Clone, Debug)]
+pub struct UserSettings {
+    pub render: RenderSettings,
+    /// degrees the camera turns for every pixel the mouse moves
+    pub mouse_sensitivity: f32,
+    pub window: Option<WindowPlacement>,
+    /// actions moved to other keys
+    pub bindings: Vec<(Action, KeyCode)>,
+}
+
+impl UserSettings {
+    /// Settings before anything was saved
+    ///
+    /// Args:
+    ///     render: render options from the app's configuration
+    ///     mouse_sensitivity: sensitivity from the app's configuration
+    pub fn new(render: RenderSettings, mouse_sensitivity: f32) -> Self {
+        Self { render, mouse_sensitivity, window: None, bindings: Vec::new() }
+    }
+
+    /// Read settings on top of these, lines that are left out keep their value
+    ///
+    /// Blank lines and lines starting with # are skipped.
+    pub fn parse(mut self, text: &str) -> anyhow::Result<Self> {
+        let (mut width, mut height, mut x, mut y) = (None, None, None, None);
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| anyhow::anyhow!("line {}: {}", line_number + 1, message);
+            let (name, value) = line.split_once('=').ok_or_else(|| error("expected name = value".to_string()))?;
+            let (name, value) = (name.trim(), value.trim().trim_matches('"'));
+            let render = &mut self.render;
+            let result = match name {
+                "render.hdr_output" => parse_into(value, &mut render.hdr_output),
+                "render.msaa_samples" => parse_into(value, &mut render.msaa_samples),
+                "render.fxaa" => parse_into(value, &mut render.fxaa),
+                "render.dof" => parse_into(value, &mut render.depth_of_field.enabled),
+                "render.dof_aperture" => parse_into(value, &mut render.depth_of_field.aperture),
+                "render.shadow_map_size" => parse_into(value, &mut render.shadow_map_size),
+                "render.sort_front_to_back" => parse_into(value, &mut render.sort_front_to_back),
+                "render.min_instance_pixels" => parse_into(value, &mut render.min_instance_pixels),
+                "render.instance_fade_pixels" => parse_into(value, &mut render.instance_fade_pixels),
+                "render.min_render_scale" => parse_into(value, &mut render.min_render_scale),
+                "render.target_frame_ms" => parse_into(value, &mut render.target_frame_ms),
+                "render.upscale_sharpness" => parse_into(value, &mut render.upscale_sharpness),
+                "render.fixed_aspect" => parse_some(value, &mut render.fixed_aspect),
+                "render.tone_map" => ToneMapOperator::parse(value)
+                    .map(|operator| render.tone_mapping.operator = operator)
+                    .ok_or_else(|| anyhow::anyhow!("unknown tone map {}", value)),
+                "render.exposure" => parse_into(value, &mut render.tone_mapping.exposure),
+                "mouse.sensitivity" => parse_into(value, &mut self.mouse_sensitivity),
+                "window.width" => parse_some(value, &mut width),
+                "window.height" => parse_some(value, &mut height),
+                "window.x" => parse_some(value, &mut x),
+                "window.y" => parse_some(value, &mut y),
+                _ => match name.strip_prefix("bind.") {
+                    Some(action) => {
+                        let action = Action::parse(action).ok_or_else(|| error(format!("unknown action {}", action)))?;
+                        let key = key_bindings::parse_key(value).ok_or_else(|| error(format!("unknown key {}", value)))?;
+                        self.bindings.retain(|(bound, _)| *bound != action);
+                        self.bindings.push((action, key));
+                        Ok(())
+                    }
+                    None => Err(anyhow::anyhow!("unknown setting {}", name)),
+                },
+            };
+            result.map_err(|e| error(e.to_string()))?;
+        }
+
+        if let (Some(width), Some(height)) = (width, height) {
+            let position = x.zip(y).map(|(x, y)| PhysicalPosition::new(x, y));
+            self.window = Some(WindowPlacement { size: PhysicalSize::new(width, height), position });
+        }
+        Ok(self)
+    }
+
+    /// The settings in the form `parse` reads
+    pub fn to_text(&self) -> String {
+        let render = &self.render;
+        let mut lines = vec![
+            "# Saved by rust3d when settings change, edit it while the program is closed".to_string(),
+            format!("render.hdr_output = {}", render.hdr_output),
+            format!("render.msaa_samples = {}", render.msaa_samples),
+            format!("render.fxaa = {}", render.fxaa),
+            format!("render.dof = {}", render.depth_of_field.enabled),
+            format!("render.dof_aperture = {}", render.depth_of_field.aperture),
+            format!("render.shadow_map_size = {}", render.shadow_map_size),
+            format!("render.sort_front_to_back = {}", render.sort_front_to_back),
+            format!("render.min_instance_pixels = {}", render.min_instance_pixels),
+            format!("render.instance_fade_pixels = {}", render.instance_fade_pixels),
+            format!("render.min_render_scale = {}", render.min_render_scale),
+            format!("render.target_frame_ms = {}", render.target_frame_ms),
+            format!("render.upscale_sharpness = {}", render.upscale_sharpness),
+            format!("render.tone_map = {}", render.tone_mapping.operator.name()),
+            format!("render.exposure = {}", render.tone_mapping.exposure),
+            format!("mouse.sensitivity = {}", self.mouse_sensitivity),
+        ];
+        if let Some(aspect) = render.fixed_aspect {
+            lines.push(format!("render.fixed_aspect = {}", aspect));
+        }
+        if let Some(window) = self.window {
+            lines.push(format!("window.width = {}", window.size.width));
+            lines.push(format!("window.height = {}", window.size.height));
+            if let Some(position) = window.position {
+                lines.push(format!("window.x = {}", position.x));
+                lines.push(format!("window.y = {}", position.y));
+            }
+        }
+        lines.extend(self.bindings.iter().map(|(action, key)| format!("bind.{} = \"{:?}\"", action.name(), key)));
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Parse a value into a setting, leaving it as it was if it can't be read
+fn parse_into<T: std::str::FromStr>(value: &str, setting: &mut T) -> anyhow::Result<()>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    *setting = value.parse()?;
+    Ok(())
+}
+
+/// Parse a value into a setting that has no value until it's read
+fn parse_some<T: std::str::FromStr>(value: &str, setting: &mut Option<T>) -> anyhow::Result<()>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    *setting = Some(value.parse()?);
+    Ok(())
+}
+
+/// Writes the settings file when the settings change
+pub struct SettingsSaver {
+    path: PathBuf,
+    /// the text last written or read, nothing is written while the settings still match it
+    saved: String,
+    since_check: f32,
+}
+
+impl SettingsSaver {
+    /// Read the settings file over the defaults, a missing or broken file gives the defaults
+    pub fn load(path: PathBuf, defaults: UserSettings) -> (UserSettings, Self) {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                log::info!("No settings loaded from {}: {}", path.display(), e);
+                String::new()
+            }
+        };
+        let settings = match defaults.clone().parse(&text) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Couldn't read {}, using the defaults: {}", path.display(), e);
+                defaults
+            }
+        };
+        (settings, Self { path, saved: text, since_check: 0.0 })
+    }
+
+    /// Whether it's time to look for changes, call with the seconds since the last call
+    pub fn is_due(&mut self, dt: f32) -> bool {
+        self.since_check += dt;
+        if self.since_check < SAVE_CHECK_SECONDS {
+            return false;
+        }
+        self.since_check = 0.0;
+        true
+    }
+
+    /// Write the settings in the background if they changed since they were last saved
+    pub fn save(&mut self, settings: &UserSettings) {
+        let Some(text) = self.changed_text(settings) else {
+            return;
+        };
+        let path = self.path.clone();
+        let task = crate::tasks::spawn_blocking(move || write_settings(&path, &text));
+        crate::tasks::spawn(async move {
+            if let Ok(Err(e)) = task.await {
+                log::warn!("Couldn't save the settings: {}", e);
+            }
+        });
+    }
+
+    /// Write the settings before returning if they changed, for when the program closes
+    pub fn save_now(&mut self, settings: &UserSettings) {
+        if let Some(text) = self.changed_text(settings) {
+            if let Err(e) = write_settings(&self.path, &text) {
+                log::warn!("Couldn't save the settings: {}", e);
+            }
+        }
+    }
+
+    /// The text to write, None if it's what was last saved
+    fn changed_text(&mut self, settings: &UserSettings) -> Option<String> {
+        let text = settings.to_text();
+        if text == self.saved {
+            return None;
+        }
+        self.saved.clone_from(&text);
+        Some(text)
+    }
+}
+
+/// Write the settings file, making the config folder if it isn't there yet
+fn write_settings(path: &Path, text: &str) -> std::io::Result<()> {
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    std::fs::write(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> UserSettings {
+        UserSettings::new(RenderSettings::default(), 0.1)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut settings = defaults();
+        settings.render.msaa_samples = 4;
+        settings.render.fxaa = true;
+        settings.render.depth_of_field.aperture = 2.5;
+        settings.render.tone_mapping.operator = ToneMapOperator::Reinhard;
+        settings.render.fixed_aspect = Some(1.5);
+        settings.mouse_sensitivity = 0.25;
+        settings.window = Some(WindowPlacement { size: PhysicalSize::new(1280, 720), position: Some(PhysicalPosition::new(-8, 40)) });
+        settings.bindings = vec![(Action::MoveForward, KeyCode::ArrowUp)];
+
+        let parsed = defaults().parse(&settings.to_text()).unwrap();
+        assert_eq!(parsed.render.msaa_samples, 4);
+        assert!(parsed.render.fxaa);
+        assert_eq!(parsed.render.depth_of_field.aperture, 2.5);
+        assert_eq!(parsed.render.tone_mapping.operator, ToneMapOperator::Reinhard);
+        assert_eq!(parsed.render.fixed_aspect, Some(1.5));
+        assert_eq!(parsed.mouse_sensitivity, 0.25);
+        assert_eq!(parsed.window, settings.window);
+        assert_eq!(parsed.bindings, settings.bindings);
+        assert_eq!(parsed.to_text(), settings.to_text());
+    }
+
+    #[test]
+    fn test_parse() {
+        // left out lines keep their values, a window needs both sides
+        let settings = defaults().parse("# mine\nrender.hdr_output = true\nwindow.width = 800\n").unwrap();
+        assert!(settings.render.hdr_output);
+        assert_eq!(settings.render.shadow_map_size, RenderSettings::default().shadow_map_size);
+        assert_eq!(settings.window, None);
+
+        assert!(defaults().parse("render.msaa_samples = many").unwrap_err().to_string().starts_with("line 1"));
+        assert!(defaults().parse("\nrender.fancy = true").unwrap_err().to_string().contains("unknown setting"));
+        assert!(defaults().parse("render.tone_map = filmic").is_err());
+        assert!(defaults().parse("bind.fly = KeyF").is_err());
+        assert!(defaults().parse("bind.spin = Hyper").is_err());
+
+        // moved keys go on top of the bindings file and are checked the same way
+        let settings = defaults().parse("bind.spin = \"KeyQ\"").unwrap();
+        let bindings = key_bindings::KeyBindings::default().with_overrides(&settings.bindings).unwrap();
+        assert_eq!(bindings.action(KeyCode::KeyQ), Some(Action::Spin));
+        assert_eq!(bindings.action(KeyCode::Digit1), None);
+        assert!(key_bindings::KeyBindings::default().with_overrides(&[(Action::Spin, KeyCode::KeyJ)]).is_err());
+    }
+
+    #[test]
+    fn test_saves_changes() {
+        let path = std::env::temp_dir().join(format!("rust3d_settings_{}", std::process::id())).join(SETTINGS_FILE);
+        let (mut settings, mut saver) = SettingsSaver::load(path.clone(), defaults());
+        assert!(!saver.is_due(SAVE_CHECK_SECONDS / 2.0));
+        assert!(saver.is_due(SAVE_CHECK_SECONDS / 2.0));
+
+        settings.render.sort_front_to_back = true;
+        saver.save_now(&settings);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(text.contains("render.sort_front_to_back = true"));
+        // nothing changed, so nothing is written again
+        assert_eq!(saver.changed_text(&settings), None);
+    }
+}
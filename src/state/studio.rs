@@ -0,0 +1,210 @@
+//! Product shot mode, a blurred sky behind the models and their shadows on an invisible floor
+use wgpu::util::DeviceExt;
+use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+use super::up_axis::UpAxis;
+
+/// How blurred the sky is in studio mode, see `Skybox::blur`
+pub const STUDIO_SKY_BLUR: f32 = 0.7;
+
+/// How dark a full shadow on the floor is drawn
+const CATCHER_STRENGTH: f32 = 0.6;
+
+/// How far past the outermost model the floor reaches, so the fade at its edge doesn't cut into shadows
+const CATCHER_MARGIN: f32 = 4.0;
+
+/// Where the shadow catcher lies
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct CatcherUniform {
+    /// xyz is the middle of the floor, w the radius
+    center: [f32; 4],
+    right: [f32; 4],
+    forward: [f32; 4],
+    /// x is the strength of the shadows
+    params: [f32; 4],
+}
+
+impl CatcherUniform {
+    /// A floor around a point, lying flat for the world's up axis
+    fn new(center: cgmath::Point3<f32>, radius: f32, up_axis: UpAxis) -> Self {
+        let right = up_axis.from_y_up(cgmath::Vector3::unit_x());
+        let forward = up_axis.from_y_up(cgmath::Vector3::unit_z());
+        Self {
+            center: [center.x, center.y, center.z, radius + CATCHER_MARGIN],
+            right: [right.x, right.y, right.z, 0.0],
+            forward: [forward.x, forward.y, forward.z, 0.0],
+            params: [CATCHER_STRENGTH, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+/// Studio mode, switched with I
+///
+/// While on, the sky is blurred and a see-through floor catches the sun's shadows under the models.
+pub struct Studio {
+    pub enabled: bool,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    up_axis: UpAxis,
+}
+
+impl Studio {
+    /// Make the shadow catcher pipeline
+    ///
+    /// Args:
+    ///     camera_bind_group_layout: layout of the camera the floor is seen through
+    ///     light_bind_group_layout: layout of the lights and shadow map the floor reads
+    ///     format: format of the surface the floor is drawn to
+    ///     depth_format: format of the depth buffer in the pass
+    ///     sample_count: samples per pixel of the pass
+    ///     up_axis: which way is up in the world, the floor lies flat for it
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        up_axis: UpAxis,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Catcher Buffer"),
+            contents: bytemuck::cast_slice(&[CatcherUniform::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("catcher_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("catcher_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../catcher.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Catcher Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, light_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Catcher Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            // hidden behind the models like a real floor, but it never hides anything itself
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self { enabled: false, pipeline, uniform_buffer, bind_group, up_axis }
+    }
+
+    /// Call this for key presses, I switches studio mode
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyI),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.enabled = !self.enabled;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move the floor under the models
+    ///
+    /// Args:
+    ///     center: middle of the floor, at the bottom of the lowest model
+    ///     radius: how far out the models reach from the center
+    pub fn update(&self, queue: &wgpu::Queue, center: cgmath::Point3<f32>, radius: f32) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[CatcherUniform::new(center, radius, self.up_axis)]));
+    }
+
+    /// Draw the floor's shadows, call this after the sky so they blend over it
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup, light_bind_group: &'a wgpu::BindGroup) {
+        if !self.enabled {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, light_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    #[test]
+    fn test_catcher_lies_flat() {
+        for up_axis in [UpAxis::Y, UpAxis::Z] {
+            let uniform = CatcherUniform::new((1.0, 2.0, 3.0).into(), 5.0, up_axis);
+            let along = |v: [f32; 4]| cgmath::Vector3::new(v[0], v[1], v[2]).dot(up_axis.up());
+            assert_eq!(along(uniform.right), 0.0);
+            assert_eq!(along(uniform.forward), 0.0);
+            assert_eq!(uniform.center, [1.0, 2.0, 3.0, 5.0 + CATCHER_MARGIN]);
+        }
+    }
+}
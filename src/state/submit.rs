@@ -0,0 +1,64 @@
+//! Sending a frame's GPU work to the queue in as few submissions as possible
+//!
+//! Work recorded away from the frame, like the mip levels of textures loaded on the task threads,
+//! is handed to `defer` instead of being submitted on its own. The next `submit` sends it in the
+//! same submission as the frame, ahead of the frame's commands so they see its results. Every
+//! submission is counted for the stats overlay.
+use std::sync::Mutex;
+
+/// Submissions and command buffers sent since the stats were last taken
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SubmitStats {
+    pub submissions: u32,
+    pub command_buffers: u32,
+}
+
+/// Command buffers waiting for the next submission on the device they were recorded for
+static DEFERRED: Mutex<Vec<(wgpu::Id<wgpu::Device>, wgpu::CommandBuffer)>> = Mutex::new(Vec::new());
+
+static STATS: Mutex<SubmitStats> = Mutex::new(SubmitStats { submissions: 0, command_buffers: 0 });
+
+/// Send recorded commands with the next submission on their device rather than on their own
+pub fn defer(device: &wgpu::Device, commands: wgpu::CommandBuffer) {
+    DEFERRED.lock().unwrap().push((device.global_id(), commands));
+}
+
+/// Submit the deferred commands for a device followed by these, all in one submission
+pub fn submit(device: &wgpu::Device, queue: &wgpu::Queue, commands: impl IntoIterator<Item = wgpu::CommandBuffer>) -> wgpu::SubmissionIndex {
+    let mut buffers = {
+        let mut deferred = DEFERRED.lock().unwrap();
+        let (mine, others): (Vec<_>, Vec<_>) = std::mem::take(&mut *deferred).into_iter().partition(|(id, _)| *id == device.global_id());
+        *deferred = others;
+        mine.into_iter().map(|(_, commands)| commands).collect::<Vec<_>>()
+    };
+    buffers.extend(commands);
+    record(buffers.len());
+    queue.submit(buffers)
+}
+
+/// The stats since the last call, counting starts again from nothing
+pub fn take_stats() -> SubmitStats {
+    std::mem::take(&mut *STATS.lock().unwrap())
+}
+
+/// Count one submission of some command buffers
+fn record(command_buffers: usize) {
+    let mut stats = STATS.lock().unwrap();
+    stats.submissions += 1;
+    stats.command_buffers += command_buffers as u32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        take_stats();
+        // the frame with a texture's mip levels deferred into it, then a gallery picture
+        record(2);
+        record(1);
+        assert_eq!(take_stats(), SubmitStats { submissions: 2, command_buffers: 3 });
+        assert_eq!(take_stats(), SubmitStats::default());
+    }
+}
@@ -0,0 +1,71 @@
+//! Framing and lighting for model preview pictures
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use super::{camera::{Camera, Projection}, light::{DirectionalLight, LightUniform}, up_axis::UpAxis, world::model::BoundingBox};
+
+/// Field of view of thumbnails in degrees, narrow so the model isn't stretched at the edges
+const THUMBNAIL_FOVY: f32 = 30.0;
+
+/// Room left around the model, as a share of the distance that would just fit it
+const THUMBNAIL_MARGIN: f32 = 1.1;
+
+/// Brightness everything in a thumbnail gets even facing away from the light
+const THUMBNAIL_AMBIENT: f32 = 0.35;
+
+/// The 3/4 view thumbnails look from, front right and a little above, with y up
+fn view_direction(up_axis: UpAxis) -> cgmath::Vector3<f32> {
+    up_axis.from_y_up(cgmath::Vector3::new(1.0, 0.7, 1.0)).normalize()
+}
+
+/// A square camera looking at the box from the front right and above, close enough that the whole box fits
+pub fn thumbnail_camera(bounds: &BoundingBox, up_axis: UpAxis) -> Camera {
+    let center = cgmath::Point3::from_vec((bounds.min + bounds.max) * 0.5);
+    // anything that fits the sphere around the box fits the box, whichever way it is turned
+    let radius = ((bounds.max - bounds.min).magnitude() * 0.5).max(0.01);
+    let distance = radius / (THUMBNAIL_FOVY.to_radians() / 2.0).sin() * THUMBNAIL_MARGIN;
+    Camera {
+        eye: center + view_direction(up_axis) * distance,
+        target: center,
+        up: up_axis.up(),
+        aspect: 1.0,
+        fovy: THUMBNAIL_FOVY,
+        znear: (distance - radius * 2.0).max(distance * 0.01),
+        zfar: distance + radius * 2.0,
+        projection: Projection::Perspective,
+    }
+}
+
+/// Plain white light from over the camera's shoulder, the same for every model and no matter how the world is lit
+pub fn thumbnail_lights(up_axis: UpAxis) -> LightUniform {
+    let over_shoulder = view_direction(up_axis) + up_axis.up() * 0.5;
+    let mut sun = DirectionalLight::new(-over_shoulder.normalize(), 1.0);
+    sun.intensity = 0.8;
+    LightUniform::new(&[], THUMBNAIL_AMBIENT, Some(&sun), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix4, Vector4};
+
+    use super::*;
+
+    #[test]
+    fn test_box_fits_in_view() {
+        for up_axis in [UpAxis::Y, UpAxis::Z] {
+            let bounds = BoundingBox { min: cgmath::Vector3::new(-1.0, 0.0, -3.0), max: cgmath::Vector3::new(2.0, 5.0, 1.0) };
+            let camera = thumbnail_camera(&bounds, up_axis);
+            // plain OpenGL clip space, where everything in view is inside -1 to 1 on every axis
+            let view_proj = cgmath::perspective(cgmath::Deg(camera.fovy), camera.aspect, camera.znear, camera.zfar)
+                * Matrix4::look_at_rh(camera.eye, camera.target, camera.up);
+            for corner in 0..8 {
+                let pick = |bit: i32, min: f32, max: f32| if corner & bit == 0 { min } else { max };
+                let point = Vector4::new(pick(1, bounds.min.x, bounds.max.x), pick(2, bounds.min.y, bounds.max.y), pick(4, bounds.min.z, bounds.max.z), 1.0);
+                let clip = view_proj * point;
+                let ndc = clip.truncate() / clip.w;
+                assert!(ndc.x.abs() < 1.0 && ndc.y.abs() < 1.0 && ndc.z.abs() < 1.0, "corner {} is cut off", corner);
+            }
+            // looking down on it from above
+            assert!((camera.eye - camera.target).dot(up_axis.up()) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,647 @@
+//! Play scripted sequences of camera moves, instance animation, material swaps and captions
+use std::fmt;
+
+use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+use crate::tasks::Task;
+
+use super::{easing::EasingCurve, quantize::{QuantizedTrack, UnpackedKey}, world::{instance::EntityId, resources::{load_string, save_string}}};
+
+/// The cutscene in the res folder, played with T
+pub const CUTSCENE_FILE: &str = "cutscene.txt";
+
+/// Folder in res holding named cutscenes, `<name>.txt` each, read the first time they are played
+pub const CUTSCENE_DIR: &str = "cutscenes";
+
+/// Named cutscenes kept loaded, the ones played longest ago are dropped past it
+pub const MAX_LOADED_CLIPS: usize = 4;
+
+/// What happens at a point on the timeline
+#[derive(Clone, Debug, PartialEq)]
+pub enum Key {
+    /// move the camera, interpolated between keys
+    Camera { eye: cgmath::Point3<f32>, target: cgmath::Point3<f32> },
+    /// move an instance of a model, interpolated between keys for the same instance
+    Entity { model: usize, id: EntityId, position: cgmath::Vector3<f32> },
+    /// switch the material of a model
+    Material { model: usize, material: usize },
+    /// show a caption, an empty caption clears it
+    Text(String),
+}
+
+/// Something keys animate smoothly, each with its own easing curve
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Track {
+    Camera,
+    Entity { model: usize, id: EntityId },
+}
+
+impl Track {
+    /// The track a key moves along and the numbers it moves it to, None for keys that switch at once
+    fn of(key: &Key) -> Option<(Track, Vec<f32>)> {
+        match key {
+            Key::Camera { eye, target } => Some((Track::Camera, vec![eye.x, eye.y, eye.z, target.x, target.y, target.z])),
+            Key::Entity { model, id, position } => Some((Track::Entity { model: *model, id: *id }, vec![position.x, position.y, position.z])),
+            _ => None,
+        }
+    }
+
+    /// Numbers in each key of the track
+    fn width(self) -> usize {
+        match self {
+            Track::Camera => 6,
+            Track::Entity { .. } => 3,
+        }
+    }
+
+    /// The key moving the track to some numbers, the other way around from `of`
+    fn key(self, values: &[f32]) -> Key {
+        match self {
+            Track::Camera => Key::Camera { eye: (values[0], values[1], values[2]).into(), target: (values[3], values[4], values[5]).into() },
+            Track::Entity { model, id } => Key::Entity { model, id, position: (values[0], values[1], values[2]).into() },
+        }
+    }
+}
+
+impl fmt::Display for Track {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Track::Camera => write!(f, "camera"),
+            Track::Entity { model, id } => write!(f, "entity {} {}", model, id.0),
+        }
+    }
+}
+
+/// A key at a time in seconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub key: Key,
+}
+
+/// Everything that should be applied at one moment of the timeline
+#[derive(Debug, Default, PartialEq)]
+pub struct TimelineFrame {
+    pub camera: Option<(cgmath::Point3<f32>, cgmath::Point3<f32>)>,
+    pub entities: Vec<(usize, EntityId, cgmath::Vector3<f32>)>,
+    pub materials: Vec<(usize, usize)>,
+    pub text: Option<String>,
+}
+
+/// How much memory the cutscenes' keys take
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnimationStats {
+    pub keys: usize,
+    /// bytes held by the keys, their captions and the tracks' curves
+    pub bytes: usize,
+    /// named cutscenes loaded from `CUTSCENE_DIR`
+    pub clips: usize,
+}
+
+impl std::ops::Add for AnimationStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self { keys: self.keys + other.keys, bytes: self.bytes + other.bytes, clips: self.clips + other.clips }
+    }
+}
+
+/// A sequence of keys sharing one clock
+///
+/// Stored as text with one key per line: `<time> camera ex ey ez tx ty tz`,
+/// `<time> entity <model> <id> x y z`, `<time> material <model> <material>` or `<time> text <caption>`.
+/// Moves between keys follow a track's curve, `ease camera x1 y1 x2 y2` or
+/// `ease entity <model> <id> x1 y1 x2 y2`, and are linear without one.
+/// Blank lines and lines starting with `#` are skipped.
+///
+/// Camera and instance keys are packed into a `QuantizedTrack` for each track when the timeline is made,
+/// so what is sampled and saved is within half a step of what was read.
+#[derive(Debug, Default, PartialEq)]
+pub struct Timeline {
+    /// keys that switch something at once, materials and captions, in time order
+    events: Vec<Keyframe>,
+    /// keys that move something, in the order their tracks first appear
+    tracks: Vec<(Track, QuantizedTrack)>,
+    /// curves of the tracks that aren't linear
+    curves: Vec<(Track, EasingCurve)>,
+}
+
+impl Timeline {
+    /// Create a timeline from keys in any order
+    pub fn new(mut keys: Vec<Keyframe>) -> Self {
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let mut events = Vec::new();
+        let mut track_keys: Vec<(Track, Vec<UnpackedKey>)> = Vec::new();
+        for keyframe in keys {
+            match Track::of(&keyframe.key) {
+                Some((track, values)) => match track_keys.iter_mut().find(|(other, _)| *other == track) {
+                    Some((_, keys)) => keys.push((keyframe.time, values)),
+                    None => track_keys.push((track, vec![(keyframe.time, values)])),
+                },
+                None => events.push(keyframe),
+            }
+        }
+        let tracks = track_keys.into_iter().map(|(track, keys)| (track, QuantizedTrack::new(track.width(), &keys))).collect();
+        Self { events, tracks, curves: Vec::new() }
+    }
+
+    /// Every key, with the tracks' unpacked, in time order
+    pub fn keyframes(&self) -> Vec<Keyframe> {
+        let mut keyframes = self.tracks.iter()
+            .flat_map(|(track, keys)| (0..keys.len()).map(|index| Keyframe { time: keys.time(index), key: track.key(&keys.values(index)) }))
+            .chain(self.events.iter().cloned())
+            .collect::<Vec<_>>();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        keyframes
+    }
+
+    /// Parse a timeline from its text form
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut keys = Vec::new();
+        let mut curves = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at_line = |e: anyhow::Error| anyhow::anyhow!("line {}: {}", line_number + 1, e);
+            match line.strip_prefix("ease ") {
+                Some(curve) => curves.push(Self::parse_curve(curve).map_err(at_line)?),
+                None => keys.push(Self::parse_line(line).map_err(at_line)?),
+            }
+        }
+        let mut timeline = Self::new(keys);
+        for (track, curve) in curves {
+            timeline.set_curve(track, curve);
+        }
+        Ok(timeline)
+    }
+
+    fn parse_curve(text: &str) -> anyhow::Result<(Track, EasingCurve)> {
+        let mut words = text.split_whitespace();
+        let kind = words.next().unwrap_or_default();
+        let numbers = words.map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()?;
+        let (track, handles) = match (kind, &numbers[..]) {
+            ("camera", handles) => (Track::Camera, handles),
+            ("entity", [model, id, handles @ ..]) => (Track::Entity { model: *model as usize, id: EntityId(*id as u32) }, handles),
+            ("entity", _) => anyhow::bail!("entity needs a model and an id"),
+            _ => anyhow::bail!("unknown track {}", kind),
+        };
+        match handles {
+            [x1, y1, x2, y2] => Ok((track, EasingCurve::new((*x1, *y1), (*x2, *y2)))),
+            _ => anyhow::bail!("a curve needs x1 y1 x2 y2"),
+        }
+    }
+
+    fn parse_line(line: &str) -> anyhow::Result<Keyframe> {
+        let mut parts = line.splitn(3, char::is_whitespace);
+        let time: f32 = parts.next().unwrap_or_default().parse()?;
+        let kind = parts.next().ok_or_else(|| anyhow::anyhow!("missing key type"))?;
+        let rest = parts.next().unwrap_or_default().trim();
+
+        let numbers = || rest.split_whitespace().map(str::parse::<f32>).collect::<Result<Vec<_>, _>>();
+        let key = match kind {
+            "camera" => match numbers()?[..] {
+                [ex, ey, ez, tx, ty, tz] => Key::Camera { eye: (ex, ey, ez).into(), target: (tx, ty, tz).into() },
+                _ => anyhow::bail!("camera needs 6 numbers"),
+            },
+            "entity" => match numbers()?[..] {
+                [model, id, x, y, z] => Key::Entity { model: model as usize, id: EntityId(id as u32), position: (x, y, z).into() },
+                _ => anyhow::bail!("entity needs a model, an id and 3 numbers"),
+            },
+            "material" => match numbers()?[..] {
+                [model, material] => Key::Material { model: model as usize, material: material as usize },
+                _ => anyhow::bail!("material needs a model and a material"),
+            },
+            "text" => Key::Text(rest.to_string()),
+            _ => anyhow::bail!("unknown key type {}", kind),
+        };
+
+        Ok(Keyframe { time, key })
+    }
+
+    /// Every track the keys animate, in the order they first appear
+    pub fn tracks(&self) -> Vec<Track> {
+        self.tracks.iter().map(|(track, _)| *track).collect()
+    }
+
+    /// The curve moves along a track follow
+    pub fn curve(&self, track: Track) -> EasingCurve {
+        self.curves.iter().find(|(other, _)| *other == track).map_or(EasingCurve::LINEAR, |(_, curve)| *curve)
+    }
+
+    /// Change the curve of a track
+    pub fn set_curve(&mut self, track: Track, curve: EasingCurve) {
+        self.curves.retain(|(other, _)| *other != track);
+        if curve != EasingCurve::LINEAR {
+            self.curves.push((track, curve));
+        }
+    }
+
+    /// Write the timeline to `cutscene.txt` in the background
+    pub fn save(&self) {
+        let text = format!("# Saved by the viewer, see timeline.rs for the format\n{}", self);
+        let task = save_string(&CUTSCENE_FILE, text);
+        crate::tasks::spawn(async move {
+            if let Ok(Err(e)) = task.await {
+                log::warn!("Couldn't save the cutscene: {}", e);
+            }
+        });
+    }
+
+    /// Memory held by the timeline, for the stats overlay
+    pub fn stats(&self) -> AnimationStats {
+        let captions = self.events.iter().map(|keyframe| match &keyframe.key {
+            Key::Text(text) => text.capacity(),
+            _ => 0,
+        }).sum::<usize>();
+        let bytes = self.events.capacity() * std::mem::size_of::<Keyframe>()
+            + self.tracks.capacity() * std::mem::size_of::<(Track, QuantizedTrack)>()
+            + self.tracks.iter().map(|(_, keys)| keys.bytes()).sum::<usize>()
+            + self.curves.capacity() * std::mem::size_of::<(Track, EasingCurve)>()
+            + captions;
+        let keys = self.events.len() + self.tracks.iter().map(|(_, keys)| keys.len()).sum::<usize>();
+        AnimationStats { keys, bytes, clips: 0 }
+    }
+
+    /// Time of the last key
+    pub fn duration(&self) -> f32 {
+        let events = self.events.last().map_or(0.0, |k| k.time);
+        self.tracks.iter().map(|(_, keys)| keys.duration()).fold(events, f32::max)
+    }
+
+    /// Work out what should be shown at a time in seconds
+    pub fn sample(&self, time: f32) -> TimelineFrame {
+        let mut frame = TimelineFrame::default();
+
+        for (track, keys) in &self.tracks {
+            let Some(values) = keys.sample(time, self.curve(*track)) else {
+                continue;
+            };
+            match track.key(&values) {
+                Key::Camera { eye, target } => frame.camera = Some((eye, target)),
+                Key::Entity { model, id, position } => frame.entities.push((model, id, position)),
+                _ => {}
+            }
+        }
+
+        for keyframe in self.events.iter().take_while(|keyframe| keyframe.time <= time) {
+            match &keyframe.key {
+                Key::Material { model, material } => {
+                    frame.materials.retain(|m| m.0 != *model);
+                    frame.materials.push((*model, *material));
+                }
+                Key::Text(text) => {
+                    frame.text = if text.is_empty() { None } else { Some(text.clone()) };
+                }
+                _ => {}
+            }
+        }
+
+        frame
+    }
+}
+
+/// Write the timeline back out in the same form `parse` reads
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (track, curve) in &self.curves {
+            writeln!(f, "ease {} {}", track, curve)?;
+        }
+        for keyframe in self.keyframes() {
+            write!(f, "{} ", keyframe.time)?;
+            match &keyframe.key {
+                Key::Camera { eye, target } => writeln!(f, "camera {} {} {} {} {} {}", eye.x, eye.y, eye.z, target.x, target.y, target.z)?,
+                Key::Entity { model, id, position } => writeln!(f, "entity {} {} {} {} {}", model, id.0, position.x, position.y, position.z)?,
+                Key::Material { model, material } => writeln!(f, "material {} {}", model, material)?,
+                Key::Text(text) => writeln!(f, "text {}", text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plays a timeline when its key is pressed, or a named one from `CUTSCENE_DIR`
+///
+/// Time only moves forward through `advance`, so hosts stepping frames by hand get the same playback.
+/// Named cutscenes are read in the background the first time they are played and start once they are in,
+/// only the `MAX_LOADED_CLIPS` played last stay in memory.
+pub struct CutscenePlayer {
+    /// the cutscene T plays and the curve editor shapes
+    pub timeline: Timeline,
+    /// named cutscenes that were loaded, the one played last at the end
+    clips: Vec<(String, Timeline)>,
+    /// a named cutscene being read, played once it's in
+    loading: Option<(String, Task<anyhow::Result<Timeline>>)>,
+    /// the named cutscene playing, None for `timeline`
+    playing: Option<String>,
+    /// seconds since playback started, None when stopped
+    elapsed: Option<f32>,
+}
+
+impl CutscenePlayer {
+    pub fn new(timeline: Timeline) -> Self {
+        Self { timeline, clips: Vec::new(), loading: None, playing: None, elapsed: None }
+    }
+
+    /// Start playing `timeline` from the beginning, or stop if anything is playing
+    pub fn toggle(&mut self) {
+        if self.is_playing() {
+            self.stop();
+        } else {
+            self.playing = None;
+            self.elapsed = Some(0.0);
+        }
+    }
+
+    /// Play a cutscene from `CUTSCENE_DIR` from the beginning, reading it first if it isn't loaded
+    ///
+    /// Returns whether it started now, false while it's being read
+    pub fn play(&mut self, name: &str) -> bool {
+        self.stop();
+        if let Some(index) = self.clips.iter().position(|(other, _)| other == name) {
+            let clip = self.clips.remove(index);
+            self.clips.push(clip);
+            self.playing = Some(name.to_string());
+            self.elapsed = Some(0.0);
+            return true;
+        }
+        let path = std::path::Path::new(CUTSCENE_DIR).join(format!("{}.txt", name));
+        let task = crate::tasks::spawn(async move { load_string(&path).await.and_then(|text| Timeline::parse(&text)) });
+        self.loading = Some((name.to_string(), task));
+        false
+    }
+
+    /// Stop playback and forget a cutscene that was being read to play
+    pub fn stop(&mut self) {
+        self.loading = None;
+        self.playing = None;
+        self.elapsed = None;
+    }
+
+    /// Whether a cutscene is playing or waiting to
+    pub fn is_playing(&self) -> bool {
+        self.elapsed.is_some() || self.loading.is_some()
+    }
+
+    /// Keep a cutscene that was read and start it, dropping the one played longest ago past `MAX_LOADED_CLIPS`
+    fn add_clip(&mut self, name: String, timeline: Timeline) {
+        self.clips.retain(|(other, _)| *other != name);
+        self.clips.push((name.clone(), timeline));
+        if self.clips.len() > MAX_LOADED_CLIPS {
+            self.clips.remove(0);
+        }
+        self.playing = Some(name);
+        self.elapsed = Some(0.0);
+    }
+
+    /// Move playback forward by some seconds, starting a named cutscene once it has been read
+    pub fn advance(&mut self, dt: f32) {
+        if let Some((name, task)) = &mut self.loading {
+            match task.try_take() {
+                Some(Ok(Ok(timeline))) => {
+                    let name = std::mem::take(name);
+                    self.loading = None;
+                    self.add_clip(name, timeline);
+                    return;
+                }
+                Some(Ok(Err(e)) | Err(e)) => {
+                    log::warn!("Couldn't load cutscene {}: {}", name, e);
+                    self.loading = None;
+                }
+                None => {}
+            }
+        }
+        if let Some(elapsed) = &mut self.elapsed {
+            *elapsed += dt;
+        }
+    }
+
+    /// Run the `cutscene` console command, returns what to print
+    ///
+    /// `play <name>` plays `<name>.txt` from `CUTSCENE_DIR`, `play` on its own the cutscene T plays, and `stop` stops playback.
+    pub fn run_command(&mut self, arguments: &[&str]) -> anyhow::Result<String> {
+        match arguments {
+            ["play"] => {
+                self.stop();
+                self.toggle();
+                Ok("Playing the cutscene".to_string())
+            }
+            ["play", name] => Ok(if self.play(name) { format!("Playing {}", name) } else { format!("Loading {}, it plays once it's in", name) }),
+            ["stop"] => {
+                let was_playing = self.is_playing();
+                self.stop();
+                Ok(if was_playing { "Stopped the cutscene" } else { "No cutscene is playing" }.to_string())
+            }
+            _ => anyhow::bail!("cutscene play [name] starts a cutscene, cutscene stop stops it"),
+        }
+    }
+
+    /// Memory held by `timeline` and the named cutscenes that are loaded
+    pub fn stats(&self) -> AnimationStats {
+        let clips = self.clips.iter().map(|(_, timeline)| timeline.stats()).fold(AnimationStats::default(), |total, stats| total + stats);
+        self.timeline.stats() + AnimationStats { clips: self.clips.len(), ..clips }
+    }
+
+    /// Call this for key presses, T starts or stops playback
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.toggle();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// What to show right now, or None when nothing is playing
+    pub fn current_frame(&mut self) -> Option<TimelineFrame> {
+        let elapsed = self.elapsed?;
+        let timeline = match &self.playing {
+            Some(name) => &self.clips.iter().find(|(other, _)| other == name)?.1,
+            None => &self.timeline,
+        };
+        if elapsed > timeline.duration() {
+            self.playing = None;
+            self.elapsed = None;
+            return None;
+        }
+        Some(timeline.sample(elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "
+        # fly in while the first cube rises
+        0 camera 0 1 10 0 0 0
+        2 camera 0 1 2 0 0 0
+        0 entity 0 3 0 0 0
+        2 entity 0 3 0 4 0
+        1 material 0 2
+        0 text Hello there
+        1.5 text
+        ease entity 0 3 0.42 0 0.58 1
+    ";
+
+    #[test]
+    fn test_parse_and_round_trip() {
+        let timeline = Timeline::parse(SCRIPT).unwrap();
+        assert_eq!(timeline.duration(), 2.0);
+        assert_eq!(Timeline::parse(&timeline.to_string()).unwrap(), timeline);
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(Timeline::default().stats(), AnimationStats::default());
+        let stats = Timeline::parse(SCRIPT).unwrap().stats();
+        assert_eq!(stats.keys, 7);
+        // at least the caption
+        assert!(stats.bytes >= "Hello there".len());
+
+        // long tracks are packed into far less than their keys would take
+        let keys = (0..1000).map(|i| Keyframe { time: i as f32, key: Key::Entity { model: 0, id: EntityId(1), position: (i as f32, 0.0, 1.0).into() } });
+        let stats = Timeline::new(keys.collect()).stats();
+        assert_eq!(stats.keys, 1000);
+        assert!(stats.bytes < 1000 * std::mem::size_of::<Keyframe>() / 3);
+    }
+
+    #[test]
+    fn test_quantized_keys() {
+        let keys = (0..200).map(|i| {
+            let time = i as f32 * 0.21;
+            Keyframe { time, key: Key::Camera { eye: (time.sin() * 30.0, 2.0, time).into(), target: (0.0, time * 0.1, 0.0).into() } }
+        }).collect::<Vec<_>>();
+        let timeline = Timeline::new(keys.clone());
+        let (time_error, value_error) = timeline.tracks[0].1.max_error();
+        for (original, unpacked) in keys.iter().zip(timeline.keyframes()) {
+            assert!((original.time - unpacked.time).abs() <= time_error * 1.01);
+            let (Key::Camera { eye, .. }, Key::Camera { eye: unpacked_eye, .. }) = (&original.key, &unpacked.key) else {
+                panic!("expected camera keys");
+            };
+            assert!((eye.x - unpacked_eye.x).abs() <= value_error * 1.01);
+        }
+        // saving and loading again doesn't drift
+        assert_eq!(Timeline::parse(&timeline.to_string()).unwrap(), timeline);
+    }
+
+    #[test]
+    fn test_parse_error_reports_line() {
+        let error = Timeline::parse("0 camera 1 2 3\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: camera needs 6 numbers");
+    }
+
+    #[test]
+    fn test_sample() {
+        let timeline = Timeline::parse(SCRIPT).unwrap();
+
+        let frame = timeline.sample(1.0);
+        assert_eq!(frame.camera, Some(((0.0, 1.0, 6.0).into(), (0.0, 0.0, 0.0).into())));
+        assert_eq!(frame.entities.len(), 1);
+        assert!((frame.entities[0].2.y - 2.0).abs() < 1e-3);
+        assert_eq!(frame.materials, vec![(0, 2)]);
+        assert_eq!(frame.text, Some("Hello there".to_string()));
+
+        // keys hold their last value and the caption was cleared
+        let frame = timeline.sample(3.0);
+        assert_eq!(frame.camera, Some(((0.0, 1.0, 2.0).into(), (0.0, 0.0, 0.0).into())));
+        assert_eq!(frame.text, None);
+    }
+
+    #[test]
+    fn test_curves() {
+        let mut timeline = Timeline::parse(SCRIPT).unwrap();
+        let cube = Track::Entity { model: 0, id: EntityId(3) };
+        assert_eq!(timeline.tracks(), vec![Track::Camera, cube]);
+        assert_eq!(timeline.curve(Track::Camera), EasingCurve::LINEAR);
+
+        // the cube eases in, so it's behind the linear camera early on
+        let frame = timeline.sample(0.4);
+        assert!(frame.entities[0].2.y < 0.4);
+
+        timeline.set_curve(cube, EasingCurve::LINEAR);
+        assert!((timeline.sample(0.4).entities[0].2.y - 0.8).abs() < 1e-4);
+        timeline.set_curve(Track::Camera, EasingCurve::new((0.0, 1.0), (0.0, 1.0)));
+        assert_eq!(Timeline::parse(&timeline.to_string()).unwrap(), timeline);
+
+        assert!(Timeline::parse("ease camera 1 2 3").unwrap_err().to_string().starts_with("line 1"));
+        assert!(Timeline::parse("ease entity 1 2 3 4").is_err());
+        assert!(Timeline::parse("ease sun 0 0 1 1").is_err());
+    }
+
+    #[test]
+    fn test_player_advances() {
+        let mut player = CutscenePlayer::new(Timeline::parse(SCRIPT).unwrap());
+        player.advance(1.0);
+        assert_eq!(player.current_frame(), None);
+
+        player.toggle();
+        player.advance(1.0);
+        assert_eq!(player.current_frame(), Some(player.timeline.sample(1.0)));
+
+        // playback stops by itself at the end
+        player.advance(1.5);
+        assert_eq!(player.current_frame(), None);
+        player.advance(1.0);
+        assert_eq!(player.current_frame(), None);
+    }
+
+    #[test]
+    fn test_named_clips() {
+        let mut player = CutscenePlayer::new(Timeline::default());
+
+        // a missing one is read in the background, then given up on
+        assert!(!player.play("missing"));
+        assert!(player.is_playing());
+        let (_, task) = player.loading.as_mut().unwrap();
+        assert!(futures::executor::block_on(task).unwrap().is_err());
+        player.advance(0.1);
+        assert!(!player.is_playing());
+
+        player.add_clip("fly".to_string(), Timeline::parse(SCRIPT).unwrap());
+        player.advance(1.0);
+        assert_eq!(player.current_frame(), Some(Timeline::parse(SCRIPT).unwrap().sample(1.0)));
+        player.stop();
+        assert_eq!(player.current_frame(), None);
+
+        // loaded ones start at once, and only the ones played last stay loaded
+        assert!(player.play("fly"));
+        for name in ["a", "b", "c", "d"] {
+            player.add_clip(name.to_string(), Timeline::default());
+        }
+        assert_eq!(player.stats().clips, MAX_LOADED_CLIPS);
+        assert!(!player.play("fly"));
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut player = CutscenePlayer::new(Timeline::parse(SCRIPT).unwrap());
+        assert_eq!(player.run_command(&["play"]).unwrap(), "Playing the cutscene");
+        player.advance(1.0);
+        assert_eq!(player.current_frame(), Some(player.timeline.sample(1.0)));
+        assert_eq!(player.run_command(&["stop"]).unwrap(), "Stopped the cutscene");
+        assert_eq!(player.current_frame(), None);
+        assert_eq!(player.run_command(&["stop"]).unwrap(), "No cutscene is playing");
+
+        player.add_clip("fly".to_string(), Timeline::parse(SCRIPT).unwrap());
+        player.stop();
+        assert_eq!(player.run_command(&["play", "fly"]).unwrap(), "Playing fly");
+        assert!(player.is_playing());
+        assert_eq!(player.run_command(&["play", "later"]).unwrap(), "Loading later, it plays once it's in");
+        assert_eq!(player.run_command(&["stop"]).unwrap(), "Stopped the cutscene");
+        assert!(!player.is_playing());
+
+        assert!(player.run_command(&[]).is_err());
+        assert!(player.run_command(&["rewind"]).is_err());
+    }
+}
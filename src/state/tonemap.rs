@@ -0,0 +1,396 @@
+//! The last step of drawing the scene, bringing the HDR frame into the window's format at the exposure picked for it
+//!
+//! The scene is drawn into an `HDR_FORMAT` frame instead of the window, measured by `AutoExposure` when
+//! that is on, and drawn over the window by `tonemap.wgsl` with a curve that rolls off light brighter than white.
+use wgpu::util::DeviceExt;
+
+use std::sync::Arc;
+
+use super::{bind_groups::BindGroupCache, camera::Viewport, exposure::{AutoExposure, ExposureState}};
+
+/// Format the scene is drawn in before tone mapping, room for light brighter than white
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The curve bringing HDR colors into the 0 to 1 the output shows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// clip everything brighter than white, how the scene looked before it was drawn in HDR
+    Linear,
+    /// x / (1 + x), keeps every color's hue but washes out bright ones
+    Reinhard,
+    /// the ACES filmic curve as fitted by Krzysztof Narkowicz, more contrast and bright colors that go towards white
+    #[default]
+    Aces,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 3] = [ToneMapOperator::Linear, ToneMapOperator::Reinhard, ToneMapOperator::Aces];
+
+    /// The name settings files use
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMapOperator::Linear => "linear",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::Aces => "aces",
+        }
+    }
+
+    /// The operator with a name from `name`, in any case
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|operator| operator.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// How the HDR frame is brought into the window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapSettings {
+    pub operator: ToneMapOperator,
+    /// what the scene is multiplied by before the curve, on top of what auto-exposure picks
+    pub exposure: f32,
+}
+
+impl Default for ToneMapSettings {
+    fn default() -> Self {
+        Self { operator: ToneMapOperator::default(), exposure: 1.0 }
+    }
+}
+
+/// Brightness in nits SDR white is shown at on an HDR monitor
+pub const PAPER_WHITE_NITS: f32 = 200.0;
+
+/// Nits an scRGB value of 1.0 stands for
+const SCRGB_WHITE_NITS: f32 = 80.0;
+
+/// What the window shows the tone mapped frame on
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMapOutput {
+    /// an SDR surface, the curve brings the frame into 0 to 1
+    #[default]
+    Sdr,
+    /// a linear scRGB surface on an HDR monitor, the frame keeps light brighter than white
+    /// and is scaled so SDR white is shown at `paper_white_nits`
+    ScRgb { paper_white_nits: f32 },
+}
+
+impl ToneMapOutput {
+    /// index the shader picks the output by
+    fn index(self) -> u32 {
+        match self {
+            ToneMapOutput::Sdr => 0,
+            ToneMapOutput::ScRgb { .. } => 1,
+        }
+    }
+
+    /// what a color of 1.0 out of the scene is multiplied by to be shown as white
+    fn paper_white(self) -> f32 {
+        match self {
+            ToneMapOutput::Sdr => 1.0,
+            ToneMapOutput::ScRgb { paper_white_nits } => paper_white_nits.max(0.0) / SCRGB_WHITE_NITS,
+        }
+    }
+}
+
+/// The settings as the shader reads them
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMapUniform {
+    /// index of the operator in `ToneMapOperator::ALL`
+    curve: u32,
+    exposure: f32,
+    /// 0 for SDR, 1 for scRGB
+    output: u32,
+    paper_white: f32,
+}
+
+impl ToneMapUniform {
+    fn new(settings: &ToneMapSettings, output: ToneMapOutput) -> Self {
+        Self {
+            curve: settings.operator as u32,
+            exposure: settings.exposure.max(0.0),
+            output: output.index(),
+            paper_white: output.paper_white(),
+        }
+    }
+}
+
+/// The window's HDR frame and what reads it
+pub struct HdrFrame {
+    pub view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+    tone_map_group: wgpu::BindGroup,
+    /// None without auto-exposure
+    exposure_group: Option<wgpu::BindGroup>,
+}
+
+/// Brings HDR frames into the output format, with the adapted exposure or as they are
+pub struct ToneMapper {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// None when the exposure is only the one in `settings`
+    auto_exposure: Option<AutoExposure>,
+    /// an exposure of 1 for pictures that aren't adapted
+    unit_exposure: wgpu::Buffer,
+    /// the curve and exposure from the settings, as a `ToneMapUniform`
+    settings_buffer: wgpu::Buffer,
+    /// no curve at an exposure of 1, for pictures drawn without post processing
+    plain_settings: wgpu::Buffer,
+    output: ToneMapOutput,
+}
+
+impl ToneMapper {
+    /// Set up the pass
+    ///
+    /// Args:
+    ///     output_format: format of the window, and of the pictures rendered off screen
+    ///     auto_exposure: adapts the exposure to the window's frames, None keeps it where `settings` puts it
+    ///     output: what `output_format` is shown on, see `RenderSettings::tone_map_output`
+    pub fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        auto_exposure: Option<AutoExposure>,
+        settings: ToneMapSettings,
+        output: ToneMapOutput,
+    ) -> Self {
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tone Map Settings Buffer"),
+            contents: bytemuck::cast_slice(&[ToneMapUniform::new(&settings, output)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let plain_settings = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Plain Tone Map Settings Buffer"),
+            contents: bytemuck::cast_slice(&[ToneMapUniform::new(&ToneMapSettings { operator: ToneMapOperator::Linear, exposure: 1.0 }, output)]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let unit_exposure = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Unit Exposure Buffer"),
+            contents: bytemuck::cast_slice(&[ExposureState::UNIT]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("tonemap_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../tonemap.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tonemap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tonemap Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self { pipeline, bind_group_layout, auto_exposure, unit_exposure, settings_buffer, plain_settings, output }
+    }
+
+    /// Change the curve or exposure, from the next frame drawn
+    pub fn set_settings(&self, queue: &wgpu::Queue, settings: ToneMapSettings) {
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[ToneMapUniform::new(&settings, self.output)]));
+    }
+
+    /// The window's HDR frame at a size, measured for auto-exposure when it is resolved
+    pub fn frame(&self, device: &wgpu::Device, width: u32, height: u32) -> HdrFrame {
+        let view = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hdr_frame"),
+            size: wgpu::Extent3d { width: width.max(1), height: height.max(1), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }).create_view(&wgpu::TextureViewDescriptor::default());
+        HdrFrame {
+            tone_map_group: self.bind(device, &view, true),
+            exposure_group: self.auto_exposure.as_ref().map(|auto_exposure| auto_exposure.bind(device, &view)),
+            view,
+            width: width.max(1),
+            height: height.max(1),
+        }
+    }
+
+    /// Bind an HDR frame for `draw`
+    ///
+    /// Args:
+    ///     is_post_processed: use the settings' curve and exposure and the one auto-exposure settled on,
+    ///         otherwise bring the frame in as it was drawn
+    pub fn bind(&self, device: &wgpu::Device, hdr_view: &wgpu::TextureView, is_post_processed: bool) -> wgpu::BindGroup {
+        self.bind_with(hdr_view, is_post_processed, |descriptor| device.create_bind_group(descriptor))
+    }
+
+    /// Bind an HDR frame for `draw` through a cache, for frames drawn into often like the gallery's
+    pub fn bind_cached(&self, bind_groups: &BindGroupCache, hdr_view: &wgpu::TextureView, is_post_processed: bool) -> Arc<wgpu::BindGroup> {
+        self.bind_with(hdr_view, is_post_processed, |descriptor| bind_groups.get(descriptor))
+    }
+
+    /// Describe the group binding an HDR frame and hand it to `create`
+    fn bind_with<T>(&self, hdr_view: &wgpu::TextureView, is_post_processed: bool, create: impl FnOnce(&wgpu::BindGroupDescriptor) -> T) -> T {
+        let exposure = match &self.auto_exposure {
+            Some(auto_exposure) if is_post_processed => auto_exposure.state_buffer(),
+            _ => &self.unit_exposure,
+        };
+        let settings = if is_post_processed { &self.settings_buffer } else { &self.plain_settings };
+        create(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: exposure.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: settings.as_entire_binding(),
+                },
+            ],
+            label: Some("tonemap_bind_group"),
+        })
+    }
+
+    /// Let time pass for the eye to adapt
+    ///
+    /// Args:
+    ///     dt: seconds since the last update
+    pub fn update(&mut self, dt: f32) {
+        if let Some(auto_exposure) = &mut self.auto_exposure {
+            auto_exposure.update(dt);
+        }
+    }
+
+    /// Record adapting the exposure to the window's frame, when auto-exposure is on, and drawing it over the window
+    ///
+    /// Args:
+    ///     viewport: where in the window the frame goes, the rest is cleared to black
+    pub fn resolve(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, frame: &HdrFrame, output: &wgpu::TextureView, viewport: Viewport) {
+        if let (Some(auto_exposure), Some(exposure_group)) = (&mut self.auto_exposure, &frame.exposure_group) {
+            auto_exposure.measure(queue, encoder, exposure_group, frame.width, frame.height);
+        }
+        self.draw(encoder, &frame.tone_map_group, output, Some(viewport));
+    }
+
+    /// Record the pass drawing a bound HDR frame over an output
+    ///
+    /// Args:
+    ///     viewport: where in the output the frame is stretched to, None covers all of it
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, bind_group: &wgpu::BindGroup, output: &wgpu::TextureView, viewport: Option<Viewport>) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        if let Some(viewport) = viewport {
+            pass.set_viewport(viewport.x as f32, viewport.y as f32, viewport.width as f32, viewport.height as f32, 0.0, 1.0);
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operator_names() {
+        for operator in ToneMapOperator::ALL {
+            assert_eq!(ToneMapOperator::parse(operator.name()), Some(operator));
+        }
+        assert_eq!(ToneMapOperator::parse("ACES"), Some(ToneMapOperator::Aces));
+        assert_eq!(ToneMapOperator::parse("filmic"), None);
+    }
+
+    #[test]
+    fn test_uniform_matches_shader() {
+        // the shader picks the curve by index, so the order of ALL is the order of its cases
+        for (index, operator) in ToneMapOperator::ALL.into_iter().enumerate() {
+            assert_eq!(ToneMapUniform::new(&ToneMapSettings { operator, exposure: 1.0 }, ToneMapOutput::Sdr).curve, index as u32);
+        }
+        assert_eq!(ToneMapUniform::new(&ToneMapSettings { exposure: -2.0, ..Default::default() }, ToneMapOutput::Sdr).exposure, 0.0);
+        assert_eq!(std::mem::size_of::<ToneMapUniform>(), 16);
+
+        let shader = include_str!("../tonemap.wgsl");
+        assert!(shader.contains("const REINHARD: u32 = 1u;") && shader.contains("const ACES: u32 = 2u;"));
+        assert!(shader.contains("const SCRGB: u32 = 1u;"));
+    }
+
+    #[test]
+    fn test_uniform_output() {
+        let settings = ToneMapSettings::default();
+        let sdr = ToneMapUniform::new(&settings, ToneMapOutput::Sdr);
+        assert_eq!((sdr.output, sdr.paper_white), (0, 1.0));
+
+        // scRGB's 1.0 is 80 nits, so white at 200 nits is 2.5 of it
+        let hdr = ToneMapUniform::new(&settings, ToneMapOutput::ScRgb { paper_white_nits: PAPER_WHITE_NITS });
+        assert_eq!((hdr.output, hdr.paper_white), (1, 2.5));
+        assert_eq!((hdr.curve, hdr.exposure), (sdr.curve, sdr.exposure));
+    }
+}
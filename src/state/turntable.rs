@@ -0,0 +1,183 @@
+//! A presentation mode where the camera slowly circles the scene on its own
+use cgmath::{EuclideanSpace, VectorSpace};
+use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+use super::up_axis::UpAxis;
+
+/// How the turntable camera moves
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TurntableSettings {
+    /// distance from the center to the camera, measured along the ground
+    pub radius: f32,
+    /// how far above the center the camera sits
+    pub height: f32,
+    /// seconds for one full turn, negative turns the other way
+    pub seconds_per_turn: f32,
+    /// seconds to glide between the user's camera and the orbit, 0 jumps straight there
+    pub ease_seconds: f32,
+}
+
+impl Default for TurntableSettings {
+    fn default() -> Self {
+        Self {
+            radius: 8.0,
+            height: 3.0,
+            seconds_per_turn: 20.0,
+            ease_seconds: 1.5,
+        }
+    }
+}
+
+/// A running presentation
+#[derive(Debug)]
+struct Presentation {
+    /// where the user's camera was when the presentation started, it goes back there after
+    home: (cgmath::Point3<f32>, cgmath::Point3<f32>),
+    center: cgmath::Point3<f32>,
+    /// radians around the up axis
+    angle: f32,
+    /// 0 at the user's camera, 1 on the orbit
+    blend: f32,
+    is_leaving: bool,
+}
+
+/// Orbits the camera around a point while switched on with O
+pub struct Turntable {
+    pub settings: TurntableSettings,
+    up_axis: UpAxis,
+    presentation: Option<Presentation>,
+    is_toggle_pressed: bool,
+}
+
+impl Turntable {
+    pub fn new(settings: TurntableSettings, up_axis: UpAxis) -> Self {
+        Self { settings, up_axis, presentation: None, is_toggle_pressed: false }
+    }
+
+    /// True while the turntable has the camera, including the glide in and out
+    pub fn is_presenting(&self) -> bool {
+        self.presentation.is_some()
+    }
+
+    /// Call this for key presses, O starts or stops the presentation
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyO),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.is_toggle_pressed = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// True once after O was pressed, the caller then picks a center and calls `toggle`
+    pub fn take_toggle(&mut self) -> bool {
+        std::mem::take(&mut self.is_toggle_pressed)
+    }
+
+    /// Start orbiting `center` from the current camera, or head back to where the presentation started
+    pub fn toggle(&mut self, eye: cgmath::Point3<f32>, target: cgmath::Point3<f32>, center: cgmath::Point3<f32>) {
+        match &mut self.presentation {
+            Some(presentation) => presentation.is_leaving = !presentation.is_leaving,
+            None => {
+                // start the orbit on the side the camera is already looking from
+                let offset = self.up_axis.to_y_up(eye - center);
+                let angle = if offset.x == 0.0 && offset.z == 0.0 { 0.0 } else { offset.z.atan2(offset.x) };
+                self.presentation = Some(Presentation { home: (eye, target), center, angle, blend: 0.0, is_leaving: false });
+            }
+        }
+    }
+
+    /// Move the presentation forward, returns the eye and target the camera should use, None when not presenting
+    ///
+    /// Args:
+    ///     dt: seconds since the last update
+    pub fn advance(&mut self, dt: f32) -> Option<(cgmath::Point3<f32>, cgmath::Point3<f32>)> {
+        let settings = self.settings;
+        let presentation = self.presentation.as_mut()?;
+
+        if settings.seconds_per_turn != 0.0 {
+            presentation.angle = (presentation.angle + std::f32::consts::TAU * dt / settings.seconds_per_turn) % std::f32::consts::TAU;
+        }
+        let step = if settings.ease_seconds > 0.0 { dt / settings.ease_seconds } else { 1.0 };
+        presentation.blend = (presentation.blend + if presentation.is_leaving { -step } else { step }).clamp(0.0, 1.0);
+
+        // back at the user's camera, hand it over exactly where it was
+        if presentation.is_leaving && presentation.blend == 0.0 {
+            let home = presentation.home;
+            self.presentation = None;
+            return Some(home);
+        }
+
+        let (angle, height) = (presentation.angle, settings.height);
+        let orbit = self.up_axis.from_y_up(cgmath::Vector3::new(angle.cos() * settings.radius, height, angle.sin() * settings.radius));
+        let orbit_eye = presentation.center + orbit;
+
+        // ease in and out so the camera doesn't jerk at either end
+        let t = presentation.blend * presentation.blend * (3.0 - 2.0 * presentation.blend);
+        let (home_eye, home_target) = presentation.home;
+        let eye = cgmath::Point3::from_vec(home_eye.to_vec().lerp(orbit_eye.to_vec(), t));
+        let target = cgmath::Point3::from_vec(home_target.to_vec().lerp(presentation.center.to_vec(), t));
+        Some((eye, target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    fn turntable(up_axis: UpAxis) -> Turntable {
+        Turntable::new(TurntableSettings { radius: 5.0, height: 2.0, seconds_per_turn: 10.0, ease_seconds: 1.0 }, up_axis)
+    }
+
+    /// distance from the up axis through the center
+    fn ground_distance(up_axis: UpAxis, point: cgmath::Point3<f32>, center: cgmath::Point3<f32>) -> f32 {
+        let offset = up_axis.to_y_up(point - center);
+        cgmath::Vector2::new(offset.x, offset.z).magnitude()
+    }
+
+    #[test]
+    fn test_orbit_after_easing_in() {
+        for up_axis in [UpAxis::Y, UpAxis::Z] {
+            let mut turntable = turntable(up_axis);
+            let center = cgmath::Point3::new(1.0, 1.0, 1.0);
+            assert_eq!(turntable.advance(0.1), None);
+
+            turntable.toggle(center + up_axis.ground(0.0, 20.0), center, center);
+            assert!(turntable.is_presenting());
+            let (eye, target) = turntable.advance(0.5).unwrap();
+            assert!(ground_distance(up_axis, eye, center) > 5.0);
+
+            let (eye, target_after) = turntable.advance(0.5).unwrap();
+            assert!((ground_distance(up_axis, eye, center) - 5.0).abs() < 1e-4);
+            assert!(((eye - center).dot(up_axis.up()) - 2.0).abs() < 1e-4);
+            assert_eq!(target, center);
+            assert_eq!(target_after, center);
+        }
+    }
+
+    #[test]
+    fn test_leaving_returns_home() {
+        let mut turntable = turntable(UpAxis::Y);
+        let home = (cgmath::Point3::new(0.0, 1.0, 10.0), cgmath::Point3::new(0.0, 1.0, 9.0));
+        turntable.toggle(home.0, home.1, cgmath::Point3::new(0.0, 0.0, 0.0));
+        turntable.advance(2.0);
+
+        turntable.toggle(home.0, home.1, cgmath::Point3::new(0.0, 0.0, 0.0));
+        let (eye, _) = turntable.advance(0.5).unwrap();
+        assert_ne!(eye, home.0);
+        assert_eq!(turntable.advance(0.5), Some(home));
+        assert!(!turntable.is_presenting());
+        assert_eq!(turntable.advance(0.5), None);
+    }
+}
@@ -0,0 +1,233 @@
+//! Numbers and colors shaders declare for tuning while the program runs, from the console or the debug panel
+//!
+//! A shader declares a tweak with a comment line, `// tweak float <name> <default> <min> <max>` or
+//! `// tweak color <name> <r> <g> <b>`, and reads it with `tweak_<name>()`, a function that is added
+//! to the shader before it is compiled. The values live in one uniform buffer of `MAX_TWEAKS` slots.
+use std::fmt::Write;
+
+/// Tweaks the uniform has room for
+pub const MAX_TWEAKS: usize = 16;
+
+/// What a tweak holds and how it can be changed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TweakKind {
+    /// a number kept between two limits
+    Float { min: f32, max: f32 },
+    /// a linear rgb color
+    Color,
+}
+
+/// One tunable value, a float uses the first component only
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tweak {
+    pub name: String,
+    pub kind: TweakKind,
+    pub default: [f32; 3],
+    pub value: [f32; 3],
+}
+
+impl Tweak {
+    /// The value in the form console commands print and take
+    fn value_text(&self) -> String {
+        match self.kind {
+            TweakKind::Float { .. } => format!("{}", self.value[0]),
+            TweakKind::Color => format!("{} {} {}", self.value[0], self.value[1], self.value[2]),
+        }
+    }
+
+    /// Set the value from the words after the name, a float is clamped to its limits
+    fn set_from_words(&mut self, words: &[&str]) -> anyhow::Result<()> {
+        let numbers = words.iter().map(|word| word.parse::<f32>()).collect::<Result<Vec<_>, _>>()?;
+        match (self.kind, &numbers[..]) {
+            (TweakKind::Float { min, max }, [value]) => self.value = [value.clamp(min, max), 0.0, 0.0],
+            (TweakKind::Float { .. }, _) => anyhow::bail!("{} takes one number", self.name),
+            (TweakKind::Color, [r, g, b]) => self.value = [*r, *g, *b],
+            (TweakKind::Color, _) => anyhow::bail!("{} takes three numbers, r g b", self.name),
+        }
+        Ok(())
+    }
+}
+
+/// Read the tweaks a shader declares, in the order of their slots in the uniform
+pub fn parse_tweaks(source: &str) -> anyhow::Result<Vec<Tweak>> {
+    let mut tweaks: Vec<Tweak> = Vec::new();
+    for (line_number, line) in source.lines().enumerate() {
+        let Some(declaration) = line.trim().strip_prefix("// tweak ") else {
+            continue;
+        };
+        let error = |message: String| anyhow::anyhow!("line {}: {}", line_number + 1, message);
+        let words = declaration.split_whitespace().collect::<Vec<_>>();
+        let [kind, name, numbers @ ..] = &words[..] else {
+            return Err(error("a tweak needs a kind and a name".to_string()));
+        };
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(error(format!("{} can't be used in a function name", name)));
+        }
+        if tweaks.iter().any(|tweak| tweak.name == *name) {
+            return Err(error(format!("{} is declared twice", name)));
+        }
+        let numbers = numbers.iter().map(|word| word.parse::<f32>()).collect::<Result<Vec<_>, _>>().map_err(|e| error(e.to_string()))?;
+        let (kind, default) = match (*kind, &numbers[..]) {
+            ("float", [default, min, max]) if min <= max => (TweakKind::Float { min: *min, max: *max }, [*default, 0.0, 0.0]),
+            ("float", _) => return Err(error("float needs a default, a minimum and a maximum".to_string())),
+            ("color", [r, g, b]) => (TweakKind::Color, [*r, *g, *b]),
+            ("color", _) => return Err(error("color needs r g b".to_string())),
+            _ => return Err(error(format!("unknown kind {}", kind))),
+        };
+        if tweaks.len() == MAX_TWEAKS {
+            return Err(error(format!("only {} tweaks fit in the uniform", MAX_TWEAKS)));
+        }
+        tweaks.push(Tweak { name: name.to_string(), kind, default, value: default });
+    }
+    Ok(tweaks)
+}
+
+/// WGSL functions reading every tweak out of the uniform, added to the shader that declared them
+pub fn tweak_functions(tweaks: &[Tweak]) -> String {
+    let mut text = String::new();
+    for (slot, tweak) in tweaks.iter().enumerate() {
+        let _ = match tweak.kind {
+            TweakKind::Float { .. } => writeln!(text, "fn tweak_{}() -> f32 {{ return tweaks.values[{}].x; }}", tweak.name, slot),
+            TweakKind::Color => writeln!(text, "fn tweak_{}() -> vec3<f32> {{ return tweaks.values[{}].rgb; }}", tweak.name, slot),
+        };
+    }
+    text
+}
+
+/// Tweak values laid out for the shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TweakUniform {
+    values: [[f32; 4]; MAX_TWEAKS],
+}
+
+/// The tweaks of the main shader and their current values
+#[derive(Clone, Debug, Default)]
+pub struct Tweaks {
+    tweaks: Vec<Tweak>,
+    /// set when a value changed since the uniform was last taken
+    is_changed: bool,
+}
+
+impl Tweaks {
+    pub fn new(tweaks: Vec<Tweak>) -> Self {
+        Self { tweaks, is_changed: false }
+    }
+
+    /// Every tweak, in slot order
+    pub fn tweaks(&self) -> &[Tweak] {
+        &self.tweaks
+    }
+
+    /// Take new values for tweaks edited elsewhere, like the debug panel, matched by name
+    pub fn set_values(&mut self, edited: &[Tweak]) {
+        for (tweak, edited) in self.tweaks.iter_mut().zip(edited) {
+            if tweak.name == edited.name && tweak.value != edited.value {
+                tweak.value = edited.value;
+                self.is_changed = true;
+            }
+        }
+    }
+
+    /// Values for the uniform buffer if any changed since it was last written
+    pub fn take_uniform(&mut self) -> Option<TweakUniform> {
+        std::mem::take(&mut self.is_changed).then(|| self.to_uniform())
+    }
+
+    pub fn to_uniform(&self) -> TweakUniform {
+        let mut uniform = TweakUniform { values: [[0.0; 4]; MAX_TWEAKS] };
+        for (slot, tweak) in self.tweaks.iter().enumerate() {
+            uniform.values[slot] = [tweak.value[0], tweak.value[1], tweak.value[2], 0.0];
+        }
+        uniform
+    }
+
+    /// Run a console command, returns what to print
+    ///
+    /// `tweaks` lists every tweak, `<name>` prints one, `<name> <value>...` sets it and
+    /// `<name> reset` puts its default back.
+    pub fn run_command(&mut self, command: &str) -> anyhow::Result<String> {
+        let words = command.split_whitespace().collect::<Vec<_>>();
+        let [name, arguments @ ..] = &words[..] else {
+            anyhow::bail!("type tweaks for a list of tweaks");
+        };
+        if *name == "tweaks" {
+            return Ok(self.tweaks.iter().map(|tweak| format!("{} = {}", tweak.name, tweak.value_text())).collect::<Vec<_>>().join("\n"));
+        }
+
+        let tweak = self.tweaks.iter_mut().find(|tweak| tweak.name == *name).ok_or_else(|| anyhow::anyhow!("unknown command {}", name))?;
+        match arguments {
+            [] => {}
+            ["reset"] => {
+                tweak.value = tweak.default;
+                self.is_changed = true;
+            }
+            _ => {
+                tweak.set_from_words(arguments)?;
+                self.is_changed = true;
+            }
+        }
+        Ok(format!("{} = {}", tweak.name, tweak.value_text()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+        // tweak float exposure 1 0 4
+        fn main() {}
+            // tweak color tint 1 0.5 0
+    ";
+
+    #[test]
+    fn test_parse_tweaks() {
+        let tweaks = parse_tweaks(SHADER).unwrap();
+        assert_eq!(tweaks.len(), 2);
+        assert_eq!(tweaks[0].kind, TweakKind::Float { min: 0.0, max: 4.0 });
+        assert_eq!(tweaks[1].value, [1.0, 0.5, 0.0]);
+
+        let functions = tweak_functions(&tweaks);
+        assert!(functions.contains("fn tweak_exposure() -> f32 { return tweaks.values[0].x; }"));
+        assert!(functions.contains("fn tweak_tint() -> vec3<f32> { return tweaks.values[1].rgb; }"));
+
+        assert!(parse_tweaks("// tweak float a 1 2").unwrap_err().to_string().starts_with("line 1"));
+        assert!(parse_tweaks("// tweak float a 1 2 0").is_err());
+        assert!(parse_tweaks("// tweak color a-b 1 1 1").is_err());
+        assert!(parse_tweaks("// tweak color a 1 1 1\n// tweak float a 1 0 2").unwrap_err().to_string().contains("twice"));
+        assert!(parse_tweaks("// tweak vector a 1 1 1").is_err());
+        let too_many = (0..=MAX_TWEAKS).map(|i| format!("// tweak float t{} 0 0 1\n", i)).collect::<String>();
+        assert!(parse_tweaks(&too_many).is_err());
+    }
+
+    #[test]
+    fn test_console_commands() {
+        let mut tweaks = Tweaks::new(parse_tweaks(SHADER).unwrap());
+        assert!(tweaks.take_uniform().is_none());
+
+        assert_eq!(tweaks.run_command("exposure 9").unwrap(), "exposure = 4");
+        assert_eq!(tweaks.run_command("tint 0.2 0.3 0.4").unwrap(), "tint = 0.2 0.3 0.4");
+        assert_eq!(tweaks.run_command("tweaks").unwrap(), "exposure = 4\ntint = 0.2 0.3 0.4");
+        let uniform = tweaks.take_uniform().unwrap();
+        assert_eq!(uniform.values[0], [4.0, 0.0, 0.0, 0.0]);
+        assert_eq!(uniform.values[1], [0.2, 0.3, 0.4, 0.0]);
+        assert!(tweaks.take_uniform().is_none());
+
+        assert_eq!(tweaks.run_command("exposure reset").unwrap(), "exposure = 1");
+        assert!(tweaks.run_command("tint 1").is_err());
+        assert!(tweaks.run_command("fly").unwrap_err().to_string().contains("unknown command"));
+        assert!(tweaks.run_command("  ").is_err());
+    }
+
+    #[test]
+    fn test_main_shader_tweaks() {
+        let source = concat!(include_str!("../shader.wgsl"), "\n", include_str!("../pbr.wgsl"));
+        let tweaks = parse_tweaks(source).unwrap();
+        // every declared tweak is read somewhere, and the uniform in the shader is the size of ours
+        for tweak in &tweaks {
+            assert!(source.contains(&format!("tweak_{}()", tweak.name)), "{} is never used", tweak.name);
+        }
+        assert!(source.contains(&format!("values: array<vec4<f32>, {}>", MAX_TWEAKS)));
+    }
+}
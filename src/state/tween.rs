@@ -0,0 +1,166 @@
+//! Values that move from one setting to another over some seconds, for transitions like camera flights and fades
+//!
+//! Tweens only move when they are advanced by the frame time, so they take the same time at any frame rate.
+use cgmath::{EuclideanSpace, VectorSpace};
+
+use super::easing::EasingCurve;
+
+/// How long transitions take, in seconds, 0 jumps straight to the end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationSettings {
+    /// the field of view changing when the lens zooms in or out
+    pub zoom_seconds: f32,
+    /// the camera flying to the help cube and back
+    pub help_flight_seconds: f32,
+    /// the help cube growing in and shrinking away
+    pub help_fade_seconds: f32,
+    /// depth of field pulling focus to newly picked cubes
+    pub focus_seconds: f32,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            zoom_seconds: 0.25,
+            help_flight_seconds: 0.6,
+            help_fade_seconds: 0.3,
+            focus_seconds: 0.4,
+        }
+    }
+}
+
+/// Something that can be blended between two values
+pub trait Lerp: Copy {
+    /// The value a share of the way to another, 0 is self and 1 is `to`
+    fn lerp(self, to: Self, amount: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, to: Self, amount: f32) -> Self {
+        self + (to - self) * amount
+    }
+}
+
+impl Lerp for cgmath::Point3<f32> {
+    fn lerp(self, to: Self, amount: f32) -> Self {
+        cgmath::Point3::from_vec(self.to_vec().lerp(to.to_vec(), amount))
+    }
+}
+
+/// Pairs blend each half on its own, like a camera's eye and target
+impl<A: Lerp, B: Lerp> Lerp for (A, B) {
+    fn lerp(self, to: Self, amount: f32) -> Self {
+        (self.0.lerp(to.0, amount), self.1.lerp(to.1, amount))
+    }
+}
+
+/// A value moving between two others, eased in and out unless given another curve
+///
+/// `on_complete` hands back a value once the tween finishes, for the owner to act on, like
+/// hiding something that just faded out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tween<T, A = ()> {
+    from: T,
+    to: T,
+    /// seconds the whole tween takes
+    duration: f32,
+    elapsed: f32,
+    easing: EasingCurve,
+    on_complete: Option<A>,
+}
+
+impl<T: Lerp> Tween<T> {
+    pub fn new(from: T, to: T, duration: f32) -> Self {
+        Self { from, to, duration: duration.max(0.0), elapsed: 0.0, easing: EasingCurve::EASE_IN_OUT, on_complete: None }
+    }
+}
+
+impl<T: Lerp, A> Tween<T, A> {
+    /// Move along another curve instead
+    pub fn with_easing(self, easing: EasingCurve) -> Self {
+        Self { easing, ..self }
+    }
+
+    /// Hand back a value from `take_completed` once the tween finishes
+    pub fn on_complete<B>(self, action: B) -> Tween<T, B> {
+        Tween { from: self.from, to: self.to, duration: self.duration, elapsed: self.elapsed, easing: self.easing, on_complete: Some(action) }
+    }
+
+    /// Move the tween forward, returns the value to use now
+    ///
+    /// Args:
+    ///     dt: seconds since the last update
+    pub fn advance(&mut self, dt: f32) -> T {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+        self.value()
+    }
+
+    /// The value at the current time
+    pub fn value(&self) -> T {
+        if self.duration <= 0.0 {
+            return self.to;
+        }
+        self.from.lerp(self.to, self.easing.evaluate(self.elapsed / self.duration))
+    }
+
+    /// The value the tween ends on
+    pub fn to(&self) -> T {
+        self.to
+    }
+
+    /// whether the value reached the end
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// The `on_complete` value, once, after the tween finished
+    pub fn take_completed(&mut self) -> Option<A> {
+        if self.is_finished() {
+            self.on_complete.take()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance() {
+        let mut tween = Tween::new(45.0, 15.0, 1.0).on_complete("done");
+        assert_eq!(tween.value(), 45.0);
+        // eased, so the first quarter of the time covers less than a quarter of the way
+        assert!(tween.clone().advance(0.25) > 37.5);
+        assert_eq!(tween.clone().with_easing(EasingCurve::LINEAR).advance(0.25), 37.5);
+        assert!((tween.advance(0.5) - 30.0).abs() < 1e-3);
+        assert!(!tween.is_finished());
+        assert_eq!(tween.take_completed(), None);
+
+        assert_eq!(tween.advance(5.0), 15.0);
+        assert!(tween.is_finished());
+        assert_eq!(tween.take_completed(), Some("done"));
+        assert_eq!(tween.take_completed(), None);
+    }
+
+    #[test]
+    fn test_frame_rate_independent() {
+        let eye = (cgmath::Point3::new(0.0, 0.0, 0.0), cgmath::Point3::new(0.0, 0.0, -1.0));
+        let help = (cgmath::Point3::new(0.0, 2.0, 4.0), cgmath::Point3::new(0.0, 0.0, 0.0));
+        let mut slow = Tween::new(eye, help, 1.0);
+        let mut fast = slow.clone();
+        for _ in 0..10 {
+            slow.advance(0.03);
+        }
+        for _ in 0..100 {
+            fast.advance(0.003);
+        }
+        assert!((slow.value().0.y - fast.value().0.y).abs() < 1e-4);
+
+        // no time at all lands on the end right away
+        let mut instant = Tween::new(1.0, 0.0, 0.0).on_complete(());
+        assert_eq!(instant.value(), 0.0);
+        assert_eq!(instant.take_completed(), Some(()));
+    }
+}
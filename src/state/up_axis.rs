@@ -0,0 +1,85 @@
+//! Which way is up in the world, for people coming from tools that use Z-up
+use cgmath::{Point3, Vector3};
+
+/// The axis pointing up in the world
+///
+/// Everything written for the original Y-up world (camera moves, the cube grid, imported .obj
+/// files which are Y-up by convention) is turned into the chosen axis with `from_y_up`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpAxis {
+    /// +y is up and -z is forward, like OpenGL and most game engines
+    #[default]
+    Y,
+    /// +z is up and +y is forward, like Blender and most CAD tools
+    Z,
+}
+
+impl UpAxis {
+    /// The direction of up
+    pub fn up(self) -> Vector3<f32> {
+        self.from_y_up(Vector3::unit_y())
+    }
+
+    /// Turn a direction or offset from a Y-up world into this one
+    ///
+    /// Z-up is a quarter turn around x, so y becomes z and z becomes -y
+    pub fn from_y_up(self, v: Vector3<f32>) -> Vector3<f32> {
+        match self {
+            UpAxis::Y => v,
+            UpAxis::Z => Vector3::new(v.x, -v.z, v.y),
+        }
+    }
+
+    /// Turn a direction from this world back into a Y-up one, undoing `from_y_up`
+    pub fn to_y_up(self, v: Vector3<f32>) -> Vector3<f32> {
+        match self {
+            UpAxis::Y => v,
+            UpAxis::Z => Vector3::new(v.x, v.z, -v.y),
+        }
+    }
+
+    /// Turn a position from a Y-up world into this one
+    pub fn point_from_y_up(self, p: Point3<f32>) -> Point3<f32> {
+        let v = self.from_y_up(Vector3::new(p.x, p.y, p.z));
+        Point3::new(v.x, v.y, v.z)
+    }
+
+    /// A point on the ground plane
+    ///
+    /// Args:
+    ///     x: distance to the right
+    ///     depth: distance towards the viewer, z in a Y-up world
+    pub fn ground(self, x: f32, depth: f32) -> Vector3<f32> {
+        self.from_y_up(Vector3::new(x, 0.0, depth))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_up() {
+        assert_eq!(UpAxis::Y.up(), Vector3::unit_y());
+        assert_eq!(UpAxis::Z.up(), Vector3::unit_z());
+    }
+
+    #[test]
+    fn test_from_y_up() {
+        use cgmath::InnerSpace;
+
+        // forward in a Y-up world is -z, in a Z-up world it's +y
+        assert_eq!(UpAxis::Z.from_y_up(Vector3::new(0.0, 0.0, -1.0)), Vector3::unit_y());
+        assert_eq!(UpAxis::Z.point_from_y_up(Point3::new(1.0, 2.0, 3.0)), Point3::new(1.0, -3.0, 2.0));
+        assert_eq!(UpAxis::Y.ground(1.0, 2.0), Vector3::new(1.0, 0.0, 2.0));
+        assert_eq!(UpAxis::Z.to_y_up(UpAxis::Z.from_y_up(Vector3::new(1.0, 2.0, 3.0))), Vector3::new(1.0, 2.0, 3.0));
+
+        // the ground is always flat against up
+        assert_eq!(UpAxis::Z.ground(1.0, 2.0).dot(UpAxis::Z.up()), 0.0);
+
+        // turning keeps the axes right handed
+        let x = UpAxis::Z.from_y_up(Vector3::unit_x());
+        let y = UpAxis::Z.from_y_up(Vector3::unit_y());
+        assert_eq!(x.cross(y), UpAxis::Z.from_y_up(Vector3::unit_z()));
+    }
+}
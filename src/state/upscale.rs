@@ -0,0 +1,358 @@
+//! Drawing the scene at a lower resolution when frames run slow, and stretching it back over the window
+//!
+//! `DynamicResolution` picks the share of the window's resolution to draw at from how long frames take,
+//! `Upscaler` owns the smaller targets and the pass that upscales and sharpens them, see `upscale.wgsl`.
+use wgpu::util::DeviceExt;
+
+/// How much the render scale changes in one step, coarse so the targets aren't made again every frame
+const SCALE_STEP: f32 = 0.05;
+
+/// Seconds of frames averaged before the scale may change again
+const ADJUST_SECONDS: f32 = 1.0;
+
+/// Frames averaging this much over the target lower the scale, frames under the target raise it
+const SLOW_MARGIN: f32 = 1.1;
+
+/// Picks the resolution the scene is drawn at to keep frames near a target time
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicResolution {
+    /// lowest share of the window's width and height drawn, 1 keeps the full resolution
+    min_scale: f32,
+    /// highest share drawn, 1 unless `cap` lowered it
+    max_scale: f32,
+    /// seconds a frame should take
+    target_seconds: f32,
+    scale: f32,
+    /// frame time added up since the scale last changed, and how many frames that was
+    elapsed: f32,
+    frames: u32,
+}
+
+impl DynamicResolution {
+    /// Args:
+    ///     min_scale: lowest share of the window's resolution to draw at, 1 turns dynamic resolution off
+    ///     target_ms: milliseconds a frame should take
+    pub fn new(min_scale: f32, target_ms: f32) -> Self {
+        Self { min_scale: min_scale.clamp(SCALE_STEP, 1.0), max_scale: 1.0, target_seconds: target_ms / 1000.0, scale: 1.0, elapsed: 0.0, frames: 0 }
+    }
+
+    /// Share of the window's width and height the scene is drawn at
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// whether the scene is drawn smaller than the window and needs upscaling
+    pub fn is_active(&self) -> bool {
+        self.scale < 1.0
+    }
+
+    /// Count a frame, stepping the scale down when frames run slow and back up when they are quick
+    ///
+    /// Args:
+    ///     dt: seconds the last frame took
+    pub fn update(&mut self, dt: f32) {
+        if self.min_scale >= 1.0 {
+            return;
+        }
+        self.elapsed += dt;
+        self.frames += 1;
+        if self.elapsed < ADJUST_SECONDS {
+            return;
+        }
+
+        let average = self.elapsed / self.frames as f32;
+        let step = if average > self.target_seconds * SLOW_MARGIN {
+            -1.0
+        } else if average < self.target_seconds {
+            1.0
+        } else {
+            0.0
+        };
+        // steps land on whole multiples so the sizes repeat and going back up reaches 1 exactly
+        self.scale = ((self.scale / SCALE_STEP).round() + step) * SCALE_STEP;
+        self.scale = self.scale.clamp(self.min_scale, self.max_scale);
+        self.elapsed = 0.0;
+        self.frames = 0;
+    }
+
+    /// Never draw at more than a share of the window's resolution, returns false if that's no lower than before
+    pub fn cap(&mut self, max_scale: f32) -> bool {
+        let max_scale = max_scale.clamp(SCALE_STEP, 1.0);
+        if max_scale >= self.max_scale {
+            return false;
+        }
+        self.max_scale = max_scale;
+        self.min_scale = self.min_scale.min(max_scale);
+        self.scale = self.scale.min(max_scale);
+        true
+    }
+
+    /// The size the scene is drawn at for a window size, at least one pixel each way
+    pub fn scaled_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |pixels: u32| ((pixels as f32 * self.scale).round() as u32).clamp(1, pixels.max(1));
+        (scale(width), scale(height))
+    }
+}
+
+/// What the upscale shader needs to know about the smaller frame
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscaleUniform {
+    texel: [f32; 2],
+    source_size: [f32; 2],
+    sharpness: f32,
+    _padding: f32,
+}
+
+/// The textures the scene is drawn into at the smaller size
+pub struct ScaledTarget {
+    pub width: u32,
+    pub height: u32,
+    /// the resolved frame the upscale pass reads
+    pub color_view: wgpu::TextureView,
+    /// multisampled color resolved into `color_view`, None without MSAA
+    pub msaa_view: Option<wgpu::TextureView>,
+    pub depth_view: wgpu::TextureView,
+    /// the depth alone, read back by passes after the scene like the light shafts
+    pub depth_sample_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Draws the scene's smaller frame over the whole window, resampled and sharpened
+pub struct Upscaler {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    /// 0 sharpens a little, 1 the most
+    sharpness: f32,
+    target: Option<ScaledTarget>,
+}
+
+impl Upscaler {
+    /// Set up the upscale pass
+    ///
+    /// Args:
+    ///     format: the window's format, the scene is drawn into the smaller frame with the same one
+    ///     sharpness: from 0, a little sharpening, to 1, the most
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sharpness: f32) -> Self {
+        let sharpness = sharpness.clamp(0.0, 1.0);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Upscale Buffer"),
+            contents: bytemuck::cast_slice(&[UpscaleUniform { texel: [1.0, 1.0], source_size: [1.0, 1.0], sharpness, _padding: 0.0 }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("upscale_bind_group_layout"),
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("upscale_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../upscale.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscale Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Upscale Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self { pipeline, bind_group_layout, sampler, uniform_buffer, sharpness, target: None }
+    }
+
+    /// Make the targets to draw the scene into at a size, again only when the size changed
+    ///
+    /// Args:
+    ///     format: the window's format
+    ///     depth_format: format of the window's depth buffer
+    ///     sample_count: samples per pixel of the main pass
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, (width, height): (u32, u32), format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32) {
+        if self.target.as_ref().is_some_and(|target| target.width == width && target.height == height) {
+            return;
+        }
+
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = |label, format, sample_count, usage| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        let view = |texture: &wgpu::Texture| texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let color_view = view(&texture("scaled_color", format, 1, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING));
+        let msaa_view = (sample_count > 1).then(|| view(&texture("scaled_msaa", format, sample_count, wgpu::TextureUsages::RENDER_ATTACHMENT)));
+        let depth = texture("scaled_depth", depth_format, sample_count, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING);
+        let depth_view = view(&depth);
+        let depth_sample_view = super::world::texture::depth_only_view(&depth);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("upscale_bind_group"),
+        });
+
+        let uniform = UpscaleUniform {
+            texel: [1.0 / width as f32, 1.0 / height as f32],
+            source_size: [width as f32, height as f32],
+            sharpness: self.sharpness,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        self.target = Some(ScaledTarget { width, height, color_view, msaa_view, depth_view, depth_sample_view, bind_group });
+    }
+
+    /// The targets made by the last `prepare`
+    pub fn target(&self) -> Option<&ScaledTarget> {
+        self.target.as_ref()
+    }
+
+    /// Let go of the smaller targets while the scene is drawn at full size
+    pub fn release(&mut self) {
+        self.target = None;
+    }
+
+    /// Record the pass drawing the smaller frame over a view the size of the window, does nothing before `prepare`
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &target.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dynamic_resolution() {
+        // a minimum of 1 never changes the scale however slow frames are
+        let mut off = DynamicResolution::new(1.0, 16.0);
+        for _ in 0..10 {
+            off.update(0.5);
+        }
+        assert!(!off.is_active());
+
+        let mut resolution = DynamicResolution::new(0.5, 16.0);
+        // slow frames lower the scale one step a second, down to the minimum
+        for _ in 0..8 {
+            resolution.update(0.25);
+        }
+        assert!((resolution.scale() - 0.9).abs() < 1e-6);
+        assert_eq!(resolution.scaled_size(1000, 500), (900, 450));
+        for _ in 0..100 {
+            resolution.update(0.25);
+        }
+        assert_eq!(resolution.scale(), 0.5);
+        assert!(resolution.is_active());
+
+        // quick frames bring it back up to exactly full size
+        for _ in 0..2000 {
+            resolution.update(0.01);
+        }
+        assert_eq!(resolution.scale(), 1.0);
+        assert_eq!(resolution.scaled_size(0, 3), (1, 3));
+
+        // a cap holds even with dynamic resolution off and frames to spare
+        assert!(off.cap(0.5));
+        assert!(!off.cap(0.75));
+        for _ in 0..100 {
+            off.update(0.01);
+        }
+        assert_eq!(off.scale(), 0.5);
+    }
+}
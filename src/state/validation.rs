@@ -0,0 +1,85 @@
+//! Collect wgpu validation errors so they can be shown in the window instead of only on stderr
+use std::sync::{Arc, Mutex};
+
+/// How many times the same message is logged before it is only counted
+pub const LOGGED_PER_MESSAGE: usize = 3;
+
+/// One unique error message and how often it happened
+pub struct CapturedError {
+    pub message: String,
+    pub count: usize,
+}
+
+/// Validation errors seen so far, grouped by message
+#[derive(Default)]
+pub struct ErrorCapture {
+    pub errors: Vec<CapturedError>,
+}
+
+impl ErrorCapture {
+    /// Record an error, returns true if it should still be logged
+    pub fn record(&mut self, message: &str) -> bool {
+        let count = match self.errors.iter_mut().find(|e| e.message == message) {
+            Some(error) => {
+                error.count += 1;
+                error.count
+            }
+            None => {
+                self.errors.push(CapturedError { message: message.to_string(), count: 1 });
+                1
+            }
+        };
+        count <= LOGGED_PER_MESSAGE
+    }
+
+    /// Short description for the window, None if nothing went wrong
+    pub fn summary(&self) -> Option<String> {
+        let first = self.errors.first()?;
+        let total: usize = self.errors.iter().map(|e| e.count).sum();
+        // only the first line of the first error fits in a title bar
+        let first_line = first.message.lines().find(|line| !line.trim().is_empty()).unwrap_or_default();
+        Some(format!("{} wgpu error(s), {} unique: {}", total, self.errors.len(), first_line.trim()))
+    }
+}
+
+/// Send every uncaptured device error into a shared capture
+pub fn install(device: &wgpu::Device) -> Arc<Mutex<ErrorCapture>> {
+    let capture = Arc::new(Mutex::new(ErrorCapture::default()));
+    let handler_capture = capture.clone();
+    device.on_uncaptured_error(Box::new(move |error| {
+        let message = error.to_string();
+        if handler_capture.lock().unwrap().record(&message) {
+            log::error!("wgpu error: {}", message);
+        }
+    }));
+    capture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_limits_logging() {
+        let mut capture = ErrorCapture::default();
+        for _ in 0..LOGGED_PER_MESSAGE {
+            assert!(capture.record("bad binding"));
+        }
+        assert!(!capture.record("bad binding"));
+        assert!(capture.record("bad shader"));
+
+        assert_eq!(capture.errors.len(), 2);
+        assert_eq!(capture.errors[0].count, LOGGED_PER_MESSAGE + 1);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut capture = ErrorCapture::default();
+        assert_eq!(capture.summary(), None);
+
+        capture.record("\nValidation Error\n\nCaused by: something");
+        capture.record("other");
+        capture.record("other");
+        assert_eq!(capture.summary(), Some("3 wgpu error(s), 2 unique: Validation Error".to_string()));
+    }
+}
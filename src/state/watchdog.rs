@@ -0,0 +1,140 @@
+//! Noticing when the GPU can't keep up at all, and turning quality down instead of stalling on
+//!
+//! A frame as long as `HANG_SECONDS`, or one whose surface timed out, is a strike. After `STRIKES`
+//! in a row the next `Fallback` is applied for the rest of the run and a notice goes on the overlay.
+
+/// Frames at least this long count as hung, it's the longest time `FrameClock` hands on
+pub const HANG_SECONDS: f32 = crate::time::MAX_FRAME_TIME.as_secs_f32();
+
+/// Hung frames in a row before quality is lowered, so a single stall like loading a file doesn't
+pub const STRIKES: u32 = 3;
+
+/// Seconds the notice stays on screen
+pub const NOTICE_SECONDS: f32 = 8.0;
+
+/// Largest share of the window's resolution drawn once `Fallback::Resolution` is applied
+pub const FALLBACK_SCALE: f32 = 0.5;
+
+/// One way of making frames cheaper
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fallback {
+    /// stop drawing the shadow map, and the light shafts that need it
+    Shadows,
+    /// go back to flat ambient light from the light probes
+    GlobalIllumination,
+    /// draw the scene at `FALLBACK_SCALE` at most
+    Resolution,
+}
+
+impl Fallback {
+    /// The order they are tried in, what costs the most for the least loss first
+    pub const ALL: [Self; 3] = [Self::Shadows, Self::GlobalIllumination, Self::Resolution];
+
+    /// Key of the notice in the locale files
+    pub fn message_key(self) -> &'static str {
+        match self {
+            Self::Shadows => "watchdog.shadows",
+            Self::GlobalIllumination => "watchdog.gi",
+            Self::Resolution => "watchdog.resolution",
+        }
+    }
+}
+
+/// Counts hung frames and picks the next fallback
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GpuWatchdog {
+    strikes: u32,
+    /// set by `timed_out` until the next frame is counted
+    timed_out: bool,
+    /// how many of `Fallback::ALL` were handed out
+    tried: usize,
+    /// the fallback last applied, and seconds its notice has left
+    notice: Option<(Fallback, f32)>,
+}
+
+impl GpuWatchdog {
+    /// Note that the surface timed out this frame, the frame counts as hung however long it took
+    pub fn timed_out(&mut self) {
+        self.timed_out = true;
+    }
+
+    /// Count a frame, returns true when quality should be lowered with `next_fallback`
+    ///
+    /// Args:
+    ///     dt: seconds the frame took
+    pub fn frame(&mut self, dt: f32) -> bool {
+        if let Some((_, seconds)) = &mut self.notice {
+            *seconds -= dt;
+            if *seconds <= 0.0 {
+                self.notice = None;
+            }
+        }
+        let is_hung = std::mem::take(&mut self.timed_out) || dt >= HANG_SECONDS;
+        if !is_hung {
+            self.strikes = 0;
+            return false;
+        }
+        self.strikes += 1;
+        if self.strikes < STRIKES {
+            return false;
+        }
+        self.strikes = 0;
+        self.tried < Fallback::ALL.len()
+    }
+
+    /// The next fallback to try, None once every one was handed out
+    ///
+    /// Call `applied` with it if it changed anything, or ask for the next one if it was already off.
+    pub fn next_fallback(&mut self) -> Option<Fallback> {
+        let fallback = Fallback::ALL.get(self.tried).copied();
+        self.tried += 1;
+        fallback
+    }
+
+    /// Show the notice for a fallback that was applied
+    pub fn applied(&mut self, fallback: Fallback) {
+        self.notice = Some((fallback, NOTICE_SECONDS));
+    }
+
+    /// The fallback whose notice is showing
+    pub fn notice(&self) -> Option<Fallback> {
+        self.notice.map(|(fallback, _)| fallback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strikes() {
+        let mut watchdog = GpuWatchdog::default();
+        // a quick frame between two stalls starts the count again
+        assert!(!watchdog.frame(HANG_SECONDS));
+        assert!(!watchdog.frame(HANG_SECONDS));
+        assert!(!watchdog.frame(0.016));
+        assert!(!watchdog.frame(HANG_SECONDS));
+        assert!(!watchdog.frame(HANG_SECONDS));
+        // a timeout counts even when the frame was quick
+        watchdog.timed_out();
+        assert!(watchdog.frame(0.016));
+        assert_eq!(watchdog.next_fallback(), Some(Fallback::Shadows));
+        watchdog.applied(Fallback::Shadows);
+        assert_eq!(watchdog.notice(), Some(Fallback::Shadows));
+        assert!(!watchdog.frame(NOTICE_SECONDS));
+        assert_eq!(watchdog.notice(), None);
+    }
+
+    #[test]
+    fn test_runs_out_of_fallbacks() {
+        let mut watchdog = GpuWatchdog::default();
+        let mut applied = Vec::new();
+        for _ in 0..STRIKES * 10 {
+            if watchdog.frame(1.0) {
+                applied.extend(watchdog.next_fallback());
+            }
+        }
+        assert_eq!(applied, Fallback::ALL);
+        assert_eq!(watchdog.next_fallback(), None);
+    }
+}
@@ -1,86 +1,252 @@
 /// Represents the overall world with all its models.
-use std::{future::Future, rc::Rc};
-use futures::{future::join_all, stream::FuturesUnordered, StreamExt};
+use std::collections::HashSet;
 
-use model::{DrawModel, Model};
-use resources::{load_model, load_string};
-use wgpu::BindGroupLayout;
+use behavior::{Behavior, BehaviorContext};
+use diagnostics::{Issue, ValidationReport};
+use environment::Environment;
+use ik::IkChain;
+use instance::{EntityId, IdAllocator, Instance, SPAWNED_IDS};
+use measure::Measurement;
+use model::{DrawModel, MaterialPipelines, Model, ViewContext};
+use resources::{load_string, save_string};
+use scene::{EntityProperties, Scene, SCENE_FILE};
+use selection::{EditCommand, Group, GroupTransform, Selection, SnapSettings};
+use undo::UndoStack;
+use stats::WorldStats;
 
-use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+use super::{accessibility::AccessibilitySettings, camera::Ray, data_binding::BoundProperty, debug_ui::MAX_GRID_SIZE, key_bindings::{Action, KeyBindings}, light::{self, DirectionalLight, Light}, easing::EasingCurve, timeline::TimelineFrame, tween::{AnimationSettings, Tween}, up_axis::UpAxis};
+
+use winit::{event::{ElementState, KeyEvent, MouseButton, WindowEvent}, keyboard::{KeyCode, ModifiersState, PhysicalKey}};
 use cgmath::prelude::*;
 
+pub mod archive;
+pub mod behavior;
+pub mod crowd;
+pub mod diagnostics;
+pub mod environment;
+pub mod ik;
+pub mod importer;
+pub mod manifest;
 pub mod instance;
+pub mod measure;
 pub mod model;
+pub mod normals;
+pub mod plot;
+pub mod ply;
 pub mod resources;
+pub mod scene;
+pub mod selection;
+pub mod skybox;
+pub mod stats;
+pub mod stl;
+pub mod stream;
+pub mod tangents;
 pub mod texture;
+pub mod undo;
+pub mod video;
+pub mod vox;
+pub mod weld;
+
+/// A model loaded into the world, for hosts to refer to it by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ModelHandle(pub(crate) usize);
+
+/// What a ray into the world hit first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickHit {
+    pub model: ModelHandle,
+    /// the instance of the model that was hit
+    pub id: EntityId,
+    /// where the ray meets the instance's bounding box
+    pub point: cgmath::Point3<f32>,
+    /// how far from the ray's origin the point is
+    pub distance: f32,
+}
+
+/// A single picked cube as the inspector shows it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inspection {
+    pub id: EntityId,
+    /// what can be edited, written back with `World::edit_properties`
+    pub properties: EntityProperties,
+    /// names of the materials the cubes can be drawn with
+    pub materials: Vec<String>,
+    /// names of the behaviors attached to the cube
+    pub behaviors: Vec<&'static str>,
+    /// how many cubes are in the cube's group, None if it isn't in one
+    pub group_size: Option<usize>,
+    /// set when this edit goes on from the last one, like the rest of a drag, so both share an undo step
+    pub is_continuing: bool,
+}
+
+/// how far in front of the camera models dropped onto nothing are placed
+const DROP_DISTANCE: f32 = 5.0;
+
+/// the help cube grows a little past full size and settles back, so it pops in
+const HELP_POP: EasingCurve = EasingCurve::new((0.34, 1.56), (0.64, 1.0));
+
+/// degrees the grid turns every second while spinning
+const SPIN_RATE: f32 = 30.0;
+
+/// how much the grid's scale changes every second while resizing
+const RESIZE_RATE: f32 = 0.6;
+
+/// Size of the crowd's agents next to the grid's cubes
+const CROWD_SCALE: f32 = 0.4;
 
 pub struct World {
     // model vector
     pub models: Vec<Model>, 
+    /// how many models came from `resources.txt`, the rest were added while running
+    resource_models: usize,
+    /// point lights shading the models, only the first `light::MAX_LIGHTS` are used
+    pub lights: Vec<Light>,
+    /// brightness everything gets even away from the lights
+    pub ambient: f32,
+    /// light from far away, the only one that casts shadows
+    pub sun: DirectionalLight,
+    /// the air between the models, thick air shows the sun's light shafts
+    pub environment: Environment,
     // model's cube's features
     is_increase_pressed: bool,
     is_decrease_pressed: bool,
     is_spin: bool,
-    is_spin_pressed: bool,
     is_color_change: bool,
     is_color_change_pressed: bool,
     cur_angle: f32,
     cur_scale: f32,
     is_resize: bool,
-    is_resize_pressed: bool,
     is_upscalling: bool,
     num_instances: u32,
+    /// agents walking around between the cubes
+    crowd: crowd::Crowd,
     // initialization flag
     initialized: bool,
     // world help controls
     is_help_pressed: bool,
     is_being_helped: bool,
-    is_help_just_pressed: bool
+    /// the help cube growing in or shrinking away, shrinking ends with showing the grid again
+    help_fade: Option<Tween<f32>>,
+    /// seconds the help cube takes to grow in and shrink away
+    pub animation: AnimationSettings,
+    // picking controls
+    is_delete_pressed: bool,
+    is_behavior_pressed: bool,
+    /// cubes that were deleted, so rebuilding the grid skips them
+    deleted: HashSet<EntityId>,
+    /// whether modal keys are held or toggled
+    accessibility: AccessibilitySettings,
+    /// which key does what
+    bindings: KeyBindings,
+    /// which way is up, the grid lies flat against it
+    up_axis: UpAxis,
+    // selection controls
+    is_select_pressed: bool,
+    modifiers: ModifiersState,
+    /// edits asked for since the last frame
+    pending_edits: Vec<EditCommand>,
+    /// cubes picked with the mouse
+    selection: Selection,
+    /// groups, moved and pasted cubes, saved to `scene_file`
+    scene: Scene,
+    /// file in `res` the scene is saved to, `scene.txt` until another is opened
+    scene_file: String,
+    /// set when the scene changed in a way only rebuilding the grid shows
+    is_scene_changed: bool,
+    /// scenes before each edit
+    history: UndoStack<Scene>,
+    /// copied cubes and whether they were a whole group
+    clipboard: Vec<Instance>,
+    is_clipboard_grouped: bool,
+    /// ids for pasted cubes
+    spawned_ids: IdAllocator,
+    /// how edits line up with the grid
+    pub snap: SnapSettings,
+    /// whether clicks add measure points instead of selecting
+    is_measuring: bool,
+    /// points clicked while measuring
+    measurement: Measurement,
+    /// chains bent every frame with the model their instances are in, numbered by their place here
+    ik_chains: Vec<(usize, IkChain)>,
 }
 
 impl World {
-    /// Create a new world by loading all possible models and textures
-    pub async fn new(device: &Rc<wgpu::Device>, queue: &wgpu::Queue, texture_bind_group_layout: &BindGroupLayout) -> World {
-        // we'll use a cube for now
-
-        // load all the models specified in "resources.txt"
-        let models = load_string(&"resources.txt")
-            .await
-            .unwrap()
-            .split("\n")
-            .map(|file_name| {
-                load_model(file_name.trim_right(), device.clone(), queue, texture_bind_group_layout)
-            }).collect::<FuturesUnordered<_>>()
-            .collect::<Vec<_>>()
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
+    /// Create a new world from the models specified in "resources.txt", see `loading::ModelLoader`
+    pub async fn new(mut models: Vec<Model>, accessibility: AccessibilitySettings, bindings: KeyBindings, up_axis: UpAxis, snap: SnapSettings) -> World {
+        // the help cube is hidden until go_to_help grows it in
+        models[1].visible = false;
+
+        // groups and moved cubes from last time, there's nothing to load on the first run
+        let mut scene = match load_string(&SCENE_FILE).await.and_then(|text| Scene::parse(&text)) {
+            Ok(scene) => scene,
+            Err(e) => {
+                log::info!("No scene loaded: {}", e);
+                Scene::default()
+            }
+        };
+
+        // undoing back to before the material was first changed goes back to the one the cubes came with
+        scene.material.get_or_insert(models[0].material());
+
+        let spawned_ids = spawned_ids(&scene);
 
         Self {
+            resource_models: models.len(),
             models,
+            // one light above and in front of the grid
+            lights: vec![Light::new(up_axis.point_from_y_up((2.0, 6.0, 4.0).into()), 40.0)],
+            ambient: light::DEFAULT_AMBIENT,
+            // coming down at an angle so the cubes throw shadows onto each other
+            sun: DirectionalLight::new(up_axis.from_y_up(cgmath::Vector3::new(-0.4, -1.0, -0.3)), 30.0),
+            environment: Environment::default(),
             is_decrease_pressed: false,
             is_increase_pressed: false,
             is_spin: false,
-            is_spin_pressed: false,
             is_color_change: false,
             is_color_change_pressed: false,        
             cur_angle: 0.0,
             cur_scale: 1.0,
             is_resize: false,
-            is_resize_pressed: false,
             is_upscalling: false,        
             num_instances: 5,
+            crowd: crowd::Crowd::default(),
             initialized: true,
             is_help_pressed: false,
             is_being_helped: true,
-            is_help_just_pressed: false
+            help_fade: None,
+            animation: AnimationSettings::default(),
+            is_delete_pressed: false,
+            is_behavior_pressed: false,
+            deleted: HashSet::new(),
+            accessibility,
+            bindings,
+            up_axis,
+            is_select_pressed: false,
+            modifiers: ModifiersState::empty(),
+            pending_edits: Vec::new(),
+            selection: Selection::default(),
+            scene,
+            scene_file: SCENE_FILE.to_string(),
+            is_scene_changed: false,
+            history: UndoStack::default(),
+            clipboard: Vec::new(),
+            is_clipboard_grouped: false,
+            spawned_ids,
+            snap,
+            is_measuring: false,
+            measurement: Measurement::default(),
+            ik_chains: Vec::new(),
         }
     }
 
     /// handle window events
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
-        match event {
+        let mut result = false;
+        for model in &mut self.models {
+            result = model.behavior_event(event) || result;
+        }
+
+        let handled = match event {
             WindowEvent::KeyboardInput {
                 event: KeyEvent {
                     state,
@@ -92,66 +258,115 @@ impl World {
             } => {
                 let is_pressed = *state == ElementState::Pressed;
                 match keycode {
-                    // increase number of cubes
-                    KeyCode::KeyJ => {
-                        self.is_increase_pressed = is_pressed;
-                        true
-                    }
-                    // decrease number of cubes
-                    KeyCode::KeyK => {
-                        self.is_decrease_pressed = is_pressed;
-                        true
-                    }
-                    // toggle is_spin if the key is pressed or released 
-                    KeyCode::Digit1 => {
-                        if is_pressed && !self.is_spin_pressed {
-                            self.is_spin = !self.is_spin;
-                            self.is_spin_pressed = true;
-                        } else if !is_pressed && self.is_spin_pressed{
-                            self.is_spin_pressed = false;
-                        }
-                        true
-                    }
-                    // toggle is_color_change if the key is pressed or released 
-                    KeyCode::Digit2 => {
+                    // copy, paste, duplicate, undo and redo
+                    KeyCode::KeyC | KeyCode::KeyV | KeyCode::KeyD | KeyCode::KeyZ | KeyCode::KeyY if self.modifiers.control_key() => {
                         if is_pressed {
-                            self.is_color_change = true;
-                        } else {
-                            self.is_color_change = false;
+                            self.pending_edits.push(match keycode {
+                                KeyCode::KeyC => EditCommand::Copy,
+                                KeyCode::KeyV => EditCommand::Paste,
+                                KeyCode::KeyD => EditCommand::Duplicate,
+                                KeyCode::KeyZ => EditCommand::Undo,
+                                _ => EditCommand::Redo,
+                            });
                         }
                         true
                     }
-                    // toggle resize if the key is pressed or released
-                    KeyCode::Digit3 => {
-                        if is_pressed && !self.is_resize_pressed {
-                            self.is_resize = !self.is_resize;
-                            self.is_resize_pressed = true;
-                        } else if !is_pressed && self.is_resize_pressed{
-                            self.is_resize_pressed = false;
+                    _ => match self.bindings.action(*keycode) {
+                        // increase number of cubes
+                        Some(Action::GrowGrid) => {
+                            self.is_increase_pressed = is_pressed;
+                            true
                         }
-                        true
-                    }
-                    // toggle is_help_pressed if the key is pressed
-                    KeyCode::KeyH => {
-                        self.is_help_pressed = is_pressed;
-                        if is_pressed && !self.is_being_helped {
-                            self.is_being_helped = true;
+                        // decrease number of cubes
+                        Some(Action::ShrinkGrid) => {
+                            self.is_decrease_pressed = is_pressed;
+                            true
                         }
-                        else if is_pressed && self.is_being_helped {
-                            self.is_help_just_pressed = true;
-                            self.is_being_helped = false;
+                        // toggle is_spin if the key is pressed or released 
+                        Some(Action::Spin) => {
+                            self.accessibility.modal_controls.switch(&mut self.is_spin, is_pressed);
+                            true
                         }
-                        true
-                    }
-                    _ => false,
+                        // toggle is_color_change if the key is pressed or released 
+                        Some(Action::ChangeColor) => {
+                            self.is_color_change = is_pressed;
+                            true
+                        }
+                        // toggle resize if the key is pressed or released
+                        Some(Action::Resize) => {
+                            self.accessibility.modal_controls.switch(&mut self.is_resize, is_pressed);
+                            true
+                        }
+                        // toggle is_help_pressed if the key is pressed
+                        Some(Action::Help) => {
+                            self.is_help_pressed = is_pressed;
+                            self.accessibility.modal_controls.switch(&mut self.is_being_helped, is_pressed);
+                            true
+                        }
+                        // delete the cube being looked at
+                        Some(Action::Delete) => {
+                            if is_pressed {
+                                self.is_delete_pressed = true;
+                            }
+                            true
+                        }
+                        // make the cube being looked at spin and bob
+                        Some(Action::AttachBehavior) => {
+                            if is_pressed {
+                                self.is_behavior_pressed = true;
+                            }
+                            true
+                        }
+                        // switch between selecting and measuring, leaving measure mode forgets the points
+                        Some(Action::Measure) => {
+                            if is_pressed {
+                                self.is_measuring = !self.is_measuring;
+                                self.measurement.clear();
+                            }
+                            true
+                        }
+                        // group the selected cubes, shift ungroups them
+                        Some(Action::Group) => {
+                            if is_pressed {
+                                self.pending_edits.push(if self.modifiers.shift_key() { EditCommand::Ungroup } else { EditCommand::Group });
+                            }
+                            true
+                        }
+                        // move, turn and scale the selected cubes
+                        _ => match (is_pressed, self.selection_transform(*keycode)) {
+                            (true, Some(transform)) => {
+                                let snap = self.snap.is_active(self.modifiers.alt_key());
+                                self.pending_edits.push(EditCommand::Transform { transform, snap });
+                                true
+                            }
+                            (_, transform) => transform.is_some(),
+                        },
+                    },
                 }
             }
+            // shift-click adds to the selection instead of replacing it, ctrl is for editing shortcuts
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.is_select_pressed = true;
+                false
+            }
             _ => false,
-        }
+        };
+        handled || result
     }
 
     /// update the objects in the world based off the key presses
-    pub fn update_world(&mut self) {
+    ///
+    /// Args:
+    ///     dt: seconds since the last update, spinning and resizing advance by their rate times this
+    pub fn update_world(&mut self, dt: f32) {
         // if not in the help menu
         if !self.is_being_helped {
             let mut change_occurred = false;
@@ -159,6 +374,10 @@ impl World {
                 change_occurred = true;
                 self.initialized = false;
             }
+            if self.is_scene_changed {
+                change_occurred = true;
+                self.is_scene_changed = false;
+            }
             if self.is_increase_pressed {
                 self.num_instances += 1;
                 change_occurred = true;
@@ -168,20 +387,22 @@ impl World {
                 change_occurred = !change_occurred;
             }
             
+            // the crowd's agents move every frame, so the instances are made again
+            if !self.crowd.is_empty() {
+                change_occurred = true;
+                self.crowd.step(dt);
+            }
+
             if self.is_spin {
                 change_occurred = true;
-                // as the number of instances it takes longer to spin all of them, 
-                // so we increase the change according to the number of instances
-                self.cur_angle = self.cur_angle + 0.5 + (self.num_instances/200) as f32 + (self.num_instances/1000) as f32;
-                if self.cur_angle >= 360.0 {
-                    self.cur_angle-=360.0;
-                }
+                self.cur_angle = (self.cur_angle + SPIN_RATE * dt) % 360.0;
             }
 
             if self.is_color_change && !self.is_color_change_pressed{
                 change_occurred = true;
                 self.is_color_change_pressed = true;
                 self.models[0].change_material();
+                self.scene.material = Some(self.models[0].material());
             } else if !self.is_color_change {
                 self.is_color_change_pressed = false;
             }
@@ -191,13 +412,13 @@ impl World {
 
                 // increase or decreace the scale depending if the instances are getting bigger or smaller
                 if self.is_upscalling {
-                    self.cur_scale = self.cur_scale + 0.01;
+                    self.cur_scale += RESIZE_RATE * dt;
                     // if we reached the max size, start to decreace the scale
                     if self.cur_scale >= 1.0 {
                         self.is_upscalling = false;
                     }
                 } else {
-                    self.cur_scale = self.cur_scale - 0.01;
+                    self.cur_scale -= RESIZE_RATE * dt;
                     // if we reached the min size, start to increase the scale
                     if self.cur_scale <= 0.5 {
                         self.is_upscalling = true;
@@ -215,70 +436,708 @@ impl World {
                 let mut angle = self.cur_angle;
                 let scale: f32 = self.cur_scale;
 
+                let deleted = &self.deleted;
+                let up_axis = self.up_axis;
+
                 // we are making a n*n grid of cubes that are rotated at weird angles
+                // each cube's id comes from its cell so it keeps the same id when the grid is rebuilt
                 let instances = (0..num_instances).flat_map(|z| {
                     (0..num_instances).map(move |x| {
+                        let id = EntityId(z << 16 | x);
                         let x = SPACE_BETWEEN * (x as f32 - num_instances as f32 / 2.0);
                         let z = SPACE_BETWEEN * (z as f32 - num_instances as f32 / 2.0);
 
-                        let position = cgmath::Vector3 { x, y: 0.0, z };
+                        let position = up_axis.ground(x, z);
                         
                         if position.is_zero() {
                             angle+= 45.0;
                         }
-                        let rotation = cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(angle));
+                        let rotation = cgmath::Quaternion::from_axis_angle(up_axis.from_y_up(cgmath::Vector3::unit_z()), cgmath::Deg(angle));
 
-                        instance::Instance {
+                        (id, instance::Instance {
                             position, rotation, scale
-                        }
+                        })
                     })
-                }).collect::<Vec<_>>();
-                self.models[0].set_instances(instances);
+                }).chain(self.crowd.instances(up_axis, scale * CROWD_SCALE))
+                .filter(|(id, _)| !deleted.contains(id))
+                // cubes moved by hand stay where they were put
+                .map(|(id, instance)| match self.scene.placed.iter().find(|(placed, _)| *placed == id) {
+                    Some((_, placed)) => (id, placed.clone()),
+                    None => (id, instance),
+                })
+                // and pasted cubes are added on top of the grid
+                .chain(self.scene.spawned().cloned())
+                .collect::<Vec<_>>();
+                self.models[0].set_instances_with_ids(instances);
+                self.models[0].set_hidden(self.scene.hidden_ids());
+                if let Some(material) = self.scene.material {
+                    self.models[0].set_material(material);
+                }
             }
         }
     }
 
-    // creates an instance of a cube with the help menu texture in models[1]
-    // and switches the visible models
-    pub fn go_to_help(&mut self) {
-        
+    /// pick the cube hit by the next ray as if it was clicked, for clicks that don't come from the window
+    pub fn press_select(&mut self) {
+        self.is_select_pressed = true;
+    }
+
+    /// act on the cube hit by a ray if delete or the behavior key was pressed since the last frame
+    ///
+    /// Args:
+    ///     origin: where the ray starts, usually the camera eye
+    ///     direction: normalized direction of the ray
+    ///     queue: command queue used to patch the instance buffer
+    pub fn handle_picking(&mut self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, queue: &wgpu::Queue) {
+        let is_delete_pressed = std::mem::take(&mut self.is_delete_pressed);
+        let is_behavior_pressed = std::mem::take(&mut self.is_behavior_pressed);
+        let is_select_pressed = std::mem::take(&mut self.is_select_pressed);
+
         if self.is_being_helped {
-            // set up 1 instance of a cube
-            // this will have the help menu texture
-            let scale: f32 = 1.0;
-
-            // we are making 1 cube
-            let instances = (0..1).flat_map(|z| {
-                (0..1).map(move |x| {
-                    let x = 0.0;
-                    let z = -1.0;
-                    let position = cgmath::Vector3 {x, y: 0.0, z};
-                    
-                    let rotation = cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(270.0));
-                    
-
-                    instance::Instance {
-                        position, rotation, scale
+            return;
+        }
+
+        // measure on the surface of the cube clicked, which the bounding box fits better than the sphere
+        if is_select_pressed && self.is_measuring {
+            if let Some((_, point)) = self.models[0].pick_point(origin, direction) {
+                self.measurement.add(point);
+            }
+            return;
+        }
+
+        let picked = self.models[0].pick(origin, direction).map(|(id, _)| id);
+        if is_select_pressed {
+            self.select(picked);
+        }
+        let Some(picked) = picked else {
+            return;
+        };
+
+        if is_delete_pressed {
+            self.models[0].remove_instance(picked, queue);
+            self.selection.ids.retain(|id| *id != picked);
+            if picked.0 >= SPAWNED_IDS {
+                // pasted cubes only live in the scene, so deleting them can be undone
+                self.history.record(self.scene.clone());
+                self.scene.remove(picked);
+                self.save_scene();
+            } else {
+                // remember it so the cube stays gone when the grid is rebuilt
+                self.deleted.insert(picked);
+            }
+        } else if is_behavior_pressed {
+            let behaviors: [Box<dyn Behavior>; 2] = [
+                Box::new(behavior::Rotate::new(self.up_axis.up(), 120.0)),
+                Box::new(behavior::Bob::new(0.5, 3.0)),
+            ];
+            for behavior in behaviors {
+                self.models[0].attach_behavior(picked, behavior);
+            }
+        }
+    }
+
+    /// pick a cube with the mouse, grouped cubes are picked with the rest of their group
+    fn select(&mut self, picked: Option<EntityId>) {
+        let picked = picked.map(|id| match self.scene.group_of(id) {
+            Some(group) => group.members.clone(),
+            None => vec![id],
+        });
+
+        match (picked, self.modifiers.shift_key()) {
+            (Some(ids), true) => {
+                // shift-clicking a picked group drops all of it
+                if ids.iter().all(|id| self.selection.contains(*id)) {
+                    self.selection.ids.retain(|id| !ids.contains(id));
+                } else {
+                    for id in ids.into_iter().filter(|id| !self.selection.contains(*id)).collect::<Vec<_>>() {
+                        self.selection.toggle(id);
                     }
-                })
-            }).collect::<Vec<_>>();
-            self.models[1].set_instances(instances);
-            self.models[0].visible = false;
-            self.models[1].visible = true;
+                }
+            }
+            (Some(ids), false) => self.selection.replace(ids),
+            // clicking nothing clears the selection, unless adding to it
+            (None, false) => self.selection.replace([]),
+            (None, true) => {}
         }
+    }
 
-        if !self.is_being_helped {
-            if self.is_help_just_pressed {
-                self.models[1].visible = false;
-                self.models[0].visible = true;
-                self.is_help_just_pressed = false;
+    /// the change a key makes to the selection, moves are half a unit, turns 15 degrees and scales 10%
+    ///
+    /// While snapping, moves are one grid cell and turns one rotation step
+    fn selection_transform(&self, keycode: KeyCode) -> Option<GroupTransform> {
+        let snapping = self.snap.is_active(self.modifiers.alt_key());
+        let step = if snapping { self.snap.grid_size } else { 0.5 };
+        let angle = if snapping { self.snap.rotation_step } else { 15.0 };
+        let turn = |degrees: f32| cgmath::Quaternion::from_axis_angle(self.up_axis.up(), cgmath::Deg(degrees));
+        Some(match keycode {
+            KeyCode::Numpad8 => GroupTransform::translate(self.up_axis.ground(0.0, -step)),
+            KeyCode::Numpad2 => GroupTransform::translate(self.up_axis.ground(0.0, step)),
+            KeyCode::Numpad4 => GroupTransform::translate(self.up_axis.ground(-step, 0.0)),
+            KeyCode::Numpad6 => GroupTransform::translate(self.up_axis.ground(step, 0.0)),
+            KeyCode::Numpad9 => GroupTransform::translate(self.up_axis.up() * step),
+            KeyCode::Numpad3 => GroupTransform::translate(self.up_axis.up() * -step),
+            KeyCode::Numpad7 => GroupTransform::rotate(turn(angle)),
+            KeyCode::Numpad1 => GroupTransform::rotate(turn(-angle)),
+            KeyCode::NumpadAdd => GroupTransform::scale(1.1),
+            KeyCode::NumpadSubtract => GroupTransform::scale(1.0 / 1.1),
+            _ => return None,
+        })
+    }
+
+    /// group, transform, copy and paste the selected cubes and undo or redo edits, as asked since the last frame
+    pub fn edit_selection(&mut self, queue: &wgpu::Queue) {
+        let edits = std::mem::take(&mut self.pending_edits);
+        if self.is_being_helped || edits.is_empty() {
+            return;
+        }
+
+        let mut changed = false;
+        for edit in edits {
+            // only edits that change the scene get an undo step
+            let before = self.scene.clone();
+            let recorded = match edit {
+                EditCommand::Group | EditCommand::Ungroup => self.group_selection(edit == EditCommand::Group),
+                EditCommand::Transform { transform, snap } => self.transform_selection(transform, snap, queue),
+                EditCommand::Copy => {
+                    self.copy_selection();
+                    false
+                }
+                EditCommand::Paste => self.paste(),
+                EditCommand::Duplicate => {
+                    let clipboard = (std::mem::take(&mut self.clipboard), self.is_clipboard_grouped);
+                    self.copy_selection();
+                    let pasted = self.paste();
+                    (self.clipboard, self.is_clipboard_grouped) = clipboard;
+                    pasted
+                }
+                EditCommand::Undo | EditCommand::Redo => {
+                    let current = self.scene.clone();
+                    let restored = if edit == EditCommand::Undo { self.history.undo(current) } else { self.history.redo(current) };
+                    if let Some(restored) = restored {
+                        self.scene = restored;
+                        // cubes the restored scene doesn't have can't stay picked
+                        let models = &self.models;
+                        let scene = &self.scene;
+                        self.selection.ids.retain(|id| id.0 < SPAWNED_IDS && models[0].instance(*id).is_some() || scene.placed.iter().any(|(placed, _)| placed == id));
+                        self.is_scene_changed = true;
+                        changed = true;
+                    }
+                    false
+                }
+            };
+            if recorded {
+                self.history.record(before);
+                changed = true;
             }
         }
+
+        if changed {
+            self.save_scene();
+        }
+    }
+
+    /// where a cube is now, pasted cubes may not be in the grid until it is rebuilt
+    fn current_instance(&self, id: EntityId) -> Option<Instance> {
+        match self.scene.placed.iter().find(|(placed, _)| *placed == id) {
+            Some((_, instance)) => Some(instance.clone()),
+            None => self.models[0].instance(id).cloned(),
+        }
+    }
+
+    /// bundle the selected cubes into a group, or take them out of theirs, returns true if anything changed
+    fn group_selection(&mut self, group: bool) -> bool {
+        if self.selection.is_empty() {
+            return false;
+        }
+
+        // a cube is only ever in one group
+        let selection = &self.selection;
+        for group in &mut self.scene.groups {
+            group.members.retain(|id| !selection.contains(*id));
+        }
+        self.scene.groups.retain(|group| !group.members.is_empty());
+
+        if group && self.selection.ids.len() > 1 {
+            let instances = self.selection.ids.iter().filter_map(|id| self.current_instance(*id).map(|instance| (*id, instance))).collect::<Vec<_>>();
+            self.scene.groups.push(Group::new(instances.iter().map(|(id, instance)| (*id, instance)).collect()));
+        }
+        true
+    }
+
+    /// move, turn or scale the selected cubes together, returns true if anything changed
+    ///
+    /// Snapping puts the pivot on the grid, moving everything by the same amount so groups keep
+    /// their shape, and rounds each cube's turn and scale.
+    fn transform_selection(&mut self, transform: GroupTransform, snap: bool, queue: &wgpu::Queue) -> bool {
+        let instances = self.selection.ids.iter().filter_map(|id| self.current_instance(*id).map(|instance| (*id, instance))).collect::<Vec<_>>();
+        if instances.is_empty() {
+            return false;
+        }
+
+        // a whole group turns around its own pivot, anything else around its center
+        let group = self.scene.groups.iter_mut().find(|group| group.matches(&self.selection));
+        let pivot = match &group {
+            Some(group) => group.pivot,
+            None => selection::center(instances.iter().map(|(_, instance)| instance)),
+        };
+        let moved_pivot = pivot + transform.translation;
+        let shift = if snap { self.snap.snap_position(moved_pivot) - moved_pivot } else { cgmath::Vector3::zero() };
+        if let Some(group) = group {
+            group.pivot = moved_pivot + shift;
+        }
+
+        for (id, instance) in instances {
+            let mut moved = transform.apply(&instance, pivot);
+            if snap {
+                moved.position += shift;
+                moved.rotation = self.snap.snap_rotation(moved.rotation, self.up_axis.up());
+                moved.scale = self.snap.snap_scale(moved.scale);
+            }
+            self.models[0].set_instance(id, moved.clone(), queue);
+            self.scene.place(id, moved);
+        }
+        true
+    }
+
+    /// the picked cube with everything the inspector shows, None unless exactly one cube is picked
+    pub fn inspect(&self) -> Option<Inspection> {
+        let [id] = self.selection.ids[..] else {
+            return None;
+        };
+        let model = &self.models[0];
+        Some(Inspection {
+            id,
+            properties: EntityProperties {
+                instance: self.current_instance(id)?,
+                material: model.material(),
+                hidden: self.scene.is_hidden(id),
+                tags: self.scene.tags_of(id).to_vec(),
+                group_enabled: self.scene.group_of(id).map(|group| group.enabled),
+            },
+            materials: model.materials.iter().map(|material| material.name.clone()).collect(),
+            behaviors: model.behavior_names(id),
+            group_size: self.scene.group_of(id).map(|group| group.members.len()),
+            is_continuing: false,
+        })
+    }
+
+    /// change a cube from the inspector, as one undo step unless it goes on from the last change
+    ///
+    /// Args:
+    ///     is_continuing: the change is part of the same drag or typing as the last one
+    pub fn edit_properties(&mut self, id: EntityId, properties: EntityProperties, is_continuing: bool, queue: &wgpu::Queue) {
+        if self.is_being_helped || self.current_instance(id).is_none() {
+            return;
+        }
+        if !is_continuing {
+            self.history.record(self.scene.clone());
+        }
+
+        if self.current_instance(id).as_ref() != Some(&properties.instance) {
+            self.models[0].set_instance(id, properties.instance.clone(), queue);
+            self.scene.place(id, properties.instance);
+        }
+        let group_enabled = self.scene.group_of(id).map(|group| group.enabled);
+        if self.scene.is_hidden(id) != properties.hidden || group_enabled != properties.group_enabled {
+            self.scene.set_hidden(id, properties.hidden);
+            if let Some(enabled) = properties.group_enabled {
+                self.scene.set_group_enabled(id, enabled);
+            }
+            self.models[0].set_hidden(self.scene.hidden_ids());
+        }
+        self.scene.set_tags(id, &properties.tags);
+        self.models[0].set_material(properties.material);
+        self.scene.material = Some(self.models[0].material());
+        self.save_scene();
+    }
+
+    /// remember the selected cubes so they can be pasted
+    fn copy_selection(&mut self) {
+        if self.selection.is_empty() {
+            return;
+        }
+        self.is_clipboard_grouped = self.scene.groups.iter().any(|group| group.matches(&self.selection));
+        self.clipboard = self.selection.ids.iter().filter_map(|id| self.current_instance(*id)).collect();
+    }
+
+    /// add copies of the clipboard a little to the side and pick them, returns true if anything was pasted
+    ///
+    /// The clipboard follows the copies, so pasting again keeps stepping to the side
+    fn paste(&mut self) -> bool {
+        if self.clipboard.is_empty() {
+            return false;
+        }
+
+        let offset = self.up_axis.ground(0.5, 0.5);
+        let mut pasted = Vec::new();
+        for instance in &mut self.clipboard {
+            instance.position += offset;
+            let id = self.spawned_ids.allocate();
+            self.scene.place(id, instance.clone());
+            pasted.push(id);
+        }
+
+        if self.is_clipboard_grouped && pasted.len() > 1 {
+            self.scene.groups.push(Group::new(pasted.iter().copied().zip(&self.clipboard).collect()));
+        }
+        self.selection.replace(pasted);
+        self.is_scene_changed = true;
+        true
+    }
+
+    /// The file in `res` the scene is saved to
+    pub fn scene_file(&self) -> &str {
+        &self.scene_file
+    }
+
+    /// write the groups, moved and pasted cubes to `scene.txt` in the background
+    fn save_scene(&self) {
+        let task = save_string(&self.scene_file, self.scene.to_text());
+        crate::tasks::spawn(async move {
+            if let Ok(Err(e)) = task.await {
+                log::warn!("Couldn't save the scene: {}", e);
+            }
+        });
+    }
+
+    /// move a sphere out of every visible instance, returns the new center
+    pub fn push_out(&self, mut point: cgmath::Point3<f32>, radius: f32) -> cgmath::Point3<f32> {
+        for model in self.models.iter().filter(|model| model.visible) {
+            point = model.push_out(point, radius);
+        }
+        point
+    }
+
+    /// apply the entity and material keys of a cutscene frame
+    pub fn apply_timeline(&mut self, frame: &TimelineFrame, queue: &wgpu::Queue) {
+        for (model, id, position) in &frame.entities {
+            if let Some(model) = self.models.get_mut(*model) {
+                model.set_instance_position(*id, *position, queue);
+            }
+        }
+        for (model, material) in &frame.materials {
+            if let Some(model) = self.models.get_mut(*model) {
+                model.set_material(*material);
+            }
+        }
+    }
+
+    /// set what a live data binding drives, see `data_binding`
+    pub fn apply_binding(&mut self, property: BoundProperty, value: f32, queue: &wgpu::Queue) {
+        let up = self.up_axis.up();
+        match property {
+            BoundProperty::Height { model, id } => {
+                let Some(model) = self.models.get_mut(model) else {
+                    return;
+                };
+                if let Some(instance) = model.instance(id) {
+                    let position = instance.position - up * instance.position.dot(up) + up * value;
+                    model.set_instance_position(id, position, queue);
+                }
+            }
+            BoundProperty::Scale { model, id } => {
+                let Some(model) = self.models.get_mut(model) else {
+                    return;
+                };
+                if let Some(instance) = model.instance(id) {
+                    let instance = Instance { scale: value.max(0.0), ..instance.clone() };
+                    model.set_instance(id, instance, queue);
+                }
+            }
+            BoundProperty::Material { model } => {
+                if let Some(model) = self.models.get_mut(model) {
+                    model.set_material(value.round().max(0.0) as usize);
+                }
+            }
+            BoundProperty::Count => self.set_grid_size(value.round().clamp(0.0, MAX_GRID_SIZE as f32) as u32),
+        }
+    }
+
+    /// what a presentation should circle, the middle of the selected cubes or of all of them
+    pub fn focus_point(&self) -> cgmath::Point3<f32> {
+        let center = if self.selection.is_empty() {
+            selection::center(self.models[0].instances().iter())
+        } else {
+            selection::center(self.selection.ids.iter().filter_map(|id| self.current_instance(*id)).collect::<Vec<_>>().iter())
+        };
+        cgmath::Point3::from_vec(center)
+    }
+
+    /// the floor under a point, just below the lowest cube, and how far out it has to reach to cover every cube
+    pub fn ground_under(&self, center: cgmath::Point3<f32>) -> (cgmath::Point3<f32>, f32) {
+        let model = &self.models[0];
+        let up = self.up_axis.up();
+        let bounds = &model.bounding_box;
+        let half_diagonal = (bounds.max - bounds.min).magnitude() / 2.0;
+
+        let floor = model.instances().iter().map(|instance| instance.lowest_point(bounds, up)).reduce(f32::min).unwrap_or(0.0);
+        let radius = model.instances().iter().map(|instance| {
+            let offset = instance.position - center.to_vec();
+            (offset - up * offset.dot(up)).magnitude() + half_diagonal * instance.scale
+        }).fold(0.0, f32::max);
+        (center + up * (floor - center.to_vec().dot(up)), radius)
+    }
+
+    /// handles of every loaded model, in load order
+    pub fn model_handles(&self) -> Vec<ModelHandle> {
+        (0..self.models.len()).map(ModelHandle).collect()
+    }
+
+    /// the model a handle refers to
+    pub fn model(&self, handle: ModelHandle) -> Option<&Model> {
+        self.models.get(handle.0)
+    }
+
+    /// switch to the scene in another file, later edits are saved to it
+    ///
+    /// Everything tied to the scene open before is let go: its undo steps, the picked cubes,
+    /// measure points, cubes deleted from the grid and instances of models added while running.
+    /// Those models stay loaded, so placing them again is quick.
+    ///
+    /// Args:
+    ///     file_name: where the scene is saved, relative to `res`
+    ///     text: the scene in the form `Scene::parse` reads
+    pub fn open_scene(&mut self, file_name: &str, text: &str) -> anyhow::Result<()> {
+        let mut scene = Scene::parse(text)?;
+        scene.material.get_or_insert(self.models[0].material());
+        self.spawned_ids = spawned_ids(&scene);
+        self.scene = scene;
+        self.scene_file = file_name.to_string();
+        self.history = UndoStack::default();
+        self.selection.replace([]);
+        self.measurement.clear();
+        self.pending_edits.clear();
+        self.deleted.clear();
+        for model in self.models.iter_mut().skip(self.resource_models) {
+            model.set_instances(Vec::new());
+        }
+        self.is_scene_changed = true;
+        Ok(())
+    }
+
+    /// add a model loaded after the world was made, with no instances yet
+    pub fn add_model(&mut self, model: Model) -> ModelHandle {
+        self.models.push(model);
+        ModelHandle(self.models.len() - 1)
+    }
+
+    /// where a ray lands: on the nearest visible instance, else on the ground, else a little way along it
+    pub fn drop_point(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> cgmath::Point3<f32> {
+        if let Some(hit) = self.pick(&Ray { origin, direction }) {
+            return hit.point;
+        }
+
+        // the ground is the plane through the origin facing up
+        let up = self.up_axis.up();
+        let distance = -origin.to_vec().dot(up) / direction.dot(up);
+        if distance.is_finite() && distance > 0.0 {
+            return origin + direction * distance;
+        }
+        origin + direction * DROP_DISTANCE
+    }
+
+    /// The closest instance of any visible model whose bounding box the ray hits
+    pub fn pick(&self, ray: &Ray) -> Option<PickHit> {
+        self.models.iter()
+            .enumerate()
+            .filter(|(_, model)| model.visible)
+            .filter_map(|(index, model)| {
+                let (id, distance) = model.pick_box(ray.origin, ray.direction)?;
+                Some(PickHit { model: ModelHandle(index), id, point: ray.origin + ray.direction * distance, distance })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// where to push frames for the materials with `map_stream <name>`, None if no loaded material has that name
+    pub fn image_stream(&self, name: &str) -> Option<stream::ImageStream> {
+        self.models.iter()
+            .flat_map(|model| &model.materials)
+            .filter_map(|material| material.stream.as_ref())
+            .find(|stream| stream.name == name)
+            .map(stream::StreamTexture::stream)
+    }
+
+    /// the cubes picked, in the order they were picked
+    pub fn selection(&self) -> &[EntityId] {
+        &self.selection.ids
+    }
+
+    /// pick cubes the way a click would, ids that aren't cubes in the grid are left out
+    pub fn set_selection(&mut self, ids: impl IntoIterator<Item = EntityId>) {
+        let cubes = &self.models[0];
+        self.selection.replace(ids.into_iter().filter(|id| cubes.instance(*id).is_some()));
+    }
+
+    /// place a new instance of a model standing on a point, returns None if the handle is unknown
+    pub fn spawn_model(&mut self, handle: ModelHandle, point: cgmath::Point3<f32>) -> Option<EntityId> {
+        let model = self.models.get_mut(handle.0)?;
+        let up = self.up_axis.up();
+        let mut instance = Instance {
+            position: point.to_vec(),
+            rotation: cgmath::Quaternion::from_axis_angle(up, cgmath::Deg(0.0)),
+            scale: 1.0,
+        };
+        // lift it so its lowest point touches the point rather than its middle
+        let sink = instance.lowest_point(&model.bounding_box, up) - point.to_vec().dot(up);
+        instance.position -= up * sink;
+        Some(model.add_instances(instance))
+    }
+
+    /// which way is up in the world
+    pub fn up_axis(&self) -> UpAxis {
+        self.up_axis
+    }
+
+    /// cubes along each side of the grid
+    pub fn grid_size(&self) -> u32 {
+        self.num_instances
+    }
+
+    /// change how many cubes are along each side of the grid, it is rebuilt on the next update
+    pub fn set_grid_size(&mut self, size: u32) {
+        if size != self.num_instances {
+            self.num_instances = size;
+            self.is_scene_changed = true;
+        }
+    }
+
+    /// How many agents walk around between the cubes
+    pub fn crowd_size(&self) -> u32 {
+        self.crowd.len()
+    }
+
+    /// Add or remove walking agents, up to `crowd::MAX_AGENTS`
+    pub fn set_crowd_size(&mut self, count: u32) {
+        if count != self.crowd.len() {
+            self.crowd.resize(count);
+            self.is_scene_changed = true;
+        }
+    }
+
+    /// The points measured so far, None when not measuring
+    pub fn measurement(&self) -> Option<&Measurement> {
+        self.is_measuring.then_some(&self.measurement)
+    }
+
+    /// Counts of what the world holds and what it drew last frame, for overlays and dashboards
+    pub fn stats(&self) -> WorldStats {
+        WorldStats::from_models(self.models.iter().map(Model::stats).collect())
+    }
+
+    /// Look for problems in every model: missing textures, meshes without uvs or normals, degenerate
+    /// triangles, absurd instance scales and oversized textures
+    ///
+    /// Args:
+    ///     max_texture_size: textures wider or taller than this are reported
+    pub fn validate(&self, max_texture_size: u32) -> ValidationReport {
+        let issues = self.models
+            .iter()
+            .enumerate()
+            .flat_map(|(model, m)| m.validate(max_texture_size).into_iter().map(move |problem| Issue { model, problem }))
+            .collect();
+        ValidationReport { issues }
+    }
+
+    /// work out what every model draws this frame, running behaviors and picking detail levels
+    pub fn prepare_models(&mut self, context: &BehaviorContext, view: &ViewContext, queue: &wgpu::Queue) {
+        for (index, model) in self.models.iter_mut().enumerate() {
+            let chains = self.ik_chains.iter().filter(|(model, _)| *model == index).map(|(_, chain)| *chain).collect::<Vec<_>>();
+            model.prepare(context, view, &chains, queue);
+        }
+    }
+
+    /// Bend three instances of a model every frame so the last reaches a target, see `IkChain`
+    ///
+    /// Returns the chain's number for `set_ik_target`, None if the model or one of the instances doesn't
+    /// exist, or the same instance is used twice
+    pub fn add_ik_chain(&mut self, model: ModelHandle, chain: IkChain) -> Option<usize> {
+        let instances = self.models.get(model.0)?;
+        if chain.root == chain.joint || chain.joint == chain.end || chain.root == chain.end {
+            return None;
+        }
+        if [chain.root, chain.joint, chain.end].iter().any(|id| instances.instance(*id).is_none()) {
+            return None;
+        }
+        self.ik_chains.push((model.0, chain));
+        Some(self.ik_chains.len() - 1)
+    }
+
+    /// Move where a chain reaches for, returns false if there's no chain with that number
+    pub fn set_ik_target(&mut self, chain: usize, target: cgmath::Point3<f32>) -> bool {
+        match self.ik_chains.get_mut(chain) {
+            Some((_, chain)) => {
+                chain.target = target;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// One line for each IK chain with its number, model, instances and target, for the console
+    pub fn ik_listing(&self) -> String {
+        if self.ik_chains.is_empty() {
+            return "No IK chains".to_string();
+        }
+        self.ik_chains.iter().enumerate().map(|(number, (model, chain))| {
+            let target = chain.target;
+            format!("{}: model {} instances {} {} {} -> {} {} {}", number, model, chain.root.0, chain.joint.0, chain.end.0, target.x, target.y, target.z)
+        }).collect::<Vec<_>>().join("\n")
+    }
+
+    /// grow the help cube in models[1] in place of the grid when help is turned on, and shrink it away when turned off
+    ///
+    /// Args:
+    ///     dt: seconds the fade moves on
+    pub fn go_to_help(&mut self, dt: f32) {
+        let is_shown = self.models[1].visible && self.help_fade.as_ref().is_none_or(|fade| fade.to() > 0.0);
+        if self.is_being_helped != is_shown {
+            let from = self.help_fade.as_ref().map_or(if is_shown { 1.0 } else { 0.0 }, Tween::value);
+            let seconds = self.animation.help_fade_seconds;
+            self.help_fade = Some(if self.is_being_helped {
+                self.models[0].visible = false;
+                self.models[1].visible = true;
+                Tween::new(from, 1.0, seconds).with_easing(HELP_POP)
+            } else {
+                Tween::new(from, 0.0, seconds).on_complete(())
+            });
+        }
+
+        let Some(fade) = &mut self.help_fade else {
+            return;
+        };
+        let scale = fade.advance(dt);
+        if fade.take_completed().is_some() {
+            self.models[1].visible = false;
+            self.models[0].visible = true;
+        }
+        if fade.is_finished() {
+            self.help_fade = None;
+        }
+        let up_axis = self.up_axis;
+        // a single cube with the help menu texture, facing the camera
+        self.models[1].set_instances(vec![Instance {
+            position: up_axis.ground(0.0, -1.0),
+            rotation: cgmath::Quaternion::from_axis_angle(up_axis.from_y_up(cgmath::Vector3::unit_z()), cgmath::Deg(270.0)),
+            scale,
+        }]);
     }
 }
 
+/// ids for pasted cubes, never one the scene already uses
+fn spawned_ids(scene: &Scene) -> IdAllocator {
+    let mut spawned_ids = IdAllocator::starting_at(SPAWNED_IDS);
+    for (id, _) in scene.spawned() {
+        spawned_ids.reserve(*id);
+    }
+    spawned_ids
+}
+
 pub trait DrawWorld<'a> {
-    fn draw_world(&mut self, world: &'a World, camera_bind_group: &'a wgpu::BindGroup);
+    fn draw_world(&mut self, world: &'a World, pipelines: &'a MaterialPipelines, camera_bind_group: &'a wgpu::BindGroup);
+    /// draw the shadow catchers `draw_world` leaves out, after the sky so they darken it
+    fn draw_world_shadow_catchers(&mut self, world: &'a World, pipelines: &'a MaterialPipelines, camera_bind_group: &'a wgpu::BindGroup);
+    /// draw only the depth of every model, for passes like the shadow map that have their pipeline set already
+    fn draw_world_depth(&mut self, world: &'a World);
 }
 
 /// set up drawing models for our RenderPass rendering pipeline
@@ -286,9 +1145,21 @@ impl<'a, 'b> DrawWorld<'b> for wgpu::RenderPass<'a>
 where
     'b: 'a,
 {
-    fn draw_world(&mut self, world: &'b World, camera_bind_group: &'b wgpu::BindGroup) {
+    fn draw_world(&mut self, world: &'b World, pipelines: &'b MaterialPipelines, camera_bind_group: &'b wgpu::BindGroup) {
+        for model in &world.models {
+            self.draw_model(model, pipelines, camera_bind_group);
+        }
+    }
+
+    fn draw_world_shadow_catchers(&mut self, world: &'b World, pipelines: &'b MaterialPipelines, camera_bind_group: &'b wgpu::BindGroup) {
+        for model in &world.models {
+            self.draw_model_shadow_catchers(model, pipelines, camera_bind_group);
+        }
+    }
+
+    fn draw_world_depth(&mut self, world: &'b World) {
         for model in &world.models {
-            self.draw_model(model, camera_bind_group);
+            self.draw_model_depth(model);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+//! Resources packed into one .pak file, a zip archive with the same layout as the `res` folder
+use std::{collections::HashSet, fs::File, io::{BufReader, Read}, path::{Path, PathBuf}, sync::Mutex};
+
+/// An opened .pak file
+///
+/// Reads take turns on the one open file, so files in the archive load one at a time.
+pub struct PakArchive {
+    path: PathBuf,
+    /// every file in the archive, with / between folders
+    names: HashSet<String>,
+    zip: Mutex<zip::ZipArchive<BufReader<File>>>,
+}
+
+impl PakArchive {
+    /// Open a .pak file and read its list of files
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let zip = zip::ZipArchive::new(BufReader::new(File::open(path)?))?;
+        let names = zip.file_names().map(|name| name.trim_start_matches("./").to_string()).collect();
+        Ok(Self { path: path.to_path_buf(), names, zip: Mutex::new(zip) })
+    }
+
+    /// The file the archive was opened from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The bytes of a file relative to the resource folder, None if it isn't in the archive
+    pub fn read(&self, file_name: &Path) -> Option<anyhow::Result<Vec<u8>>> {
+        let name = entry_name(file_name);
+        if !self.names.contains(&name) {
+            return None;
+        }
+        let mut zip = self.zip.lock().unwrap();
+        let read = zip.by_name(&name).map_err(anyhow::Error::from).and_then(|mut file| {
+            let mut bytes = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        });
+        Some(read)
+    }
+}
+
+/// The name a file has in the archive, `..` and `.` are resolved so paths from .mtl files match
+pub fn entry_name(file_name: &Path) -> String {
+    let mut parts = Vec::new();
+    for part in file_name.components() {
+        match part {
+            std::path::Component::Normal(part) => parts.push(part.to_string_lossy()),
+            std::path::Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join(format!("rust3d_archive_{}.pak", std::process::id()));
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        writer.start_file("cube/cube.mtl", options).unwrap();
+        writer.write_all(b"newmtl cube").unwrap();
+        writer.finish().unwrap();
+
+        let archive = PakArchive::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(archive.read(Path::new("cube/../cube/./cube.mtl")).unwrap().unwrap(), b"newmtl cube");
+        assert!(archive.read(Path::new("cube/cube.obj")).is_none());
+    }
+}
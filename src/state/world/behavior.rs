@@ -0,0 +1,149 @@
+//! Small per-instance scripts that run every frame without needing their own system
+use cgmath::Rotation3;
+use winit::event::WindowEvent;
+
+use super::instance::Instance;
+
+/// Information about the frame that behaviors can react to
+pub struct BehaviorContext {
+    /// where the camera currently is
+    #[allow(unused)]
+    pub camera_eye: cgmath::Point3<f32>,
+    /// which way is up in the world
+    pub up: cgmath::Vector3<f32>,
+    /// seconds since the last frame, 0 when the same moment is drawn again
+    pub dt: f32,
+}
+
+/// Logic attached to a single instance
+///
+/// Behaviors are applied on top of the instance's stored transform every frame, so they should
+/// keep their own state (angle, phase, ...) and offset the instance from there.
+pub trait Behavior: Send {
+    /// What the behavior is called in the inspector
+    fn name(&self) -> &'static str;
+
+    /// Called once when the behavior is attached
+    fn on_spawn(&mut self, _instance: &Instance) {}
+
+    /// Called every frame to change how the instance is drawn
+    fn on_update(&mut self, _instance: &mut Instance, _context: &BehaviorContext) {}
+
+    /// Called for every window event, returns true if the event was handled
+    fn on_event(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+}
+
+/// Spin an instance around an axis
+pub struct Rotate {
+    pub axis: cgmath::Vector3<f32>,
+    /// degrees turned every second
+    pub speed: f32,
+    angle: f32,
+}
+
+impl Rotate {
+    pub fn new(axis: cgmath::Vector3<f32>, speed: f32) -> Self {
+        Self { axis, speed, angle: 0.0 }
+    }
+}
+
+impl Behavior for Rotate {
+    fn name(&self) -> &'static str {
+        "rotate"
+    }
+
+    fn on_update(&mut self, instance: &mut Instance, context: &BehaviorContext) {
+        self.angle = (self.angle + self.speed * context.dt) % 360.0;
+        instance.rotation = instance.rotation * cgmath::Quaternion::from_axis_angle(self.axis, cgmath::Deg(self.angle));
+    }
+}
+
+/// Move an instance up and down
+pub struct Bob {
+    /// how far up and down it moves
+    pub height: f32,
+    /// radians advanced every second
+    pub speed: f32,
+    phase: f32,
+}
+
+impl Bob {
+    pub fn new(height: f32, speed: f32) -> Self {
+        Self { height, speed, phase: 0.0 }
+    }
+}
+
+impl Behavior for Bob {
+    fn name(&self) -> &'static str {
+        "bob"
+    }
+
+    fn on_update(&mut self, instance: &mut Instance, context: &BehaviorContext) {
+        self.phase = (self.phase + self.speed * context.dt) % std::f32::consts::TAU;
+        instance.position += context.up * self.height * self.phase.sin();
+    }
+}
+
+/// Keep an instance at a fixed offset from the camera
+#[allow(unused)]
+pub struct FollowCamera {
+    pub offset: cgmath::Vector3<f32>,
+}
+
+impl Behavior for FollowCamera {
+    fn name(&self) -> &'static str {
+        "follow camera"
+    }
+
+    fn on_update(&mut self, instance: &mut Instance, context: &BehaviorContext) {
+        instance.position = cgmath::Vector3::new(context.camera_eye.x, context.camera_eye.y, context.camera_eye.z) + self.offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_instance() -> Instance {
+        Instance {
+            position: cgmath::Vector3::new(1.0, 2.0, 3.0),
+            rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0)),
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_bob_offsets_from_base() {
+        let context = BehaviorContext { camera_eye: (0.0, 0.0, 0.0).into(), up: cgmath::Vector3::unit_y(), dt: 1.0 };
+        let mut bob = Bob::new(2.0, std::f32::consts::FRAC_PI_2);
+
+        let mut instance = test_instance();
+        bob.on_update(&mut instance, &context);
+        assert_eq!(instance.position, cgmath::Vector3::new(1.0, 4.0, 3.0));
+    }
+
+    #[test]
+    fn test_rotate_follows_time() {
+        // one long frame turns as far as two short ones
+        let mut one = Rotate::new(cgmath::Vector3::unit_y(), 90.0);
+        let mut two = Rotate::new(cgmath::Vector3::unit_y(), 90.0);
+        let context = |dt| BehaviorContext { camera_eye: (0.0, 0.0, 0.0).into(), up: cgmath::Vector3::unit_y(), dt };
+        one.on_update(&mut test_instance(), &context(0.5));
+        two.on_update(&mut test_instance(), &context(0.25));
+        two.on_update(&mut test_instance(), &context(0.25));
+        assert_eq!(one.angle, 45.0);
+        assert_eq!(two.angle, one.angle);
+    }
+
+    #[test]
+    fn test_follow_camera() {
+        let context = BehaviorContext { camera_eye: (5.0, 5.0, 5.0).into(), up: cgmath::Vector3::unit_y(), dt: 1.0 };
+        let mut follow = FollowCamera { offset: cgmath::Vector3::new(0.0, -1.0, 0.0) };
+
+        let mut instance = test_instance();
+        follow.on_update(&mut instance, &context);
+        assert_eq!(instance.position, cgmath::Vector3::new(5.0, 4.0, 5.0));
+    }
+}
@@ -0,0 +1,256 @@
+//! Agents walking around the floor without bumping into each other, for stress tests with moving instances
+//!
+//! Every agent heads for a goal of its own inside a circle around the origin and picks another once
+//! it gets there. On the way it steers around its neighbours like reciprocal velocity obstacles (RVO):
+//! it tries a handful of velocities and takes the one closest to the velocity it wants that is
+//! furthest from running into anyone, counting on the others to take half the trouble of avoiding it.
+//! There is no navmesh, so goals are walked to in a straight line.
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Rotation3, Vector2};
+
+use super::{super::up_axis::UpAxis, instance::{EntityId, Instance, CROWD_IDS}};
+
+/// Most agents a crowd can have
+pub const MAX_AGENTS: u32 = 5000;
+
+/// Distance from an agent's center to its edge
+const AGENT_RADIUS: f32 = 0.5;
+
+/// Units an agent walks every second at most
+const MAX_SPEED: f32 = 2.0;
+
+/// Floor space every agent gets, the circle they walk in grows with the crowd
+const AREA_PER_AGENT: f32 = 12.0;
+
+/// Agents closer than this are steered around, also the size of the cells neighbours are looked up in
+const NEIGHBOUR_DISTANCE: f32 = 4.0;
+
+/// Seconds ahead collisions are looked for, ones further off don't change the way an agent walks
+const TIME_HORIZON: f32 = 2.0;
+
+/// How much an earlier collision weighs against walking off the wanted velocity
+const COLLISION_WEIGHT: f32 = 2.0;
+
+/// Velocities tried around the circle, at a few speeds each
+const SAMPLE_DIRECTIONS: usize = 12;
+
+/// How much passing on the right is preferred, so two agents walking straight at each other don't both
+/// step to the same side and stall face to face
+const SIDE_BIAS: f32 = 0.5;
+
+/// Agents this close to their goal pick a new one
+const ARRIVE_DISTANCE: f32 = 0.5;
+
+/// One walker, on the floor plane in the world's ground coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Agent {
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+    goal: Vector2<f32>,
+}
+
+/// Agents walking to goals while avoiding each other
+#[derive(Clone, Debug, Default)]
+pub struct Crowd {
+    agents: Vec<Agent>,
+    /// state of the random numbers picking places, the same crowd walks the same way every run
+    seed: u32,
+}
+
+impl Crowd {
+    /// How many agents are walking
+    pub fn len(&self) -> u32 {
+        self.agents.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+
+    /// Add or remove agents, the ones staying keep walking where they were
+    pub fn resize(&mut self, count: u32) {
+        let count = count.min(MAX_AGENTS) as usize;
+        self.agents.truncate(count);
+        while self.agents.len() < count {
+            let area = area_radius(count);
+            let position = self.random_point(area);
+            let goal = self.random_point(area);
+            self.agents.push(Agent { position, velocity: Vector2::new(0.0, 0.0), goal });
+        }
+    }
+
+    /// Move every agent on by some seconds
+    pub fn step(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+        let area = area_radius(self.agents.len());
+        for index in 0..self.agents.len() {
+            if (self.agents[index].goal - self.agents[index].position).magnitude() < ARRIVE_DISTANCE {
+                self.agents[index].goal = self.random_point(area);
+            }
+        }
+
+        let cells = self.cells();
+        let velocities = (0..self.agents.len()).map(|index| self.choose_velocity(index, &cells)).collect::<Vec<_>>();
+        for (agent, velocity) in self.agents.iter_mut().zip(velocities) {
+            agent.velocity = velocity;
+            agent.position += velocity * dt;
+        }
+    }
+
+    /// The agents as cube instances standing on the floor and facing the way they walk
+    pub fn instances(&self, up_axis: UpAxis, scale: f32) -> impl Iterator<Item = (EntityId, Instance)> + '_ {
+        self.agents.iter().enumerate().map(move |(index, agent)| {
+            let heading = if agent.velocity.magnitude2() > 1e-6 { agent.velocity.x.atan2(agent.velocity.y) } else { 0.0 };
+            let instance = Instance {
+                position: up_axis.ground(agent.position.x, agent.position.y),
+                rotation: cgmath::Quaternion::from_axis_angle(up_axis.up(), cgmath::Rad(heading)),
+                scale,
+            };
+            (EntityId(CROWD_IDS + index as u32), instance)
+        })
+    }
+
+    /// The agents by the cell of the floor they stand in, to find neighbours without looking at everyone
+    fn cells(&self) -> HashMap<(i32, i32), Vec<usize>> {
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (index, agent) in self.agents.iter().enumerate() {
+            cells.entry(cell_of(agent.position)).or_default().push(index);
+        }
+        cells
+    }
+
+    /// The velocity an agent takes this step, the one closest to straight at its goal that avoids its neighbours
+    fn choose_velocity(&self, index: usize, cells: &HashMap<(i32, i32), Vec<usize>>) -> Vector2<f32> {
+        let agent = &self.agents[index];
+        let to_goal = agent.goal - agent.position;
+        let distance = to_goal.magnitude();
+        let preferred = if distance > 1e-6 { to_goal * (MAX_SPEED.min(distance) / distance) } else { Vector2::new(0.0, 0.0) };
+
+        let (cell_x, cell_y) = cell_of(agent.position);
+        let neighbours = (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (cell_x + dx, cell_y + dy)))
+            .filter_map(|cell| cells.get(&cell))
+            .flatten()
+            .map(|other| &self.agents[*other])
+            .filter(|other| !std::ptr::eq(*other, agent) && (other.position - agent.position).magnitude() < NEIGHBOUR_DISTANCE)
+            .collect::<Vec<_>>();
+        if neighbours.is_empty() {
+            return preferred;
+        }
+
+        let candidates = std::iter::once(preferred)
+            .chain(std::iter::once(Vector2::new(0.0, 0.0)))
+            .chain((0..SAMPLE_DIRECTIONS).flat_map(|direction| {
+                let angle = direction as f32 / SAMPLE_DIRECTIONS as f32 * std::f32::consts::TAU;
+                [1.0, 0.5].map(|share| Vector2::new(angle.cos(), angle.sin()) * MAX_SPEED * share)
+            }));
+        let penalty = |velocity: Vector2<f32>| {
+            let collision = neighbours.iter()
+                .map(|other| time_to_collision(other.position - agent.position, velocity * 2.0 - agent.velocity - other.velocity, AGENT_RADIUS * 2.0))
+                .fold(f32::INFINITY, f32::min);
+            let danger = if collision < TIME_HORIZON { COLLISION_WEIGHT / collision.max(1e-3) } else { 0.0 };
+            // the right of the preferred velocity is where its cross product with the candidate is negative
+            let side = if distance > 1e-6 { to_goal.perp_dot(velocity) / distance / MAX_SPEED } else { 0.0 };
+            danger + (velocity - preferred).magnitude() + SIDE_BIAS * side
+        };
+        candidates.min_by(|a, b| penalty(*a).total_cmp(&penalty(*b))).unwrap_or(preferred)
+    }
+
+    /// A point spread evenly over a circle around the origin
+    fn random_point(&mut self, radius: f32) -> Vector2<f32> {
+        let angle = self.random() * std::f32::consts::TAU;
+        let distance = self.random().sqrt() * radius;
+        Vector2::new(angle.cos(), angle.sin()) * distance
+    }
+
+    /// A number from 0 up to 1, xorshift so no crate is needed
+    fn random(&mut self) -> f32 {
+        let mut x = if self.seed == 0 { 0x9E37_79B9 } else { self.seed };
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Radius of the circle a crowd walks in, so every agent gets the same room however many there are
+fn area_radius(count: usize) -> f32 {
+    (count.max(1) as f32 * AREA_PER_AGENT / std::f32::consts::PI).sqrt()
+}
+
+fn cell_of(position: Vector2<f32>) -> (i32, i32) {
+    ((position.x / NEIGHBOUR_DISTANCE).floor() as i32, (position.y / NEIGHBOUR_DISTANCE).floor() as i32)
+}
+
+/// Seconds until something at an offset, closing at a velocity, comes within a distance, infinite if it never does
+fn time_to_collision(offset: Vector2<f32>, velocity: Vector2<f32>, distance: f32) -> f32 {
+    let c = offset.magnitude2() - distance * distance;
+    if c < 0.0 {
+        // already touching
+        return 0.0;
+    }
+    let a = velocity.magnitude2();
+    let b = offset.dot(velocity);
+    let discriminant = b * b - a * c;
+    if a < 1e-9 || b <= 0.0 || discriminant < 0.0 {
+        return f32::INFINITY;
+    }
+    (b - discriminant.sqrt()) / a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_to_collision() {
+        // two units apart closing at one unit a second, touching at a distance of one
+        assert!((time_to_collision(Vector2::new(2.0, 0.0), Vector2::new(1.0, 0.0), 1.0) - 1.0).abs() < 1e-6);
+        assert_eq!(time_to_collision(Vector2::new(2.0, 0.0), Vector2::new(-1.0, 0.0), 1.0), f32::INFINITY);
+        assert_eq!(time_to_collision(Vector2::new(2.0, 0.0), Vector2::new(0.0, 1.0), 1.0), f32::INFINITY);
+        assert_eq!(time_to_collision(Vector2::new(0.5, 0.0), Vector2::new(0.0, 0.0), 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_agents_pass_each_other() {
+        // two agents walking straight at each other step aside instead of walking through
+        let mut crowd = Crowd {
+            agents: vec![
+                Agent { position: Vector2::new(-5.0, 0.0), velocity: Vector2::new(0.0, 0.0), goal: Vector2::new(5.0, 0.0) },
+                Agent { position: Vector2::new(5.0, 0.0), velocity: Vector2::new(0.0, 0.0), goal: Vector2::new(-5.0, 0.0) },
+            ],
+            ..Default::default()
+        };
+        let mut closest = f32::INFINITY;
+        for _ in 0..200 {
+            let goals = crowd.agents.iter().map(|agent| agent.goal).collect::<Vec<_>>();
+            crowd.step(1.0 / 30.0);
+            // keep the goals so they have to get past each other
+            for (agent, goal) in crowd.agents.iter_mut().zip(goals) {
+                agent.goal = goal;
+            }
+            closest = closest.min((crowd.agents[0].position - crowd.agents[1].position).magnitude());
+        }
+        assert!(closest > AGENT_RADIUS * 2.0 * 0.9, "came within {}", closest);
+        assert!(crowd.agents[0].position.x > 3.0 && crowd.agents[1].position.x < -3.0);
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut crowd = Crowd::default();
+        crowd.resize(100);
+        let kept = crowd.agents[..10].to_vec();
+        crowd.resize(10);
+        assert_eq!(crowd.agents, kept);
+        crowd.resize(MAX_AGENTS + 1);
+        assert_eq!(crowd.len(), MAX_AGENTS);
+        // everyone starts inside the circle, with an id of their own above the grid's
+        let area = area_radius(MAX_AGENTS as usize);
+        assert!(crowd.agents.iter().all(|agent| agent.position.magnitude() <= area));
+        let ids = crowd.instances(UpAxis::Y, 1.0).map(|(id, _)| id).collect::<std::collections::HashSet<_>>();
+        assert_eq!(ids.len(), MAX_AGENTS as usize);
+    }
+}
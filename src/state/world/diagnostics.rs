@@ -0,0 +1,176 @@
+//! Problems found in what the world loaded, see `World::validate`
+use std::{fmt, ops::RangeInclusive};
+
+use cgmath::InnerSpace;
+
+use super::{instance::EntityId, model::ModelVertex};
+
+/// Instance scales outside this range are almost always a typo in the scene
+pub const SANE_SCALES: RangeInclusive<f32> = 1e-3..=1e3;
+
+/// Triangles with less area than this are counted as degenerate
+const DEGENERATE_AREA: f32 = 1e-10;
+
+/// What was noticed about a mesh while loading it, the vertices aren't kept after upload
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshDiagnostics {
+    /// whether every part of the file gave uvs, without them textures stretch one texel over the mesh
+    pub has_tex_coords: bool,
+    /// whether every part of the file gave normals, missing ones were generated from the faces
+    pub has_normals: bool,
+    /// triangles with no area, which draw nothing and break tangents and picking
+    pub degenerate_triangles: usize,
+}
+
+impl MeshDiagnostics {
+    /// Look over a mesh's triangles
+    pub fn new(vertices: &[ModelVertex], indices: &[u32], has_tex_coords: bool, has_normals: bool) -> Self {
+        Self { has_tex_coords, has_normals, degenerate_triangles: degenerate_triangles(vertices, indices) }
+    }
+}
+
+/// How many triangles repeat a corner or have all corners on one line
+pub fn degenerate_triangles(vertices: &[ModelVertex], indices: &[u32]) -> usize {
+    indices
+        .chunks_exact(3)
+        .filter(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| cgmath::Vector3::from(vertices[triangle[i] as usize].position));
+            triangle[0] == triangle[1] || triangle[1] == triangle[2] || triangle[0] == triangle[2]
+                || (b - a).cross(c - a).magnitude2() < DEGENERATE_AREA
+        })
+        .count()
+}
+
+/// How bad a problem is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// the scene draws, but probably not how it was meant to
+    Warning,
+    /// something is missing or won't work on every device
+    Error,
+}
+
+/// One thing wrong with a model
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// a material's texture couldn't be loaded and a plain one was used instead
+    MissingTexture { material: String, file: String },
+    /// a mesh has no uvs
+    MissingTexCoords { mesh: String },
+    /// a mesh had no normals and they were generated
+    MissingNormals { mesh: String },
+    /// a mesh has triangles with no area
+    DegenerateTriangles { mesh: String, count: usize },
+    /// an instance's scale is zero, negative, not a number or outside `SANE_SCALES`
+    AbsurdScale { id: EntityId, scale: f32 },
+    /// a material's texture is bigger than the limit it was checked against
+    OversizedTexture { material: String, width: u32, height: u32, limit: u32 },
+}
+
+impl Problem {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Problem::MissingTexture { .. } | Problem::OversizedTexture { .. } => Severity::Error,
+            Problem::AbsurdScale { scale, .. } if !scale.is_finite() || *scale <= 0.0 => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingTexture { material, file } => write!(f, "material {} is missing its texture {}", material, file),
+            Problem::MissingTexCoords { mesh } => write!(f, "mesh {} has no uvs", mesh),
+            Problem::MissingNormals { mesh } => write!(f, "mesh {} has no normals, they were generated", mesh),
+            Problem::DegenerateTriangles { mesh, count } => write!(f, "mesh {} has {} degenerate triangles", mesh, count),
+            Problem::AbsurdScale { id, scale } => write!(f, "instance {} has a scale of {}", id.0, scale),
+            Problem::OversizedTexture { material, width, height, limit } => {
+                write!(f, "material {} has a {}x{} texture, over the limit of {}", material, width, height, limit)
+            }
+        }
+    }
+}
+
+/// A problem and the model it was found in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    /// index of the model, in load order
+    pub model: usize,
+    pub problem: Problem,
+}
+
+/// Everything `World::validate` found
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Whether nothing was found at all
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// How many issues have a severity
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues.iter().filter(|issue| issue.problem.severity() == severity).count()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut issues = self.issues.iter().collect::<Vec<_>>();
+        // errors first, otherwise in model order
+        issues.sort_by_key(|issue| std::cmp::Reverse(issue.problem.severity()));
+        for issue in issues {
+            let severity = match issue.problem.severity() {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+            writeln!(f, "{}: model {}: {}", severity, issue.model, issue.problem)?;
+        }
+        write!(f, "{} errors, {} warnings", self.count(Severity::Error), self.count(Severity::Warning))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3]) -> ModelVertex {
+        ModelVertex { position, tex_coords: [0.0; 2], normal: [0.0, 1.0, 0.0], color: [1.0; 3], tangent: [0.0; 4] }
+    }
+
+    #[test]
+    fn test_degenerate_triangles() {
+        let vertices = [vertex([0.0, 0.0, 0.0]), vertex([1.0, 0.0, 0.0]), vertex([0.0, 0.0, 1.0]), vertex([2.0, 0.0, 0.0])];
+        // a good triangle, one repeating a corner and one with its corners in a line
+        let indices = [0, 1, 2, 0, 0, 1, 0, 1, 3];
+        assert_eq!(degenerate_triangles(&vertices, &indices), 2);
+        assert_eq!(degenerate_triangles(&vertices, &indices[..3]), 0);
+    }
+
+    #[test]
+    fn test_report() {
+        let report = ValidationReport {
+            issues: vec![
+                Issue { model: 0, problem: Problem::MissingTexCoords { mesh: "cube.obj".to_string() } },
+                Issue { model: 2, problem: Problem::AbsurdScale { id: EntityId(4), scale: 0.0 } },
+                Issue { model: 3, problem: Problem::AbsurdScale { id: EntityId(5), scale: 5000.0 } },
+            ],
+        };
+
+        assert!(!report.is_clean());
+        assert_eq!(report.count(Severity::Error), 1);
+        assert_eq!(report.count(Severity::Warning), 2);
+        assert_eq!(
+            report.to_string(),
+            "error: model 2: instance 4 has a scale of 0\n\
+             warning: model 0: mesh cube.obj has no uvs\n\
+             warning: model 3: instance 5 has a scale of 5000\n\
+             1 errors, 2 warnings"
+        );
+        assert_eq!(ValidationReport::default().to_string(), "0 errors, 0 warnings");
+    }
+}
@@ -0,0 +1,72 @@
+//! The air the world sits in, how it catches the sun's light and hides what is far away
+
+/// How fog thickens with distance from the camera
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FogMode {
+    /// clear air all the way to the far plane
+    #[default]
+    Off,
+    /// none before `Fog::start`, then thickening evenly until everything is fog at `Fog::end`
+    Linear,
+    /// thickening ever more slowly past `Fog::start`, by `Fog::density` per unit
+    Exponential,
+}
+
+/// Fog fading faraway things into a color, so big worlds fade out before the far plane cuts them off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    /// color far away things fade into, in linear light, usually close to the sky's color at the horizon
+    pub color: [f32; 3],
+    /// how quickly exponential fog thickens
+    pub density: f32,
+    /// units from the camera the fog starts at
+    pub start: f32,
+    /// units from the camera linear fog hides everything at, keep it inside the camera's far plane
+    pub end: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::Off,
+            color: [0.5, 0.6, 0.7],
+            density: 0.05,
+            start: 20.0,
+            end: 90.0,
+        }
+    }
+}
+
+/// Settings for the space between the models
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Environment {
+    /// how thick the air is, light shafts from the sun show through it, 0 is clear air with no shafts
+    pub light_shaft_density: f32,
+    /// how much the air scatters the sun's light onward instead of to the sides, -1 is all back towards
+    /// the sun, 0 evenly every way and 1 straight on, so shafts are brightest looking into the sun
+    pub light_shaft_scattering: f32,
+    /// how strongly the environment map lights and reflects in surfaces, 0 uses the flat ambient light
+    /// instead, see `ibl`
+    pub sky_light: f32,
+    /// fog between the camera and what it sees
+    pub fog: Fog,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            light_shaft_density: 0.0,
+            light_shaft_scattering: 0.6,
+            sky_light: 1.0,
+            fog: Fog::default(),
+        }
+    }
+}
+
+impl Environment {
+    /// Whether the air is thick enough to show light shafts
+    pub fn has_light_shafts(&self) -> bool {
+        self.light_shaft_density > 0.0
+    }
+}
@@ -0,0 +1,184 @@
+//! Two-bone inverse kinematics, bending a chain of two bones so its end reaches a target
+//!
+//! Works on world space points, the root, the joint between the bones and the end of the chain.
+//! The bones keep their lengths and the joint bends towards a pole, like a knee pointing forward.
+//! An `IkChain` bends three instances of a model this way every frame, after the timeline moved them
+//! and their behaviors ran, so only what is drawn changes.
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use super::instance::{EntityId, Instance};
+
+/// Three instances of a model bent every frame so the last one reaches a target
+///
+/// The bones are as long as the instances are apart before bending, so moving the root moves the whole chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IkChain {
+    pub root: EntityId,
+    pub joint: EntityId,
+    pub end: EntityId,
+    /// where the end reaches for, set it from the host with `App::set_ik_target` or the `ik` console command
+    pub target: Point3<f32>,
+    /// the side the joint bends towards
+    pub pole: Point3<f32>,
+}
+
+impl IkChain {
+    /// Move the joint and the end among instances found by id, returns false if one of the chain's isn't there
+    pub fn apply(&self, instances: &mut [(EntityId, Instance)]) -> bool {
+        let find = |id: EntityId| instances.iter().position(|(other, _)| *other == id);
+        let (Some(root), Some(joint), Some(end)) = (find(self.root), find(self.joint), find(self.end)) else {
+            return false;
+        };
+        let point = |index: usize| Point3::from_vec(instances[index].1.position);
+        let solution = solve_two_bone(point(root), point(joint), point(end), self.target, self.pole);
+        instances[joint].1.position = solution.joint.to_vec();
+        instances[end].1.position = solution.end.to_vec();
+        true
+    }
+}
+
+/// Read the arguments of the `ik` console command, `<chain> <x> <y> <z>` moves a chain's target
+///
+/// Returns None for no arguments, which lists the chains.
+pub fn parse_command(arguments: &[&str]) -> anyhow::Result<Option<(usize, Point3<f32>)>> {
+    match arguments {
+        [] => Ok(None),
+        [chain, x, y, z] => Ok(Some((chain.parse()?, Point3::new(x.parse()?, y.parse()?, z.parse()?)))),
+        _ => anyhow::bail!("ik <chain> <x> <y> <z> moves a chain's target, ik lists them"),
+    }
+}
+
+/// Where the joint and the end of a chain go to reach a target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TwoBoneSolution {
+    pub joint: Point3<f32>,
+    pub end: Point3<f32>,
+    /// whether the end is on the target, false when the target is too far or too close to the root
+    pub reached: bool,
+}
+
+/// Bend a chain of two bones so its end reaches for a target
+///
+/// A target out of reach leaves the chain stretched towards it, or folded as far as it goes. A chain with
+/// a bone of no length, like two of its points on top of each other, can't bend and is left as it is.
+///
+/// Args:
+///     root, joint, end: the chain as it is, the bones' lengths are taken from it
+///     target: where the end should go
+///     pole: the side the joint bends towards, the joint's own side is kept if the pole is in line with the target
+pub fn solve_two_bone(root: Point3<f32>, joint: Point3<f32>, end: Point3<f32>, target: Point3<f32>, pole: Point3<f32>) -> TwoBoneSolution {
+    let upper = (joint - root).magnitude();
+    let lower = (end - joint).magnitude();
+    let max_reach = upper + lower;
+    if upper.min(lower) <= 1e-3 * max_reach || max_reach <= 1e-6 {
+        return TwoBoneSolution { joint, end, reached: (end - target).magnitude() <= 1e-3 * max_reach.max(1e-6) };
+    }
+    let to_target = target - root;
+    let distance = to_target.magnitude();
+    let Some(direction) = normalized(to_target).or_else(|| normalized(end - root)) else {
+        return TwoBoneSolution { joint, end, reached: distance == 0.0 && end == root };
+    };
+
+    // a fully straight or folded chain has no bend to point anywhere, so keep a hair away from either
+    let min_reach = (upper - lower).abs();
+    let reach = distance.clamp(min_reach + 1e-4 * max_reach, max_reach * (1.0 - 1e-4));
+
+    let bend = [pole - root, joint - root].into_iter()
+        .find_map(|side| normalized(side - direction * side.dot(direction)))
+        .unwrap_or_else(|| any_perpendicular(direction));
+
+    // the angle at the root from the triangle's three sides
+    let cos_root = ((upper * upper + reach * reach - lower * lower) / (2.0 * upper * reach)).clamp(-1.0, 1.0);
+    let sin_root = (1.0 - cos_root * cos_root).sqrt();
+    let joint = root + direction * (upper * cos_root) + bend * (upper * sin_root);
+    let end = root + direction * reach;
+    let tolerance = 1e-3 * max_reach.max(1e-6);
+    TwoBoneSolution { joint, end, reached: (end - target).magnitude() <= tolerance }
+}
+
+/// A vector's direction, None for vectors too short to have one
+fn normalized(vector: Vector3<f32>) -> Option<Vector3<f32>> {
+    let length = vector.magnitude();
+    (length > 1e-6).then(|| vector / length)
+}
+
+/// Some direction at a right angle to a unit vector
+fn any_perpendicular(direction: Vector3<f32>) -> Vector3<f32> {
+    let axis = if direction.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    direction.cross(axis).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_two_bone() {
+        // a leg standing straight down, knee pointing forward along -z
+        let root = Point3::new(0.0, 2.0, 0.0);
+        let joint = Point3::new(0.0, 1.0, 0.0);
+        let end = Point3::new(0.0, 0.0, 0.0);
+        let pole = Point3::new(0.0, 1.0, -5.0);
+
+        // lifting the foot bends the knee forward and keeps both bones their length
+        let target = Point3::new(0.0, 0.5, 0.0);
+        let solution = solve_two_bone(root, joint, end, target, pole);
+        assert!(solution.reached);
+        assert!((solution.end - target).magnitude() < 1e-3);
+        assert!(((solution.joint - root).magnitude() - 1.0).abs() < 1e-4);
+        assert!(((solution.end - solution.joint).magnitude() - 1.0).abs() < 1e-4);
+        assert!(solution.joint.z < -0.5);
+
+        // a target out of reach leaves the leg stretched towards it
+        let solution = solve_two_bone(root, joint, end, Point3::new(3.0, 2.0, 0.0), pole);
+        assert!(!solution.reached);
+        assert!((solution.end - Point3::new(2.0, 2.0, 0.0)).magnitude() < 1e-3);
+
+        // with the pole in line with the target the knee keeps to the side it was bent to
+        let bent = Point3::new(0.5, 1.0, 0.0);
+        let solution = solve_two_bone(root, bent, end, target, Point3::new(0.0, -4.0, 0.0));
+        assert!(solution.joint.x > 0.5);
+    }
+
+    #[test]
+    fn test_degenerate_chain() {
+        let (root, end, target, pole) = (Point3::new(0.0, 2.0, 0.0), Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 0.0), Point3::new(0.0, 1.0, -5.0));
+        // a joint on the root, on the end, or a hair away from one of them leaves the chain alone
+        for joint in [root, end, Point3::new(0.0, 1e-5, 0.0)] {
+            let solution = solve_two_bone(root, joint, end, target, pole);
+            assert_eq!((solution.joint, solution.end, solution.reached), (joint, end, false));
+        }
+        let solution = solve_two_bone(root, root, root, root, pole);
+        assert_eq!((solution.joint, solution.end, solution.reached), (root, root, true));
+
+        // the same instance used twice is a bone of no length
+        let instance = Instance { position: (0.0, 1.0, 0.0).into(), rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0), scale: 1.0 };
+        let mut instances = vec![(EntityId(1), instance.clone()), (EntityId(2), Instance { position: (0.0, 3.0, 0.0).into(), ..instance })];
+        let chain = IkChain { root: EntityId(1), joint: EntityId(1), end: EntityId(2), target, pole };
+        assert!(chain.apply(&mut instances));
+        assert_eq!(instances[1].1.position, Vector3::new(0.0, 3.0, 0.0));
+    }
+
+    #[test]
+    fn test_chain_apply() {
+        let instance = |y: f32| Instance { position: (0.0, y, 0.0).into(), rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0), scale: 1.0 };
+        let mut instances = vec![(EntityId(7), instance(0.0)), (EntityId(3), instance(2.0)), (EntityId(5), instance(1.0))];
+        let target = Point3::new(0.0, 0.5, 0.0);
+        let chain = IkChain { root: EntityId(3), joint: EntityId(5), end: EntityId(7), target, pole: Point3::new(0.0, 1.0, -5.0) };
+        assert!(chain.apply(&mut instances));
+        assert!((Point3::from_vec(instances[0].1.position) - target).magnitude() < 1e-3);
+        assert!(instances[2].1.position.z < -0.5);
+        // the root stays where it was
+        assert_eq!(instances[1].1.position, Vector3::new(0.0, 2.0, 0.0));
+
+        assert!(!IkChain { end: EntityId(9), ..chain }.apply(&mut instances));
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command(&[]).unwrap(), None);
+        assert_eq!(parse_command(&["1", "0", "2.5", "-1"]).unwrap(), Some((1, Point3::new(0.0, 2.5, -1.0))));
+        assert!(parse_command(&["1", "0"]).is_err());
+        assert!(parse_command(&["one", "0", "0", "0"]).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+//! Plug-in point for model formats other than .obj
+//!
+//! An `AssetImporter` turns a file's bytes into vertices and plain materials. Registered importers
+//! are picked by the file's extension wherever a model is loaded: lines of `resources.txt`, their
+//! detail levels and the asset browser. Uploading, welding and tangents are left to `resources.rs`
+//! so every format comes out the same as an .obj.
+use std::{path::Path, sync::{Arc, Mutex}};
+
+use super::{plot::PlotImporter, ply::PlyImporter, resources::MeshData, stl::StlImporter, vox::VoxImporter};
+
+/// Reads one or more model formats
+pub trait AssetImporter: Send + Sync {
+    /// Extensions the importer reads, lower case without the dot
+    fn extensions(&self) -> &[&str];
+
+    /// Read a model from a file's bytes
+    ///
+    /// Args:
+    ///     file_name: name of the file, for messages and naming meshes
+    ///     bytes: the file's contents
+    fn import(&self, file_name: &str, bytes: &[u8]) -> anyhow::Result<ImportedModel>;
+}
+
+/// What an importer read, in the same Y-up space .obj files use
+#[derive(Debug, Default)]
+pub struct ImportedModel {
+    pub meshes: Vec<MeshData>,
+    /// materials the submeshes point at, a plain default one is made when this is empty
+    pub materials: Vec<ImportedMaterial>,
+}
+
+/// A material without any texture files
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportedMaterial {
+    pub name: String,
+    /// multiplied with the vertex colors
+    pub diffuse: [u8; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// draw the back faces too, for open meshes
+    pub double_sided: bool,
+}
+
+impl Default for ImportedMaterial {
+    fn default() -> Self {
+        Self { name: "default".to_string(), diffuse: [255; 4], metallic: 0.0, roughness: 0.5, double_sided: false }
+    }
+}
+
+/// Importers added with `register_importer`, looked at before the built in ones
+static REGISTERED: Mutex<Vec<Arc<dyn AssetImporter>>> = Mutex::new(Vec::new());
+
+/// Add an importer for more formats, it wins over built in importers of the same extension
+pub fn register_importer(importer: impl AssetImporter + 'static) {
+    REGISTERED.lock().unwrap().push(Arc::new(importer));
+}
+
+/// The importer for a file, None for .obj files and extensions nothing reads
+pub fn importer_for(file_name: &str) -> Option<Arc<dyn AssetImporter>> {
+    let extension = Path::new(file_name).extension()?.to_str()?.to_ascii_lowercase();
+    let registered = REGISTERED.lock().unwrap();
+    // later registrations win so a host can replace an importer it registered before
+    registered.iter().rev().cloned()
+        .chain(built_in())
+        .find(|importer| importer.extensions().contains(&extension.as_str()))
+}
+
+/// Whether a file is a model that can be loaded, an .obj or something an importer reads
+pub fn is_model_file(file_name: &str) -> bool {
+    let is_obj = Path::new(file_name).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("obj"));
+    is_obj || importer_for(file_name).is_some()
+}
+
+/// Undo the sRGB curve colors in files are usually stored with, vertex colors are used as linear
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Importers that ship with the playground
+fn built_in() -> [Arc<dyn AssetImporter>; 4] {
+    [Arc::new(StlImporter::default()), Arc::new(VoxImporter), Arc::new(PlyImporter), Arc::new(PlotImporter)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Empty;
+
+    impl AssetImporter for Empty {
+        fn extensions(&self) -> &[&str] {
+            &["empty"]
+        }
+
+        fn import(&self, _file_name: &str, _bytes: &[u8]) -> anyhow::Result<ImportedModel> {
+            Ok(ImportedModel::default())
+        }
+    }
+
+    #[test]
+    fn test_importer_for() {
+        assert!(importer_for("part.STL").is_some());
+        assert!(importer_for("castle.vox").is_some());
+        assert!(importer_for("scan.ply").is_some());
+        assert!(importer_for("sales.CSV").is_some());
+        assert!(importer_for("cube/cube.obj").is_none());
+        assert!(importer_for("no_extension").is_none());
+        assert!(is_model_file("cube/cube.obj"));
+        assert!(!is_model_file("scene.vox.txt"));
+
+        assert!(!is_model_file("model.empty"));
+        register_importer(Empty);
+        assert!(is_model_file("model.empty"));
+        assert!(importer_for("model.empty").unwrap().import("model.empty", &[]).unwrap().meshes.is_empty());
+    }
+}
@@ -1,5 +1,43 @@
-/// represent the instance of one model
+//! represent the instance of one model
+use cgmath::{EuclideanSpace, InnerSpace, Rotation};
 
+use super::model::BoundingBox;
+
+/// Stable handle for an instance that stays valid when other instances are removed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntityId(pub u32);
+
+/// First id handed to instances made in the viewer, far above any id the cube grid uses
+pub const SPAWNED_IDS: u32 = 1 << 31;
+
+/// First id of the crowd's agents, above the grid's and below the ones made in the viewer
+pub const CROWD_IDS: u32 = 1 << 30;
+
+/// Hands out ids that were never used before
+#[derive(Clone, Debug)]
+pub struct IdAllocator {
+    next: u32,
+}
+
+impl IdAllocator {
+    pub fn starting_at(first: u32) -> Self {
+        Self { next: first }
+    }
+
+    /// A fresh id
+    pub fn allocate(&mut self) -> EntityId {
+        let id = EntityId(self.next);
+        self.next += 1;
+        id
+    }
+
+    /// Make sure an id that came from somewhere else, like a saved scene, is never handed out
+    pub fn reserve(&mut self, id: EntityId) {
+        self.next = self.next.max(id.0 + 1);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Instance {
     pub position: cgmath::Vector3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
@@ -11,15 +49,150 @@ pub struct Instance {
 /// Represent the raw form of the instance that can be sent to gpu
 pub struct InstanceRaw {
     model: [[f32; 4]; 4],
+    /// how much of the instance is drawn with a dither pattern:
+    /// positive keeps that fraction of the pattern, negative keeps the opposite part, 1 draws everything
+    fade: f32,
 }
 
 impl Instance {
     /// Convert the instance to its raw form
     pub fn to_raw(&self) -> InstanceRaw {
+        self.to_raw_faded(1.0)
+    }
+
+    /// Convert the instance to its raw form with only part of it drawn, used to cross-fade
+    pub fn to_raw_faded(&self, fade: f32) -> InstanceRaw {
         InstanceRaw {
             model: (cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation) * cgmath::Matrix4::from_scale(self.scale)).into(),
+            fade,
         }
     }
+
+    /// Intersect a ray with the bounding sphere of this instance
+    ///
+    /// Args:
+    ///     origin: start of the ray
+    ///     direction: normalized direction of the ray
+    ///     radius: bounding radius of the model before scaling
+    ///
+    /// Returns the distance along the ray to the hit, if there is one in front of the origin
+    pub fn intersect_ray(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, radius: f32) -> Option<f32> {
+        let radius = radius * self.scale;
+        let to_center = self.position - cgmath::Vector3::new(origin.x, origin.y, origin.z);
+        let along = to_center.dot(direction);
+        let dist_squared = to_center.magnitude2() - along * along;
+        if dist_squared > radius * radius {
+            return None;
+        }
+
+        // take the near side of the sphere, or the far side if we are inside it
+        let half_chord = (radius * radius - dist_squared).sqrt();
+        if along - half_chord >= 0.0 {
+            Some(along - half_chord)
+        } else if along + half_chord >= 0.0 {
+            Some(along + half_chord)
+        } else {
+            None
+        }
+    }
+
+    /// Intersect a ray with this instance's bounding box, tighter than the sphere for boxy models
+    ///
+    /// Args:
+    ///     origin: start of the ray
+    ///     direction: normalized direction of the ray
+    ///     bounds: box around the model before it is placed
+    ///
+    /// Returns the distance along the ray to the hit, if there is one in front of the origin
+    pub fn intersect_box(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, bounds: &BoundingBox) -> Option<f32> {
+        // in the model's own space the box is axis aligned, scaling both the same keeps distances along the ray
+        let inverse = self.rotation.invert();
+        let local_origin = inverse.rotate_vector(origin - cgmath::Point3::new(self.position.x, self.position.y, self.position.z)) / self.scale;
+        let local_direction = inverse.rotate_vector(direction) / self.scale;
+
+        // clip the ray against each pair of faces in turn
+        let (mut near, mut far) = (0.0_f32, f32::INFINITY);
+        for axis in 0..3 {
+            if local_direction[axis].abs() < f32::EPSILON {
+                if local_origin[axis] < bounds.min[axis] || local_origin[axis] > bounds.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (bounds.min[axis] - local_origin[axis]) / local_direction[axis];
+            let t2 = (bounds.max[axis] - local_origin[axis]) / local_direction[axis];
+            near = near.max(t1.min(t2));
+            far = far.min(t1.max(t2));
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    /// Push a sphere out of this instance's bounding box
+    ///
+    /// Args:
+    ///     point: center of the sphere
+    ///     radius: radius of the sphere
+    ///     bounds: box around the model before it is placed
+    ///
+    /// Returns where the sphere has to move to, or None if it isn't touching the box
+    pub fn push_out(&self, point: cgmath::Point3<f32>, radius: f32, bounds: &BoundingBox) -> Option<cgmath::Point3<f32>> {
+        // work in the model's own space where the box is axis aligned
+        let local = self.rotation.invert().rotate_vector(point - cgmath::Point3::new(self.position.x, self.position.y, self.position.z)) / self.scale;
+        let local_radius = radius / self.scale;
+
+        let closest = cgmath::Vector3::new(
+            local.x.clamp(bounds.min.x, bounds.max.x),
+            local.y.clamp(bounds.min.y, bounds.max.y),
+            local.z.clamp(bounds.min.z, bounds.max.z),
+        );
+        let away = local - closest;
+
+        let pushed = if away.magnitude2() > 0.0 {
+            if away.magnitude2() >= local_radius * local_radius {
+                return None;
+            }
+            closest + away.normalize() * local_radius
+        } else {
+            // the center is inside the box, leave through the closest face
+            let mut pushed = local;
+            let faces = [
+                (local.x - bounds.min.x, 0, bounds.min.x - local_radius),
+                (bounds.max.x - local.x, 0, bounds.max.x + local_radius),
+                (local.y - bounds.min.y, 1, bounds.min.y - local_radius),
+                (bounds.max.y - local.y, 1, bounds.max.y + local_radius),
+                (local.z - bounds.min.z, 2, bounds.min.z - local_radius),
+                (bounds.max.z - local.z, 2, bounds.max.z + local_radius),
+            ];
+            let (_, axis, value) = faces.iter().min_by(|a, b| a.0.total_cmp(&b.0)).unwrap();
+            pushed[*axis] = *value;
+            pushed
+        };
+
+        Some(cgmath::Point3::new(self.position.x, self.position.y, self.position.z) + self.rotation.rotate_vector(pushed * self.scale))
+    }
+
+    /// How far along a direction the lowest corner of this instance's bounding box is
+    ///
+    /// Args:
+    ///     bounds: box around the model before it is placed
+    ///     up: normalized direction heights are measured along
+    pub fn lowest_point(&self, bounds: &BoundingBox, up: cgmath::Vector3<f32>) -> f32 {
+        self.corners(bounds).iter().map(|corner| corner.to_vec().dot(up)).fold(f32::INFINITY, f32::min)
+    }
+
+    /// The corners of a model's bounding box once it is placed, the box picking tests against
+    ///
+    /// Bit 0 of the index picks the larger x, bit 1 the larger y and bit 2 the larger z.
+    pub fn corners(&self, bounds: &BoundingBox) -> [cgmath::Point3<f32>; 8] {
+        std::array::from_fn(|corner| {
+            let pick = |bit: usize, axis: usize| if corner & bit == 0 { bounds.min[axis] } else { bounds.max[axis] };
+            let local = cgmath::Vector3::new(pick(1, 0), pick(2, 1), pick(4, 2));
+            cgmath::Point3::from_vec(self.position + self.rotation.rotate_vector(local * self.scale))
+        })
+    }
 }
 
 impl InstanceRaw {
@@ -57,6 +230,11 @@ impl InstanceRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32,
+                },
             ],
         }
     }
@@ -67,6 +245,15 @@ mod tests {
     use super::*;
     use cgmath::Rotation3;
 
+    #[test]
+    fn test_id_allocator() {
+        let mut ids = IdAllocator::starting_at(SPAWNED_IDS);
+        assert_eq!(ids.allocate(), EntityId(SPAWNED_IDS));
+        ids.reserve(EntityId(SPAWNED_IDS + 5));
+        ids.reserve(EntityId(3));
+        assert_eq!(ids.allocate(), EntityId(SPAWNED_IDS + 6));
+    }
+
     #[test]
     fn test_instance_conversion() {
         let position = cgmath::Vector3 {x:1.0, y: 2.0, z:3.0};
@@ -87,4 +274,71 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_intersect_ray() {
+        let position = cgmath::Vector3 {x: 0.0, y: 0.0, z: -5.0};
+        let rotation = cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0));
+
+        let inst = Instance {
+            position, rotation, scale: 2.0,
+        };
+        let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+
+        // looking straight at it hits the front of the scaled sphere
+        assert_eq!(inst.intersect_ray(origin, -cgmath::Vector3::unit_z(), 1.0), Some(3.0));
+        // looking away misses
+        assert_eq!(inst.intersect_ray(origin, cgmath::Vector3::unit_z(), 1.0), None);
+        // looking to the side misses
+        assert_eq!(inst.intersect_ray(origin, cgmath::Vector3::unit_x(), 1.0), None);
+    }
+
+    #[test]
+    fn test_intersect_box() {
+        let inst = Instance {
+            position: cgmath::Vector3::new(0.0, 0.0, -5.0),
+            rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_y(), cgmath::Deg(45.0)),
+            scale: 2.0,
+        };
+        let bounds = BoundingBox { min: cgmath::Vector3::new(-1.0, -1.0, -1.0), max: cgmath::Vector3::new(1.0, 1.0, 1.0) };
+        let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+
+        // turned 45 degrees the nearest edge sticks out by the half diagonal
+        let hit = inst.intersect_box(origin, -cgmath::Vector3::unit_z(), &bounds).unwrap();
+        assert!((hit - (5.0 - 2.0 * 2.0_f32.sqrt())).abs() < 1e-4);
+        assert_eq!(inst.intersect_box(origin, cgmath::Vector3::unit_z(), &bounds), None);
+        assert_eq!(inst.intersect_box(origin, cgmath::Vector3::unit_x(), &bounds), None);
+
+        // turning around the up axis doesn't lower the box, tipping it onto an edge does
+        assert!((inst.lowest_point(&bounds, cgmath::Vector3::unit_y()) + 2.0).abs() < 1e-4);
+        let tipped = Instance { rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(45.0)), ..inst };
+        assert!((tipped.lowest_point(&bounds, cgmath::Vector3::unit_y()) + 2.0 * 2.0_f32.sqrt()).abs() < 1e-4);
+        // the last corner is the largest on every axis of the model, turned and scaled with it
+        let corner = inst.corners(&bounds)[7];
+        assert!((corner - cgmath::Point3::new(2.0 * 2.0_f32.sqrt(), 2.0, -5.0)).magnitude() < 1e-4);
+
+        // from inside the box the hit is right where the ray starts
+        assert_eq!(inst.intersect_box(cgmath::Point3::new(0.0, 0.0, -5.0), cgmath::Vector3::unit_x(), &bounds), Some(0.0));
+    }
+
+    #[test]
+    fn test_push_out() {
+        let position = cgmath::Vector3 {x: 0.0, y: 0.0, z: 0.0};
+        let rotation = cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0));
+        let bounds = BoundingBox {
+            min: cgmath::Vector3::new(-1.0, -1.0, -1.0),
+            max: cgmath::Vector3::new(1.0, 1.0, 1.0),
+        };
+
+        let inst = Instance {
+            position, rotation, scale: 1.0,
+        };
+
+        // too far away to touch
+        assert_eq!(inst.push_out((0.0, 0.0, 2.0).into(), 0.5, &bounds), None);
+        // touching the front face gets pushed straight back
+        assert_eq!(inst.push_out((0.0, 0.0, 1.25).into(), 0.5, &bounds), Some((0.0, 0.0, 1.5).into()));
+        // inside gets pushed out of the closest face
+        assert_eq!(inst.push_out((0.0, 0.75, 0.0).into(), 0.5, &bounds), Some((0.0, 1.5, 0.0).into()));
+    }
 }
\ No newline at end of file
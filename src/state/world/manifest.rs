@@ -0,0 +1,106 @@
+//! Reading `resources.txt`, which lists the models to load one per line
+//!
+//! Only the standard library and anyhow are used here, so build.rs can include this file to find
+//! the files the `embed-assets` feature builds into the executable.
+use std::path::{Path, PathBuf};
+
+/// One line of `resources.txt`: a model and its lower detail versions
+#[derive(Debug, PartialEq)]
+pub struct ModelEntry<'a> {
+    pub file_name: &'a str,
+    /// lower detail .obj files and the camera distance each one starts at
+    pub lods: Vec<(&'a str, f32)>,
+    /// shade the model with the physically based pipeline
+    pub pbr: bool,
+}
+
+impl<'a> ModelEntry<'a> {
+    /// Parse `model.obj [pbr] [lod.obj distance]...`
+    pub fn parse(line: &'a str) -> anyhow::Result<Self> {
+        let mut words = line.split_whitespace().peekable();
+        let file_name = words.next().ok_or_else(|| anyhow::anyhow!("empty model line"))?;
+        let pbr = words.next_if_eq(&"pbr").is_some();
+
+        let mut lods = Vec::new();
+        while let Some(lod_file) = words.next() {
+            let distance = words
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("{} is missing its distance", lod_file))?
+                .parse::<f32>()?;
+            lods.push((lod_file, distance));
+        }
+
+        Ok(Self { file_name, lods, pbr })
+    }
+}
+
+/// Every file a line of `resources.txt` loads from, relative to the resource folder
+///
+/// That is the model, its detail levels, the .mtl files they name and the textures those use.
+///
+/// Args:
+///     read: the text of a file relative to the resource folder, None if it can't be read
+pub fn model_files(line: &str, read: impl Fn(&Path) -> Option<String>) -> anyhow::Result<Vec<PathBuf>> {
+    let entry = ModelEntry::parse(line)?;
+    let mut files = Vec::new();
+    for model_file in std::iter::once(entry.file_name).chain(entry.lods.iter().map(|(file, _)| *file)) {
+        let model_file = PathBuf::from(model_file);
+        let dir = model_file.parent().map(Path::to_path_buf).unwrap_or_default();
+        if model_file.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("obj")) {
+            let text = read(&model_file).unwrap_or_default();
+            for mtl in text.lines().filter_map(|line| line.trim().strip_prefix("mtllib ")) {
+                let mtl_file = dir.join(mtl.trim());
+                let text = read(&mtl_file).unwrap_or_default();
+                files.extend(text.lines().filter_map(texture_statement).map(|texture| dir.join(texture)));
+                files.push(mtl_file);
+            }
+        }
+        files.push(model_file);
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// The file a .mtl line points at if it's a texture, options before it are skipped
+fn texture_statement(line: &str) -> Option<&str> {
+    let mut words = line.split_whitespace();
+    let key = words.next()?;
+    let is_texture = key.starts_with("map_") || ["bump", "norm", "disp"].contains(&key);
+    if is_texture { words.last() } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that textures are found through the .mtl a model names
+    #[test]
+    fn test_model_files() {
+        let read = |file: &Path| match file.to_str()? {
+            "props/chair.obj" => Some("mtllib chair.mtl\nv 0 0 0".to_string()),
+            "props/chair.mtl" => Some("newmtl wood\nmap_Kd -s 2 2 1 wood.png\nbump normal.png\nKd 1 1 1".to_string()),
+            _ => None,
+        };
+        let files = model_files("props/chair.obj props/chair-low.stl 20", read).unwrap();
+        let expected = ["props/chair-low.stl", "props/chair.mtl", "props/chair.obj", "props/normal.png", "props/wood.png"];
+        assert_eq!(files, expected.map(PathBuf::from));
+    }
+
+    /// Test that model lines with detail levels are read properly
+    #[test]
+    fn test_parse_model_entry() {
+        let entry = ModelEntry::parse("cube/cube.obj cube/cube-low.obj 20 cube/cube-lower.obj 40.5").unwrap();
+        assert_eq!(entry.file_name, "cube/cube.obj");
+        assert_eq!(entry.lods, vec![("cube/cube-low.obj", 20.0), ("cube/cube-lower.obj", 40.5)]);
+
+        assert!(!entry.pbr);
+        assert_eq!(ModelEntry::parse("cube/hcube.obj").unwrap().lods, vec![]);
+
+        let entry = ModelEntry::parse("cube/cube.obj pbr cube/cube-low.obj 20").unwrap();
+        assert!(entry.pbr);
+        assert_eq!(entry.lods, vec![("cube/cube-low.obj", 20.0)]);
+        assert!(ModelEntry::parse("cube/cube.obj cube/cube-low.obj").is_err());
+        assert!(ModelEntry::parse("").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Distances and angles between points clicked on the models
+use cgmath::{InnerSpace, Point3};
+
+/// Points a measurement holds, two for a distance and a third for the angle at the second
+pub const MAX_MEASURE_POINTS: usize = 3;
+
+/// Points clicked on model surfaces while measuring
+///
+/// The first two points give a distance. A third point gives the angle between the lines
+/// from the second point to the other two, so the second point is the corner.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Measurement {
+    pub points: Vec<Point3<f32>>,
+}
+
+impl Measurement {
+    /// Add a point, starting a new measurement if this one is full
+    pub fn add(&mut self, point: Point3<f32>) {
+        if self.points.len() >= MAX_MEASURE_POINTS {
+            self.points.clear();
+        }
+        self.points.push(point);
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Distance between the first two points
+    pub fn distance(&self) -> Option<f32> {
+        match self.points[..] {
+            [a, b, ..] => Some((b - a).magnitude()),
+            _ => None,
+        }
+    }
+
+    /// Angle at the second point between the other two, in degrees
+    pub fn angle(&self) -> Option<f32> {
+        let [a, corner, b] = self.points[..] else {
+            return None;
+        };
+        let (to_a, to_b) = (a - corner, b - corner);
+        if to_a.magnitude2() < f32::EPSILON || to_b.magnitude2() < f32::EPSILON {
+            return None;
+        }
+        Some(cgmath::Deg::from(to_a.angle(to_b)).0)
+    }
+
+    /// Lines joining the points in the order they were clicked
+    pub fn segments(&self) -> impl Iterator<Item = (Point3<f32>, Point3<f32>)> + '_ {
+        self.points.windows(2).map(|pair| (pair[0], pair[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_and_angle() {
+        let mut measurement = Measurement::default();
+        measurement.add(Point3::new(3.0, 0.0, 0.0));
+        assert_eq!(measurement.distance(), None);
+
+        measurement.add(Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(measurement.distance(), Some(3.0));
+        assert_eq!(measurement.angle(), None);
+
+        measurement.add(Point3::new(0.0, 0.0, 2.0));
+        assert!((measurement.angle().unwrap() - 90.0).abs() < 1e-4);
+        assert_eq!(measurement.segments().count(), 2);
+
+        // a fourth click starts over
+        measurement.add(Point3::new(1.0, 1.0, 1.0));
+        assert_eq!(measurement.points, vec![Point3::new(1.0, 1.0, 1.0)]);
+    }
+
+    #[test]
+    fn test_angle_needs_separate_points() {
+        let point = Point3::new(1.0, 2.0, 3.0);
+        let measurement = Measurement { points: vec![point, point, Point3::new(0.0, 0.0, 0.0)] };
+        assert_eq!(measurement.distance(), Some(0.0));
+        assert_eq!(measurement.angle(), None);
+    }
+}
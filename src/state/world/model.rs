@@ -1,9 +1,14 @@
 /// Represent a model and how its rendered.
-use std::{ops::Range, rc::Rc};
+use std::{collections::{HashMap, HashSet}, ops::Range, sync::Arc};
 
+use cgmath::InnerSpace;
 use wgpu::util::DeviceExt;
 
-use super::{instance::{self, Instance}, texture};
+use winit::event::WindowEvent;
+
+use crate::state::render_settings::RenderSettings;
+
+use super::{behavior::{Behavior, BehaviorContext}, diagnostics::{self, MeshDiagnostics, Problem}, ik::IkChain, instance::{self, EntityId, Instance, InstanceRaw}, stats::{self, ModelStats}, stream::StreamTexture, texture};
 
 pub trait Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -15,6 +20,10 @@ pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    /// multiplied with the texture, white for models without vertex colors
+    pub color: [f32; 3],
+    /// direction u grows in along the surface, w flips the bitangent for mirrored uvs, zero without uvs
+    pub tangent: [f32; 4],
 }
 
 impl Vertex for ModelVertex {
@@ -36,98 +45,910 @@ impl Vertex for ModelVertex {
                     shader_location: 1, // texture field  
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 3, // normal field
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 2, // color field
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4, // tangent field
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
 
+/// Axis aligned box in a model's own space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min: cgmath::Vector3<f32>,
+    pub max: cgmath::Vector3<f32>,
+}
+
+impl BoundingBox {
+    /// Smallest box containing all the positions, or an empty box at the origin if there are none
+    pub fn from_positions(positions: impl Iterator<Item = [f32; 3]>) -> Self {
+        positions
+            .map(|p| BoundingBox { min: p.into(), max: p.into() })
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(BoundingBox { min: cgmath::Vector3::new(0.0, 0.0, 0.0), max: cgmath::Vector3::new(0.0, 0.0, 0.0) })
+    }
+
+    /// Smallest box containing both boxes
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min: cgmath::Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: cgmath::Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+}
+
+/// A lower detail version of a model's meshes, drawn when instances are far away
+pub struct LodLevel {
+    pub meshes: Vec<Mesh>,
+    /// camera distance this level starts being used at
+    pub distance: f32,
+    instance_buffers: FrameRing<wgpu::Buffer>,
+    instance_count: u32,
+}
+
+/// Work out which detail levels an instance is drawn with and how faded each one is
+///
+/// Args:
+///     distance: distance from the camera to the instance
+///     thresholds: distance each lower detail level starts at, in increasing order
+///     band: distance before a threshold over which the two levels cross-fade
+///
+/// Returns the levels to draw (0 is the full model) with their fade, see `Instance::to_raw_faded`
+pub fn lod_fades(distance: f32, thresholds: &[f32], band: f32) -> Vec<(usize, f32)> {
+    let level = thresholds.iter().filter(|threshold| distance >= **threshold).count();
+
+    // close to the next threshold both levels are drawn with opposite dither patterns
+    if let Some(next) = thresholds.get(level) {
+        let fade_start = next - band;
+        if band > 0.0 && distance > fade_start {
+            let remaining = 1.0 - (distance - fade_start) / band;
+            return vec![(level, remaining), (level + 1, -remaining)];
+        }
+    }
+    vec![(level, 1.0)]
+}
+
+/// Limit how much of a cross-fading instance is drawn, keeping the two detail levels from overlapping
+///
+/// Args:
+///     lod_fade: fade from `lod_fades`
+///     visible: fraction of the instance that should still be drawn
+pub fn scale_fade(lod_fade: f32, visible: f32) -> f32 {
+    if lod_fade >= 0.0 {
+        lod_fade * visible
+    } else {
+        // the incoming level draws the top part of the pattern, so shrink it from below
+        -(1.0 - (1.0 + lod_fade) * visible)
+    }
+}
+
+/// Distance covered by one bucket when sorting instances front to back
+pub const SORT_BUCKET_SIZE: f32 = 2.0;
+
+/// Roughly sort things by distance from the camera, closest first
+///
+/// Things are only sorted by which distance bucket they fall in, which is cheap and keeps the order of
+/// things in the same bucket from jumping around every frame.
+pub fn sort_front_to_back<T>(items: &mut [T], distance: impl Fn(&T) -> f32, bucket_size: f32) {
+    items.sort_by_cached_key(|item| (distance(item) / bucket_size) as u32);
+}
+
+/// Where each instance sits in an instance buffer sorted front to back
+///
+/// The order only changes when an instance moves into another distance bucket, so while the camera
+/// stays within its buckets single instances can still be rewritten in place.
+#[derive(Debug, Default)]
+struct DrawOrder {
+    /// distance bucket of each slot when the order was worked out, empty while the buffer is in slot order
+    buckets: Vec<u32>,
+    /// position in the buffer of each slot
+    positions: Vec<usize>,
+}
+
+impl DrawOrder {
+    /// Position in the buffer of a slot
+    fn position(&self, slot: usize) -> usize {
+        self.positions.get(slot).copied().unwrap_or(slot)
+    }
+
+    /// Slots in the order they are in the buffer
+    fn slots(&self, count: usize) -> Vec<usize> {
+        let mut slots = (0..count).collect::<Vec<_>>();
+        if !self.positions.is_empty() {
+            slots.sort_by_key(|slot| self.positions[*slot]);
+        }
+        slots
+    }
+
+    /// Sort by the buckets the slots are in now, returns whether the order changed
+    fn sort(&mut self, buckets: Vec<u32>) -> bool {
+        if buckets == self.buckets {
+            return false;
+        }
+        let mut slots = (0..buckets.len()).collect::<Vec<_>>();
+        slots.sort_by_key(|slot| buckets[*slot]);
+        let mut positions = vec![0; slots.len()];
+        for (position, slot) in slots.into_iter().enumerate() {
+            positions[slot] = position;
+        }
+        self.buckets = buckets;
+        let is_changed = positions != self.positions;
+        self.positions = positions;
+        is_changed
+    }
+
+    /// Go back to slot order, returns whether the buffer was sorted
+    fn clear(&mut self) -> bool {
+        self.buckets.clear();
+        !std::mem::take(&mut self.positions).is_empty()
+    }
+}
+
+/// What the camera sees this frame, used to decide what is worth drawing
+pub struct ViewContext<'a> {
+    /// where the camera currently is
+    pub camera_eye: cgmath::Point3<f32>,
+    /// pixels a unit long object covers one unit from the camera, see `Camera::pixels_per_unit`
+    pub pixels_per_unit: f32,
+    /// sizes on screen don't shrink with distance, `pixels_per_unit` holds at every distance
+    pub is_orthographic: bool,
+    /// global thresholds for skipping tiny instances
+    pub render: &'a RenderSettings,
+}
+
+/// Frames the GPU may still be drawing while the next one is written, the surface is configured with it
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// One of something for each frame in flight, used in turn
+///
+/// Buffers rewritten every frame are kept in one of these so a frame never writes the buffer the
+/// GPU may still be drawing an earlier frame from, which would have to wait for it. An item that
+/// missed a change made while another one was current is marked stale until it is filled again.
+#[derive(Debug)]
+struct FrameRing<T> {
+    items: [T; FRAMES_IN_FLIGHT],
+    stale: [bool; FRAMES_IN_FLIGHT],
+    current: usize,
+}
+
+impl<T> FrameRing<T> {
+    fn new(make: impl FnMut(usize) -> T) -> Self {
+        Self { items: std::array::from_fn(make), stale: [false; FRAMES_IN_FLIGHT], current: 0 }
+    }
+
+    /// Move on to the next item, call once a frame before writing it
+    fn advance(&mut self) {
+        self.current = (self.current + 1) % FRAMES_IN_FLIGHT;
+    }
+
+    fn current(&self) -> &T {
+        &self.items[self.current]
+    }
+
+    fn current_mut(&mut self) -> &mut T {
+        &mut self.items[self.current]
+    }
+
+    /// Note that only the current item saw a change
+    fn mark_others_stale(&mut self) {
+        self.stale = std::array::from_fn(|i| i != self.current);
+    }
+
+    /// Note that every item, the current one too, has to be filled again
+    fn mark_all_stale(&mut self) {
+        self.stale = [true; FRAMES_IN_FLIGHT];
+    }
+
+    /// Whether the current item missed a change, it counts as filled again afterwards
+    fn take_stale(&mut self) -> bool {
+        std::mem::take(&mut self.stale[self.current])
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter()
+    }
+}
+
+/// make an empty instance buffer that can hold some instances
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("LOD Instance Buffer"),
+        size: (capacity.max(1) * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// make an instance buffer for every frame in flight, each holding the instances
+fn create_instance_buffers(device: &wgpu::Device, instances: &[Instance]) -> FrameRing<wgpu::Buffer> {
+    let instance_data = instances.iter().map(instance::Instance::to_raw).collect::<Vec<_>>();
+    FrameRing::new(|_| device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        }
+    ))
+}
+
 /// Represent a model
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
     pub visible: bool,
+    /// radius of a sphere around the origin containing every mesh
+    pub bounding_radius: f32,
+    /// box containing every mesh
+    pub bounding_box: BoundingBox,
     instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
+    /// the instances drawn, one buffer per frame in flight
+    instance_buffers: FrameRing<wgpu::Buffer>,
+    /// id of the instance stored in each slot of the instance buffer
+    slot_ids: Vec<EntityId>,
+    /// slot in the instance buffer for each id, so ids survive compaction
+    id_slots: HashMap<EntityId, usize>,
+    next_id: u32,
+    /// scripts attached to individual instances
+    behaviors: HashMap<EntityId, Vec<Box<dyn Behavior>>>,
+    /// instances that are skipped when drawing and picking
+    hidden: HashSet<EntityId>,
+    /// lower detail versions, ordered by distance
+    lods: Vec<LodLevel>,
+    /// distance over which detail levels cross-fade instead of popping
+    pub lod_fade_band: f32,
+    /// how many instances at the start of the instance buffer get drawn
+    draw_count: u32,
+    /// where each slot is in the instance buffer while every instance is drawn sorted front to back
+    draw_order: DrawOrder,
     /// device this model is rendered with
-    device: Rc<wgpu::Device>,
+    device: Arc<wgpu::Device>,
 }
 
 impl Model {
     /// make a new model
-    pub fn new(meshes: Vec<Mesh>, materials: Vec<Material>, device: Rc<wgpu::Device>) -> Model{
+    pub fn new(meshes: Vec<Mesh>, materials: Vec<Material>, device: Arc<wgpu::Device>) -> Model{
         // No instances to start
         let instances = Vec::new();
+        let instance_buffers = create_instance_buffers(&device, &instances);
 
-        let instance_data = instances.iter().map(instance::Instance::to_raw).collect::<Vec<_>>();
-
-        // create instance buffer
-        let instance_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
+        let bounding_radius = meshes.iter().map(|mesh| mesh.bounding_radius).fold(0.0, f32::max);
+        let bounding_box = meshes.iter().map(|mesh| mesh.bounding_box).reduce(|a, b| a.union(&b))
+            .unwrap_or(BoundingBox::from_positions(std::iter::empty()));
 
         Self {
             meshes,
             materials,
             visible:true,
+            bounding_radius,
+            bounding_box,
             instances,
-            instance_buffer,
+            instance_buffers,
+            slot_ids: Vec::new(),
+            id_slots: HashMap::new(),
+            next_id: 0,
+            behaviors: HashMap::new(),
+            hidden: HashSet::new(),
+            lods: Vec::new(),
+            lod_fade_band: 4.0,
+            draw_count: 0,
+            draw_order: DrawOrder::default(),
             device,
         }
     }
 
+    /// Add a lower detail version of the model used from a camera distance onwards
+    pub fn add_lod(&mut self, mut meshes: Vec<Mesh>, distance: f32) {
+        let material = self.material();
+        for submesh in meshes.iter_mut().flat_map(|mesh| mesh.submeshes.iter_mut()) {
+            submesh.material = material;
+        }
+
+        let instance_buffers = FrameRing::new(|_| create_instance_buffer(&self.device, self.instances.len()));
+        self.lods.push(LodLevel { meshes, distance, instance_buffers, instance_count: 0 });
+        self.lods.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    }
+
+
     /// set instances to something
+    ///
+    /// This replaces every instance, so ids are handed out again starting from 0 in the order given
     pub fn set_instances(&mut self, instances: Vec<Instance>) {
+        self.set_instances_with_ids(instances.into_iter().enumerate().map(|(i, instance)| (EntityId(i as u32), instance)).collect());
+    }
 
-        let instance_data = instances.iter().map(instance::Instance::to_raw).collect::<Vec<_>>();
-        
-        // create instance buffer
-        let instance_buffer = self.device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
+    /// set instances to something while choosing their ids
+    ///
+    /// Behaviors stay attached to any id that is still present
+    pub fn set_instances_with_ids(&mut self, instances: Vec<(EntityId, Instance)>) {
+        let (ids, instances): (Vec<_>, Vec<_>) = instances.into_iter().unzip();
+        let instance_buffers = create_instance_buffers(&self.device, &instances);
 
+        self.next_id = ids.iter().map(|id| id.0 + 1).max().unwrap_or(0);
+        self.id_slots = ids.iter().enumerate().map(|(slot, id)| (*id, slot)).collect();
+        self.slot_ids = ids;
+        self.behaviors.retain(|id, _| self.id_slots.contains_key(id));
+        self.draw_count = instances.len() as u32;
+        self.draw_order = DrawOrder::default();
         self.instances = instances;
-        self.instance_buffer = instance_buffer;
+        self.instance_buffers = instance_buffers;
     }
 
     /// Add a new instance
-    pub fn add_instances(&mut self, instance: Instance) {
+    pub fn add_instances(&mut self, instance: Instance) -> EntityId {
         self.instances.push(instance);
 
-        let instance_data = self.instances.iter().map(instance::Instance::to_raw).collect::<Vec<_>>();
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+        self.id_slots.insert(id, self.slot_ids.len());
+        self.slot_ids.push(id);
+        self.draw_count = self.instances.len() as u32;
+        self.draw_order = DrawOrder::default();
+        self.instance_buffers = create_instance_buffers(&self.device, &self.instances);
+        id
+    }
+
+    /// Remove an instance without rebuilding the instance buffer
+    ///
+    /// The last instance is moved into the freed slot and only that slot is rewritten on the GPU,
+    /// ids of every other instance stay valid. Returns false if the id doesn't exist.
+    pub fn remove_instance(&mut self, id: EntityId, queue: &wgpu::Queue) -> bool {
+        let slot = match self.id_slots.remove(&id) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        self.instances.swap_remove(slot);
+        self.slot_ids.swap_remove(slot);
+        self.behaviors.remove(&id);
+        self.draw_count = self.instances.len() as u32;
+
+        // if something was moved into the hole, point its id at the new slot and upload it
+        if slot < self.instances.len() {
+            self.id_slots.insert(self.slot_ids[slot], slot);
+        }
+        // a sorted buffer has a hole in the middle, it is sorted again on the next frame
+        if self.draw_order.clear() {
+            self.instance_buffers.mark_all_stale();
+        } else if slot < self.instances.len() {
+            self.write_slot(slot, queue);
+        }
+
+        true
+    }
+
+    /// Attach a behavior to an instance, returns false if the id doesn't exist
+    pub fn attach_behavior(&mut self, id: EntityId, mut behavior: Box<dyn Behavior>) -> bool {
+        match self.id_slots.get(&id) {
+            Some(slot) => {
+                behavior.on_spawn(&self.instances[*slot]);
+                self.behaviors.entry(id).or_default().push(behavior);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of the behaviors attached to an instance, in the order they run
+    pub fn behavior_names(&self, id: EntityId) -> Vec<&'static str> {
+        self.behaviors.get(&id).map_or_else(Vec::new, |behaviors| behaviors.iter().map(|behavior| behavior.name()).collect())
+    }
+
+    /// Choose which instances are hidden, they stay hidden when the instances are replaced
+    ///
+    /// Takes effect from the next `prepare`.
+    pub fn set_hidden(&mut self, hidden: HashSet<EntityId>) {
+        self.hidden = hidden;
+    }
+
+    /// Let every attached behavior see a window event, returns true if any of them handled it
+    pub fn behavior_event(&mut self, event: &WindowEvent) -> bool {
+        let mut result = false;
+        for behavior in self.behaviors.values_mut().flatten() {
+            result = behavior.on_event(event) || result;
+        }
+        result
+    }
+
+    /// Work out what gets drawn this frame and upload it
+    ///
+    /// Runs the behaviors, then bends the IK chains, fades out or skips instances too small on screen and sorts
+    /// the rest into detail levels. The stored instances are left alone, this only changes what gets drawn.
+    ///
+    /// Args:
+    ///     ik_chains: chains of the model's instances to bend, see `IkChain`
+    pub fn prepare(&mut self, context: &BehaviorContext, view: &ViewContext, ik_chains: &[IkChain], queue: &wgpu::Queue) {
+        for stream in self.materials.iter().filter_map(|material| material.stream.as_ref()) {
+            stream.update(queue);
+        }
+
+        // write the buffers the GPU is done with rather than waiting on the ones it may still be drawing from
+        self.instance_buffers.advance();
+        for lod in &mut self.lods {
+            lod.instance_buffers.advance();
+        }
+
+        let eye = cgmath::Vector3::new(view.camera_eye.x, view.camera_eye.y, view.camera_eye.z);
+
+        // once nothing is hidden anymore every instance has to be uploaded again, so the buffer is only left alone when it has all of them
+        let is_all_drawn = self.hidden.is_empty() && self.draw_count as usize == self.instances.len();
+        if self.behaviors.is_empty() && ik_chains.is_empty() && self.lods.is_empty() && view.render.min_instance_pixels <= 0.0 && is_all_drawn {
+            // only reordered when an instance moves into another distance bucket
+            let is_reordered = if view.render.sort_front_to_back {
+                let buckets = self.instances.iter().map(|instance| ((instance.position - eye).magnitude() / SORT_BUCKET_SIZE) as u32).collect();
+                self.draw_order.sort(buckets)
+            } else {
+                self.draw_order.clear()
+            };
+            if is_reordered {
+                self.instance_buffers.mark_all_stale();
+            }
+            if self.instance_buffers.take_stale() {
+                let instance_data = self.draw_order.slots(self.instances.len()).into_iter().map(|slot| self.instances[slot].to_raw()).collect::<Vec<_>>();
+                queue.write_buffer(self.instance_buffers.current(), 0, bytemuck::cast_slice(&instance_data));
+            }
+            return;
+        }
+
+        let hidden = &self.hidden;
+        let mut posed = self.instances.iter().zip(&self.slot_ids).filter(|(_, id)| !hidden.contains(id)).map(|(instance, id)| {
+            let mut instance = instance.clone();
+            if let Some(behaviors) = self.behaviors.get_mut(id) {
+                for behavior in behaviors {
+                    behavior.on_update(&mut instance, context);
+                }
+            }
+            (*id, instance)
+        }).collect::<Vec<_>>();
+
+        // chains reach from wherever the timeline and the behaviors put their roots, a hidden instance leaves its chain alone
+        for chain in ik_chains {
+            chain.apply(&mut posed);
+        }
+        let mut instances = posed.into_iter().map(|(_, instance)| instance).collect::<Vec<_>>();
+
+        // draw close instances first so the depth test can throw away the pixels hidden behind them
+        if view.render.sort_front_to_back {
+            sort_front_to_back(&mut instances, |instance| (instance.position - eye).magnitude(), SORT_BUCKET_SIZE);
+        }
+
+        // split the instances between the detail levels by how far they are from the camera
+        let thresholds = self.lods.iter().map(|lod| lod.distance).collect::<Vec<_>>();
+        let mut levels = vec![Vec::new(); self.lods.len() + 1];
+        for instance in &instances {
+            let distance = (instance.position - eye).magnitude();
+
+            // skip instances that would only cover a pixel or two, fading them out just before
+            let pixels = 2.0 * self.bounding_radius * instance.scale * view.pixels_per_unit / if view.is_orthographic { 1.0 } else { distance.max(f32::EPSILON) };
+            let Some(visible) = view.render.screen_size_fade(pixels) else {
+                continue;
+            };
+
+            for (level, fade) in lod_fades(distance, &thresholds, self.lod_fade_band) {
+                levels[level].push(instance.to_raw_faded(scale_fade(fade, visible)));
+            }
+        }
+
+        queue.write_buffer(self.instance_buffers.current(), 0, bytemuck::cast_slice(&levels[0]));
+        self.draw_count = levels[0].len() as u32;
+        // none of them hold every instance by slot now, this one included
+        self.draw_order.clear();
+        self.instance_buffers.mark_all_stale();
+
+        for (lod, data) in self.lods.iter_mut().zip(&levels[1..]) {
+            let needed = (data.len() * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+            if lod.instance_buffers.current().size() < needed {
+                *lod.instance_buffers.current_mut() = create_instance_buffer(&self.device, data.len());
+            }
+            queue.write_buffer(lod.instance_buffers.current(), 0, bytemuck::cast_slice(data));
+            lod.instance_count = data.len() as u32;
+        }
+    }
+
+    /// Upload one instance to the buffer drawn this frame, the others get every instance again on their turn
+    fn write_slot(&mut self, slot: usize, queue: &wgpu::Queue) {
+        let offset = (self.draw_order.position(slot) * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+        queue.write_buffer(self.instance_buffers.current(), offset, bytemuck::cast_slice(&[self.instances[slot].to_raw()]));
+        self.instance_buffers.mark_others_stale();
+    }
+
+    /// Count what the model holds and what it drew last frame
+    pub fn stats(&self) -> ModelStats {
+        let triangles = |meshes: &[Mesh]| meshes.iter().map(|mesh| mesh.num_elements as u64 / 3).sum::<u64>();
+        let mesh_bytes = |meshes: &[Mesh]| meshes.iter().map(|mesh| mesh.vertex_buffer.size() + mesh.index_buffer.size()).sum::<u64>();
+
+        let mut stats = ModelStats {
+            instances: self.instances.len(),
+            memory_bytes: mesh_bytes(&self.meshes)
+                + self.instance_buffers.iter().map(wgpu::Buffer::size).sum::<u64>()
+                + self.materials.iter().map(|m| stats::texture_bytes(&m.diffuse_texture.texture)).sum::<u64>(),
+            ..Default::default()
+        };
+        if self.visible {
+            stats.visible_instances = self.draw_count as usize;
+            stats.triangles = self.draw_count as u64 * triangles(&self.meshes);
+        }
+        for lod in &self.lods {
+            stats.memory_bytes += mesh_bytes(&lod.meshes) + lod.instance_buffers.iter().map(wgpu::Buffer::size).sum::<u64>();
+            if self.visible {
+                stats.visible_instances += lod.instance_count as usize;
+                stats.triangles += lod.instance_count as u64 * triangles(&lod.meshes);
+            }
+        }
+        stats
+    }
+
+    /// Problems with the model's meshes, materials and instances
+    ///
+    /// Args:
+    ///     max_texture_size: textures wider or taller than this are reported
+    pub fn validate(&self, max_texture_size: u32) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for material in &self.materials {
+            for file in &material.missing_textures {
+                problems.push(Problem::MissingTexture { material: material.name.clone(), file: file.clone() });
+            }
+            let textures = std::iter::once(&material.diffuse_texture).chain([&material.normal_texture]).chain(&material.pbr_textures);
+            for texture in textures.map(|t| &t.texture) {
+                if texture.width() > max_texture_size || texture.height() > max_texture_size {
+                    problems.push(Problem::OversizedTexture {
+                        material: material.name.clone(),
+                        width: texture.width(),
+                        height: texture.height(),
+                        limit: max_texture_size,
+                    });
+                }
+            }
+        }
+
+        let meshes = self.meshes.iter().chain(self.lods.iter().flat_map(|lod| &lod.meshes));
+        for mesh in meshes {
+            let name = || mesh.name.clone();
+            if !mesh.diagnostics.has_tex_coords {
+                problems.push(Problem::MissingTexCoords { mesh: name() });
+            }
+            if !mesh.diagnostics.has_normals {
+                problems.push(Problem::MissingNormals { mesh: name() });
+            }
+            if mesh.diagnostics.degenerate_triangles > 0 {
+                problems.push(Problem::DegenerateTriangles { mesh: name(), count: mesh.diagnostics.degenerate_triangles });
+            }
+        }
+
+        for (id, instance) in self.slot_ids.iter().zip(&self.instances) {
+            if !diagnostics::SANE_SCALES.contains(&instance.scale) {
+                problems.push(Problem::AbsurdScale { id: *id, scale: instance.scale });
+            }
+        }
+        problems
+    }
 
-        // create instance buffer
-        let instance_buffer = self.device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
+    /// Move a sphere so it doesn't overlap any instance, returns the new center
+    pub fn push_out(&self, mut point: cgmath::Point3<f32>, radius: f32) -> cgmath::Point3<f32> {
+        for (instance, _) in self.instances.iter().zip(&self.slot_ids).filter(|(_, id)| !self.hidden.contains(id)) {
+            if let Some(pushed) = instance.push_out(point, radius, &self.bounding_box) {
+                point = pushed;
             }
-        );
+        }
+        point
+    }
+
+    /// Find the closest instance hit by a ray
+    ///
+    /// Returns the id of the instance and the distance to it along the ray
+    pub fn pick(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Option<(EntityId, f32)> {
+        self.instances
+            .iter()
+            .enumerate()
+            .filter(|(slot, _)| !self.hidden.contains(&self.slot_ids[*slot]))
+            .filter_map(|(slot, instance)| {
+                instance
+                    .intersect_ray(origin, direction, self.bounding_radius)
+                    .map(|distance| (self.slot_ids[slot], distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// The closest point on an instance's bounding box along a ray, with the instance it is on
+    pub fn pick_point(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Option<(EntityId, cgmath::Point3<f32>)> {
+        self.pick_box(origin, direction).map(|(id, distance)| (id, origin + direction * distance))
+    }
+
+    /// The closest visible instance whose bounding box a ray hits, with how far along the ray it is
+    pub fn pick_box(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Option<(EntityId, f32)> {
+        self.placed_instances()
+            .filter_map(|(id, instance)| instance.intersect_box(origin, direction, &self.bounding_box).map(|distance| (id, distance)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// The stored instances that aren't hidden, with their ids
+    pub fn placed_instances(&self) -> impl Iterator<Item = (EntityId, &Instance)> + '_ {
+        self.slot_ids.iter().zip(&self.instances).filter(|(id, _)| !self.hidden.contains(id)).map(|(id, instance)| (*id, instance))
+    }
+
+    /// The stored transform of an instance, before behaviors
+    pub fn instance(&self, id: EntityId) -> Option<&Instance> {
+        self.id_slots.get(&id).map(|&slot| &self.instances[slot])
+    }
+
+    /// Every instance of the model, whether or not it was drawn last frame
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    /// Move one instance without rebuilding the instance buffer, returns false if the id doesn't exist
+    pub fn set_instance_position(&mut self, id: EntityId, position: cgmath::Vector3<f32>, queue: &wgpu::Queue) -> bool {
+        match self.instance(id) {
+            Some(instance) => {
+                let instance = Instance { position, ..instance.clone() };
+                self.set_instance(id, instance, queue)
+            }
+            None => false,
+        }
+    }
+
+    /// Replace one instance's transform without rebuilding the instance buffer, returns false if the id doesn't exist
+    pub fn set_instance(&mut self, id: EntityId, instance: Instance, queue: &wgpu::Queue) -> bool {
+        match self.id_slots.get(&id) {
+            Some(&slot) => {
+                self.instances[slot] = instance;
+                self.write_slot(slot, queue);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The material the model's first part is drawn with
+    pub fn material(&self) -> usize {
+        self.meshes[0].submeshes.first().map_or(0, |submesh| submesh.material)
+    }
+
+    /// Swap in meshes and materials loaded again from the files, instances and behaviors stay
+    ///
+    /// A single material picked for the whole model is kept if the new files still have it.
+    pub fn replace_assets(&mut self, loaded: Model) {
+        let submeshes = self.meshes.iter().flat_map(|mesh| &mesh.submeshes);
+        let picked = submeshes.clone().all(|submesh| submesh.material == self.material()).then(|| self.material());
+
+        self.meshes = loaded.meshes;
+        self.materials = loaded.materials;
+        self.bounding_radius = loaded.bounding_radius;
+        self.bounding_box = loaded.bounding_box;
+        // instance counts are filled in again by the next prepare
+        self.lods = loaded.lods;
+        if let Some(material) = picked {
+            self.set_material(material);
+        }
+    }
 
-        self.instance_buffer = instance_buffer;
+    /// Draw every part of the model and its lower detail versions with a specific material,
+    /// ignored if the model doesn't have it
+    pub fn set_material(&mut self, material: usize) {
+        if material < self.materials.len() {
+            let meshes = self.meshes.iter_mut().chain(self.lods.iter_mut().flat_map(|lod| lod.meshes.iter_mut()));
+            for submesh in meshes.flat_map(|mesh| mesh.submeshes.iter_mut()) {
+                submesh.material = material;
+            }
+        }
     }
 
     pub fn change_material(&mut self){
-        self.meshes[0].material = self.meshes[0].material + 1;
-        if self.materials.len() <=self.meshes[0].material {
-            self.meshes[0].material = 0;
+        let mut material = self.material() + 1;
+        if self.materials.len() <= material {
+            material = 0;
         }
+        self.set_material(material);
     }
 }
 
 /// represent the material for a model
 pub struct Material {
     pub name: String,
-    pub diffuse_texture: texture::Texture,
+    /// shared with other materials using the same file, see `resources::load_shared_texture`
+    pub diffuse_texture: Arc<texture::Texture>,
+    /// tangent space normals, flat for materials without a normal map
+    pub normal_texture: Arc<texture::Texture>,
+    /// metallic, roughness and ambient occlusion in the red channel of each, white without maps
+    pub pbr_textures: [Arc<texture::Texture>; 3],
+    /// how the material is lit, or if it only catches shadows
+    pub shading: Shading,
     pub bind_group: wgpu::BindGroup,
+    /// draw the back faces too, for thin geometry like leaves and flags
+    pub double_sided: bool,
+    /// offset for decals and overlays so they don't z-fight
+    pub depth_bias: DepthBiasPreset,
+    /// pixels with less alpha than this are cut out, for fences and foliage
+    pub alpha_cutoff: f32,
+    /// 0 for plastic, wood and the like, 1 for bare metal, only used by the pbr pipeline
+    pub metallic: f32,
+    /// 0 for a mirror finish, 1 for chalk, only used by the pbr pipeline
+    pub roughness: f32,
+    /// holds the material's settings for the shader
+    pub uniform_buffer: wgpu::Buffer,
+    /// texture files that couldn't be loaded, plain textures are used in their place
+    pub missing_textures: Vec<String>,
+    /// frames from the host or a video shown on the diffuse texture, see `resources::load_stream`
+    pub stream: Option<StreamTexture>,
+}
+
+impl Material {
+    /// The pipeline this material needs
+    pub fn pipeline_variant(&self) -> PipelineVariant {
+        PipelineVariant { double_sided: self.double_sided, depth_bias: self.depth_bias, shading: self.shading }
+    }
+
+    /// Shadow catchers are drawn after everything else so they can darken what is behind them
+    pub fn is_shadow_catcher(&self) -> bool {
+        self.shading == Shading::ShadowCatcher
+    }
+
+    /// Change the alpha cutoff, 0 keeps every pixel
+    #[allow(unused)]
+    pub fn set_alpha_cutoff(&mut self, alpha_cutoff: f32, queue: &wgpu::Queue) {
+        self.alpha_cutoff = alpha_cutoff;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[MaterialUniform::new(alpha_cutoff, self.metallic, self.roughness)]));
+    }
+}
+
+/// Material settings as the shader sees them
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    alpha_cutoff: f32,
+    /// multiplied with the metallic map
+    metallic: f32,
+    /// multiplied with the roughness map
+    roughness: f32,
+    // uniforms have to be 16 byte aligned
+    _padding: f32,
+}
+
+impl MaterialUniform {
+    pub fn new(alpha_cutoff: f32, metallic: f32, roughness: f32) -> Self {
+        Self { alpha_cutoff, metallic, roughness, _padding: 0.0 }
+    }
+}
+
+/// How far geometry is pushed towards the camera so it wins against surfaces it sits on
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DepthBiasPreset {
+    /// no offset
+    None,
+    /// decals lying flat on a surface
+    Decal,
+    /// lines and debug geometry drawn over the edges of a surface
+    WireframeOverlay,
+}
+
+impl DepthBiasPreset {
+    pub const ALL: [DepthBiasPreset; 3] = [DepthBiasPreset::None, DepthBiasPreset::Decal, DepthBiasPreset::WireframeOverlay];
+
+    /// Read the name used for the preset in .mtl files
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(DepthBiasPreset::None),
+            "decal" => Some(DepthBiasPreset::Decal),
+            "wireframe" => Some(DepthBiasPreset::WireframeOverlay),
+            _ => None,
+        }
+    }
+
+    /// The depth bias for the pipeline, negative moves towards the camera since closer is smaller depth
+    pub fn state(self) -> wgpu::DepthBiasState {
+        match self {
+            DepthBiasPreset::None => wgpu::DepthBiasState::default(),
+            // slope scaling keeps decals on surfaces seen at a grazing angle from flickering
+            DepthBiasPreset::Decal => wgpu::DepthBiasState { constant: -4, slope_scale: -2.0, clamp: 0.0 },
+            DepthBiasPreset::WireframeOverlay => wgpu::DepthBiasState { constant: -1, slope_scale: -1.0, clamp: 0.0 },
+        }
+    }
+}
+
+/// How a material is lit
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Shading {
+    /// ambient and diffuse light
+    Basic,
+    /// physically based metallic/roughness lighting
+    Pbr,
+    /// invisible except for the sun's shadows, which are multiplied into whatever is behind it
+    ShadowCatcher,
+}
+
+impl Shading {
+    pub const ALL: [Shading; 3] = [Shading::Basic, Shading::Pbr, Shading::ShadowCatcher];
+}
+
+/// Everything about a material that needs its own pipeline
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineVariant {
+    /// draw both sides of every triangle
+    pub double_sided: bool,
+    pub depth_bias: DepthBiasPreset,
+    pub shading: Shading,
+}
+
+impl PipelineVariant {
+    /// Every variant a material can ask for
+    pub fn all() -> impl Iterator<Item = PipelineVariant> {
+        Shading::ALL.into_iter().flat_map(|shading| {
+            [false, true].into_iter().flat_map(move |double_sided| {
+                DepthBiasPreset::ALL.into_iter().map(move |depth_bias| PipelineVariant { double_sided, depth_bias, shading })
+            })
+        })
+    }
+
+    /// The fragment shader entry point the variant draws with
+    pub fn fragment_entry_point(self) -> &'static str {
+        match self.shading {
+            Shading::Basic => "fs_main",
+            Shading::Pbr => "fs_pbr",
+            Shading::ShadowCatcher => "fs_shadow_catcher",
+        }
+    }
+
+    /// How the variant's color is combined with what is already drawn
+    pub fn blend(self) -> wgpu::BlendState {
+        match self.shading {
+            // multiply, so shadows darken the background without covering it
+            Shading::ShadowCatcher => wgpu::BlendState {
+                color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::Src, operation: wgpu::BlendOperation::Add },
+                alpha: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+            },
+            _ => wgpu::BlendState::REPLACE,
+        }
+    }
+
+    /// Whether the variant hides what is drawn behind it later
+    pub fn writes_depth(self) -> bool {
+        self.shading != Shading::ShadowCatcher
+    }
+
+    /// Which faces the pipeline skips
+    pub fn cull_mode(self) -> Option<wgpu::Face> {
+        if self.double_sided {
+            None
+        } else {
+            Some(wgpu::Face::Back)
+        }
+    }
+}
+
+/// The pipelines materials can be drawn with, one per variant
+pub struct MaterialPipelines {
+    pipelines: HashMap<PipelineVariant, wgpu::RenderPipeline>,
+    /// draw back faces of every material, so they can be seen through a section cut
+    pub force_double_sided: bool,
+}
+
+impl MaterialPipelines {
+    /// Make a pipeline for every variant
+    pub fn new(mut create: impl FnMut(PipelineVariant) -> wgpu::RenderPipeline) -> Self {
+        Self {
+            pipelines: PipelineVariant::all().map(|variant| (variant, create(variant))).collect(),
+            force_double_sided: false,
+        }
+    }
+
+    /// The pipeline a material needs
+    pub fn for_material(&self, material: &Material) -> &wgpu::RenderPipeline {
+        let mut variant = material.pipeline_variant();
+        variant.double_sided |= self.force_double_sided;
+        &self.pipelines[&variant]
+    }
 }
 
 /// represent the mesh for a model
@@ -136,25 +957,46 @@ pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
-    pub material: usize,
+    /// index ranges and the material each is drawn with
+    pub submeshes: Vec<Submesh>,
+    /// distance of the furthest vertex from the origin
+    pub bounding_radius: f32,
+    /// box containing every vertex
+    pub bounding_box: BoundingBox,
+    /// what loading noticed about the mesh, for `Model::validate`
+    pub diagnostics: MeshDiagnostics,
 }
 
 
+/// Part of a mesh drawn with one material
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submesh {
+    /// range in the mesh's index buffer
+    pub indices: Range<u32>,
+    pub material: usize,
+}
+
 /// interface for drawing our models
 pub trait DrawModel<'a> {
-    fn draw_mesh(&mut self, mesh: &'a Mesh, material: &'a Material, camera_bind_group: &'a wgpu::BindGroup);
+    fn draw_mesh(&mut self, mesh: &'a Mesh, materials: &'a [Material], pipelines: &'a MaterialPipelines, camera_bind_group: &'a wgpu::BindGroup);
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
-        material: &'a Material,
+        materials: &'a [Material],
         instances: Range<u32>,
+        pipelines: &'a MaterialPipelines,
         camera_bind_group: &'a wgpu::BindGroup,
     );
-    fn draw_model(&mut self, model: &'a Model, camera_bind_group: &'a wgpu::BindGroup);
+    fn draw_model(&mut self, model: &'a Model, pipelines: &'a MaterialPipelines, camera_bind_group: &'a wgpu::BindGroup);
+    /// draw only the shadow catcher materials, which `draw_model` skips
+    fn draw_model_shadow_catchers(&mut self, model: &'a Model, pipelines: &'a MaterialPipelines, camera_bind_group: &'a wgpu::BindGroup);
+    /// draw every detail level without touching pipelines or bind groups
+    fn draw_model_depth(&mut self, model: &'a Model);
     fn draw_model_instanced(
         &mut self,
         model: &'a Model,
         instances: Range<u32>,
+        pipelines: &'a MaterialPipelines,
         camera_bind_group: &'a wgpu::BindGroup,
     );
 }
@@ -168,37 +1010,95 @@ where
     /// 
     /// Args:
     ///     mesh: mesh to draw
-    ///     material: material to draw onto object
+    ///     materials: materials of the model, picked by the submeshes
+    ///     pipelines: pipelines the material picks from
     ///     camera_bind_group: camera group to render in
-    fn draw_mesh(&mut self, mesh: &'b Mesh, material: &'b Material, camera_bind_group: &'b wgpu::BindGroup) {
+    fn draw_mesh(&mut self, mesh: &'b Mesh, materials: &'b [Material], pipelines: &'b MaterialPipelines, camera_bind_group: &'b wgpu::BindGroup) {
 
-        self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group);
+        self.draw_mesh_instanced(mesh, materials, 0..1, pipelines, camera_bind_group);
     }
 
     /// Draws several instances of a model
     /// 
     /// Args:
     ///     mesh: mesh to draw
-    ///     material: material to drawn onto the object
+    ///     materials: materials of the model, each submesh is drawn with its own
     ///     instances: list of which instances to draw
+    ///     pipelines: pipelines the material picks from
     ///     camera_bind_group: camera information to render into the group
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'b Mesh,
-        material: &'b Material,
+        materials: &'b [Material],
         instances: Range<u32>,
+        pipelines: &'b MaterialPipelines,
         camera_bind_group: &'b wgpu::BindGroup,
     ){
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        self.set_bind_group(0, &material.bind_group, &[]);
         self.set_bind_group(1, camera_bind_group, &[]);
-        self.draw_indexed(0..mesh.num_elements, 0, instances);
+        // shadow catchers have to go after the sky, see draw_model_shadow_catchers
+        for submesh in mesh.submeshes.iter().filter(|submesh| !materials[submesh.material].is_shadow_catcher()) {
+            let material = &materials[submesh.material];
+            self.set_pipeline(pipelines.for_material(material));
+            self.set_bind_group(0, &material.bind_group, &[]);
+            self.draw_indexed(submesh.indices.clone(), 0, instances.clone());
+        }
     }
 
     /// Draw a model using its texture to a camera
-    fn draw_model(&mut self, model: &'b Model, camera_bind_group: &'b wgpu::BindGroup) {
-        self.draw_model_instanced(model, 0..model.instances.len() as u32, camera_bind_group);
+    fn draw_model(&mut self, model: &'b Model, pipelines: &'b MaterialPipelines, camera_bind_group: &'b wgpu::BindGroup) {
+        self.draw_model_instanced(model, 0..model.draw_count, pipelines, camera_bind_group);
+
+        // the far away instances are drawn with the simpler meshes
+        if model.visible {
+            for lod in &model.lods {
+                self.set_vertex_buffer(1, lod.instance_buffers.current().slice(..));
+                for mesh in &lod.meshes {
+                    self.draw_mesh_instanced(mesh, &model.materials, 0..lod.instance_count, pipelines, camera_bind_group);
+                }
+            }
+        }
+    }
+
+    /// Draw the parts of a model with shadow catcher materials, at every detail level
+    fn draw_model_shadow_catchers(&mut self, model: &'b Model, pipelines: &'b MaterialPipelines, camera_bind_group: &'b wgpu::BindGroup) {
+        if !model.visible || !model.materials.iter().any(Material::is_shadow_catcher) {
+            return;
+        }
+        self.set_bind_group(1, camera_bind_group, &[]);
+        let levels = std::iter::once((&model.meshes, model.instance_buffers.current(), model.draw_count))
+            .chain(model.lods.iter().map(|lod| (&lod.meshes, lod.instance_buffers.current(), lod.instance_count)));
+        for (meshes, instance_buffer, count) in levels {
+            self.set_vertex_buffer(1, instance_buffer.slice(..));
+            for mesh in meshes {
+                self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                for submesh in mesh.submeshes.iter().filter(|submesh| model.materials[submesh.material].is_shadow_catcher()) {
+                    let material = &model.materials[submesh.material];
+                    self.set_pipeline(pipelines.for_material(material));
+                    self.set_bind_group(0, &material.bind_group, &[]);
+                    self.draw_indexed(submesh.indices.clone(), 0, 0..count);
+                }
+            }
+        }
+    }
+
+    /// Draw a model's geometry only, every submesh at once since materials don't matter
+    fn draw_model_depth(&mut self, model: &'b Model) {
+        if !model.visible {
+            return;
+        }
+        let levels = std::iter::once((&model.meshes, model.instance_buffers.current(), model.draw_count))
+            .chain(model.lods.iter().map(|lod| (&lod.meshes, lod.instance_buffers.current(), lod.instance_count)));
+        for (meshes, instance_buffer, count) in levels {
+            self.set_vertex_buffer(1, instance_buffer.slice(..));
+            for mesh in meshes {
+                self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                self.draw_indexed(0..mesh.num_elements, 0, 0..count);
+            }
+        }
     }
 
     /// Draw a model using its texture to a camera
@@ -206,14 +1106,120 @@ where
         &mut self,
         model: &'b Model,
         instances: Range<u32>,
+        pipelines: &'b MaterialPipelines,
         camera_bind_group: &'b wgpu::BindGroup,
     ) {
         if model.visible {
             for mesh in &model.meshes {
-                let material = &model.materials[mesh.material];
-                self.set_vertex_buffer(1, model.instance_buffer.slice(..));
-                self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group);
+                self.set_vertex_buffer(1, model.instance_buffers.current().slice(..));
+                self.draw_mesh_instanced(mesh, &model.materials, instances.clone(), pipelines, camera_bind_group);
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lod_fades() {
+        let thresholds = [10.0, 20.0];
+
+        // well inside a level only that level is drawn
+        assert_eq!(lod_fades(5.0, &thresholds, 2.0), vec![(0, 1.0)]);
+        assert_eq!(lod_fades(15.0, &thresholds, 2.0), vec![(1, 1.0)]);
+        assert_eq!(lod_fades(30.0, &thresholds, 2.0), vec![(2, 1.0)]);
+
+        // halfway through the band both levels share the pixels
+        assert_eq!(lod_fades(9.0, &thresholds, 2.0), vec![(0, 0.5), (1, -0.5)]);
+        assert_eq!(lod_fades(19.5, &thresholds, 2.0), vec![(1, 0.25), (2, -0.25)]);
+
+        // no band means levels pop
+        assert_eq!(lod_fades(9.9, &thresholds, 0.0), vec![(0, 1.0)]);
+    }
+
+    #[test]
+    fn test_pipeline_variants() {
+        let variants = PipelineVariant::all().collect::<Vec<_>>();
+        assert_eq!(variants.len(), 18);
+        assert!(variants.contains(&PipelineVariant { double_sided: true, depth_bias: DepthBiasPreset::Decal, shading: Shading::Pbr }));
+        let basic = PipelineVariant { double_sided: false, depth_bias: DepthBiasPreset::None, shading: Shading::Basic };
+        assert_eq!(basic.fragment_entry_point(), "fs_main");
+        assert!(basic.writes_depth());
+
+        // shadow catchers multiply into what's behind and never hide it
+        let catcher = PipelineVariant { shading: Shading::ShadowCatcher, ..basic };
+        assert_eq!(catcher.fragment_entry_point(), "fs_shadow_catcher");
+        assert!(!catcher.writes_depth());
+        assert_eq!(catcher.blend().color.dst_factor, wgpu::BlendFactor::Src);
+
+        assert_eq!(DepthBiasPreset::parse("decal"), Some(DepthBiasPreset::Decal));
+        assert_eq!(DepthBiasPreset::parse("sideways"), None);
+
+        // biased geometry has to move towards the camera
+        for preset in [DepthBiasPreset::Decal, DepthBiasPreset::WireframeOverlay] {
+            assert!(preset.state().constant < 0);
+        }
+    }
+
+    #[test]
+    fn test_sort_front_to_back() {
+        let mut distances = vec![9.0, 1.5, 4.0, 0.5, 5.0];
+        sort_front_to_back(&mut distances, |d| *d, 2.0);
+
+        // 1.5 and 0.5 share a bucket so they keep their order, as do 4.0 and 5.0
+        assert_eq!(distances, vec![1.5, 0.5, 4.0, 5.0, 9.0]);
+    }
+
+    #[test]
+    fn test_draw_order() {
+        let mut order = DrawOrder::default();
+        assert_eq!((order.position(2), order.slots(3)), (2, vec![0, 1, 2]));
+
+        assert!(order.sort(vec![4, 0, 2]));
+        assert_eq!(order.slots(3), vec![1, 2, 0]);
+        assert_eq!(order.position(0), 2);
+        // the camera moving within the buckets changes nothing, so single slots can still be written in place
+        assert!(!order.sort(vec![4, 0, 2]));
+        // other buckets in the same order don't either
+        assert!(!order.sort(vec![5, 0, 3]));
+
+        assert!(order.clear());
+        assert_eq!(order.position(0), 0);
+        assert!(!order.clear());
+    }
+
+    #[test]
+    fn test_scale_fade() {
+        // fully visible instances keep their cross-fade
+        assert_eq!(scale_fade(1.0, 1.0), 1.0);
+        assert_eq!(scale_fade(-0.25, 1.0), -0.25);
+
+        // halfway faded out both levels draw half of their share, 0.5 in total
+        assert_eq!(scale_fade(1.0, 0.5), 0.5);
+        assert_eq!(scale_fade(0.25, 0.5), 0.125);
+        assert_eq!(scale_fade(-0.25, 0.5), -0.625);
+    }
+
+    #[test]
+    fn test_frame_ring() {
+        let mut ring = FrameRing::new(|i| i);
+        assert_eq!(*ring.current(), 0);
+
+        // an instance moved while the first buffer is current, the next one missed it
+        ring.mark_others_stale();
+        assert!(!ring.take_stale());
+        ring.advance();
+        assert_eq!(*ring.current(), 1);
+        assert!(ring.take_stale());
+        assert!(!ring.take_stale());
+
+        // every buffer gets a turn before the first comes around again
+        for _ in 0..FRAMES_IN_FLIGHT {
+            ring.advance();
+        }
+        assert_eq!(*ring.current(), 1);
+        assert_eq!(ring.iter().count(), FRAMES_IN_FLIGHT);
+    }
+}
@@ -0,0 +1,156 @@
+//! Build normals for meshes that were saved without them
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+
+use super::model::ModelVertex;
+
+/// Faces meeting at a sharper angle than this, in degrees, keep a hard edge between them
+pub const DEFAULT_SMOOTHING_ANGLE: f32 = 60.0;
+
+/// Give every vertex a normal from the triangles around it
+///
+/// Triangles touching the same position are smoothed together when their faces are within
+/// `smoothing_angle` degrees of each other, so a sphere comes out round while a cube keeps its
+/// edges. Corners of one vertex that end up with different normals are split into new vertices.
+///
+/// Args:
+///     vertices: vertices of the mesh, their normals are replaced
+///     indices: triangle list into the vertices, rewritten when vertices are split
+///     smoothing_angle: largest angle in degrees between faces that are shaded as one surface
+pub fn generate_normals(vertices: &mut Vec<ModelVertex>, indices: &mut [u32], smoothing_angle: f32) {
+    let position = |i: u32| Vector3::from(vertices[i as usize].position);
+
+    // area weighted face normals, the cross product's length is twice the triangle's area
+    let faces = indices
+        .chunks_exact(3)
+        .map(|t| (position(t[1]) - position(t[0])).cross(position(t[2]) - position(t[0])))
+        .collect::<Vec<_>>();
+
+    // faces around each position, vertices that only differ in uv or color still share one
+    let mut around: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (i, &index) in indices.iter().enumerate() {
+        around.entry(position_key(&vertices[index as usize])).or_default().push(i / 3);
+    }
+
+    let min_cos = smoothing_angle.to_radians().cos();
+    let mut split: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    // whether a vertex already got a normal, any other normal for it needs a copy
+    let mut claimed = vec![false; vertices.len()];
+    for (i, index) in indices.iter_mut().enumerate() {
+        let face = faces[i / 3];
+        let normal = around[&position_key(&vertices[*index as usize])]
+            .iter()
+            .map(|&other| faces[other])
+            .filter(|other| other.magnitude2() > 0.0 && cos_between(face, *other) >= min_cos)
+            .fold(Vector3::new(0.0, 0.0, 0.0), |sum, other| sum + other);
+        // a degenerate face smooths with everything around it, with nothing around it stays at zero
+        let normal: [f32; 3] = if normal.magnitude2() > 0.0 { normal.normalize().into() } else { [0.0; 3] };
+
+        let key = (*index, normal.map(f32::to_bits));
+        *index = match split.get(&key) {
+            Some(&existing) => existing,
+            // the first corner gets the original vertex, later corners with another normal get a copy
+            None => {
+                let vertex = &mut vertices[*index as usize];
+                let new_index = if claimed[*index as usize] {
+                    let mut copy = *vertex;
+                    copy.normal = normal;
+                    vertices.push(copy);
+                    vertices.len() as u32 - 1
+                } else {
+                    claimed[*index as usize] = true;
+                    vertex.normal = normal;
+                    *index
+                };
+                split.insert(key, new_index);
+                new_index
+            }
+        };
+    }
+}
+
+/// cosine of the angle between two face normals, a zero normal counts as matching anything
+fn cos_between(a: Vector3<f32>, b: Vector3<f32>) -> f32 {
+    if a.magnitude2() == 0.0 {
+        return 1.0;
+    }
+    a.normalize().dot(b.normalize())
+}
+
+/// a position that can be hashed, exact matches only
+fn position_key(vertex: &ModelVertex) -> [u32; 3] {
+    vertex.position.map(f32::to_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3]) -> ModelVertex {
+        ModelVertex { position, tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], color: [1.0, 1.0, 1.0], tangent: [0.0; 4] }
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5), "{:?} != {:?}", a, b);
+    }
+
+    /// A flat quad shares its vertices and every normal faces out of it
+    #[test]
+    fn test_flat_quad() {
+        let mut vertices = vec![vertex([0.0, 0.0, 0.0]), vertex([1.0, 0.0, 0.0]), vertex([1.0, 1.0, 0.0]), vertex([0.0, 1.0, 0.0])];
+        let mut indices = vec![0, 1, 2, 0, 2, 3];
+        generate_normals(&mut vertices, &mut indices, DEFAULT_SMOOTHING_ANGLE);
+
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, vec![0, 1, 2, 0, 2, 3]);
+        for v in &vertices {
+            assert_close(v.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    /// Two faces at a right angle keep a hard edge, so the shared vertices are split
+    #[test]
+    fn test_hard_edge() {
+        // floor facing +y and wall facing -z sharing the edge along x
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0]), vertex([1.0, 0.0, 0.0]), vertex([0.0, 0.0, -1.0]),
+            vertex([0.0, 1.0, 0.0]),
+        ];
+        let mut indices = vec![0, 1, 2, 0, 3, 1];
+        generate_normals(&mut vertices, &mut indices, DEFAULT_SMOOTHING_ANGLE);
+
+        assert_eq!(vertices.len(), 6);
+        for (i, &index) in indices.iter().enumerate() {
+            let expected = if i < 3 { [0.0, 1.0, 0.0] } else { [0.0, 0.0, -1.0] };
+            assert_close(vertices[index as usize].normal, expected);
+        }
+    }
+
+    /// Two faces at a shallow angle are smoothed into one normal along their shared edge
+    #[test]
+    fn test_smooth_edge() {
+        // same as the hard edge but with the wall tilted back so they meet at 30 degrees
+        let (sin, cos) = 30.0f32.to_radians().sin_cos();
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0]), vertex([1.0, 0.0, 0.0]), vertex([0.0, 0.0, -1.0]),
+            vertex([0.0, sin, cos]),
+        ];
+        let mut indices = vec![0, 1, 2, 0, 3, 1];
+        generate_normals(&mut vertices, &mut indices, DEFAULT_SMOOTHING_ANGLE);
+
+        assert_eq!(vertices.len(), 4);
+        // the edge is halfway between both faces, the far corners keep their own face's normal
+        let (half_sin, half_cos) = 15.0f32.to_radians().sin_cos();
+        assert_close(vertices[0].normal, [0.0, half_cos, -half_sin]);
+        assert_close(vertices[1].normal, [0.0, half_cos, -half_sin]);
+        assert_close(vertices[2].normal, [0.0, 1.0, 0.0]);
+        assert_close(vertices[3].normal, [0.0, cos, -sin]);
+
+        // lowering the threshold below the angle gives a hard edge instead
+        let mut vertices = vertices.iter().map(|v| vertex(v.position)).collect::<Vec<_>>();
+        let mut indices = vec![0, 1, 2, 0, 3, 1];
+        generate_normals(&mut vertices, &mut indices, 20.0);
+        assert_eq!(vertices.len(), 6);
+    }
+}
@@ -0,0 +1,660 @@
+//! Reads tables of numbers from .csv and .json files and builds 3D charts of them
+//!
+//! A table with `x`, `y` and `z` columns becomes a scatter plot: a sphere at each row's point,
+//! colored by its `value` column (or by its height without one) and sized by its `size` column if
+//! there is one. Any other table becomes bars on a grid, one per number with rows going back and
+//! columns going right, so a spreadsheet of monthly figures charts as it is laid out. Cells that
+//! aren't numbers, like headers and row names, are skipped.
+//!
+//! CSV files may start with a header row and use `,`, `;` or tabs between cells. JSON files are an
+//! array of flat objects, one per row, or an array of arrays of numbers. Both get axes with tick
+//! marks numbered with seven segment digits, colors run from dark blue for the smallest values to
+//! yellow for the largest. The chart is one model standing on the origin, so it can be placed,
+//! instanced and lit like any other.
+use super::{importer::{srgb_to_linear, AssetImporter, ImportedMaterial, ImportedModel}, model::{ModelVertex, Submesh}, resources::MeshData};
+
+/// Width and depth of the area charts are drawn over
+pub const CHART_SIZE: f32 = 4.0;
+
+/// Height of the tallest bar or of the scatter plot's highest point
+pub const CHART_HEIGHT: f32 = 2.0;
+
+/// Most bars or points in one chart, so a huge file doesn't make a mesh too big to draw
+pub const MAX_ITEMS: usize = 20_000;
+
+/// Tick marks along each axis, past the one at its start
+const TICKS: usize = 4;
+
+/// Share of each grid cell a bar covers
+const BAR_WIDTH: f32 = 0.7;
+
+/// Radius of scatter spheres, before their `size`
+const POINT_RADIUS: f32 = 0.05;
+
+/// Sides and rings of the spheres, few enough that thousands of points stay cheap
+const SPHERE_SEGMENTS: u32 = 10;
+const SPHERE_RINGS: u32 = 6;
+
+/// Thickness of the axes and tick marks
+const AXIS_WIDTH: f32 = 0.02;
+
+/// Height of the tick numbers
+const DIGIT_HEIGHT: f32 = 0.12;
+
+/// sRGB colors values are shaded with, from the smallest to the largest, like viridis
+const COLOR_STOPS: [[u8; 3]; 5] = [[68, 1, 84], [59, 82, 139], [33, 145, 140], [94, 201, 98], [253, 231, 37]];
+
+/// Linear colors of the axes and their numbers
+const AXIS_COLOR: [f32; 3] = [0.35, 0.35, 0.35];
+const LABEL_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+
+/// The built in importer for tables in .csv and .json files
+pub struct PlotImporter;
+
+impl AssetImporter for PlotImporter {
+    fn extensions(&self) -> &[&str] {
+        &["csv", "json"]
+    }
+
+    fn import(&self, file_name: &str, bytes: &[u8]) -> anyhow::Result<ImportedModel> {
+        let text = std::str::from_utf8(bytes)?;
+        let table = if file_name.to_ascii_lowercase().ends_with(".json") { parse_json(text)? } else { parse_csv(text) };
+        let chart = Chart::from_table(&table)?;
+        let mut mesh = MeshBuilder::default();
+        chart.build(&mut mesh);
+        let material = ImportedMaterial { name: "chart".to_string(), roughness: 0.6, ..Default::default() };
+        Ok(ImportedModel { meshes: vec![mesh.finish(file_name)], materials: vec![material] })
+    }
+}
+
+/// One cell of a table
+#[derive(Clone, Debug, PartialEq)]
+enum Cell {
+    Number(f32),
+    Text(String),
+}
+
+impl Cell {
+    fn number(&self) -> Option<f32> {
+        match self {
+            Cell::Number(value) => Some(*value),
+            Cell::Text(_) => None,
+        }
+    }
+}
+
+/// Rows of cells under column names, which are empty when the file had none
+#[derive(Debug, Default, PartialEq)]
+struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    /// Numbers of a named column, in row order, None when there is no such column
+    fn column(&self, name: &str) -> Option<Vec<Option<f32>>> {
+        let index = self.columns.iter().position(|column| column.trim().eq_ignore_ascii_case(name))?;
+        Some(self.rows.iter().map(|row| row.get(index).and_then(Cell::number)).collect())
+    }
+}
+
+/// Read a CSV file, the first line is the header when any of its cells isn't a number
+fn parse_csv(text: &str) -> Table {
+    let lines = text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).collect::<Vec<_>>();
+    let Some(first) = lines.first() else {
+        return Table::default();
+    };
+    let separator = [',', ';', '\t'].into_iter().max_by_key(|separator| first.matches(*separator).count()).unwrap_or(',');
+    let mut rows = lines.iter().map(|line| split_csv_line(line, separator).into_iter().map(|cell| parse_cell(&cell)).collect::<Vec<_>>());
+    let first = rows.next().unwrap_or_default();
+    if first.iter().all(|cell| matches!(cell, Cell::Number(_))) {
+        return Table { columns: Vec::new(), rows: std::iter::once(first).chain(rows).collect() };
+    }
+    let columns = first.into_iter().map(|cell| match cell {
+        Cell::Text(text) => text,
+        Cell::Number(value) => value.to_string(),
+    });
+    Table { columns: columns.collect(), rows: rows.collect() }
+}
+
+/// The cells of a line, quoted cells may hold the separator and `""` for a quote
+fn split_csv_line(line: &str, separator: char) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut is_quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let cell = cells.last_mut().unwrap();
+        match c {
+            '"' if is_quoted && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            }
+            '"' => is_quoted = !is_quoted,
+            c if c == separator && !is_quoted => cells.push(String::new()),
+            c => cell.push(c),
+        }
+    }
+    cells
+}
+
+fn parse_cell(text: &str) -> Cell {
+    let text = text.trim();
+    match text.parse::<f32>() {
+        Ok(value) if value.is_finite() => Cell::Number(value),
+        _ => Cell::Text(text.to_string()),
+    }
+}
+
+/// A JSON value, only as much as tables need
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f32),
+    String(String),
+    Array(Vec<Json>),
+    /// keys in the order they were written, which is the order of the table's columns
+    Object(Vec<(String, Json)>),
+}
+
+/// Read a JSON array of objects or of arrays into a table
+fn parse_json(text: &str) -> anyhow::Result<Table> {
+    let mut parser = JsonParser { text: text.as_bytes(), at: 0 };
+    let value = parser.value()?;
+    parser.skip_space();
+    if parser.at < text.len() {
+        anyhow::bail!("unexpected text after the JSON at byte {}", parser.at);
+    }
+    let Json::Array(items) = value else {
+        anyhow::bail!("expected an array of rows");
+    };
+    let cell = |value: &Json| match value {
+        Json::Number(value) => Cell::Number(*value),
+        Json::Bool(value) => Cell::Number(if *value { 1.0 } else { 0.0 }),
+        Json::String(text) => Cell::Text(text.clone()),
+        Json::Null | Json::Array(_) | Json::Object(_) => Cell::Text(String::new()),
+    };
+    let mut table = Table::default();
+    for item in &items {
+        match item {
+            Json::Array(values) => table.rows.push(values.iter().map(cell).collect()),
+            Json::Object(fields) => {
+                // a key first seen in a later row adds a column
+                for (key, _) in fields {
+                    if !table.columns.contains(key) {
+                        table.columns.push(key.clone());
+                    }
+                }
+                let row = table.columns.iter().map(|column| {
+                    fields.iter().find(|(key, _)| key == column).map_or(Cell::Text(String::new()), |(_, value)| cell(value))
+                });
+                table.rows.push(row.collect());
+            }
+            _ => anyhow::bail!("expected each row to be an object or an array"),
+        }
+    }
+    Ok(table)
+}
+
+/// Reads JSON a byte at a time
+struct JsonParser<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_space(&mut self) {
+        while self.text.get(self.at).is_some_and(u8::is_ascii_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    /// Skip a byte that has to come next
+    fn expect(&mut self, byte: u8) -> anyhow::Result<()> {
+        self.skip_space();
+        if self.text.get(self.at) != Some(&byte) {
+            anyhow::bail!("expected '{}' at byte {}", byte as char, self.at);
+        }
+        self.at += 1;
+        Ok(())
+    }
+
+    /// Skip a byte if it comes next
+    fn accept(&mut self, byte: u8) -> bool {
+        self.skip_space();
+        let is_next = self.text.get(self.at) == Some(&byte);
+        self.at += is_next as usize;
+        is_next
+    }
+
+    fn value(&mut self) -> anyhow::Result<Json> {
+        self.skip_space();
+        let rest = &self.text[self.at..];
+        for (word, value) in [("null", Json::Null), ("true", Json::Bool(true)), ("false", Json::Bool(false))] {
+            if rest.starts_with(word.as_bytes()) {
+                self.at += word.len();
+                return Ok(value);
+            }
+        }
+        match rest.first() {
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                if !self.accept(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.accept(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some(b'{') => {
+                self.at += 1;
+                let mut fields = Vec::new();
+                if !self.accept(b'}') {
+                    loop {
+                        self.skip_space();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        fields.push((key, self.value()?));
+                        if self.accept(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Object(fields))
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(_) => {
+                let length = rest.iter().position(|c| !(c.is_ascii_digit() || b"+-.eE".contains(c))).unwrap_or(rest.len());
+                let number = std::str::from_utf8(&rest[..length])?.parse::<f32>().map_err(|_| anyhow::anyhow!("expected a value at byte {}", self.at))?;
+                self.at += length;
+                Ok(Json::Number(number))
+            }
+            None => anyhow::bail!("the JSON ends early"),
+        }
+    }
+
+    /// A quoted string, with the escapes that turn up in table data
+    fn string(&mut self) -> anyhow::Result<String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self.text.get(self.at).ok_or_else(|| anyhow::anyhow!("string runs past the end"))?;
+            self.at += 1;
+            match byte {
+                b'"' => return Ok(String::from_utf8(bytes)?),
+                b'\\' => {
+                    let escaped = *self.text.get(self.at).ok_or_else(|| anyhow::anyhow!("string runs past the end"))?;
+                    self.at += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b't' => bytes.push(b'\t'),
+                        b'u' => {
+                            let code = self.text.get(self.at..self.at + 4).and_then(|hex| u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+                            let c = code.and_then(char::from_u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+                            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                            self.at += 4;
+                        }
+                        other => bytes.push(other),
+                    }
+                }
+                byte => bytes.push(byte),
+            }
+        }
+    }
+}
+
+/// What a table is drawn as
+#[derive(Debug, PartialEq)]
+enum Chart {
+    /// a grid of heights, rows of columns, None where a cell wasn't a number
+    Bars { values: Vec<Vec<Option<f32>>> },
+    /// points with the value their color comes from and how big they are
+    Scatter { points: Vec<[f32; 3]>, values: Vec<f32>, sizes: Vec<f32> },
+}
+
+impl Chart {
+    /// Pick the chart for a table, scatter when it has x, y and z columns
+    fn from_table(table: &Table) -> anyhow::Result<Chart> {
+        let chart = match (table.column("x"), table.column("y"), table.column("z")) {
+            (Some(x), Some(y), Some(z)) => {
+                let value = table.column("value");
+                let size = table.column("size");
+                let (mut points, mut values, mut sizes) = (Vec::new(), Vec::new(), Vec::new());
+                for row in 0..table.rows.len() {
+                    // rows missing a coordinate have nowhere to go
+                    let (Some(px), Some(py), Some(pz)) = (x[row], y[row], z[row]) else {
+                        continue;
+                    };
+                    points.push([px, py, pz]);
+                    values.push(value.as_ref().and_then(|value| value[row]).unwrap_or(py));
+                    sizes.push(size.as_ref().and_then(|size| size[row]).unwrap_or(1.0).max(0.0));
+                }
+                Chart::Scatter { points, values, sizes }
+            }
+            _ => Chart::Bars { values: table.rows.iter().map(|row| row.iter().map(Cell::number).collect()).collect() },
+        };
+        let count = match &chart {
+            Chart::Bars { values } => values.iter().flatten().flatten().count(),
+            Chart::Scatter { points, .. } => points.len(),
+        };
+        if count == 0 {
+            anyhow::bail!("the table has no numbers to chart");
+        }
+        if count > MAX_ITEMS {
+            anyhow::bail!("the table has {} numbers, charts are limited to {}", count, MAX_ITEMS);
+        }
+        Ok(chart)
+    }
+
+    fn build(&self, mesh: &mut MeshBuilder) {
+        match self {
+            Chart::Bars { values } => build_bars(mesh, values),
+            Chart::Scatter { points, values, sizes } => build_scatter(mesh, points, values, sizes),
+        }
+    }
+}
+
+/// Bars standing on a grid centered on the origin, scaled so the largest magnitude is `CHART_HEIGHT`
+fn build_bars(mesh: &mut MeshBuilder, values: &[Vec<Option<f32>>]) {
+    let (rows, columns) = (values.len(), values.iter().map(Vec::len).max().unwrap_or(0));
+    let numbers = || values.iter().flatten().flatten().copied();
+    let (min, max) = range(numbers());
+    let largest = numbers().map(f32::abs).fold(0.0, f32::max);
+    let height_scale = if largest > 0.0 { CHART_HEIGHT / largest } else { 0.0 };
+    let cell = CHART_SIZE / rows.max(columns) as f32;
+    let (left, back) = (-(columns as f32) * cell / 2.0, -(rows as f32) * cell / 2.0);
+    let half_bar = cell * BAR_WIDTH / 2.0;
+    for (row, row_values) in values.iter().enumerate() {
+        for (column, value) in row_values.iter().enumerate() {
+            let Some(value) = value else {
+                continue;
+            };
+            let (x, z) = (left + (column as f32 + 0.5) * cell, back + (row as f32 + 0.5) * cell);
+            // negative values hang below the floor
+            let height = value * height_scale;
+            mesh.cuboid([x - half_bar, height.min(0.0), z - half_bar], [x + half_bar, height.max(0.0), z + half_bar], colormap(normalize(*value, min, max)));
+        }
+    }
+
+    // the floor's edges and the height axis in the back left corner, numbered in the values' units
+    let (right, front) = (-left, -back);
+    mesh.cuboid([left, -AXIS_WIDTH, back - AXIS_WIDTH], [right, 0.0, back], AXIS_COLOR);
+    mesh.cuboid([left - AXIS_WIDTH, -AXIS_WIDTH, back], [left, 0.0, front], AXIS_COLOR);
+    let (bottom, top) = (min.min(0.0) * height_scale, max.max(0.0) * height_scale);
+    mesh.cuboid([left - AXIS_WIDTH, bottom, back - AXIS_WIDTH], [left, top, back], AXIS_COLOR);
+    if height_scale > 0.0 {
+        for tick in 0..=TICKS {
+            let height = bottom + (top - bottom) * tick as f32 / TICKS as f32;
+            mesh.cuboid([left - 4.0 * AXIS_WIDTH, height - AXIS_WIDTH / 2.0, back - AXIS_WIDTH], [left, height + AXIS_WIDTH / 2.0, back], AXIS_COLOR);
+            let label = format_tick(height / height_scale);
+            let width = text_width(&label, DIGIT_HEIGHT);
+            mesh.text(&label, [left - 6.0 * AXIS_WIDTH - width, height - DIGIT_HEIGHT / 2.0, back], DIGIT_HEIGHT, LABEL_COLOR);
+        }
+    }
+}
+
+/// Spheres fitted into a box of `CHART_SIZE` across and `CHART_HEIGHT` tall, with numbered axes along three edges
+fn build_scatter(mesh: &mut MeshBuilder, points: &[[f32; 3]], values: &[f32], sizes: &[f32]) {
+    let ranges = [0, 1, 2].map(|axis| range(points.iter().map(|point| point[axis])));
+    let (value_min, value_max) = range(values.iter().copied());
+    let extents = [CHART_SIZE, CHART_HEIGHT, CHART_SIZE];
+    // the box's corner nearest the origin of each axis, so the chart stands on the origin centered
+    let corner = [-CHART_SIZE / 2.0, 0.0, -CHART_SIZE / 2.0];
+    let place = |axis: usize, value: f32| corner[axis] + normalize(value, ranges[axis].0, ranges[axis].1) * extents[axis];
+    for ((point, value), size) in points.iter().zip(values).zip(sizes) {
+        let center = [0, 1, 2].map(|axis| place(axis, point[axis]));
+        mesh.sphere(center, POINT_RADIUS * size, colormap(normalize(*value, value_min, value_max)));
+    }
+
+    for axis in 0..3 {
+        let mut end = corner;
+        end[axis] += extents[axis];
+        let (mut min, mut max) = (corner, end);
+        for other in (0..3).filter(|other| *other != axis) {
+            min[other] -= AXIS_WIDTH / 2.0;
+            max[other] += AXIS_WIDTH / 2.0;
+        }
+        mesh.cuboid(min, max, AXIS_COLOR);
+        for tick in 0..=TICKS {
+            let share = tick as f32 / TICKS as f32;
+            let mut at = corner;
+            at[axis] += extents[axis] * share;
+            let (low, high) = ranges[axis];
+            let label = format_tick(low + (high - low) * share);
+            // numbers sit beside the heights and in front of the floor's edges, facing forward
+            let origin = match axis {
+                1 => [at[0] - 3.0 * AXIS_WIDTH - text_width(&label, DIGIT_HEIGHT), at[1] - DIGIT_HEIGHT / 2.0, at[2]],
+                _ => [at[0] - text_width(&label, DIGIT_HEIGHT) / 2.0, at[1] - 2.0 * DIGIT_HEIGHT, at[2]],
+            };
+            mesh.text(&label, origin, DIGIT_HEIGHT, LABEL_COLOR);
+        }
+    }
+}
+
+/// The smallest and largest of some numbers
+fn range(values: impl Iterator<Item = f32>) -> (f32, f32) {
+    values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| (min.min(value), max.max(value)))
+}
+
+/// Where a value is between the smallest and largest from 0 to 1, the middle when they are the same
+fn normalize(value: f32, min: f32, max: f32) -> f32 {
+    if max > min { (value - min) / (max - min) } else { 0.5 }
+}
+
+/// The linear color a value from 0 to 1 is shaded with
+fn colormap(share: f32) -> [f32; 3] {
+    let position = share.clamp(0.0, 1.0) * (COLOR_STOPS.len() - 1) as f32;
+    let index = (position as usize).min(COLOR_STOPS.len() - 2);
+    let blend = position - index as f32;
+    let (from, to) = (COLOR_STOPS[index], COLOR_STOPS[index + 1]);
+    [0, 1, 2].map(|channel| srgb_to_linear((from[channel] as f32 * (1.0 - blend) + to[channel] as f32 * blend) / 255.0))
+}
+
+/// A tick's number, with at most two decimals and none that are zero
+fn format_tick(value: f32) -> String {
+    let text = format!("{:.2}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" { "0".to_string() } else { text.to_string() }
+}
+
+/// Segments lit for each character, bits from a to g: top, upper right, lower right, bottom, lower left, upper left, middle
+fn segments(c: char) -> u8 {
+    match c {
+        '0' => 0b0111111,
+        '1' => 0b0000110,
+        '2' => 0b1011011,
+        '3' => 0b1001111,
+        '4' => 0b1100110,
+        '5' => 0b1101101,
+        '6' => 0b1111101,
+        '7' => 0b0000111,
+        '8' => 0b1111111,
+        '9' => 0b1101111,
+        '-' => 0b1000000,
+        _ => 0,
+    }
+}
+
+/// How far a character moves the next one along, in units of the text's height
+fn advance(c: char) -> f32 {
+    if c == '.' { 0.25 } else { 0.7 }
+}
+
+/// Width of a line of text at a height
+fn text_width(text: &str, height: f32) -> f32 {
+    text.chars().map(advance).sum::<f32>() * height
+}
+
+/// Builds one mesh of flat shaded boxes and smooth spheres with vertex colors
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<ModelVertex>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, position: [f32; 3], normal: [f32; 3], color: [f32; 3]) -> u32 {
+        self.vertices.push(ModelVertex { position, tex_coords: [0.0, 0.0], normal, color, tangent: [0.0; 4] });
+        self.vertices.len() as u32 - 1
+    }
+
+    /// A box between two corners, each face with its own vertices so the edges stay sharp
+    fn cuboid(&mut self, min: [f32; 3], max: [f32; 3], color: [f32; 3]) {
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for (side, sign) in [(min[axis], -1.0), (max[axis], 1.0)] {
+                let mut normal = [0.0; 3];
+                normal[axis] = sign;
+                let start = self.vertices.len() as u32;
+                for (du, dv) in [(min[u], min[v]), (max[u], min[v]), (max[u], max[v]), (min[u], max[v])] {
+                    let mut position = [0.0; 3];
+                    (position[axis], position[u], position[v]) = (side, du, dv);
+                    self.vertex(position, normal, color);
+                }
+                // u then v winds counter-clockwise seen from the positive side
+                let quad = if sign > 0.0 { [0, 1, 2, 0, 2, 3] } else { [0, 2, 1, 0, 3, 2] };
+                self.indices.extend(quad.map(|corner| start + corner));
+            }
+        }
+    }
+
+    /// A sphere of rings of quads, with smooth normals
+    fn sphere(&mut self, center: [f32; 3], radius: f32, color: [f32; 3]) {
+        let start = self.vertices.len() as u32;
+        for ring in 0..=SPHERE_RINGS {
+            let polar = std::f32::consts::PI * ring as f32 / SPHERE_RINGS as f32;
+            for segment in 0..=SPHERE_SEGMENTS {
+                let azimuth = std::f32::consts::TAU * segment as f32 / SPHERE_SEGMENTS as f32;
+                let normal = [polar.sin() * azimuth.cos(), polar.cos(), -polar.sin() * azimuth.sin()];
+                self.vertex([0, 1, 2].map(|axis| center[axis] + normal[axis] * radius), normal, color);
+            }
+        }
+        let row = SPHERE_SEGMENTS + 1;
+        for ring in 0..SPHERE_RINGS {
+            for segment in 0..SPHERE_SEGMENTS {
+                let (top, bottom) = (start + ring * row + segment, start + (ring + 1) * row + segment);
+                self.indices.extend([top, bottom, bottom + 1, top, bottom + 1, top + 1]);
+            }
+        }
+    }
+
+    /// Numbers as seven segment digits made of thin boxes, facing +Z from the bottom left corner
+    fn text(&mut self, text: &str, origin: [f32; 3], height: f32, color: [f32; 3]) {
+        let (width, thickness) = (height * 0.5, height * 0.12);
+        let half = height / 2.0;
+        // each segment's rectangle as left, bottom, right and top in the character
+        let rectangles = [
+            [0.0, height - thickness, width, height],
+            [width - thickness, half, width, height],
+            [width - thickness, 0.0, width, half],
+            [0.0, 0.0, width, thickness],
+            [0.0, 0.0, thickness, half],
+            [0.0, half, thickness, height],
+            [0.0, half - thickness / 2.0, width, half + thickness / 2.0],
+        ];
+        let mut x = origin[0];
+        for c in text.chars() {
+            let mut boxes = rectangles.iter().enumerate().filter(|(bit, _)| segments(c) & (1 << bit) != 0).map(|(_, rectangle)| *rectangle).collect::<Vec<_>>();
+            if c == '.' {
+                boxes.push([0.0, 0.0, thickness, thickness]);
+            }
+            for [left, bottom, right, top] in boxes {
+                self.cuboid([x + left, origin[1] + bottom, origin[2]], [x + right, origin[1] + top, origin[2] + thickness], color);
+            }
+            x += advance(c) * height;
+        }
+    }
+
+    fn finish(self, name: &str) -> MeshData {
+        MeshData {
+            name: name.to_string(),
+            submeshes: vec![Submesh { indices: 0..self.indices.len() as u32, material: 0 }],
+            vertices: self.vertices,
+            indices: self.indices,
+            has_tex_coords: false,
+            has_normals: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let table = parse_csv("# sales\nregion;Jan;Feb\n\"North; East\";10;12.5\nSouth;7;\n");
+        assert_eq!(table.columns, vec!["region", "Jan", "Feb"]);
+        assert_eq!(table.rows[0], vec![Cell::Text("North; East".to_string()), Cell::Number(10.0), Cell::Number(12.5)]);
+        assert_eq!(table.rows[1][2], Cell::Text(String::new()));
+        // all numbers is data, not a header
+        let table = parse_csv("1,2\n3,4");
+        assert!(table.columns.is_empty());
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(split_csv_line("a,\"say \"\"hi\"\"\",b", ','), vec!["a", "say \"hi\"", "b"]);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let table = parse_json(r#"[{"x": 1, "y": -2.5e0, "name": "a\"b"}, {"x": 3, "z": true, "y": null}]"#).unwrap();
+        assert_eq!(table.columns, vec!["x", "y", "name", "z"]);
+        // rows before a column first shows up are shorter
+        assert_eq!(table.rows[0], vec![Cell::Number(1.0), Cell::Number(-2.5), Cell::Text("a\"b".to_string())]);
+        assert_eq!(table.rows[1], vec![Cell::Number(3.0), Cell::Text(String::new()), Cell::Text(String::new()), Cell::Number(1.0)]);
+        assert_eq!(parse_json("[[1, 2], [3]]").unwrap().rows, vec![vec![Cell::Number(1.0), Cell::Number(2.0)], vec![Cell::Number(3.0)]]);
+        assert!(parse_json("{\"x\": 1}").is_err());
+        assert!(parse_json("[1, 2").is_err());
+        assert!(parse_json("[1] 2").is_err());
+    }
+
+    #[test]
+    fn test_from_table() {
+        let scatter = Chart::from_table(&parse_csv("x,y,z,size\n0,1,2,3\n4,,6,1\n7,8,9,")).unwrap();
+        // the row without a y is left out, a missing size is 1 and the color follows the height
+        assert_eq!(scatter, Chart::Scatter { points: vec![[0.0, 1.0, 2.0], [7.0, 8.0, 9.0]], values: vec![1.0, 8.0], sizes: vec![3.0, 1.0] });
+        let bars = Chart::from_table(&parse_csv("month,a,b\njan,1,2\nfeb,3,x")).unwrap();
+        assert_eq!(bars, Chart::Bars { values: vec![vec![None, Some(1.0), Some(2.0)], vec![None, Some(3.0), None]] });
+        assert!(Chart::from_table(&parse_csv("name\nnothing")).is_err());
+    }
+
+    #[test]
+    fn test_bars() {
+        let mut mesh = MeshBuilder::default();
+        build_bars(&mut mesh, &[vec![Some(4.0), Some(-2.0)]]);
+        // the tallest bar reaches the chart's height and the negative one hangs below the floor
+        let (min, max) = (0..3).fold(([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]), |(mut min, mut max), axis| {
+            for vertex in &mesh.vertices[..48] {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+            (min, max)
+        });
+        assert_eq!((min[1], max[1]), (-1.0, CHART_HEIGHT));
+        assert!(max[0] <= CHART_SIZE / 2.0 && min[0] >= -CHART_SIZE / 2.0);
+        assert_eq!(mesh.vertices[0].color, colormap(1.0));
+        assert!(mesh.indices.iter().all(|index| (*index as usize) < mesh.vertices.len()));
+    }
+
+    #[test]
+    fn test_format_tick() {
+        assert_eq!(format_tick(12.0), "12");
+        assert_eq!(format_tick(0.123), "0.12");
+        assert_eq!(format_tick(-0.001), "0");
+        assert_eq!(format_tick(2.5), "2.5");
+        assert_eq!(segments('8'), 0b1111111);
+        assert!((text_width("-1.5", 1.0) - 2.35).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_colormap() {
+        assert_eq!(colormap(0.0), COLOR_STOPS[0].map(|c| srgb_to_linear(c as f32 / 255.0)));
+        assert_eq!(colormap(2.0), colormap(1.0));
+        assert!(colormap(1.0)[0] > colormap(0.0)[0]);
+    }
+}
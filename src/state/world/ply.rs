@@ -0,0 +1,370 @@
+//! Reads .ply files, common output of photogrammetry and scanning tools
+//!
+//! Text and both binary byte orders are read. Vertices bring their positions and, when the file
+//! has them, normals and colors; faces are split into triangles. Files with no faces are point
+//! clouds, each point is drawn as a small octahedron in its color since there is no point pipeline.
+//! Positions are kept as they are, PLY files have no agreed up axis.
+use super::{importer::{srgb_to_linear, AssetImporter, ImportedModel}, model::{self, ModelVertex}, normals, resources::MeshData};
+
+/// Size of the octahedra points are drawn as, relative to the longest side of the cloud's box
+pub const POINT_SIZE: f32 = 0.002;
+
+/// The built in importer for .ply files
+pub struct PlyImporter;
+
+impl AssetImporter for PlyImporter {
+    fn extensions(&self) -> &[&str] {
+        &["ply"]
+    }
+
+    fn import(&self, file_name: &str, bytes: &[u8]) -> anyhow::Result<ImportedModel> {
+        let file = parse(bytes)?;
+        let mesh = if file.faces.is_empty() { point_cloud(file_name, &file) } else { mesh_data(file_name, file) };
+        Ok(ImportedModel { meshes: vec![mesh], materials: Vec::new() })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// Number types a property can have
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> anyhow::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => anyhow::bail!("unknown property type {}", name),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Property {
+    Scalar { name: String, scalar: Scalar },
+    /// a count followed by that many items
+    List { name: String, count: Scalar, item: Scalar },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } | Property::List { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads property values from the body of a file
+struct Body<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    /// words left in the text form
+    words: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> anyhow::Result<f64> {
+        if self.format == Format::Ascii {
+            return Ok(self.words.next().ok_or_else(|| anyhow::anyhow!("the file ends early"))?.parse()?);
+        }
+        if self.bytes.len() < scalar.size() {
+            anyhow::bail!("the file ends early");
+        }
+        let (value, rest) = self.bytes.split_at(scalar.size());
+        self.bytes = rest;
+        let mut value = value.to_vec();
+        if self.format == Format::BigEndian {
+            value.reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => value[0] as i8 as f64,
+            Scalar::U8 => value[0] as f64,
+            Scalar::I16 => i16::from_le_bytes(value.try_into().unwrap()) as f64,
+            Scalar::U16 => u16::from_le_bytes(value.try_into().unwrap()) as f64,
+            Scalar::I32 => i32::from_le_bytes(value.try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(value.try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(value.try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(value.try_into().unwrap()),
+        })
+    }
+}
+
+/// The vertices and faces read from a file
+#[derive(Debug, Default)]
+struct PlyFile {
+    positions: Vec<[f32; 3]>,
+    /// empty when the file has no normals
+    normals: Vec<[f32; 3]>,
+    /// linear colors, empty when the file has none
+    colors: Vec<[f32; 3]>,
+    /// vertex indices of each polygon
+    faces: Vec<Vec<u32>>,
+}
+
+/// Read the header, then every element in the body
+fn parse(bytes: &[u8]) -> anyhow::Result<PlyFile> {
+    const END: &[u8] = b"end_header";
+    let end = bytes.windows(END.len()).position(|w| w == END).ok_or_else(|| anyhow::anyhow!("not a PLY file"))?;
+    // the body starts after the line ending the header
+    let body_start = bytes[end..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |i| end + i + 1);
+    let header = std::str::from_utf8(&bytes[..end])?;
+
+    let mut lines = header.lines().map(str::split_whitespace);
+    if lines.next().and_then(|mut words| words.next()) != Some("ply") {
+        anyhow::bail!("not a PLY file");
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for words in lines {
+        let words = words.collect::<Vec<_>>();
+        match words.as_slice() {
+            ["format", name, ..] => format = Some(match *name {
+                "ascii" => Format::Ascii,
+                "binary_little_endian" => Format::LittleEndian,
+                "binary_big_endian" => Format::BigEndian,
+                _ => anyhow::bail!("unknown format {}", name),
+            }),
+            ["element", name, count] => elements.push(Element { name: name.to_string(), count: count.parse()?, properties: Vec::new() }),
+            ["property", "list", count, item, name] => {
+                let element = elements.last_mut().ok_or_else(|| anyhow::anyhow!("a property before any element"))?;
+                element.properties.push(Property::List { name: name.to_string(), count: Scalar::parse(count)?, item: Scalar::parse(item)? });
+            }
+            ["property", scalar, name] => {
+                let element = elements.last_mut().ok_or_else(|| anyhow::anyhow!("a property before any element"))?;
+                element.properties.push(Property::Scalar { name: name.to_string(), scalar: Scalar::parse(scalar)? });
+            }
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| anyhow::anyhow!("the header has no format"))?;
+
+    let text = if format == Format::Ascii { std::str::from_utf8(&bytes[body_start..])? } else { "" };
+    let mut body = Body { format, bytes: &bytes[body_start..], words: text.split_ascii_whitespace() };
+    let mut file = PlyFile::default();
+    for element in &elements {
+        let find = |name: &str| element.properties.iter().position(|p| p.name() == name);
+        let position = [find("x"), find("y"), find("z")];
+        let normal = [find("nx"), find("ny"), find("nz")];
+        let color = [find("red"), find("green"), find("blue")];
+        // 8 bit colors are scaled to 0..1, float colors already are
+        let color_scale = match element.properties.get(color[0].unwrap_or(usize::MAX)) {
+            Some(Property::Scalar { scalar: Scalar::F32 | Scalar::F64, .. }) => 1.0,
+            _ => 1.0 / 255.0,
+        };
+
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            let mut list = Vec::new();
+            for (value, property) in values.iter_mut().zip(&element.properties) {
+                match property {
+                    Property::Scalar { scalar, .. } => *value = body.read(*scalar)?,
+                    Property::List { name, count, item } => {
+                        let count = body.read(*count)? as usize;
+                        let items = (0..count).map(|_| body.read(*item)).collect::<anyhow::Result<Vec<_>>>()?;
+                        if name == "vertex_indices" || name == "vertex_index" {
+                            list = items;
+                        }
+                    }
+                }
+            }
+
+            let get = |indices: [Option<usize>; 3]| indices.iter().map(|i| i.map(|i| values[i] as f32)).collect::<Option<Vec<_>>>();
+            match element.name.as_str() {
+                "vertex" => {
+                    let position = get(position).ok_or_else(|| anyhow::anyhow!("vertices without x, y and z"))?;
+                    file.positions.push([position[0], position[1], position[2]]);
+                    if let Some(normal) = get(normal) {
+                        file.normals.push([normal[0], normal[1], normal[2]]);
+                    }
+                    if let Some(color) = get(color) {
+                        file.colors.push([0, 1, 2].map(|i| srgb_to_linear(color[i] * color_scale)));
+                    }
+                }
+                "face" => file.faces.push(list.into_iter().map(|i| i as u32).collect()),
+                _ => {}
+            }
+        }
+    }
+
+    if file.faces.iter().flatten().any(|&i| i as usize >= file.positions.len()) {
+        anyhow::bail!("a face uses a vertex that doesn't exist");
+    }
+    Ok(file)
+}
+
+/// A vertex with the file's normal and color where it has them
+fn vertex(file: &PlyFile, i: usize) -> ModelVertex {
+    ModelVertex {
+        position: file.positions[i],
+        tex_coords: [0.0, 0.0],
+        normal: file.normals.get(i).copied().unwrap_or_default(),
+        color: file.colors.get(i).copied().unwrap_or([1.0; 3]),
+        tangent: [0.0; 4],
+    }
+}
+
+/// One mesh of the faces, split into fans of triangles
+fn mesh_data(file_name: &str, file: PlyFile) -> MeshData {
+    let mut vertices = (0..file.positions.len()).map(|i| vertex(&file, i)).collect::<Vec<_>>();
+    let mut indices = file.faces
+        .iter()
+        .filter(|face| face.len() >= 3)
+        .flat_map(|face| (1..face.len() - 1).flat_map(move |i| [face[0], face[i], face[i + 1]]))
+        .collect::<Vec<_>>();
+    let has_normals = !file.normals.is_empty();
+    if !has_normals {
+        normals::generate_normals(&mut vertices, &mut indices, normals::DEFAULT_SMOOTHING_ANGLE);
+    }
+    let submeshes = vec![model::Submesh { indices: 0..indices.len() as u32, material: 0 }];
+    MeshData { name: file_name.to_string(), vertices, indices, submeshes, has_tex_coords: false, has_normals }
+}
+
+/// A small octahedron at every point, facing out or along the point's normal if it has one
+fn point_cloud(file_name: &str, file: &PlyFile) -> MeshData {
+    let bounds = model::BoundingBox::from_positions(file.positions.iter().copied());
+    let extent = bounds.max - bounds.min;
+    let radius = extent.x.max(extent.y).max(extent.z).max(1.0) * POINT_SIZE / 2.0;
+
+    let corners: [[f32; 3]; 6] = [[1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0]];
+    // the eight faces, counterclockwise from outside
+    let faces: [[usize; 3]; 8] = [[0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4], [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5]];
+
+    let mut vertices = Vec::with_capacity(file.positions.len() * 24);
+    let mut indices = Vec::with_capacity(file.positions.len() * 24);
+    for i in 0..file.positions.len() {
+        let point = vertex(file, i);
+        for face in faces {
+            let face_normal = face.iter().map(|&corner| cgmath::Vector3::from(corners[corner])).fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, corner| sum + corner);
+            let normal = file.normals.get(i).copied().unwrap_or_else(|| (face_normal / 3f32.sqrt()).into());
+            for corner in face {
+                indices.push(vertices.len() as u32);
+                vertices.push(ModelVertex {
+                    position: [0, 1, 2].map(|axis| point.position[axis] + corners[corner][axis] * radius),
+                    normal,
+                    ..point
+                });
+            }
+        }
+    }
+    let submeshes = vec![model::Submesh { indices: 0..indices.len() as u32, material: 0 }];
+    MeshData { name: file_name.to_string(), vertices, indices, submeshes, has_tex_coords: false, has_normals: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "ply
+format ascii 1.0
+comment a square of two colors
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 0 -1 0 0 255
+0 0 -1 0 0 255
+4 0 1 2 3
+";
+
+    /// The same square as a binary file, without colors
+    fn binary_square(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        ).into_bytes();
+        let f = |value: f32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let i = |value: i32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        for [x, y, z] in [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, -1.0], [0.0, 0.0, -1.0]] {
+            bytes.extend([f(x), f(y), f(z)].concat());
+        }
+        bytes.push(4);
+        for index in 0..4 {
+            bytes.extend(i(index));
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_ascii() {
+        let model = PlyImporter.import("square.ply", SQUARE.as_bytes()).unwrap();
+        let mesh = &model.meshes[0];
+        // the quad is split in two triangles facing up
+        assert_eq!(mesh.indices.len(), 6);
+        assert!(mesh.vertices.iter().all(|v| (v.normal[1] - 1.0).abs() < 1e-5));
+        assert_eq!(mesh.vertices[0].color, [1.0, 0.0, 0.0]);
+        assert!(!mesh.has_normals);
+    }
+
+    #[test]
+    fn test_binary() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let model = PlyImporter.import("square.ply", &binary_square(format, big_endian)).unwrap();
+            let mesh = &model.meshes[0];
+            assert_eq!(mesh.indices.len(), 6);
+            assert_eq!(mesh.vertices[2].position, [1.0, 0.0, -1.0]);
+            assert_eq!(mesh.vertices[2].color, [1.0; 3]);
+        }
+        let mut truncated = binary_square("binary_little_endian", false);
+        truncated.pop();
+        assert!(PlyImporter.import("square.ply", &truncated).is_err());
+    }
+
+    #[test]
+    fn test_point_cloud() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 2\nproperty double x\nproperty double y\nproperty double z\n\
+                    property float nx\nproperty float ny\nproperty float nz\nend_header\n0 0 0 0 1 0\n10 0 0 0 1 0\n";
+        let model = PlyImporter.import("scan.ply", text.as_bytes()).unwrap();
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 2 * 24);
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 1.0, 0.0]));
+        let widest = mesh.vertices.iter().map(|v| v.position[0]).fold(f32::MIN, f32::max);
+        assert!((widest - (10.0 + 10.0 * POINT_SIZE / 2.0)).abs() < 1e-4);
+
+        assert!(PlyImporter.import("scan.ply", b"ply\nformat ascii 1.0\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n3 0 1 2\n").is_err());
+    }
+}
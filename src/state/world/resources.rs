@@ -1,63 +1,246 @@
-/// help load files and objects
+//! help load files and objects
 
-use std::{io::{BufReader, Cursor}, path::Path, rc::Rc};
+use std::{collections::HashMap, io::{BufReader, Cursor}, path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock, Weak}};
 
 use wgpu::util::DeviceExt;
 
-use super::{model, texture};
+use crate::tasks;
 
-/// function to load string data from a file
-pub async fn load_string(file_name: &dyn AsRef<Path>) -> anyhow::Result<String> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(file_name);
-    let txt = std::fs::read_to_string(path)?;
+use crate::state::up_axis::UpAxis;
+
+use super::{archive::{self, PakArchive}, diagnostics::MeshDiagnostics, importer::{self, AssetImporter, ImportedMaterial}, manifest::{self, ModelEntry}, model, normals, tangents, stream, texture, video, weld};
+
+/// Environment variable naming the folder resources are loaded from
+pub const RESOURCE_DIR_VAR: &str = "RUST3D_RES";
+
+/// Environment variable naming a .pak file to load resources from before the folder
+pub const ARCHIVE_VAR: &str = "RUST3D_PAK";
+
+/// Where resources are loaded from, set once with `install` before anything loads
+///
+/// `res_dir` gives the installed root, or the one `from_env` finds if none was installed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceContext {
+    /// folder holding `resources.txt`, the locale files and the models
+    pub root: PathBuf,
+    /// a .pak file, a zip with the same layout as the folder, files in it are used before loose ones
+    pub archive: Option<PathBuf>,
+}
+
+impl ResourceContext {
+    /// Load resources from a folder
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), archive: None }
+    }
+
+    /// The folder in `RUST3D_RES`, else a `res` folder next to the executable, else the copy build.rs made
+    ///
+    /// The archive is the one in `RUST3D_PAK`, else `res.pak` next to the executable if it's there.
+    pub fn from_env() -> Self {
+        let exe_dir = std::env::current_exe().ok().and_then(|exe| Some(exe.parent()?.to_path_buf()));
+        Self::find(std::env::var_os(RESOURCE_DIR_VAR).map(PathBuf::from), std::env::var_os(ARCHIVE_VAR).map(PathBuf::from), exe_dir)
+    }
+
+    fn find(root_var: Option<PathBuf>, archive_var: Option<PathBuf>, exe_dir: Option<PathBuf>) -> Self {
+        let root = root_var
+            .or(exe_dir.as_ref().map(|folder| folder.join("res")).filter(|folder| folder.is_dir()))
+            .unwrap_or_else(|| Path::new(env!("OUT_DIR")).join("res"));
+        let archive = archive_var.or(exe_dir.map(|folder| folder.join("res.pak")).filter(|file| file.is_file()));
+        Self { root, archive }
+    }
+
+    /// Make this the folder every later load reads from, an archive that can't be opened is left out
+    pub fn install(&self) {
+        let archive = self.archive.as_ref().and_then(|path| match PakArchive::open(path) {
+            Ok(archive) => {
+                log::info!("Loading resources from {} before {}", archive.path().display(), self.root.display());
+                Some(Arc::new(archive))
+            }
+            Err(e) => {
+                log::warn!("Couldn't open {}, loading loose files: {}", path.display(), e);
+                None
+            }
+        });
+        *CONTEXT.write().unwrap() = Some(Installed { root: self.root.clone(), archive });
+    }
+}
+
+impl Default for ResourceContext {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// A context after `install` opened its archive
+struct Installed {
+    root: PathBuf,
+    archive: Option<Arc<PakArchive>>,
+}
+
+/// The context installed last, None until the first load or `install`
+static CONTEXT: RwLock<Option<Installed>> = RwLock::new(None);
+
+/// Look at the installed context, installing the one `from_env` finds if there isn't one yet
+fn installed<T>(look: impl Fn(&Installed) -> T) -> T {
+    if let Some(installed) = CONTEXT.read().unwrap().as_ref() {
+        return look(installed);
+    }
+    ResourceContext::from_env().install();
+    look(CONTEXT.read().unwrap().as_ref().unwrap())
+}
+
+/// the folder resources are loaded from, see `ResourceContext`
+pub fn res_dir() -> std::path::PathBuf {
+    installed(|installed| installed.root.clone())
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "embed-assets")] {
+        /// resources.txt and the files it loads, built in by build.rs, by path relative to `res`
+        static EMBEDDED: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+
+        /// a file built into the executable, None if it wasn't
+        fn embedded(file_name: &Path) -> Option<&'static [u8]> {
+            let name = file_name.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            EMBEDDED.iter().find(|(embedded, _)| *embedded == name).map(|(_, bytes)| *bytes)
+        }
+    } else {
+        /// a file built into the executable, there are none without the `embed-assets` feature
+        fn embedded(_file_name: &Path) -> Option<&'static [u8]> {
+            None
+        }
+    }
+}
+
+/// The bytes of a resource, built in with the `embed-assets` feature, else from the archive, else the loose file
+///
+/// Files are read on the task threads so several loads can wait on the disk at once
+async fn read_resource(file_name: &(dyn AsRef<Path> + Sync)) -> anyhow::Result<Vec<u8>> {
+    if let Some(bytes) = embedded(file_name.as_ref()) {
+        return Ok(bytes.to_vec());
+    }
+    let file_name = file_name.as_ref().to_path_buf();
+    let (root, archive) = installed(|installed| (installed.root.clone(), installed.archive.clone()));
+    tasks::spawn_blocking(move || match archive.as_ref().and_then(|archive| archive.read(&file_name)) {
+        Some(read) => read,
+        None => Ok(std::fs::read(root.join(&file_name))?),
+    })
+    .await?
+}
+
+/// function to load string data from a file, see `read_resource` for where it's looked for
+pub async fn load_string(file_name: &(dyn AsRef<Path> + Sync)) -> anyhow::Result<String> {
+    let txt = String::from_utf8(read_resource(file_name).await?)?;
 
     Ok(txt)
 }
 
-/// Function to load binary data from a file
-pub async fn load_binary(file_name: &dyn AsRef<Path>) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(file_name);
-    let data = std::fs::read(path)?;
+/// Function to load binary data from a file, see `read_resource` for where it's looked for
+pub async fn load_binary(file_name: &(dyn AsRef<Path> + Sync)) -> anyhow::Result<Vec<u8>> {
+    let data = read_resource(file_name).await?;
 
     Ok(data)
 }
 
+/// function to save string data to a file next to the other resources
+///
+/// The write happens on the task threads, await the task to find out if it worked
+pub fn save_string(file_name: &dyn AsRef<Path>, contents: String) -> tasks::Task<std::io::Result<()>> {
+    let path = res_dir().join(file_name);
+    tasks::spawn_blocking(move || std::fs::write(path, contents))
+}
+
 /// function to load a texture from file
 /// 
 /// Args:
 ///     file_name: path to texture
+///     is_linear: whether the texture holds data like normals rather than colors, so it isn't read as sRGB
 ///     device: device to load onto
 ///     queue: command queue for device
 pub async fn load_texture(
-    file_name: &dyn AsRef<Path>,
+    file_name: &(dyn AsRef<Path> + Sync),
+    is_linear: bool,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
 ) -> anyhow::Result<texture::Texture> {
     let data = load_binary(file_name).await?;
-    texture::Texture::from_bytes(device, queue, &data, file_name.as_ref().file_name().unwrap().to_str().unwrap())
+    texture::Texture::from_bytes(device, queue, &data, file_name.as_ref().file_name().unwrap().to_str().unwrap(), is_linear)
+}
+
+/// A texture loaded for a material, kept in the cache while any material still holds it
+struct CachedTexture {
+    device: Weak<wgpu::Device>,
+    texture: Weak<texture::Texture>,
 }
 
+/// Textures loaded for materials, by `texture_key`
+static TEXTURES: Mutex<Option<HashMap<(String, bool), CachedTexture>>> = Mutex::new(None);
+
+/// The cache key of a texture file, paths reaching the same file from different model folders match
+fn texture_key(file_name: &Path, is_linear: bool) -> (String, bool) {
+    (archive::entry_name(file_name), is_linear)
+}
 
-/// function to load a model from a .obj file
+/// load a texture, or share the one already uploaded if another material uses the same file
+///
+/// Textures are only shared on the device they were made on, and are freed when the last material using them is.
+pub async fn load_shared_texture(
+    file_name: &Path,
+    is_linear: bool,
+    device: &Arc<wgpu::Device>,
+    queue: &wgpu::Queue,
+) -> anyhow::Result<Arc<texture::Texture>> {
+    let key = texture_key(file_name, is_linear);
+    let cached = TEXTURES.lock().unwrap().as_ref().and_then(|textures| {
+        let cached = textures.get(&key)?;
+        if Weak::as_ptr(&cached.device) != Arc::as_ptr(device) {
+            return None;
+        }
+        cached.texture.upgrade()
+    });
+    if let Some(texture) = cached {
+        return Ok(texture);
+    }
+
+    let texture = Arc::new(load_texture(&file_name, is_linear, device, queue).await?);
+    let mut textures = TEXTURES.lock().unwrap();
+    let textures = textures.get_or_insert_with(HashMap::new);
+    textures.retain(|_, cached| cached.texture.strong_count() > 0);
+    textures.insert(key, CachedTexture { device: Arc::downgrade(device), texture: Arc::downgrade(&texture) });
+    Ok(texture)
+}
+
+/// Stop sharing the textures loaded so far, so files changed on disk are read again
+pub fn forget_textures() {
+    *TEXTURES.lock().unwrap() = None;
+}
+
+/// load a material's texture, a file that can't be loaded is noted in `missing` and replaced
 ///
 /// Args:
-///     file_name: name of file/ path to file
-///     device: graphics/compute device to load into
-///     queue: command queue to device
-///     layout: model memory layout
-pub async fn load_model(
-    file_name: &str,
-    device: Rc<wgpu::Device>,
+///     fallback: makes the plain texture used in place of the file
+async fn load_material_texture(
+    file_name: &Path,
+    is_linear: bool,
+    fallback: fn(&wgpu::Device, &wgpu::Queue) -> anyhow::Result<texture::Texture>,
+    missing: &mut Vec<String>,
+    device: &Arc<wgpu::Device>,
     queue: &wgpu::Queue,
-    layout: &wgpu::BindGroupLayout,
-) -> anyhow::Result<model::Model> {
+) -> anyhow::Result<Arc<texture::Texture>> {
+    match load_shared_texture(file_name, is_linear, device, queue).await {
+        Ok(texture) => Ok(texture),
+        Err(e) => {
+            log::warn!("{}: {}", file_name.display(), e);
+            missing.push(file_name.display().to_string());
+            Ok(Arc::new(fallback(device, queue)?))
+        }
+    }
+}
+
+/// read a .obj file and its materials with tobj
+async fn load_obj(file_name: &str) -> anyhow::Result<(Vec<tobj::Model>, Vec<tobj::Material>)> {
     // read file
     let model_dir = Path::new(file_name).parent().unwrap();
-    // .as_os_str().to_str().unwrap();
     let obj_text = load_string(&file_name).await?;
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
@@ -77,110 +260,621 @@ pub async fn load_model(
     )
     .await?;
 
-    let mut materials = Vec::new();
-    // load all the textures for all the materials and create their bindings
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&model_dir.join(m.diffuse_texture), &device, queue).await?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: None,
-        });
+    Ok((models, obj_materials?))
+}
 
-        materials.push(model::Material {
-            name: m.name,
-            diffuse_texture,
-            bind_group,
-        })
+/// texture coordinates of a vertex, flipped for wgpu, or [0, 0] for meshes without any
+fn tex_coords(mesh: &tobj::Mesh, i: usize) -> [f32; 2] {
+    if mesh.texcoords.is_empty() {
+        return [0.0, 0.0];
     }
+    [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+}
 
-    // load all the meshes as vertexes
-    let meshes = models
-        .into_iter()
-        .map(|m| {
-                let vertices = (0..m.mesh.positions.len() / 3)
-                .map(|i| {
-                    if m.mesh.normals.is_empty(){  // if the normals aren't specified we use [0, 0, 0]
-                        model::ModelVertex {
-                            position: [
-                                m.mesh.positions[i * 3],
-                                m.mesh.positions[i * 3 + 1],
-                                m.mesh.positions[i * 3 + 2],
-                            ],
-                            tex_coords: [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]],
-                            normal: [0.0, 0.0, 0.0],
-                        }
-                    }else{  // otherwise we grab the normals from the mesh
-                        model::ModelVertex {
-                            position: [
-                                m.mesh.positions[i * 3],
-                                m.mesh.positions[i * 3 + 1],
-                                m.mesh.positions[i * 3 + 2],
-                            ],
-                            tex_coords: [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]],
-                            normal: [
-                                m.mesh.normals[i * 3],
-                                m.mesh.normals[i * 3 + 1],
-                                m.mesh.normals[i * 3 + 2],
-                            ],
-                        }
+/// color of a vertex (from `v x y z r g b` lines), or white for meshes without colors so textures show as is
+fn vertex_color(mesh: &tobj::Mesh, i: usize) -> [f32; 3] {
+    if mesh.vertex_color.is_empty() {
+        return [1.0, 1.0, 1.0];
+    }
+    [mesh.vertex_color[i * 3], mesh.vertex_color[i * 3 + 1], mesh.vertex_color[i * 3 + 2]]
+}
+
+/// vertices and indices of one mesh before they are uploaded
+#[derive(Debug)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<model::ModelVertex>,
+    pub indices: Vec<u32>,
+    /// index ranges drawn with each material
+    pub submeshes: Vec<model::Submesh>,
+    /// whether every part came with uvs
+    pub has_tex_coords: bool,
+    /// whether every part came with normals
+    pub has_normals: bool,
+}
+
+/// turn the meshes tobj read into vertices and indices
+///
+/// tobj splits an object into one model per material, those parts are joined back into one mesh
+/// with a submesh for each material.
+fn mesh_data(models: Vec<tobj::Model>) -> Vec<MeshData> {
+    let mut meshes: Vec<MeshData> = Vec::new();
+    for m in models {
+        let vertices = (0..m.mesh.positions.len() / 3)
+            .map(|i| {
+                if m.mesh.normals.is_empty(){  // if the normals aren't specified they are generated below
+                    model::ModelVertex {
+                        position: [
+                            m.mesh.positions[i * 3],
+                            m.mesh.positions[i * 3 + 1],
+                            m.mesh.positions[i * 3 + 2],
+                        ],
+                        tex_coords: tex_coords(&m.mesh, i),
+                        normal: [0.0, 0.0, 0.0],
+                        color: vertex_color(&m.mesh, i),
+                        // filled in once the mesh is welded
+                        tangent: [0.0; 4],
                     }
-                })
-                .collect::<Vec<_>>();
-
-            // now we create a vertex buffer to represent the possible vertexes for the model
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
+                }else{  // otherwise we grab the normals from the mesh
+                    model::ModelVertex {
+                        position: [
+                            m.mesh.positions[i * 3],
+                            m.mesh.positions[i * 3 + 1],
+                            m.mesh.positions[i * 3 + 2],
+                        ],
+                        tex_coords: tex_coords(&m.mesh, i),
+                        normal: [
+                            m.mesh.normals[i * 3],
+                            m.mesh.normals[i * 3 + 1],
+                            m.mesh.normals[i * 3 + 2],
+                        ],
+                        color: vertex_color(&m.mesh, i),
+                        // filled in once the mesh is welded
+                        tangent: [0.0; 4],
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let material = m.mesh.material_id.unwrap_or(0);
+        let (has_tex_coords, has_normals) = (!m.mesh.texcoords.is_empty(), !m.mesh.normals.is_empty());
 
-            // now we create an index buffer for the model
-            // this is to reduce the amount of vertices we have my reindex them over and over again
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
+        // a file without normals would be lit black, so build them from the faces
+        let mut vertices = vertices;
+        let mut indices = m.mesh.indices;
+        if m.mesh.normals.is_empty() {
+            normals::generate_normals(&mut vertices, &mut indices, normals::DEFAULT_SMOOTHING_ANGLE);
+        }
 
-            model::Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+        // the parts of one object come one after another with the same name
+        let mesh = match meshes.last_mut() {
+            Some(mesh) if mesh.name == m.name => mesh,
+            _ => {
+                meshes.push(MeshData { name: m.name, vertices: Vec::new(), indices: Vec::new(), submeshes: Vec::new(), has_tex_coords, has_normals });
+                meshes.last_mut().unwrap()
             }
+        };
+        mesh.has_tex_coords &= has_tex_coords;
+        mesh.has_normals &= has_normals;
+
+        let offset = mesh.vertices.len() as u32;
+        let start = mesh.indices.len() as u32;
+        mesh.vertices.extend(vertices);
+        mesh.indices.extend(indices.iter().map(|i| i + offset));
+        let end = mesh.indices.len() as u32;
+
+        match mesh.submeshes.last_mut() {
+            Some(submesh) if submesh.material == material && submesh.indices.end == start => submesh.indices.end = end,
+            _ => mesh.submeshes.push(model::Submesh { indices: start..end, material }),
+        }
+    }
+    meshes
+}
+
+/// upload a mesh's vertices and indices to the gpu
+fn create_mesh(file_name: &str, data: MeshData, device: &wgpu::Device) -> model::Mesh {
+    // find how far the mesh reaches from its origin so we can hit test it later
+    let bounding_radius = data.vertices
+        .iter()
+        .map(|v| (v.position[0] * v.position[0] + v.position[1] * v.position[1] + v.position[2] * v.position[2]).sqrt())
+        .fold(0.0, f32::max);
+    let bounding_box = model::BoundingBox::from_positions(data.vertices.iter().map(|v| v.position));
+    let diagnostics = MeshDiagnostics::new(&data.vertices, &data.indices, data.has_tex_coords, data.has_normals);
+
+    // now we create a vertex buffer to represent the possible vertexes for the model
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Vertex Buffer", file_name)),
+        contents: bytemuck::cast_slice(&data.vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    // now we create an index buffer for the model
+    // this is to reduce the amount of vertices we have my reindex them over and over again
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Index Buffer", file_name)),
+        contents: bytemuck::cast_slice(&data.indices),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+
+    model::Mesh {
+        name: file_name.to_string(),
+        vertex_buffer,
+        index_buffer,
+        num_elements: data.indices.len() as u32,
+        submeshes: data.submeshes,
+        bounding_radius,
+        bounding_box,
+        diagnostics,
+    }
+}
+
+/// turn vertices from a Y-up .obj file to the world's up axis
+fn turn_to_up_axis(vertices: &mut [model::ModelVertex], up_axis: UpAxis) {
+    for vertex in vertices {
+        vertex.position = up_axis.from_y_up(vertex.position.into()).into();
+        vertex.normal = up_axis.from_y_up(vertex.normal.into()).into();
+    }
+}
+
+/// turn meshes read from a file into vertex and index buffers
+fn create_meshes(file_name: &str, meshes: Vec<MeshData>, up_axis: UpAxis, device: &wgpu::Device) -> Vec<model::Mesh> {
+    let mut stats = weld::WeldStats::default();
+    let meshes = meshes
+        .into_iter()
+        .map(|mut data| {
+            turn_to_up_axis(&mut data.vertices, up_axis);
+            // files often repeat a vertex for every face using it, merge them before uploading
+            let welded = weld::weld(&mut data.vertices, &mut data.indices, weld::DEFAULT_WELD_EPSILON);
+            stats.vertices_before += welded.vertices_before;
+            stats.vertices_after += welded.vertices_after;
+            // after welding so the triangles sharing a vertex all add to its tangent
+            tangents::generate_tangents(&mut data.vertices, &data.indices);
+            create_mesh(file_name, data, device)
         })
-        .collect::<Vec<_>>();
+        .collect();
+
+    if stats.removed() > 0 {
+        log::info!("{}: welded {} vertices down to {}", file_name, stats.vertices_before, stats.vertices_after);
+    }
+    meshes
+}
+
+/// function to load only the meshes of a .obj file, used for lower detail versions of a model
+///
+/// Args:
+///     file_name: name of file/ path to file
+///     up_axis: which way is up in the world
+///     device: graphics/compute device to load into
+pub async fn load_meshes(file_name: &str, up_axis: UpAxis, device: &wgpu::Device) -> anyhow::Result<Vec<model::Mesh>> {
+    let meshes = match importer::importer_for(file_name) {
+        Some(importer) => importer.import(file_name, &load_binary(&file_name).await?)?.meshes,
+        None => mesh_data(load_obj(file_name).await?.0),
+    };
+    Ok(create_meshes(file_name, meshes, up_axis, device))
+}
+
+/// function to load a model from a .obj file, or any file a registered importer reads
+///
+/// Args:
+///     file_name: name of file/ path to file
+///     device: graphics/compute device to load into
+///     queue: command queue to device
+///     layout: model memory layout
+///     up_axis: which way is up in the world, the file is turned to match
+///     pbr: shade the materials with the physically based pipeline
+pub async fn load_model(
+    file_name: &str,
+    device: Arc<wgpu::Device>,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    up_axis: UpAxis,
+    pbr: bool,
+) -> anyhow::Result<model::Model> {
+    if let Some(importer) = importer::importer_for(file_name) {
+        return import_model(file_name, importer.as_ref(), device, queue, layout, up_axis, pbr).await;
+    }
+    let model_dir = Path::new(file_name).parent().unwrap();
+    let (models, obj_materials) = load_obj(file_name).await?;
+
+    let mut materials = Vec::new();
+    // load all the textures for all the materials and create their bindings
+    for m in obj_materials {
+        // files that can't be loaded are swapped for the texture used when there is no file, see World::validate
+        let mut missing = Vec::new();
+        // a video or frames pushed by the host are shown in place of the diffuse texture
+        let stream = load_stream(&m, model_dir, &mut missing, &device);
+        // materials without a texture only show the vertex colors
+        let diffuse_texture = if let Some(stream) = &stream {
+            stream.texture.clone()
+        } else if m.diffuse_texture.is_empty() {
+            Arc::new(texture::Texture::white(&device, queue)?)
+        } else {
+            load_material_texture(&model_dir.join(&m.diffuse_texture), false, texture::Texture::white, &mut missing, &device, queue).await?
+        };
+        // map_Bump in the .mtl, materials without one keep the surface flat
+        let normal_texture = if m.normal_texture.is_empty() {
+            Arc::new(texture::Texture::flat_normal(&device, queue)?)
+        } else {
+            load_material_texture(&model_dir.join(&m.normal_texture), true, texture::Texture::flat_normal, &mut missing, &device, queue).await?
+        };
+        // map_Pm, map_Pr and map_ao, missing maps leave the values from Pm and Pr as they are
+        let mut pbr_textures = Vec::with_capacity(3);
+        for key in ["map_Pm", "map_Pr", "map_ao"] {
+            pbr_textures.push(match m.unknown_param.get(key) {
+                Some(file) => load_material_texture(&model_dir.join(file.trim()), true, texture::Texture::white, &mut missing, &device, queue).await?,
+                None => Arc::new(texture::Texture::white(&device, queue)?),
+            });
+        }
+        let pbr_textures = pbr_textures.try_into().unwrap_or_else(|_| unreachable!());
+        let options = MaterialOptions::from_mtl(&m, pbr);
+        let mut material = create_material(m.name, diffuse_texture, normal_texture, pbr_textures, options, &device, layout);
+        material.missing_textures = missing;
+        material.stream = stream;
+        materials.push(material);
+    }
+
+    // vertex colored models often come without any material at all
+    if materials.is_empty() {
+        let diffuse_texture = Arc::new(texture::Texture::white(&device, queue)?);
+        let normal_texture = Arc::new(texture::Texture::flat_normal(&device, queue)?);
+        let pbr_textures = [
+            Arc::new(texture::Texture::white(&device, queue)?),
+            Arc::new(texture::Texture::white(&device, queue)?),
+            Arc::new(texture::Texture::white(&device, queue)?),
+        ];
+        let options = MaterialOptions { shading: if pbr { model::Shading::Pbr } else { model::Shading::Basic }, ..MaterialOptions::default() };
+        materials.push(create_material("default".to_string(), diffuse_texture, normal_texture, pbr_textures, options, &device, layout));
+    }
+
+    // load all the meshes as vertexes
+    let meshes = create_meshes(file_name, mesh_data(models), up_axis, &device);
+
+    Ok(model::Model::new(meshes, materials, device))
+}
+
+/// load a model with an importer, see `load_model`
+async fn import_model(
+    file_name: &str,
+    importer: &dyn AssetImporter,
+    device: Arc<wgpu::Device>,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    up_axis: UpAxis,
+    pbr: bool,
+) -> anyhow::Result<model::Model> {
+    let mut imported = importer.import(file_name, &load_binary(&file_name).await?)?;
+    if imported.materials.is_empty() {
+        imported.materials.push(ImportedMaterial::default());
+    }
 
+    let mut materials = Vec::new();
+    for m in imported.materials {
+        // the color goes in a one pixel texture so these draw with the same pipelines as .obj materials
+        let color = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(m.diffuse)));
+        let diffuse_texture = Arc::new(texture::Texture::from_image(&device, queue, &color, Some(&m.name), false)?);
+        let normal_texture = Arc::new(texture::Texture::flat_normal(&device, queue)?);
+        let pbr_textures = [
+            Arc::new(texture::Texture::white(&device, queue)?),
+            Arc::new(texture::Texture::white(&device, queue)?),
+            Arc::new(texture::Texture::white(&device, queue)?),
+        ];
+        let options = MaterialOptions {
+            double_sided: m.double_sided,
+            shading: if pbr { model::Shading::Pbr } else { model::Shading::Basic },
+            metallic: m.metallic,
+            roughness: m.roughness,
+            ..MaterialOptions::default()
+        };
+        materials.push(create_material(m.name, diffuse_texture, normal_texture, pbr_textures, options, &device, layout));
+    }
+
+    let meshes = create_meshes(file_name, imported.meshes, up_axis, &device);
     Ok(model::Model::new(meshes, materials, device))
 }
 
+/// settings a material reads from the extra lines of its .mtl
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MaterialOptions {
+    double_sided: bool,
+    depth_bias: model::DepthBiasPreset,
+    alpha_cutoff: f32,
+    shading: model::Shading,
+    metallic: f32,
+    roughness: f32,
+    /// how every texture of the material is sampled
+    sampler: texture::SamplerSettings,
+}
+
+impl Default for MaterialOptions {
+    fn default() -> Self {
+        Self {
+            double_sided: false,
+            depth_bias: model::DepthBiasPreset::None,
+            alpha_cutoff: 0.0,
+            shading: model::Shading::Basic,
+            metallic: 0.0,
+            roughness: 0.5,
+            sampler: texture::SamplerSettings::default(),
+        }
+    }
+}
+
+impl MaterialOptions {
+    fn from_mtl(material: &tobj::Material, pbr: bool) -> Self {
+        Self {
+            double_sided: is_double_sided(material),
+            depth_bias: depth_bias(material),
+            alpha_cutoff: alpha_cutoff(material),
+            shading: shading(material, pbr),
+            metallic: metallic(material),
+            roughness: roughness(material),
+            sampler: sampler_settings(material),
+        }
+    }
+}
+
+/// create the bindings for a material's textures
+///
+/// Args:
+///     pbr_textures: metallic, roughness and ambient occlusion maps
+fn create_material(
+    name: String,
+    diffuse_texture: Arc<texture::Texture>,
+    normal_texture: Arc<texture::Texture>,
+    pbr_textures: [Arc<texture::Texture>; 3],
+    options: MaterialOptions,
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+) -> model::Material {
+    let MaterialOptions { double_sided, depth_bias, alpha_cutoff, shading, metallic, roughness, sampler } = options;
+    // the material's own sampler rather than the textures', which may be shared with materials sampling them differently
+    let sampler = sampler.create_sampler(device);
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Material Buffer", name)),
+        contents: bytemuck::cast_slice(&[model::MaterialUniform::new(alpha_cutoff, metallic, roughness)]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(&pbr_textures[0].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&pbr_textures[1].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(&pbr_textures[2].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+        label: None,
+    });
+
+    model::Material {
+        name,
+        diffuse_texture,
+        normal_texture,
+        pbr_textures,
+        shading,
+        bind_group,
+        double_sided,
+        depth_bias,
+        alpha_cutoff,
+        metallic,
+        roughness,
+        uniform_buffer,
+        missing_textures: Vec::new(),
+        stream: None,
+    }
+}
+
+/// the texture `map_stream <name>` or `map_video <file>` in a material's .mtl fills, at its `video_size` or 640 by 360
+///
+/// Videos that can't be played are listed as missing and the material keeps its diffuse texture.
+fn load_stream(material: &tobj::Material, model_dir: &Path, missing: &mut Vec<String>, device: &wgpu::Device) -> Option<stream::StreamTexture> {
+    let (width, height) = match material.unknown_param.get("video_size") {
+        Some(value) => stream::parse_size(value).unwrap_or_else(|| {
+            log::warn!("{} has an invalid video_size {:?}", material.name, value);
+            stream::DEFAULT_STREAM_SIZE
+        }),
+        None => stream::DEFAULT_STREAM_SIZE,
+    };
+    if let Some(name) = material.unknown_param.get("map_stream") {
+        return Some(stream::StreamTexture::new(name.trim().to_string(), width, height, device));
+    }
+
+    let file = material.unknown_param.get("map_video")?;
+    let path = res_dir().join(model_dir).join(file.trim());
+    let mut texture = stream::StreamTexture::new(file.trim().to_string(), width, height, device);
+    match video::Decoder::start(&path, texture.stream()) {
+        Ok(decoder) => {
+            texture.set_decoder(decoder);
+            Some(texture)
+        }
+        Err(e) => {
+            log::warn!("{}: {}", path.display(), e);
+            missing.push(path.display().to_string());
+            None
+        }
+    }
+}
+
+/// the `depth_bias` preset (`decal` or `wireframe`) of a material's .mtl
+fn depth_bias(material: &tobj::Material) -> model::DepthBiasPreset {
+    let Some(value) = material.unknown_param.get("depth_bias") else {
+        return model::DepthBiasPreset::None;
+    };
+    model::DepthBiasPreset::parse(value.trim()).unwrap_or_else(|| {
+        log::warn!("{} has an unknown depth_bias {:?}", material.name, value);
+        model::DepthBiasPreset::None
+    })
+}
+
+/// the `alpha_cutoff` of a material's .mtl, pixels with less alpha than this are cut out
+///
+/// 0 (the default) keeps every pixel
+fn alpha_cutoff(material: &tobj::Material) -> f32 {
+    let Some(value) = material.unknown_param.get("alpha_cutoff") else {
+        return 0.0;
+    };
+    value.trim().parse().unwrap_or_else(|_| {
+        log::warn!("{} has an invalid alpha_cutoff {:?}", material.name, value);
+        0.0
+    })
+}
+
+/// the `Pm` of a material's .mtl, how metallic it is from 0 to 1
+///
+/// 0 (the default) is a plain dielectric like plastic or wood
+fn metallic(material: &tobj::Material) -> f32 {
+    let Some(value) = material.unknown_param.get("Pm") else {
+        return 0.0;
+    };
+    value.trim().parse::<f32>().map(|value| value.clamp(0.0, 1.0)).unwrap_or_else(|_| {
+        log::warn!("{} has an invalid Pm {:?}", material.name, value);
+        0.0
+    })
+}
+
+/// the `Pr` of a material's .mtl, how rough it is from 0 to 1
+///
+/// Files without it get one from the specular exponent `Ns` the way Blender exports it, `Ns = ((1 - r) * 30)^2`
+fn roughness(material: &tobj::Material) -> f32 {
+    let from_shininess = (1.0 - material.shininess.max(0.0).sqrt() / 30.0).clamp(0.0, 1.0);
+    let Some(value) = material.unknown_param.get("Pr") else {
+        return from_shininess;
+    };
+    value.trim().parse::<f32>().map(|value| value.clamp(0.0, 1.0)).unwrap_or_else(|_| {
+        log::warn!("{} has an invalid Pr {:?}", material.name, value);
+        from_shininess
+    })
+}
+
+/// how a material's textures are sampled, from `sampler_wrap`, `sampler_filter` and `sampler_anisotropy` in its .mtl
+///
+/// Left out or invalid values keep the defaults: clamped at the edges, trilinear, no anisotropic filtering.
+fn sampler_settings(material: &tobj::Material) -> texture::SamplerSettings {
+    let mut settings = texture::SamplerSettings::default();
+    let get = |key: &str| material.unknown_param.get(key).map(|value| value.trim());
+    if let Some(value) = get("sampler_wrap") {
+        match texture::SamplerSettings::parse_address_mode(value) {
+            Some(mode) => settings.address_mode = mode,
+            None => log::warn!("{} has an unknown sampler_wrap {:?}", material.name, value),
+        }
+    }
+    if let Some(value) = get("sampler_filter") {
+        match texture::SamplerSettings::parse_filter(value) {
+            Some(filter) => settings.filter = filter,
+            None => log::warn!("{} has an unknown sampler_filter {:?}", material.name, value),
+        }
+    }
+    if let Some(value) = get("sampler_anisotropy") {
+        match value.parse::<u16>() {
+            Ok(anisotropy) => settings.anisotropy = anisotropy,
+            Err(_) => log::warn!("{} has an invalid sampler_anisotropy {:?}", material.name, value),
+        }
+    }
+    settings
+}
+
+/// whether a material has `double_sided 1` (or `true`) in its .mtl, so its back faces aren't culled
+fn is_double_sided(material: &tobj::Material) -> bool {
+    material
+        .unknown_param
+        .get("double_sided")
+        .is_some_and(|value| matches!(value.trim(), "1" | "true"))
+}
+
+/// how a material is lit, `shadow_catcher 1` in its .mtl makes it only show shadows
+///
+/// Args:
+///     pbr: the model asked for physically based shading
+fn shading(material: &tobj::Material, pbr: bool) -> model::Shading {
+    let is_catcher = material
+        .unknown_param
+        .get("shadow_catcher")
+        .is_some_and(|value| matches!(value.trim(), "1" | "true"));
+    match (is_catcher, pbr) {
+        (true, _) => model::Shading::ShadowCatcher,
+        (false, true) => model::Shading::Pbr,
+        (false, false) => model::Shading::Basic,
+    }
+}
+
+/// Every file a line of `resources.txt` loads from, relative to the resource folder
+///
+/// That is the model, its detail levels, the .mtl files they name and the textures those use.
+/// Files that can't be read are still listed, so they are noticed once they appear.
+pub fn model_files(line: &str) -> anyhow::Result<Vec<PathBuf>> {
+    manifest::model_files(line, |file| std::fs::read_to_string(res_dir().join(file)).ok())
+}
+
+/// function to load a model described by a line of `resources.txt`, with its lower detail versions
+///
+/// Args:
+///     line: the line from `resources.txt`
+///     device: graphics/compute device to load into
+///     queue: command queue to device
+///     layout: model memory layout
+///     up_axis: which way is up in the world
+pub async fn load_model_entry(
+    line: &str,
+    device: Arc<wgpu::Device>,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    up_axis: UpAxis,
+) -> anyhow::Result<model::Model> {
+    let entry = ModelEntry::parse(line)?;
+    let mut model = load_model(entry.file_name, device.clone(), queue, layout, up_axis, entry.pbr).await?;
+    for (lod_file, distance) in entry.lods {
+        model.add_lod(load_meshes(lod_file, up_axis, &device).await?, distance);
+    }
+    Ok(model)
+}
+
 /// Tests for resources
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_texture_key() {
+        // two models next to each other sharing a texture folder
+        let key = texture_key(Path::new("house/../textures/brick.png"), false);
+        assert_eq!(key, texture_key(Path::new("shed/../textures/brick.png"), false));
+        assert_ne!(key, texture_key(Path::new("textures/brick.png"), true));
+    }
+
     /// Test that we can properly read text from file
     #[test]
     fn test_load_text() {
-
-        // type is inferred
-        let foo = 67;
-
-        // can cast when necessary
-        let bar = foo as u8 as char;
-        let baz = 32 as u16;
-
         let text = tokio_test::block_on(load_string(&"test_files/hello_world.txt")).unwrap();
 
         assert_eq!(text, "Hello World!");
@@ -193,4 +887,143 @@ mod tests {
 
         assert_eq!(text, vec![72, 101, 108, 108, 111, 32, 87, 111, 114, 108, 100, 33]);
     }
-}
\ No newline at end of file
+
+    /// Test that built in files are read instead of the ones on disk
+    #[cfg(feature = "embed-assets")]
+    #[test]
+    fn test_embedded() {
+        let listing = tokio_test::block_on(load_string(&"resources.txt")).unwrap();
+        assert_eq!(embedded(Path::new("resources.txt")), Some(listing.as_bytes()));
+        assert!(embedded(Path::new("cube/cube.mtl")).is_some());
+        assert!(embedded(Path::new("test_files/hello_world.txt")).is_none());
+    }
+
+    /// Test that the environment variables win, then files next to the executable if they're there
+    #[test]
+    fn test_find_root() {
+        let out_dir = Path::new(env!("OUT_DIR"));
+        let built = out_dir.join("res");
+        assert_eq!(ResourceContext::find(Some("assets".into()), None, Some(out_dir.to_path_buf())).root, Path::new("assets"));
+        // the build folder has a res folder in it but no res.pak
+        assert_eq!(ResourceContext::find(None, None, Some(out_dir.to_path_buf())), ResourceContext::new(&built));
+        let found = ResourceContext::find(None, Some("scene.pak".into()), Some(built.join("test_files")));
+        assert_eq!((found.root, found.archive), (built.clone(), Some(PathBuf::from("scene.pak"))));
+
+        let exe_dir = std::env::temp_dir().join(format!("rust3d_exe_{}", std::process::id()));
+        std::fs::create_dir_all(&exe_dir).unwrap();
+        std::fs::write(exe_dir.join("res.pak"), "").unwrap();
+        let found = ResourceContext::find(None, None, Some(exe_dir.clone()));
+        std::fs::remove_dir_all(&exe_dir).unwrap();
+        assert_eq!((found.root, found.archive), (built, Some(exe_dir.join("res.pak"))));
+    }
+
+    /// Test that vertex colors are read and white is used when there aren't any
+    #[test]
+    fn test_vertex_colors() {
+        let (models, materials) = tokio_test::block_on(load_obj("test_files/colored_triangle.obj")).unwrap();
+        assert!(materials.is_empty());
+
+        let mesh = &models[0].mesh;
+        assert_eq!(vertex_color(mesh, 0), [1.0, 0.0, 0.0]);
+        assert_eq!(vertex_color(mesh, 2), [0.0, 0.0, 1.0]);
+        assert_eq!(tex_coords(mesh, 0), [0.0, 0.0]);
+
+        let plain = tobj::Mesh { positions: vec![0.0; 3], ..Default::default() };
+        assert_eq!(vertex_color(&plain, 0), [1.0, 1.0, 1.0]);
+    }
+
+    /// Test that the double sided flag is read from the .mtl
+    #[test]
+    fn test_is_double_sided() {
+        let mut material = tobj::Material::default();
+        assert!(!is_double_sided(&material));
+
+        material.unknown_param.insert("double_sided".to_string(), "1".to_string());
+        assert!(is_double_sided(&material));
+
+        material.unknown_param.insert("double_sided".to_string(), "0".to_string());
+        assert!(!is_double_sided(&material));
+    }
+
+    /// Test that the alpha cutoff is read from the .mtl
+    #[test]
+    fn test_alpha_cutoff() {
+        let mut material = tobj::Material::default();
+        assert_eq!(alpha_cutoff(&material), 0.0);
+
+        material.unknown_param.insert("alpha_cutoff".to_string(), "0.5".to_string());
+        assert_eq!(alpha_cutoff(&material), 0.5);
+
+        material.unknown_param.insert("alpha_cutoff".to_string(), "half".to_string());
+        assert_eq!(alpha_cutoff(&material), 0.0);
+    }
+
+    /// Test that the depth bias preset is read from the .mtl
+    #[test]
+    fn test_depth_bias() {
+        let mut material = tobj::Material::default();
+        assert_eq!(depth_bias(&material), model::DepthBiasPreset::None);
+
+        material.unknown_param.insert("depth_bias".to_string(), "decal".to_string());
+        assert_eq!(depth_bias(&material), model::DepthBiasPreset::Decal);
+    }
+
+    /// Test that metallic and roughness are read from the .mtl, with roughness falling back to Ns
+    #[test]
+    fn test_pbr_params() {
+        let mut material = tobj::Material { shininess: 324.0, ..Default::default() };
+        assert_eq!(metallic(&material), 0.0);
+        assert!((roughness(&material) - 0.4).abs() < 1e-5);
+
+        material.unknown_param.insert("Pm".to_string(), "1".to_string());
+        material.unknown_param.insert("Pr".to_string(), " 0.25".to_string());
+        assert_eq!(metallic(&material), 1.0);
+        assert_eq!(roughness(&material), 0.25);
+
+        assert_eq!(shading(&material, true), model::Shading::Pbr);
+        material.unknown_param.insert("shadow_catcher".to_string(), "1".to_string());
+        assert_eq!(shading(&material, false), model::Shading::ShadowCatcher);
+    }
+
+    /// Test that the sampler options are read from the .mtl
+    #[test]
+    fn test_sampler_settings() {
+        let mut material = tobj::Material::default();
+        assert_eq!(sampler_settings(&material), texture::SamplerSettings::default());
+
+        material.unknown_param.insert("sampler_wrap".to_string(), "repeat".to_string());
+        material.unknown_param.insert("sampler_filter".to_string(), "fuzzy".to_string());
+        material.unknown_param.insert("sampler_anisotropy".to_string(), " 8".to_string());
+        let settings = sampler_settings(&material);
+        assert_eq!(settings.address_mode, wgpu::AddressMode::Repeat);
+        assert_eq!(settings.filter, wgpu::FilterMode::Linear);
+        assert_eq!(settings.anisotropy, 8);
+    }
+
+    /// Test that the parts of an object using different materials become submeshes of one mesh
+    #[test]
+    fn test_submeshes() {
+        let (models, materials) = tokio_test::block_on(load_obj("test_files/two_materials.obj")).unwrap();
+        assert_eq!(materials.len(), 2);
+
+        let meshes = mesh_data(models);
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(mesh.submeshes, vec![
+            model::Submesh { indices: 0..3, material: 0 },
+            model::Submesh { indices: 3..6, material: 1 },
+        ]);
+        // the second part's indices point past the first part's vertices
+        assert!(mesh.indices[3..].iter().all(|&i| i >= 3 && (i as usize) < mesh.vertices.len()));
+    }
+
+    /// Test that the files a model loads from are found through its .mtl
+    #[test]
+    fn test_model_files() {
+        let files = model_files("cube/cube.obj").unwrap();
+        assert!(files.contains(&PathBuf::from("cube/cube.obj")));
+        assert!(files.contains(&PathBuf::from("cube/cube.mtl")));
+        assert!(files.iter().any(|file| file.extension().is_some_and(|extension| extension == "png" || extension == "jpg")));
+    }
+}
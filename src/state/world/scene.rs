@@ -0,0 +1,284 @@
+//! Edits made to the world that are saved between runs
+use std::fmt::Write;
+
+use super::{instance::{EntityId, Instance, SPAWNED_IDS}, selection::Group};
+
+/// Name of the scene file in `res`
+pub const SCENE_FILE: &str = "scene.txt";
+
+/// Groups, hand placed cubes and cubes pasted in the viewer
+///
+/// Pasted cubes have ids from `SPAWNED_IDS` up, every other id is a cube of the grid.
+/// Stored as text with one entry per line: `group px py pz <id>...` for a group and its pivot,
+/// `disabled_group px py pz <id>...` for a group switched off with everything in it,
+/// `place <id> x y z qw qx qy qz scale` for a cube moved away from its spot in the grid or pasted,
+/// `hide <id>` for a hidden cube, `tag <id> <tag>...` for a cube's tags and `material <index>` for
+/// the material every cube is drawn with. Blank lines and lines starting with `#` are skipped.
+#[derive(Clone, Default)]
+pub struct Scene {
+    pub groups: Vec<Group>,
+    /// cubes with their own transform, the grid keeps them there when it's rebuilt
+    pub placed: Vec<(EntityId, Instance)>,
+    /// cubes hidden on their own, see `hidden_ids` for every cube that isn't drawn
+    pub hidden: Vec<EntityId>,
+    /// words attached to cubes, never empty for a cube that is listed
+    pub tags: Vec<(EntityId, Vec<String>)>,
+    /// material the cubes are drawn with, None until one is chosen
+    pub material: Option<usize>,
+}
+
+/// What the inspector edits on a single cube
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityProperties {
+    pub instance: Instance,
+    /// material of the cubes, shared by all of them
+    pub material: usize,
+    pub hidden: bool,
+    pub tags: Vec<String>,
+    /// whether the cube's group is switched on, None if it isn't in one
+    pub group_enabled: Option<bool>,
+}
+
+impl Scene {
+    /// Parse a scene from its text form
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut scene = Self::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            scene.parse_line(line)
+                .map_err(|e| anyhow::anyhow!("line {}: {}", line_number + 1, e))?;
+        }
+        Ok(scene)
+    }
+
+    fn parse_line(&mut self, line: &str) -> anyhow::Result<()> {
+        let mut words = line.split_whitespace();
+        let kind = words.next().unwrap_or_default();
+        if kind == "tag" {
+            let id = EntityId(words.next().ok_or_else(|| anyhow::anyhow!("tag needs an id"))?.parse()?);
+            let tags = words.map(str::to_string).collect::<Vec<_>>();
+            if tags.is_empty() {
+                anyhow::bail!("tag needs at least one tag");
+            }
+            self.tags.push((id, tags));
+            return Ok(());
+        }
+        let numbers = words.map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()?;
+
+        match (kind, &numbers[..]) {
+            ("group" | "disabled_group", [px, py, pz, ids @ ..]) if !ids.is_empty() => self.groups.push(Group {
+                members: ids.iter().map(|id| EntityId(*id as u32)).collect(),
+                pivot: (*px, *py, *pz).into(),
+                enabled: kind == "group",
+            }),
+            ("group" | "disabled_group", _) => anyhow::bail!("{} needs a pivot and at least one id", kind),
+            ("place", [id, x, y, z, qw, qx, qy, qz, scale]) => self.placed.push((EntityId(*id as u32), Instance {
+                position: (*x, *y, *z).into(),
+                rotation: cgmath::Quaternion::new(*qw, *qx, *qy, *qz),
+                scale: *scale,
+            })),
+            ("place", _) => anyhow::bail!("place needs an id, a position, a rotation and a scale"),
+            ("hide", [id]) => self.hidden.push(EntityId(*id as u32)),
+            ("hide", _) => anyhow::bail!("hide needs an id"),
+            ("material", [index]) => self.material = Some(*index as usize),
+            ("material", _) => anyhow::bail!("material needs an index"),
+            _ => anyhow::bail!("unknown entry {}", kind),
+        }
+        Ok(())
+    }
+
+    /// The scene in the form `parse` reads
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# Saved by the viewer: group px py pz <id>... | disabled_group px py pz <id>... | place <id> x y z qw qx qy qz scale | hide <id> | tag <id> <tag>... | material <index>\n");
+        if let Some(material) = self.material {
+            let _ = writeln!(text, "material {}", material);
+        }
+        for group in &self.groups {
+            let kind = if group.enabled { "group" } else { "disabled_group" };
+            let _ = write!(text, "{} {} {} {}", kind, group.pivot.x, group.pivot.y, group.pivot.z);
+            for id in &group.members {
+                let _ = write!(text, " {}", id.0);
+            }
+            text.push('\n');
+        }
+        for (id, instance) in &self.placed {
+            let (p, q) = (instance.position, instance.rotation);
+            let _ = writeln!(text, "place {} {} {} {} {} {} {} {} {}", id.0, p.x, p.y, p.z, q.s, q.v.x, q.v.y, q.v.z, instance.scale);
+        }
+        for id in &self.hidden {
+            let _ = writeln!(text, "hide {}", id.0);
+        }
+        for (id, tags) in &self.tags {
+            let _ = writeln!(text, "tag {} {}", id.0, tags.join(" "));
+        }
+        text
+    }
+
+    /// The group an instance belongs to
+    pub fn group_of(&self, id: EntityId) -> Option<&Group> {
+        self.groups.iter().find(|group| group.contains(id))
+    }
+
+    /// Cubes that were pasted rather than being part of the grid
+    pub fn spawned(&self) -> impl Iterator<Item = &(EntityId, Instance)> {
+        self.placed.iter().filter(|(id, _)| id.0 >= SPAWNED_IDS)
+    }
+
+    /// Forget a cube, taking it out of its group
+    pub fn remove(&mut self, id: EntityId) {
+        self.placed.retain(|(placed, _)| *placed != id);
+        self.hidden.retain(|hidden| *hidden != id);
+        self.tags.retain(|(tagged, _)| *tagged != id);
+        for group in &mut self.groups {
+            group.members.retain(|member| *member != id);
+        }
+        self.groups.retain(|group| !group.members.is_empty());
+    }
+
+    /// Remember where a cube was put, replacing where it was before
+    pub fn place(&mut self, id: EntityId, instance: Instance) {
+        match self.placed.iter_mut().find(|(placed, _)| *placed == id) {
+            Some((_, existing)) => *existing = instance,
+            None => self.placed.push((id, instance)),
+        }
+    }
+
+    /// Whether a cube is hidden
+    pub fn is_hidden(&self, id: EntityId) -> bool {
+        self.hidden.contains(&id)
+    }
+
+    /// Every cube that isn't drawn or picked, the ones hidden on their own and the ones in a disabled group
+    pub fn hidden_ids(&self) -> std::collections::HashSet<EntityId> {
+        self.groups.iter()
+            .filter(|group| !group.enabled)
+            .flat_map(|group| group.members.iter())
+            .chain(&self.hidden)
+            .copied()
+            .collect()
+    }
+
+    /// Switch a cube's group on or off, does nothing if it isn't in one
+    pub fn set_group_enabled(&mut self, id: EntityId, enabled: bool) {
+        if let Some(group) = self.groups.iter_mut().find(|group| group.contains(id)) {
+            group.enabled = enabled;
+        }
+    }
+
+    /// Show or hide a cube
+    pub fn set_hidden(&mut self, id: EntityId, hidden: bool) {
+        self.hidden.retain(|other| *other != id);
+        if hidden {
+            self.hidden.push(id);
+        }
+    }
+
+    /// The tags on a cube, empty if it has none
+    pub fn tags_of(&self, id: EntityId) -> &[String] {
+        self.tags.iter().find(|(tagged, _)| *tagged == id).map_or(&[], |(_, tags)| tags)
+    }
+
+    /// Replace a cube's tags, tags can't contain whitespace so each is split into words
+    pub fn set_tags(&mut self, id: EntityId, tags: &[String]) {
+        let words = tags.iter().flat_map(|tag| tag.split_whitespace()).map(str::to_string).collect::<Vec<_>>();
+        self.tags.retain(|(tagged, _)| *tagged != id);
+        if !words.is_empty() {
+            self.tags.push((id, words));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = "
+        # a group of two and one moved cube
+        group 1 0 -2 3 65539
+        place 3 1 2 3 1 0 0 0 0.5
+    ";
+
+    #[test]
+    fn test_parse() {
+        let scene = Scene::parse(SCENE).unwrap();
+        assert_eq!(scene.groups, vec![Group { members: vec![EntityId(3), EntityId(65539)], pivot: (1.0, 0.0, -2.0).into(), enabled: true }]);
+        assert_eq!(scene.group_of(EntityId(65539)), Some(&scene.groups[0]));
+        assert_eq!(scene.group_of(EntityId(4)), None);
+
+        let (id, instance) = &scene.placed[0];
+        assert_eq!(*id, EntityId(3));
+        assert_eq!(instance.position, (1.0, 2.0, 3.0).into());
+        assert_eq!(instance.scale, 0.5);
+
+        assert_eq!(scene.spawned().count(), 0);
+
+        assert!(Scene::parse("group 1 2 3").is_err());
+        assert!(Scene::parse("place 1 2").is_err());
+        assert!(Scene::parse("tag 3").is_err());
+        assert!(Scene::parse("hide x").is_err());
+    }
+
+    #[test]
+    fn test_properties_round_trip() {
+        let mut scene = Scene::parse("material 2\nhide 4\ntag 3 red  door").unwrap();
+        assert_eq!(scene.material, Some(2));
+        assert!(scene.is_hidden(EntityId(4)) && !scene.is_hidden(EntityId(3)));
+        assert_eq!(scene.tags_of(EntityId(3)), ["red", "door"]);
+
+        scene.set_tags(EntityId(4), &["blue green".to_string()]);
+        scene.set_hidden(EntityId(4), false);
+        let reparsed = Scene::parse(&scene.to_text()).unwrap();
+        assert_eq!(reparsed.tags_of(EntityId(4)), ["blue", "green"]);
+        assert!(reparsed.hidden.is_empty());
+        assert_eq!(reparsed.material, Some(2));
+
+        scene.remove(EntityId(3));
+        scene.set_tags(EntityId(4), &[]);
+        assert!(scene.tags.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut scene = Scene::parse(SCENE).unwrap();
+        let mut moved = scene.placed[0].1.clone();
+        moved.position.x = 7.0;
+        scene.place(EntityId(3), moved);
+        assert_eq!(scene.placed.len(), 1);
+
+        let reparsed = Scene::parse(&scene.to_text()).unwrap();
+        assert_eq!(reparsed.groups, scene.groups);
+        assert_eq!(reparsed.placed[0].1.position, (7.0, 2.0, 3.0).into());
+    }
+
+    #[test]
+    fn test_disabled_group() {
+        let mut scene = Scene::parse(&format!("{}\nhide 4", SCENE)).unwrap();
+        assert_eq!(scene.hidden_ids(), [EntityId(4)].into());
+
+        // switching the group off hides every member, and their own visibility is kept for when it's back on
+        scene.set_group_enabled(EntityId(3), false);
+        assert_eq!(scene.hidden_ids(), [EntityId(3), EntityId(4), EntityId(65539)].into());
+        assert!(!scene.is_hidden(EntityId(3)));
+        scene.set_group_enabled(EntityId(4), true);
+
+        let reparsed = Scene::parse(&scene.to_text()).unwrap();
+        assert!(!reparsed.groups[0].enabled);
+        assert_eq!(reparsed.hidden_ids(), scene.hidden_ids());
+        assert!(Scene::parse("disabled_group 1 2 3").is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut scene = Scene::parse(SCENE).unwrap();
+        scene.place(EntityId(SPAWNED_IDS), scene.placed[0].1.clone());
+        assert_eq!(scene.spawned().map(|(id, _)| *id).collect::<Vec<_>>(), vec![EntityId(SPAWNED_IDS)]);
+
+        scene.remove(EntityId(3));
+        scene.remove(EntityId(65539));
+        assert!(scene.groups.is_empty());
+        assert_eq!(scene.placed.len(), 1);
+    }
+}
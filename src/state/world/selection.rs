@@ -0,0 +1,271 @@
+//! Picked instances and the groups they can be bundled into
+use cgmath::{InnerSpace, Rotation, Zero};
+
+use super::instance::{EntityId, Instance};
+
+/// The instances currently picked, in the order they were picked
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    pub ids: Vec<EntityId>,
+}
+
+impl Selection {
+    /// Add an instance, or take it out if it was already picked
+    pub fn toggle(&mut self, id: EntityId) {
+        match self.ids.iter().position(|selected| *selected == id) {
+            Some(index) => {
+                self.ids.remove(index);
+            }
+            None => self.ids.push(id),
+        }
+    }
+
+    /// Pick only these instances
+    pub fn replace(&mut self, ids: impl IntoIterator<Item = EntityId>) {
+        self.ids = ids.into_iter().collect();
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.ids.contains(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Instances that are picked and moved as one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub members: Vec<EntityId>,
+    /// point the group rotates and scales around
+    pub pivot: cgmath::Vector3<f32>,
+    /// off hides every member from drawing and picking, whatever their own visibility
+    pub enabled: bool,
+}
+
+impl Group {
+    /// Group instances around their center
+    pub fn new(members: Vec<(EntityId, &Instance)>) -> Self {
+        let pivot = center(members.iter().map(|(_, instance)| *instance));
+        Self { members: members.into_iter().map(|(id, _)| id).collect(), pivot, enabled: true }
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.members.contains(&id)
+    }
+
+    /// Whether the selection is exactly this group
+    pub fn matches(&self, selection: &Selection) -> bool {
+        self.members.len() == selection.ids.len() && self.members.iter().all(|id| selection.contains(*id))
+    }
+}
+
+/// Something to do with the selection, queued by a key press until the next frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditCommand {
+    Group,
+    Ungroup,
+    /// move, turn or scale, lining the result up with the grid if `snap` is set
+    Transform { transform: GroupTransform, snap: bool },
+    Copy,
+    Paste,
+    /// copy and paste in one go, leaving the clipboard alone
+    Duplicate,
+    Undo,
+    Redo,
+}
+
+/// A change applied to everything selected at once
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GroupTransform {
+    pub translation: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale: f32,
+}
+
+impl GroupTransform {
+    pub fn translate(translation: cgmath::Vector3<f32>) -> Self {
+        Self { translation, ..Self::identity() }
+    }
+
+    pub fn rotate(rotation: cgmath::Quaternion<f32>) -> Self {
+        Self { rotation, ..Self::identity() }
+    }
+
+    pub fn scale(scale: f32) -> Self {
+        Self { scale, ..Self::identity() }
+    }
+
+    fn identity() -> Self {
+        Self { translation: cgmath::Vector3::zero(), rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0), scale: 1.0 }
+    }
+
+    /// Move an instance as part of a group turning and scaling around a pivot
+    pub fn apply(&self, instance: &Instance, pivot: cgmath::Vector3<f32>) -> Instance {
+        let offset = self.rotation.rotate_vector(instance.position - pivot) * self.scale;
+        Instance {
+            position: pivot + offset + self.translation,
+            rotation: (self.rotation * instance.rotation).normalize(),
+            scale: instance.scale * self.scale,
+        }
+    }
+}
+
+/// How edits line up when snapping, hold Alt while editing to flip snapping on or off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SnapSettings {
+    /// snap without holding Alt, Alt then turns snapping off
+    pub always: bool,
+    /// spacing of the grid positions snap to
+    pub grid_size: f32,
+    /// degrees rotations around up snap to, 15 and 45 are common
+    pub rotation_step: f32,
+    /// scales snap to multiples of this
+    pub scale_step: f32,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self {
+            always: false,
+            grid_size: 0.5,
+            rotation_step: 15.0,
+            scale_step: 0.1,
+        }
+    }
+}
+
+impl SnapSettings {
+    /// Whether edits snap right now
+    pub fn is_active(&self, alt_held: bool) -> bool {
+        self.always != alt_held
+    }
+
+    /// The closest grid point
+    pub fn snap_position(&self, position: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+        position.map(|v| snap(v, self.grid_size))
+    }
+
+    /// Round the turn around up to a whole number of steps, leaving any tilt alone
+    pub fn snap_rotation(&self, rotation: cgmath::Quaternion<f32>, up: cgmath::Vector3<f32>) -> cgmath::Quaternion<f32> {
+        use cgmath::Rotation3;
+
+        // split the rotation into a twist around up and the swing left over
+        let along_up = up * rotation.v.dot(up);
+        let twist = cgmath::Quaternion::from_sv(rotation.s, along_up);
+        if twist.magnitude2() < f32::EPSILON {
+            return rotation;
+        }
+        let twist = twist.normalize();
+        let swing = rotation * twist.conjugate();
+
+        let angle = (2.0 * twist.v.dot(up).atan2(twist.s)).to_degrees();
+        let snapped = cgmath::Quaternion::from_axis_angle(up, cgmath::Deg(snap(angle, self.rotation_step)));
+        (swing * snapped).normalize()
+    }
+
+    /// The closest scale step, never snapping all the way to nothing
+    pub fn snap_scale(&self, scale: f32) -> f32 {
+        snap(scale, self.scale_step).max(self.scale_step)
+    }
+}
+
+/// round to the closest multiple of a step, a step of 0 leaves the value alone
+fn snap(value: f32, step: f32) -> f32 {
+    if step > 0.0 {
+        (value / step).round() * step
+    } else {
+        value
+    }
+}
+
+/// Average position of some instances
+pub fn center<'a>(instances: impl Iterator<Item = &'a Instance>) -> cgmath::Vector3<f32> {
+    let (sum, count) = instances.fold((cgmath::Vector3::zero(), 0), |(sum, count), instance| (sum + instance.position, count + 1));
+    if count == 0 {
+        sum
+    } else {
+        sum / count as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Rotation3;
+
+    use super::*;
+
+    fn instance_at(x: f32, z: f32) -> Instance {
+        Instance {
+            position: cgmath::Vector3::new(x, 0.0, z),
+            rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut selection = Selection::default();
+        selection.toggle(EntityId(1));
+        selection.toggle(EntityId(2));
+        selection.toggle(EntityId(1));
+        assert_eq!(selection.ids, vec![EntityId(2)]);
+
+        selection.replace([EntityId(3), EntityId(4)]);
+        let group = Group { members: vec![EntityId(4), EntityId(3)], pivot: cgmath::Vector3::zero(), enabled: true };
+        assert!(group.matches(&selection));
+        selection.toggle(EntityId(3));
+        assert!(!group.matches(&selection));
+    }
+
+    #[test]
+    fn test_group_pivot() {
+        let (a, b) = (instance_at(-1.0, 0.0), instance_at(3.0, 2.0));
+        let group = Group::new(vec![(EntityId(0), &a), (EntityId(1), &b)]);
+        assert_eq!(group.pivot, cgmath::Vector3::new(1.0, 0.0, 1.0));
+        assert_eq!(group.members, vec![EntityId(0), EntityId(1)]);
+    }
+
+    #[test]
+    fn test_snap() {
+        let snap = SnapSettings::default();
+        assert!(snap.is_active(true));
+        assert!(!SnapSettings { always: true, ..snap }.is_active(true));
+
+        assert_eq!(snap.snap_position(cgmath::Vector3::new(0.3, -0.2, 1.76)), cgmath::Vector3::new(0.5, 0.0, 2.0));
+        assert!((snap.snap_scale(1.04) - 1.0).abs() < 1e-6);
+        assert_eq!(snap.snap_scale(0.01), snap.scale_step);
+
+        // 50 degrees around up rounds to 45
+        let up = cgmath::Vector3::unit_y();
+        let turned = cgmath::Quaternion::from_axis_angle(up, cgmath::Deg(50.0));
+        let snapped = snap.snap_rotation(turned, up);
+        let expected = cgmath::Quaternion::from_axis_angle(up, cgmath::Deg(45.0));
+        assert!(snapped.dot(expected).abs() > 1.0 - 1e-5);
+
+        // a tilt on top of the turn is kept
+        let tilt = cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_x(), cgmath::Deg(30.0));
+        let snapped = snap.snap_rotation(tilt * turned, up);
+        assert!(snapped.dot(tilt * expected).abs() > 1.0 - 1e-5);
+    }
+
+    #[test]
+    fn test_transform_around_pivot() {
+        let pivot = cgmath::Vector3::new(1.0, 0.0, 0.0);
+        let instance = instance_at(2.0, 0.0);
+
+        let moved = GroupTransform::translate(cgmath::Vector3::new(0.0, 1.0, 0.0)).apply(&instance, pivot);
+        assert_eq!(moved.position, cgmath::Vector3::new(2.0, 1.0, 0.0));
+
+        // scaling pushes the instance away from the pivot as well as growing it
+        let scaled = GroupTransform::scale(2.0).apply(&instance, pivot);
+        assert_eq!(scaled.position, cgmath::Vector3::new(3.0, 0.0, 0.0));
+        assert_eq!(scaled.scale, 2.0);
+
+        // a quarter turn around y swings it from +x to -z of the pivot
+        let turn = cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_y(), cgmath::Deg(90.0));
+        let turned = GroupTransform::rotate(turn).apply(&instance, pivot);
+        assert!((turned.position - cgmath::Vector3::new(1.0, 0.0, -1.0)).magnitude() < 1e-5);
+    }
+}
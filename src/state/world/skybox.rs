@@ -0,0 +1,380 @@
+//! The sky drawn behind the world from a cube map
+use cgmath::InnerSpace;
+use image::{GenericImageView, RgbaImage};
+use wgpu::util::DeviceExt;
+
+use super::{super::{camera::Camera, ibl::{self, EnvironmentMap}, up_axis::UpAxis}, resources::load_binary};
+
+/// Folder in `res` the sky images are loaded from
+pub const SKYBOX_DIR: &str = "skybox";
+
+/// File names of the faces in the order cube maps store them: +x, -x, +y, -y, +z, -z
+pub const FACE_FILES: [&str; 6] = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+
+/// File name of the sky as one horizontal cross image, used when the separate faces are missing
+pub const CROSS_FILE: &str = "cross.png";
+
+/// Size of the faces of the gradient sky used when there are no images
+const GRADIENT_SIZE: u32 = 64;
+
+/// Where the camera looks, turned so the sky's +y is up
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    forward: [f32; 4],
+    /// right scaled by half the screen width at distance 1
+    right: [f32; 4],
+    /// up scaled by half the screen height at distance 1
+    up: [f32; 4],
+    /// x is the mip level sampled, higher is blurrier
+    blur: [f32; 4],
+}
+
+/// A cube map drawn everywhere the world doesn't cover
+///
+/// The faces are read from `res/skybox`, either as six images named in `FACE_FILES` or as one
+/// horizontal cross. With an HDR environment map the sky shows that instead. The images are Y-up
+/// like most skies, so a Z-up world turns them to match.
+pub struct Skybox {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    up_axis: UpAxis,
+    mip_level_count: u32,
+    /// how blurred the sky is drawn, 0 is sharp and 1 the smallest mip level
+    pub blur: f32,
+}
+
+impl Skybox {
+    /// Load the sky, falling back to a plain gradient if there are no images
+    ///
+    /// Args:
+    ///     environment: drawn instead of the images when it was loaded
+    ///     format: format of the surface the sky is drawn to
+    ///     depth_format: format of the depth buffer in the pass, the sky is drawn behind everything in it
+    ///     sample_count: samples per pixel of the pass
+    ///     up_axis: which way is up in the world
+    pub async fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        environment: &EnvironmentMap,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        up_axis: UpAxis,
+    ) -> Self {
+        let images;
+        let (view, sampler, mip_level_count) = if environment.is_loaded {
+            // the rougher levels of the environment blur the sky just as well
+            (&environment.specular, &environment.sampler, ibl::SPECULAR_LEVELS)
+        } else {
+            let faces = match load_faces().await {
+                Ok(faces) => faces,
+                Err(e) => {
+                    log::info!("No skybox loaded, using a gradient: {}", e);
+                    gradient_faces(GRADIENT_SIZE, [0.75, 0.85, 0.95], [0.25, 0.45, 0.8], [0.3, 0.3, 0.32])
+                }
+            };
+            let mips = mip_chain(faces);
+            let mip_level_count = mips.len() as u32;
+            images = create_cube_texture(device, queue, &mips);
+            (&images.0, &images.1, mip_level_count)
+        };
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Buffer"),
+            contents: bytemuck::cast_slice(&[SkyUniform::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("sky_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("sky_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../../skybox.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // the sky sits on the far plane, so it only fills pixels nothing else was drawn to
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self { pipeline, uniform_buffer, bind_group, up_axis, mip_level_count, blur: 0.0 }
+    }
+
+    /// Point the sky the way the camera looks
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        let half_height = (camera.fovy.to_radians() / 2.0).tan();
+        let half_width = half_height * camera.aspect;
+
+        let to_sky = |v: cgmath::Vector3<f32>| {
+            let v = self.up_axis.to_y_up(v);
+            [v.x, v.y, v.z, 0.0]
+        };
+        let uniform = SkyUniform {
+            forward: to_sky(forward),
+            right: to_sky(right * half_width),
+            up: to_sky(up * half_height),
+            blur: [self.blur.clamp(0.0, 1.0) * (self.mip_level_count - 1) as f32, 0.0, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Draw the sky, call this after the world so it's only drawn where the world isn't
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Read the six faces, or the cross if they aren't there
+async fn load_faces() -> anyhow::Result<[RgbaImage; 6]> {
+    let path = |file: &str| format!("{}/{}", SKYBOX_DIR, file);
+
+    let mut faces = Vec::with_capacity(6);
+    for file in FACE_FILES {
+        match load_binary(&path(file)).await {
+            Ok(bytes) => faces.push(image::load_from_memory(&bytes)?.to_rgba8()),
+            Err(_) if faces.is_empty() => {
+                let bytes = load_binary(&path(CROSS_FILE)).await?;
+                return split_cross(&image::load_from_memory(&bytes)?.to_rgba8());
+            }
+            Err(e) => anyhow::bail!("{} is missing: {}", file, e),
+        }
+    }
+
+    let size = faces[0].dimensions();
+    if size.0 != size.1 || faces.iter().any(|face| face.dimensions() != size) {
+        anyhow::bail!("skybox faces must all be the same square size");
+    }
+    Ok(faces.try_into().unwrap_or_else(|_| unreachable!()))
+}
+
+/// Cut a horizontal cross into its faces
+///
+/// The cross is 4 faces wide and 3 high, with the middle row -x, +z, +x, -z and
+/// +y above and -y below +z.
+pub fn split_cross(image: &RgbaImage) -> anyhow::Result<[RgbaImage; 6]> {
+    let (width, height) = image.dimensions();
+    let size = width / 4;
+    if size == 0 || width != size * 4 || height != size * 3 {
+        anyhow::bail!("a skybox cross must be 4 faces wide and 3 high, not {}x{}", width, height);
+    }
+    // column and row of each face, in cube map order
+    let cells = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+    Ok(cells.map(|(column, row)| image.view(column * size, row * size, size, size).to_image()))
+}
+
+/// Direction a texel of a cube map face points in, following the layout GPUs sample cube maps with
+///
+/// Args:
+///     face: index in `FACE_FILES` order
+///     u: texel position from -1 at the left to 1 at the right
+///     v: texel position from -1 at the top to 1 at the bottom
+pub fn face_direction(face: usize, u: f32, v: f32) -> cgmath::Vector3<f32> {
+    match face {
+        0 => cgmath::Vector3::new(1.0, -v, -u),
+        1 => cgmath::Vector3::new(-1.0, -v, u),
+        2 => cgmath::Vector3::new(u, 1.0, v),
+        3 => cgmath::Vector3::new(u, -1.0, -v),
+        4 => cgmath::Vector3::new(u, -v, 1.0),
+        _ => cgmath::Vector3::new(-u, -v, -1.0),
+    }
+}
+
+/// A sky fading from the horizon up to the top, with the ground below it
+fn gradient_faces(size: u32, horizon: [f32; 3], zenith: [f32; 3], ground: [f32; 3]) -> [RgbaImage; 6] {
+    std::array::from_fn(|face| {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let to_unit = |i: u32| (i as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let height = face_direction(face, to_unit(x), to_unit(y)).normalize().y;
+            let (from, to, amount) = if height >= 0.0 { (horizon, zenith, height.sqrt()) } else { (horizon, ground, (-height * 8.0).min(1.0)) };
+            let channel = |i: usize| ((from[i] + (to[i] - from[i]) * amount) * 255.0).round() as u8;
+            image::Rgba([channel(0), channel(1), channel(2), 255])
+        })
+    })
+}
+
+/// Halve the faces down to a single texel, each level a blurrier copy of the sky
+///
+/// Levels are filtered per face, so seams can show at the edges of the blurriest levels.
+pub fn mip_chain(faces: [RgbaImage; 6]) -> Vec<[RgbaImage; 6]> {
+    let mut mips = vec![faces];
+    loop {
+        let size = mips.last().unwrap()[0].width() / 2;
+        if size == 0 {
+            return mips;
+        }
+        let next = mips.last().unwrap().each_ref().map(|face| image::imageops::resize(face, size, size, image::imageops::FilterType::Triangle));
+        mips.push(next);
+    }
+}
+
+/// Upload square faces with all their mip levels as a cube texture
+fn create_cube_texture(device: &wgpu::Device, queue: &wgpu::Queue, mips: &[[RgbaImage; 6]]) -> (wgpu::TextureView, wgpu::Sampler) {
+    let size = mips[0][0].width();
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("skybox"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: mips.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        // every level of one face, then the next face
+        &(0..6).flat_map(|face| mips.iter().flat_map(move |level| level[face].as_raw().iter().copied())).collect::<Vec<_>>(),
+    );
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    (view, sampler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_cross() {
+        // give every cell of the cross its own color
+        let cross = RgbaImage::from_fn(8, 6, |x, y| image::Rgba([(x / 2) as u8, (y / 2) as u8, 0, 255]));
+        let faces = split_cross(&cross).unwrap();
+        let cell = |face: &RgbaImage| (face.get_pixel(1, 1)[0], face.get_pixel(1, 1)[1]);
+
+        assert_eq!(faces.iter().map(cell).collect::<Vec<_>>(), vec![(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)]);
+        assert_eq!(faces[0].dimensions(), (2, 2));
+        assert!(split_cross(&RgbaImage::new(8, 8)).is_err());
+    }
+
+    #[test]
+    fn test_face_direction() {
+        for face in 0..6 {
+            // the center of each face points straight down its axis
+            let center = face_direction(face, 0.0, 0.0);
+            let axis = face / 2;
+            let sign = if face % 2 == 0 { 1.0 } else { -1.0 };
+            assert_eq!(center[axis], sign);
+            assert_eq!(center.magnitude(), 1.0);
+        }
+        // the top of the side faces looks up
+        assert!(face_direction(4, 0.0, -1.0).y > 0.0);
+
+        // the gradient is brighter at the horizon than at the top
+        let faces = gradient_faces(4, [1.0; 3], [0.0; 3], [0.5; 3]);
+        assert!(faces[4].get_pixel(2, 2)[0] > faces[2].get_pixel(2, 2)[0]);
+    }
+
+    #[test]
+    fn test_mip_chain() {
+        let faces = std::array::from_fn(|face| RgbaImage::from_pixel(8, 8, image::Rgba([face as u8 * 40, 0, 0, 255])));
+        let mips = mip_chain(faces);
+        assert_eq!(mips.iter().map(|level| level[0].width()).collect::<Vec<_>>(), vec![8, 4, 2, 1]);
+        // a flat color stays the same color all the way down
+        assert_eq!(mips[3][5].get_pixel(0, 0)[0], 200);
+    }
+}
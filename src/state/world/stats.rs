@@ -0,0 +1,81 @@
+//! Counts describing what the world holds and what it drew last frame
+
+/// Numbers for one model
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelStats {
+    /// instances the model has
+    pub instances: usize,
+    /// instances drawn last frame, after skipping tiny ones (cross-fading ones count once per detail level)
+    pub visible_instances: usize,
+    /// triangles drawn last frame
+    pub triangles: u64,
+    /// bytes of vertex, index, instance and texture data on the GPU
+    pub memory_bytes: u64,
+}
+
+/// Numbers for the whole world, see `World::stats`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldStats {
+    /// stats for every model, in load order
+    pub models: Vec<ModelStats>,
+    /// instances across every model
+    pub instances: usize,
+    /// instances drawn last frame
+    pub visible_instances: usize,
+    /// triangles drawn last frame
+    pub triangles: u64,
+    /// bytes of GPU memory used by the models
+    pub memory_bytes: u64,
+}
+
+impl WorldStats {
+    /// Add up the stats of every model
+    pub fn from_models(models: Vec<ModelStats>) -> Self {
+        Self {
+            instances: models.iter().map(|m| m.instances).sum(),
+            visible_instances: models.iter().map(|m| m.visible_instances).sum(),
+            triangles: models.iter().map(|m| m.triangles).sum(),
+            memory_bytes: models.iter().map(|m| m.memory_bytes).sum(),
+            models,
+        }
+    }
+
+    /// how many models are loaded
+    pub fn model_count(&self) -> usize {
+        self.models.len()
+    }
+}
+
+/// Bytes a texture takes up, counting every mip level
+pub fn texture_bytes(texture: &wgpu::Texture) -> u64 {
+    let format = texture.format();
+    let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+    // compressed formats store a block of texels in block_size bytes
+    let (block_width, block_height) = format.block_dimensions();
+    (0..texture.mip_level_count())
+        .map(|level| {
+            let blocks_wide = (texture.width() >> level).max(1).div_ceil(block_width) as u64;
+            let blocks_high = (texture.height() >> level).max(1).div_ceil(block_height) as u64;
+            blocks_wide * blocks_high * texture.depth_or_array_layers() as u64 * block_size
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_models() {
+        let stats = WorldStats::from_models(vec![
+            ModelStats { instances: 10, visible_instances: 8, triangles: 96, memory_bytes: 1000 },
+            ModelStats { instances: 1, visible_instances: 0, triangles: 0, memory_bytes: 500 },
+        ]);
+
+        assert_eq!(stats.model_count(), 2);
+        assert_eq!(stats.instances, 11);
+        assert_eq!(stats.visible_instances, 8);
+        assert_eq!(stats.triangles, 96);
+        assert_eq!(stats.memory_bytes, 1500);
+    }
+}
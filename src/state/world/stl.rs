@@ -0,0 +1,190 @@
+//! Reads .stl files, the triangle soups 3D printing tools save
+//!
+//! Both the binary and the text form are read into one mesh with the default material. STL files
+//! are Z-up, so they are turned to the Y-up space .obj files use, and their facet normals are
+//! ignored in favor of smoothed ones, since slicers often leave them zeroed.
+use super::{importer::{AssetImporter, ImportedModel}, model::{self, ModelVertex}, normals, resources::MeshData};
+
+/// Size parts are scaled to by default, STL files are usually in millimeters which would be huge here
+pub const DEFAULT_FIT_SIZE: f32 = 1.0;
+
+/// Bytes before the triangle count of a binary file
+const HEADER_SIZE: usize = 80;
+/// Bytes of one triangle in a binary file: normal, three corners and an attribute count
+const TRIANGLE_SIZE: usize = 50;
+
+/// The built in importer for .stl files
+///
+/// Register one with a different `fit_size` to change how parts are sized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StlImporter {
+    /// center each part standing on the origin and scale its longest side to this,
+    /// None keeps the file's own units and position
+    pub fit_size: Option<f32>,
+}
+
+impl Default for StlImporter {
+    fn default() -> Self {
+        Self { fit_size: Some(DEFAULT_FIT_SIZE) }
+    }
+}
+
+impl AssetImporter for StlImporter {
+    fn extensions(&self) -> &[&str] {
+        &["stl"]
+    }
+
+    fn import(&self, file_name: &str, bytes: &[u8]) -> anyhow::Result<ImportedModel> {
+        let corners = if is_binary(bytes) { parse_binary(bytes)? } else { parse_ascii(std::str::from_utf8(bytes)?)? };
+        let mut mesh = mesh_data(file_name, corners);
+        if let Some(size) = self.fit_size {
+            fit(&mut mesh.vertices, size);
+        }
+        // no materials gets the plain default one
+        Ok(ImportedModel { meshes: vec![mesh], materials: Vec::new() })
+    }
+}
+
+/// Whether a file is binary, text files start with "solid" but so do some binary ones, so the size decides
+fn is_binary(bytes: &[u8]) -> bool {
+    match bytes.get(HEADER_SIZE..HEADER_SIZE + 4) {
+        Some(count) => {
+            let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+            bytes.len() == HEADER_SIZE + 4 + count * TRIANGLE_SIZE
+        }
+        None => false,
+    }
+}
+
+/// The corners of every triangle in a binary file, three after three
+fn parse_binary(bytes: &[u8]) -> anyhow::Result<Vec<[f32; 3]>> {
+    let float = |at: usize| f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let count = (bytes.len() - HEADER_SIZE - 4) / TRIANGLE_SIZE;
+    let mut corners = Vec::with_capacity(count * 3);
+    for triangle in 0..count {
+        // skipping the facet normal
+        let start = HEADER_SIZE + 4 + triangle * TRIANGLE_SIZE + 12;
+        for corner in 0..3 {
+            let at = start + corner * 12;
+            corners.push([float(at), float(at + 4), float(at + 8)]);
+        }
+    }
+    Ok(corners)
+}
+
+/// The corners of every triangle in a text file, three after three
+fn parse_ascii(text: &str) -> anyhow::Result<Vec<[f32; 3]>> {
+    let mut corners = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("vertex") {
+            continue;
+        }
+        let mut corner = [0.0; 3];
+        for value in &mut corner {
+            *value = words
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("line {} has a vertex with less than 3 numbers", number + 1))?
+                .parse()?;
+        }
+        corners.push(corner);
+    }
+    if corners.len() % 3 != 0 {
+        anyhow::bail!("{} vertices don't make whole triangles", corners.len());
+    }
+    Ok(corners)
+}
+
+/// One mesh from the corners, turned to Y-up with normals generated
+fn mesh_data(file_name: &str, corners: Vec<[f32; 3]>) -> MeshData {
+    let mut vertices = corners
+        .into_iter()
+        .map(|[x, y, z]| ModelVertex {
+            position: [x, z, -y],
+            tex_coords: [0.0, 0.0],
+            normal: [0.0, 0.0, 0.0],
+            color: [1.0, 1.0, 1.0],
+            tangent: [0.0; 4],
+        })
+        .collect::<Vec<_>>();
+    let mut indices = (0..vertices.len() as u32).collect::<Vec<_>>();
+    normals::generate_normals(&mut vertices, &mut indices, normals::DEFAULT_SMOOTHING_ANGLE);
+    let submeshes = vec![model::Submesh { indices: 0..indices.len() as u32, material: 0 }];
+    MeshData { name: file_name.to_string(), vertices, indices, submeshes, has_tex_coords: false, has_normals: false }
+}
+
+/// Center vertices over the origin with the lowest on it, and scale the longest side of their box to a size
+fn fit(vertices: &mut [ModelVertex], size: f32) {
+    let bounds = model::BoundingBox::from_positions(vertices.iter().map(|v| v.position));
+    let extent = bounds.max - bounds.min;
+    let longest = extent.x.max(extent.y).max(extent.z);
+    if longest <= 0.0 || !longest.is_finite() {
+        return;
+    }
+    let scale = size / longest;
+    let origin = cgmath::Vector3::new((bounds.min.x + bounds.max.x) / 2.0, bounds.min.y, (bounds.min.z + bounds.max.z) / 2.0);
+    for vertex in vertices {
+        vertex.position = ((cgmath::Vector3::from(vertex.position) - origin) * scale).into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// leaves positions as the file has them
+    const UNFITTED: StlImporter = StlImporter { fit_size: None };
+
+    const TRIANGLE: &str = "solid part
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 0 1 0
+  endloop
+endfacet
+endsolid part";
+
+    #[test]
+    fn test_ascii() {
+        let model = UNFITTED.import("part.stl", TRIANGLE.as_bytes()).unwrap();
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.indices.len(), 3);
+        // Z-up turned to Y-up
+        assert_eq!(mesh.vertices[2].position, [0.0, 0.0, -1.0]);
+        assert!((mesh.vertices[0].normal[1] - 1.0).abs() < 1e-5);
+
+        assert!(UNFITTED.import("part.stl", b"solid\nvertex 0 0 0\nendsolid").is_err());
+        assert!(UNFITTED.import("part.stl", b"solid\nvertex 0 0\nendsolid").is_err());
+    }
+
+    #[test]
+    fn test_binary() {
+        // a header starting with "solid" like some exporters write
+        let mut bytes = b"solid binary".to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend(1u32.to_le_bytes());
+        for value in [0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend([0, 0]);
+        assert!(is_binary(&bytes));
+        assert!(!is_binary(TRIANGLE.as_bytes()));
+
+        let model = UNFITTED.import("part.stl", &bytes).unwrap();
+        assert_eq!(model.meshes[0].vertices[1].position, [1.0, 0.0, 0.0]);
+        assert_eq!(model.meshes[0].submeshes, vec![model::Submesh { indices: 0..3, material: 0 }]);
+    }
+
+    #[test]
+    fn test_fit() {
+        // a triangle in millimeters, off to the side and below the ground
+        let text = TRIANGLE.replace("vertex 0 0 0", "vertex 100 0 -50").replace("vertex 1 0 0", "vertex 140 0 -50").replace("vertex 0 1 0", "vertex 100 0 -30");
+        let model = StlImporter::default().import("part.stl", text.as_bytes()).unwrap();
+        let bounds = model::BoundingBox::from_positions(model.meshes[0].vertices.iter().map(|v| v.position));
+        assert!((bounds.max.x - bounds.min.x - DEFAULT_FIT_SIZE).abs() < 1e-5);
+        assert!((bounds.min.x + bounds.max.x).abs() < 1e-5);
+        assert!(bounds.min.y.abs() < 1e-5);
+        assert!((bounds.max.y - 0.5).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,169 @@
+//! Textures filled with frames made outside the renderer, by the host application or a video decoder
+//!
+//! A material with `map_stream <name>` in its .mtl draws from a texture that frames can be pushed into
+//! from any thread through an `ImageStream`, see `App::image_stream`, for webcam feeds, charts or remote
+//! views on surfaces in the world. Frames wait in a short channel and the newest is copied into the
+//! texture when the models are prepared. Frames pushed while the channel is full are dropped.
+use std::sync::{mpsc, Arc};
+
+use super::{texture::{SamplerSettings, Texture}, video::Decoder};
+
+/// Size of stream and video textures when the .mtl has no `video_size`
+pub const DEFAULT_STREAM_SIZE: (u32, u32) = (640, 360);
+
+/// Frames that can wait to be uploaded, more smooths out uneven producers at the cost of latency
+const QUEUED_FRAMES: usize = 2;
+
+/// Where frames for a stream texture are pushed, cheap to clone and send to other threads
+#[derive(Clone, Debug)]
+pub struct ImageStream {
+    sender: mpsc::SyncSender<Vec<u8>>,
+    width: u32,
+    height: u32,
+}
+
+impl ImageStream {
+    /// Width and height in pixels every frame has to be
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Queue a frame of tightly packed RGBA pixels, rows from the top
+    ///
+    /// Fails when the frame is the wrong size or the texture was unloaded.
+    pub fn push(&self, pixels: Vec<u8>) -> anyhow::Result<()> {
+        let expected = frame_bytes(self.width, self.height);
+        if pixels.len() != expected {
+            anyhow::bail!("a {}x{} frame is {} bytes, not {}", self.width, self.height, expected, pixels.len());
+        }
+        match self.sender.try_send(pixels) {
+            // a full queue means frames come faster than they're drawn, this one is skipped
+            Ok(()) | Err(mpsc::TrySendError::Full(_)) => Ok(()),
+            Err(mpsc::TrySendError::Disconnected(_)) => anyhow::bail!("the stream's texture is gone"),
+        }
+    }
+
+    /// Queue an image, which has to be the stream's size
+    pub fn push_image(&self, image: image::RgbaImage) -> anyhow::Result<()> {
+        if image.dimensions() != self.size() {
+            anyhow::bail!("a {}x{} image can't go in a {}x{} stream", image.width(), image.height(), self.width, self.height);
+        }
+        self.push(image.into_raw())
+    }
+}
+
+/// A texture showing the newest frame pushed into its stream
+pub struct StreamTexture {
+    /// what `map_stream` called it, or the video file playing on it
+    pub name: String,
+    /// bound as the material's diffuse texture, a single level since it changes every frame
+    pub texture: Arc<Texture>,
+    stream: ImageStream,
+    frames: mpsc::Receiver<Vec<u8>>,
+    /// the video filling the texture, stopped when it is dropped
+    decoder: Option<Decoder>,
+}
+
+impl StreamTexture {
+    /// A black texture waiting for frames
+    pub fn new(name: String, width: u32, height: u32, device: &wgpu::Device) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let (sender, frames) = mpsc::sync_channel(QUEUED_FRAMES);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&name),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = SamplerSettings::default().create_sampler(device);
+        Self {
+            name,
+            texture: Arc::new(Texture { texture, view, sampler }),
+            stream: ImageStream { sender, width, height },
+            frames,
+            decoder: None,
+        }
+    }
+
+    /// A handle frames can be pushed through
+    pub fn stream(&self) -> ImageStream {
+        self.stream.clone()
+    }
+
+    /// Keep a video decoder running for as long as the texture is around
+    pub fn set_decoder(&mut self, decoder: Decoder) {
+        self.decoder = Some(decoder);
+    }
+
+    /// Show the newest frame pushed since the last call, false when there wasn't one
+    pub fn update(&self, queue: &wgpu::Queue) -> bool {
+        let Some(frame) = self.frames.try_iter().last() else {
+            return false;
+        };
+        let (width, height) = self.stream.size();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &frame,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        true
+    }
+}
+
+/// Bytes in an RGBA frame
+pub fn frame_bytes(width: u32, height: u32) -> usize {
+    width as usize * height as usize * 4
+}
+
+/// Read `video_size` from a .mtl, width and height in pixels
+pub fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let mut parts = value.split_whitespace().map(str::parse::<u32>);
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(width)), Some(Ok(height)), None) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let (sender, frames) = mpsc::sync_channel(QUEUED_FRAMES);
+        let stream = ImageStream { sender, width: 2, height: 1 };
+        assert!(stream.push(vec![0; 4]).is_err());
+        // frames past the queue are dropped rather than blocking the producer
+        for value in 0..4 {
+            stream.push(vec![value; 8]).unwrap();
+        }
+        assert_eq!(frames.try_iter().map(|frame| frame[0]).collect::<Vec<_>>(), vec![0, 1]);
+        assert!(stream.push_image(image::RgbaImage::new(1, 2)).is_err());
+        stream.push_image(image::RgbaImage::new(2, 1)).unwrap();
+        drop(frames);
+        assert!(stream.push(vec![0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size(" 1280 720 "), Some((1280, 720)));
+        assert_eq!(parse_size("1280"), None);
+        assert_eq!(parse_size("0 720"), None);
+        assert_eq!(parse_size("1280 720 3"), None);
+    }
+}
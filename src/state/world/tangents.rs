@@ -0,0 +1,92 @@
+//! Build the tangents normal maps are read along
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+use super::model::ModelVertex;
+
+/// Give every vertex a tangent from the texture coordinates of the triangles around it
+///
+/// The tangent points where u grows along the surface, made square to the vertex normal.
+/// Its w is 1 or -1 for whether v grows along `normal x tangent` or against it, mirrored uvs
+/// flip it. Vertices without uvs, or whose triangles have no area in uv space, get a zero
+/// tangent and the shader ignores the normal map for them.
+pub fn generate_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut tangents = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    let mut bitangents = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| &vertices[i as usize]);
+        let edge1 = Vector3::from(b.position) - Vector3::from(a.position);
+        let edge2 = Vector3::from(c.position) - Vector3::from(a.position);
+        let uv1 = Vector2::from(b.tex_coords) - Vector2::from(a.tex_coords);
+        let uv2 = Vector2::from(c.tex_coords) - Vector2::from(a.tex_coords);
+
+        let determinant = uv1.x * uv2.y - uv2.x * uv1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        // bigger triangles count for more since the sums aren't divided by the area
+        let tangent = (edge1 * uv2.y - edge2 * uv1.y) * determinant.signum();
+        let bitangent = (edge2 * uv1.x - edge1 * uv2.x) * determinant.signum();
+        for &i in triangle {
+            tangents[i as usize] += tangent;
+            bitangents[i as usize] += bitangent;
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = Vector3::from(vertex.normal);
+        // keep only the part of the tangent lying flat on the surface
+        let tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < f32::EPSILON * f32::EPSILON || normal.magnitude2() < f32::EPSILON {
+            vertex.tangent = [0.0; 4];
+            continue;
+        }
+        let tangent = tangent.normalize();
+        // tex_coords have v flipped, which flips the handedness the shader expects
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { 1.0 } else { -1.0 };
+        vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], tex_coords: [f32; 2]) -> ModelVertex {
+        ModelVertex { position, tex_coords, normal: [0.0, 0.0, 1.0], color: [1.0, 1.0, 1.0], tangent: [0.0; 4] }
+    }
+
+    #[test]
+    fn test_quad_tangents() {
+        // a quad facing +z with u along +x, tex_coords already have v flipped like the loader does
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+            vertex([1.0, 1.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+        ];
+        generate_tangents(&mut vertices, &[0, 1, 2, 0, 2, 3]);
+        for vertex in &vertices {
+            assert_eq!(vertex.tangent, [1.0, 0.0, 0.0, 1.0]);
+        }
+
+        // mirroring the uvs across u flips the tangent and keeps the handedness
+        let mut mirrored = vertices.clone();
+        for vertex in &mut mirrored {
+            vertex.tex_coords[0] = 1.0 - vertex.tex_coords[0];
+        }
+        generate_tangents(&mut mirrored, &[0, 1, 2, 0, 2, 3]);
+        assert_eq!(mirrored[0].tangent, [-1.0, 0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn test_no_uvs_no_tangent() {
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+        ];
+        generate_tangents(&mut vertices, &[0, 1, 2]);
+        assert_eq!(vertices[0].tangent, [0.0; 4]);
+    }
+}
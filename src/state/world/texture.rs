@@ -1,26 +1,37 @@
 /// file to handle loading textures
+use std::sync::{Arc, Mutex};
+
 use image::GenericImageView;
 use anyhow::*;
 
+/// Format of shadow maps, every adapter can render and sample it
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// First bytes of every KTX2 file
+pub const KTX2_MAGIC: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Block compressed formats a device should be asked for when the adapter has them, so KTX2 textures encoded to them load
+pub const COMPRESSION_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ETC2)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ASTC);
+
 // data structure to store textures
 pub struct Texture {
-    #[allow(unused)]
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
 impl Texture {
-    // we need a format for creating the depth texture
-    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
-    
     /// Function to create depth texture
     /// 
     /// Args:
     ///     device: device to create depth texture for
     ///     config: config for screen
+    ///     format: depth (and maybe stencil) format to use
+    ///     sample_count: samples per pixel, the same as the color target
     ///     label: label/name for depth texture
-    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, format: wgpu::TextureFormat, sample_count: u32, label: &str) -> Self {
         // we need to make sure the depth texture is the same size of the screen
         let size = wgpu::Extent3d {
             width: config.width.max(1),
@@ -31,9 +42,9 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count, // has to match the color target when multisampling
             dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
+            format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT // we are going to render this texture
                 | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
@@ -59,15 +70,163 @@ impl Texture {
         Self { texture, view, sampler }
     }
 
-    // load texture from bytes
+    /// Create a square depth texture the sun's view is rendered into, sampled with a comparison sampler
+    ///
+    /// Args:
+    ///     device: device to create the shadow map for
+    ///     size: width and height in texels
+    ///     label: label/name for the shadow map
+    pub fn create_shadow_map(device: &wgpu::Device, size: u32, label: &str) -> Self {
+        let desc = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size.max(1),
+                height: size.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT // the shadow pass draws into it
+                | wgpu::TextureUsages::TEXTURE_BINDING, // and the main pass reads it
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&desc);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                // anything outside the map is treated as lit by the shader
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                // linear filtering compares the 4 closest texels, softening the edges a little
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                ..Default::default()
+            }
+        );
+
+        Self { texture, view, sampler }
+    }
+
+    // load texture from bytes, KTX2 files are uploaded as they are and anything else is decoded as an image
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8], 
-        label: &str
+        label: &str,
+        is_linear: bool,
     ) -> Result<Self> {
+        if bytes.starts_with(&KTX2_MAGIC) {
+            return Self::from_ktx2(device, queue, bytes, label, is_linear);
+        }
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image(device, queue, &img, Some(label), is_linear)
+    }
+
+    /// Load a KTX2 texture, keeping the block compressed format it was encoded to
+    ///
+    /// The file's mip levels are used as they are, unpacking zstd supercompressed ones first. Basis
+    /// Universal files (ETC1S or UASTC) would have to be transcoded to a format the GPU samples, there is
+    /// no transcoder yet, so they are an error that names which of the two the file is.
+    ///
+    /// Args:
+    ///     bytes: the whole file
+    ///     label: label/name for the texture, also used in errors
+    ///     is_linear: whether the texture holds data like normals rather than colors, which picks the sRGB or plain format
+    pub fn from_ktx2(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str, is_linear: bool) -> Result<Self> {
+        let reader = ktx2::Reader::new(bytes).map_err(|e| anyhow!("{}: not a KTX2 file: {}", label, e))?;
+        let header = reader.header();
+        let file_format = header.format.ok_or_else(|| {
+            anyhow!("{}: Basis Universal {} textures can't be transcoded yet, encode it to BC7, ASTC or ETC2 instead", label, basis_encoding(&reader))
+        })?;
+        let format = ktx2_format(file_format, is_linear).ok_or_else(|| anyhow!("{}: KTX2 format {:?} isn't supported", label, file_format))?;
+        if !device.features().contains(format.required_features()) {
+            bail!("{}: the GPU can't sample {:?} textures", label, format);
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            bail!("{}: only flat 2D textures can be loaded from KTX2", label);
+        }
+        let (block_width, block_height) = format.block_dimensions();
+        if header.pixel_width % block_width != 0 || header.pixel_height % block_height != 0 {
+            bail!("{}: {}x{} isn't a whole number of {}x{} blocks", label, header.pixel_width, header.pixel_height, block_width, block_height);
+        }
+
+        let levels = reader.levels().map(|level| match header.supercompression_scheme {
+            None => Ok(level.data.to_vec()),
+            Some(ktx2::SupercompressionScheme::Zstandard) => unzstd(level.data),
+            Some(scheme) => bail!("{}: {:?} supercompression isn't supported", label, scheme),
+        }).collect::<Result<Vec<_>>>()?;
+
+        // uncompressed files with a single level get the rest drawn like other images
+        let generate_mips = levels.len() == 1 && !format.is_compressed();
+        let size = wgpu::Extent3d {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: if generate_mips { mip_level_count(size.width, size.height) } else { levels.len() as u32 },
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST
+                    | if generate_mips { wgpu::TextureUsages::RENDER_ATTACHMENT } else { wgpu::TextureUsages::empty() },
+                view_formats: &[],
+            }
+        );
+
+        let block_size = format.block_copy_size(None).unwrap_or(4);
+        for (level, data) in levels.iter().enumerate() {
+            // levels smaller than a block still take up a whole one
+            let level_size = size.mip_level_size(level as u32, wgpu::TextureDimension::D2).physical_size(format);
+            let (blocks_wide, blocks_high) = (level_size.width / block_width, level_size.height / block_height);
+            if data.len() < (blocks_wide * blocks_high * block_size) as usize {
+                bail!("{}: mip level {} is cut short", label, level);
+            }
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(blocks_wide * block_size),
+                    rows_per_image: Some(blocks_high),
+                },
+                level_size,
+            );
+        }
+        if generate_mips {
+            generate_mipmaps(device, &texture);
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = SamplerSettings::default().create_sampler(device);
+
+        Ok(Self { texture, view, sampler })
+    }
+
+    // plain white texture for materials that don't have one
+    pub fn white(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])));
+        Self::from_image(device, queue, &img, Some("white"), false)
+    }
+
+    // normal map pointing straight out of the surface, for materials that don't have one
+    pub fn flat_normal(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255])));
+        Self::from_image(device, queue, &img, Some("flat_normal"), true)
     }
 
     // load texture from image
@@ -75,7 +234,8 @@ impl Texture {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
-        label: Option<&str>
+        label: Option<&str>,
+        is_linear: bool,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -85,15 +245,19 @@ impl Texture {
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
+        // a full chain of smaller copies, so far away surfaces sample an average instead of shimmering
+        let mip_level_count = mip_level_count(dimensions.0, dimensions.1);
         let texture = device.create_texture(
             &wgpu::TextureDescriptor {
                 label,
                 size,
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                // normal and roughness maps hold data rather than colors, reading them as sRGB would skew them
+                format: if is_linear { wgpu::TextureFormat::Rgba8Unorm } else { wgpu::TextureFormat::Rgba8UnormSrgb },
+                // the smaller levels are drawn from the larger ones
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::RENDER_ATTACHMENT,
                 view_formats: &[],
             }
         );
@@ -113,20 +277,291 @@ impl Texture {
             },
             size,
         );
+        generate_mipmaps(device, &texture);
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
-            &wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                ..Default::default()
-            }
-        );
+        let sampler = SamplerSettings::default().create_sampler(device);
         
         Ok(Self { texture, view, sampler })
     }
+}
+
+/// How a texture is sampled, materials pick theirs in the .mtl, see `resources::sampler_settings`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerSettings {
+    /// what happens past the edges of the texture, on both axes
+    pub address_mode: wgpu::AddressMode,
+    /// linear blends texels and mip levels (trilinear), nearest keeps pixel art blocky
+    pub filter: wgpu::FilterMode,
+    /// samples taken along surfaces seen at a slant, 1 is off and 16 the most
+    pub anisotropy: u16,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self { address_mode: wgpu::AddressMode::ClampToEdge, filter: wgpu::FilterMode::Linear, anisotropy: 1 }
+    }
+}
+
+impl SamplerSettings {
+    /// Most samples anisotropic filtering can take
+    pub const MAX_ANISOTROPY: u16 = 16;
+
+    /// The sampler these settings describe
+    pub fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        // anisotropic filtering only works with every filter linear
+        let anisotropy = if self.filter == wgpu::FilterMode::Linear { self.anisotropy.clamp(1, Self::MAX_ANISOTROPY) } else { 1 };
+        wgpu::SamplerDescriptor {
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.filter,
+            min_filter: self.filter,
+            mipmap_filter: self.filter,
+            anisotropy_clamp: anisotropy,
+            ..Default::default()
+        }
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&self.descriptor())
+    }
+
+    /// An address mode by its name, `clamp`, `repeat` or `mirror`
+    pub fn parse_address_mode(name: &str) -> Option<wgpu::AddressMode> {
+        match name {
+            "clamp" => Some(wgpu::AddressMode::ClampToEdge),
+            "repeat" => Some(wgpu::AddressMode::Repeat),
+            "mirror" => Some(wgpu::AddressMode::MirrorRepeat),
+            _ => None,
+        }
+    }
+
+    /// A filter by its name, `linear` or `nearest`
+    pub fn parse_filter(name: &str) -> Option<wgpu::FilterMode> {
+        match name {
+            "linear" => Some(wgpu::FilterMode::Linear),
+            "nearest" => Some(wgpu::FilterMode::Nearest),
+            _ => None,
+        }
+    }
+}
+
+/// A view of only the depth of a depth texture, for reading it in a shader
+///
+/// Formats with stencil can't be sampled through a view of every aspect.
+pub fn depth_only_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        aspect: wgpu::TextureAspect::DepthOnly,
+        ..Default::default()
+    })
+}
+
+/// Mip levels in a full chain down to 1x1 for a texture size
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// The pipeline drawing mip levels of one format on one device, see `mipmap.wgsl`
+struct MipBlit {
+    device: wgpu::Id<wgpu::Device>,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+}
+
+/// Pipelines made so far, textures load on the task threads so they are shared between them
+static MIP_BLITS: Mutex<Vec<Arc<MipBlit>>> = Mutex::new(Vec::new());
+
+/// The pipeline for a format, made the first time a texture of that format is loaded
+fn mip_blit(device: &wgpu::Device, format: wgpu::TextureFormat) -> Arc<MipBlit> {
+    let mut blits = MIP_BLITS.lock().unwrap();
+    if let Some(blit) = blits.iter().find(|blit| blit.device == device.global_id() && blit.format == format) {
+        return blit.clone();
+    }
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../../mipmap.wgsl"));
+    // the layout comes from the shader, it only reads one texture and a sampler
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Mipmap Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    let blit = Arc::new(MipBlit { device: device.global_id(), format, pipeline, sampler });
+    blits.push(blit.clone());
+    blit
+}
+
+/// The texture format a KTX2 format is uploaded as, None for ones that aren't supported
+///
+/// Like images, whether the texture is read as sRGB goes by what it is used for rather than the file.
+fn ktx2_format(format: ktx2::Format, is_linear: bool) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format as K;
+    use wgpu::{AstcBlock, AstcChannel, TextureFormat as T};
+
+    let format = match format {
+        K::R8G8B8A8_UNORM | K::R8G8B8A8_SRGB => T::Rgba8Unorm,
+        K::BC1_RGB_UNORM_BLOCK | K::BC1_RGB_SRGB_BLOCK | K::BC1_RGBA_UNORM_BLOCK | K::BC1_RGBA_SRGB_BLOCK => T::Bc1RgbaUnorm,
+        K::BC2_UNORM_BLOCK | K::BC2_SRGB_BLOCK => T::Bc2RgbaUnorm,
+        K::BC3_UNORM_BLOCK | K::BC3_SRGB_BLOCK => T::Bc3RgbaUnorm,
+        K::BC4_UNORM_BLOCK => T::Bc4RUnorm,
+        K::BC5_UNORM_BLOCK => T::Bc5RgUnorm,
+        K::BC7_UNORM_BLOCK | K::BC7_SRGB_BLOCK => T::Bc7RgbaUnorm,
+        K::ETC2_R8G8B8_UNORM_BLOCK | K::ETC2_R8G8B8_SRGB_BLOCK => T::Etc2Rgb8Unorm,
+        K::ETC2_R8G8B8A1_UNORM_BLOCK | K::ETC2_R8G8B8A1_SRGB_BLOCK => T::Etc2Rgb8A1Unorm,
+        K::ETC2_R8G8B8A8_UNORM_BLOCK | K::ETC2_R8G8B8A8_SRGB_BLOCK => T::Etc2Rgba8Unorm,
+        K::EAC_R11_UNORM_BLOCK => T::EacR11Unorm,
+        K::EAC_R11G11_UNORM_BLOCK => T::EacRg11Unorm,
+        K::ASTC_4x4_UNORM_BLOCK | K::ASTC_4x4_SRGB_BLOCK => T::Astc { block: AstcBlock::B4x4, channel: AstcChannel::Unorm },
+        K::ASTC_6x6_UNORM_BLOCK | K::ASTC_6x6_SRGB_BLOCK => T::Astc { block: AstcBlock::B6x6, channel: AstcChannel::Unorm },
+        K::ASTC_8x8_UNORM_BLOCK | K::ASTC_8x8_SRGB_BLOCK => T::Astc { block: AstcBlock::B8x8, channel: AstcChannel::Unorm },
+        _ => return None,
+    };
+    Some(if is_linear { format } else { format.add_srgb_suffix() })
+}
+
+/// Unpack a zstd supercompressed KTX2 level
+/// Which Basis Universal encoding a KTX2 file without a format holds, from its supercompression or its data format
+fn basis_encoding(reader: &ktx2::Reader<&[u8]>) -> &'static str {
+    if reader.header().supercompression_scheme == Some(ktx2::SupercompressionScheme::BasisLZ) {
+        return "ETC1S";
+    }
+    let color_model = reader.dfd_blocks().find_map(|block| ktx2::DfdBlockBasic::parse(block.data).ok()).and_then(|block| block.header.color_model);
+    match color_model {
+        Some(ktx2::ColorModel::ETC1S) => "ETC1S",
+        Some(ktx2::ColorModel::UASTC) => "UASTC",
+        _ => "(unknown encoding)",
+    }
+}
+
+fn unzstd(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = ruzstd::decoding::StreamingDecoder::new(data)?;
+    let mut unpacked = Vec::new();
+    std::io::Read::read_to_end(&mut decoder, &mut unpacked)?;
+    Ok(unpacked)
+}
+
+/// Fill in every mip level below the first by drawing each from the level above it
+pub fn generate_mipmaps(device: &wgpu::Device, texture: &wgpu::Texture) {
+    if texture.mip_level_count() < 2 {
+        return;
+    }
+    let blit = mip_blit(device, texture.format());
+    let level_view = |level| texture.create_view(&wgpu::TextureViewDescriptor {
+        base_mip_level: level,
+        mip_level_count: Some(1),
+        ..Default::default()
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Mipmap Encoder") });
+    for level in 1..texture.mip_level_count() {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &blit.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&level_view(level - 1)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&blit.sampler),
+                },
+            ],
+            label: Some("Mipmap Bind Group"),
+        });
+        let target = level_view(level);
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mipmap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&blit.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+    // goes out with the next frame rather than in a submission of its own
+    crate::state::submit::defer(device, encoder.finish());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mip_level_count() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        // the longer side decides, sizes that aren't powers of two round down each level
+        assert_eq!(mip_level_count(300, 2), 9);
+        assert_eq!(mip_level_count(0, 0), 1);
+    }
+
+    #[test]
+    fn test_sampler_descriptor() {
+        let settings = SamplerSettings { address_mode: wgpu::AddressMode::Repeat, filter: wgpu::FilterMode::Linear, anisotropy: 64 };
+        let descriptor = settings.descriptor();
+        assert_eq!(descriptor.anisotropy_clamp, SamplerSettings::MAX_ANISOTROPY);
+        assert_eq!(descriptor.address_mode_v, wgpu::AddressMode::Repeat);
+        // nearest filtering can't be anisotropic
+        let descriptor = SamplerSettings { filter: wgpu::FilterMode::Nearest, ..settings }.descriptor();
+        assert_eq!((descriptor.anisotropy_clamp, descriptor.mipmap_filter), (1, wgpu::FilterMode::Nearest));
+        assert_eq!(SamplerSettings::parse_address_mode("mirror"), Some(wgpu::AddressMode::MirrorRepeat));
+        assert_eq!(SamplerSettings::parse_filter("cubic"), None);
+    }
+
+    #[test]
+    fn test_ktx2_format() {
+        // colors are read as sRGB whatever the file says, data never is
+        assert_eq!(ktx2_format(ktx2::Format::BC7_UNORM_BLOCK, false), Some(wgpu::TextureFormat::Bc7RgbaUnormSrgb));
+        assert_eq!(ktx2_format(ktx2::Format::BC7_SRGB_BLOCK, true), Some(wgpu::TextureFormat::Bc7RgbaUnorm));
+        assert_eq!(ktx2_format(ktx2::Format::BC5_UNORM_BLOCK, false), Some(wgpu::TextureFormat::Bc5RgUnorm));
+        assert_eq!(
+            ktx2_format(ktx2::Format::ASTC_4x4_UNORM_BLOCK, false),
+            Some(wgpu::TextureFormat::Astc { block: wgpu::AstcBlock::B4x4, channel: wgpu::AstcChannel::UnormSrgb }),
+        );
+        assert_eq!(ktx2_format(ktx2::Format::R16G16B16A16_SFLOAT, false), None);
+    }
+
+    #[test]
+    fn test_unzstd() {
+        let level = vec![7u8; 4096];
+        let packed = ruzstd::encoding::compress_to_vec(level.as_slice(), ruzstd::encoding::CompressionLevel::Fastest);
+        assert!(packed.len() < level.len());
+        assert_eq!(unzstd(&packed).unwrap(), level);
+        assert!(unzstd(&[1, 2, 3]).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+//! Step back and forward through edits
+/// How many edits can be undone before the oldest is forgotten
+pub const UNDO_LIMIT: usize = 100;
+
+/// Snapshots taken before each edit
+///
+/// Edits are undone by swapping the current state for the snapshot before it, so anything that
+/// can be cloned can be undone without writing an inverse for every kind of edit.
+#[derive(Clone, Debug)]
+pub struct UndoStack<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    limit: usize,
+}
+
+impl<T> Default for UndoStack<T> {
+    fn default() -> Self {
+        Self::new(UNDO_LIMIT)
+    }
+}
+
+impl<T> UndoStack<T> {
+    pub fn new(limit: usize) -> Self {
+        Self { undo: Vec::new(), redo: Vec::new(), limit }
+    }
+
+    /// Remember the state before an edit, anything undone so far can't be redone anymore
+    pub fn record(&mut self, before: T) {
+        self.undo.push(before);
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// The state before the last edit, if there is one
+    ///
+    /// Args:
+    ///     current: the state now, kept so the edit can be redone
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let before = self.undo.pop()?;
+        self.redo.push(current);
+        Some(before)
+    }
+
+    /// The state after the last undone edit, if there is one
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let after = self.redo.pop()?;
+        self.undo.push(current);
+        Some(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut stack = UndoStack::default();
+        stack.record(1);
+        stack.record(2);
+
+        assert_eq!(stack.undo(3), Some(2));
+        assert_eq!(stack.undo(2), Some(1));
+        assert_eq!(stack.undo(1), None);
+        assert_eq!(stack.redo(1), Some(2));
+
+        // a new edit drops what could have been redone
+        stack.record(2);
+        assert_eq!(stack.redo(5), None);
+        assert_eq!(stack.undo(5), Some(2));
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut stack = UndoStack::new(2);
+        for state in 0..5 {
+            stack.record(state);
+        }
+        assert_eq!(stack.undo(5), Some(4));
+        assert_eq!(stack.undo(4), Some(3));
+        assert_eq!(stack.undo(3), None);
+    }
+}
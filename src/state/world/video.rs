@@ -0,0 +1,81 @@
+//! Videos playing on a material's diffuse texture, `map_video` in its .mtl, for screens in a scene
+//!
+//! ffmpeg decodes the file in a process of its own and writes raw RGBA frames to its output, which a
+//! background thread pushes into the material's stream texture, see `stream`. Videos loop at their own
+//! speed and ffmpeg has to be on the path, like for recording.
+use std::{
+    io::Read,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use super::stream::{frame_bytes, ImageStream};
+
+/// An ffmpeg decoding a video into a stream, stopped when it is dropped
+pub struct Decoder {
+    process: Child,
+}
+
+impl Decoder {
+    /// Start decoding a video into a stream, scaled to the stream's size
+    pub fn start(path: &Path, stream: ImageStream) -> anyhow::Result<Self> {
+        if !path.is_file() {
+            anyhow::bail!("no video at {}", path.display());
+        }
+        let (width, height) = stream.size();
+        let mut process = Command::new("ffmpeg")
+            .args(decode_args(path, width, height))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("couldn't start ffmpeg: {}", e))?;
+        let mut output = process.stdout.take().ok_or_else(|| anyhow::anyhow!("ffmpeg has no output"))?;
+
+        let name = path.display().to_string();
+        std::thread::spawn(move || loop {
+            let mut frame = vec![0; frame_bytes(width, height)];
+            if let Err(e) = output.read_exact(&mut frame) {
+                log::warn!("{} stopped playing: {}", name, e);
+                break;
+            }
+            if stream.push(frame).is_err() {
+                // the texture was dropped
+                break;
+            }
+        });
+        Ok(Self { process })
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        // ffmpeg loops forever, so it has to be stopped
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// ffmpeg's arguments for looping a video at its own pace as raw RGBA frames of a size on its output
+fn decode_args(path: &Path, width: u32, height: u32) -> Vec<String> {
+    let mut args = ["-loglevel", "error", "-re", "-stream_loop", "-1", "-i"].map(String::from).to_vec();
+    args.push(path.display().to_string());
+    args.extend(["-vf".to_string(), format!("scale={}:{}", width, height)]);
+    args.extend(["-f", "rawvideo", "-pix_fmt", "rgba", "-"].map(String::from));
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_args() {
+        let args = decode_args(Path::new("screen.mp4"), 320, 240);
+        // the file is read once, looped, and written raw to the output
+        assert_eq!(args.iter().position(|arg| arg == "-i").map(|index| &args[index + 1]), Some(&"screen.mp4".to_string()));
+        assert!(args.windows(2).any(|pair| pair == ["-stream_loop", "-1"]));
+        assert!(args.windows(2).any(|pair| pair == ["-vf", "scale=320:240"]));
+        assert!(args.windows(2).any(|pair| pair == ["-pix_fmt", "rgba"]));
+        assert_eq!(args.last().map(String::as_str), Some("-"));
+    }
+}
@@ -0,0 +1,369 @@
+//! Reads MagicaVoxel .vox files
+//!
+//! Every model in the file becomes a mesh, built with greedy meshing so a flat wall of voxels of
+//! one color is a single quad instead of a quad per voxel face. Colors come from the palette as
+//! vertex colors, and palette entries MagicaVoxel marks as metal get a material of their own.
+//! Files are Z-up like the editor, they are turned to Y-up with each model standing on the origin.
+//! The scene graph chunks placing several models relative to each other aren't read.
+use std::collections::HashMap;
+
+use super::{importer::{srgb_to_linear, AssetImporter, ImportedMaterial, ImportedModel}, model::{self, ModelVertex}, resources::MeshData};
+
+/// Length of one voxel's edge in the world
+pub const VOXEL_SIZE: f32 = 0.1;
+
+/// The built in importer for .vox files
+pub struct VoxImporter;
+
+impl AssetImporter for VoxImporter {
+    fn extensions(&self) -> &[&str] {
+        &["vox"]
+    }
+
+    fn import(&self, file_name: &str, bytes: &[u8]) -> anyhow::Result<ImportedModel> {
+        let file = parse(bytes)?;
+
+        // material 0 is plain, metal palette entries each get the next one
+        let mut materials = vec![ImportedMaterial { name: "palette".to_string(), ..Default::default() }];
+        let mut palette_materials = HashMap::new();
+        let mut metals = file.metals.iter().collect::<Vec<_>>();
+        metals.sort_by_key(|(index, _)| **index);
+        for (&index, &(metallic, roughness)) in metals {
+            palette_materials.insert(index, materials.len());
+            materials.push(ImportedMaterial { name: format!("metal {}", index), metallic, roughness, ..Default::default() });
+        }
+
+        let meshes = file.models
+            .iter()
+            .enumerate()
+            .map(|(i, grid)| {
+                let name = if file.models.len() == 1 { file_name.to_string() } else { format!("{}#{}", file_name, i) };
+                mesh_data(name, grid, &file.palette, &palette_materials)
+            })
+            .collect();
+        Ok(ImportedModel { meshes, materials })
+    }
+}
+
+/// One model's voxels, palette indices with 0 for empty
+struct VoxelGrid {
+    size: [usize; 3],
+    voxels: Vec<u8>,
+}
+
+impl VoxelGrid {
+    /// The palette index at a voxel, 0 outside the grid
+    fn get(&self, at: [i64; 3]) -> u8 {
+        if (0..3).any(|axis| at[axis] < 0 || at[axis] >= self.size[axis] as i64) {
+            return 0;
+        }
+        let [x, y, z] = at.map(|a| a as usize);
+        self.voxels[x + self.size[0] * (y + self.size[1] * z)]
+    }
+}
+
+/// What was read from a file
+struct VoxFile {
+    models: Vec<VoxelGrid>,
+    /// colors by palette index, index 0 is unused
+    palette: [[u8; 4]; 256],
+    /// metallic and roughness of palette entries with a metal material
+    metals: HashMap<u8, (f32, f32)>,
+}
+
+/// Reads little endian numbers and strings from a chunk
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            anyhow::bail!("the file ends in the middle of a chunk");
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+}
+
+/// Read the chunks of a file
+fn parse(bytes: &[u8]) -> anyhow::Result<VoxFile> {
+    let mut reader = Reader { bytes };
+    if reader.take(4)? != b"VOX " {
+        anyhow::bail!("not a MagicaVoxel file");
+    }
+    let _version = reader.u32()?;
+    if reader.take(4)? != b"MAIN" {
+        anyhow::bail!("the file doesn't start with a MAIN chunk");
+    }
+    // MAIN has no content of its own, every other chunk is one of its children
+    let _ = (reader.u32()?, reader.u32()?);
+
+    let mut file = VoxFile { models: Vec::new(), palette: default_palette(), metals: HashMap::new() };
+    let mut size = None;
+    while !reader.bytes.is_empty() {
+        let id = reader.take(4)?;
+        let content_size = reader.u32()? as usize;
+        let children_size = reader.u32()? as usize;
+        let mut content = Reader { bytes: reader.take(content_size)? };
+        reader.take(children_size)?;
+
+        match id {
+            b"SIZE" => size = Some([content.u32()?, content.u32()?, content.u32()?].map(|s| s as usize)),
+            b"XYZI" => {
+                let size = size.take().ok_or_else(|| anyhow::anyhow!("voxels without a SIZE chunk before them"))?;
+                let mut grid = VoxelGrid { size, voxels: vec![0; size[0] * size[1] * size[2]] };
+                for _ in 0..content.u32()? {
+                    let [x, y, z, index] = content.take(4)?.try_into().unwrap();
+                    if (x as usize) < size[0] && (y as usize) < size[1] && (z as usize) < size[2] {
+                        grid.voxels[x as usize + size[0] * (y as usize + size[1] * z as usize)] = index;
+                    }
+                }
+                file.models.push(grid);
+            }
+            b"RGBA" => {
+                // the chunk's first color is palette index 1
+                for index in 1..256 {
+                    file.palette[index] = content.take(4)?.try_into().unwrap();
+                }
+            }
+            b"MATL" => {
+                let index = content.u32()?;
+                let properties = (0..content.u32()?)
+                    .map(|_| Ok((content.string()?, content.string()?)))
+                    .collect::<anyhow::Result<HashMap<_, _>>>()?;
+                let number = |key: &str, default: f32| properties.get(key).and_then(|v| v.parse().ok()).unwrap_or(default);
+                if properties.get("_type").map(String::as_str) == Some("_metal") && (1..256).contains(&index) {
+                    file.metals.insert(index as u8, (number("_metal", 1.0), number("_rough", 0.5)));
+                }
+            }
+            _ => {}
+        }
+    }
+    if file.models.is_empty() {
+        anyhow::bail!("the file has no voxels");
+    }
+    Ok(file)
+}
+
+/// The palette files without an RGBA chunk use
+///
+/// Index 1 to 215 step through a cube of colors from white to almost black, then come ramps of
+/// blue, green, red and gray.
+fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0; 4]; 256];
+    let levels = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let mut index = 1;
+    for r in levels {
+        for g in levels {
+            for b in levels {
+                if index < 216 {
+                    palette[index] = [r, g, b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for channels in [[false, false, true], [false, true, false], [true, false, false], [true, true, true]] {
+        for level in ramp {
+            let [r, g, b] = channels.map(|on| if on { level } else { 0 });
+            palette[index] = [r, g, b, 0xff];
+            index += 1;
+        }
+    }
+    palette
+}
+
+/// Build the mesh of one model with greedy meshing
+///
+/// For every direction a face can point and every slice of the grid across it, the faces that
+/// show are marked with their palette index, then grown into the largest rectangles of one color.
+fn mesh_data(name: String, grid: &VoxelGrid, palette: &[[u8; 4]; 256], palette_materials: &HashMap<u8, usize>) -> MeshData {
+    let mut vertices = Vec::new();
+    // triangles of each material, joined into submeshes at the end
+    let mut material_indices: Vec<Vec<u32>> = vec![Vec::new(); palette_materials.len() + 1];
+    // stand the model on the origin, centered like MagicaVoxel shows it
+    let center = [grid.size[0] as f32 / 2.0, grid.size[1] as f32 / 2.0, 0.0];
+
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let (width, height) = (grid.size[u], grid.size[v]);
+        for sign in [1i64, -1] {
+            let mut normal = [0.0; 3];
+            normal[axis] = sign as f32;
+            for slice in 0..grid.size[axis] {
+                // palette index of every face showing in this slice
+                let mut mask = vec![0u8; width * height];
+                for j in 0..height {
+                    for i in 0..width {
+                        let mut at = [0i64; 3];
+                        at[axis] = slice as i64;
+                        at[u] = i as i64;
+                        at[v] = j as i64;
+                        let mut beyond = at;
+                        beyond[axis] += sign;
+                        if grid.get(beyond) == 0 {
+                            mask[i + j * width] = grid.get(at);
+                        }
+                    }
+                }
+
+                for j in 0..height {
+                    let mut i = 0;
+                    while i < width {
+                        let index = mask[i + j * width];
+                        if index == 0 {
+                            i += 1;
+                            continue;
+                        }
+                        // grow along u, then along v while the whole row matches
+                        let mut w = 1;
+                        while i + w < width && mask[i + w + j * width] == index {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        while j + h < height && (i..i + w).all(|k| mask[k + (j + h) * width] == index) {
+                            h += 1;
+                        }
+                        for row in j..j + h {
+                            mask[i + row * width..i + w + row * width].fill(0);
+                        }
+
+                        let mut corner = [0.0; 3];
+                        corner[axis] = (slice as i64 + sign.max(0)) as f32;
+                        corner[u] = i as f32;
+                        corner[v] = j as f32;
+                        let corners = [(0.0, 0.0), (w as f32, 0.0), (w as f32, h as f32), (0.0, h as f32)].map(|(du, dv)| {
+                            let mut p = corner;
+                            p[u] += du;
+                            p[v] += dv;
+                            p
+                        });
+                        let [r, g, b, _] = palette[index as usize];
+                        let start = vertices.len() as u32;
+                        for [x, y, z] in corners {
+                            vertices.push(ModelVertex {
+                                // Z-up to Y-up
+                                position: [(x - center[0]) * VOXEL_SIZE, (z - center[2]) * VOXEL_SIZE, -(y - center[1]) * VOXEL_SIZE],
+                                tex_coords: [0.0, 0.0],
+                                normal: [normal[0], normal[2], -normal[1]],
+                                color: [r, g, b].map(|c| srgb_to_linear(c as f32 / 255.0)),
+                                tangent: [0.0; 4],
+                            });
+                        }
+                        // u then v turns counterclockwise seen from the front of a positive face
+                        let triangles = if sign > 0 { [0, 1, 2, 0, 2, 3] } else { [0, 2, 1, 0, 3, 2] };
+                        let material = palette_materials.get(&index).copied().unwrap_or(0);
+                        material_indices[material].extend(triangles.map(|t| start + t));
+                        i += w;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut indices = Vec::new();
+    let mut submeshes = Vec::new();
+    for (material, triangles) in material_indices.into_iter().enumerate().filter(|(_, t)| !t.is_empty()) {
+        let start = indices.len() as u32;
+        indices.extend(triangles);
+        submeshes.push(model::Submesh { indices: start..indices.len() as u32, material });
+    }
+    MeshData { name, vertices, indices, submeshes, has_tex_coords: false, has_normals: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file with one model of the given size and voxels, and a MATL chunk
+    fn vox_file(size: [u32; 3], voxels: &[[u8; 4]], matl: Option<(u32, &[(&str, &str)])>) -> Vec<u8> {
+        let chunk = |id: &[u8], content: Vec<u8>| {
+            let mut bytes = id.to_vec();
+            bytes.extend((content.len() as u32).to_le_bytes());
+            bytes.extend(0u32.to_le_bytes());
+            bytes.extend(content);
+            bytes
+        };
+        let mut children = chunk(b"SIZE", size.iter().flat_map(|s| s.to_le_bytes()).collect());
+        let mut xyzi = (voxels.len() as u32).to_le_bytes().to_vec();
+        xyzi.extend(voxels.iter().flatten());
+        children.extend(chunk(b"XYZI", xyzi));
+        if let Some((index, properties)) = matl {
+            let mut content = index.to_le_bytes().to_vec();
+            content.extend((properties.len() as u32).to_le_bytes());
+            for text in properties.iter().flat_map(|(k, v)| [k, v]) {
+                content.extend((text.len() as u32).to_le_bytes());
+                content.extend(text.as_bytes());
+            }
+            children.extend(chunk(b"MATL", content));
+        }
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(150u32.to_le_bytes());
+        bytes.extend(b"MAIN");
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend((children.len() as u32).to_le_bytes());
+        bytes.extend(children);
+        bytes
+    }
+
+    #[test]
+    fn test_greedy_meshing() {
+        // a 3x2x1 slab of one color is 6 quads, one per side
+        let voxels = [[0, 0, 0, 1], [1, 0, 0, 1], [2, 0, 0, 1], [0, 1, 0, 1], [1, 1, 0, 1], [2, 1, 0, 1]];
+        let model = VoxImporter.import("slab.vox", &vox_file([3, 2, 1], &voxels, None)).unwrap();
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 6 * 4);
+        assert_eq!(mesh.indices.len(), 6 * 6);
+        assert_eq!(model.materials.len(), 1);
+        // white from the default palette, centered and standing on the origin
+        assert_eq!(mesh.vertices[0].color, [1.0, 1.0, 1.0]);
+        let lowest = mesh.vertices.iter().map(|v| v.position[1]).fold(f32::MAX, f32::min);
+        let widest = mesh.vertices.iter().map(|v| v.position[0]).fold(f32::MIN, f32::max);
+        assert!(lowest.abs() < 1e-6);
+        assert!((widest - 1.5 * VOXEL_SIZE).abs() < 1e-6);
+
+        // every triangle faces the way its normal points
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| cgmath::Vector3::from(mesh.vertices[triangle[i] as usize].position));
+            let normal = cgmath::Vector3::from(mesh.vertices[triangle[0] as usize].normal);
+            assert!(cgmath::InnerSpace::dot((b - a).cross(c - a), normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_two_colors_and_metal() {
+        let voxels = [[0, 0, 0, 1], [1, 0, 0, 2]];
+        let matl = (2, &[("_type", "_metal"), ("_metal", "0.8"), ("_rough", "0.2")][..]);
+        let model = VoxImporter.import("pair.vox", &vox_file([2, 1, 1], &voxels, Some(matl))).unwrap();
+        let mesh = &model.meshes[0];
+        // the faces between the two touch and aren't drawn, and colors aren't merged
+        assert_eq!(mesh.vertices.len(), 10 * 4);
+        assert_eq!(model.materials[1], ImportedMaterial { name: "metal 2".to_string(), metallic: 0.8, roughness: 0.2, ..Default::default() });
+        assert_eq!(mesh.submeshes.len(), 2);
+        assert_eq!(mesh.submeshes[1].material, 1);
+        assert_eq!(mesh.submeshes[1].indices.len(), 5 * 6);
+    }
+
+    #[test]
+    fn test_default_palette() {
+        let palette = default_palette();
+        assert_eq!(palette[1], [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(palette[2], [0xff, 0xff, 0xcc, 0xff]);
+        assert_eq!(palette[216], [0, 0, 0xee, 0xff]);
+        assert_eq!(palette[255], [0x11, 0x11, 0x11, 0xff]);
+        assert!(VoxImporter.import("bad.vox", b"VOX ").is_err());
+    }
+}
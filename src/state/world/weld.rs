@@ -0,0 +1,87 @@
+//! Merge duplicated vertices so meshes take less memory and fewer vertices get shaded
+use std::collections::HashMap;
+
+use super::model::ModelVertex;
+
+/// Vertices closer than this in every attribute are treated as the same vertex
+pub const DEFAULT_WELD_EPSILON: f32 = 1e-5;
+
+/// How much welding shrunk a mesh
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WeldStats {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+}
+
+impl WeldStats {
+    /// vertices that were merged away
+    pub fn removed(&self) -> usize {
+        self.vertices_before - self.vertices_after
+    }
+}
+
+/// Merge vertices with the same position, uv, normal and color and point the indices at the survivors
+///
+/// Attributes are snapped to a grid of `epsilon` before comparing, so two values straddling a
+/// grid line may stay apart, which only costs a little memory.
+pub fn weld(vertices: &mut Vec<ModelVertex>, indices: &mut [u32], epsilon: f32) -> WeldStats {
+    let before = vertices.len();
+    let mut welded: Vec<ModelVertex> = Vec::with_capacity(before);
+    let mut seen: HashMap<Vec<i64>, u32> = HashMap::new();
+
+    // where each old vertex ended up
+    let remap = vertices
+        .iter()
+        .map(|vertex| {
+            *seen.entry(weld_key(vertex, epsilon)).or_insert_with(|| {
+                welded.push(*vertex);
+                welded.len() as u32 - 1
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for index in indices.iter_mut() {
+        *index = remap[*index as usize];
+    }
+    *vertices = welded;
+
+    WeldStats { vertices_before: before, vertices_after: vertices.len() }
+}
+
+/// every attribute of a vertex snapped to the grid
+fn weld_key(vertex: &ModelVertex, epsilon: f32) -> Vec<i64> {
+    vertex.position
+        .iter()
+        .chain(&vertex.tex_coords)
+        .chain(&vertex.normal)
+        .chain(&vertex.color)
+        .map(|value| (value / epsilon).round() as i64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], tex_coords: [f32; 2]) -> ModelVertex {
+        ModelVertex { position, tex_coords, normal: [0.0, 0.0, 1.0], color: [1.0, 1.0, 1.0], tangent: [0.0; 4] }
+    }
+
+    #[test]
+    fn test_weld() {
+        // a quad saved as two separate triangles, with one corner off by less than epsilon
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 0.0, 0.0], [1.0, 0.0]), vertex([1.0, 1.0, 0.0], [1.0, 1.0]),
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 1.0000001, 0.0], [1.0, 1.0]), vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+            // same position as the first corner but a different uv, a seam that has to stay
+            vertex([0.0, 0.0, 0.0], [0.5, 0.0]),
+        ];
+        let mut indices = vec![0, 1, 2, 3, 4, 5, 6, 6, 6];
+        let stats = weld(&mut vertices, &mut indices, DEFAULT_WELD_EPSILON);
+
+        assert_eq!(stats, WeldStats { vertices_before: 7, vertices_after: 5 });
+        assert_eq!(stats.removed(), 2);
+        assert_eq!(indices, vec![0, 1, 2, 0, 2, 3, 4, 4, 4]);
+        assert_eq!(vertices[4].tex_coords, [0.5, 0.0]);
+    }
+}
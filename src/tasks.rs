@@ -0,0 +1,107 @@
+//! Background work that runs on a small thread pool instead of the render thread
+use std::{
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::OnceLock,
+    task::{Context, Poll},
+};
+
+use futures::{channel::oneshot, executor::ThreadPool, FutureExt};
+
+/// Start of the names of the task threads
+pub const THREAD_PREFIX: &str = "rust3d-task-";
+
+/// The pool shared by every task, started the first time something is spawned
+fn pool() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        ThreadPool::builder()
+            .name_prefix(THREAD_PREFIX)
+            .create()
+            .expect("couldn't start the task threads")
+    })
+}
+
+/// The result of a spawned task
+///
+/// Await it from async code, or check it every frame with `try_take` so the render loop never waits on it.
+pub struct Task<T> {
+    receiver: oneshot::Receiver<T>,
+}
+
+impl<T> Task<T> {
+    /// The result if the task is done, without waiting for it
+    pub fn try_take(&mut self) -> Option<anyhow::Result<T>> {
+        match self.receiver.try_recv() {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => None,
+            Err(_) => Some(Err(anyhow::anyhow!("background task panicked"))),
+        }
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = anyhow::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| result.map_err(|_| anyhow::anyhow!("background task panicked")))
+    }
+}
+
+/// Run a future on the task threads
+pub fn spawn<T, F>(future: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    pool().spawn_ok(async move {
+        // a panic would take the pool thread down with it, dropping the sender is enough to report it
+        if let Ok(value) = AssertUnwindSafe(future).catch_unwind().await {
+            // nobody waiting for the result is fine, it just gets dropped
+            let _ = sender.send(value);
+        }
+    });
+    Task { receiver }
+}
+
+/// Run blocking work like file reads on the task threads
+pub fn spawn_blocking<T, F>(work: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    spawn(async move { work() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn() {
+        let result = tokio_test::block_on(spawn(async { 2 + 2 })).unwrap();
+        assert_eq!(result, 4);
+    }
+
+    #[test]
+    fn test_try_take() {
+        let mut task = spawn_blocking(|| "loaded");
+        let result = loop {
+            if let Some(result) = task.try_take() {
+                break result;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(result.unwrap(), "loaded");
+    }
+
+    #[test]
+    fn test_panic_is_an_error() {
+        let task = spawn_blocking(|| -> u32 { panic!("broken file") });
+        assert!(tokio_test::block_on(task).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+//! Measuring how long each frame took, so things move at the same speed at any frame rate
+use std::time::{Duration, Instant};
+
+/// Longest frame time handed on, so a stall like dragging the window doesn't teleport the camera
+pub const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+/// Measures the time between frames
+pub struct FrameClock {
+    last_frame: Instant,
+}
+
+impl FrameClock {
+    /// Start timing from now
+    pub fn new() -> Self {
+        Self { last_frame: Instant::now() }
+    }
+
+    /// Time since the last tick, or since the clock started, at most `MAX_FRAME_TIME`
+    pub fn tick(&mut self) -> Duration {
+        let now = Instant::now();
+        let dt = now - self.last_frame;
+        self.last_frame = now;
+        clamp_frame_time(dt)
+    }
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cut a frame time down to `MAX_FRAME_TIME`
+pub fn clamp_frame_time(dt: Duration) -> Duration {
+    dt.min(MAX_FRAME_TIME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_frame_time() {
+        assert_eq!(clamp_frame_time(Duration::from_millis(16)), Duration::from_millis(16));
+        assert_eq!(clamp_frame_time(Duration::from_secs(3)), MAX_FRAME_TIME);
+    }
+
+    #[test]
+    fn test_tick_measures_time_passed() {
+        let mut clock = FrameClock::new();
+        std::thread::sleep(Duration::from_millis(5));
+        let dt = clock.tick();
+        assert!(dt >= Duration::from_millis(5) && dt <= MAX_FRAME_TIME);
+    }
+}
@@ -0,0 +1,79 @@
+// Tone mapping shader, brings the HDR frame into the output at the exposure picked for it
+//
+// The frame is scaled by the exposure from the settings and the one auto-exposure adapted to, then a
+// curve brings light brighter than white back under it. The output does the sRGB encoding itself.
+// On an scRGB output the curve is skipped, light brighter than white stays brighter and white is
+// scaled up to the paper white brightness.
+
+// see exposure.wgsl
+struct ExposureState {
+    average_luminance: f32,
+    exposure: f32,
+};
+
+// see `ToneMapUniform` in tonemap.rs
+struct ToneMapSettings {
+    // index of the curve, one of the constants below
+    curve: u32,
+    exposure: f32,
+    // SDR or SCRGB
+    output: u32,
+    // what white is multiplied by on an scRGB output
+    paper_white: f32,
+};
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read> exposure: ExposureState;
+@group(0) @binding(2)
+var<uniform> settings: ToneMapSettings;
+
+// the order of `ToneMapOperator::ALL`
+const LINEAR: u32 = 0u;
+const REINHARD: u32 = 1u;
+const ACES: u32 = 2u;
+
+// the order of `ToneMapOutput::index`
+const SDR: u32 = 0u;
+const SCRGB: u32 = 1u;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle covering the whole viewport
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    switch settings.curve {
+        case REINHARD: { return color / (1.0 + color); }
+        case ACES: { return aces(color); }
+        default: { return color; }
+    }
+}
+
+// the frame is the viewport's size, which may sit anywhere in the output, so pixels are found by where they are in it
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = textureDimensions(t_hdr);
+    let pixel = min(vec2<u32>(in.uv * vec2<f32>(size)), size - 1u);
+    let color = textureLoad(t_hdr, pixel, 0);
+    let exposed = color.rgb * settings.exposure * exposure.exposure;
+    if settings.output == SCRGB {
+        return vec4<f32>(exposed * settings.paper_white, color.a);
+    }
+    return vec4<f32>(tone_map(exposed), color.a);
+}
@@ -0,0 +1,84 @@
+// Upscale shader, stretches a frame drawn at a lower resolution over the window and sharpens it
+//
+// Like FSR 1 the upscale is spatial only and followed by contrast adaptive sharpening: the
+// frame is resampled with a Catmull-Rom filter, which keeps edges crisper than bilinear, then
+// sharpened more where the picture is flat and less where it already has contrast, so edges don't ring.
+
+// how the frame is sampled, see upscale.rs
+struct UpscaleUniform {
+    // size of one pixel of the low resolution frame in uv, and its size in pixels
+    texel: vec2<f32>,
+    source_size: vec2<f32>,
+    // 0 sharpens a little, 1 the most
+    sharpness: f32,
+};
+@group(0) @binding(0)
+var<uniform> upscale: UpscaleUniform;
+@group(0) @binding(1)
+var t_source: texture_2d<f32>;
+@group(0) @binding(2)
+var s_source: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle covering the whole window
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Catmull-Rom resampling in 9 bilinear taps, the middle taps share one sample each row and column
+fn sample_catmull_rom(uv: vec2<f32>) -> vec3<f32> {
+    let position = uv * upscale.source_size;
+    let center = floor(position - 0.5) + 0.5;
+    let f = position - center;
+
+    let w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+    let w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+    let w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+    let w3 = f * f * (-0.5 + 0.5 * f);
+    let w12 = w1 + w2;
+    let offset12 = w2 / w12;
+
+    let uv0 = (center - 1.0) * upscale.texel;
+    let uv3 = (center + 2.0) * upscale.texel;
+    let uv12 = (center + offset12) * upscale.texel;
+
+    var color = vec3<f32>(0.0);
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv0.x, uv0.y), 0.0).rgb * w0.x * w0.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv12.x, uv0.y), 0.0).rgb * w12.x * w0.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv3.x, uv0.y), 0.0).rgb * w3.x * w0.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv0.x, uv12.y), 0.0).rgb * w0.x * w12.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv12.x, uv12.y), 0.0).rgb * w12.x * w12.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv3.x, uv12.y), 0.0).rgb * w3.x * w12.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv0.x, uv3.y), 0.0).rgb * w0.x * w3.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv12.x, uv3.y), 0.0).rgb * w12.x * w3.y;
+    color += textureSampleLevel(t_source, s_source, vec2<f32>(uv3.x, uv3.y), 0.0).rgb * w3.x * w3.y;
+    // the negative lobes can overshoot below black
+    return max(color, vec3<f32>(0.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let middle = sample_catmull_rom(in.uv);
+    let up = textureSampleLevel(t_source, s_source, in.uv - vec2<f32>(0.0, upscale.texel.y), 0.0).rgb;
+    let down = textureSampleLevel(t_source, s_source, in.uv + vec2<f32>(0.0, upscale.texel.y), 0.0).rgb;
+    let left = textureSampleLevel(t_source, s_source, in.uv - vec2<f32>(upscale.texel.x, 0.0), 0.0).rgb;
+    let right = textureSampleLevel(t_source, s_source, in.uv + vec2<f32>(upscale.texel.x, 0.0), 0.0).rgb;
+
+    // how much room there is to sharpen before leaving the range of the neighbors, none above white
+    let low = min(middle, min(min(up, down), min(left, right)));
+    let high = max(middle, max(max(up, down), max(left, right)));
+    let room = sqrt(clamp(min(low, 1.0 - high) / max(high, vec3<f32>(1e-5)), vec3<f32>(0.0), vec3<f32>(1.0)));
+    let weight = room * (-1.0 / mix(8.0, 5.0, upscale.sharpness));
+
+    let sharpened = (middle + (up + down + left + right) * weight) / (1.0 + 4.0 * weight);
+    return vec4<f32>(max(sharpened, vec3<f32>(0.0)), 1.0);
+}